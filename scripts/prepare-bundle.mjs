@@ -1,15 +1,17 @@
 /**
  * prepare-bundle.mjs
  * Cross-platform build preparation for Tauri
- * Runs: next build → copy standalone output → copy static files → copy portable node
+ * Runs: next build → copy standalone output → copy static files → copy portable node/bun
+ *       → write the resource manifest (checked by `validate_installation`)
  *
  * Usage: node scripts/prepare-bundle.mjs
  *   or:  bun scripts/prepare-bundle.mjs
  */
 
 import { execSync } from 'child_process';
-import { existsSync, mkdirSync, cpSync, readdirSync, rmSync } from 'fs';
-import { join, resolve, dirname } from 'path';
+import { createHash } from 'crypto';
+import { existsSync, mkdirSync, cpSync, readdirSync, readFileSync, rmSync, statSync, writeFileSync } from 'fs';
+import { join, resolve, dirname, relative, sep } from 'path';
 import { fileURLToPath } from 'url';
 
 const __dirname = dirname(fileURLToPath(import.meta.url));
@@ -38,7 +40,7 @@ function listDir(dir) {
 // ═══════════════════════════════════════════════════════════
 //  Step 0: Clean .next cache to prevent stale Turbopack artifacts
 // ═══════════════════════════════════════════════════════════
-log('\n=== Step 0/5: Cleaning .next cache ===\n');
+log('\n=== Step 0/6: Cleaning .next cache ===\n');
 
 const nextDir = join(ROOT, '.next');
 if (existsSync(nextDir)) {
@@ -51,7 +53,7 @@ if (existsSync(nextDir)) {
 // ═══════════════════════════════════════════════════════════
 //  Step 1: Build Next.js (standalone)
 // ═══════════════════════════════════════════════════════════
-log('\n=== Step 1/5: Building Next.js (standalone) ===\n');
+log('\n=== Step 1/6: Building Next.js (standalone) ===\n');
 
 const standaloneDir = join(ROOT, '.next', 'standalone');
 
@@ -76,7 +78,7 @@ ok('Standalone output found');
 // ═══════════════════════════════════════════════════════════
 //  Step 2: Copy static files into standalone
 // ═══════════════════════════════════════════════════════════
-log('\n=== Step 2/5: Copying static & public files ===\n');
+log('\n=== Step 2/6: Copying static & public files ===\n');
 
 // .next/static → .next/standalone/.next/static
 const srcStatic = join(ROOT, '.next', 'static');
@@ -103,7 +105,7 @@ if (existsSync(srcPublic)) {
 // ═══════════════════════════════════════════════════════════
 //  Step 3: Copy standalone → src-tauri/bundled/server
 // ═══════════════════════════════════════════════════════════
-log('\n=== Step 3/5: Copying to src-tauri/bundled/server ===\n');
+log('\n=== Step 3/6: Copying to src-tauri/bundled/server ===\n');
 
 const bundledServer = join(ROOT, 'src-tauri', 'bundled', 'server');
 mkdirSync(bundledServer, { recursive: true });
@@ -118,9 +120,9 @@ if (!existsSync(serverJs)) {
 ok('Verified server.js exists');
 
 // ═══════════════════════════════════════════════════════════
-//  Step 4: Copy portable Node.js if available
+//  Step 4: Copy portable Node.js and Bun if available
 // ═══════════════════════════════════════════════════════════
-log('\n=== Step 4/5: Checking portable Node.js ===\n');
+log('\n=== Step 4/6: Checking portable Node.js and Bun ===\n');
 
 const portableNodeDir = join(ROOT, 'portable-node');
 const bundledNodeDir = join(ROOT, 'src-tauri', 'bundled', 'node');
@@ -136,10 +138,22 @@ if (portableFiles.length > 0) {
   warn('The app will try system Node.js as fallback');
 }
 
+// Optional: a Bun binary (bun.exe / bun) in portable-bun/ runs the server
+// instead of Node when [server] runtime is "auto" or "bun"
+const portableBunDir = join(ROOT, 'portable-bun');
+const bundledBunDir = join(ROOT, 'src-tauri', 'bundled', 'bun');
+if (listDir(portableBunDir).some(f => f !== '.gitkeep')) {
+  mkdirSync(bundledBunDir, { recursive: true });
+  cpSync(portableBunDir, bundledBunDir, { recursive: true, force: true });
+  ok('Copied portable Bun → src-tauri/bundled/bun/');
+} else {
+  ok('portable-bun/ is empty, the server will run on Node.js');
+}
+
 // ═══════════════════════════════════════════════════════════
 //  Step 5: Verify dist/ splash page
 // ═══════════════════════════════════════════════════════════
-log('\n=== Step 5/5: Verifying splash page ===\n');
+log('\n=== Step 5/6: Verifying splash page ===\n');
 
 const distIndex = join(ROOT, 'dist', 'index.html');
 if (existsSync(distIndex)) {
@@ -148,6 +162,38 @@ if (existsSync(distIndex)) {
   warn('dist/index.html not found');
 }
 
+// ═══════════════════════════════════════════════════════════
+//  Step 6: Write the resource manifest
+// ═══════════════════════════════════════════════════════════
+log('\n=== Step 6/6: Writing resource manifest ===\n');
+
+// Paths are relative to bundled/ with forward slashes. The manifest lives
+// in server/ because only the server, node, bun and native folders are bundled.
+const bundledDir = join(ROOT, 'src-tauri', 'bundled');
+const MANIFEST_NAME = 'resource-manifest.json';
+const manifestFiles = [];
+function addFiles(dir) {
+  for (const name of listDir(dir)) {
+    const path = join(dir, name);
+    const stat = statSync(path);
+    if (stat.isDirectory()) {
+      addFiles(path);
+    } else if (stat.isFile() && name !== '.gitkeep' && name !== MANIFEST_NAME) {
+      const sha256 = createHash('sha256').update(readFileSync(path)).digest('hex');
+      manifestFiles.push({ path: relative(bundledDir, path).split(sep).join('/'), size: stat.size, sha256 });
+    }
+  }
+}
+for (const part of ['server', 'node', 'bun', 'native']) {
+  addFiles(join(bundledDir, part));
+}
+const tauriConf = JSON.parse(readFileSync(join(ROOT, 'src-tauri', 'tauri.conf.json'), 'utf8'));
+writeFileSync(
+  join(bundledDir, 'server', MANIFEST_NAME),
+  JSON.stringify({ format: 1, version: tauriConf.version, files: manifestFiles }, null, 2) + '\n',
+);
+ok(`${MANIFEST_NAME} lists ${manifestFiles.length} files`);
+
 // ═══════════════════════════════════════════════════════════
 log('\n=== Bundle preparation complete! ===\n');
 ok('All steps completed');
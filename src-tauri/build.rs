@@ -1,3 +1,5 @@
 fn main() {
+    // Baked into the updater (see `updater`)
+    println!("cargo:rerun-if-env-changed=KARAOKE_UPDATER_PUBKEY");
     tauri_build::build()
 }
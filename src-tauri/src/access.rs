@@ -0,0 +1,339 @@
+//! Capability model for local, remote and guest-originated actions.
+//!
+//! Every action that changes something passes a `Capability` check against
+//! the acting `Principal` before it runs. Roles are fixed in Rust, so a bug
+//! (or a tampered page) in a guest-facing frontend cannot reach settings or
+//! audio routing:
+//!   - **host**     — the operator at the main window; everything.
+//!   - **operator** — a trusted helper on a phone or tablet; runs the show
+//!     (queue, playback, library) but cannot touch devices or settings.
+//!   - **guest**    — anyone else; may browse and request songs.
+//!
+//! Tauri commands derive the principal from the invoking webview: only the
+//! main window on a local origin is host, and only an operator while kiosk
+//! mode is on (so guests at the terminal cannot switch it off through the
+//! settings; `exit_kiosk` with the PIN is the way out); audience/player
+//! windows and any remote page loaded into a webview are guests. LAN and WebSocket clients
+//! get the role they authenticated with, but never host.
+//!
+//! Every registered command calls `require_webview` first, except the
+//! ones listed in the tests' `UNGUARDED` (see there for what they may
+//! do); a test walks the handler list in `lib.rs` to keep it that way.
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Host,
+    Operator,
+    Guest,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    /// Browse the library and see the queue.
+    ViewLibrary,
+    /// Add a song to the queue for oneself.
+    RequestSong,
+    /// Reorder or remove other people's queue entries.
+    ManageQueue,
+    /// Play, pause, seek, skip.
+    ControlPlayback,
+    /// Import, edit and delete songs.
+    ManageLibrary,
+    /// Select devices, channel maps, offsets, calibration.
+    ConfigureAudio,
+    /// Write app settings.
+    ChangeSettings,
+}
+
+pub const ALL_CAPABILITIES: &[Capability] = &[
+    Capability::ViewLibrary,
+    Capability::RequestSong,
+    Capability::ManageQueue,
+    Capability::ControlPlayback,
+    Capability::ManageLibrary,
+    Capability::ConfigureAudio,
+    Capability::ChangeSettings,
+];
+
+impl Role {
+    pub fn allows(self, capability: Capability) -> bool {
+        use Capability::*;
+        match self {
+            Role::Host => true,
+            Role::Operator => !matches!(capability, ConfigureAudio | ChangeSettings),
+            Role::Guest => matches!(capability, ViewLibrary | RequestSong),
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Role::Host => "host",
+            Role::Operator => "operator",
+            Role::Guest => "guest",
+        }
+    }
+}
+
+/// Where an action came from.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Origin {
+    /// A webview of this app, by window label.
+    Webview { label: String },
+    /// A LAN / WebSocket client, by client id.
+    Remote { client_id: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Principal {
+    pub origin: Origin,
+    pub role: Role,
+}
+
+impl Principal {
+    /// A remote client with the role it authenticated as. Remote clients are
+    /// capped at operator: host rights exist only at the machine itself.
+    pub fn remote(client_id: impl Into<String>, role: Role) -> Self {
+        Self {
+            origin: Origin::Remote { client_id: client_id.into() },
+            role: if role == Role::Host { Role::Operator } else { role },
+        }
+    }
+
+    /// Principal for a Tauri command invoked from `webview`.
+    pub fn from_webview<R: tauri::Runtime>(webview: &tauri::Webview<R>) -> Self {
+        let label = webview.label().to_string();
+        let url = webview.url().ok();
+        let kiosk = crate::desktop::kiosk::is_active(webview.app_handle());
+        let role = webview_role(&label, url.as_ref().map(|u| (u.scheme(), u.host_str().unwrap_or(""))), kiosk);
+        Self {
+            origin: Origin::Webview { label },
+            role,
+        }
+    }
+
+    /// `Err` with a readable reason when this principal lacks `capability`.
+    pub fn require(&self, capability: Capability) -> Result<(), String> {
+        if self.role.allows(capability) {
+            return Ok(());
+        }
+        tracing::warn!("[access] Denied {:?} to {:?} ({})", capability, self.origin, self.role.as_str());
+        Err(format!(
+            "Permission denied: {} role may not {}",
+            self.role.as_str(),
+            describe(capability)
+        ))
+    }
+}
+
+/// Shorthand for command handlers: check the invoking webview.
+pub fn require_webview<R: tauri::Runtime>(webview: &tauri::Webview<R>, capability: Capability) -> Result<(), String> {
+    Principal::from_webview(webview).require(capability)
+}
+
+/// Role of a webview from its label and current `(scheme, host)`, and
+/// whether kiosk mode is on.
+fn webview_role(label: &str, url: Option<(&str, &str)>, kiosk: bool) -> Role {
+    let local_origin = match url {
+        Some(("tauri", _)) => true,
+        // The UI shell (`server::shell`)
+        Some(("app", "localhost")) => true,
+        Some(("http" | "https", host)) => {
+            matches!(host, "localhost" | "127.0.0.1" | "[::1]" | "tauri.localhost" | "app.localhost")
+        }
+        _ => false,
+    };
+    if label == "main" && local_origin {
+        if kiosk {
+            Role::Operator
+        } else {
+            Role::Host
+        }
+    } else {
+        Role::Guest
+    }
+}
+
+fn describe(capability: Capability) -> &'static str {
+    match capability {
+        Capability::ViewLibrary => "view the library",
+        Capability::RequestSong => "request songs",
+        Capability::ManageQueue => "manage the queue",
+        Capability::ControlPlayback => "control playback",
+        Capability::ManageLibrary => "change the library",
+        Capability::ConfigureAudio => "change audio devices",
+        Capability::ChangeSettings => "change settings",
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RoleCapabilities {
+    pub role: Role,
+    pub capabilities: Vec<Capability>,
+}
+
+/// The fixed capability table, for settings and help screens.
+#[tauri::command]
+pub fn access_get_role_capabilities() -> Vec<RoleCapabilities> {
+    [Role::Host, Role::Operator, Role::Guest]
+        .into_iter()
+        .map(|role| RoleCapabilities {
+            role,
+            capabilities: ALL_CAPABILITIES.iter().copied().filter(|&c| role.allows(c)).collect(),
+        })
+        .collect()
+}
+
+/// The principal the calling webview acts as.
+#[tauri::command]
+pub fn access_whoami(webview: tauri::Webview) -> Principal {
+    Principal::from_webview(&webview)
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guests_request_songs_but_not_devices() {
+        let guest = Principal::remote("phone-1", Role::Guest);
+        assert!(guest.require(Capability::RequestSong).is_ok());
+        assert!(guest.require(Capability::ConfigureAudio).is_err());
+        assert!(guest.require(Capability::ControlPlayback).is_err());
+
+        let operator = Principal::remote("tablet", Role::Operator);
+        assert!(operator.require(Capability::ManageQueue).is_ok());
+        assert!(operator.require(Capability::ChangeSettings).is_err());
+    }
+
+    #[test]
+    fn remote_clients_are_never_host() {
+        assert_eq!(Principal::remote("x", Role::Host).role, Role::Operator);
+    }
+
+    #[test]
+    fn only_local_main_window_is_host() {
+        assert_eq!(webview_role("main", Some(("http", "localhost")), false), Role::Host);
+        assert_eq!(webview_role("main", Some(("tauri", "localhost")), false), Role::Host);
+        assert_eq!(webview_role("main", Some(("app", "localhost")), false), Role::Host);
+        assert_eq!(webview_role("main", Some(("https", "evil.example")), false), Role::Guest);
+        assert_eq!(webview_role("audience", Some(("http", "localhost")), false), Role::Guest);
+        assert_eq!(webview_role("main", None, false), Role::Guest);
+    }
+
+    #[test]
+    fn kiosk_main_window_cannot_change_settings() {
+        let kiosk = webview_role("main", Some(("app", "localhost")), true);
+        assert_eq!(kiosk, Role::Operator);
+        // `set_config` is how `[kiosk] enabled = false` would get in
+        let config = module_source(&["config"]);
+        let set_config = fn_body(&config, "set_config").unwrap();
+        assert!(set_config.contains("require_webview(&webview, Capability::ChangeSettings)"));
+        assert!(!kiosk.allows(Capability::ChangeSettings));
+        // The PIN is what lets the terminal out
+        let kiosk_source = module_source(&["desktop", "kiosk"]);
+        let exit = fn_body(&kiosk_source, "exit_kiosk").unwrap();
+        assert!(exit.contains("require_webview(&webview, Capability::ControlPlayback)"));
+        assert!(kiosk.allows(Capability::ControlPlayback));
+    }
+
+    /// Commands any webview may call, guest windows and remote pages
+    /// included. None of them takes a file path or queues a job:
+    ///   - native dialogs, shown to whoever sits at the machine;
+    ///   - `launch_ready`, which only releases events held until the UI
+    ///     has loaded;
+    ///   - readers of what a guest may see anyway: devices, playback
+    ///     position, the library, queue, history, profiles and scores,
+    ///     job and server status. Some of them name data directories or
+    ///     library files (`get_storage_usage`, `get_cleanup_suggestions`,
+    ///     `get_duplicate_report`);
+    ///   - probes of the install (`validate_installation`,
+    ///     `get_tool_versions`), which hash the bundle or run the managed
+    ///     tools with `--version`;
+    ///   - `get_config`, with secrets redacted unless the caller may change
+    ///     settings.
+    /// Everything else must call `require_webview`; commands reading a path
+    /// also confine it to the library (`library::roots`).
+    const UNGUARDED: &[&str] = &[
+        // Native dialogs shown to whoever sits at the machine
+        "native_pick_folder", "native_pick_file_open", "native_pick_file_save",
+        "native_message", "native_confirm",
+        "list_monitors", "launch_ready",
+        "audio_list_devices", "audio_list_input_devices", "audio_get_default_device", "audio_position",
+        "audio_get_position", "audio_get_state", "audio_get_channel_map", "audio_get_device_offset",
+        "audio_get_device_offsets", "audio_get_active_offset", "audio_get_reference_level", "audio_get_outputs",
+        "audio_get_rt_priority_status", "list_audio_inputs", "get_duplicate_report", "audio_crepe_info",
+        "access_get_role_capabilities", "access_whoami", "watch_party_status",
+        "db_load_songs", "db_get_song_count", "db_search_songs", "db_load_folders", "db_load_root_folders",
+        "db_load_profiles", "db_load_highscores", "db_load_playlists", "db_get_stats",
+        "search_songs", "search_songs_fast", "get_song", "get_storage_usage", "get_cleanup_suggestions",
+        "get_jobs", "party_status", "viral_get_matched_ids", "viral_get_entries", "viral_get_status",
+        "network_get_local_ip", "get_server_port", "server_status", "get_server_stats",
+        "queue_list", "get_history", "get_stats", "profile_list", "profile_get",
+        "clipboard_watch_get_enabled", "get_transcode_jobs",
+        "get_tool_versions", "get_scheduled_tasks", "get_autostart", "validate_installation", "get_config",
+        "list_midi_inputs", "multiroom_status", "cast_status", "get_pending_telemetry",
+    ];
+
+    /// Source of the module `path` (`a::b`) names, under `src/`.
+    fn module_source(path: &[&str]) -> String {
+        let src = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+        if path.is_empty() {
+            return std::fs::read_to_string(src.join("lib.rs")).unwrap();
+        }
+        let base = path.iter().fold(src, |dir, part| dir.join(part));
+        std::fs::read_to_string(base.with_extension("rs"))
+            .or_else(|_| std::fs::read_to_string(base.join("mod.rs")))
+            .unwrap_or_else(|_| panic!("no source for {}", path.join("::")))
+    }
+
+    /// The body of `fn name` in `source`, up to its closing brace at column 0.
+    fn fn_body<'a>(source: &'a str, name: &str) -> Option<&'a str> {
+        let needle = format!("fn {}(", name);
+        let (start, _) = source.match_indices(&needle).find(|&(at, _)| at == 0 || source[..at].ends_with([' ', '\n']))?;
+        let rest = &source[start..];
+        Some(&rest[..rest.find("\n}\n").map(|end| end + 3).unwrap_or(rest.len())])
+    }
+
+    #[test]
+    fn every_command_is_guarded_or_listed() {
+        let lib = module_source(&[]);
+        let handlers = &lib[lib.find("generate_handler![").expect("no generate_handler!")..];
+        let handlers = &handlers[..handlers.find("])").unwrap()];
+        let commands: Vec<&str> = handlers
+            .lines()
+            .skip(1)
+            .map(|line| line.trim().trim_end_matches(','))
+            .filter(|line| !line.is_empty() && !line.starts_with("//"))
+            .collect();
+        assert!(commands.len() > 100, "parsed only {} commands", commands.len());
+
+        let mut open = Vec::new();
+        for command in &commands {
+            let mut path: Vec<&str> = command.split("::").collect();
+            let name = path.pop().unwrap();
+            let source = module_source(&path);
+            let body = fn_body(&source, name).unwrap_or_else(|| panic!("{} not found", command));
+            if !body.contains("require_webview(") && !UNGUARDED.contains(&name) {
+                open.push(*command);
+            }
+        }
+        assert!(open.is_empty(), "commands without a capability check: {:?}", open);
+
+        let names: Vec<&str> = commands.iter().map(|command| command.rsplit("::").next().unwrap()).collect();
+        let stale: Vec<&&str> = UNGUARDED.iter().filter(|name| !names.contains(name)).collect();
+        assert!(stale.is_empty(), "UNGUARDED lists unregistered commands: {:?}", stale);
+    }
+}
@@ -143,7 +143,7 @@ mod crepe_impl {
             self.session = Some(session);
             self.loaded = true;
             self.error_msg = None;
-            println!("[crepe] Model loaded from: {}", model_path);
+            tracing::info!("[crepe] Model loaded from: {}", model_path);
             Ok(())
         }
 
@@ -194,7 +194,7 @@ mod crepe_impl {
             let input_ref = match ort::value::TensorRef::from_array_view(&input_array) {
                 Ok(r) => r,
                 Err(e) => {
-                    eprintln!("[crepe] Failed to create input tensor: {}", e);
+                    tracing::error!("[crepe] Failed to create input tensor: {}", e);
                     return (0.0, 0.0);
                 }
             };
@@ -203,7 +203,7 @@ mod crepe_impl {
             let outputs = match session.run(ort::inputs![input_ref]) {
                 Ok(o) => o,
                 Err(e) => {
-                    eprintln!("[crepe] Inference failed: {}", e);
+                    tracing::error!("[crepe] Inference failed: {}", e);
                     return (0.0, 0.0);
                 }
             };
@@ -0,0 +1,215 @@
+//! Persistent cache for expensive audio analysis results.
+//!
+//! Pitch tracks, BPM, waveforms and loudness are stored as JSON sidecar
+//! files under `<app cache dir>/analysis/`, named
+//! `<content hash>.<kind>[.<options hash>].json`. Keys come from the audio
+//! content, not the path, so renaming or moving a song keeps its analysis
+//! and re-scans never trigger it again.
+//!
+//! Each file carries the format version of its kind; bump `CACHE_VERSION`
+//! when the analysis itself changes and old entries are silently ignored
+//! (and overwritten on the next run).
+//!
+//! Pitch and BPM results are cached by the analysis thread itself,
+//! loudness and waveform peaks by `loudness` and `waveform`; results
+//! computed in the webview go through the `audio_cache_get` /
+//! `audio_cache_put` commands.
+
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use super::stream_decoder::open_media_file;
+use crate::access::{require_webview, Capability};
+use crate::library::roots::require_in_library;
+use crate::library::scanner::fnv1a64;
+
+/// Bump when any analysis algorithm changes its output.
+pub const CACHE_VERSION: u32 = 1;
+
+/// Subdirectory of the app cache dir. Never age-pruned by the scheduler.
+pub const CACHE_SUBDIR: &str = "analysis";
+
+/// Bytes hashed from the start and the end of the file.
+const HASH_SPAN: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CacheKind {
+    Pitch,
+    Bpm,
+    Waveform,
+    Loudness,
+    Fingerprint,
+}
+
+impl CacheKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Pitch => "pitch",
+            Self::Bpm => "bpm",
+            Self::Waveform => "waveform",
+            Self::Loudness => "loudness",
+            Self::Fingerprint => "fingerprint",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheEnvelope<T> {
+    version: u32,
+    kind: String,
+    created_at: i64,
+    data: T,
+}
+
+/// Handle to the on-disk cache; cheap to clone into worker commands.
+#[derive(Debug, Clone)]
+pub struct AnalysisCache {
+    dir: PathBuf,
+}
+
+impl AnalysisCache {
+    pub fn new(app_cache_dir: &Path) -> Self {
+        Self {
+            dir: app_cache_dir.join(CACHE_SUBDIR),
+        }
+    }
+
+    pub fn from_app(app: &tauri::AppHandle) -> Option<Self> {
+        use tauri::Manager;
+        app.path().app_cache_dir().ok().map(|dir| Self::new(&dir))
+    }
+
+    /// Content key for the audio file at `file_path`.
+    pub fn key_for(file_path: &str) -> Result<String, String> {
+        let (file, _) = open_media_file(file_path)?;
+        content_hash(file).map_err(|e| format!("Failed to hash {}: {}", file_path, e))
+    }
+
+    /// Look up an entry. `variant` distinguishes results computed with
+    /// different options (e.g. the pitch algorithm).
+    pub fn get<T: DeserializeOwned>(&self, key: &str, kind: CacheKind, variant: Option<&str>) -> Option<T> {
+        let bytes = std::fs::read(self.entry_path(key, kind, variant)).ok()?;
+        let envelope: CacheEnvelope<T> = serde_json::from_slice(&bytes).ok()?;
+        (envelope.version == CACHE_VERSION).then_some(envelope.data)
+    }
+
+    /// Store an entry, replacing any older version.
+    pub fn put<T: Serialize>(&self, key: &str, kind: CacheKind, variant: Option<&str>, data: &T) -> Result<(), String> {
+        std::fs::create_dir_all(&self.dir).map_err(|e| format!("Failed to create analysis cache: {}", e))?;
+        let envelope = CacheEnvelope {
+            version: CACHE_VERSION,
+            kind: kind.as_str().to_string(),
+            created_at: now_ms(),
+            data,
+        };
+        let json = serde_json::to_vec(&envelope).map_err(|e| format!("Failed to serialize cache entry: {}", e))?;
+
+        // Write to a temp file and rename, so a crash never leaves a torn entry
+        let path = self.entry_path(key, kind, variant);
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, json).map_err(|e| format!("Failed to write cache entry: {}", e))?;
+        std::fs::rename(&tmp, &path).map_err(|e| format!("Failed to write cache entry: {}", e))
+    }
+
+    fn entry_path(&self, key: &str, kind: CacheKind, variant: Option<&str>) -> PathBuf {
+        let name = match variant {
+            Some(v) => format!("{}.{}.{:08x}.json", key, kind.as_str(), fnv1a64(v.as_bytes()) as u32),
+            None => format!("{}.{}.json", key, kind.as_str()),
+        };
+        self.dir.join(name)
+    }
+}
+
+/// Hash of the file size plus its first and last `HASH_SPAN` bytes.
+///
+/// Reading whole multi-GB videos would cost more than the analysis saves;
+/// head + tail + size tells edited or re-encoded files apart in practice.
+fn content_hash(mut file: std::fs::File) -> std::io::Result<String> {
+    let len = file.metadata()?.len();
+    let mut data = len.to_le_bytes().to_vec();
+
+    let mut head = Vec::new();
+    (&mut file).take(HASH_SPAN).read_to_end(&mut head)?;
+    data.extend_from_slice(&head);
+
+    if len > HASH_SPAN * 2 {
+        file.seek(SeekFrom::Start(len - HASH_SPAN))?;
+        let mut tail = Vec::new();
+        file.take(HASH_SPAN).read_to_end(&mut tail)?;
+        data.extend_from_slice(&tail);
+    }
+    Ok(format!("{:016x}", fnv1a64(&data)))
+}
+
+fn now_ms() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+/// Cached result for `file_path`, or `None` on a miss.
+#[tauri::command]
+pub fn audio_cache_get(
+    app: tauri::AppHandle,
+    webview: tauri::Webview,
+    file_path: String,
+    kind: CacheKind,
+    variant: Option<String>,
+) -> Result<Option<serde_json::Value>, String> {
+    require_webview(&webview, Capability::ViewLibrary)?;
+    require_in_library(&app, &file_path)?;
+    let cache = AnalysisCache::from_app(&app).ok_or("No cache directory")?;
+    let key = AnalysisCache::key_for(&file_path)?;
+    Ok(cache.get(&key, kind, variant.as_deref()))
+}
+
+/// Store a result computed in the webview for `file_path`.
+#[tauri::command]
+pub fn audio_cache_put(
+    app: tauri::AppHandle,
+    webview: tauri::Webview,
+    file_path: String,
+    kind: CacheKind,
+    variant: Option<String>,
+    data: serde_json::Value,
+) -> Result<(), String> {
+    require_webview(&webview, Capability::ManageLibrary)?;
+    let cache = AnalysisCache::from_app(&app).ok_or("No cache directory")?;
+    let key = AnalysisCache::key_for(&file_path)?;
+    cache.put(&key, kind, variant.as_deref(), &data)
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_and_ignores_other_versions() {
+        let dir = crate::paths::test_dir("analysis-cache");
+        let cache = AnalysisCache::new(&dir);
+
+        cache.put("abc", CacheKind::Bpm, None, &128.5f64).unwrap();
+        assert_eq!(cache.get::<f64>("abc", CacheKind::Bpm, None), Some(128.5));
+        assert_eq!(cache.get::<f64>("abc", CacheKind::Bpm, Some("yin")), None);
+        assert_eq!(cache.get::<f64>("abc", CacheKind::Loudness, None), None);
+
+        let stale = serde_json::json!({ "version": CACHE_VERSION + 1, "kind": "bpm", "created_at": 0, "data": 99.0 });
+        std::fs::write(dir.join(CACHE_SUBDIR).join("abc.bpm.json"), stale.to_string()).unwrap();
+        assert_eq!(cache.get::<f64>("abc", CacheKind::Bpm, None), None);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -16,7 +16,10 @@ use super::analysis::{
     analyzer::AudioAnalyzer,
     crepe,
 };
+use super::analysis_cache::{AnalysisCache, CacheKind};
 use super::player::decode_mono_f64;
+use crate::access::{require_webview, Capability};
+use crate::library::roots::require_in_library;
 
 // ---------------------------------------------------------------------------
 // Analysis state (managed by Tauri)
@@ -26,12 +29,14 @@ enum AnalysisCommand {
     Analyze {
         file_path: String,
         options: AnalysisOptions,
+        cache: Option<AnalysisCache>,
         on_progress: Channel<AnalysisProgress>,
         on_complete: Channel<PitchAnalysisResult>,
         on_error: Channel<String>,
     },
     DetectBpm {
         file_path: String,
+        cache: Option<AnalysisCache>,
         on_complete: Channel<BpmDetectionResult>,
         on_error: Channel<String>,
     },
@@ -74,7 +79,18 @@ fn run_analysis_thread(
 ) {
     loop {
         match rx.recv_timeout(Duration::from_millis(100)) {
-            Ok(AnalysisCommand::Analyze { file_path, options, on_progress, on_complete, on_error }) => {
+            Ok(AnalysisCommand::Analyze { file_path, options, cache, on_progress, on_complete, on_error }) => {
+                // Results depend on the options (algorithm, thresholds)
+                let variant = serde_json::to_string(&options).unwrap_or_default();
+                let cached = cache_lookup(cache.as_ref(), &file_path);
+                if let Some(result) = cached
+                    .as_ref()
+                    .and_then(|(cache, key)| cache.get::<PitchAnalysisResult>(key, CacheKind::Pitch, Some(&variant)))
+                {
+                    let _ = on_complete.send(result);
+                    continue;
+                }
+
                 let _ = on_progress.send(AnalysisProgress {
                     stage: super::analysis::types::AnalysisStage::Loading,
                     progress: 0.0,
@@ -98,6 +114,11 @@ fn run_analysis_thread(
                             }),
                         );
 
+                        if let Some((cache, key)) = &cached {
+                            if let Err(e) = cache.put(key, CacheKind::Pitch, Some(&variant), &result) {
+                                tracing::warn!("[analysis] {}", e);
+                            }
+                        }
                         let _ = on_complete.send(result);
                     }
                     Err(e) => {
@@ -105,18 +126,34 @@ fn run_analysis_thread(
                     }
                 }
             }
-            Ok(AnalysisCommand::DetectBpm { file_path, on_complete, on_error }) => {
+            Ok(AnalysisCommand::DetectBpm { file_path, cache, on_complete, on_error }) => {
+                let cached = cache_lookup(cache.as_ref(), &file_path);
+                if let Some(result) = cached
+                    .as_ref()
+                    .and_then(|(cache, key)| cache.get::<BpmDetectionResult>(key, CacheKind::Bpm, None))
+                {
+                    // The cached entry may come from a moved/renamed copy
+                    let _ = on_complete.send(BpmDetectionResult { file_path, ..result });
+                    continue;
+                }
+
                 match decode_mono_f64(&file_path) {
                     Ok(decoded) => {
                         use super::analysis::bpm::BpmDetector;
                         let mut det = BpmDetector::new(1024, 512, decoded.sample_rate);
                         let bpm = det.detect(&decoded.samples);
 
-                        let _ = on_complete.send(BpmDetectionResult {
+                        let result = BpmDetectionResult {
                             bpm,
                             file_path,
                             duration_ms: decoded.duration_ms,
-                        });
+                        };
+                        if let Some((cache, key)) = &cached {
+                            if let Err(e) = cache.put(key, CacheKind::Bpm, None, &result) {
+                                tracing::warn!("[analysis] {}", e);
+                            }
+                        }
+                        let _ = on_complete.send(result);
                     }
                     Err(e) => {
                         let _ = on_error.send(e);
@@ -130,6 +167,14 @@ fn run_analysis_thread(
     }
 }
 
+/// Cache handle plus content key for `file_path`; `None` if caching is
+/// unavailable (no cache dir, unreadable file — analysis then reports the error).
+fn cache_lookup(cache: Option<&AnalysisCache>, file_path: &str) -> Option<(AnalysisCache, String)> {
+    let cache = cache?;
+    let key = AnalysisCache::key_for(file_path).ok()?;
+    Some((cache.clone(), key))
+}
+
 // ---------------------------------------------------------------------------
 // Serializable result for BPM detection
 // ---------------------------------------------------------------------------
@@ -161,17 +206,21 @@ pub struct CrepeInfo {
 #[tauri::command]
 pub fn audio_analyze_pitch(
     app: AppHandle,
+    webview: tauri::Webview,
     file_path: String,
     options: Option<AnalysisOptions>,
     on_progress: Channel<AnalysisProgress>,
     on_complete: Channel<PitchAnalysisResult>,
     on_error: Channel<String>,
 ) -> Result<String, String> {
+    require_webview(&webview, Capability::ControlPlayback)?;
+    require_in_library(&app, &file_path)?;
     let analysis_state = app.state::<AnalysisState>();
     let tx = analysis_state.command_tx.lock().map_err(|e| e.to_string())?;
     tx.send(AnalysisCommand::Analyze {
         file_path,
         options: options.unwrap_or_default(),
+        cache: AnalysisCache::from_app(&app),
         on_progress,
         on_complete,
         on_error,
@@ -186,14 +235,18 @@ pub fn audio_analyze_pitch(
 #[tauri::command]
 pub fn audio_detect_bpm(
     app: AppHandle,
+    webview: tauri::Webview,
     file_path: String,
     on_complete: Channel<BpmDetectionResult>,
     on_error: Channel<String>,
 ) -> Result<String, String> {
+    require_webview(&webview, Capability::ControlPlayback)?;
+    require_in_library(&app, &file_path)?;
     let analysis_state = app.state::<AnalysisState>();
     let tx = analysis_state.command_tx.lock().map_err(|e| e.to_string())?;
     tx.send(AnalysisCommand::DetectBpm {
         file_path,
+        cache: AnalysisCache::from_app(&app),
         on_complete,
         on_error,
     })
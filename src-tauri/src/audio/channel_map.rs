@@ -0,0 +1,161 @@
+//! Per-device channel mapping for multi-channel audio interfaces.
+//!
+//! Venue USB mixers expose 8+ channels; the music usually has to go to a
+//! specific pair (e.g. outputs 5/6) and the singer's mic sits on a specific
+//! input (e.g. input 3). A `ChannelMap` records that routing per device name
+//! and is persisted in `app_settings` under `audio_channel_map:<device name>`.
+//!
+//! All channel indices are **0-based** (outputs 5/6 → `[4, 5]`).
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+
+use super::player::convert_channels;
+
+/// Settings key prefix for persisted channel maps.
+const SETTINGS_PREFIX: &str = "audio_channel_map:";
+
+/// Highest channel index accepted from the frontend.
+const MAX_CHANNEL_INDEX: u16 = 63;
+
+/// Routing for one device.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChannelMap {
+    /// Device output channels the backing track is sent to, in source order
+    /// (first entry = left, second = right). Empty = default layout.
+    pub music_outputs: Vec<u16>,
+    /// Device input channels carrying microphones (mic 1 = first entry).
+    pub mic_inputs: Vec<u16>,
+}
+
+impl ChannelMap {
+    /// Reject out-of-range or duplicate channels.
+    pub fn validate(&self) -> Result<(), String> {
+        for (label, list) in [("music_outputs", &self.music_outputs), ("mic_inputs", &self.mic_inputs)] {
+            if let Some(&bad) = list.iter().find(|&&c| c > MAX_CHANNEL_INDEX) {
+                return Err(format!("{}: channel {} out of range (max {})", label, bad, MAX_CHANNEL_INDEX));
+            }
+            let mut seen = list.clone();
+            seen.sort_unstable();
+            seen.dedup();
+            if seen.len() != list.len() {
+                return Err(format!("{}: duplicate channel index", label));
+            }
+        }
+        Ok(())
+    }
+
+    /// Number of device output channels the stream must open to honour the map.
+    pub fn required_output_channels(&self) -> u16 {
+        self.music_outputs.iter().max().map(|&c| c + 1).unwrap_or(0)
+    }
+}
+
+/// Channel maps shared between the command handlers and the audio thread,
+/// keyed by device name.
+pub type SharedChannelMaps = Arc<Mutex<HashMap<String, ChannelMap>>>;
+
+/// Load all persisted channel maps from the settings table.
+pub fn load_all(conn: &Connection) -> Result<HashMap<String, ChannelMap>, String> {
+    let mut stmt = conn
+        .prepare("SELECT key, value FROM app_settings WHERE key LIKE ?1")
+        .map_err(|e| format!("load channel maps failed: {}", e))?;
+    let rows = stmt
+        .query_map([format!("{}%", SETTINGS_PREFIX)], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })
+        .map_err(|e| format!("load channel maps query failed: {}", e))?;
+
+    let mut maps = HashMap::new();
+    for (key, value) in rows.flatten() {
+        let device_name = key[SETTINGS_PREFIX.len()..].to_string();
+        match serde_json::from_str::<ChannelMap>(&value) {
+            Ok(map) => {
+                maps.insert(device_name, map);
+            }
+            Err(e) => tracing::warn!("[audio] Ignoring invalid channel map for '{}': {}", device_name, e),
+        }
+    }
+    Ok(maps)
+}
+
+/// Persist (or clear, when `map` is the default) the map for one device.
+pub fn save(conn: &Connection, device_name: &str, map: &ChannelMap) -> Result<(), String> {
+    let key = format!("{}{}", SETTINGS_PREFIX, device_name);
+    if *map == ChannelMap::default() {
+        conn.execute("DELETE FROM app_settings WHERE key = ?1", [&key])
+            .map_err(|e| format!("Failed to clear channel map: {}", e))?;
+        return Ok(());
+    }
+    let json = serde_json::to_string(map).map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT OR REPLACE INTO app_settings (key, value) VALUES (?1, ?2)",
+        (&key, &json),
+    )
+    .map_err(|e| format!("Failed to save channel map: {}", e))?;
+    Ok(())
+}
+
+/// Route interleaved `samples` (with `src_channels`) into a `dst_channels`
+/// wide frame, placing the source channels on `outputs` and silencing all
+/// other device channels.
+///
+/// The source is first up/downmixed to `outputs.len()` channels, so a mono
+/// track on `[4, 5]` plays on both, and a stereo track on `[2]` is summed.
+/// Output indices beyond `dst_channels` are dropped.
+pub fn apply_output_map(samples: Vec<f32>, src_channels: u16, dst_channels: u16, outputs: &[u16]) -> Vec<f32> {
+    if outputs.is_empty() || dst_channels == 0 {
+        return convert_channels(samples, src_channels, dst_channels);
+    }
+
+    let routed = convert_channels(samples, src_channels, outputs.len() as u16);
+    let width = outputs.len();
+    let dst = dst_channels as usize;
+    let frames = routed.len() / width;
+
+    let mut output = vec![0.0f32; frames * dst];
+    for (frame_idx, frame) in routed.chunks_exact(width).enumerate() {
+        let base = frame_idx * dst;
+        for (&target, &sample) in outputs.iter().zip(frame) {
+            let target = target as usize;
+            if target < dst {
+                output[base + target] = sample;
+            }
+        }
+    }
+    output
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stereo_routed_to_outputs_5_and_6() {
+        // Two stereo frames: (0.1, 0.2), (0.3, 0.4)
+        let samples = vec![0.1, 0.2, 0.3, 0.4];
+        let out = apply_output_map(samples, 2, 8, &[4, 5]);
+        assert_eq!(out.len(), 16);
+        assert_eq!(&out[0..8], &[0.0, 0.0, 0.0, 0.0, 0.1, 0.2, 0.0, 0.0]);
+        assert_eq!(&out[8..16], &[0.0, 0.0, 0.0, 0.0, 0.3, 0.4, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn rejects_duplicates_and_out_of_range() {
+        let dup = ChannelMap { music_outputs: vec![4, 4], mic_inputs: vec![] };
+        assert!(dup.validate().is_err());
+        let far = ChannelMap { music_outputs: vec![], mic_inputs: vec![200] };
+        assert!(far.validate().is_err());
+        let ok = ChannelMap { music_outputs: vec![4, 5], mic_inputs: vec![2] };
+        assert!(ok.validate().is_ok());
+        assert_eq!(ok.required_output_channels(), 6);
+    }
+}
@@ -1,3 +1,4 @@
+use std::sync::atomic::Ordering;
 use std::sync::mpsc::{self, RecvTimeoutError};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
@@ -5,8 +6,21 @@ use std::time::Duration;
 use serde::{Deserialize, Serialize};
 use tauri::{ipc::Channel, AppHandle, Manager};
 
+use super::channel_map::{self, ChannelMap, SharedChannelMaps};
+use super::device_offsets;
 use super::devices::{self, AudioDeviceInfo};
-use super::player::{NativeAudioPlayer, PlaybackState};
+use super::level_calibration::{self, LevelCalibrationProgress, LevelCalibrationResult};
+use super::loudness;
+use super::output_mix::{self, ClickTrack, MixProfiles, OutputConfig, SharedOutputSettings};
+use super::pitch_shift::MAX_SEMITONES;
+use super::player::{DecodedAudio, NativeAudioPlayer, PlaybackState};
+use super::position::AudioPosition;
+use super::test_tone::{self, TestSignal, TEST_TONE_SAMPLE_RATE};
+use super::transition::{self, Transition, TransitionMode, MAX_SECONDS};
+use crate::access::{require_webview, Capability};
+use crate::db::DbState;
+use crate::media::native_video;
+use crate::paths::path_key;
 
 // ---------------------------------------------------------------------------
 // Commands sent from Tauri handlers → dedicated audio thread
@@ -16,15 +30,42 @@ enum AudioCommand {
     Play {
         file_path: String,
         device_id: String,
+        /// Loudness normalisation gain for the file (linear).
+        track_gain: f32,
         on_time_update: Channel<u64>,
         on_ended: Channel<()>,
         on_error: Channel<String>,
     },
+    /// Open a file paused at 0; progress is published as `audio://position`.
+    /// Replies with the track's duration in ms.
+    Load {
+        file_path: String,
+        device_id: String,
+        track_gain: f32,
+        reply: mpsc::Sender<Result<u64, String>>,
+    },
+    /// Stage the song playback continues with (`None` withdraws it).
+    /// Replies with its duration in ms.
+    StageNext {
+        file_path: Option<String>,
+        track_gain: f32,
+        reply: mpsc::Sender<Result<u64, String>>,
+    },
+    /// Play a generated buffer (test tone / pink noise) on specific channels.
+    PlayBuffer {
+        audio: DecodedAudio,
+        device_id: String,
+        output_channels: Option<Vec<u16>>,
+    },
     Pause,
     Resume,
     Seek(u64),
     SetVolume(f32),
     Stop,
+    /// The device monitor saw the device list change; try to reconnect.
+    DevicesChanged,
+    /// The output configuration changed; reopen the secondary output.
+    OutputsChanged,
     Shutdown,
 }
 
@@ -35,33 +76,236 @@ enum AudioCommand {
 /// Global audio state managed by Tauri.
 /// The actual `NativeAudioPlayer` (which owns a !Send cpal::Stream) lives on a
 /// dedicated thread.  We communicate with it via a channel and share the
-/// `PlaybackState` via `Arc<Mutex<_>>`.
+/// lock-free `PlaybackState` via `Arc`.
 pub struct AudioState {
     /// Channel sender wrapped in Mutex for Sync (mpsc::Sender is Send-only).
     command_tx: Mutex<mpsc::Sender<AudioCommand>>,
     /// Shared playback state updated by the audio thread / cpal callbacks.
-    state: Arc<Mutex<PlaybackState>>,
+    state: Arc<PlaybackState>,
+    /// Per-device channel routing, read by the player when opening a stream.
+    channel_maps: SharedChannelMaps,
+    /// Primary/secondary outputs and their mixes, read the same way.
+    outputs: SharedOutputSettings,
+    /// Song staged with `audio_preload_next`, until playback reaches it.
+    staged_path: Mutex<Option<String>>,
+    /// Song opened or moved on to last; `None` once stopped.
+    current_path: Mutex<Option<String>>,
+    /// Key the singer on stage wants, and the files (as `path_key`) of the
+    /// song they sing it for; any other song opens in its own key.
+    armed_key: Mutex<Option<(Vec<String>, i32)>>,
 }
 
 impl AudioState {
     /// Spawn the dedicated audio thread and return the managed state.
     pub fn new() -> Result<Self, String> {
         let (tx, rx) = mpsc::channel::<AudioCommand>();
-        let state = Arc::new(Mutex::new(PlaybackState::default()));
+        let state = Arc::new(PlaybackState::default());
         let shared_state = state.clone();
+        let channel_maps: SharedChannelMaps = Arc::default();
+        let player_maps = channel_maps.clone();
+        let outputs: SharedOutputSettings = Arc::default();
+        let player_outputs = outputs.clone();
 
         std::thread::Builder::new()
             .name("karaoke-audio".into())
             .spawn(move || {
-                run_audio_thread(rx, shared_state);
+                run_audio_thread(rx, shared_state, player_maps, player_outputs);
             })
             .map_err(|e| format!("Failed to spawn audio thread: {}", e))?;
 
         Ok(Self {
             command_tx: Mutex::new(tx),
             state,
+            channel_maps,
+            outputs,
+            staged_path: Mutex::new(None),
+            current_path: Mutex::new(None),
+            armed_key: Mutex::new(None),
         })
     }
+
+    /// Load persisted channel maps once the database is available.
+    pub fn load_channel_maps(&self, db: &DbState) -> Result<(), String> {
+        let conn = db.conn.lock().map_err(|e| e.to_string())?;
+        let maps = channel_map::load_all(&conn)?;
+        *self.channel_maps.lock().map_err(|e| e.to_string())? = maps;
+        Ok(())
+    }
+
+    /// Load the persisted output configuration.
+    pub fn load_output_config(&self, db: &DbState) -> Result<(), String> {
+        let conn = db.conn.lock().map_err(|e| e.to_string())?;
+        self.outputs.lock().map_err(|e| e.to_string())?.config = output_mix::load(&conn);
+        Ok(())
+    }
+
+    /// Load the persisted transition between songs.
+    pub fn load_transition(&self, db: &DbState) -> Result<(), String> {
+        let conn = db.conn.lock().map_err(|e| e.to_string())?;
+        self.state.set_transition(transition::load(&conn));
+        Ok(())
+    }
+
+    fn send(&self, command: AudioCommand) -> Result<(), String> {
+        let tx = self.command_tx.lock().map_err(|e| e.to_string())?;
+        tx.send(command).map_err(|e| e.to_string())
+    }
+}
+
+impl AudioState {
+    /// Called by the hot-plug monitor whenever devices appear or disappear.
+    pub fn notify_devices_changed(&self) {
+        if let Ok(tx) = self.command_tx.lock() {
+            let _ = tx.send(AudioCommand::DevicesChanged);
+        }
+    }
+
+    /// Playback position of the native player, for followers such as lyrics.
+    pub fn position_ms(&self) -> u64 {
+        self.state.position_ms.load(Ordering::Relaxed)
+    }
+
+    /// Drift the native player `ms` ahead (behind when negative) over the
+    /// next seconds instead of seeking; for followers such as multi-room.
+    pub fn nudge(&self, ms: i64) {
+        self.state.request_nudge(ms);
+    }
+
+    /// Pause if playing, otherwise resume the loaded track. Returns whether
+    /// it is playing now.
+    pub fn toggle_playback(&self) -> Result<bool, String> {
+        if self.is_playing() {
+            self.send(AudioCommand::Pause)?;
+            Ok(false)
+        } else if self.state.duration_ms.load(Ordering::Relaxed) > 0 {
+            self.send(AudioCommand::Resume)?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    pub fn pause(&self) -> Result<(), String> {
+        self.send(AudioCommand::Pause)
+    }
+
+    /// Resume the loaded track; a no-op when none is loaded.
+    pub fn resume(&self) -> Result<(), String> {
+        if self.state.duration_ms.load(Ordering::Relaxed) == 0 {
+            return Ok(());
+        }
+        self.send(AudioCommand::Resume)
+    }
+
+    pub fn seek(&self, position_ms: u64) -> Result<(), String> {
+        self.send(AudioCommand::Seek(position_ms))
+    }
+
+    /// Playback volume, 0 (silent) to 1 (unchanged).
+    pub fn set_volume(&self, volume: f32) -> Result<(), String> {
+        self.send(AudioCommand::SetVolume(volume.clamp(0.0, 1.0)))
+    }
+
+    /// Current key change in semitones.
+    pub fn key(&self) -> i32 {
+        self.state.key_offset()
+    }
+
+    /// Set the key change, clamped to ±`MAX_SEMITONES`, re-rendering the
+    /// queued audio from the current position. Returns the value applied.
+    pub fn set_key(&self, semitones: i32) -> i32 {
+        let semitones = semitones.clamp(-MAX_SEMITONES, MAX_SEMITONES);
+        let state = &self.state;
+        if state.key_offset() != semitones {
+            state.set_key_offset(semitones);
+            if state.duration_ms.load(Ordering::Relaxed) > 0 {
+                state.request_seek(state.position_ms.load(Ordering::Relaxed));
+            }
+            tracing::info!("[audio] Key offset {:+} semitones", semitones);
+        }
+        semitones
+    }
+
+    /// Key change, clamped like `set_key`, that the song with one of
+    /// `files` starts in whenever it opens, until the next call.
+    pub fn arm_song_key(&self, files: &[String], semitones: i32) {
+        let files = files.iter().map(|f| path_key(f)).collect();
+        if let Ok(mut armed) = self.armed_key.lock() {
+            *armed = Some((files, semitones.clamp(-MAX_SEMITONES, MAX_SEMITONES)));
+        }
+    }
+
+    /// Set the key `file_path` starts in when it opens or is moved on to:
+    /// the armed key if it is the singer's song, 0 for anything else (a
+    /// filler song in between).
+    fn prepare_key(&self, file_path: &str) {
+        let key = path_key(file_path);
+        let semitones = self
+            .armed_key
+            .lock()
+            .ok()
+            .and_then(|armed| armed.as_ref().filter(|(files, _)| files.contains(&key)).map(|(_, semitones)| *semitones))
+            .unwrap_or(0);
+        self.state.set_next_key_offset(semitones);
+    }
+
+    /// Configured primary output device id, if any.
+    pub fn primary_output(&self) -> Option<String> {
+        self.outputs.lock().unwrap_or_else(|e| e.into_inner()).config.primary.clone()
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.state.is_playing.load(Ordering::Relaxed)
+    }
+
+    /// Input channel of `device_name` that carries the mic, from its
+    /// channel map; `None` mixes all channels.
+    pub fn mic_channel(&self, device_name: &str) -> Option<u16> {
+        let maps = self.channel_maps.lock().unwrap_or_else(|e| e.into_inner());
+        maps.get(device_name).and_then(|m| m.mic_inputs.first().copied())
+    }
+
+    /// Bumped each time playback moves on to a staged song.
+    pub fn track_seq(&self) -> u64 {
+        self.state.track_seq.load(Ordering::Relaxed)
+    }
+
+    /// The staged song playback has moved on to, once; it is the current
+    /// song from now on.
+    pub fn take_staged_path(&self) -> Option<String> {
+        let staged = self.staged_path.lock().unwrap_or_else(|e| e.into_inner()).take();
+        if staged.is_some() {
+            self.set_current_path(staged.clone());
+        }
+        staged
+    }
+
+    fn set_staged_path(&self, file_path: Option<String>) {
+        *self.staged_path.lock().unwrap_or_else(|e| e.into_inner()) = file_path;
+    }
+
+    /// The song opened on the native player, if any.
+    pub fn current_path(&self) -> Option<String> {
+        self.current_path.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// The song open and not yet over (playing or paused); one that
+    /// played to its end does not count.
+    fn unfinished_song(&self) -> Option<String> {
+        self.current_path().filter(|_| !self.state.played_to_end())
+    }
+
+    fn set_current_path(&self, file_path: Option<String>) {
+        *self.current_path.lock().unwrap_or_else(|e| e.into_inner()) = file_path;
+    }
+
+    pub fn position(&self) -> AudioPosition {
+        AudioPosition::new(
+            self.state.position_ms.load(Ordering::Relaxed),
+            self.state.duration_ms.load(Ordering::Relaxed),
+            self.state.is_playing.load(Ordering::Relaxed),
+        )
+    }
 }
 
 impl Drop for AudioState {
@@ -79,10 +323,14 @@ impl Drop for AudioState {
 
 fn run_audio_thread(
     rx: mpsc::Receiver<AudioCommand>,
-    shared_state: Arc<Mutex<PlaybackState>>,
+    shared_state: Arc<PlaybackState>,
+    channel_maps: SharedChannelMaps,
+    outputs: SharedOutputSettings,
 ) {
-    let mut player = NativeAudioPlayer::with_shared_state(shared_state.clone());
+    let mut player = NativeAudioPlayer::with_shared_state(shared_state.clone(), channel_maps, outputs);
     let mut ended_emitted = false;
+    let mut device_lost_reported = false;
+    let mut track_seq = shared_state.track_seq.load(Ordering::Relaxed);
 
     // Channels for streaming events to the frontend (set on each Play command).
     let mut time_update_ch: Option<Channel<u64>> = None;
@@ -94,21 +342,53 @@ fn run_audio_thread(
             Ok(AudioCommand::Play {
                 file_path,
                 device_id,
+                track_gain,
                 on_time_update,
                 on_ended,
                 on_error,
             }) => {
                 ended_emitted = false;
+                shared_state.set_track_gain(track_gain);
                 time_update_ch = Some(on_time_update);
                 ended_ch = Some(on_ended);
                 error_ch = Some(on_error);
                 if let Err(e) = player.play_file(&file_path, &device_id) {
-                    eprintln!("Play failed for '{}': {}", file_path, e);
+                    tracing::error!("Play failed for '{}': {}", file_path, e);
                     if let Some(ch) = &error_ch {
                         let _ = ch.send(e.to_string());
                     }
                 }
             }
+            Ok(AudioCommand::Load { file_path, device_id, track_gain, reply }) => {
+                ended_emitted = false;
+                shared_state.set_track_gain(track_gain);
+                // Loaded songs report through `audio://position`
+                time_update_ch = None;
+                ended_ch = None;
+                error_ch = None;
+                let result = player.load_file(&file_path, &device_id);
+                if let Err(e) = &result {
+                    tracing::error!("[audio] Load failed for '{}': {}", file_path, e);
+                }
+                let _ = reply.send(result.map(|_| shared_state.duration_ms.load(Ordering::Relaxed)));
+            }
+            Ok(AudioCommand::StageNext { file_path, track_gain, reply }) => {
+                let result = player.stage_next(file_path.as_deref(), track_gain);
+                if let Err(e) = &result {
+                    tracing::warn!("[audio] Could not stage next song: {}", e);
+                }
+                let _ = reply.send(result);
+            }
+            Ok(AudioCommand::PlayBuffer { audio, device_id, output_channels }) => {
+                ended_emitted = false;
+                // Test signals are fire-and-forget: no frontend channels attached
+                time_update_ch = None;
+                ended_ch = None;
+                error_ch = None;
+                if let Err(e) = player.play_decoded(audio, &device_id, output_channels) {
+                    tracing::error!("[audio] Test signal playback failed: {}", e);
+                }
+            }
             Ok(AudioCommand::Pause) => {
                 player.pause();
             }
@@ -129,32 +409,62 @@ fn run_audio_thread(
                 ended_ch = None;
                 error_ch = None;
             }
+            Ok(AudioCommand::DevicesChanged) => {
+                match player.try_reconnect() {
+                    Ok(true) => device_lost_reported = false,
+                    Ok(false) => {}
+                    Err(e) => {
+                        tracing::error!("[audio] Reconnect failed: {}", e);
+                        if let Some(ch) = &error_ch {
+                            let _ = ch.send(format!("Audio device reconnect failed: {}", e));
+                        }
+                    }
+                }
+            }
+            Ok(AudioCommand::OutputsChanged) => {
+                if let Err(e) = player.reload_outputs() {
+                    tracing::warn!("[audio] Secondary output unavailable: {}", e);
+                    if let Some(ch) = &error_ch {
+                        let _ = ch.send(format!("Secondary output unavailable: {}", e));
+                    }
+                }
+            }
             Ok(AudioCommand::Shutdown) => {
                 player.stop();
                 break;
             }
             Err(RecvTimeoutError::Timeout) => {
                 // Poll shared state for event emission
-                let Ok(state) = shared_state.lock() else {
-                    // Mutex poisoned — another thread panicked. Exit audio thread gracefully.
-                    eprintln!("[audio] Shared state mutex poisoned, exiting audio thread");
-                    break;
-                };
+                let state = &*shared_state;
+                let is_playing = state.is_playing.load(Ordering::Relaxed);
+                let position_ms = state.position_ms.load(Ordering::Acquire);
+
+                // The callback moved on to the staged song
+                let seq = state.track_seq.load(Ordering::Relaxed);
+                if seq != track_seq {
+                    track_seq = seq;
+                    ended_emitted = false;
+                    player.on_track_switched();
+                }
 
                 // Send periodic time-update while playing via Channel IPC
-                if state.is_playing {
+                if is_playing {
                     if let Some(ch) = &time_update_ch {
-                        let _ = ch.send(state.position_ms);
+                        let _ = ch.send(position_ms);
+                    }
+                }
+
+                // Report a lost output device once; playback resumes automatically
+                // when the device monitor sees it come back.
+                if state.device_lost.load(Ordering::Relaxed) && !device_lost_reported {
+                    if let Some(ch) = &error_ch {
+                        let _ = ch.send("Audio output device disconnected — waiting for it to reconnect".to_string());
                     }
+                    device_lost_reported = true;
                 }
 
                 // Detect playback ended (set by the cpal callback inside player)
-                if !ended_emitted
-                    && !state.is_playing
-                    && state.duration_ms > 0
-                    && state.position_ms >= state.duration_ms
-                {
-                    drop(state); // release lock before sending
+                if !ended_emitted && state.played_to_end() {
                     if let Some(ch) = ended_ch.take() {
                         let _ = ch.send(());
                     }
@@ -181,6 +491,12 @@ pub fn audio_list_devices() -> Result<Vec<AudioDeviceInfo>, String> {
     devices::list_output_devices()
 }
 
+/// List all available audio input (microphone) devices.
+#[tauri::command]
+pub fn audio_list_input_devices() -> Result<Vec<AudioDeviceInfo>, String> {
+    devices::list_input_devices()
+}
+
 /// Get the default output device info.
 #[tauri::command]
 pub fn audio_get_default_device() -> Result<AudioDeviceInfo, String> {
@@ -193,87 +509,554 @@ pub fn audio_get_default_device() -> Result<AudioDeviceInfo, String> {
 #[tauri::command]
 pub fn audio_play_file(
     app: AppHandle,
+    webview: tauri::Webview,
     file_path: String,
     device_id: String,
     on_time_update: Channel<u64>,
     on_ended: Channel<()>,
     on_error: Channel<String>,
 ) -> Result<(), String> {
+    require_webview(&webview, Capability::ControlPlayback)?;
+    let track_gain = loudness::playback_gain(&app, &file_path);
     let audio_state = app.state::<AudioState>();
+    audio_state.prepare_key(&file_path);
+    audio_state.set_staged_path(None);
+    audio_state.set_current_path(Some(file_path.clone()));
     let tx = audio_state.command_tx.lock().map_err(|e| e.to_string())?;
     tx.send(AudioCommand::Play {
-        file_path,
+        file_path: file_path.clone(),
         device_id,
+        track_gain,
         on_time_update,
         on_ended,
         on_error,
     })
-    .map_err(|e| e.to_string())
+    .map_err(|e| e.to_string())?;
+    native_video::open(&app, &file_path);
+    Ok(())
 }
 
-/// Pause native audio playback.
+/// `audio_load` without the capability check, for in-process callers
+/// (`multiroom` followers).
+pub(crate) async fn load_track(app: AppHandle, file_path: String, device_id: Option<String>) -> Result<u64, String> {
+    let (reply, result) = mpsc::channel();
+    app.state::<AudioState>().prepare_key(&file_path);
+    app.state::<AudioState>().set_staged_path(None);
+    app.state::<AudioState>().send(AudioCommand::Load {
+        track_gain: loudness::playback_gain(&app, &file_path),
+        file_path: file_path.clone(),
+        device_id: device_id.unwrap_or_default(),
+        reply,
+    })?;
+    let duration_ms = tauri::async_runtime::spawn_blocking(move || {
+        result.recv_timeout(Duration::from_secs(10)).map_err(|e| format!("Audio thread did not answer: {}", e))?
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+    app.state::<AudioState>().set_current_path(Some(file_path.clone()));
+    native_video::open(&app, &file_path);
+    Ok(duration_ms)
+}
+
+/// Open a file on the native player without starting it; `audio_play`
+/// starts it. Empty `device_id` means the configured primary output.
+/// Returns the duration in ms. Video files are shown natively in the
+/// player window when it is open (see `media::native_video`).
+#[tauri::command]
+pub async fn audio_load(
+    app: AppHandle,
+    webview: tauri::Webview,
+    file_path: String,
+    device_id: Option<String>,
+) -> Result<u64, String> {
+    require_webview(&webview, Capability::ControlPlayback)?;
+    load_track(app, file_path, device_id).await
+}
+
+/// `audio_play` without the capability check.
+pub(crate) fn play(app: AppHandle) -> Result<(), String> {
+    app.state::<AudioState>().send(AudioCommand::Resume)?;
+    native_video::follow(&app);
+    Ok(())
+}
+
+/// Start (or resume) the loaded track.
 #[tauri::command]
-pub fn audio_pause(app: AppHandle) -> Result<(), String> {
+pub fn audio_play(app: AppHandle, webview: tauri::Webview) -> Result<(), String> {
+    require_webview(&webview, Capability::ControlPlayback)?;
+    play(app)
+}
+
+/// `audio_pause` without the capability check.
+pub(crate) fn pause(app: AppHandle) -> Result<(), String> {
     let audio_state = app.state::<AudioState>();
     let tx = audio_state.command_tx.lock().map_err(|e| e.to_string())?;
-    tx.send(AudioCommand::Pause).map_err(|e| e.to_string())
+    tx.send(AudioCommand::Pause).map_err(|e| e.to_string())?;
+    native_video::follow(&app);
+    Ok(())
+}
+
+/// Pause native audio playback.
+#[tauri::command]
+pub fn audio_pause(app: AppHandle, webview: tauri::Webview) -> Result<(), String> {
+    require_webview(&webview, Capability::ControlPlayback)?;
+    pause(app)
 }
 
 /// Resume native audio playback.
 #[tauri::command]
-pub fn audio_resume(app: AppHandle) -> Result<(), String> {
+pub fn audio_resume(app: AppHandle, webview: tauri::Webview) -> Result<(), String> {
+    require_webview(&webview, Capability::ControlPlayback)?;
     let audio_state = app.state::<AudioState>();
     let tx = audio_state.command_tx.lock().map_err(|e| e.to_string())?;
-    tx.send(AudioCommand::Resume).map_err(|e| e.to_string())
+    tx.send(AudioCommand::Resume).map_err(|e| e.to_string())?;
+    native_video::follow(&app);
+    Ok(())
 }
 
-/// Seek to a position in milliseconds.
-#[tauri::command]
-pub fn audio_seek(app: AppHandle, position_ms: u64) -> Result<(), String> {
+/// `audio_seek` without the capability check.
+pub(crate) fn seek(app: AppHandle, position_ms: u64) -> Result<(), String> {
     let audio_state = app.state::<AudioState>();
     let tx = audio_state.command_tx.lock().map_err(|e| e.to_string())?;
     tx.send(AudioCommand::Seek(position_ms))
-        .map_err(|e| e.to_string())
+        .map_err(|e| e.to_string())?;
+    native_video::seek(&app, position_ms);
+    Ok(())
+}
+
+/// Seek to a position in milliseconds.
+#[tauri::command]
+pub fn audio_seek(app: AppHandle, webview: tauri::Webview, position_ms: u64) -> Result<(), String> {
+    require_webview(&webview, Capability::ControlPlayback)?;
+    seek(app, position_ms)
 }
 
 /// Set volume (0.0 – 1.0).
 #[tauri::command]
-pub fn audio_set_volume(app: AppHandle, volume: f32) -> Result<(), String> {
+pub fn audio_set_volume(app: AppHandle, webview: tauri::Webview, volume: f32) -> Result<(), String> {
+    require_webview(&webview, Capability::ControlPlayback)?;
     let audio_state = app.state::<AudioState>();
     let tx = audio_state.command_tx.lock().map_err(|e| e.to_string())?;
     tx.send(AudioCommand::SetVolume(volume))
         .map_err(|e| e.to_string())
 }
 
-/// Stop native audio playback.
+/// Change the key of the playing track by `semitones` (tempo unchanged),
+/// clamped to ±12. Applies at once: the queued audio is re-rendered from
+/// the current position. Reset to 0 whenever another file is opened.
+#[tauri::command]
+pub fn set_key_offset(app: AppHandle, webview: tauri::Webview, semitones: i32) -> Result<i32, String> {
+    require_webview(&webview, Capability::ControlPlayback)?;
+    Ok(app.state::<AudioState>().set_key(semitones))
+}
+
+/// Attenuate centre-panned vocals of the playing track (and the ones
+/// after it) with `strength` 0..=1, default 1. `band_pass` (default on)
+/// cancels only the vocal band, keeping centred bass and kick. Applies
+/// at once, like `set_key_offset`. Stereo sources only.
 #[tauri::command]
-pub fn audio_stop(app: AppHandle) -> Result<(), String> {
+pub fn set_vocal_removal(
+    app: AppHandle,
+    webview: tauri::Webview,
+    enabled: bool,
+    strength: Option<f32>,
+    band_pass: Option<bool>,
+) -> Result<f32, String> {
+    require_webview(&webview, Capability::ControlPlayback)?;
+    let strength = if enabled { strength.unwrap_or(1.0).clamp(0.0, 1.0) } else { 0.0 };
+    let band_pass = band_pass.unwrap_or(true);
+    let audio_state = app.state::<AudioState>();
+    let state = &audio_state.state;
+    if state.vocal_removal() != (strength, band_pass) {
+        state.set_vocal_removal(strength, band_pass);
+        if state.duration_ms.load(Ordering::Relaxed) > 0 {
+            state.request_seek(state.position_ms.load(Ordering::Relaxed));
+        }
+        tracing::info!("[audio] Vocal removal {:.0}%{}", strength * 100.0, if band_pass { " (vocal band)" } else { "" });
+    }
+    Ok(strength)
+}
+
+/// Stage the song to continue with when the current one ends, per the
+/// transition setting; `None` withdraws it. Returns its duration in ms.
+/// Playback then moves on by itself and `audio://track-changed` follows.
+#[tauri::command]
+pub async fn audio_preload_next(
+    app: AppHandle,
+    webview: tauri::Webview,
+    file_path: Option<String>,
+) -> Result<u64, String> {
+    require_webview(&webview, Capability::ControlPlayback)?;
+    let track_gain = file_path.as_deref().map_or(1.0, |path| loudness::playback_gain(&app, path));
+    if let Some(path) = &file_path {
+        app.state::<AudioState>().prepare_key(path);
+    }
+    let (reply, result) = mpsc::channel();
+    app.state::<AudioState>().send(AudioCommand::StageNext { file_path: file_path.clone(), track_gain, reply })?;
+    let duration_ms = tauri::async_runtime::spawn_blocking(move || {
+        result.recv_timeout(Duration::from_secs(10)).map_err(|e| format!("Audio thread did not answer: {}", e))?
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+    app.state::<AudioState>().set_staged_path(file_path);
+    Ok(duration_ms)
+}
+
+/// How playback continues into a staged song: "off", "gap" (`seconds` of
+/// silence, 0 = gapless) or "crossfade" (over `seconds`). Persisted.
+#[tauri::command]
+pub fn set_transition(
+    app: AppHandle,
+    webview: tauri::Webview,
+    mode: String,
+    seconds: Option<f32>,
+) -> Result<Transition, String> {
+    require_webview(&webview, Capability::ControlPlayback)?;
+    let transition = Transition {
+        mode: TransitionMode::parse(&mode)?,
+        seconds: seconds.unwrap_or(0.0).clamp(0.0, MAX_SECONDS),
+    };
+    {
+        let db = app.state::<DbState>();
+        let conn = db.conn.lock().map_err(|e| e.to_string())?;
+        transition::save(&conn, &transition)?;
+    }
+    app.state::<AudioState>().state.set_transition(transition);
+    tracing::info!("[audio] Transition {:?} ({:.1} s)", transition.mode, transition.seconds);
+    Ok(transition)
+}
+
+/// `audio_stop` without the capability check.
+pub(crate) fn stop(app: AppHandle) -> Result<(), String> {
     let audio_state = app.state::<AudioState>();
+    audio_state.set_staged_path(None);
+    audio_state.set_current_path(None);
     let tx = audio_state.command_tx.lock().map_err(|e| e.to_string())?;
-    tx.send(AudioCommand::Stop).map_err(|e| e.to_string())
+    tx.send(AudioCommand::Stop).map_err(|e| e.to_string())?;
+    native_video::close(&app);
+    Ok(())
+}
+
+/// Stop native audio playback.
+#[tauri::command]
+pub fn audio_stop(app: AppHandle, webview: tauri::Webview) -> Result<(), String> {
+    require_webview(&webview, Capability::ControlPlayback)?;
+    stop(app)
 }
 
 /// Get the current playback position in milliseconds.
 #[tauri::command]
 pub fn audio_get_position(app: AppHandle) -> Result<u64, String> {
     let audio_state = app.state::<AudioState>();
-    let state = audio_state.state.lock().map_err(|e| e.to_string())?;
-    Ok(state.position_ms)
+    Ok(audio_state.state.position_ms.load(Ordering::Relaxed))
+}
+
+/// Position, duration and play state; the same payload as `audio://position`.
+#[tauri::command]
+pub fn audio_position(app: AppHandle) -> AudioPosition {
+    app.state::<AudioState>().position()
 }
 
 /// Get the current playback state.
 #[tauri::command]
 pub fn audio_get_state(app: AppHandle) -> Result<AudioPlaybackState, String> {
     let audio_state = app.state::<AudioState>();
-    let state = audio_state.state.lock().map_err(|e| e.to_string())?;
+    let state = &audio_state.state;
     Ok(AudioPlaybackState {
-        position_ms: state.position_ms,
-        duration_ms: state.duration_ms,
-        is_playing: state.is_playing,
-        volume: state.volume,
+        position_ms: state.position_ms.load(Ordering::Relaxed),
+        duration_ms: state.duration_ms.load(Ordering::Relaxed),
+        is_playing: state.is_playing.load(Ordering::Relaxed),
+        volume: state.volume(),
+        device_lost: state.device_lost.load(Ordering::Relaxed),
+        key_offset: state.key_offset(),
+        vocal_removal: state.vocal_removal().0,
     })
 }
 
+/// Get the channel map configured for a device (default layout if none).
+#[tauri::command]
+pub fn audio_get_channel_map(app: AppHandle, device_name: String) -> Result<ChannelMap, String> {
+    let audio_state = app.state::<AudioState>();
+    let maps = audio_state.channel_maps.lock().map_err(|e| e.to_string())?;
+    Ok(maps.get(&device_name).cloned().unwrap_or_default())
+}
+
+/// Set (and persist) the channel map for a device. Takes effect the next time
+/// a stream is opened on that device.
+#[tauri::command]
+pub fn audio_set_channel_map(app: AppHandle, webview: tauri::Webview, device_name: String, map: ChannelMap) -> Result<(), String> {
+    require_webview(&webview, Capability::ConfigureAudio)?;
+    map.validate()?;
+    {
+        let db = app.state::<DbState>();
+        let conn = db.conn.lock().map_err(|e| e.to_string())?;
+        channel_map::save(&conn, &device_name, &map)?;
+    }
+    let audio_state = app.state::<AudioState>();
+    let mut maps = audio_state.channel_maps.lock().map_err(|e| e.to_string())?;
+    if map == ChannelMap::default() {
+        maps.remove(&device_name);
+    } else {
+        maps.insert(device_name, map);
+    }
+    Ok(())
+}
+
+/// Current primary/secondary output configuration.
+#[tauri::command]
+pub fn audio_get_outputs(app: AppHandle) -> Result<OutputConfig, String> {
+    let audio_state = app.state::<AudioState>();
+    let outputs = audio_state.outputs.lock().map_err(|e| e.to_string())?;
+    Ok(outputs.config.clone())
+}
+
+/// Configure (and persist) the outputs: `primary` is used when
+/// `audio_play_file` gets an empty device id, `secondary` (if any) plays
+/// alongside it with its own mix — e.g. the full mix on the PA and a
+/// vocals-lowered mix with click on the stage monitors. Applies to the
+/// playing track immediately for the secondary, on the next track for the
+/// primary.
+#[tauri::command]
+pub fn configure_outputs(
+    app: AppHandle,
+    webview: tauri::Webview,
+    primary: Option<String>,
+    secondary: Option<String>,
+    mix_profiles: Option<MixProfiles>,
+) -> Result<OutputConfig, String> {
+    require_webview(&webview, Capability::ConfigureAudio)?;
+    let config = OutputConfig {
+        primary: primary.filter(|d| !d.is_empty()),
+        secondary: secondary.filter(|d| !d.is_empty()),
+        mix_profiles: mix_profiles.unwrap_or_default(),
+    };
+    config.validate()?;
+    {
+        let db = app.state::<DbState>();
+        let conn = db.conn.lock().map_err(|e| e.to_string())?;
+        output_mix::save(&conn, &config)?;
+    }
+    let audio_state = app.state::<AudioState>();
+    audio_state.outputs.lock().map_err(|e| e.to_string())?.config = config.clone();
+    audio_state.send(AudioCommand::OutputsChanged)?;
+    Ok(config)
+}
+
+/// Make `device` the primary output, keeping the secondary and the mixes
+/// (used for a device pinned in `config.toml`). No-op if it already is.
+pub fn set_primary_output(app: &AppHandle, device: &str) -> Result<(), String> {
+    let audio_state = app.state::<AudioState>();
+    let config = {
+        let mut outputs = audio_state.outputs.lock().map_err(|e| e.to_string())?;
+        if outputs.config.primary.as_deref() == Some(device) {
+            return Ok(());
+        }
+        outputs.config.primary = Some(device.to_string()).filter(|d| !d.is_empty());
+        outputs.config.clone()
+    };
+    {
+        let db = app.state::<DbState>();
+        let conn = db.conn.lock().map_err(|e| e.to_string())?;
+        output_mix::save(&conn, &config)?;
+    }
+    audio_state.send(AudioCommand::OutputsChanged)
+}
+
+/// Set the beat grid for mix profiles with a click (usually from the song's
+/// BPM and gap), or clear it. Takes effect at once on every output.
+#[tauri::command]
+pub fn audio_set_click_track(app: AppHandle, webview: tauri::Webview, click: Option<ClickTrack>) -> Result<(), String> {
+    require_webview(&webview, Capability::ControlPlayback)?;
+    if let Some(click) = &click {
+        if !(20.0..=400.0).contains(&click.bpm) {
+            return Err(format!("Click tempo {} bpm out of range (20–400)", click.bpm));
+        }
+    }
+    let audio_state = app.state::<AudioState>();
+    audio_state.state.set_click(click);
+    Ok(())
+}
+
+/// Play a test signal for speaker checks.
+///
+/// * `output` — device id ("default" or "<host_name>:<device_index>")
+/// * `channel` — 0-based device channel to play on; `None` plays on all channels
+/// * `frequency` — sine frequency in Hz (default 1000, ignored for pink noise)
+/// * `signal` — `"sine"` (default) or `"pink_noise"`
+/// * `duration_ms` — default 2000, max 30000
+///
+/// Stop early with `audio_stop`. Refused while a song is open (playing or
+/// paused, not once it played to its end): the signal replaces whatever
+/// the player has loaded.
+#[tauri::command]
+pub fn audio_play_test_tone(
+    app: AppHandle,
+    webview: tauri::Webview,
+    output: String,
+    channel: Option<u16>,
+    frequency: Option<f64>,
+    signal: Option<TestSignal>,
+    duration_ms: Option<u64>,
+) -> Result<(), String> {
+    require_webview(&webview, Capability::ConfigureAudio)?;
+    if let Some(song) = app.state::<AudioState>().unfinished_song() {
+        return Err(format!("Stop {} before playing a test signal", song));
+    }
+    let duration_ms = duration_ms.unwrap_or(2000).clamp(100, 30_000);
+    let frequency = frequency.unwrap_or(1000.0);
+    if !(20.0..=20_000.0).contains(&frequency) {
+        return Err(format!("Test tone frequency {} Hz out of range (20–20000)", frequency));
+    }
+
+    let samples = match signal.unwrap_or(TestSignal::Sine) {
+        TestSignal::Sine => test_tone::sine(frequency, duration_ms, TEST_TONE_SAMPLE_RATE),
+        TestSignal::PinkNoise => test_tone::pink_noise(duration_ms, TEST_TONE_SAMPLE_RATE),
+    };
+
+    let audio_state = app.state::<AudioState>();
+    let tx = audio_state.command_tx.lock().map_err(|e| e.to_string())?;
+    tx.send(AudioCommand::PlayBuffer {
+        audio: DecodedAudio {
+            samples,
+            sample_rate: TEST_TONE_SAMPLE_RATE,
+            channels: 1,
+            duration_ms,
+        },
+        device_id: output,
+        output_channels: channel.map(|c| vec![c]),
+    })
+    .map_err(|e| e.to_string())?;
+    drop(tx);
+    // The song that ended is unloaded now
+    audio_state.set_current_path(None);
+    Ok(())
+}
+
+/// Get the stored scoring offset (ms) for an input device.
+#[tauri::command]
+pub fn audio_get_device_offset(app: AppHandle, device_name: String) -> Result<i64, String> {
+    let db = app.state::<DbState>();
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    device_offsets::offset_for_device(&conn, &device_name)
+}
+
+/// Store the scoring offset (ms) for an input device, from calibration or
+/// manual entry. Returns the clamped value that was saved.
+#[tauri::command]
+pub fn audio_set_device_offset(
+    app: AppHandle,
+    webview: tauri::Webview,
+    device_name: String,
+    offset_ms: i64,
+) -> Result<i64, String> {
+    require_webview(&webview, Capability::ConfigureAudio)?;
+    let db = app.state::<DbState>();
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    device_offsets::set_offset_for_device(&conn, &device_name, offset_ms)
+}
+
+/// Get all stored per-device scoring offsets.
+#[tauri::command]
+pub fn audio_get_device_offsets(app: AppHandle) -> Result<std::collections::HashMap<String, i64>, String> {
+    let db = app.state::<DbState>();
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    device_offsets::all_offsets(&conn)
+}
+
+/// Record the selected input device and return its scoring offset, so the
+/// scoring engine picks up the right calibration on every device switch.
+#[tauri::command]
+pub fn audio_select_input_device(app: AppHandle, webview: tauri::Webview, device_name: String) -> Result<i64, String> {
+    require_webview(&webview, Capability::ConfigureAudio)?;
+    let db = app.state::<DbState>();
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT OR REPLACE INTO app_settings (key, value) VALUES (?1, ?2)",
+        (device_offsets::SELECTED_INPUT_KEY, &device_name),
+    )
+    .map_err(|e| format!("Failed to save selected input: {}", e))?;
+    device_offsets::offset_for_device(&conn, &device_name)
+}
+
+/// Scoring offset of the currently selected input device.
+#[tauri::command]
+pub fn audio_get_active_offset(app: AppHandle) -> Result<i64, String> {
+    let db = app.state::<DbState>();
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    device_offsets::active_offset(&conn)
+}
+
+/// Run the guided level calibration on an input device.
+///
+/// Records `duration_ms` (default 5000, 2000–20000) while the singer performs
+/// at show volume, streaming live levels via `on_progress`. On a valid result
+/// the measured speech level is stored as the device's reference level (see
+/// `audio_get_reference_level`). The mic channel comes from the device's channel
+/// map (first mic input); without a map all input channels are mixed.
+#[tauri::command]
+pub fn audio_run_level_calibration(
+    app: AppHandle,
+    webview: tauri::Webview,
+    device_id: String,
+    duration_ms: Option<u64>,
+    on_progress: Channel<LevelCalibrationProgress>,
+    on_complete: Channel<LevelCalibrationResult>,
+    on_error: Channel<String>,
+) -> Result<String, String> {
+    require_webview(&webview, Capability::ConfigureAudio)?;
+    let duration_ms = duration_ms.unwrap_or(5000).clamp(2000, 20_000);
+    let device = devices::resolve_input_device(&device_id)?;
+
+    std::thread::Builder::new()
+        .name("karaoke-level-calibration".into())
+        .spawn(move || {
+            let device_name = cpal::traits::DeviceTrait::name(&device).unwrap_or_default();
+            let mic_channel = {
+                let audio_state = app.state::<AudioState>();
+                let maps = audio_state.channel_maps.lock().unwrap_or_else(|e| e.into_inner());
+                maps.get(&device_name).and_then(|m| m.mic_inputs.first().copied())
+            };
+
+            let result = level_calibration::run_calibration(&device, mic_channel, duration_ms, |p| {
+                let _ = on_progress.send(p);
+            });
+
+            match result {
+                Ok(result) => {
+                    if result.valid {
+                        let db = app.state::<DbState>();
+                        let saved = db.conn.lock().map_err(|e| e.to_string()).and_then(|conn| {
+                            level_calibration::save_reference_level(&conn, &device_name, result.speech_rms_dbfs)
+                        });
+                        if let Err(e) = saved {
+                            let _ = on_error.send(e);
+                            return;
+                        }
+                    }
+                    tracing::info!(
+                        "[audio] Level calibration for '{}': {:.1} dBFS RMS, peak {:.1} dBFS, gain {:+.1} dB",
+                        device_name, result.speech_rms_dbfs, result.peak_dbfs, result.recommended_gain_db
+                    );
+                    let _ = on_complete.send(result);
+                }
+                Err(e) => {
+                    let _ = on_error.send(e);
+                }
+            }
+        })
+        .map_err(|e| format!("Failed to spawn calibration thread: {}", e))?;
+
+    Ok("Level calibration started".to_string())
+}
+
+/// Calibrated reference level (dBFS) for an input device, if any.
+#[tauri::command]
+pub fn audio_get_reference_level(app: AppHandle, device_name: String) -> Result<Option<f64>, String> {
+    let db = app.state::<DbState>();
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    level_calibration::reference_level(&conn, &device_name)
+}
+
 /// Serializable playback state for the frontend.
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct AudioPlaybackState {
@@ -281,4 +1064,10 @@ pub struct AudioPlaybackState {
     pub duration_ms: u64,
     pub is_playing: bool,
     pub volume: f32,
+    /// True while the output device is disconnected and awaiting reconnect.
+    pub device_lost: bool,
+    /// Key change in semitones (`set_key_offset`).
+    pub key_offset: i32,
+    /// Vocal removal strength, 0 = off (`set_vocal_removal`).
+    pub vocal_removal: f32,
 }
@@ -0,0 +1,118 @@
+//! Per-input-device timing offsets for scoring.
+//!
+//! Every mic path has its own latency (USB interface, Bluetooth headset,
+//! webcam mic…). The offset measured by calibration — or entered manually —
+//! is stored per device name in `app_settings` under
+//! `scoring_offset_ms:<device name>` and looked up whenever that device is
+//! selected, so calibration survives restarts and device swaps.
+//!
+//! The output side of the latency (what lyric sync compensates) is a
+//! single `output_latency_ms` setting, written by latency calibration.
+
+use std::collections::HashMap;
+
+use rusqlite::Connection;
+
+/// Settings key prefix for per-device offsets.
+const SETTINGS_PREFIX: &str = "scoring_offset_ms:";
+
+/// Settings key holding the name of the currently selected input device.
+pub const SELECTED_INPUT_KEY: &str = "audio_selected_input";
+
+/// Settings key of the calibrated output latency.
+const OUTPUT_LATENCY_KEY: &str = "output_latency_ms";
+
+/// Accepted offset range in ms (matches the manual slider in the UI).
+pub const MAX_OFFSET_MS: i64 = 1000;
+
+/// Offset stored for `device_name`, or 0 if none was ever saved.
+pub fn offset_for_device(conn: &Connection, device_name: &str) -> Result<i64, String> {
+    let key = format!("{}{}", SETTINGS_PREFIX, device_name);
+    let result = conn.query_row(
+        "SELECT value FROM app_settings WHERE key = ?1",
+        [&key],
+        |row| row.get::<_, String>(0),
+    );
+    match result {
+        Ok(value) => value
+            .trim()
+            .parse::<i64>()
+            .map_err(|e| format!("Invalid stored offset for '{}': {}", device_name, e)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(0),
+        Err(e) => Err(format!("offset lookup failed: {}", e)),
+    }
+}
+
+/// Store the offset for `device_name`, clamped to ±`MAX_OFFSET_MS`.
+/// Returns the value actually stored.
+pub fn set_offset_for_device(conn: &Connection, device_name: &str, offset_ms: i64) -> Result<i64, String> {
+    let clamped = offset_ms.clamp(-MAX_OFFSET_MS, MAX_OFFSET_MS);
+    conn.execute(
+        "INSERT OR REPLACE INTO app_settings (key, value) VALUES (?1, ?2)",
+        (format!("{}{}", SETTINGS_PREFIX, device_name), clamped.to_string()),
+    )
+    .map_err(|e| format!("Failed to save device offset: {}", e))?;
+    Ok(clamped)
+}
+
+/// All stored offsets, keyed by device name.
+pub fn all_offsets(conn: &Connection) -> Result<HashMap<String, i64>, String> {
+    let mut stmt = conn
+        .prepare("SELECT key, value FROM app_settings WHERE key LIKE ?1")
+        .map_err(|e| format!("list device offsets failed: {}", e))?;
+    let rows = stmt
+        .query_map([format!("{}%", SETTINGS_PREFIX)], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })
+        .map_err(|e| format!("list device offsets query failed: {}", e))?;
+
+    Ok(rows
+        .flatten()
+        .filter_map(|(key, value)| {
+            let offset = value.trim().parse::<i64>().ok()?;
+            Some((key[SETTINGS_PREFIX.len()..].to_string(), offset))
+        })
+        .collect())
+}
+
+/// Offset of the currently selected input device (0 if none selected).
+pub fn active_offset(conn: &Connection) -> Result<i64, String> {
+    let selected = conn.query_row(
+        "SELECT value FROM app_settings WHERE key = ?1",
+        [SELECTED_INPUT_KEY],
+        |row| row.get::<_, String>(0),
+    );
+    match selected {
+        Ok(name) => offset_for_device(conn, &name),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(0),
+        Err(e) => Err(format!("selected input lookup failed: {}", e)),
+    }
+}
+
+/// Calibrated output latency in ms (0 if never calibrated).
+pub fn output_latency(conn: &Connection) -> Result<i64, String> {
+    let result = conn.query_row(
+        "SELECT value FROM app_settings WHERE key = ?1",
+        [OUTPUT_LATENCY_KEY],
+        |row| row.get::<_, String>(0),
+    );
+    match result {
+        Ok(value) => value
+            .trim()
+            .parse::<i64>()
+            .map_err(|e| format!("Invalid stored output latency: {}", e)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(0),
+        Err(e) => Err(format!("output latency lookup failed: {}", e)),
+    }
+}
+
+/// Store the output latency, clamped to 0..=`MAX_OFFSET_MS`.
+pub fn set_output_latency(conn: &Connection, latency_ms: i64) -> Result<i64, String> {
+    let clamped = latency_ms.clamp(0, MAX_OFFSET_MS);
+    conn.execute(
+        "INSERT OR REPLACE INTO app_settings (key, value) VALUES (?1, ?2)",
+        (OUTPUT_LATENCY_KEY, clamped.to_string()),
+    )
+    .map_err(|e| format!("Failed to save output latency: {}", e))?;
+    Ok(clamped)
+}
@@ -1,7 +1,7 @@
 use cpal::traits::{DeviceTrait, HostTrait};
 use serde::Serialize;
 
-/// Serializable representation of an audio output device.
+/// Serializable representation of an audio device (output or input).
 #[derive(Debug, Clone, Serialize)]
 pub struct AudioDeviceInfo {
     /// Unique identifier: "<host_id>:<device_index>"
@@ -12,17 +12,34 @@ pub struct AudioDeviceInfo {
     pub host_name: String,
     /// Default sample rate in Hz.
     pub default_sample_rate: u32,
-    /// Maximum output channels.
+    /// Maximum output channels (input channels for capture devices).
     pub max_channels: u16,
 }
 
+/// Which side of the audio interface a device belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceDirection {
+    Output,
+    Input,
+}
+
 /// List all available output devices across all hosts.
 pub fn list_output_devices() -> Result<Vec<AudioDeviceInfo>, String> {
-    let mut result = Vec::new();
+    Ok(collect_devices(DeviceDirection::Output))
+}
+
+/// List all available input (capture) devices across all hosts.
+pub fn list_input_devices() -> Result<Vec<AudioDeviceInfo>, String> {
+    Ok(collect_devices(DeviceDirection::Input))
+}
 
-    let available_hosts = cpal::available_hosts();
+/// Enumerate devices of one direction across all hosts.
+/// Devices that cannot report a default config are skipped.
+fn collect_devices(direction: DeviceDirection) -> Vec<AudioDeviceInfo> {
+    let mut result = Vec::new();
 
-    for host_id in available_hosts {
+    for host_id in cpal::available_hosts() {
         let host_name = format!("{:?}", host_id);
 
         let host = match cpal::host_from_id(host_id) {
@@ -30,7 +47,11 @@ pub fn list_output_devices() -> Result<Vec<AudioDeviceInfo>, String> {
             Err(_) => continue,
         };
 
-        let devices = match host.output_devices() {
+        let devices = match direction {
+            DeviceDirection::Output => host.output_devices(),
+            DeviceDirection::Input => host.input_devices(),
+        };
+        let devices = match devices {
             Ok(d) => d,
             Err(_) => continue,
         };
@@ -41,7 +62,11 @@ pub fn list_output_devices() -> Result<Vec<AudioDeviceInfo>, String> {
                 Err(_) => format!("Unknown Device {}", idx),
             };
 
-            let default_config = match device.default_output_config() {
+            let default_config = match direction {
+                DeviceDirection::Output => device.default_output_config(),
+                DeviceDirection::Input => device.default_input_config(),
+            };
+            let default_config = match default_config {
                 Ok(c) => c,
                 Err(_) => continue, // skip devices we can't query
             };
@@ -56,7 +81,7 @@ pub fn list_output_devices() -> Result<Vec<AudioDeviceInfo>, String> {
         }
     }
 
-    Ok(result)
+    result
 }
 
 /// Get the default output device.
@@ -76,3 +101,51 @@ pub fn get_default_device() -> Result<AudioDeviceInfo, String> {
         max_channels: config.channels(),
     })
 }
+
+/// Find an output device by its human-readable name.
+///
+/// Device indices shift when hardware is plugged in or removed, so the
+/// `"<host>:<index>"` id captured at play time cannot be trusted after a
+/// hot-plug event. The name is stable across power cycles of the same
+/// interface. `host_name` is searched first; other hosts are a fallback.
+pub fn find_output_device_by_name(host_name: &str, device_name: &str) -> Option<cpal::Device> {
+    let mut hosts = cpal::available_hosts();
+    hosts.sort_by_key(|id| format!("{:?}", id) != host_name);
+
+    for host_id in hosts {
+        let Ok(host) = cpal::host_from_id(host_id) else { continue };
+        let Ok(devices) = host.output_devices() else { continue };
+        for device in devices {
+            if device.name().map(|n| n == device_name).unwrap_or(false) {
+                return Some(device);
+            }
+        }
+    }
+    None
+}
+
+/// Resolve an input device id (`"default"` or `"<host_name>:<device_index>"`,
+/// as returned by `list_input_devices`) to a cpal device.
+pub fn resolve_input_device(device_id: &str) -> Result<cpal::Device, String> {
+    if device_id == "default" {
+        return cpal::default_host()
+            .default_input_device()
+            .ok_or_else(|| "No default input device".to_string());
+    }
+
+    let (host_name, index) = device_id
+        .split_once(':')
+        .ok_or_else(|| format!("Invalid device_id format: {}", device_id))?;
+    let index: usize = index.parse().map_err(|_| "Invalid device index")?;
+
+    let host_id = cpal::available_hosts()
+        .into_iter()
+        .find(|id| format!("{:?}", id) == host_name)
+        .ok_or_else(|| format!("Audio host '{}' not available", host_name))?;
+    let host = cpal::host_from_id(host_id).map_err(|e| e.to_string())?;
+
+    host.input_devices()
+        .map_err(|e| format!("Cannot enumerate input devices: {}", e))?
+        .nth(index)
+        .ok_or_else(|| format!("Input device not found: {}", device_id))
+}
@@ -0,0 +1,453 @@
+//! Duplicate detection by audio fingerprint.
+//!
+//! Libraries merged from several drives hold the same recording under
+//! different names, tags and bitrates, which neither paths nor the
+//! artist/title check at import can tell. `find_duplicates` queues a
+//! background job (see `jobs`) that computes a Chromaprint fingerprint of
+//! the first `FINGERPRINT_MS` of every song's audio (kept in the analysis
+//! cache, so later runs only decode new files), then:
+//!
+//!   1. pairs songs sharing at least `MIN_SHARED_VALUES` fingerprint items
+//!      through an inverted index, so the library is never compared
+//!      all-against-all;
+//!   2. aligns each pair within ±`MAX_OFFSET` items (leading silence,
+//!      different intros) and scores the share of equal bits;
+//!   3. groups pairs scoring at least `MIN_SIMILARITY` transitively.
+//!
+//! Progress is published as `duplicates://progress`, the report as
+//! `duplicates://complete`; `get_duplicate_report` returns the last one.
+//! Nothing is deleted here: each group marks the copy with the highest
+//! bitrate as `keep`, and cleanup goes through the usual deletion commands.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+
+use rusqlite::Connection;
+use rusty_chromaprint::{Configuration, Fingerprinter};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+use super::analysis_cache::{AnalysisCache, CacheKind};
+use super::stream_decoder::{PcmSource, StreamingDecoder};
+use crate::access::{require_webview, Capability};
+use crate::db::DbState;
+use crate::events::{publish, AppEvent};
+use crate::jobs::{self, JobContext, JobSpec};
+use crate::scheduler::now_ms;
+
+pub const PROGRESS_EVENT: &str = "duplicates://progress";
+pub const COMPLETE_EVENT: &str = "duplicates://complete";
+
+/// Audio fingerprinted per song, as AcoustID does.
+const FINGERPRINT_MS: u64 = 120_000;
+/// Items two fingerprints must share to be compared at all.
+const MIN_SHARED_VALUES: u32 = 12;
+/// Items found in more songs than this (silence, hum) are not indexed.
+const MAX_POSTINGS: usize = 64;
+/// About 10 s at Chromaprint's ~8 items per second.
+const MAX_OFFSET: i64 = 80;
+/// Items that must overlap at the best offset.
+const MIN_OVERLAP: usize = 64;
+/// Share of equal bits; unrelated audio scores about 0.5.
+pub const MIN_SIMILARITY: f64 = 0.8;
+
+/// A song's fingerprint, as cached.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Fingerprint {
+    pub items: Vec<u32>,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateSong {
+    pub song_id: String,
+    pub title: String,
+    pub artist: String,
+    pub path: String,
+    pub duration_ms: u64,
+    pub size_bytes: Option<u64>,
+    /// Estimated from size and duration.
+    pub bitrate_kbps: Option<u64>,
+    /// The copy to keep: the highest bitrate in its group.
+    pub keep: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateGroup {
+    /// Lowest pair similarity in the group, 0–1.
+    pub similarity: f64,
+    pub songs: Vec<DuplicateSong>,
+}
+
+/// Payload of `duplicates://complete`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateReport {
+    pub groups: Vec<DuplicateGroup>,
+    pub fingerprinted: usize,
+    pub failed: usize,
+    pub cancelled: bool,
+    pub created_at: i64,
+}
+
+/// Payload of `duplicates://progress`, after each song.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FingerprintProgress {
+    pub done: usize,
+    pub total: usize,
+    pub song_id: String,
+    pub error: Option<String>,
+}
+
+/// Managed state: the last report.
+#[derive(Default)]
+pub struct DuplicateState {
+    report: Mutex<Option<DuplicateReport>>,
+}
+
+// ---------------------------------------------------------------------------
+// Fingerprinting
+// ---------------------------------------------------------------------------
+
+/// Fingerprint of the first `FINGERPRINT_MS` of `path`.
+pub fn fingerprint_file(path: &str) -> Result<Fingerprint, String> {
+    let mut source = StreamingDecoder::open(path)?;
+    let (sample_rate, channels) = (source.sample_rate(), source.channels().max(1));
+    let config = Configuration::preset_test2();
+    let mut printer = Fingerprinter::new(&config);
+    printer
+        .start(sample_rate, channels as u32)
+        .map_err(|e| format!("Cannot fingerprint {} Hz audio: {:?}", sample_rate, e))?;
+
+    let limit = (FINGERPRINT_MS * sample_rate as u64 / 1000) as usize * channels as usize;
+    let (mut fed, mut chunk, mut pcm) = (0, Vec::new(), Vec::new());
+    while fed < limit {
+        chunk.clear();
+        let more = source.read_chunk(&mut chunk)?;
+        let take = chunk.len().min(limit - fed);
+        pcm.clear();
+        pcm.extend(chunk[..take].iter().map(|&s| (s.clamp(-1.0, 1.0) * i16::MAX as f32) as i16));
+        printer.consume(&pcm);
+        fed += take;
+        if !more {
+            break;
+        }
+    }
+    printer.finish();
+
+    let items = printer.fingerprint().to_vec();
+    if items.len() < MIN_OVERLAP {
+        return Err("Too little audio to fingerprint".to_string());
+    }
+    Ok(Fingerprint { items, duration_ms: source.duration_ms() })
+}
+
+fn fingerprint_cached(cache: Option<&AnalysisCache>, path: &str) -> Result<Fingerprint, String> {
+    let key = cache.and_then(|_| AnalysisCache::key_for(path).ok());
+    if let (Some(cache), Some(key)) = (cache, key.as_ref()) {
+        if let Some(fingerprint) = cache.get::<Fingerprint>(key, CacheKind::Fingerprint, None) {
+            return Ok(fingerprint);
+        }
+    }
+    let fingerprint = fingerprint_file(path)?;
+    if let (Some(cache), Some(key)) = (cache, key.as_ref()) {
+        if let Err(e) = cache.put(key, CacheKind::Fingerprint, None, &fingerprint) {
+            tracing::warn!("[duplicates] {}", e);
+        }
+    }
+    Ok(fingerprint)
+}
+
+// ---------------------------------------------------------------------------
+// Matching
+// ---------------------------------------------------------------------------
+
+/// Share of equal bits between `a` and `b` at their best alignment
+/// within ±`MAX_OFFSET` items; 0 when they barely overlap.
+pub fn similarity(a: &[u32], b: &[u32]) -> f64 {
+    let mut best = 0.0f64;
+    for offset in -MAX_OFFSET..=MAX_OFFSET {
+        let (a, b) = if offset >= 0 {
+            (a.get(offset as usize..).unwrap_or_default(), b)
+        } else {
+            (a, b.get((-offset) as usize..).unwrap_or_default())
+        };
+        let overlap = a.len().min(b.len());
+        if overlap < MIN_OVERLAP {
+            continue;
+        }
+        let errors: u32 = a.iter().zip(b).map(|(x, y)| (x ^ y).count_ones()).sum();
+        best = best.max(1.0 - errors as f64 / (overlap as f64 * 32.0));
+    }
+    best
+}
+
+/// Pairs `(i, j)`, `i < j`, sharing at least `MIN_SHARED_VALUES` items.
+fn candidate_pairs(fingerprints: &[&[u32]]) -> Vec<(usize, usize)> {
+    let mut postings: HashMap<u32, Vec<usize>> = HashMap::new();
+    for (index, items) in fingerprints.iter().enumerate() {
+        let mut values: Vec<u32> = items.iter().copied().filter(|&v| v != 0).collect();
+        values.sort_unstable();
+        values.dedup();
+        for value in values {
+            postings.entry(value).or_default().push(index);
+        }
+    }
+    let mut shared: HashMap<(usize, usize), u32> = HashMap::new();
+    for songs in postings.values().filter(|songs| songs.len() > 1 && songs.len() <= MAX_POSTINGS) {
+        for (n, &i) in songs.iter().enumerate() {
+            for &j in &songs[n + 1..] {
+                *shared.entry((i, j)).or_default() += 1;
+            }
+        }
+    }
+    let mut pairs: Vec<_> = shared.into_iter().filter(|&(_, count)| count >= MIN_SHARED_VALUES).map(|(pair, _)| pair).collect();
+    pairs.sort_unstable();
+    pairs
+}
+
+fn root(parents: &mut [usize], mut i: usize) -> usize {
+    while parents[i] != i {
+        parents[i] = parents[parents[i]];
+        i = parents[i];
+    }
+    i
+}
+
+/// Groups of likely duplicates (indices into `fingerprints`) with their
+/// lowest matching pair similarity.
+pub fn group_duplicates(fingerprints: &[&[u32]]) -> Vec<(Vec<usize>, f64)> {
+    let mut parents: Vec<usize> = (0..fingerprints.len()).collect();
+    let mut lowest: HashMap<usize, f64> = HashMap::new();
+    let mut matches = Vec::new();
+    for (i, j) in candidate_pairs(fingerprints) {
+        let score = similarity(fingerprints[i], fingerprints[j]);
+        if score >= MIN_SIMILARITY {
+            let (a, b) = (root(&mut parents, i), root(&mut parents, j));
+            parents[a.max(b)] = a.min(b);
+            matches.push((i, score));
+        }
+    }
+    for (i, score) in matches {
+        let group = root(&mut parents, i);
+        let entry = lowest.entry(group).or_insert(1.0);
+        *entry = entry.min(score);
+    }
+
+    let mut groups: HashMap<usize, Vec<usize>> = HashMap::new();
+    for i in 0..fingerprints.len() {
+        let group = root(&mut parents, i);
+        if lowest.contains_key(&group) {
+            groups.entry(group).or_default().push(i);
+        }
+    }
+    let mut groups: Vec<_> = groups.into_iter().map(|(group, members)| (members, lowest[&group])).collect();
+    groups.sort_by_key(|(members, _)| members[0]);
+    groups
+}
+
+// ---------------------------------------------------------------------------
+// Library job
+// ---------------------------------------------------------------------------
+
+struct LibrarySong {
+    id: String,
+    title: String,
+    artist: String,
+    path: String,
+}
+
+/// Songs with a file to fingerprint: the audio, else the video's soundtrack.
+fn library_songs(conn: &Connection) -> Result<Vec<LibrarySong>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, title, artist, folder_path, COALESCE(NULLIF(audio_file_name, ''), video_file_name) FROM songs
+             WHERE folder_path != '' AND COALESCE(NULLIF(audio_file_name, ''), video_file_name, '') != ''
+             ORDER BY id",
+        )
+        .map_err(|e| format!("Failed to query songs: {}", e))?;
+    let rows = stmt
+        .query_map([], |row| {
+            let folder: String = row.get(3)?;
+            let file: String = row.get(4)?;
+            Ok(LibrarySong {
+                id: row.get(0)?,
+                title: row.get(1)?,
+                artist: row.get(2)?,
+                path: Path::new(&folder).join(file).to_string_lossy().to_string(),
+            })
+        })
+        .map_err(|e| format!("Failed to query songs: {}", e))?;
+    rows.collect::<Result<_, _>>().map_err(|e| format!("Failed to read songs: {}", e))
+}
+
+fn duplicate_song(song: &LibrarySong, fingerprint: &Fingerprint) -> DuplicateSong {
+    let size_bytes = std::fs::metadata(crate::paths::long_path(Path::new(&song.path))).ok().map(|m| m.len());
+    let bitrate_kbps = size_bytes.filter(|_| fingerprint.duration_ms > 0).map(|size| size * 8 / fingerprint.duration_ms);
+    DuplicateSong {
+        song_id: song.id.clone(),
+        title: song.title.clone(),
+        artist: song.artist.clone(),
+        path: song.path.clone(),
+        duration_ms: fingerprint.duration_ms,
+        size_bytes,
+        bitrate_kbps,
+        keep: false,
+    }
+}
+
+fn build_report(songs: &[LibrarySong], fingerprints: &[Option<Fingerprint>]) -> Vec<DuplicateGroup> {
+    let printed: Vec<(&LibrarySong, &Fingerprint)> =
+        songs.iter().zip(fingerprints).filter_map(|(song, fp)| fp.as_ref().map(|fp| (song, fp))).collect();
+    let items: Vec<&[u32]> = printed.iter().map(|(_, fp)| fp.items.as_slice()).collect();
+    group_duplicates(&items)
+        .into_iter()
+        .map(|(members, similarity)| {
+            let mut songs: Vec<DuplicateSong> = members.iter().map(|&i| duplicate_song(printed[i].0, printed[i].1)).collect();
+            songs.sort_by(|a, b| b.bitrate_kbps.cmp(&a.bitrate_kbps));
+            songs[0].keep = true;
+            DuplicateGroup { similarity, songs }
+        })
+        .collect()
+}
+
+/// Body of a duplicates job: fingerprint the library, pausing between
+/// songs while one plays, and publish the report.
+pub(crate) async fn run_job(ctx: &JobContext) -> Result<(), String> {
+    let app = ctx.app();
+    let mut songs = {
+        let db = app.state::<DbState>();
+        let conn = db.conn.lock().map_err(|e| e.to_string())?;
+        library_songs(&conn)?
+    };
+    let total = songs.len();
+    tracing::info!("[duplicates] Fingerprinting {} songs", total);
+
+    let cache = AnalysisCache::from_app(app);
+    let mut fingerprints = Vec::with_capacity(total);
+    let mut failed = 0;
+    for (done, song) in songs.iter().enumerate() {
+        if !ctx.checkpoint().await {
+            break;
+        }
+        let printed = {
+            let (cache, path) = (cache.clone(), song.path.clone());
+            tauri::async_runtime::spawn_blocking(move || fingerprint_cached(cache.as_ref(), &path))
+                .await
+                .map_err(|e| e.to_string())
+                .and_then(|r| r)
+        };
+        if let Err(e) = &printed {
+            tracing::warn!("[duplicates] {}: {}", song.path, e);
+            failed += 1;
+        }
+        ctx.progress(done + 1, total);
+        publish(
+            app,
+            AppEvent::FingerprintProgress(FingerprintProgress {
+                done: done + 1,
+                total,
+                song_id: song.id.clone(),
+                error: printed.as_ref().err().cloned(),
+            }),
+        );
+        fingerprints.push(printed.ok());
+    }
+
+    let cancelled = ctx.is_cancelled();
+    songs.truncate(fingerprints.len());
+    let fingerprinted = songs.len() - failed;
+    let groups = tauri::async_runtime::spawn_blocking(move || build_report(&songs, &fingerprints))
+        .await
+        .unwrap_or_default();
+
+    let report = DuplicateReport {
+        fingerprinted,
+        failed,
+        cancelled,
+        created_at: now_ms(),
+        groups,
+    };
+    tracing::info!("[duplicates] {} groups among {} songs", report.groups.len(), report.fingerprinted);
+    if let Ok(mut last) = app.state::<DuplicateState>().report.lock() {
+        *last = Some(report.clone());
+    }
+    publish(app, AppEvent::DuplicatesComplete(report));
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+/// Fingerprint the library in the background and group likely duplicates.
+/// Returns the number of songs to fingerprint; the report follows as
+/// `duplicates://complete`.
+#[tauri::command]
+pub fn find_duplicates(app: AppHandle, webview: tauri::Webview) -> Result<usize, String> {
+    require_webview(&webview, Capability::ManageLibrary)?;
+    crate::telemetry::feature(&app, "duplicate_finder");
+    let total = {
+        let db = app.state::<DbState>();
+        let conn = db.conn.lock().map_err(|e| e.to_string())?;
+        if jobs::has_pending(&conn, JobSpec::Duplicates.kind())? {
+            return Err("Duplicate detection is already running".to_string());
+        }
+        library_songs(&conn)?.len()
+    };
+    jobs::enqueue(&app, JobSpec::Duplicates)?;
+    tracing::info!("[duplicates] Queued {} songs", total);
+    Ok(total)
+}
+
+/// The report of the last `find_duplicates` run, if any.
+#[tauri::command]
+pub fn get_duplicate_report(app: AppHandle) -> Result<Option<DuplicateReport>, String> {
+    let state = app.state::<DuplicateState>();
+    let report = state.report.lock().map_err(|e| e.to_string())?;
+    Ok(report.clone())
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic pseudo-random fingerprint.
+    fn noise(seed: u32, len: usize) -> Vec<u32> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state
+            })
+            .collect()
+    }
+
+    #[test]
+    fn matches_shifted_and_slightly_altered_copies() {
+        let original = noise(7, 900);
+        // Another encode: 2 s more leading silence, a few flipped bits
+        let mut copy = vec![0u32; 16];
+        copy.extend(original.iter().enumerate().map(|(i, v)| if i % 3 == 0 { v ^ 0b101 } else { *v }));
+        let other = noise(99, 900);
+
+        assert!(similarity(&original, &copy) > 0.95);
+        assert!(similarity(&original, &other) < 0.6);
+        assert_eq!(similarity(&original[..10], &copy), 0.0);
+
+        let groups = group_duplicates(&[&original, &other, &copy]);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].0, vec![0, 2]);
+        assert!(groups[0].1 > 0.95);
+    }
+}
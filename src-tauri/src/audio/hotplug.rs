@@ -0,0 +1,124 @@
+//! Audio device hot-plug monitoring.
+//!
+//! cpal has no portable device-change notification, so a low-priority
+//! background thread polls the device lists and diffs them against the
+//! previous snapshot. On any change it:
+//!   1. emits `audio://device-changed` to the webview, and
+//!   2. pokes the audio thread so it can reconnect a stream whose device
+//!      dropped out (e.g. a USB interface that was power-cycled).
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+use super::commands::AudioState;
+use super::devices::{self, AudioDeviceInfo, DeviceDirection};
+use crate::events::{publish, AppEvent};
+
+/// How often the device lists are re-enumerated.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+pub const DEVICE_CHANGED_EVENT: &str = "audio://device-changed";
+
+/// Payload of the `audio://device-changed` event.
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceChangedEvent {
+    pub added: Vec<DeviceChange>,
+    pub removed: Vec<DeviceChange>,
+}
+
+/// A single device that appeared or disappeared.
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceChange {
+    pub direction: DeviceDirection,
+    pub device: AudioDeviceInfo,
+}
+
+/// Identity used for diffing. The `"<host>:<index>"` id is not stable across
+/// plug events (indices shift), so devices are keyed by host + name.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct DeviceKey {
+    direction: DeviceDirection,
+    host_name: String,
+    name: String,
+}
+
+fn snapshot() -> Vec<(DeviceKey, DeviceChange)> {
+    let outputs = devices::list_output_devices().unwrap_or_default();
+    let inputs = devices::list_input_devices().unwrap_or_default();
+
+    outputs
+        .into_iter()
+        .map(|d| (DeviceDirection::Output, d))
+        .chain(inputs.into_iter().map(|d| (DeviceDirection::Input, d)))
+        .map(|(direction, device)| {
+            let key = DeviceKey {
+                direction,
+                host_name: device.host_name.clone(),
+                name: device.name.clone(),
+            };
+            (key, DeviceChange { direction, device })
+        })
+        .collect()
+}
+
+/// Spawn the device monitor thread. It runs for the lifetime of the app.
+pub fn spawn_device_monitor(app: AppHandle) -> Result<(), String> {
+    std::thread::Builder::new()
+        .name("karaoke-device-monitor".into())
+        .spawn(move || run_device_monitor(app))
+        .map(|_| ())
+        .map_err(|e| format!("Failed to spawn device monitor thread: {}", e))
+}
+
+fn run_device_monitor(app: AppHandle) {
+    let mut known: HashSet<DeviceKey> = snapshot().into_iter().map(|(k, _)| k).collect();
+
+    loop {
+        std::thread::sleep(POLL_INTERVAL);
+
+        let current = snapshot();
+        let current_keys: HashSet<DeviceKey> = current.iter().map(|(k, _)| k.clone()).collect();
+
+        let added: Vec<DeviceChange> = current
+            .iter()
+            .filter(|(k, _)| !known.contains(k))
+            .map(|(_, c)| c.clone())
+            .collect();
+
+        let removed: Vec<DeviceChange> = known
+            .difference(&current_keys)
+            .map(|k| DeviceChange {
+                direction: k.direction,
+                device: AudioDeviceInfo {
+                    id: String::new(),
+                    name: k.name.clone(),
+                    host_name: k.host_name.clone(),
+                    default_sample_rate: 0,
+                    max_channels: 0,
+                },
+            })
+            .collect();
+
+        if added.is_empty() && removed.is_empty() {
+            continue;
+        }
+
+        tracing::info!(
+            "[audio] Device list changed: {} added, {} removed",
+            added.len(),
+            removed.len()
+        );
+
+        publish(&app, AppEvent::AudioDeviceChanged(DeviceChangedEvent { added, removed }));
+
+        // Let the audio thread re-open a stream whose device came back
+        if let Some(audio_state) = app.try_state::<AudioState>() {
+            audio_state.notify_devices_changed();
+        }
+
+        known = current_keys;
+    }
+}
@@ -0,0 +1,357 @@
+//! Round-trip latency calibration.
+//!
+//! `run_latency_calibration` plays a short train of clicks on an output
+//! while recording a mic next to the speaker. Both streams stamp the
+//! instant their first frame passed the callback on a shared clock, so the
+//! sample index where each click should appear in the recording is known;
+//! the delay until it actually shows up is the round trip from the player
+//! handing out a sample to the mic thread seeing it — exactly the lag
+//! between `audio.position_ms()` and a pitch frame. The median over the
+//! clicks is stored as the mic's scoring offset (`device_offsets`); the
+//! round trip minus the input driver's reported latency is stored as the
+//! output latency the lyric follower shifts by. Bluetooth speakers and USB
+//! interfaces are the cases this exists for.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use cpal::traits::{DeviceTrait, StreamTrait};
+use cpal::{FromSample, SampleFormat, StreamConfig};
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+use super::commands::AudioState;
+use super::device_offsets;
+use super::devices;
+use super::level_calibration;
+use super::player::resolve_device;
+use crate::access::{require_webview, Capability};
+use crate::db::DbState;
+
+const CLICKS: usize = 6;
+const LEAD_IN_MS: u64 = 600;
+/// Longer than any round trip worth measuring, so clicks never overlap.
+const CLICK_INTERVAL_MS: u64 = 1000;
+const MAX_ROUND_TRIP_MS: u64 = 800;
+const CLICK_MS: u64 = 5;
+const CLICK_HZ: f32 = 1_000.0;
+const CLICK_LEVEL: f32 = 0.8;
+/// Onsets must clear the lead-in noise by this factor, and this level.
+const NOISE_FACTOR: f32 = 4.0;
+const MIN_ONSET_LEVEL: f32 = 0.01;
+const MIN_DETECTED: usize = 4;
+const MAX_JITTER_MS: f64 = 15.0;
+const DRAIN_INTERVAL: Duration = Duration::from_millis(50);
+const UNSET: u64 = u64::MAX;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LatencyCalibrationResult {
+    pub output_device: String,
+    pub input_device: String,
+    pub clicks_played: usize,
+    pub clicks_detected: usize,
+    /// Median delay from the output callback to the capture callback.
+    pub round_trip_ms: f64,
+    /// Spread between the earliest and latest detected click.
+    pub jitter_ms: f64,
+    /// Share of the round trip on the output side (used by lyric sync).
+    pub output_latency_ms: f64,
+    /// Whether enough clicks agreed to store the result.
+    pub valid: bool,
+    pub recommendation: String,
+}
+
+/// When a stream's first frame passed its callback, on an `Instant` shared
+/// by both streams. Marked once, from the callback.
+pub(super) struct StreamClock {
+    origin: Instant,
+    first_frame_ns: AtomicU64,
+    reported_latency_us: AtomicU64,
+}
+
+impl StreamClock {
+    pub(super) fn new(origin: Instant) -> Arc<Self> {
+        Arc::new(Self { origin, first_frame_ns: AtomicU64::new(UNSET), reported_latency_us: AtomicU64::new(0) })
+    }
+
+    /// `lead`: how long before now the callback's first frame was (the
+    /// length of a capture buffer; zero for output). `reported_latency`:
+    /// what the driver says the stream adds.
+    pub(super) fn mark(&self, lead: Duration, reported_latency: Option<Duration>) {
+        if self.first_frame_ns.load(Ordering::Relaxed) != UNSET {
+            return;
+        }
+        let at = self.origin.elapsed().saturating_sub(lead);
+        let reported = reported_latency.map_or(0, |d| d.as_micros() as u64);
+        self.reported_latency_us.store(reported, Ordering::Relaxed);
+        self.first_frame_ns.store(at.as_nanos() as u64, Ordering::Release);
+    }
+
+    fn first_frame_ns(&self) -> Option<u64> {
+        Some(self.first_frame_ns.load(Ordering::Acquire)).filter(|&ns| ns != UNSET)
+    }
+
+    fn reported_latency_ms(&self) -> f64 {
+        self.reported_latency_us.load(Ordering::Relaxed) as f64 / 1000.0
+    }
+}
+
+/// Where the clicks are, in output frames.
+#[derive(Clone, Copy)]
+struct ClickSchedule {
+    sample_rate: u32,
+    lead_in: u64,
+    interval: u64,
+    length: u64,
+}
+
+impl ClickSchedule {
+    fn new(sample_rate: u32) -> Self {
+        let frames = |ms: u64| ms * sample_rate as u64 / 1000;
+        Self { sample_rate, lead_in: frames(LEAD_IN_MS), interval: frames(CLICK_INTERVAL_MS), length: frames(CLICK_MS).max(1) }
+    }
+
+    fn start_frame(&self, click: usize) -> u64 {
+        self.lead_in + click as u64 * self.interval
+    }
+
+    fn duration_ms(&self) -> u64 {
+        LEAD_IN_MS + CLICKS as u64 * CLICK_INTERVAL_MS
+    }
+
+    /// A short decaying 1 kHz burst: sharp onset, no DC.
+    fn sample(&self, frame: u64) -> f32 {
+        let Some(from_start) = frame.checked_sub(self.lead_in) else { return 0.0 };
+        let (click, i) = (from_start / self.interval, from_start % self.interval);
+        if click >= CLICKS as u64 || i >= self.length {
+            return 0.0;
+        }
+        let t = i as f32 / self.sample_rate as f32;
+        CLICK_LEVEL * (2.0 * std::f32::consts::PI * CLICK_HZ * t).sin() * (1.0 - i as f32 / self.length as f32)
+    }
+}
+
+/// Delay (ms) of each click found in `captured`, searching from `expected`
+/// — the capture index at which each click was handed to the output.
+/// Clicks before the recording started or never heard are left out.
+fn detect_clicks(captured: &[f32], sample_rate: u32, expected: &[i64]) -> Vec<f64> {
+    // Whatever is heard before the first click is the room's noise floor
+    let noise_end = expected.first().map_or(0, |&e| e.clamp(0, captured.len() as i64) as usize);
+    let noise = captured[..noise_end].iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+    let threshold = (noise * NOISE_FACTOR).max(MIN_ONSET_LEVEL);
+    let window = (MAX_ROUND_TRIP_MS * sample_rate as u64 / 1000) as usize;
+
+    expected
+        .iter()
+        .filter(|&&start| start >= 0 && (start as usize) < captured.len())
+        .filter_map(|&start| {
+            let start = start as usize;
+            let end = (start + window).min(captured.len());
+            let onset = captured[start..end].iter().position(|s| s.abs() > threshold)?;
+            Some(onset as f64 * 1000.0 / sample_rate as f64)
+        })
+        .collect()
+}
+
+/// Median and spread of the detected delays, and the verdict.
+fn evaluate(
+    output_device: &str,
+    input_device: &str,
+    mut delays: Vec<f64>,
+    input_latency_ms: f64,
+) -> LatencyCalibrationResult {
+    delays.sort_by(|a, b| a.total_cmp(b));
+    let round_trip = delays.get(delays.len() / 2).copied().unwrap_or(0.0);
+    let jitter = delays.last().zip(delays.first()).map_or(0.0, |(max, min)| max - min);
+    let valid = delays.len() >= MIN_DETECTED && jitter <= MAX_JITTER_MS;
+    let recommendation = if delays.len() < MIN_DETECTED {
+        format!(
+            "Only {} of {} clicks were heard — turn the speaker up, move the mic closer and run calibration again.",
+            delays.len(),
+            CLICKS
+        )
+    } else if !valid {
+        format!("The clicks arrived {:.0} ms apart — reduce background noise and run calibration again.", jitter)
+    } else {
+        format!("Round trip is {:.0} ms; scoring and lyrics are compensated.", round_trip)
+    };
+    LatencyCalibrationResult {
+        output_device: output_device.to_string(),
+        input_device: input_device.to_string(),
+        clicks_played: CLICKS,
+        clicks_detected: delays.len(),
+        round_trip_ms: (round_trip * 10.0).round() / 10.0,
+        jitter_ms: (jitter * 10.0).round() / 10.0,
+        output_latency_ms: ((round_trip - input_latency_ms).max(0.0) * 10.0).round() / 10.0,
+        valid,
+        recommendation,
+    }
+}
+
+/// Play the clicks on `output` while recording `input`. Blocks for the
+/// length of the click train plus the longest accepted round trip.
+fn measure(output: &cpal::Device, input: &cpal::Device, mic_channel: Option<u16>) -> Result<LatencyCalibrationResult, String> {
+    let origin = Instant::now();
+    let input_clock = StreamClock::new(origin);
+    let (capture, mut consumer, input_rate) = level_calibration::open_timed_capture(input, mic_channel, input_clock.clone())?;
+
+    let supported = output
+        .default_output_config()
+        .map_err(|e| format!("Cannot get output config: {}", e))?;
+    let sample_format = supported.sample_format();
+    let config: StreamConfig = supported.into();
+    let schedule = ClickSchedule::new(config.sample_rate.0);
+    let output_clock = StreamClock::new(origin);
+    let stream = match sample_format {
+        SampleFormat::F32 => build_click_output::<f32>(output, &config, schedule, output_clock.clone())?,
+        SampleFormat::I16 => build_click_output::<i16>(output, &config, schedule, output_clock.clone())?,
+        SampleFormat::U16 => build_click_output::<u16>(output, &config, schedule, output_clock.clone())?,
+        other => return Err(format!("Unsupported output sample format: {:?}", other)),
+    };
+    stream.play().map_err(|e| format!("Failed to start click output: {}", e))?;
+
+    let listen = Duration::from_millis(schedule.duration_ms() + MAX_ROUND_TRIP_MS + 200);
+    let mut captured = Vec::with_capacity((input_rate as u64 * listen.as_millis() as u64 / 1000) as usize);
+    let mut drained = vec![0.0f32; input_rate as usize / 10];
+    let start = Instant::now();
+    while start.elapsed() < listen {
+        std::thread::sleep(DRAIN_INTERVAL);
+        loop {
+            let n = consumer.pop(&mut drained);
+            if n == 0 {
+                break;
+            }
+            captured.extend_from_slice(&drained[..n]);
+        }
+    }
+    drop(stream);
+    drop(capture);
+
+    let (Some(output_first), Some(input_first)) = (output_clock.first_frame_ns(), input_clock.first_frame_ns()) else {
+        return Err("The output or input stream never started".to_string());
+    };
+    // Capture index at which each click left the output callback
+    let expected: Vec<i64> = (0..CLICKS)
+        .map(|click| {
+            let handed_ns = output_first as f64 + schedule.start_frame(click) as f64 * 1e9 / schedule.sample_rate as f64;
+            ((handed_ns - input_first as f64) * input_rate as f64 / 1e9).round() as i64
+        })
+        .collect();
+    let delays = detect_clicks(&captured, input_rate, &expected);
+    Ok(evaluate(
+        &output.name().unwrap_or_default(),
+        &input.name().unwrap_or_default(),
+        delays,
+        input_clock.reported_latency_ms(),
+    ))
+}
+
+fn build_click_output<T>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    schedule: ClickSchedule,
+    clock: Arc<StreamClock>,
+) -> Result<cpal::Stream, String>
+where
+    T: cpal::SizedSample + FromSample<f32> + Send + 'static,
+{
+    let channels = config.channels.max(1) as usize;
+    let mut frame = 0u64;
+    device
+        .build_output_stream(
+            config,
+            move |data: &mut [T], info: &cpal::OutputCallbackInfo| {
+                let timestamp = info.timestamp();
+                clock.mark(Duration::ZERO, timestamp.playback.duration_since(&timestamp.callback));
+                for out in data.chunks_mut(channels) {
+                    let s = T::from_sample(schedule.sample(frame));
+                    out.fill(s);
+                    frame += 1;
+                }
+            },
+            |err| tracing::error!("[audio] Click output error: {}", err),
+            None,
+        )
+        .map_err(|e| format!("Failed to open output stream: {}", e))
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+/// Measure the round trip from `output_device_id` (default: the configured
+/// primary output) to `input_device_id` (default: the system mic) with a
+/// click train, and on a valid result store it for scoring and lyric sync.
+/// The mic must be able to hear the speaker; playback must be stopped.
+#[tauri::command]
+pub async fn run_latency_calibration(
+    app: AppHandle,
+    webview: tauri::Webview,
+    output_device_id: Option<String>,
+    input_device_id: Option<String>,
+) -> Result<LatencyCalibrationResult, String> {
+    require_webview(&webview, Capability::ConfigureAudio)?;
+    let output_id = {
+        let audio = app.state::<AudioState>();
+        if audio.is_playing() {
+            return Err("Stop playback before calibrating latency".to_string());
+        }
+        output_device_id.or_else(|| audio.primary_output()).unwrap_or_else(|| "default".to_string())
+    };
+    let input_id = input_device_id.unwrap_or_else(|| "default".to_string());
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let (output, _) = resolve_device(&output_id)?;
+        let input = devices::resolve_input_device(&input_id)?;
+        let input_name = input.name().unwrap_or_default();
+        let mic_channel = app.state::<AudioState>().mic_channel(&input_name);
+        let result = measure(&output, &input, mic_channel)?;
+        tracing::info!(
+            "[audio] Latency calibration {} → {}: {:.1} ms round trip ({}/{} clicks, jitter {:.1} ms)",
+            result.output_device, result.input_device, result.round_trip_ms, result.clicks_detected, result.clicks_played, result.jitter_ms
+        );
+        if result.valid {
+            let db = app.state::<DbState>();
+            let conn = db.conn.lock().map_err(|e| e.to_string())?;
+            device_offsets::set_offset_for_device(&conn, &input_name, result.round_trip_ms.round() as i64)?;
+            device_offsets::set_output_latency(&conn, result.output_latency_ms.round() as i64)?;
+        }
+        Ok(result)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_delayed_clicks_above_the_noise_floor() {
+        let sr = 48_000;
+        let schedule = ClickSchedule::new(sr);
+        let delay = (0.123 * sr as f64) as u64;
+        // Low hum throughout, clicks arriving 123 ms late, the third lost
+        let mut captured: Vec<f32> = (0..sr as usize * 8).map(|i| 0.002 * (i as f32 * 0.05).sin()).collect();
+        for click in (0..CLICKS).filter(|&c| c != 2) {
+            let at = schedule.start_frame(click) + delay;
+            for i in 0..schedule.length {
+                captured[(at + i) as usize] += 0.5 * schedule.sample(schedule.start_frame(click) + i);
+            }
+        }
+        let expected: Vec<i64> = (0..CLICKS).map(|c| schedule.start_frame(c) as i64).collect();
+        let delays = detect_clicks(&captured, sr, &expected);
+        assert_eq!(delays.len(), CLICKS - 1);
+        assert!(delays.iter().all(|d| (d - 123.0).abs() < 1.0), "{:?}", delays);
+
+        let result = evaluate("speaker", "mic", delays, 20.0);
+        assert!(result.valid);
+        assert!((result.round_trip_ms - 123.0).abs() < 1.0 && (result.output_latency_ms - 103.0).abs() < 1.0);
+        assert!(!evaluate("speaker", "mic", vec![100.0, 300.0, 110.0, 120.0], 0.0).valid);
+    }
+}
@@ -0,0 +1,403 @@
+//! Guided microphone level calibration.
+//!
+//! The operator asks the singer to speak/sing at performance volume while
+//! the input is recorded for a few seconds. From that we measure:
+//!   - peak level and clipping (samples at or near full scale),
+//!   - the RMS level of the voiced parts (quiet gaps between phrases are
+//!     ignored so pauses do not drag the average down),
+//! and recommend a gain change towards the target level. The measured
+//! speech level is stored per device (`mic_reference_level_dbfs:<device
+//! name>`) and reported with the mic inputs and by
+//! `audio_get_reference_level`; nothing on the backend adjusts levels by it.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use cpal::traits::{DeviceTrait, StreamTrait};
+use cpal::{FromSample, SampleFormat, StreamConfig};
+use rusqlite::Connection;
+use serde::Serialize;
+
+use super::latency_calibration::StreamClock;
+use super::rt_priority::RtPromotion;
+use super::spsc::{ring, RingConsumer, RingProducer};
+
+/// Settings key prefix for the calibrated reference level.
+const SETTINGS_PREFIX: &str = "mic_reference_level_dbfs:";
+
+/// Target speech RMS in dBFS — leaves ~15 dB headroom for belting.
+const TARGET_RMS_DBFS: f64 = -18.0;
+
+/// Peaks above this are too hot even if they did not clip yet.
+const HOT_PEAK_DBFS: f64 = -3.0;
+
+/// Absolute sample value treated as clipped.
+pub(crate) const CLIP_THRESHOLD: f32 = 0.999;
+
+/// Blocks quieter than this are treated as silence between phrases.
+const SILENCE_DBFS: f64 = -50.0;
+
+/// Length of one measurement block (also the progress interval).
+const BLOCK_MS: u64 = 100;
+
+/// Captured audio the ring holds between two drains (generous, so a late
+/// wake-up of the measuring thread loses nothing).
+const CAPTURE_BUFFER_MS: u64 = 2000;
+/// Frames mixed down per pass in the capture callback.
+const CAPTURE_CHUNK: usize = 512;
+
+/// Progress update streamed while measuring.
+#[derive(Debug, Clone, Serialize)]
+pub struct LevelCalibrationProgress {
+    pub elapsed_ms: u64,
+    pub duration_ms: u64,
+    /// RMS of the most recent block.
+    pub rms_dbfs: f64,
+    /// Highest peak so far.
+    pub peak_dbfs: f64,
+    pub clipping: bool,
+}
+
+/// Final calibration verdict.
+#[derive(Debug, Clone, Serialize)]
+pub struct LevelCalibrationResult {
+    pub device_name: String,
+    /// Average RMS of the voiced blocks — stored as the reference level.
+    pub speech_rms_dbfs: f64,
+    pub peak_dbfs: f64,
+    pub clipped_samples: usize,
+    pub clipping: bool,
+    /// Suggested change to the interface / OS input gain in dB.
+    pub recommended_gain_db: f64,
+    /// Human-readable advice for the operator.
+    pub recommendation: String,
+    /// Whether enough voiced signal was captured to trust the result.
+    pub valid: bool,
+}
+
+/// Convert a linear amplitude to dBFS (floored at -120).
+pub fn to_dbfs(amplitude: f64) -> f64 {
+    if amplitude <= 1e-6 {
+        -120.0
+    } else {
+        20.0 * amplitude.log10()
+    }
+}
+
+/// Running statistics over mono samples, grouped into fixed-size blocks.
+#[derive(Debug, Default)]
+pub struct LevelStats {
+    block_len: usize,
+    block_sum_sq: f64,
+    block_count: usize,
+    voiced_sum_sq: f64,
+    voiced_count: usize,
+    last_block_rms: f64,
+    peak: f32,
+    clipped: usize,
+}
+
+impl LevelStats {
+    pub fn new(block_len: usize) -> Self {
+        Self {
+            block_len: block_len.max(1),
+            ..Default::default()
+        }
+    }
+
+    pub fn push(&mut self, samples: &[f32]) {
+        for &s in samples {
+            let a = s.abs();
+            if a > self.peak {
+                self.peak = a;
+            }
+            if a >= CLIP_THRESHOLD {
+                self.clipped += 1;
+            }
+            self.block_sum_sq += (s as f64) * (s as f64);
+            self.block_count += 1;
+            if self.block_count == self.block_len {
+                let rms = (self.block_sum_sq / self.block_len as f64).sqrt();
+                self.last_block_rms = rms;
+                if to_dbfs(rms) > SILENCE_DBFS {
+                    self.voiced_sum_sq += self.block_sum_sq;
+                    self.voiced_count += self.block_len;
+                }
+                self.block_sum_sq = 0.0;
+                self.block_count = 0;
+            }
+        }
+    }
+
+    pub fn peak_dbfs(&self) -> f64 {
+        to_dbfs(self.peak as f64)
+    }
+
+    pub fn last_block_dbfs(&self) -> f64 {
+        to_dbfs(self.last_block_rms)
+    }
+
+    pub fn clipped_samples(&self) -> usize {
+        self.clipped
+    }
+
+    /// RMS of voiced blocks, or `None` if nothing above the silence floor.
+    pub fn speech_rms_dbfs(&self) -> Option<f64> {
+        if self.voiced_count == 0 {
+            return None;
+        }
+        Some(to_dbfs((self.voiced_sum_sq / self.voiced_count as f64).sqrt()))
+    }
+}
+
+/// Derive the recommendation from the collected statistics.
+pub fn evaluate(device_name: &str, stats: &LevelStats, sample_rate: u32) -> LevelCalibrationResult {
+    let peak_dbfs = stats.peak_dbfs();
+    let clipped = stats.clipped_samples();
+    let clipping = clipped > 0;
+
+    // Require at least one second of voiced signal for a trustworthy result
+    let valid = stats.voiced_count >= sample_rate as usize;
+    let speech = stats.speech_rms_dbfs().unwrap_or(-120.0);
+
+    let (gain, recommendation) = if !valid {
+        (0.0, "Not enough signal captured — check the mic is unmuted and sing louder, then run calibration again.".to_string())
+    } else if clipping {
+        // Clipped audio hides the true level; ask for a decisive cut
+        let gain = (TARGET_RMS_DBFS - speech).min(-6.0);
+        (gain, format!("Input is clipping ({} samples). Lower the gain by at least {:.0} dB.", clipped, -gain))
+    } else if peak_dbfs > HOT_PEAK_DBFS {
+        let gain = (HOT_PEAK_DBFS - 3.0 - peak_dbfs).min(TARGET_RMS_DBFS - speech);
+        (gain, format!("Peaks are very hot ({:.1} dBFS). Lower the gain by about {:.0} dB.", peak_dbfs, -gain))
+    } else {
+        let gain = (TARGET_RMS_DBFS - speech).clamp(-30.0, 30.0);
+        // Never recommend a boost that would push the measured peak over the hot limit
+        let gain = gain.min(HOT_PEAK_DBFS - peak_dbfs);
+        if gain.abs() < 3.0 {
+            (gain, "Level looks good.".to_string())
+        } else if gain > 0.0 {
+            (gain, format!("Input is quiet. Raise the gain by about {:.0} dB.", gain))
+        } else {
+            (gain, format!("Input is loud. Lower the gain by about {:.0} dB.", -gain))
+        }
+    };
+
+    LevelCalibrationResult {
+        device_name: device_name.to_string(),
+        speech_rms_dbfs: speech,
+        peak_dbfs,
+        clipped_samples: clipped,
+        clipping,
+        recommended_gain_db: (gain * 10.0).round() / 10.0,
+        recommendation,
+        valid,
+    }
+}
+
+/// Record from `device` for `duration_ms` and evaluate the level.
+///
+/// `mic_channel` selects one input channel of a multi-channel interface
+/// (0-based); `None` mixes all channels to mono. Blocks the calling thread.
+pub fn run_calibration<F>(
+    device: &cpal::Device,
+    mic_channel: Option<u16>,
+    duration_ms: u64,
+    mut on_progress: F,
+) -> Result<LevelCalibrationResult, String>
+where
+    F: FnMut(LevelCalibrationProgress),
+{
+    let device_name = device.name().unwrap_or_default();
+    // The callback only pushes mono samples; levels are computed here
+    let (stream, mut consumer, sample_rate) = open_capture(device, mic_channel)?;
+    let block_len = (sample_rate as u64 * BLOCK_MS / 1000) as usize;
+    let mut stats = LevelStats::new(block_len);
+    let mut drained = vec![0.0f32; block_len.max(1)];
+
+    let start = Instant::now();
+    loop {
+        std::thread::sleep(Duration::from_millis(BLOCK_MS));
+        let elapsed_ms = start.elapsed().as_millis() as u64;
+        loop {
+            let n = consumer.pop(&mut drained);
+            if n == 0 {
+                break;
+            }
+            stats.push(&drained[..n]);
+        }
+        on_progress(LevelCalibrationProgress {
+            elapsed_ms: elapsed_ms.min(duration_ms),
+            duration_ms,
+            rms_dbfs: stats.last_block_dbfs(),
+            peak_dbfs: stats.peak_dbfs(),
+            clipping: stats.clipped_samples() > 0,
+        });
+        if elapsed_ms >= duration_ms {
+            break;
+        }
+    }
+    drop(stream);
+
+    Ok(evaluate(&device_name, &stats, sample_rate))
+}
+
+/// Start a mono capture stream on `device` (see `run_calibration` for
+/// `mic_channel`). Samples arrive in the returned ring, which holds
+/// `CAPTURE_BUFFER_MS` between drains; the stream stops when dropped.
+pub(super) fn open_capture(
+    device: &cpal::Device,
+    mic_channel: Option<u16>,
+) -> Result<(cpal::Stream, RingConsumer, u32), String> {
+    start_capture(device, mic_channel, None)
+}
+
+/// `open_capture` that also marks `clock` when the first samples arrive
+/// (see `latency_calibration`).
+pub(super) fn open_timed_capture(
+    device: &cpal::Device,
+    mic_channel: Option<u16>,
+    clock: Arc<StreamClock>,
+) -> Result<(cpal::Stream, RingConsumer, u32), String> {
+    start_capture(device, mic_channel, Some(clock))
+}
+
+fn start_capture(
+    device: &cpal::Device,
+    mic_channel: Option<u16>,
+    clock: Option<Arc<StreamClock>>,
+) -> Result<(cpal::Stream, RingConsumer, u32), String> {
+    let supported = device
+        .default_input_config()
+        .map_err(|e| format!("Cannot get input config: {}", e))?;
+    let sample_format = supported.sample_format();
+    let config: StreamConfig = supported.into();
+    let sample_rate = config.sample_rate.0;
+    let (producer, consumer) = ring(sample_rate as usize * CAPTURE_BUFFER_MS as usize / 1000);
+
+    let stream = match sample_format {
+        SampleFormat::F32 => build_capture::<f32>(device, &config, mic_channel, producer, clock)?,
+        SampleFormat::I16 => build_capture::<i16>(device, &config, mic_channel, producer, clock)?,
+        SampleFormat::U16 => build_capture::<u16>(device, &config, mic_channel, producer, clock)?,
+        other => return Err(format!("Unsupported input sample format: {:?}", other)),
+    };
+    stream.play().map_err(|e| format!("Failed to start capture: {}", e))?;
+    Ok((stream, consumer, sample_rate))
+}
+
+fn build_capture<T>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    mic_channel: Option<u16>,
+    mut producer: RingProducer,
+    clock: Option<Arc<StreamClock>>,
+) -> Result<cpal::Stream, String>
+where
+    T: cpal::SizedSample + Send + 'static,
+    f32: cpal::FromSample<T>,
+{
+    let channels = config.channels.max(1) as usize;
+    let selected = mic_channel.map(|c| c as usize).filter(|&c| c < channels);
+    let sample_rate = config.sample_rate.0;
+    let mut promotion = RtPromotion::new("capture");
+
+    device
+        .build_input_stream(
+            config,
+            move |data: &[T], info: &cpal::InputCallbackInfo| {
+                // Real-time context: mix down through a stack buffer and
+                // hand off via the ring; no locks, no allocation
+                if !promotion.ready((data.len() / channels) as u32, sample_rate) {
+                    return;
+                }
+                if let Some(clock) = &clock {
+                    let buffered = Duration::from_secs_f64((data.len() / channels) as f64 / sample_rate.max(1) as f64);
+                    let timestamp = info.timestamp();
+                    clock.mark(buffered, timestamp.callback.duration_since(&timestamp.capture));
+                }
+                let mut mono = [0.0f32; CAPTURE_CHUNK];
+                for frames in data.chunks(channels * CAPTURE_CHUNK) {
+                    let mut n = 0;
+                    for frame in frames.chunks(channels) {
+                        mono[n] = match selected {
+                            Some(ch) => frame.get(ch).map(|&s| f32::from_sample_(s)).unwrap_or(0.0),
+                            None => frame.iter().map(|&s| f32::from_sample_(s)).sum::<f32>() / frame.len() as f32,
+                        };
+                        n += 1;
+                    }
+                    // A stalled reader loses samples rather than blocking
+                    producer.push(&mono[..n]);
+                }
+            },
+            |err| tracing::error!("[audio] Capture error: {}", err),
+            None,
+        )
+        .map_err(|e| format!("Failed to open input stream: {}", e))
+}
+
+/// Stored reference level for `device_name` (dBFS), if calibrated.
+pub fn reference_level(conn: &Connection, device_name: &str) -> Result<Option<f64>, String> {
+    let result = conn.query_row(
+        "SELECT value FROM app_settings WHERE key = ?1",
+        [format!("{}{}", SETTINGS_PREFIX, device_name)],
+        |row| row.get::<_, String>(0),
+    );
+    match result {
+        Ok(v) => Ok(v.trim().parse::<f64>().ok()),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(format!("reference level lookup failed: {}", e)),
+    }
+}
+
+/// Persist the calibrated reference level for `device_name`.
+pub fn save_reference_level(conn: &Connection, device_name: &str, level_dbfs: f64) -> Result<(), String> {
+    conn.execute(
+        "INSERT OR REPLACE INTO app_settings (key, value) VALUES (?1, ?2)",
+        (format!("{}{}", SETTINGS_PREFIX, device_name), format!("{:.1}", level_dbfs)),
+    )
+    .map_err(|e| format!("Failed to save reference level: {}", e))?;
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tone(amplitude: f32, sr: u32, seconds: f32) -> Vec<f32> {
+        (0..(sr as f32 * seconds) as usize)
+            .map(|i| (i as f32 * 220.0 * 2.0 * std::f32::consts::PI / sr as f32).sin() * amplitude)
+            .collect()
+    }
+
+    #[test]
+    fn quiet_input_recommends_boost() {
+        let sr = 48_000;
+        let mut stats = LevelStats::new(4800);
+        stats.push(&tone(0.01, sr, 2.0)); // ≈ -43 dBFS RMS
+        let result = evaluate("mic", &stats, sr);
+        assert!(result.valid);
+        assert!(!result.clipping);
+        assert!(result.recommended_gain_db > 10.0, "got {}", result.recommended_gain_db);
+    }
+
+    #[test]
+    fn clipping_input_recommends_cut() {
+        let sr = 48_000;
+        let mut stats = LevelStats::new(4800);
+        stats.push(&tone(1.0, sr, 2.0));
+        let result = evaluate("mic", &stats, sr);
+        assert!(result.clipping);
+        assert!(result.recommended_gain_db <= -6.0);
+    }
+
+    #[test]
+    fn silence_is_not_valid() {
+        let sr = 48_000;
+        let mut stats = LevelStats::new(4800);
+        stats.push(&vec![0.0; 96_000]);
+        assert!(!evaluate("mic", &stats, sr).valid);
+    }
+}
@@ -0,0 +1,127 @@
+//! Real-time pitch of the microphone signal.
+//!
+//! The mic capture thread feeds every drained block into `LivePitch`,
+//! which keeps a sliding window just long enough for the lowest sung note
+//! and runs YIN (the same detector as offline analysis, see
+//! `analysis::yin`) every 1/60 s. Quiet windows and weak estimates count
+//! as unvoiced, so breaths and room noise do not draw a pitch line. Each
+//! result is published as `pitch://frame`.
+
+use std::collections::VecDeque;
+
+use serde::Serialize;
+
+use super::analysis::yin::YinDetectorSr;
+use super::level_calibration::to_dbfs;
+
+pub const PITCH_EVENT: &str = "pitch://frame";
+pub const FRAMES_PER_SECOND: u32 = 60;
+/// Singing range searched: a low bass E2 up to a soprano's C6.
+const MIN_HZ: f64 = 80.0;
+const MAX_HZ: f64 = 1050.0;
+const YIN_THRESHOLD: f64 = 0.15;
+/// Below this the window is treated as silence.
+const SILENCE_DBFS: f64 = -50.0;
+/// Estimates less certain than this are reported as unvoiced.
+const MIN_CONFIDENCE: f64 = 0.5;
+
+/// Payload of `pitch://frame`.
+#[derive(Debug, Clone, Serialize)]
+pub struct PitchFrame {
+    /// Fractional MIDI note (60.0 = middle C); `None` when unvoiced.
+    pub midi_note: Option<f64>,
+    pub frequency_hz: Option<f64>,
+    pub confidence: f64,
+    /// Capture time of the window's centre, ms since capture start.
+    pub ts: u64,
+    /// Native player position when the frame was published.
+    pub position_ms: u64,
+}
+
+pub struct LivePitch {
+    yin: YinDetectorSr,
+    sample_rate: u32,
+    window: VecDeque<f64>,
+    window_len: usize,
+    hop: usize,
+    since_hop: usize,
+    samples_seen: u64,
+}
+
+impl LivePitch {
+    pub fn new(sample_rate: u32) -> Self {
+        // YIN needs two periods of the lowest note
+        let window_len = 2 * ((sample_rate as f64 / MIN_HZ).ceil() as usize + 1);
+        Self {
+            yin: YinDetectorSr::new(YIN_THRESHOLD, MIN_HZ, MAX_HZ, sample_rate),
+            sample_rate,
+            window: VecDeque::with_capacity(window_len + 1),
+            window_len,
+            hop: (sample_rate / FRAMES_PER_SECOND).max(1) as usize,
+            since_hop: 0,
+            samples_seen: 0,
+        }
+    }
+
+    /// Add captured samples; `emit` gets a frame every hop once the window
+    /// is full. `position_ms` is filled in by the caller.
+    pub fn push(&mut self, samples: &[f32], mut emit: impl FnMut(PitchFrame)) {
+        for &sample in samples {
+            self.window.push_back(sample as f64);
+            if self.window.len() > self.window_len {
+                self.window.pop_front();
+            }
+            self.samples_seen += 1;
+            self.since_hop += 1;
+            if self.since_hop >= self.hop && self.window.len() == self.window_len {
+                self.since_hop = 0;
+                emit(self.analyze());
+            }
+        }
+    }
+
+    fn analyze(&mut self) -> PitchFrame {
+        let centre = self.samples_seen.saturating_sub(self.window_len as u64 / 2);
+        let ts = centre * 1000 / self.sample_rate as u64;
+        let window = self.window.make_contiguous();
+        let rms = (window.iter().map(|s| s * s).sum::<f64>() / window.len() as f64).sqrt();
+        let (frequency, confidence) = if to_dbfs(rms) < SILENCE_DBFS { (0.0, 0.0) } else { self.yin.detect(window) };
+        let voiced = frequency > 0.0 && confidence >= MIN_CONFIDENCE;
+        PitchFrame {
+            midi_note: voiced.then(|| 69.0 + 12.0 * (frequency / 440.0).log2()),
+            frequency_hz: voiced.then_some(frequency),
+            confidence,
+            ts,
+            position_ms: 0,
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracks_a_sung_a4_and_ignores_silence() {
+        let sr = 48_000;
+        let mut pitch = LivePitch::new(sr);
+        let mut frames = Vec::new();
+        pitch.push(&vec![0.0; sr as usize / 4], |f| frames.push(f));
+        assert!(!frames.is_empty() && frames.iter().all(|f| f.midi_note.is_none()));
+
+        frames.clear();
+        let tone: Vec<f32> = (0..sr as usize / 2)
+            .map(|i| 0.3 * (2.0 * std::f32::consts::PI * 440.0 * i as f32 / sr as f32).sin())
+            .collect();
+        pitch.push(&tone, |f| frames.push(f));
+        // ~30 frames in half a second; the last ones see only the tone
+        assert!((25..=35).contains(&frames.len()));
+        let note = frames.last().unwrap().midi_note.expect("voiced");
+        assert!((note - 69.0).abs() < 0.1, "got {}", note);
+        assert!(frames.windows(2).all(|w| w[0].ts < w[1].ts));
+    }
+}
@@ -0,0 +1,422 @@
+//! Loudness normalisation (EBU R128 / ITU-R BS.1770).
+//!
+//! `normalize_library` queues a background job (see `jobs`) that measures
+//! the integrated loudness of every song's audio: K-weighted mean square over 400 ms blocks
+//! (75 % overlap), gated at −70 LUFS and then 10 LU below the ungated
+//! mean. The gain that brings a track to `TARGET_LUFS` — capped so its
+//! sample peak stays below `PEAK_CEILING_DBFS` — is stored in
+//! `song_loudness` and applied by the feeder whenever the player opens
+//! that file, replay-gain style. Measurements are also kept in the
+//! analysis cache, so a re-scan or a moved song is never decoded twice.
+//! Audio paths are stored and looked up as `paths::path_key`, so the
+//! player finds a gain however the frontend spells the path. Gains looked
+//! up once stay in memory (`GAINS`), so opening a song again runs no query.
+//!
+//! Normalisation is on unless the `loudness_normalization` setting is
+//! `"false"`.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+use super::analysis_cache::{AnalysisCache, CacheKind};
+use super::stream_decoder::{PcmSource, StreamingDecoder};
+use super::vocal_removal::Biquad;
+use crate::access::{require_webview, Capability};
+use crate::db::DbState;
+use crate::events::{publish, AppEvent};
+use crate::jobs::{self, JobContext, JobSpec};
+use crate::paths::path_key;
+use crate::scheduler::{now_ms, read_setting};
+
+pub const PROGRESS_EVENT: &str = "loudness://progress";
+pub const COMPLETE_EVENT: &str = "loudness://complete";
+pub const ENABLED_SETTING: &str = "loudness_normalization";
+
+/// ReplayGain 2 reference level; karaoke backing tracks sit well at it.
+pub const TARGET_LUFS: f64 = -18.0;
+const PEAK_CEILING_DBFS: f64 = -1.0;
+const MAX_BOOST_DB: f64 = 12.0;
+const MAX_CUT_DB: f64 = -24.0;
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+const RELATIVE_GATE_LU: f64 = -10.0;
+/// Gating blocks are 400 ms, advanced in 100 ms steps.
+const STEPS_PER_BLOCK: usize = 4;
+
+/// Stored gain (dB) per audio path key; `None` for a file not measured.
+static GAINS: Mutex<Option<HashMap<String, Option<f64>>>> = Mutex::new(None);
+
+fn remember_gain(key: String, gain_db: Option<f64>) {
+    if let Ok(mut gains) = GAINS.lock() {
+        gains.get_or_insert_with(HashMap::new).insert(key, gain_db);
+    }
+}
+
+/// Result of one measurement.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Loudness {
+    /// `None` for silence (no block above the absolute gate).
+    pub integrated_lufs: Option<f64>,
+    pub peak_dbfs: f64,
+}
+
+impl Loudness {
+    /// Gain in dB that normalises this track, within the clip ceiling.
+    pub fn gain_db(&self) -> f64 {
+        let Some(lufs) = self.integrated_lufs else { return 0.0 };
+        (TARGET_LUFS - lufs).min(PEAK_CEILING_DBFS - self.peak_dbfs).clamp(MAX_CUT_DB, MAX_BOOST_DB)
+    }
+}
+
+/// BS.1770 K-weighting (high shelf + high-pass) for `sample_rate`.
+fn k_weighting(sample_rate: u32) -> (Biquad, Biquad) {
+    let rate = sample_rate.max(1) as f64;
+
+    // Stage 1: +4 dB shelf modelling the head
+    let k = (std::f64::consts::PI * 1681.974_450_955_533 / rate).tan();
+    let q = 0.707_175_236_955_419_6;
+    let vh = 10f64.powf(3.999_843_853_973_347 / 20.0);
+    let vb = vh.powf(0.499_666_774_154_541_6);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad::from_coefficients(
+        ((vh + vb * k / q + k * k) / a0) as f32,
+        (2.0 * (k * k - vh) / a0) as f32,
+        ((vh - vb * k / q + k * k) / a0) as f32,
+        (2.0 * (k * k - 1.0) / a0) as f32,
+        ((1.0 - k / q + k * k) / a0) as f32,
+    );
+
+    // Stage 2: RLB high-pass
+    let k = (std::f64::consts::PI * 38.135_470_876_024_44 / rate).tan();
+    let q = 0.500_327_037_323_877_3;
+    let a0 = 1.0 + k / q + k * k;
+    let highpass = Biquad::from_coefficients(
+        1.0,
+        -2.0,
+        1.0,
+        (2.0 * (k * k - 1.0) / a0) as f32,
+        ((1.0 - k / q + k * k) / a0) as f32,
+    );
+    (shelf, highpass)
+}
+
+/// Streaming integrated-loudness meter over interleaved audio.
+pub struct LoudnessMeter {
+    filters: Vec<(Biquad, Biquad)>,
+    weights: Vec<f64>,
+    step_len: usize,
+    frames_in_step: usize,
+    step_sum: f64,
+    /// Weighted power of the last steps, for the current 400 ms block.
+    recent_steps: Vec<f64>,
+    blocks: Vec<f64>,
+    peak: f32,
+}
+
+impl LoudnessMeter {
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        let channels = channels.max(1) as usize;
+        // 5.1 (L R C LFE Ls Rs): no LFE, surrounds weighted +1.5 dB
+        let weights = (0..channels)
+            .map(|c| match (channels, c) {
+                (6, 3) => 0.0,
+                (6, 4) | (6, 5) => 1.41,
+                _ => 1.0,
+            })
+            .collect();
+        Self {
+            filters: (0..channels).map(|_| k_weighting(sample_rate)).collect(),
+            weights,
+            step_len: (sample_rate as usize / 10).max(1),
+            frames_in_step: 0,
+            step_sum: 0.0,
+            recent_steps: Vec::with_capacity(STEPS_PER_BLOCK),
+            blocks: Vec::new(),
+            peak: 0.0,
+        }
+    }
+
+    pub fn push(&mut self, samples: &[f32]) {
+        let channels = self.filters.len();
+        for frame in samples.chunks_exact(channels) {
+            for (c, &sample) in frame.iter().enumerate() {
+                self.peak = self.peak.max(sample.abs());
+                let (shelf, highpass) = &mut self.filters[c];
+                let weighted = highpass.process(shelf.process(sample)) as f64;
+                self.step_sum += self.weights[c] * weighted * weighted;
+            }
+            self.frames_in_step += 1;
+            if self.frames_in_step == self.step_len {
+                self.finish_step();
+            }
+        }
+    }
+
+    fn finish_step(&mut self) {
+        if self.recent_steps.len() == STEPS_PER_BLOCK {
+            self.recent_steps.remove(0);
+        }
+        self.recent_steps.push(self.step_sum / self.step_len as f64);
+        self.step_sum = 0.0;
+        self.frames_in_step = 0;
+        if self.recent_steps.len() == STEPS_PER_BLOCK {
+            self.blocks.push(self.recent_steps.iter().sum::<f64>() / STEPS_PER_BLOCK as f64);
+        }
+    }
+
+    pub fn finish(&self) -> Loudness {
+        let to_lufs = |power: f64| -0.691 + 10.0 * power.log10();
+        let gated_mean = |threshold: f64| {
+            let kept: Vec<f64> = self.blocks.iter().copied().filter(|&p| to_lufs(p) > threshold).collect();
+            (!kept.is_empty()).then(|| kept.iter().sum::<f64>() / kept.len() as f64)
+        };
+        let integrated_lufs = gated_mean(ABSOLUTE_GATE_LUFS)
+            .and_then(|ungated| gated_mean((to_lufs(ungated) + RELATIVE_GATE_LU).max(ABSOLUTE_GATE_LUFS)))
+            .map(to_lufs);
+        Loudness { integrated_lufs, peak_dbfs: 20.0 * (self.peak.max(1e-9) as f64).log10() }
+    }
+}
+
+/// Decode `path` and measure it.
+pub fn measure_file(path: &str) -> Result<Loudness, String> {
+    let mut source = StreamingDecoder::open(path)?;
+    let mut meter = LoudnessMeter::new(source.sample_rate(), source.channels());
+    let mut chunk = Vec::new();
+    loop {
+        chunk.clear();
+        let more = source.read_chunk(&mut chunk)?;
+        meter.push(&chunk);
+        if !more {
+            return Ok(meter.finish());
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Storage
+// ---------------------------------------------------------------------------
+
+fn save(conn: &Connection, song_id: &str, audio_path: &str, loudness: &Loudness) -> Result<(), String> {
+    let audio_path = path_key(audio_path);
+    conn.execute(
+        "INSERT OR REPLACE INTO song_loudness (song_id, audio_path, integrated_lufs, peak_dbfs, gain_db, analyzed_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            song_id,
+            audio_path,
+            loudness.integrated_lufs,
+            loudness.peak_dbfs,
+            loudness.gain_db(),
+            now_ms(),
+        ],
+    )
+    .map_err(|e| format!("Failed to save loudness: {}", e))?;
+    remember_gain(audio_path, Some(loudness.gain_db()));
+    Ok(())
+}
+
+/// Linear gain for playing `file_path`: 1.0 when normalisation is off or
+/// the file has not been measured.
+pub fn playback_gain(app: &AppHandle, file_path: &str) -> f32 {
+    let Some(db) = app.try_state::<DbState>() else { return 1.0 };
+    let Ok(conn) = db.conn.lock() else { return 1.0 };
+    if read_setting(&conn, ENABLED_SETTING).as_deref() == Some("false") {
+        return 1.0;
+    }
+    let key = path_key(file_path);
+    let cached = GAINS.lock().ok().and_then(|gains| gains.as_ref()?.get(&key).copied());
+    let gain_db = match cached {
+        Some(gain_db) => gain_db,
+        None => {
+            // Rows measured before paths were stored as keys have the raw path
+            let gain_db: Option<f64> = conn
+                .query_row(
+                    "SELECT gain_db FROM song_loudness WHERE audio_path IN (?1, ?2) LIMIT 1",
+                    [key.as_str(), file_path],
+                    |row| row.get(0),
+                )
+                .optional()
+                .unwrap_or(None);
+            remember_gain(key, gain_db);
+            gain_db
+        }
+    };
+    gain_db.map_or(1.0, |db| 10f64.powf(db / 20.0) as f32)
+}
+
+// ---------------------------------------------------------------------------
+// Library job
+// ---------------------------------------------------------------------------
+
+/// Payload of `loudness://progress`, after each track.
+#[derive(Debug, Clone, Serialize)]
+pub struct NormalizeProgress {
+    pub done: usize,
+    pub total: usize,
+    pub song_id: String,
+    pub gain_db: Option<f64>,
+    pub error: Option<String>,
+}
+
+/// Payload of `loudness://complete`.
+#[derive(Debug, Clone, Serialize)]
+pub struct NormalizeComplete {
+    pub analyzed: usize,
+    pub failed: usize,
+    pub cancelled: bool,
+}
+
+/// (song id, audio path) of the songs to measure.
+fn pending_songs(conn: &Connection, force: bool) -> Result<Vec<(String, String)>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT s.id, s.folder_path, s.audio_file_name FROM songs s
+             LEFT JOIN song_loudness l ON l.song_id = s.id
+             WHERE s.audio_file_name IS NOT NULL AND s.audio_file_name != '' AND s.folder_path != ''
+               AND (?1 OR l.song_id IS NULL)",
+        )
+        .map_err(|e| format!("Failed to query songs: {}", e))?;
+    let rows = stmt
+        .query_map([force], |row| {
+            let folder: String = row.get(1)?;
+            let file: String = row.get(2)?;
+            Ok((row.get(0)?, Path::new(&folder).join(file).to_string_lossy().to_string()))
+        })
+        .map_err(|e| format!("Failed to query songs: {}", e))?;
+    rows.collect::<Result<_, _>>().map_err(|e| format!("Failed to read songs: {}", e))
+}
+
+fn measure_cached(cache: Option<&AnalysisCache>, path: &str) -> Result<Loudness, String> {
+    let key = cache.and_then(|_| AnalysisCache::key_for(path).ok());
+    if let (Some(cache), Some(key)) = (cache, key.as_ref()) {
+        if let Some(loudness) = cache.get::<Loudness>(key, CacheKind::Loudness, None) {
+            return Ok(loudness);
+        }
+    }
+    let loudness = measure_file(path)?;
+    if let (Some(cache), Some(key)) = (cache, key.as_ref()) {
+        if let Err(e) = cache.put(key, CacheKind::Loudness, None, &loudness) {
+            tracing::warn!("[loudness] {}", e);
+        }
+    }
+    Ok(loudness)
+}
+
+/// Body of a loudness job: measure the songs still pending when it starts,
+/// pausing between songs while one plays.
+pub(crate) async fn run_job(ctx: &JobContext, force: bool) -> Result<(), String> {
+    let app = ctx.app();
+    let songs = {
+        let db = app.state::<DbState>();
+        let conn = db.conn.lock().map_err(|e| e.to_string())?;
+        pending_songs(&conn, force)?
+    };
+    let total = songs.len();
+    tracing::info!("[loudness] Analysing {} songs", total);
+
+    let cache = AnalysisCache::from_app(app);
+    let (mut analyzed, mut failed) = (0, 0);
+    for (done, (song_id, path)) in songs.into_iter().enumerate() {
+        if !ctx.checkpoint().await {
+            break;
+        }
+        let measured = {
+            let (cache, path) = (cache.clone(), path.clone());
+            tauri::async_runtime::spawn_blocking(move || measure_cached(cache.as_ref(), &path))
+                .await
+                .map_err(|e| e.to_string())
+                .and_then(|r| r)
+        };
+        let saved = measured.and_then(|loudness| {
+            let db = app.state::<DbState>();
+            let conn = db.conn.lock().map_err(|e| e.to_string())?;
+            save(&conn, &song_id, &path, &loudness)?;
+            Ok(loudness.gain_db())
+        });
+        if saved.is_ok() {
+            analyzed += 1;
+        } else {
+            failed += 1;
+        }
+        if let Err(e) = &saved {
+            tracing::warn!("[loudness] {}: {}", path, e);
+        }
+        ctx.progress(done + 1, total);
+        publish(
+            app,
+            AppEvent::LoudnessProgress(NormalizeProgress {
+                done: done + 1,
+                total,
+                song_id,
+                gain_db: saved.as_ref().ok().copied(),
+                error: saved.err(),
+            }),
+        );
+    }
+    let cancelled = ctx.is_cancelled();
+    tracing::info!("[loudness] {} analysed, {} failed", analyzed, failed);
+    publish(app, AppEvent::LoudnessComplete(NormalizeComplete { analyzed, failed, cancelled }));
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+/// Measure every song not analysed yet (all of them with `force`) in the
+/// background, one at a time. Returns the number of songs queued.
+#[tauri::command]
+pub fn normalize_library(app: AppHandle, webview: tauri::Webview, force: Option<bool>) -> Result<usize, String> {
+    require_webview(&webview, Capability::ManageLibrary)?;
+    crate::telemetry::feature(&app, "loudness_normalization");
+    let force = force.unwrap_or(false);
+    let spec = JobSpec::Loudness { force };
+    let total = {
+        let db = app.state::<DbState>();
+        let conn = db.conn.lock().map_err(|e| e.to_string())?;
+        if jobs::has_pending(&conn, spec.kind())? {
+            return Err("Loudness analysis is already running".to_string());
+        }
+        pending_songs(&conn, force)?.len()
+    };
+    jobs::enqueue(&app, spec)?;
+    tracing::info!("[loudness] Queued {} songs", total);
+    Ok(total)
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stereo_sine_at_minus_23_dbfs_reads_minus_23_lufs() {
+        // EBU Tech 3341, case 1: 1 kHz, −23 dBFS on both channels
+        let sr = 48_000;
+        let amplitude = 10f32.powf(-23.0 / 20.0);
+        let tone: Vec<f32> = (0..sr * 5)
+            .flat_map(|i| {
+                let s = amplitude * (2.0 * std::f32::consts::PI * 1_000.0 * i as f32 / sr as f32).sin();
+                [s, s]
+            })
+            .collect();
+        let mut meter = LoudnessMeter::new(sr as u32, 2);
+        meter.push(&tone);
+        let loudness = meter.finish();
+        let lufs = loudness.integrated_lufs.unwrap();
+        assert!((lufs + 23.0).abs() < 0.1, "got {} LUFS", lufs);
+        // +5 dB to the target, allowed by the peak
+        assert!((loudness.gain_db() - 5.0).abs() < 0.1);
+
+        let silence = LoudnessMeter::new(sr as u32, 2).finish();
+        assert_eq!(silence.integrated_lufs, None);
+        assert_eq!(silence.gain_db(), 0.0);
+    }
+}
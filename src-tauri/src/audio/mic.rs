@@ -0,0 +1,298 @@
+//! Live microphone capture.
+//!
+//! `start_mic_capture` opens one input device (mono: the device's first
+//! mapped mic input, or all channels mixed) on a dedicated thread, which
+//! owns the !Send cpal stream and drains the capture ring every few
+//! milliseconds. Levels go out as `mic://level` at ~20 Hz for the meters,
+//! the sung pitch as `pitch://frame` at 60 Hz (see `live_pitch`), stamped
+//! with the playback position minus the device's scoring offset.
+//! Scoring, recording and effects hang off the same drained signal, so
+//! there is only ever one capture stream. Starting a capture replaces the
+//! running one.
+
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use cpal::traits::{DeviceTrait, HostTrait};
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+use super::commands::AudioState;
+use super::device_offsets;
+use super::devices::{self, AudioDeviceInfo};
+use super::level_calibration::{self, to_dbfs, CLIP_THRESHOLD};
+use super::live_pitch::LivePitch;
+use crate::access::{require_webview, Capability};
+use crate::db::DbState;
+use crate::events::{publish, AppEvent};
+
+pub const LEVEL_EVENT: &str = "mic://level";
+/// How often the capture thread drains the ring.
+const DRAIN_INTERVAL: Duration = Duration::from_millis(5);
+const LEVEL_INTERVAL: Duration = Duration::from_millis(50);
+pub const MAX_GAIN: f32 = 2.0;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MicInput {
+    #[serde(flatten)]
+    pub device: AudioDeviceInfo,
+    pub is_default: bool,
+    /// Calibrated speech level (see `level_calibration`), if any.
+    pub reference_level_dbfs: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MicCapture {
+    pub device_id: String,
+    pub device_name: String,
+    pub sample_rate: u32,
+}
+
+/// Payload of `mic://level`, over the last `LEVEL_INTERVAL`.
+#[derive(Debug, Clone, Serialize)]
+pub struct MicLevel {
+    pub device_id: String,
+    pub rms_dbfs: f64,
+    pub peak_dbfs: f64,
+    pub clipping: bool,
+}
+
+/// RMS / peak accumulator between two level events.
+#[derive(Default)]
+struct LevelMeter {
+    sum_squares: f64,
+    count: usize,
+    peak: f32,
+}
+
+impl LevelMeter {
+    fn push(&mut self, samples: &[f32]) {
+        for &s in samples {
+            self.sum_squares += (s as f64) * (s as f64);
+            self.peak = self.peak.max(s.abs());
+        }
+        self.count += samples.len();
+    }
+
+    /// (rms dBFS, peak dBFS, clipping) since the last call; resets.
+    fn take(&mut self) -> (f64, f64, bool) {
+        let rms = if self.count == 0 { 0.0 } else { (self.sum_squares / self.count as f64).sqrt() };
+        let level = (to_dbfs(rms), to_dbfs(self.peak as f64), self.peak >= CLIP_THRESHOLD);
+        *self = Self::default();
+        level
+    }
+}
+
+struct MicSession {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+/// Managed state: the running capture, if any, and the input gain.
+pub struct MicState {
+    session: Mutex<Option<MicSession>>,
+    /// Linear gain applied to the captured signal, as f32 bits; shared
+    /// with the capture thread.
+    gain: Arc<AtomicU32>,
+}
+
+impl Default for MicState {
+    fn default() -> Self {
+        Self { session: Mutex::new(None), gain: Arc::new(AtomicU32::new(1.0f32.to_bits())) }
+    }
+}
+
+impl MicState {
+    pub fn gain(&self) -> f32 {
+        f32::from_bits(self.gain.load(Ordering::Relaxed))
+    }
+
+    /// Set the input gain (0 ..= `MAX_GAIN`, 1 = unchanged); a running
+    /// capture picks it up at once. Returns the value applied.
+    pub fn set_gain(&self, gain: f32) -> f32 {
+        let gain = if gain.is_finite() { gain.clamp(0.0, MAX_GAIN) } else { 1.0 };
+        self.gain.store(gain.to_bits(), Ordering::Relaxed);
+        gain
+    }
+
+    fn replace(&self, next: Option<MicSession>) {
+        let previous = match self.session.lock() {
+            Ok(mut session) => std::mem::replace(&mut *session, next),
+            Err(_) => return,
+        };
+        if let Some(previous) = previous {
+            previous.stop.store(true, Ordering::Relaxed);
+            let _ = previous.thread.join();
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_capture(
+    app: AppHandle,
+    device: cpal::Device,
+    device_id: String,
+    mic_channel: Option<u16>,
+    offset_ms: i64,
+    gain: Arc<AtomicU32>,
+    stop: Arc<AtomicBool>,
+    ready: mpsc::Sender<Result<u32, String>>,
+) {
+    let (stream, mut consumer, sample_rate) = match level_calibration::open_capture(&device, mic_channel) {
+        Ok(opened) => opened,
+        Err(e) => {
+            let _ = ready.send(Err(e));
+            return;
+        }
+    };
+    let _ = ready.send(Ok(sample_rate));
+
+    let mut drained = vec![0.0f32; (sample_rate as usize / 50).max(256)];
+    let mut meter = LevelMeter::default();
+    let mut pitch = LivePitch::new(sample_rate);
+    let mut last_level = Instant::now();
+    while !stop.load(Ordering::Relaxed) {
+        std::thread::sleep(DRAIN_INTERVAL);
+        loop {
+            let n = consumer.pop(&mut drained);
+            if n == 0 {
+                break;
+            }
+            apply_gain(&mut drained[..n], f32::from_bits(gain.load(Ordering::Relaxed)));
+            meter.push(&drained[..n]);
+            pitch.push(&drained[..n], |mut frame| {
+                // The singer is heard `offset_ms` after the music was played
+                let position_ms = app.try_state::<AudioState>().map(|a| a.position_ms()).unwrap_or(0);
+                frame.position_ms = (position_ms as i64 - offset_ms).max(0) as u64;
+                publish(&app, AppEvent::PitchFrame(frame));
+            });
+        }
+        if last_level.elapsed() >= LEVEL_INTERVAL {
+            last_level = Instant::now();
+            let (rms_dbfs, peak_dbfs, clipping) = meter.take();
+            publish(&app, AppEvent::MicLevel(MicLevel { device_id: device_id.clone(), rms_dbfs, peak_dbfs, clipping }));
+        }
+    }
+    drop(stream);
+    tracing::info!("[mic] Capture on {} stopped", device_id);
+}
+
+/// Scale `samples` by `gain`, hard-limited to full scale.
+fn apply_gain(samples: &mut [f32], gain: f32) {
+    if gain == 1.0 {
+        return;
+    }
+    for s in samples {
+        *s = (*s * gain).clamp(-1.0, 1.0);
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+/// Input devices with their calibrated reference levels.
+#[tauri::command]
+pub fn list_audio_inputs(app: AppHandle) -> Result<Vec<MicInput>, String> {
+    let host = cpal::default_host();
+    let default_name = host.default_input_device().and_then(|d| d.name().ok());
+    let default_host = format!("{:?}", host.id());
+    let db = app.state::<DbState>();
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    devices::list_input_devices()?
+        .into_iter()
+        .map(|device| {
+            Ok(MicInput {
+                is_default: device.host_name == default_host && default_name.as_ref() == Some(&device.name),
+                reference_level_dbfs: level_calibration::reference_level(&conn, &device.name)?,
+                device,
+            })
+        })
+        .collect()
+}
+
+/// Capture `device_id` (`"default"` or an id from `list_audio_inputs`),
+/// replacing the running capture.
+#[tauri::command]
+pub async fn start_mic_capture(app: AppHandle, webview: tauri::Webview, device_id: String) -> Result<MicCapture, String> {
+    require_webview(&webview, Capability::ControlPlayback)?;
+    app.state::<MicState>().replace(None);
+    let device = devices::resolve_input_device(&device_id)?;
+    let device_name = device.name().unwrap_or_default();
+    let mic_channel = app.state::<AudioState>().mic_channel(&device_name);
+    let offset_ms = {
+        let db = app.state::<DbState>();
+        let conn = db.conn.lock().map_err(|e| e.to_string())?;
+        device_offsets::offset_for_device(&conn, &device_name)?
+    };
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let gain = app.state::<MicState>().gain.clone();
+    let (ready, opened) = mpsc::channel();
+    let thread = {
+        let (app, device_id, stop) = (app.clone(), device_id.clone(), stop.clone());
+        std::thread::Builder::new()
+            .name("karaoke-mic".into())
+            .spawn(move || run_capture(app, device, device_id, mic_channel, offset_ms, gain, stop, ready))
+            .map_err(|e| format!("Failed to spawn capture thread: {}", e))?
+    };
+    let opened = tauri::async_runtime::spawn_blocking(move || {
+        opened.recv_timeout(Duration::from_secs(5)).map_err(|e| format!("Capture did not start: {}", e))?
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+    let sample_rate = match opened {
+        Ok(sample_rate) => sample_rate,
+        Err(e) => {
+            // A stream that opens after the timeout must not linger
+            stop.store(true, Ordering::Relaxed);
+            return Err(e);
+        }
+    };
+
+    app.state::<MicState>().replace(Some(MicSession { stop, thread }));
+    tracing::info!("[mic] Capturing {} ({} Hz)", device_name, sample_rate);
+    Ok(MicCapture { device_id, device_name, sample_rate })
+}
+
+#[tauri::command]
+pub fn stop_mic_capture(app: AppHandle, webview: tauri::Webview) -> Result<(), String> {
+    require_webview(&webview, Capability::ControlPlayback)?;
+    app.state::<MicState>().replace(None);
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn meter_reports_and_resets() {
+        let mut meter = LevelMeter::default();
+        meter.push(&[0.5, -0.5, 0.5, -0.5]);
+        let (rms, peak, clipping) = meter.take();
+        assert!((rms - to_dbfs(0.5)).abs() < 1e-9 && (peak - rms).abs() < 1e-9 && !clipping);
+
+        meter.push(&[1.0, 0.0]);
+        assert!(meter.take().2);
+        assert_eq!(meter.take().0, to_dbfs(0.0));
+    }
+
+    #[test]
+    fn gain_scales_and_limits() {
+        let mut samples = [0.25, -0.75, 0.5];
+        apply_gain(&mut samples, 2.0);
+        assert_eq!(samples, [0.5, -1.0, 1.0]);
+
+        let mic = MicState::default();
+        assert_eq!(mic.gain(), 1.0);
+        assert_eq!(mic.set_gain(5.0), MAX_GAIN);
+        assert_eq!(mic.set_gain(f32::NAN), 1.0);
+    }
+}
@@ -1,5 +1,27 @@
 pub mod analysis;
+pub mod analysis_cache;
 pub mod analysis_commands;
+pub mod channel_map;
 pub mod commands;
+pub mod device_offsets;
 pub mod devices;
+pub mod fingerprint;
+pub mod hotplug;
+pub mod latency_calibration;
+pub mod level_calibration;
+pub mod live_pitch;
+pub mod loudness;
+pub mod mic;
+pub mod output_mix;
+pub mod pitch_shift;
+pub mod playback_feed;
 pub mod player;
+pub mod position;
+pub mod resample;
+pub mod rt_priority;
+pub mod spsc;
+pub mod stream_decoder;
+pub mod test_tone;
+pub mod transition;
+pub mod vocal_removal;
+pub mod waveform;
@@ -0,0 +1,239 @@
+//! Dual outputs: the main mix to the PA and a second, independent mix to
+//! stage monitors or headphones.
+//!
+//! `OutputConfig` names the primary device (used when `audio_play_file` is
+//! given no device) and an optional secondary device, each with its own
+//! `MixProfile`. The secondary stream decodes the same track separately
+//! and follows the primary's position, re-seeking when the two device
+//! clocks drift apart. Master volume applies to the primary only; the
+//! monitor level is the secondary profile's `gain`.
+//!
+//! Persisted in `app_settings` under `audio_outputs`.
+
+use std::sync::{Arc, Mutex};
+
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+
+const SETTINGS_KEY: &str = "audio_outputs";
+
+/// Click length per beat.
+const CLICK_MS: f64 = 25.0;
+const CLICK_HZ: f64 = 1_500.0;
+
+/// How one output's mix differs from the plain track. Applied by the feeder
+/// thread, never in the audio callback.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct MixProfile {
+    /// Linear gain, 0.0 – 2.0.
+    pub gain: f32,
+    /// 0.0 = untouched, 1.0 = centre-panned content (usually the lead vocal)
+    /// removed. Stereo tracks only.
+    pub vocal_reduction: f32,
+    /// Level of the metronome click, 0.0 = off. Needs a click track
+    /// (`audio_set_click_track`) for the current song.
+    pub click_gain: f32,
+}
+
+impl Default for MixProfile {
+    fn default() -> Self {
+        Self {
+            gain: 1.0,
+            vocal_reduction: 0.0,
+            click_gain: 0.0,
+        }
+    }
+}
+
+impl MixProfile {
+    fn validate(&self, label: &str) -> Result<(), String> {
+        if !(0.0..=2.0).contains(&self.gain) {
+            return Err(format!("{}: gain {} out of range (0–2)", label, self.gain));
+        }
+        if !(0.0..=1.0).contains(&self.vocal_reduction) || !(0.0..=1.0).contains(&self.click_gain) {
+            return Err(format!("{}: vocal reduction and click gain must be 0–1", label));
+        }
+        Ok(())
+    }
+
+    pub fn is_neutral(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MixProfiles {
+    pub primary: MixProfile,
+    pub secondary: MixProfile,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct OutputConfig {
+    /// Device id ("default" or "<host_name>:<device_index>"); `None` keeps
+    /// whatever the caller passes to `audio_play_file`.
+    pub primary: Option<String>,
+    /// Second device, or `None` for a single output.
+    pub secondary: Option<String>,
+    pub mix_profiles: MixProfiles,
+}
+
+impl OutputConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if let (Some(primary), Some(secondary)) = (&self.primary, &self.secondary) {
+            if primary == secondary {
+                return Err("Primary and secondary output must be different devices".to_string());
+            }
+        }
+        self.mix_profiles.primary.validate("primary")?;
+        self.mix_profiles.secondary.validate("secondary")
+    }
+}
+
+/// Beat grid for the click: `bpm` beats per minute, first beat at `offset_ms`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClickTrack {
+    pub bpm: f64,
+    #[serde(default)]
+    pub offset_ms: f64,
+}
+
+/// Output config, shared between the commands and the audio thread. The
+/// click track is live playback state instead (`PlaybackState::click`).
+#[derive(Debug, Default)]
+pub struct OutputSettings {
+    pub config: OutputConfig,
+}
+
+pub type SharedOutputSettings = Arc<Mutex<OutputSettings>>;
+
+pub fn load(conn: &Connection) -> OutputConfig {
+    conn.query_row("SELECT value FROM app_settings WHERE key = ?1", [SETTINGS_KEY], |row| {
+        row.get::<_, String>(0)
+    })
+    .ok()
+    .and_then(|json| match serde_json::from_str::<OutputConfig>(&json) {
+        Ok(config) => Some(config),
+        Err(e) => {
+            tracing::warn!("[audio] Ignoring invalid output config: {}", e);
+            None
+        }
+    })
+    .unwrap_or_default()
+}
+
+pub fn save(conn: &Connection, config: &OutputConfig) -> Result<(), String> {
+    let json = serde_json::to_string(config).map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT OR REPLACE INTO app_settings (key, value) VALUES (?1, ?2)",
+        (SETTINGS_KEY, &json),
+    )
+    .map_err(|e| format!("Failed to save output config: {}", e))?;
+    Ok(())
+}
+
+/// Apply `profile` in place to interleaved `samples` (`channels` wide, at
+/// `sample_rate`) whose first frame is `start_frame` of the track.
+pub fn apply_mix(
+    samples: &mut [f32],
+    channels: u16,
+    sample_rate: u32,
+    start_frame: u64,
+    profile: &MixProfile,
+    click: Option<&ClickTrack>,
+) {
+    let width = channels.max(1) as usize;
+
+    if channels == 2 && profile.vocal_reduction > 0.0 {
+        // Mid/side: centre-panned content lives in the mid signal
+        let keep = 1.0 - profile.vocal_reduction;
+        for frame in samples.chunks_exact_mut(2) {
+            let mid = (frame[0] + frame[1]) * 0.5;
+            let side = (frame[0] - frame[1]) * 0.5;
+            frame[0] = mid * keep + side;
+            frame[1] = mid * keep - side;
+        }
+    }
+
+    if profile.gain != 1.0 {
+        for s in samples.iter_mut() {
+            *s *= profile.gain;
+        }
+    }
+
+    let Some(click) = click.filter(|c| c.bpm > 0.0 && profile.click_gain > 0.0 && sample_rate > 0) else {
+        return;
+    };
+    let rate = sample_rate as f64;
+    let beat_frames = rate * 60.0 / click.bpm;
+    let click_frames = rate * CLICK_MS / 1000.0;
+    let offset_frames = click.offset_ms * rate / 1000.0;
+    for (i, frame) in samples.chunks_exact_mut(width).enumerate() {
+        let t = (start_frame + i as u64) as f64 - offset_frames;
+        if t < 0.0 {
+            continue;
+        }
+        let into_beat = t % beat_frames;
+        if into_beat >= click_frames {
+            continue;
+        }
+        // Short decaying sine burst
+        let envelope = 1.0 - into_beat / click_frames;
+        let value = (std::f64::consts::TAU * CLICK_HZ * into_beat / rate).sin() * envelope;
+        let value = value as f32 * profile.click_gain;
+        for s in frame.iter_mut() {
+            *s += value;
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vocal_reduction_removes_centre_keeps_sides() {
+        // Frame 1: centred (vocal); frame 2: hard left (guitar)
+        let mut samples = vec![0.5, 0.5, 0.4, 0.0];
+        let profile = MixProfile {
+            vocal_reduction: 1.0,
+            ..MixProfile::default()
+        };
+        apply_mix(&mut samples, 2, 48_000, 0, &profile, None);
+        assert!(samples[0].abs() < 1e-6 && samples[1].abs() < 1e-6);
+        assert!((samples[2] - 0.2).abs() < 1e-6 && (samples[3] + 0.2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn click_sounds_only_at_beat_starts() {
+        let rate = 48_000;
+        // 120 bpm → a beat every 24000 frames, each click 1200 frames long
+        let click = ClickTrack { bpm: 120.0, offset_ms: 0.0 };
+        let profile = MixProfile {
+            click_gain: 1.0,
+            ..MixProfile::default()
+        };
+        let mut samples = vec![0.0f32; 48_000];
+        apply_mix(&mut samples, 1, rate, 0, &profile, Some(&click));
+        assert!(samples[1..1_200].iter().any(|s| s.abs() > 0.1));
+        assert!(samples[1_300..24_000].iter().all(|&s| s == 0.0));
+        assert!(samples[24_001..25_200].iter().any(|s| s.abs() > 0.1));
+    }
+
+    #[test]
+    fn rejects_same_device_twice() {
+        let config = OutputConfig {
+            primary: Some("WASAPI:1".into()),
+            secondary: Some("WASAPI:1".into()),
+            ..OutputConfig::default()
+        };
+        assert!(config.validate().is_err());
+    }
+}
@@ -0,0 +1,213 @@
+//! Key change: pitch shifting at constant tempo.
+//!
+//! A phase vocoder (STFT, 2048-point frames at 4× overlap): each frame's
+//! bins are moved to `bin × ratio` with their true frequencies scaled
+//! alike, and phases are re-accumulated so partials stay continuous
+//! between frames. Transients smear slightly, which backing tracks hide
+//! well; vocals are what singers change key for, and those are theirs.
+//!
+//! Streaming and interleaved: the feeder pushes chunks of any length and
+//! gets the same number of frames back. The analysis delay (frame minus
+//! hop) is skipped at the start and `flush` returns the tail, so output
+//! stays aligned with the track clock.
+
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+
+const FRAME: usize = 2048;
+const OVERSAMPLING: usize = 4;
+const HOP: usize = FRAME / OVERSAMPLING;
+const LATENCY: usize = FRAME - HOP;
+/// Shifts beyond an octave sound broken; the UI offers less anyway.
+pub const MAX_SEMITONES: i32 = 12;
+
+struct ChannelState {
+    input: Vec<f32>,
+    output: Vec<f32>,
+    accumulator: Vec<f32>,
+    last_phase: Vec<f32>,
+    phase_sum: Vec<f32>,
+}
+
+impl ChannelState {
+    fn new() -> Self {
+        Self {
+            input: vec![0.0; FRAME],
+            output: vec![0.0; HOP],
+            accumulator: vec![0.0; 2 * FRAME],
+            last_phase: vec![0.0; FRAME / 2 + 1],
+            phase_sum: vec![0.0; FRAME / 2 + 1],
+        }
+    }
+}
+
+pub struct PitchShifter {
+    ratio: f32,
+    channels: usize,
+    states: Vec<ChannelState>,
+    /// Position in the input frame of the next sample, `LATENCY..FRAME`.
+    rover: usize,
+    /// Output frames still to drop for the analysis delay.
+    skip: usize,
+    window: Vec<f32>,
+    fft: Arc<dyn Fft<f32>>,
+    ifft: Arc<dyn Fft<f32>>,
+    spectrum: Vec<Complex<f32>>,
+    magnitudes: Vec<f32>,
+    frequencies: Vec<f32>,
+    shifted_magnitudes: Vec<f32>,
+    shifted_frequencies: Vec<f32>,
+}
+
+/// Frequency ratio of a shift by `semitones`.
+pub fn ratio(semitones: i32) -> f32 {
+    2f32.powf(semitones.clamp(-MAX_SEMITONES, MAX_SEMITONES) as f32 / 12.0)
+}
+
+impl PitchShifter {
+    pub fn new(semitones: i32, channels: u16) -> Self {
+        let mut planner = FftPlanner::new();
+        let channels = channels.max(1) as usize;
+        let bins = FRAME / 2 + 1;
+        Self {
+            ratio: ratio(semitones),
+            channels,
+            states: (0..channels).map(|_| ChannelState::new()).collect(),
+            rover: LATENCY,
+            skip: LATENCY,
+            window: (0..FRAME).map(|k| 0.5 - 0.5 * (2.0 * PI * k as f32 / FRAME as f32).cos()).collect(),
+            fft: planner.plan_fft_forward(FRAME),
+            ifft: planner.plan_fft_inverse(FRAME),
+            spectrum: vec![Complex::default(); FRAME],
+            magnitudes: vec![0.0; bins],
+            frequencies: vec![0.0; bins],
+            shifted_magnitudes: vec![0.0; bins],
+            shifted_frequencies: vec![0.0; bins],
+        }
+    }
+
+    /// Shift interleaved `input`, appending the result to `out`. Once the
+    /// analysis delay is through, `out` grows by exactly `input.len()`.
+    pub fn process(&mut self, input: &[f32], out: &mut Vec<f32>) {
+        for frame in input.chunks_exact(self.channels) {
+            let skip = self.skip > 0;
+            for (channel, &sample) in frame.iter().enumerate() {
+                let state = &mut self.states[channel];
+                state.input[self.rover] = sample;
+                if !skip {
+                    out.push(state.output[self.rover - LATENCY]);
+                }
+            }
+            self.skip = self.skip.saturating_sub(1);
+            self.rover += 1;
+            if self.rover >= FRAME {
+                self.rover = LATENCY;
+                for channel in 0..self.channels {
+                    self.shift_frame(channel);
+                }
+            }
+        }
+    }
+
+    /// Push out what is still inside the vocoder (at the end of a track).
+    pub fn flush(&mut self, out: &mut Vec<f32>) {
+        let pending = LATENCY - self.skip;
+        self.skip = 0;
+        let silence = vec![0.0; pending * self.channels];
+        self.process(&silence, out);
+    }
+
+    fn shift_frame(&mut self, channel: usize) {
+        let expected = 2.0 * PI * HOP as f32 / FRAME as f32;
+        let bins = FRAME / 2 + 1;
+        let state = &mut self.states[channel];
+
+        for (k, bin) in self.spectrum.iter_mut().enumerate() {
+            *bin = Complex::new(state.input[k] * self.window[k], 0.0);
+        }
+        self.fft.process(&mut self.spectrum);
+
+        // Analysis: magnitude and true frequency (in bins) of every bin
+        for k in 0..bins {
+            let bin = self.spectrum[k];
+            let phase = bin.im.atan2(bin.re);
+            let mut delta = phase - state.last_phase[k] - k as f32 * expected;
+            state.last_phase[k] = phase;
+            delta -= 2.0 * PI * (delta / (2.0 * PI)).round();
+            self.magnitudes[k] = 2.0 * bin.norm();
+            self.frequencies[k] = k as f32 + delta * OVERSAMPLING as f32 / (2.0 * PI);
+        }
+
+        self.shifted_magnitudes.iter_mut().for_each(|m| *m = 0.0);
+        self.shifted_frequencies.iter_mut().for_each(|f| *f = 0.0);
+        for k in 0..bins {
+            let target = (k as f32 * self.ratio) as usize;
+            if target < bins {
+                self.shifted_magnitudes[target] += self.magnitudes[k];
+                self.shifted_frequencies[target] = self.frequencies[k] * self.ratio;
+            }
+        }
+
+        // Synthesis: accumulate phase from the shifted frequencies
+        for k in 0..FRAME {
+            self.spectrum[k] = if k < bins {
+                let deviation = self.shifted_frequencies[k] - k as f32;
+                state.phase_sum[k] += 2.0 * PI * deviation / OVERSAMPLING as f32 + k as f32 * expected;
+                Complex::from_polar(self.shifted_magnitudes[k], state.phase_sum[k])
+            } else {
+                Complex::default()
+            };
+        }
+        self.ifft.process(&mut self.spectrum);
+
+        let scale = 2.0 / ((FRAME / 2) as f32 * OVERSAMPLING as f32);
+        for k in 0..FRAME {
+            state.accumulator[k] += self.window[k] * self.spectrum[k].re * scale;
+        }
+        state.output.copy_from_slice(&state.accumulator[..HOP]);
+        state.accumulator.copy_within(HOP.., 0);
+        let tail = state.accumulator.len() - HOP;
+        state.accumulator[tail..].iter_mut().for_each(|s| *s = 0.0);
+        state.input.copy_within(HOP.., 0);
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zero_crossings(samples: &[f32]) -> usize {
+        samples.windows(2).filter(|w| w[0] < 0.0 && w[1] >= 0.0).count()
+    }
+
+    #[test]
+    fn shifts_a_tone_and_keeps_length() {
+        let sr = 48_000;
+        // One second of stereo 440 Hz, fed in odd-sized chunks
+        let tone: Vec<f32> = (0..sr)
+            .flat_map(|i| {
+                let s = 0.5 * (2.0 * PI * 440.0 * i as f32 / sr as f32).sin();
+                [s, s]
+            })
+            .collect();
+        let mut shifter = PitchShifter::new(-12, 2);
+        let mut out = Vec::new();
+        for chunk in tone.chunks(2 * 333) {
+            shifter.process(chunk, &mut out);
+        }
+        shifter.flush(&mut out);
+        assert_eq!(out.len(), tone.len());
+
+        // An octave down: ~220 crossings per second in the steady middle
+        let left: Vec<f32> = out.iter().step_by(2).copied().skip(sr / 4).take(sr / 2).collect();
+        let per_second = zero_crossings(&left) * 2;
+        assert!((215..=225).contains(&per_second), "got {} Hz", per_second);
+        assert_eq!(ratio(12), 2.0);
+    }
+}
@@ -0,0 +1,646 @@
+//! Bounded buffer between a decoder thread and the output callback.
+//!
+//! The feeder thread pulls from a `PcmSource`, resamples to the device rate,
+//! applies the loudness gain, the key change, vocal removal, the output's
+//! mix profile and channel map and keeps at most `BUFFER_MS` of
+//! device-ready audio queued in an SPSC ring. The output callback only drains the ring
+//! through its `FeedReader`, so memory use is independent of track length
+//! and the callback never locks or allocates.
+//!
+//! Seeks are requested by the callback with a sequence number; the feeder
+//! repositions the source, records where its stale output ends in the ring
+//! and publishes the sequence back. Until the reader sees its latest seek
+//! acknowledged it outputs silence, then skips the stale samples.
+//!
+//! Song changes (see `transition`) work the same way in the other
+//! direction: the feeder switches to the staged song and publishes the
+//! ring position where it begins; the reader restarts its clock there.
+//!
+//! A nudge moves playback a few ms without a seek: the reader skips or
+//! repeats one frame every `NUDGE_SPACING` frames until it has made up the
+//! difference, which nobody hears.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::channel_map::apply_output_map;
+use super::output_mix::{apply_mix, ClickTrack, MixProfile};
+use super::pitch_shift::PitchShifter;
+use super::player::{convert_channels, PlaybackState};
+use super::resample::StreamResampler;
+use super::spsc::{ring, RingConsumer, RingProducer};
+use super::stream_decoder::PcmSource;
+use super::transition::{crossfade_gains, Transition, TransitionMode};
+use super::vocal_removal::VocalRemover;
+
+/// Device-ready audio kept queued ahead of the callback.
+const BUFFER_MS: u64 = 2000;
+/// Audio queued before the stream starts, so playback does not begin with
+/// an underrun.
+const PREFILL_MS: u64 = 250;
+const PREFILL_TIMEOUT: Duration = Duration::from_millis(1000);
+const IDLE_SLEEP: Duration = Duration::from_millis(5);
+/// Frames played between two skipped or repeated ones while nudging: a
+/// nudge makes up 1 ms per `NUDGE_SPACING` ms played.
+pub const NUDGE_SPACING: u64 = 200;
+
+/// State shared by the feeder thread and the reader; atomics only, except
+/// the staged song, which the reader never touches.
+struct FeedShared {
+    /// Latest seek requested by the reader, and its target.
+    requested_seek: AtomicU64,
+    requested_seek_ms: AtomicU64,
+    /// Latest seek the feeder has performed. `discard_until` and
+    /// `seek_base_frame` are written before it (release).
+    done_seek: AtomicU64,
+    /// Ring position where audio for the new position begins.
+    discard_until: AtomicUsize,
+    seek_base_frame: AtomicU64,
+    /// The source is exhausted (or failed); nothing more will be queued.
+    eof: AtomicBool,
+    /// Deck switches performed by the feeder; `switch_at` (ring position
+    /// where the new song begins) and `switch_duration_ms` are written
+    /// before it (release).
+    switch_seq: AtomicU64,
+    switch_at: AtomicUsize,
+    switch_duration_ms: AtomicU64,
+    /// `switch_seq` as of the last acknowledged seek.
+    seek_switch_seq: AtomicU64,
+    /// Latest switch the reader has played into.
+    applied_switch: AtomicU64,
+    /// Song to continue with, from `FeedControl::stage`.
+    next: Mutex<Option<NextTrack>>,
+}
+
+/// The next song, staged for a transition.
+pub struct NextTrack {
+    pub source: Box<dyn PcmSource>,
+    /// Loudness normalisation gain (linear).
+    pub gain: f32,
+}
+
+/// Player-side handle to a running feeder; does not keep it alive.
+pub struct FeedControl {
+    shared: Weak<FeedShared>,
+}
+
+impl FeedControl {
+    /// Stage (or with `None`, withdraw) the song that follows.
+    pub fn stage(&self, next: Option<NextTrack>) -> Result<(), String> {
+        let shared = self.shared.upgrade().ok_or("Playback has stopped")?;
+        *shared.next.lock().map_err(|e| e.to_string())? = next;
+        Ok(())
+    }
+}
+
+/// Callback-side end of the feed. Owned by the output callback.
+pub struct FeedReader {
+    shared: Arc<FeedShared>,
+    ring: RingConsumer,
+    sample_rate: u32,
+    channels: usize,
+    /// Frame the ring started at after the last seek.
+    base_frame: u64,
+    /// Frames read since `base_frame`.
+    played_frames: u64,
+    /// Last seek this reader requested / saw acknowledged.
+    seek_seq: u64,
+    applied_seq: u64,
+    /// Last deck switch played into, and the new duration not yet taken.
+    applied_switch: u64,
+    switched_to: Option<u64>,
+    /// Frames still to skip (positive) or repeat (negative).
+    nudge_frames: i64,
+    /// Frames played since the last one skipped or repeated.
+    since_nudge: u64,
+}
+
+impl FeedReader {
+    /// Move playback `ms` ahead (behind when negative) over the next
+    /// seconds; replaces the nudge under way.
+    pub fn nudge(&mut self, ms: i64) {
+        self.nudge_frames = ms * self.sample_rate as i64 / 1000;
+        self.since_nudge = 0;
+    }
+
+    /// Drop queued audio and ask the feeder to continue from `position_ms`.
+    pub fn request_seek(&mut self, position_ms: u64) {
+        self.seek_seq += 1;
+        self.shared.requested_seek_ms.store(position_ms, Ordering::Relaxed);
+        self.shared.requested_seek.store(self.seek_seq, Ordering::Release);
+        self.base_frame = position_ms * self.sample_rate as u64 / 1000;
+        self.played_frames = 0;
+        self.nudge_frames = 0;
+    }
+
+    /// Fill `out` with whole frames of queued audio; returns samples written.
+    /// Returns 0 while a seek is still being served.
+    pub fn read(&mut self, out: &mut [f32]) -> usize {
+        let done = self.shared.done_seek.load(Ordering::Acquire);
+        if done != self.applied_seq {
+            self.ring.skip_to(self.shared.discard_until.load(Ordering::Relaxed));
+            self.base_frame = self.shared.seek_base_frame.load(Ordering::Relaxed);
+            self.played_frames = 0;
+            self.applied_seq = done;
+            self.applied_switch = self.shared.seek_switch_seq.load(Ordering::Relaxed);
+            self.shared.applied_switch.store(self.applied_switch, Ordering::Release);
+        }
+        if self.applied_seq != self.seek_seq {
+            return 0;
+        }
+
+        // Whole frames only, so channels never shift on underrun
+        let available = self.ring.len() / self.channels * self.channels;
+        let wanted = (out.len() / self.channels * self.channels).min(available);
+        let mut n = 0;
+        let switch = self.shared.switch_seq.load(Ordering::Acquire);
+        if switch != self.applied_switch {
+            let before = self.shared.switch_at.load(Ordering::Relaxed).wrapping_sub(self.ring.read_position());
+            if before <= wanted {
+                // Play up to the boundary; the new song's clock starts there
+                n = self.ring.pop(&mut out[..before]);
+                self.base_frame = 0;
+                self.played_frames = 0;
+                self.applied_switch = switch;
+                self.shared.applied_switch.store(switch, Ordering::Release);
+                self.switched_to = Some(self.shared.switch_duration_ms.load(Ordering::Relaxed));
+            }
+        }
+        let due = self.nudge_frames != 0 && self.since_nudge >= NUDGE_SPACING;
+        if due && self.nudge_frames < 0 && wanted - n >= 2 * self.channels {
+            // Repeat the last frame instead of reading one more
+            let rest = self.ring.pop(&mut out[n..wanted - self.channels]);
+            self.played_frames += (rest / self.channels) as u64;
+            if rest < self.channels {
+                return n + rest;
+            }
+            let end = n + rest;
+            out.copy_within(end - self.channels..end, end);
+            self.nudge_frames += 1;
+            self.since_nudge = 0;
+            return end + self.channels;
+        }
+        let rest = self.ring.pop(&mut out[n..wanted]);
+        self.played_frames += (rest / self.channels) as u64;
+        self.since_nudge += (rest / self.channels) as u64;
+        if due && self.nudge_frames > 0 && self.ring.len() >= self.channels {
+            // Skip the frame that would come next
+            self.ring.skip_to(self.ring.read_position().wrapping_add(self.channels));
+            self.played_frames += 1;
+            self.nudge_frames -= 1;
+            self.since_nudge = 0;
+        }
+        n + rest
+    }
+
+    /// Duration of the song playback has just moved on to, once per switch.
+    pub fn take_switch(&mut self) -> Option<u64> {
+        self.switched_to.take()
+    }
+
+    pub fn control(&self) -> FeedControl {
+        FeedControl { shared: Arc::downgrade(&self.shared) }
+    }
+
+    /// Playback position of the next sample to be read.
+    pub fn position_ms(&self) -> u64 {
+        (self.base_frame + self.played_frames) * 1000 / self.sample_rate.max(1) as u64
+    }
+
+    /// The source ended and everything queued has been played.
+    pub fn finished(&self) -> bool {
+        self.applied_seq == self.seek_seq && self.shared.eof.load(Ordering::Acquire) && self.ring.is_empty()
+    }
+
+    /// Block until `PREFILL_MS` of audio is queued, the source ended, or the
+    /// timeout passed. Called before the stream starts, never from the callback.
+    pub fn wait_prefill(&self) {
+        let wanted = (PREFILL_MS * self.sample_rate as u64 / 1000) as usize * self.channels;
+        let started = Instant::now();
+        while started.elapsed() < PREFILL_TIMEOUT {
+            if self.shared.eof.load(Ordering::Acquire) || self.ring.len() >= wanted {
+                return;
+            }
+            thread::sleep(IDLE_SLEEP);
+        }
+    }
+}
+
+/// Routing applied by the feeder to each chunk.
+pub struct FeedRouting {
+    pub device_rate: u32,
+    pub device_channels: u16,
+    pub outputs: Vec<u16>,
+    pub mix: MixProfile,
+    /// Live settings (gain, key change, vocal removal, click) read per chunk;
+    /// `None` for test signals.
+    pub live: Option<Arc<PlaybackState>>,
+}
+
+/// Start a feeder thread for `source`, positioned at `start_ms`.
+pub fn spawn_feeder(
+    mut source: Box<dyn PcmSource>,
+    routing: FeedRouting,
+    start_ms: u64,
+) -> Result<FeedReader, String> {
+    let sample_rate = routing.device_rate;
+    let channels = routing.device_channels.max(1) as usize;
+    let capacity = (BUFFER_MS * sample_rate as u64 / 1000) as usize * channels;
+    let (producer, consumer) = ring(capacity);
+
+    let shared = Arc::new(FeedShared {
+        requested_seek: AtomicU64::new(0),
+        requested_seek_ms: AtomicU64::new(0),
+        done_seek: AtomicU64::new(0),
+        discard_until: AtomicUsize::new(0),
+        seek_base_frame: AtomicU64::new(0),
+        eof: AtomicBool::new(false),
+        switch_seq: AtomicU64::new(0),
+        switch_at: AtomicUsize::new(0),
+        switch_duration_ms: AtomicU64::new(0),
+        seek_switch_seq: AtomicU64::new(0),
+        applied_switch: AtomicU64::new(0),
+        next: Mutex::new(None),
+    });
+
+    if start_ms > 0 {
+        if let Err(e) = source.seek(start_ms) {
+            tracing::warn!("[audio] {}", e);
+        }
+    }
+
+    // The thread holds only a weak reference: once the player drops the
+    // reader (stop, new track), the thread notices and exits.
+    let weak = Arc::downgrade(&shared);
+    thread::Builder::new()
+        .name("karaoke-audio-feed".into())
+        .spawn(move || run_feeder(source, routing, producer, weak, start_ms))
+        .map_err(|e| format!("Failed to spawn audio feeder: {}", e))?;
+
+    Ok(FeedReader {
+        shared,
+        ring: consumer,
+        sample_rate,
+        channels,
+        base_frame: start_ms * sample_rate as u64 / 1000,
+        played_frames: 0,
+        seek_seq: 0,
+        applied_seq: 0,
+        applied_switch: 0,
+        switched_to: None,
+        nudge_frames: 0,
+        since_nudge: 0,
+    })
+}
+
+/// One song decoded to the device rate. The feeder plays one deck and
+/// holds a second while changing songs.
+struct Deck {
+    source: Box<dyn PcmSource>,
+    channels: u16,
+    device_rate: u32,
+    resampler: Option<StreamResampler>,
+    /// Loudness normalisation gain (linear).
+    gain: f32,
+    /// Device-rate frame where the song ends, from its duration (0 = unknown).
+    end_frame: u64,
+    ended: bool,
+}
+
+impl Deck {
+    fn new(source: Box<dyn PcmSource>, gain: f32, device_rate: u32) -> Result<Self, String> {
+        let mut deck = Self {
+            channels: source.channels(),
+            end_frame: source.duration_ms() * device_rate as u64 / 1000,
+            source,
+            device_rate,
+            resampler: None,
+            gain,
+            ended: false,
+        };
+        deck.resampler = deck.new_resampler()?;
+        Ok(deck)
+    }
+
+    fn new_resampler(&self) -> Result<Option<StreamResampler>, String> {
+        let src_rate = self.source.sample_rate();
+        (src_rate != self.device_rate)
+            .then(|| StreamResampler::new(src_rate, self.device_rate, self.channels))
+            .transpose()
+    }
+
+    fn seek(&mut self, position_ms: u64) {
+        if let Err(e) = self.source.seek(position_ms) {
+            tracing::warn!("[audio] {}", e);
+        }
+        // Filter state belongs to the old position
+        self.resampler = self.new_resampler().unwrap_or(None);
+        self.ended = false;
+    }
+
+    /// The next chunk at the device rate, gain applied; empty once ended.
+    fn read(&mut self, chunk: &mut Vec<f32>) -> Vec<f32> {
+        if self.ended {
+            return Vec::new();
+        }
+        chunk.clear();
+        let more = match self.source.read_chunk(chunk) {
+            Ok(more) => more,
+            Err(e) => {
+                tracing::warn!("[audio] {}", e);
+                false
+            }
+        };
+        self.ended = !more;
+
+        let mut resampled = Vec::new();
+        let mut converted = match self.resampler.as_mut() {
+            Some(r) => {
+                let mut result = r.process(chunk, &mut resampled);
+                if !more && result.is_ok() {
+                    result = r.flush(&mut resampled);
+                }
+                if let Err(e) = result {
+                    tracing::warn!("[audio] {}", e);
+                }
+                resampled
+            }
+            None => std::mem::take(chunk),
+        };
+        if self.gain != 1.0 {
+            converted.iter_mut().for_each(|s| *s *= self.gain);
+        }
+        converted
+    }
+}
+
+/// The previous song after a deck switch: faded out under the new one,
+/// and kept until the reader has reached the new song, so a seek that
+/// still refers to the old one can switch back.
+struct Outgoing {
+    deck: Deck,
+    /// Its audio already read, in the new deck's channel layout.
+    buffer: Vec<f32>,
+    faded: usize,
+    fade_frames: usize,
+    /// Song-specific settings to restore when switching back.
+    key_offset: i32,
+    click: Option<ClickTrack>,
+    /// The key the new song was started in, handed back with it.
+    next_key_offset: i32,
+}
+
+impl Outgoing {
+    /// Mix this deck, fading out, under `incoming` (fading in).
+    fn mix_under(&mut self, incoming: &mut [f32], channels: u16, chunk: &mut Vec<f32>) {
+        let width = channels.max(1) as usize;
+        let frames = incoming.len() / width;
+        while self.buffer.len() < frames * width && !self.deck.ended {
+            let block = self.deck.read(chunk);
+            self.buffer.extend(convert_channels(block, self.deck.channels, channels));
+        }
+        for (i, frame) in incoming.chunks_exact_mut(width).enumerate() {
+            let (fade_out, fade_in) = crossfade_gains((self.faded + i) as f32 / self.fade_frames.max(1) as f32);
+            for (c, sample) in frame.iter_mut().enumerate() {
+                let old = self.buffer.get(i * width + c).copied().unwrap_or(0.0);
+                *sample = *sample * fade_in + old * fade_out;
+            }
+        }
+        self.buffer.drain(..(frames * width).min(self.buffer.len()));
+        self.faded += frames;
+    }
+
+    fn fading(&self) -> bool {
+        self.faded < self.fade_frames
+    }
+}
+
+fn run_feeder(
+    source: Box<dyn PcmSource>,
+    routing: FeedRouting,
+    mut producer: RingProducer,
+    weak: Weak<FeedShared>,
+    start_ms: u64,
+) {
+    let rate = routing.device_rate;
+    let device_channels = routing.device_channels.max(1) as usize;
+    let gain = routing.live.as_ref().map_or(1.0, |live| live.track_gain());
+    let mut current = match Deck::new(source, gain, rate) {
+        Ok(deck) => deck,
+        Err(e) => {
+            tracing::warn!("[audio] {}", e);
+            if let Some(shared) = weak.upgrade() {
+                shared.eof.store(true, Ordering::Release);
+            }
+            return;
+        }
+    };
+    // The click of the song on deck, and the change of it last taken
+    let (mut click_seq, mut click) = routing.live.as_ref().map_or((0, None), |live| live.click());
+    let mut chunk = Vec::new();
+    let mut shifter: Option<PitchShifter> = None;
+    let mut shifter_key = 0;
+    let mut remover: Option<VocalRemover> = None;
+    let mut remover_settings = (0.0, true);
+    // Routed audio not yet pushed (the ring was full), from `pending_pos`
+    let mut pending: Vec<f32> = Vec::new();
+    let mut pending_pos = 0;
+    let mut handled_seek = 0;
+    let mut at_eof = false;
+    let mut outgoing: Option<Outgoing> = None;
+    // Deck switches so far, and the silence still due before the next one
+    let mut switches = 0;
+    let mut gap_left: Option<usize> = None;
+    // Track frame (at the device rate) of the next chunk, for the click
+    let mut next_frame = start_ms * rate as u64 / 1000;
+
+    loop {
+        let Some(shared) = weak.upgrade() else { break };
+
+        let requested = shared.requested_seek.load(Ordering::Acquire);
+        if requested != handled_seek {
+            let ms = shared.requested_seek_ms.load(Ordering::Relaxed);
+            let reader_switch = shared.applied_switch.load(Ordering::Acquire);
+            match outgoing.take() {
+                // The reader is still in the previous song: the seek is
+                // into it, and the new song goes back on deck
+                Some(previous) if reader_switch != switches => {
+                    let mut next = std::mem::replace(&mut current, previous.deck);
+                    next.seek(0);
+                    // Staged again as it was, unless another song was
+                    // staged meanwhile
+                    let restaged = shared.next.lock().is_ok_and(|mut staged| {
+                        let empty = staged.is_none();
+                        if empty {
+                            *staged = Some(NextTrack { source: next.source, gain: next.gain });
+                        }
+                        empty
+                    });
+                    if let Some(live) = &routing.live {
+                        if restaged {
+                            live.set_next_key_offset(previous.next_key_offset);
+                        }
+                        live.set_key_offset(previous.key_offset);
+                        live.set_track_gain(current.gain);
+                    }
+                    click = previous.click;
+                    switches -= 1;
+                }
+                _ => {}
+            }
+            current.seek(ms);
+            shifter = None;
+            shifter_key = 0;
+            remover = None;
+            remover_settings = (0.0, true);
+            gap_left = None;
+            pending.clear();
+            pending_pos = 0;
+            at_eof = false;
+            shared.eof.store(false, Ordering::Relaxed);
+            shared.discard_until.store(producer.written(), Ordering::Relaxed);
+            next_frame = ms * rate as u64 / 1000;
+            shared.seek_base_frame.store(next_frame, Ordering::Relaxed);
+            shared.switch_seq.store(switches, Ordering::Relaxed);
+            shared.seek_switch_seq.store(switches, Ordering::Relaxed);
+            shared.done_seek.store(requested, Ordering::Release);
+            handled_seek = requested;
+        }
+
+        if pending_pos < pending.len() {
+            // Push whole frames only, so a discarded remainder never
+            // misaligns the channels that follow it
+            let room = producer.free() / device_channels * device_channels;
+            let end = (pending_pos + room).min(pending.len());
+            pending_pos += producer.push(&pending[pending_pos..end]);
+            if pending_pos < pending.len() {
+                drop(shared);
+                thread::sleep(IDLE_SLEEP);
+                continue;
+            }
+        }
+        if at_eof {
+            shared.eof.store(true, Ordering::Release);
+            drop(shared);
+            thread::sleep(IDLE_SLEEP);
+            continue;
+        }
+        if outgoing.as_ref().is_some_and(|o| !o.fading()) && shared.applied_switch.load(Ordering::Acquire) == switches {
+            outgoing = None;
+        }
+
+        if let Some((seq, live_click)) = routing.live.as_ref().map(|live| live.click()) {
+            if seq != click_seq {
+                click_seq = seq;
+                click = live_click;
+            }
+        }
+
+        // Change decks when the transition point is reached
+        let transition = routing.live.as_ref().map_or_else(Transition::default, |live| live.transition());
+        let has_next =
+            transition.mode != TransitionMode::Off && shared.next.lock().map(|next| next.is_some()).unwrap_or(false);
+        let staged = has_next && outgoing.is_none();
+        let transition_frames = transition.frames(rate);
+        let remaining = current.end_frame.saturating_sub(next_frame) as usize;
+        let fade_frames = match transition.mode {
+            TransitionMode::Crossfade if staged && !current.ended && current.end_frame > 0 && remaining <= transition_frames => {
+                Some(remaining)
+            }
+            TransitionMode::Crossfade if staged && current.ended => Some(0),
+            _ => None,
+        };
+        if staged && transition.mode == TransitionMode::Gap && current.ended {
+            let left = *gap_left.get_or_insert(transition_frames);
+            if left > 0 {
+                let frames = left.min(rate as usize / 10);
+                gap_left = Some(left - frames);
+                let silence = vec![0.0; frames * current.channels.max(1) as usize];
+                pending = apply_output_map(silence, current.channels, routing.device_channels, &routing.outputs);
+                pending_pos = 0;
+                next_frame += frames as u64;
+                continue;
+            }
+        }
+        let switch_now = fade_frames.is_some() || (staged && transition.mode == TransitionMode::Gap && current.ended);
+        let next = switch_now.then(|| shared.next.lock().ok().and_then(|mut next| next.take())).flatten();
+        if let Some(next) = next {
+            match Deck::new(next.source, next.gain, rate) {
+                Ok(deck) => {
+                    let previous = std::mem::replace(&mut current, deck);
+                    // A key change belongs to the song it was set for
+                    let next_key_offset = routing.live.as_ref().map_or(0, |live| live.take_next_key_offset());
+                    outgoing = Some(Outgoing {
+                        deck: previous,
+                        buffer: Vec::new(),
+                        faded: 0,
+                        fade_frames: fade_frames.unwrap_or(0),
+                        key_offset: shifter_key,
+                        click: click.take(),
+                        next_key_offset,
+                    });
+                    if let Some(live) = &routing.live {
+                        live.set_key_offset(next_key_offset);
+                        live.set_track_gain(current.gain);
+                    }
+                    shifter = None;
+                    shifter_key = 0;
+                    remover = None;
+                    remover_settings = (0.0, true);
+                    gap_left = None;
+                    next_frame = 0;
+                    switches += 1;
+                    shared.switch_at.store(producer.written(), Ordering::Relaxed);
+                    shared.switch_duration_ms.store(current.source.duration_ms(), Ordering::Relaxed);
+                    shared.switch_seq.store(switches, Ordering::Release);
+                }
+                Err(e) => tracing::warn!("[audio] Next song unavailable: {}", e),
+            }
+        }
+        if current.ended {
+            // Nothing left to play, unless the staged song is still to come
+            // (the reader has not reached the previous switch yet)
+            at_eof = !has_next;
+            drop(shared);
+            thread::sleep(IDLE_SLEEP);
+            continue;
+        }
+
+        let mut converted = current.read(&mut chunk);
+        let more = !current.ended;
+        let src_channels = current.channels;
+        if let Some(previous) = outgoing.as_mut().filter(|o| o.fading()) {
+            previous.mix_under(&mut converted, src_channels, &mut chunk);
+        }
+        let key = routing.live.as_ref().map_or(0, |live| live.key_offset());
+        if key != shifter_key {
+            shifter = (key != 0).then(|| PitchShifter::new(key, src_channels));
+            shifter_key = key;
+        }
+        if let Some(shifter) = shifter.as_mut() {
+            let mut shifted = Vec::with_capacity(converted.len());
+            shifter.process(&converted, &mut shifted);
+            if !more {
+                shifter.flush(&mut shifted);
+            }
+            converted = shifted;
+        }
+        // Centre cancellation only makes sense for stereo sources
+        let vocal = routing.live.as_ref().filter(|_| src_channels == 2).map_or((0.0, true), |live| live.vocal_removal());
+        if vocal != remover_settings {
+            remover = (vocal.0 > 0.0).then(|| VocalRemover::new(vocal.0, vocal.1, rate));
+            remover_settings = vocal;
+        }
+        if let Some(remover) = remover.as_mut() {
+            remover.process(&mut converted);
+        }
+        if !routing.mix.is_neutral() {
+            apply_mix(&mut converted, src_channels, rate, next_frame, &routing.mix, click.as_ref());
+        }
+        next_frame += (converted.len() / src_channels.max(1) as usize) as u64;
+        pending = apply_output_map(converted, src_channels, routing.device_channels, &routing.outputs);
+        pending_pos = 0;
+    }
+}
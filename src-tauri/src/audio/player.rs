@@ -1,244 +1,711 @@
-use std::fs::File;
-use std::path::PathBuf;
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicI64, AtomicU32, AtomicU64, AtomicU8, Ordering};
+use std::sync::Arc;
 
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use cpal::{SampleFormat, Stream, StreamConfig};
-use rubato::{
-    Resampler, SincFixedIn, SincInterpolationParameters, SincInterpolationType, WindowFunction,
-};
-use symphonia::core::audio::SampleBuffer;
-use symphonia::core::codecs::DecoderOptions;
-use symphonia::core::errors::Error as SymphoniaError;
-use symphonia::core::formats::FormatOptions;
-use symphonia::core::io::MediaSourceStream;
-use symphonia::core::meta::MetadataOptions;
-use symphonia::core::probe::Hint;
+
+use super::channel_map::SharedChannelMaps;
+use super::output_mix::{ClickTrack, MixProfile, SharedOutputSettings};
+use super::playback_feed::{spawn_feeder, FeedControl, FeedReader, FeedRouting, NextTrack};
+use super::resample::{negotiate_output_config, resample_interleaved, StreamResampler};
+use super::rt_priority::RtPromotion;
+use super::stream_decoder::{MemorySource, PcmSource, StreamingDecoder};
+use super::transition::{Transition, TransitionMode};
+
+/// No seek pending (`PlaybackState::seek_request`).
+const NO_SEEK: u64 = u64::MAX;
+/// No nudge pending (`PlaybackState::nudge_request`).
+const NO_NUDGE: i64 = i64::MIN;
+
+/// Samples converted per pass in the output callback; preallocated so the
+/// callback never allocates.
+const CALLBACK_SCRATCH_FRAMES: usize = 1024;
+
+/// The secondary output re-seeks when it is further than this from the
+/// primary (the two device clocks are never exactly equal).
+const FOLLOWER_MAX_DRIFT_MS: u64 = 40;
 
 /// Shared playback state, safe to access from multiple threads.
+///
+/// Every field is an atomic so the output callback reads and updates it
+/// without taking a lock; a command holding a lock can never stall audio.
 #[derive(Debug)]
 pub struct PlaybackState {
     /// Current position in milliseconds.
-    pub position_ms: u64,
+    pub position_ms: AtomicU64,
     /// Total duration in milliseconds.
-    pub duration_ms: u64,
+    pub duration_ms: AtomicU64,
     /// Whether the track is currently playing.
-    pub is_playing: bool,
-    /// Volume 0.0 .. 1.0.
-    pub volume: f32,
-    /// Seek request: Some(target_ms) means seek to that position.
-    pub seek_request: Option<u64>,
+    pub is_playing: AtomicBool,
+    /// Volume 0.0 .. 1.0, as f32 bits.
+    volume: AtomicU32,
+    /// Seek request: a target in ms, or `NO_SEEK`.
+    seek_request: AtomicU64,
+    /// Nudge request in ms (see `FeedReader::nudge`), or `NO_NUDGE`.
+    nudge_request: AtomicI64,
     /// Whether a stop was requested.
-    pub stop_requested: bool,
+    pub stop_requested: AtomicBool,
+    /// Set by the stream error callback when the output device disappears.
+    /// Cleared once the stream has been rebuilt on the returning device.
+    pub device_lost: AtomicBool,
+    /// Key change in semitones, applied by the feeder (see `pitch_shift`).
+    key_offset: AtomicI32,
+    /// Key change the next song starts in instead of 0 (a singer's
+    /// preferred key), consumed when it opens.
+    next_key_offset: AtomicI32,
+    /// Vocal removal strength 0.0 ..= 1.0 as f32 bits, 0 = off (see
+    /// `vocal_removal`).
+    vocal_removal: AtomicU32,
+    /// Cancel only the vocal band of the centre instead of all of it.
+    vocal_band_limited: AtomicBool,
+    /// Loudness normalisation gain of the open track (linear, as f32 bits;
+    /// see `loudness`).
+    track_gain: AtomicU32,
+    /// How playback continues into a staged song (see `transition`).
+    transition_mode: AtomicU8,
+    transition_seconds: AtomicU32,
+    /// Beat grid of the click (see `output_mix`): bpm and first beat as f64
+    /// bits, bpm 0 = none. `click_seq` is bumped after each change.
+    click_bpm: AtomicU64,
+    click_offset_ms: AtomicU64,
+    click_seq: AtomicU64,
+    /// Bumped by the output callback each time playback moves on to the
+    /// staged song.
+    pub track_seq: AtomicU64,
 }
 
 impl Default for PlaybackState {
     fn default() -> Self {
         Self {
-            position_ms: 0,
-            duration_ms: 0,
-            is_playing: false,
-            volume: 1.0,
-            seek_request: None,
-            stop_requested: false,
+            position_ms: AtomicU64::new(0),
+            duration_ms: AtomicU64::new(0),
+            is_playing: AtomicBool::new(false),
+            volume: AtomicU32::new(1.0f32.to_bits()),
+            seek_request: AtomicU64::new(NO_SEEK),
+            nudge_request: AtomicI64::new(NO_NUDGE),
+            stop_requested: AtomicBool::new(false),
+            device_lost: AtomicBool::new(false),
+            key_offset: AtomicI32::new(0),
+            next_key_offset: AtomicI32::new(0),
+            vocal_removal: AtomicU32::new(0.0f32.to_bits()),
+            vocal_band_limited: AtomicBool::new(true),
+            track_gain: AtomicU32::new(1.0f32.to_bits()),
+            transition_mode: AtomicU8::new(0),
+            transition_seconds: AtomicU32::new(0.0f32.to_bits()),
+            click_bpm: AtomicU64::new(0.0f64.to_bits()),
+            click_offset_ms: AtomicU64::new(0.0f64.to_bits()),
+            click_seq: AtomicU64::new(0),
+            track_seq: AtomicU64::new(0),
         }
     }
 }
 
+impl PlaybackState {
+    /// Whether the open track stopped by itself at its end.
+    pub fn played_to_end(&self) -> bool {
+        let duration_ms = self.duration_ms.load(Ordering::Relaxed);
+        !self.is_playing.load(Ordering::Relaxed) && duration_ms > 0 && self.position_ms.load(Ordering::Acquire) >= duration_ms
+    }
+
+    pub fn volume(&self) -> f32 {
+        f32::from_bits(self.volume.load(Ordering::Relaxed))
+    }
+
+    pub fn set_volume(&self, volume: f32) {
+        self.volume.store(volume.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn key_offset(&self) -> i32 {
+        self.key_offset.load(Ordering::Relaxed)
+    }
+
+    pub fn set_key_offset(&self, semitones: i32) {
+        self.key_offset.store(semitones, Ordering::Relaxed);
+    }
+
+    pub fn set_next_key_offset(&self, semitones: i32) {
+        self.next_key_offset.store(semitones, Ordering::Relaxed);
+    }
+
+    /// The key the song being opened starts in; resets it to 0.
+    pub fn take_next_key_offset(&self) -> i32 {
+        self.next_key_offset.swap(0, Ordering::Relaxed)
+    }
+
+    /// (strength, band limited); strength 0 = off.
+    pub fn vocal_removal(&self) -> (f32, bool) {
+        (f32::from_bits(self.vocal_removal.load(Ordering::Relaxed)), self.vocal_band_limited.load(Ordering::Relaxed))
+    }
+
+    pub fn set_vocal_removal(&self, strength: f32, band_limited: bool) {
+        self.vocal_band_limited.store(band_limited, Ordering::Relaxed);
+        self.vocal_removal.store(strength.clamp(0.0, 1.0).to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn track_gain(&self) -> f32 {
+        f32::from_bits(self.track_gain.load(Ordering::Relaxed))
+    }
+
+    pub fn set_track_gain(&self, gain: f32) {
+        self.track_gain.store(gain.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn transition(&self) -> Transition {
+        let mode = match self.transition_mode.load(Ordering::Relaxed) {
+            1 => TransitionMode::Gap,
+            2 => TransitionMode::Crossfade,
+            _ => TransitionMode::Off,
+        };
+        Transition { mode, seconds: f32::from_bits(self.transition_seconds.load(Ordering::Relaxed)) }
+    }
+
+    pub fn set_transition(&self, transition: Transition) {
+        let mode = match transition.mode {
+            TransitionMode::Off => 0,
+            TransitionMode::Gap => 1,
+            TransitionMode::Crossfade => 2,
+        };
+        self.transition_seconds.store(transition.seconds.to_bits(), Ordering::Relaxed);
+        self.transition_mode.store(mode, Ordering::Relaxed);
+    }
+
+    /// The click track and its change count, so a feeder picks up a new
+    /// one mid-song.
+    pub fn click(&self) -> (u64, Option<ClickTrack>) {
+        let seq = self.click_seq.load(Ordering::Acquire);
+        let bpm = f64::from_bits(self.click_bpm.load(Ordering::Relaxed));
+        let offset_ms = f64::from_bits(self.click_offset_ms.load(Ordering::Relaxed));
+        (seq, (bpm > 0.0).then_some(ClickTrack { bpm, offset_ms }))
+    }
+
+    pub fn set_click(&self, click: Option<ClickTrack>) {
+        let (bpm, offset_ms) = click.map_or((0.0, 0.0), |c| (c.bpm, c.offset_ms));
+        self.click_offset_ms.store(offset_ms.to_bits(), Ordering::Relaxed);
+        self.click_bpm.store(bpm.to_bits(), Ordering::Relaxed);
+        self.click_seq.fetch_add(1, Ordering::Release);
+    }
+
+    pub fn request_seek(&self, position_ms: u64) {
+        self.seek_request.store(position_ms.min(NO_SEEK - 1), Ordering::Relaxed);
+    }
+
+    /// Take the pending seek request, if any.
+    fn take_seek(&self) -> Option<u64> {
+        Some(self.seek_request.swap(NO_SEEK, Ordering::Relaxed)).filter(|&ms| ms != NO_SEEK)
+    }
+
+    fn clear_seek(&self) {
+        self.seek_request.store(NO_SEEK, Ordering::Relaxed);
+    }
+
+    /// Drift playback `ms` ahead (behind when negative) without a seek;
+    /// replaces a nudge still under way.
+    pub fn request_nudge(&self, ms: i64) {
+        self.nudge_request.store(ms.max(NO_NUDGE + 1), Ordering::Relaxed);
+    }
+
+    fn take_nudge(&self) -> Option<i64> {
+        Some(self.nudge_request.swap(NO_NUDGE, Ordering::Relaxed)).filter(|&ms| ms != NO_NUDGE)
+    }
+}
+
 /// Decoded audio ready for playback.
-struct DecodedAudio {
+pub(crate) struct DecodedAudio {
     /// Interleaved f32 samples.
-    samples: Vec<f32>,
+    pub(crate) samples: Vec<f32>,
     /// Sample rate of the decoded audio.
-    sample_rate: u32,
+    pub(crate) sample_rate: u32,
     /// Number of channels.
-    channels: u16,
+    pub(crate) channels: u16,
     /// Duration in milliseconds.
+    pub(crate) duration_ms: u64,
+}
+
+/// Where the loaded track's audio comes from.
+enum TrackSource {
+    /// Decoded on the fly while playing; never held in memory as a whole.
+    File(String),
+    /// A generated buffer (test tones).
+    Memory(Arc<DecodedAudio>),
+}
+
+/// The currently loaded track, kept so the output stream can be rebuilt
+/// (e.g. after a device hot-plug) at the current position.
+struct LoadedTrack {
+    source: TrackSource,
+    /// Host the stream was opened on (e.g. "WASAPI").
+    host_name: String,
+    /// Name of the output device, used to find it again after re-enumeration.
+    device_name: String,
+    /// Duration reported to the frontend; also where playback ends.
     duration_ms: u64,
+    /// Explicit output channels for this track, overriding the device's
+    /// channel map (used by the speaker-check test tones).
+    output_override: Option<Vec<u16>>,
+}
+
+/// Stream on the secondary (monitor) device, following the primary.
+struct SecondaryOutput {
+    _stream: Stream,
+    host_name: String,
+    device_name: String,
+    /// Set by the stream error callback when the device disappears.
+    lost: Arc<AtomicBool>,
 }
 
 /// The native audio player.
 /// NOTE: This type is intentionally !Send because cpal::Stream is !Send on some platforms.
 /// It must live exclusively on a single dedicated audio thread.
 pub struct NativeAudioPlayer {
-    state: Arc<Mutex<PlaybackState>>,
+    state: Arc<PlaybackState>,
     stream: Option<Stream>,
+    loaded: Option<LoadedTrack>,
+    /// Per-device routing, shared with the channel-map commands.
+    channel_maps: SharedChannelMaps,
+    /// Primary/secondary devices and their mixes.
+    outputs: SharedOutputSettings,
+    secondary: Option<SecondaryOutput>,
+    /// The primary feeder, for staging the next song.
+    primary_feed: Option<FeedControl>,
+    /// Staged next song and its gain, re-staged when the stream is rebuilt.
+    staged: Option<(String, f32)>,
 }
 
 impl NativeAudioPlayer {
-    /// Lock the state, recovering data from a poisoned mutex.
-    /// This prevents a crash if the audio thread panicked while
-    /// holding the lock. On recovery, resets the state to defaults
-    /// to avoid leaving stale playback flags.
-    fn lock_state(&self) -> std::sync::MutexGuard<'_, PlaybackState> {
-        self.state.lock().unwrap_or_else(|poisoned| {
-            eprintln!("[audio] Recovering from poisoned mutex — resetting playback state");
-            let mut guard = poisoned.into_inner();
-            *guard = PlaybackState::default();
-            guard
-        })
-    }
-
     /// Create a player that shares state with an external Arc (used by the audio thread).
-    pub fn with_shared_state(state: Arc<Mutex<PlaybackState>>) -> Self {
+    pub fn with_shared_state(state: Arc<PlaybackState>, channel_maps: SharedChannelMaps, outputs: SharedOutputSettings) -> Self {
         Self {
             state,
             stream: None,
+            loaded: None,
+            channel_maps,
+            outputs,
+            secondary: None,
+            primary_feed: None,
+            staged: None,
         }
     }
 
-    /// Load an audio file, create an output stream on the given host/device,
-    /// and start playback.  `device_id` is "<host_name>:<device_index>".
+    /// Open an audio file, create an output stream on the given host/device,
+    /// and start playback.  `device_id` is "<host_name>:<device_index>"; when
+    /// empty, the configured primary output (or the default device) is used.
+    /// The file is decoded while it plays (bounded buffer, see `playback_feed`).
     pub fn play_file(&mut self, file_path: &str, device_id: &str) -> Result<(), String> {
+        self.open_file(file_path, device_id, true)
+    }
+
+    /// Like `play_file`, but the stream starts paused at 0 so `resume`
+    /// starts the song without the decoder and device opening delay.
+    pub fn load_file(&mut self, file_path: &str, device_id: &str) -> Result<(), String> {
+        self.open_file(file_path, device_id, false)
+    }
+
+    fn open_file(&mut self, file_path: &str, device_id: &str, playing: bool) -> Result<(), String> {
         // Stop any previous playback
         self.stop();
+        // A key change belongs to the song it was set for
+        self.state.set_key_offset(self.state.take_next_key_offset());
+
+        let configured;
+        let device_id = if device_id.is_empty() {
+            configured = self.outputs.lock().unwrap_or_else(|e| e.into_inner()).config.primary.clone();
+            configured.as_deref().unwrap_or("default")
+        } else {
+            device_id
+        };
+
+        let decoder = StreamingDecoder::open(file_path)?;
+        let duration_ms = decoder.duration_ms();
+        self.start_track(TrackSource::File(file_path.to_string()), Some(Box::new(decoder)), duration_ms, device_id, None, playing)
+    }
 
-        // Decode the audio file
-        let decoded = decode_audio_file(file_path)?;
+    /// Play an in-memory buffer (e.g. a generated test tone). When
+    /// `output_override` is set, the audio is routed only to those device
+    /// channels instead of following the device's channel map.
+    pub(crate) fn play_decoded(
+        &mut self,
+        decoded: DecodedAudio,
+        device_id: &str,
+        output_override: Option<Vec<u16>>,
+    ) -> Result<(), String> {
+        self.stop();
+        let duration_ms = decoded.duration_ms;
+        self.start_track(TrackSource::Memory(Arc::new(decoded)), None, duration_ms, device_id, output_override, true)
+    }
 
+    fn start_track(
+        &mut self,
+        source: TrackSource,
+        opened: Option<Box<dyn PcmSource>>,
+        duration_ms: u64,
+        device_id: &str,
+        output_override: Option<Vec<u16>>,
+        playing: bool,
+    ) -> Result<(), String> {
         // Update state
+        let state = &self.state;
+        state.duration_ms.store(duration_ms, Ordering::Relaxed);
+        state.position_ms.store(0, Ordering::Relaxed);
+        state.is_playing.store(playing, Ordering::Relaxed);
+        state.stop_requested.store(false, Ordering::Relaxed);
+        state.clear_seek();
+        state.device_lost.store(false, Ordering::Relaxed);
+
+        // Resolve the output device
+        let (device, host_name) = resolve_device(device_id)?;
+        let device_name = device.name().unwrap_or_default();
+
+        self.loaded = Some(LoadedTrack {
+            source,
+            duration_ms,
+            host_name,
+            device_name,
+            output_override,
+        });
+
+        self.open_output(&device, 0, opened)
+    }
+
+    /// Rebuild the output stream for the loaded track if its device was lost
+    /// and has reappeared. Playback resumes at the last reported position and
+    /// keeps its paused/playing state.
+    ///
+    /// Returns `Ok(true)` if a stream was reconnected.
+    pub fn try_reconnect(&mut self) -> Result<bool, String> {
+        let position_ms = self.state.position_ms.load(Ordering::Relaxed);
+        if !self.state.device_lost.load(Ordering::Relaxed) {
+            return self.try_reconnect_secondary(position_ms);
+        }
+        let Some(track) = &self.loaded else {
+            return Ok(false);
+        };
+        let Some(device) = super::devices::find_output_device_by_name(&track.host_name, &track.device_name) else {
+            return Ok(false);
+        };
+
+        tracing::info!(
+            "[audio] Output device '{}' is back, reconnecting at {} ms",
+            track.device_name, position_ms
+        );
+
+        // Drop the dead streams before opening new ones on the same hardware
+        self.stream = None;
+        self.secondary = None;
+        self.open_output(&device, position_ms, None)?;
+        self.state.device_lost.store(false, Ordering::Relaxed);
+        Ok(true)
+    }
+
+    /// Reopen a lost secondary output once its device is back.
+    fn try_reconnect_secondary(&mut self, position_ms: u64) -> Result<bool, String> {
+        let Some(secondary) = &self.secondary else { return Ok(false) };
+        if !secondary.lost.load(Ordering::Relaxed)
+            || super::devices::find_output_device_by_name(&secondary.host_name, &secondary.device_name).is_none()
         {
-            let mut state = self.lock_state();
-            state.duration_ms = decoded.duration_ms;
-            state.position_ms = 0;
-            state.is_playing = true;
-            state.stop_requested = false;
-            state.seek_request = None;
+            return Ok(false);
         }
+        tracing::info!("[audio] Secondary output '{}' is back", secondary.device_name);
+        self.secondary = None;
+        self.open_secondary(position_ms)?;
+        Ok(true)
+    }
 
-        // Resolve the output device
-        let (device, _host_name) = resolve_device(device_id)?;
-
-        // Build the output config matching the device's preferred format
-        let supported_config = device
-            .default_output_config()
-            .map_err(|e| format!("Cannot get device config: {}", e))?;
-
-        // Extract sample_format BEFORE consuming supported_config via .into()
-        let sample_format = supported_config.sample_format();
-        let config: StreamConfig = supported_config.into();
-
-        // Resample decoded audio to device sample rate if needed
-        let resampled = resample_if_needed(
-            decoded.samples,
-            decoded.sample_rate,
-            config.sample_rate.0,
-            decoded.channels,
-        )
-        .map_err(|e| format!("Resampling failed: {}", e))?;
-
-        // Convert channel layout if decoded channels differ from device channels
-        // (e.g. stereo audio on a mono device, or mono audio on a stereo device)
-        let adapted = convert_channels(resampled, decoded.channels, config.channels);
+    /// Create and start an output stream for the loaded track on `device`,
+    /// beginning playback at `start_ms`. `opened` reuses a source that was
+    /// already opened (to read its duration); otherwise a new one is opened.
+    /// Also opens the secondary output, if one is configured.
+    fn open_output(
+        &mut self,
+        device: &cpal::Device,
+        start_ms: u64,
+        opened: Option<Box<dyn PcmSource>>,
+    ) -> Result<(), String> {
+        let track = self.loaded.as_ref().ok_or("No track loaded")?;
+        let mix = if track.output_override.is_some() {
+            // Speaker checks play the raw signal
+            MixProfile::default()
+        } else {
+            let settings = self.outputs.lock().unwrap_or_else(|e| e.into_inner());
+            settings.config.mix_profiles.primary.clone()
+        };
+        let device_name = track.device_name.clone();
+        let override_outputs = track.output_override.clone();
+        let duration_ms = track.duration_ms;
 
+        let (config, sample_format, feed) =
+            self.open_feed(device, &device_name, start_ms, opened, override_outputs, mix)?;
         let channels = config.channels;
+        self.primary_feed = Some(feed.control());
 
         match sample_format {
             SampleFormat::F32 => {
-                self.build_stream::<f32>(&device, config, adapted, channels, decoded.duration_ms)?;
+                self.build_stream::<f32>(device, config, feed, channels, duration_ms)?;
             }
             SampleFormat::I16 => {
-                self.build_stream::<i16>(&device, config, adapted, channels, decoded.duration_ms)?;
+                self.build_stream::<i16>(device, config, feed, channels, duration_ms)?;
             }
             SampleFormat::U16 => {
-                self.build_stream::<u16>(&device, config, adapted, channels, decoded.duration_ms)?;
+                self.build_stream::<u16>(device, config, feed, channels, duration_ms)?;
             }
             _ => return Err(format!("Unsupported sample format: {:?}", sample_format)),
         }
 
+        // A missing monitor must never stop the show on the PA
+        if let Err(e) = self.open_secondary(start_ms) {
+            tracing::warn!("[audio] Secondary output unavailable: {}", e);
+        }
+        if let Some((path, gain)) = self.staged.take() {
+            if let Err(e) = self.stage_next(Some(&path), gain) {
+                tracing::warn!("[audio] Could not re-stage '{}': {}", path, e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Stage the song to continue with when the loaded one ends (see
+    /// `transition`); `None` withdraws it. Returns its duration in ms.
+    pub fn stage_next(&mut self, file_path: Option<&str>, gain: f32) -> Result<u64, String> {
+        let feed = self.primary_feed.as_ref().ok_or("Nothing is playing")?;
+        let Some(file_path) = file_path else {
+            self.staged = None;
+            feed.stage(None)?;
+            return Ok(0);
+        };
+        let decoder = StreamingDecoder::open(file_path)?;
+        let duration_ms = decoder.duration_ms();
+        feed.stage(Some(NextTrack { source: Box::new(decoder), gain }))?;
+        self.staged = Some((file_path.to_string(), gain));
+        Ok(duration_ms)
+    }
+
+    /// Playback has moved on to the staged song: it is the loaded track
+    /// from now on, and the secondary output starts following it.
+    pub fn on_track_switched(&mut self) {
+        let Some((path, _)) = self.staged.take() else { return };
+        let Some(track) = self.loaded.as_mut() else { return };
+        track.source = TrackSource::File(path);
+        track.duration_ms = self.state.duration_ms.load(Ordering::Relaxed);
+        let position_ms = self.state.position_ms.load(Ordering::Relaxed);
+        if let Err(e) = self.open_secondary(position_ms) {
+            tracing::warn!("[audio] Secondary output unavailable: {}", e);
+        }
+    }
+
+    /// Open the loaded track's source and start a feeder routed for
+    /// `device`, positioned at `start_ms`.
+    #[allow(clippy::too_many_arguments)]
+    fn open_feed(
+        &self,
+        device: &cpal::Device,
+        device_name: &str,
+        start_ms: u64,
+        opened: Option<Box<dyn PcmSource>>,
+        output_override: Option<Vec<u16>>,
+        mix: MixProfile,
+    ) -> Result<(StreamConfig, SampleFormat, FeedReader), String> {
+        let track = self.loaded.as_ref().ok_or("No track loaded")?;
+        // Test signals play exactly as generated
+        let live = (matches!(track.source, TrackSource::File(_)) && output_override.is_none()).then(|| self.state.clone());
+
+        // Channel routing configured for this device (if any)
+        let mut channel_map = self
+            .channel_maps
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(device_name)
+            .cloned()
+            .unwrap_or_default();
+        if let Some(outputs) = output_override {
+            channel_map.music_outputs = outputs;
+        }
+        let min_channels = channel_map.required_output_channels();
+
+        let source: Box<dyn PcmSource> = match (opened, &track.source) {
+            (Some(source), _) => source,
+            (None, TrackSource::File(path)) => Box::new(StreamingDecoder::open(path)?),
+            (None, TrackSource::Memory(audio)) => Box::new(MemorySource::new(audio.clone())),
+        };
+
+        // Negotiate the stream rate: open the device at the track's own rate
+        // when the hardware allows it, otherwise fall back to its default.
+        let (config, sample_format) = negotiate_output_config(device, source.sample_rate(), min_channels)?;
+
+        // Generated buffers are short: resample them in one go so their
+        // length stays exact; files are resampled chunk by chunk by the feeder
+        let source: Box<dyn PcmSource> = match &track.source {
+            TrackSource::Memory(audio) if audio.sample_rate != config.sample_rate.0 => {
+                let samples = resample_interleaved(audio.samples.clone(), audio.sample_rate, config.sample_rate.0, audio.channels)
+                    .map_err(|e| format!("Resampling failed: {}", e))?;
+                Box::new(MemorySource::new(Arc::new(DecodedAudio {
+                    samples,
+                    sample_rate: config.sample_rate.0,
+                    channels: audio.channels,
+                    duration_ms: audio.duration_ms,
+                })))
+            }
+            _ => source,
+        };
+
+        // The feeder converts the channel layout chunk by chunk (e.g. stereo
+        // audio on a mono device), routing
+        // onto the mapped outputs of multi-channel interfaces.
+        let feed = spawn_feeder(
+            source,
+            FeedRouting {
+                device_rate: config.sample_rate.0,
+                device_channels: config.channels,
+                outputs: channel_map.music_outputs.clone(),
+                mix,
+                live,
+            },
+            start_ms,
+        )?;
+        feed.wait_prefill();
+        Ok((config, sample_format, feed))
+    }
+
+    /// Open the configured secondary output for the loaded file, following
+    /// the primary from `start_ms`. No-op for test signals or when no
+    /// secondary device is configured.
+    fn open_secondary(&mut self, start_ms: u64) -> Result<(), String> {
+        self.secondary = None;
+        let Some(track) = &self.loaded else { return Ok(()) };
+        if track.output_override.is_some() || !matches!(track.source, TrackSource::File(_)) {
+            return Ok(());
+        }
+        let (device_id, mix) = {
+            let settings = self.outputs.lock().unwrap_or_else(|e| e.into_inner());
+            let Some(device_id) = settings.config.secondary.clone() else { return Ok(()) };
+            (device_id, settings.config.mix_profiles.secondary.clone())
+        };
+        let (device, host_name) = resolve_device(&device_id)?;
+        let device_name = device.name().unwrap_or_default();
+        if device_name == track.device_name && host_name == track.host_name {
+            return Err(format!("'{}' is already the primary output", device_name));
+        }
+        let duration_ms = track.duration_ms;
+
+        let (config, sample_format, feed) = self.open_feed(&device, &device_name, start_ms, None, None, mix)?;
+        let lost = Arc::new(AtomicBool::new(false));
+        let stream = match sample_format {
+            SampleFormat::F32 => self.build_follower_stream::<f32>(&device, config, feed, duration_ms, lost.clone())?,
+            SampleFormat::I16 => self.build_follower_stream::<i16>(&device, config, feed, duration_ms, lost.clone())?,
+            SampleFormat::U16 => self.build_follower_stream::<u16>(&device, config, feed, duration_ms, lost.clone())?,
+            _ => return Err(format!("Unsupported sample format: {:?}", sample_format)),
+        };
+        tracing::info!("[audio] Secondary output on '{}'", device_name);
+        self.secondary = Some(SecondaryOutput {
+            _stream: stream,
+            host_name,
+            device_name,
+            lost,
+        });
         Ok(())
     }
 
+    /// Re-apply the output configuration to the track that is playing: the
+    /// secondary output is reopened (or closed) at the current position.
+    pub fn reload_outputs(&mut self) -> Result<(), String> {
+        if self.stream.is_none() {
+            return Ok(());
+        }
+        self.open_secondary(self.state.position_ms.load(Ordering::Relaxed))
+    }
+
     /// Build the audio output stream for a specific sample type.
     fn build_stream<T>(
         &mut self,
         device: &cpal::Device,
         config: StreamConfig,
-        samples: Vec<f32>,
+        mut feed: FeedReader,
         channels: u16,
-        duration_ms: u64,
+        mut duration_ms: u64,
     ) -> Result<(), String>
     where
         T: cpal::Sample + cpal::SizedSample + Default + cpal::FromSample<f32> + 'static,
     {
         let sample_rate = config.sample_rate.0;
         let frame_size = channels as usize;
-        let total_frames = samples.len() / frame_size;
-
-        // Shared playback cursor
-        let cursor = Arc::new(Mutex::new(0usize)); // frame index
-        let cursor_clone = cursor.clone();
 
         let state = self.state.clone();
-        let state_clone = state.clone();
-
-        let samples = Arc::new(samples);
+        let error_state = state.clone();
+        // Allocated here, not in the callback
+        let mut scratch = vec![0.0f32; CALLBACK_SCRATCH_FRAMES * frame_size];
+        let mut promotion = RtPromotion::new("output");
 
         let stream = device
             .build_output_stream(
                 &config.into(),
                 move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
-                    let mut state = state_clone.lock().unwrap_or_else(|e| e.into_inner());
-
-                    // Handle stop
-                    if state.stop_requested {
+                    // Real-time context: atomics and the SPSC ring only — no
+                    // locks, no allocation, no blocking
+                    // Handle stop, and silence until the thread is promoted
+                    let promoted = promotion.ready((data.len() / frame_size) as u32, sample_rate);
+                    if !promoted || state.stop_requested.load(Ordering::Relaxed) {
                         for s in data.iter_mut() {
                             *s = T::default();
                         }
                         return;
                     }
 
-                    // Handle seek
-                    if let Some(target_ms) = state.seek_request.take() {
-                        let target_frame =
-                            (target_ms as f64 / 1000.0 * sample_rate as f64) as usize;
-                        *cursor_clone.lock().unwrap_or_else(|e| e.into_inner()) = target_frame.min(total_frames);
-                        state.position_ms = target_ms;
+                    // Handle seek: the feeder refills from the new position
+                    if let Some(target_ms) = state.take_seek() {
+                        feed.request_seek(target_ms.min(duration_ms));
+                        state.position_ms.store(target_ms.min(duration_ms), Ordering::Relaxed);
+                    }
+                    if let Some(ms) = state.take_nudge() {
+                        feed.nudge(ms);
                     }
 
                     // Handle pause
-                    if !state.is_playing {
+                    if !state.is_playing.load(Ordering::Relaxed) {
                         for s in data.iter_mut() {
                             *s = T::default();
                         }
                         return;
                     }
 
-                    let mut cursor = cursor_clone.lock().unwrap_or_else(|e| e.into_inner());
-                    let volume = state.volume;
-
-                    let src = &*samples;
-                    for frame in data.chunks_mut(frame_size) {
-                        if *cursor >= total_frames {
-                            // Fill silence and signal end
-                            for s in frame.iter_mut() {
-                                *s = T::default();
-                            }
-                            state.is_playing = false;
-                            state.position_ms = duration_ms;
-                            return;
+                    let volume = state.volume();
+                    let mut written = 0;
+                    while written < data.len() {
+                        let wanted = (data.len() - written).min(scratch.len());
+                        let n = feed.read(&mut scratch[..wanted]);
+                        for (s, &v) in data[written..written + n].iter_mut().zip(&scratch[..n]) {
+                            *s = sample_to::<T>(v * volume);
                         }
-
-                        let start = *cursor * frame_size;
-                        for (i, s) in frame.iter_mut().enumerate() {
-                            let src_idx = start + i;
-                            if src_idx < src.len() {
-                                let val = src[src_idx] * volume;
-                                *s = sample_to::<T>(val);
-                            } else {
-                                *s = T::default();
-                            }
+                        written += n;
+                        if n < wanted {
+                            break;
                         }
+                    }
+                    for s in data[written..].iter_mut() {
+                        *s = T::default();
+                    }
 
-                        *cursor += 1;
+                    // The feeder moved on to the staged song
+                    if let Some(next_duration_ms) = feed.take_switch() {
+                        duration_ms = next_duration_ms;
+                        state.duration_ms.store(duration_ms, Ordering::Relaxed);
+                        state.track_seq.fetch_add(1, Ordering::Relaxed);
                     }
 
-                    // Update position
-                    let elapsed_frames = *cursor;
-                    state.position_ms = (elapsed_frames as f64 / sample_rate as f64 * 1000.0) as u64;
+                    if written < data.len() && feed.finished() {
+                        // Source exhausted: signal end
+                        state.is_playing.store(false, Ordering::Relaxed);
+                        state.position_ms.store(duration_ms, Ordering::Release);
+                        return;
+                    }
+
+                    // Update position (an underrun outputs silence without advancing)
+                    state.position_ms.store(feed.position_ms(), Ordering::Relaxed);
                 },
-                |err| {
-                    eprintln!("Audio stream error: {}", err);
+                move |err| {
+                    tracing::error!("Audio stream error: {}", err);
+                    // The device was unplugged / powered off. Remember it so the
+                    // hot-plug monitor can reconnect once it reappears.
+                    if let cpal::StreamError::DeviceNotAvailable = err {
+                        error_state.device_lost.store(true, Ordering::Relaxed);
+                    }
                 },
                 None,
             )
@@ -252,46 +719,110 @@ impl NativeAudioPlayer {
         Ok(())
     }
 
+    /// Build the secondary output stream. It never drives the shared state:
+    /// it mirrors play/pause/stop and chases the primary's position.
+    fn build_follower_stream<T>(
+        &self,
+        device: &cpal::Device,
+        config: StreamConfig,
+        mut feed: FeedReader,
+        duration_ms: u64,
+        lost: Arc<AtomicBool>,
+    ) -> Result<Stream, String>
+    where
+        T: cpal::Sample + cpal::SizedSample + Default + cpal::FromSample<f32> + 'static,
+    {
+        let sample_rate = config.sample_rate.0;
+        let frame_size = config.channels as usize;
+        let state = self.state.clone();
+        let mut scratch = vec![0.0f32; CALLBACK_SCRATCH_FRAMES * frame_size];
+        let mut promotion = RtPromotion::new("output-secondary");
+
+        let stream = device
+            .build_output_stream(
+                &config.into(),
+                move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+                    // Same real-time rules as the primary callback
+                    let promoted = promotion.ready((data.len() / frame_size) as u32, sample_rate);
+                    if !promoted || state.stop_requested.load(Ordering::Relaxed) || !state.is_playing.load(Ordering::Relaxed) {
+                        for s in data.iter_mut() {
+                            *s = T::default();
+                        }
+                        return;
+                    }
+
+                    // Seeks on the primary show up here as a position jump
+                    let target = state.position_ms.load(Ordering::Relaxed).min(duration_ms);
+                    if target.abs_diff(feed.position_ms()) > FOLLOWER_MAX_DRIFT_MS {
+                        feed.request_seek(target);
+                    }
+
+                    let mut written = 0;
+                    while written < data.len() {
+                        let wanted = (data.len() - written).min(scratch.len());
+                        let n = feed.read(&mut scratch[..wanted]);
+                        for (s, &v) in data[written..written + n].iter_mut().zip(&scratch[..n]) {
+                            *s = sample_to::<T>(v);
+                        }
+                        written += n;
+                        if n < wanted {
+                            break;
+                        }
+                    }
+                    for s in data[written..].iter_mut() {
+                        *s = T::default();
+                    }
+                },
+                move |err| {
+                    tracing::error!("Secondary audio stream error: {}", err);
+                    if let cpal::StreamError::DeviceNotAvailable = err {
+                        lost.store(true, Ordering::Relaxed);
+                    }
+                },
+                None,
+            )
+            .map_err(|e| format!("Failed to build secondary output stream: {}", e))?;
+
+        stream.play().map_err(|e| format!("Failed to start secondary stream: {}", e))?;
+        Ok(stream)
+    }
+
     /// Pause playback (stream continues but outputs silence).
     pub fn pause(&self) {
-        let mut state = self.lock_state();
-        state.is_playing = false;
+        self.state.is_playing.store(false, Ordering::Relaxed);
     }
 
     /// Resume playback.
     pub fn resume(&self) {
-        let mut state = self.lock_state();
-        state.is_playing = true;
+        self.state.is_playing.store(true, Ordering::Relaxed);
     }
 
     /// Seek to a position in milliseconds.
     pub fn seek(&self, position_ms: u64) {
-        let mut state = self.lock_state();
-        state.seek_request = Some(position_ms);
+        self.state.request_seek(position_ms);
     }
 
     /// Set volume (0.0 – 1.0).
     pub fn set_volume(&self, volume: f32) {
-        let mut state = self.lock_state();
-        state.volume = volume.max(0.0).min(1.0);
+        self.state.set_volume(volume.max(0.0).min(1.0));
     }
 
     /// Stop playback and clean up.
     pub fn stop(&mut self) {
-        {
-            let mut state = self.lock_state();
-            state.stop_requested = true;
-            state.is_playing = false;
-        }
-        // Drop the stream to stop it
+        self.state.stop_requested.store(true, Ordering::Relaxed);
+        self.state.is_playing.store(false, Ordering::Relaxed);
+        // Drop the streams to stop them
         self.stream = None;
+        self.secondary = None;
+        self.loaded = None;
+        self.primary_feed = None;
+        self.staged = None;
         // Reset state
-        let mut state = self.lock_state();
-        state.position_ms = 0;
-        state.stop_requested = false;
-        state.seek_request = None;
+        self.state.position_ms.store(0, Ordering::Relaxed);
+        self.state.stop_requested.store(false, Ordering::Relaxed);
+        self.state.clear_seek();
+        self.state.device_lost.store(false, Ordering::Relaxed);
     }
-
 }
 
 impl Drop for NativeAudioPlayer {
@@ -314,175 +845,12 @@ fn sample_to<T: cpal::SizedSample + cpal::FromSample<f32>>(val: f32) -> T {
 // Helper functions
 // ---------------------------------------------------------------------------
 
-/// Decode an audio file using Symphonia. Returns interleaved f32 samples.
-pub(crate) fn decode_audio_file(file_path: &str) -> Result<DecodedAudio, String> {
-    // Helper: try opening a file with fallback path strategies.
-    // Mirrors lib.rs native_read_file_bytes but also adds canonicalize-parent.
-    fn try_open(p: &str) -> Option<File> {
-        // Attempt 1: as-is
-        let path = PathBuf::from(p);
-        if let Ok(f) = File::open(&path) { return Some(f); }
-        // Attempt 2: OS-native separators
-        let normalized = p.replace('/', &std::path::MAIN_SEPARATOR.to_string());
-        if let Ok(f) = File::open(&PathBuf::from(&normalized)) { return Some(f); }
-        // Attempt 3: Windows extended-length prefix + canonicalize parent
-        #[cfg(target_os = "windows")]
-        {
-            if let Ok(f) = File::open(&format!(r"\\?\{}", normalized)) { return Some(f); }
-            if let Ok(f) = File::open(&format!(r"\\?\{}", p.replace('/', r"\"))) { return Some(f); }
-            // Attempt 4: Canonicalize the parent directory to resolve symlinks,
-            // junctions, or case mismatches (same as lib.rs native_read_file_bytes).
-            if let Some(parent) = PathBuf::from(&normalized).parent() {
-                if let Ok(canonical_parent) = parent.canonicalize() {
-                    if let Some(file_name) = PathBuf::from(&normalized).file_name() {
-                        let canonical_path = canonical_parent.join(file_name);
-                        if let Ok(f) = File::open(&canonical_path) { return Some(f); }
-                        let verbatim = format!(r"\\?\{}", canonical_path.to_string_lossy());
-                        if let Ok(f) = File::open(&verbatim) { return Some(f); }
-                    }
-                }
-            }
-        }
-        None
-    }
-
-    let file = try_open(file_path)
-        .ok_or_else(|| format!("Audio file not found: {}", file_path))?;
-
-    // Resolve the actual path used (for hint/extension detection)
-    let resolved_path = if PathBuf::from(file_path).exists() {
-        PathBuf::from(file_path)
-    } else {
-        let normalized = file_path.replace('/', &std::path::MAIN_SEPARATOR.to_string());
-        if PathBuf::from(&normalized).exists() {
-            PathBuf::from(normalized)
-        } else {
-            PathBuf::from(file_path)
-        }
-    };
-    // symphonia 0.5 expects Box<dyn MediaSource>; std::fs::File implements MediaSource.
-    let mss = MediaSourceStream::new(Box::new(file), Default::default());
-
-    let mut hint = Hint::new();
-    if let Some(ext) = resolved_path.extension().and_then(|e| e.to_str()) {
-        hint.with_extension(ext);
-    }
-
-    let format_opts = FormatOptions::default();
-    let metadata_opts = MetadataOptions::default();
-
-    // get_probe().format() returns a ProbeResult.
-    // The actual format reader is stored in the `.format` field.
-    let probe_result = symphonia::default::get_probe()
-        .format(&hint, mss, &format_opts, &metadata_opts)
-        .map_err(|e| format!("Unsupported format: {}", e))?;
-
-    let mut format_reader = probe_result.format;
-
-    // Find a decodable audio track.
-    // For audio files the default track is fine; for video containers (MP4, MKV)
-    // the default track may be video, so we fall back to searching all tracks.
-    let decoder_opts = DecoderOptions::default();
-    let codecs = symphonia::default::get_codecs();
-
-    let (track_id, mut decoder, sample_rate, channels) = {
-        let mut found: Option<(u32, Box<dyn symphonia::core::codecs::Decoder>, u32, u16)> = None;
-
-        // 1) Try the default track
-        if let Some(default) = format_reader.default_track() {
-            if let Ok(dec) = codecs.make(&default.codec_params, &decoder_opts) {
-                let sr = default.codec_params.sample_rate.unwrap_or(44100);
-                let ch = default.codec_params.channels.map(|c| c.count() as u16).unwrap_or(2);
-                found = Some((default.id, dec, sr, ch));
-            }
-        }
-
-        // 2) If the default track couldn't be decoded, search all tracks
-        if found.is_none() {
-            for track in format_reader.tracks().iter() {
-                // Audio tracks have at least a sample rate or channels set
-                if track.codec_params.sample_rate.is_none()
-                    && track.codec_params.channels.is_none()
-                {
-                    continue;
-                }
-                if let Ok(dec) = codecs.make(&track.codec_params, &decoder_opts) {
-                    let sr = track.codec_params.sample_rate.unwrap_or(44100);
-                    let ch = track.codec_params.channels.map(|c| c.count() as u16).unwrap_or(2);
-                    found = Some((track.id, dec, sr, ch));
-                    break;
-                }
-            }
-        }
-
-        found.ok_or_else(|| {
-            let ext = resolved_path.extension().and_then(|e| e.to_str()).unwrap_or("?");
-            format!(
-                "No decodable audio track found in file (format: {})",
-                ext
-            )
-        })?
-    };
-
-    let mut all_samples: Vec<f32> = Vec::new();
-    let mut decoded_frames: u64 = 0;
-
-    loop {
-        let packet = match format_reader.next_packet() {
-            Ok(p) => p,
-            Err(SymphoniaError::ResetRequired) => continue,
-            Err(SymphoniaError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
-                break
-            }
-            Err(e) => return Err(format!("Error reading packet: {}", e)),
-        };
-
-        if packet.track_id() != track_id {
-            continue;
-        }
-
-        let audio_buf = match decoder.decode(&packet) {
-            Ok(buf) => buf,
-            Err(SymphoniaError::DecodeError(_)) => continue,
-            Err(e) => return Err(format!("Decode error: {}", e)),
-        };
-
-        // Extract info BEFORE moving audio_buf into copy_interleaved_ref
-        let buf_frames: u64 = audio_buf.frames() as u64;
-        let buf_capacity: u64 = audio_buf.capacity() as u64;
-        let buf_spec = *audio_buf.spec();
-
-        // Convert to interleaved f32 (audio_buf is consumed here)
-        let mut conv_buf: SampleBuffer<f32> = SampleBuffer::new(buf_capacity, buf_spec);
-        conv_buf.copy_interleaved_ref(audio_buf);
-        all_samples.extend_from_slice(conv_buf.samples());
-        decoded_frames += buf_frames;
-    }
-
-    if all_samples.is_empty() {
-        return Err("No audio data decoded".to_string());
-    }
-
-    let duration_ms = if decoded_frames > 0 && sample_rate > 0 {
-        (decoded_frames * 1000) / sample_rate as u64
-    } else {
-        0
-    };
-
-    Ok(DecodedAudio {
-        samples: all_samples,
-        sample_rate,
-        channels,
-        duration_ms,
-    })
-}
-
 /// Convert interleaved f32 samples from `src_channels` to `dst_channels`.
 ///
 /// - Downmix (e.g. stereo → mono): averages all source channels.
 /// - Upmix  (e.g. mono → stereo): duplicates the single channel.
 /// - If channels match, returns the input unchanged.
-fn convert_channels(samples: Vec<f32>, src_channels: u16, dst_channels: u16) -> Vec<f32> {
+pub(crate) fn convert_channels(samples: Vec<f32>, src_channels: u16, dst_channels: u16) -> Vec<f32> {
     if src_channels == dst_channels {
         return samples;
     }
@@ -531,95 +899,8 @@ fn convert_channels(samples: Vec<f32>, src_channels: u16, dst_channels: u16) ->
     output
 }
 
-/// Resample interleaved f32 samples if the source rate differs from the target rate.
-fn resample_if_needed(
-    samples: Vec<f32>,
-    source_rate: u32,
-    target_rate: u32,
-    channels: u16,
-) -> Result<Vec<f32>, String> {
-    if source_rate == target_rate {
-        return Ok(samples);
-    }
-
-    let channels = channels as usize;
-    let chunk_size = 1024;
-
-    let sinc_params = SincInterpolationParameters {
-        sinc_len: 128,
-        f_cutoff: 0.95,
-        oversampling_factor: 64,
-        interpolation: SincInterpolationType::Linear,
-        window: WindowFunction::Hann,
-    };
-
-    let mut resampler = SincFixedIn::new(
-        target_rate as f64 / source_rate as f64,
-        0.95,       // f_cutoff (normalized, must be <= 1.0)
-        sinc_params, // SincInterpolationParameters
-        channels,    // number of channels
-        chunk_size,  // processing chunk size
-    )
-    .map_err(|e| format!("Failed to create resampler: {}", e))?;
-
-    let frames_in = samples.len() / channels;
-
-    // rubato works with deinterleaved data: Vec<Vec<f32>> where each inner Vec is one channel
-    let mut deinterleaved: Vec<Vec<f32>> = vec![Vec::with_capacity(chunk_size); channels];
-    let mut output_samples: Vec<f32> = Vec::new();
-
-    for frame_idx in 0..frames_in {
-        for ch in 0..channels {
-            deinterleaved[ch].push(samples[frame_idx * channels + ch]);
-        }
-
-        if deinterleaved[0].len() == chunk_size {
-            match resampler.process(&deinterleaved, None) {
-                Ok(resampled) => {
-                    // Reinterleave: resampled is Vec<Vec<f64>> (channels × frames)
-                    let num_out = if resampled.is_empty() {
-                        0
-                    } else {
-                        resampled[0].len()
-                    };
-                    for f in 0..num_out {
-                        for ch in 0..channels {
-                            output_samples.push(resampled[ch][f] as f32);
-                        }
-                    }
-                }
-                Err(e) => return Err(format!("Resampling error: {}", e)),
-            }
-            for ch in &mut deinterleaved {
-                ch.clear();
-            }
-        }
-    }
-
-    // Process remaining samples
-    if !deinterleaved[0].is_empty() {
-        match resampler.process(&deinterleaved, None) {
-            Ok(resampled) => {
-                let num_out = if resampled.is_empty() {
-                    0
-                } else {
-                    resampled[0].len()
-                };
-                for f in 0..num_out {
-                    for ch in 0..channels {
-                        output_samples.push(resampled[ch][f] as f32);
-                    }
-                }
-            }
-            Err(e) => return Err(format!("Resampling error: {}", e)),
-        }
-    }
-
-    Ok(output_samples)
-}
-
 /// Resolve a device_id string ("<host_name>:<index>") to a cpal::Device.
-fn resolve_device(device_id: &str) -> Result<(cpal::Device, String), String> {
+pub(super) fn resolve_device(device_id: &str) -> Result<(cpal::Device, String), String> {
     if device_id == "default" {
         let host = cpal::default_host();
         let device = host
@@ -697,33 +978,132 @@ pub struct DecodedMonoAudio {
     pub duration_ms: u64,
 }
 
+/// Tracks longer than this (medleys, concert videos) are analysed at
+/// `LONG_TRACK_ANALYSIS_RATE` to keep the mono buffer small.
+const LONG_TRACK_MS: u64 = 15 * 60 * 1000;
+const LONG_TRACK_ANALYSIS_RATE: u32 = 22_050;
+/// Most mono samples held for analysis (256 MiB of f64). Tracks that would
+/// need more are analysed at a lower rate, down to `MIN_ANALYSIS_RATE`;
+/// past that only their first `MAX_ANALYSIS_SAMPLES` are decoded.
+const MAX_ANALYSIS_SAMPLES: usize = 32 * 1024 * 1024;
+const MIN_ANALYSIS_RATE: u32 = 8_000;
+
+/// Rate a track of `duration_ms` at `source_rate` is analysed at.
+fn analysis_rate(source_rate: u32, duration_ms: u64) -> u32 {
+    let mut rate = source_rate;
+    if duration_ms > LONG_TRACK_MS {
+        rate = rate.min(LONG_TRACK_ANALYSIS_RATE);
+    }
+    if duration_ms > 0 {
+        let fits = (MAX_ANALYSIS_SAMPLES as u64 * 1000 / duration_ms).min(u32::MAX as u64) as u32;
+        if rate > fits {
+            rate = fits.max(MIN_ANALYSIS_RATE).min(source_rate);
+        }
+    }
+    rate
+}
+
 /// Decode an audio file and return mono f64 samples (for the analysis pipeline).
 /// If the source is stereo, channels are mixed down to mono.
+///
+/// Decoding is streamed and mixed down per chunk, so only the mono result is
+/// ever held in memory; long tracks are additionally downsampled (vocal
+/// pitch and tempo need nothing above 11 kHz), and the result never
+/// exceeds `MAX_ANALYSIS_SAMPLES`.
 pub fn decode_mono_f64(file_path: &str) -> Result<DecodedMonoAudio, String> {
-    let decoded = decode_audio_file(file_path)?;
-    let channels = decoded.channels as usize;
-
-    let mono: Vec<f64> = if channels == 1 {
-        decoded.samples.iter().map(|&s| s as f64).collect()
-    } else {
-        let frames = decoded.samples.len() / channels;
-        let mut mono = Vec::with_capacity(frames);
-        for i in 0..frames {
-            let mut sum = 0.0f32;
-            for ch in 0..channels {
-                let idx = i * channels + ch;
-                if idx < decoded.samples.len() {
-                    sum += decoded.samples[idx];
+    let mut source = StreamingDecoder::open(file_path)?;
+    let channels = source.channels().max(1) as usize;
+    let source_rate = source.sample_rate();
+    let sample_rate = analysis_rate(source_rate, source.duration_ms());
+    let mut resampler = (sample_rate != source_rate)
+        .then(|| StreamResampler::new(source_rate, sample_rate, 1))
+        .transpose()?;
+
+    let expected = (source.duration_ms() * sample_rate as u64 / 1000) as usize;
+    let mut mono: Vec<f64> = Vec::with_capacity(expected.min(MAX_ANALYSIS_SAMPLES));
+    let mut chunk = Vec::new();
+    let mut mixed = Vec::new();
+    let mut resampled = Vec::new();
+    loop {
+        chunk.clear();
+        let more = source.read_chunk(&mut chunk)?;
+        mixed.clear();
+        mixed.extend(chunk.chunks_exact(channels).map(|f| f.iter().sum::<f32>() / channels as f32));
+
+        let block: &[f32] = match resampler.as_mut() {
+            Some(r) => {
+                resampled.clear();
+                r.process(&mixed, &mut resampled)?;
+                if !more {
+                    r.flush(&mut resampled)?;
                 }
+                &resampled
             }
-            mono.push((sum / channels as f32) as f64);
+            None => &mixed,
+        };
+        let room = MAX_ANALYSIS_SAMPLES - mono.len();
+        mono.extend(block.iter().take(room).map(|&s| s as f64));
+        if mono.len() >= MAX_ANALYSIS_SAMPLES {
+            tracing::warn!(
+                "[analysis] {} is too long; analysing its first {} s",
+                file_path,
+                MAX_ANALYSIS_SAMPLES / sample_rate.max(1) as usize
+            );
+            break;
         }
-        mono
-    };
+        if !more {
+            break;
+        }
+    }
 
+    if mono.is_empty() {
+        return Err("No audio data decoded".to_string());
+    }
+
+    let duration_ms = mono.len() as u64 * 1000 / sample_rate.max(1) as u64;
     Ok(DecodedMonoAudio {
         samples: mono,
-        sample_rate: decoded.sample_rate,
-        duration_ms: decoded.duration_ms,
+        sample_rate,
+        duration_ms,
     })
 }
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_track_ends_only_at_its_end() {
+        let state = PlaybackState::default();
+        assert!(!state.played_to_end());
+        state.duration_ms.store(180_000, Ordering::Relaxed);
+        state.position_ms.store(90_000, Ordering::Relaxed);
+        // Paused halfway
+        assert!(!state.played_to_end());
+        state.is_playing.store(true, Ordering::Relaxed);
+        state.position_ms.store(180_000, Ordering::Relaxed);
+        assert!(!state.played_to_end());
+        state.is_playing.store(false, Ordering::Relaxed);
+        assert!(state.played_to_end());
+    }
+
+    #[test]
+    fn analysis_buffer_stays_bounded() {
+        assert_eq!(analysis_rate(44_100, 4 * 60 * 1000), 44_100);
+        assert_eq!(analysis_rate(48_000, 20 * 60 * 1000), LONG_TRACK_ANALYSIS_RATE);
+        // An hour-long medley drops further, to fit the cap…
+        let hour = 60 * 60 * 1000;
+        let rate = analysis_rate(48_000, hour);
+        assert!(rate > MIN_ANALYSIS_RATE && rate < LONG_TRACK_ANALYSIS_RATE);
+        assert!(rate as usize * 3600 <= MAX_ANALYSIS_SAMPLES);
+        // …longer ones stop at the floor and are cut while decoding
+        assert_eq!(analysis_rate(48_000, 2 * hour), MIN_ANALYSIS_RATE);
+        assert_eq!(analysis_rate(6_000, 2 * hour), 6_000);
+        // Unknown duration: the cap is enforced while decoding
+        assert_eq!(analysis_rate(44_100, 0), 44_100);
+    }
+}
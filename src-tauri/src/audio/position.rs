@@ -0,0 +1,101 @@
+//! `audio://position` events from the native player.
+//!
+//! `audio_play_file` reports time updates over a per-call `Channel`, which
+//! only the caller sees. Songs started with `audio_load` / `audio_play`
+//! are followed here instead: a supervised task samples the shared
+//! playback state and publishes it on the event bus every 100 ms while
+//! playing, plus once whenever playback pauses, seeks or ends, so every
+//! window (and the WebSocket hub) sees the same clock. When playback
+//! moves on to a staged song (see `transition`), `audio://track-changed`
+//! goes out first.
+
+use std::time::Duration;
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+use super::commands::AudioState;
+use super::transition::TrackChanged;
+use crate::events::{publish, AppEvent};
+use crate::runtime::{sleep_or_cancel, TaskSupervisor};
+
+pub const POSITION_EVENT: &str = "audio://position";
+const TICK: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct AudioPosition {
+    pub position_ms: u64,
+    pub duration_ms: u64,
+    pub is_playing: bool,
+    /// Playback reached the end of the track.
+    pub ended: bool,
+}
+
+impl AudioPosition {
+    pub fn new(position_ms: u64, duration_ms: u64, is_playing: bool) -> Self {
+        Self {
+            position_ms,
+            duration_ms,
+            is_playing,
+            ended: !is_playing && duration_ms > 0 && position_ms >= duration_ms,
+        }
+    }
+}
+
+/// Whether `next` is worth an event after `previous` was published.
+fn should_publish(previous: Option<&AudioPosition>, next: &AudioPosition) -> bool {
+    match previous {
+        None => next.duration_ms > 0,
+        Some(previous) => next.is_playing || previous != next,
+    }
+}
+
+/// Spawn the position publisher; it runs for the lifetime of the app.
+pub fn spawn_position_publisher(app: AppHandle) {
+    let supervisor = app.state::<TaskSupervisor>();
+    supervisor.spawn("audio-position", move |token| async move {
+        let mut last: Option<AudioPosition> = None;
+        let mut track_seq = 0;
+        while sleep_or_cancel(&token, TICK).await {
+            let Some(audio) = app.try_state::<AudioState>() else { continue };
+            let next = audio.position();
+            if audio.track_seq() != track_seq {
+                track_seq = audio.track_seq();
+                if let Some(file_path) = audio.take_staged_path() {
+                    crate::media::native_video::open(&app, &file_path);
+                    publish(&app, AppEvent::AudioTrackChanged(TrackChanged { file_path, duration_ms: next.duration_ms }));
+                }
+            }
+            if should_publish(last.as_ref(), &next) {
+                publish(&app, AppEvent::AudioPosition(next));
+                last = Some(next);
+            }
+        }
+    });
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn publishes_while_playing_and_on_changes_only() {
+        let idle = AudioPosition::new(0, 0, false);
+        assert!(!should_publish(None, &idle));
+
+        let paused = AudioPosition::new(1_000, 60_000, false);
+        assert!(should_publish(None, &paused));
+        assert!(!should_publish(Some(&paused), &paused));
+
+        let playing = AudioPosition::new(1_000, 60_000, true);
+        assert!(should_publish(Some(&playing), &playing));
+        assert!(should_publish(Some(&playing), &paused));
+
+        let ended = AudioPosition::new(60_000, 60_000, false);
+        assert!(ended.ended && !paused.ended);
+    }
+}
@@ -0,0 +1,265 @@
+//! Sample-rate negotiation and high-quality resampling.
+//!
+//! Every stream (backing track → output device, mic → pitch detector) owns
+//! its own `StreamResampler`, so a 44.1 kHz track, a 48 kHz interface and a
+//! 16 kHz headset mic can all run side by side without pitch/speed errors.
+//!
+//! Negotiation prefers opening the device at the source rate (bit-exact, no
+//! resampling at all); only when the hardware cannot do that is audio run
+//! through rubato's windowed-sinc resampler.
+
+use cpal::traits::DeviceTrait;
+use cpal::{SampleFormat, StreamConfig};
+use rubato::{
+    calculate_cutoff, Resampler, SincFixedIn, SincInterpolationParameters, SincInterpolationType,
+    WindowFunction,
+};
+
+/// Frames fed to rubato per `process()` call.
+const CHUNK_FRAMES: usize = 1024;
+
+// ---------------------------------------------------------------------------
+// Sample-rate negotiation
+// ---------------------------------------------------------------------------
+
+/// Pick the stream sample rate given the source rate and the rate ranges the
+/// device supports.
+///
+/// Preference order:
+///   1. the source rate itself (no resampling needed),
+///   2. an integer multiple of the source rate (e.g. 44.1 → 88.2 kHz),
+///   3. the device's default rate.
+pub fn choose_sample_rate(source_rate: u32, supported: &[(u32, u32)], default_rate: u32) -> u32 {
+    let supports = |rate: u32| supported.iter().any(|&(min, max)| rate >= min && rate <= max);
+
+    if supports(source_rate) {
+        return source_rate;
+    }
+    for factor in [2, 4] {
+        let rate = source_rate * factor;
+        if supports(rate) {
+            return rate;
+        }
+    }
+    default_rate
+}
+
+/// Negotiate an output stream config for `device` that suits audio at
+/// `source_rate`. Keeps the device's default channel count and sample format
+/// and only changes the sample rate — unless `min_channels` asks for more
+/// channels than the default (channel-mapped multi-out interfaces), in which
+/// case the narrowest config that is wide enough is used.
+pub fn negotiate_output_config(
+    device: &cpal::Device,
+    source_rate: u32,
+    min_channels: u16,
+) -> Result<(StreamConfig, SampleFormat), String> {
+    let default = device
+        .default_output_config()
+        .map_err(|e| format!("Cannot get device config: {}", e))?;
+    let mut sample_format = default.sample_format();
+    let mut channels = default.channels();
+    let default_rate = default.sample_rate().0;
+
+    let supported: Vec<cpal::SupportedStreamConfigRange> = device
+        .supported_output_configs()
+        .map(|configs| configs.collect())
+        .unwrap_or_default();
+
+    if min_channels > channels {
+        let wider = supported
+            .iter()
+            .filter(|c| c.channels() >= min_channels)
+            .min_by_key(|c| c.channels())
+            .ok_or_else(|| format!("Device has no output config with {} channels", min_channels))?;
+        channels = wider.channels();
+        sample_format = wider.sample_format();
+    }
+
+    let ranges: Vec<(u32, u32)> = supported
+        .iter()
+        .filter(|c| c.channels() == channels && c.sample_format() == sample_format)
+        .map(|c| (c.min_sample_rate().0, c.max_sample_rate().0))
+        .collect();
+
+    let rate = choose_sample_rate(source_rate, &ranges, default_rate);
+
+    let config = StreamConfig {
+        channels,
+        sample_rate: cpal::SampleRate(rate),
+        buffer_size: cpal::BufferSize::Default,
+    };
+    Ok((config, sample_format))
+}
+
+// ---------------------------------------------------------------------------
+// StreamResampler
+// ---------------------------------------------------------------------------
+
+/// Chunked, stateful resampler for one interleaved f32 stream.
+///
+/// Input may be pushed in arbitrarily sized pieces; it is buffered until a
+/// full chunk is available. The sinc filter's group delay is trimmed from the
+/// head of the output so resampled audio stays aligned with the source.
+pub struct StreamResampler {
+    resampler: SincFixedIn<f32>,
+    channels: usize,
+    /// Deinterleaved input waiting for a full chunk.
+    pending: Vec<Vec<f32>>,
+    /// Output frames still to drop to compensate the filter delay.
+    delay_remaining: usize,
+}
+
+impl StreamResampler {
+    pub fn new(source_rate: u32, target_rate: u32, channels: u16) -> Result<Self, String> {
+        let channels = channels.max(1) as usize;
+        let sinc_len = 256;
+        let window = WindowFunction::BlackmanHarris2;
+
+        let params = SincInterpolationParameters {
+            sinc_len,
+            f_cutoff: calculate_cutoff(sinc_len, window),
+            oversampling_factor: 256,
+            interpolation: SincInterpolationType::Cubic,
+            window,
+        };
+
+        let resampler = SincFixedIn::<f32>::new(
+            target_rate as f64 / source_rate as f64,
+            1.0, // fixed ratio, no runtime adjustment
+            params,
+            CHUNK_FRAMES,
+            channels,
+        )
+        .map_err(|e| format!("Failed to create resampler: {}", e))?;
+
+        let delay_remaining = resampler.output_delay();
+
+        Ok(Self {
+            resampler,
+            channels,
+            pending: vec![Vec::with_capacity(CHUNK_FRAMES); channels],
+            delay_remaining,
+        })
+    }
+
+    /// Push interleaved input; any completed output is appended to `out`.
+    pub fn process(&mut self, input: &[f32], out: &mut Vec<f32>) -> Result<(), String> {
+        for frame in input.chunks_exact(self.channels) {
+            for (ch, &s) in frame.iter().enumerate() {
+                self.pending[ch].push(s);
+            }
+            if self.pending[0].len() == CHUNK_FRAMES {
+                let resampled = self
+                    .resampler
+                    .process(self.pending.as_slice(), None)
+                    .map_err(|e| format!("Resampling error: {}", e))?;
+                self.emit(&resampled, out);
+                for ch in &mut self.pending {
+                    ch.clear();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Flush buffered input (zero-padded to a full chunk) at end of stream,
+    /// then drain the filter so the last `output_delay()` frames come out too.
+    pub fn flush(&mut self, out: &mut Vec<f32>) -> Result<(), String> {
+        if !self.pending[0].is_empty() {
+            let resampled = self
+                .resampler
+                .process_partial(Some(self.pending.as_slice()), None)
+                .map_err(|e| format!("Resampling error: {}", e))?;
+            self.emit(&resampled, out);
+            for ch in &mut self.pending {
+                ch.clear();
+            }
+        }
+        let tail = self
+            .resampler
+            .process_partial::<Vec<f32>>(None, None)
+            .map_err(|e| format!("Resampling error: {}", e))?;
+        self.emit(&tail, out);
+        Ok(())
+    }
+
+    /// Reinterleave a rubato output block, dropping the leading filter delay.
+    fn emit(&mut self, resampled: &[Vec<f32>], out: &mut Vec<f32>) {
+        let frames = resampled.first().map(|c| c.len()).unwrap_or(0);
+        let skip = self.delay_remaining.min(frames);
+        self.delay_remaining -= skip;
+
+        out.reserve((frames - skip) * self.channels);
+        for f in skip..frames {
+            for ch in resampled.iter().take(self.channels) {
+                out.push(ch[f]);
+            }
+        }
+    }
+}
+
+/// Resample a whole interleaved buffer if the source rate differs from the
+/// target rate. The output length matches `frames * target / source`.
+pub fn resample_interleaved(
+    samples: Vec<f32>,
+    source_rate: u32,
+    target_rate: u32,
+    channels: u16,
+) -> Result<Vec<f32>, String> {
+    if source_rate == target_rate || source_rate == 0 || channels == 0 {
+        return Ok(samples);
+    }
+
+    let frames_in = samples.len() / channels as usize;
+    let expected_frames = (frames_in as f64 * target_rate as f64 / source_rate as f64).round() as usize;
+
+    let mut resampler = StreamResampler::new(source_rate, target_rate, channels)?;
+    let mut output = Vec::with_capacity(expected_frames * channels as usize + CHUNK_FRAMES);
+    resampler.process(&samples, &mut output)?;
+    resampler.flush(&mut output)?;
+
+    // The zero-padded final chunk yields a little extra tail — trim to length
+    output.truncate(expected_frames * channels as usize);
+    Ok(output)
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefers_source_rate_when_supported() {
+        let ranges = [(8_000, 192_000)];
+        assert_eq!(choose_sample_rate(44_100, &ranges, 48_000), 44_100);
+    }
+
+    #[test]
+    fn prefers_integer_multiple_over_default() {
+        let ranges = [(48_000, 48_000), (88_200, 88_200)];
+        assert_eq!(choose_sample_rate(44_100, &ranges, 48_000), 88_200);
+    }
+
+    #[test]
+    fn falls_back_to_default_rate() {
+        let ranges = [(48_000, 48_000)];
+        assert_eq!(choose_sample_rate(44_100, &ranges, 48_000), 48_000);
+    }
+
+    #[test]
+    fn resampled_length_matches_ratio() {
+        let sr_in = 44_100;
+        let sr_out = 48_000;
+        let frames = sr_in as usize; // one second of stereo
+        let samples: Vec<f32> = (0..frames * 2)
+            .map(|i| ((i / 2) as f32 * 440.0 * 2.0 * std::f32::consts::PI / sr_in as f32).sin() * 0.5)
+            .collect();
+
+        let out = resample_interleaved(samples, sr_in, sr_out, 2).unwrap();
+        assert_eq!(out.len(), sr_out as usize * 2);
+    }
+}
@@ -0,0 +1,203 @@
+//! Real-time scheduling for audio callback threads.
+//!
+//! Output and capture threads are promoted (MMCSS "Pro Audio" on Windows,
+//! rtkit on Linux, Mach time-constraint on macOS), sized to the buffer the
+//! device actually hands out, before they handle any audio: until then the
+//! callback outputs silence (or drops input). None of the slow part runs
+//! on the audio thread. On Linux it only reads its thread id, and a helper
+//! thread asks rtkit over D-Bus. Elsewhere it makes the one promotion
+//! syscall itself, as those APIs only promote the calling thread. The
+//! helper logs and records the outcome for `audio_get_rt_priority_status`.
+//! If the OS refuses (no rtkit, a sandbox, missing privileges), the thread
+//! keeps normal priority and the operator can see why a busy machine
+//! crackles.
+//!
+//! Disabled with the `audio_realtime_priority` setting set to `false`.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{sync_channel, SyncSender};
+use std::sync::{Arc, Mutex};
+
+use rusqlite::Connection;
+use serde::Serialize;
+
+const SETTINGS_KEY: &str = "audio_realtime_priority";
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+static REPORTS: Mutex<Vec<ThreadPriorityReport>> = Mutex::new(Vec::new());
+
+/// Reports kept (one per stream opened; older ones are dropped).
+const MAX_REPORTS: usize = 16;
+/// Audio (ms) a callback stays silent waiting for rtkit before it carries
+/// on at normal priority.
+const MAX_WAIT_MS: u64 = 500;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ThreadPriorityReport {
+    /// "output" or "capture".
+    pub role: &'static str,
+    pub realtime: bool,
+    /// Why promotion failed or was skipped.
+    pub error: Option<String>,
+    pub buffer_frames: u32,
+    pub sample_rate: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RtPriorityStatus {
+    pub enabled: bool,
+    /// Most recent first.
+    pub threads: Vec<ThreadPriorityReport>,
+}
+
+/// Apply the persisted setting; called once the database is open.
+pub fn load_setting(conn: &Connection) {
+    let value: Option<String> = conn
+        .query_row("SELECT value FROM app_settings WHERE key = ?1", [SETTINGS_KEY], |row| row.get(0))
+        .ok();
+    let enabled = value.map(|v| v.trim() != "false").unwrap_or(true);
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+fn record(role: &'static str, result: Result<(), String>, buffer_frames: u32, sample_rate: u32) {
+    let error = result.err();
+    if let Some(e) = &error {
+        tracing::warn!("[audio] {} thread stays at normal priority: {}", role, e);
+    }
+    if let Ok(mut reports) = REPORTS.lock() {
+        if reports.len() >= MAX_REPORTS {
+            reports.remove(0);
+        }
+        reports.push(ThreadPriorityReport { role, realtime: error.is_none(), error, buffer_frames, sample_rate });
+    }
+}
+
+/// What the callback hands the helper thread on its first call.
+enum Request {
+    /// Promote that thread (Linux).
+    #[cfg(target_os = "linux")]
+    Promote(audio_thread_priority::RtPriorityThreadInfo, u32, u32),
+    /// The callback promoted itself, with this outcome. Failing allocates
+    /// the message, once.
+    Done(Result<(), String>, u32, u32),
+}
+
+/// One-shot promotion for the thread running a stream callback. Create it
+/// outside the callback and call `ready` at the top of every invocation.
+pub struct RtPromotion {
+    requests: Option<SyncSender<Request>>,
+    ready: Arc<AtomicBool>,
+    /// Frames silenced while waiting.
+    waited_frames: u64,
+}
+
+impl RtPromotion {
+    /// Starts the helper thread that reports (and on Linux performs) the
+    /// promotion.
+    pub fn new(role: &'static str) -> Self {
+        let ready = Arc::new(AtomicBool::new(false));
+        if !ENABLED.load(Ordering::Relaxed) {
+            record(role, Err("Disabled in settings".to_string()), 0, 0);
+            ready.store(true, Ordering::Relaxed);
+            return Self { requests: None, ready, waited_frames: 0 };
+        }
+        // Bounded: sending never allocates on the audio thread
+        let (tx, rx) = sync_channel::<Request>(1);
+        let done = ready.clone();
+        let spawned = std::thread::Builder::new().name(format!("karaoke-rt-{}", role)).spawn(move || {
+            // Ends without a request when the stream is dropped first
+            let Ok(request) = rx.recv() else { return };
+            let (result, frames, rate) = match request {
+                #[cfg(target_os = "linux")]
+                Request::Promote(info, frames, rate) => {
+                    let result = audio_thread_priority::promote_thread_to_real_time(info, frames, rate)
+                        .map(|handle| {
+                            // The promotion lasts as long as the thread; the
+                            // stream owns the thread and ends it when dropped
+                            std::mem::forget(handle);
+                        })
+                        .map_err(|e| e.to_string());
+                    (result, frames, rate)
+                }
+                Request::Done(result, frames, rate) => (result, frames, rate),
+            };
+            done.store(true, Ordering::Release);
+            record(role, result, frames, rate);
+        });
+        if let Err(e) = spawned {
+            record(role, Err(format!("No helper thread: {}", e)), 0, 0);
+            ready.store(true, Ordering::Relaxed);
+            return Self { requests: None, ready, waited_frames: 0 };
+        }
+        Self { requests: Some(tx), ready, waited_frames: 0 }
+    }
+
+    /// Whether the callback may handle audio: the thread is promoted, or
+    /// promotion failed, is off or took longer than `MAX_WAIT_MS`. The
+    /// first call asks for it.
+    pub fn ready(&mut self, buffer_frames: u32, sample_rate: u32) -> bool {
+        if let Some(requests) = self.requests.take() {
+            let request = self.request(buffer_frames, sample_rate);
+            if requests.try_send(request).is_err() {
+                self.ready.store(true, Ordering::Release);
+            }
+        }
+        if self.ready.load(Ordering::Acquire) {
+            return true;
+        }
+        self.waited_frames += buffer_frames as u64;
+        self.waited_frames * 1000 >= MAX_WAIT_MS * sample_rate.max(1) as u64
+    }
+
+    #[cfg(target_os = "linux")]
+    fn request(&self, buffer_frames: u32, sample_rate: u32) -> Request {
+        match audio_thread_priority::get_current_thread_info() {
+            Ok(info) => Request::Promote(info, buffer_frames, sample_rate),
+            Err(e) => Request::Done(Err(e.to_string()), buffer_frames, sample_rate),
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn request(&self, buffer_frames: u32, sample_rate: u32) -> Request {
+        let result = audio_thread_priority::promote_current_thread_to_real_time(buffer_frames, sample_rate).map(|handle| {
+            // The promotion lasts as long as the thread; the stream owns
+            // the thread and ends it when dropped
+            std::mem::forget(handle);
+        });
+        self.ready.store(true, Ordering::Release);
+        Request::Done(result.map_err(|e| e.to_string()), buffer_frames, sample_rate)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+/// Whether real-time scheduling is enabled and what each audio thread got.
+#[tauri::command]
+pub fn audio_get_rt_priority_status() -> RtPriorityStatus {
+    let mut threads = REPORTS.lock().map(|r| r.clone()).unwrap_or_default();
+    threads.reverse();
+    RtPriorityStatus {
+        enabled: ENABLED.load(Ordering::Relaxed),
+        threads,
+    }
+}
+
+/// Enable or disable promotion for streams opened from now on.
+#[tauri::command]
+pub fn audio_set_rt_priority_enabled(app: tauri::AppHandle, webview: tauri::Webview, enabled: bool) -> Result<(), String> {
+    use tauri::Manager;
+    crate::access::require_webview(&webview, crate::access::Capability::ConfigureAudio)?;
+    let db = app.state::<crate::db::DbState>();
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT OR REPLACE INTO app_settings (key, value) VALUES (?1, ?2)",
+        (SETTINGS_KEY, if enabled { "true" } else { "false" }),
+    )
+    .map_err(|e| format!("Failed to save setting: {}", e))?;
+    ENABLED.store(enabled, Ordering::Relaxed);
+    Ok(())
+}
@@ -0,0 +1,175 @@
+//! Lock-free single-producer/single-consumer ring buffer for f32 samples.
+//!
+//! This is the only way samples cross between an audio callback and another
+//! thread: the callback side never locks, allocates or blocks, so a busy
+//! library scan or download cannot stall it into a dropout.
+//!
+//! Samples are stored as `AtomicU32` bit patterns, which needs no `unsafe`;
+//! element accesses are relaxed and ordered by the acquire/release updates
+//! of the two indices, so they compile to plain loads and stores.
+//! Indices count samples ever written/read and wrap around `usize`; the
+//! capacity is a power of two so masking stays correct across the wrap.
+
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+struct Ring {
+    slots: Box<[AtomicU32]>,
+    mask: usize,
+    /// Total samples written (owned by the producer).
+    head: AtomicUsize,
+    /// Total samples read (owned by the consumer).
+    tail: AtomicUsize,
+}
+
+impl Ring {
+    fn len(&self) -> usize {
+        self.head.load(Ordering::Acquire).wrapping_sub(self.tail.load(Ordering::Acquire))
+    }
+}
+
+/// Writing half; owned by exactly one thread.
+pub struct RingProducer {
+    ring: Arc<Ring>,
+}
+
+/// Reading half; owned by exactly one thread (usually the audio callback).
+pub struct RingConsumer {
+    ring: Arc<Ring>,
+}
+
+/// Allocate a ring holding at least `capacity` samples. This is the only
+/// allocation; both halves are fixed-size from here on.
+pub fn ring(capacity: usize) -> (RingProducer, RingConsumer) {
+    let capacity = capacity.max(2).next_power_of_two();
+    let ring = Arc::new(Ring {
+        slots: (0..capacity).map(|_| AtomicU32::new(0)).collect(),
+        mask: capacity - 1,
+        head: AtomicUsize::new(0),
+        tail: AtomicUsize::new(0),
+    });
+    (RingProducer { ring: ring.clone() }, RingConsumer { ring })
+}
+
+impl RingProducer {
+    /// Samples that can be pushed without overwriting unread data.
+    pub fn free(&self) -> usize {
+        self.ring.slots.len() - self.ring.len()
+    }
+
+    /// Total samples pushed so far; a position consumers can `skip_to`.
+    pub fn written(&self) -> usize {
+        self.ring.head.load(Ordering::Relaxed)
+    }
+
+    /// Push as many of `samples` as fit; returns how many were pushed.
+    pub fn push(&mut self, samples: &[f32]) -> usize {
+        let ring = &*self.ring;
+        let head = ring.head.load(Ordering::Relaxed);
+        let tail = ring.tail.load(Ordering::Acquire);
+        let free = ring.slots.len() - head.wrapping_sub(tail);
+        let n = samples.len().min(free);
+        for (i, &s) in samples[..n].iter().enumerate() {
+            ring.slots[head.wrapping_add(i) & ring.mask].store(s.to_bits(), Ordering::Relaxed);
+        }
+        ring.head.store(head.wrapping_add(n), Ordering::Release);
+        n
+    }
+}
+
+impl RingConsumer {
+    /// Samples available to read.
+    pub fn len(&self) -> usize {
+        self.ring.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Pop up to `out.len()` samples into `out`; returns how many were read.
+    pub fn pop(&mut self, out: &mut [f32]) -> usize {
+        let ring = &*self.ring;
+        let tail = ring.tail.load(Ordering::Relaxed);
+        let head = ring.head.load(Ordering::Acquire);
+        let n = out.len().min(head.wrapping_sub(tail));
+        for (i, s) in out[..n].iter_mut().enumerate() {
+            *s = f32::from_bits(ring.slots[tail.wrapping_add(i) & ring.mask].load(Ordering::Relaxed));
+        }
+        ring.tail.store(tail.wrapping_add(n), Ordering::Release);
+        n
+    }
+
+    /// Total samples read so far, comparable to `RingProducer::written`.
+    pub fn read_position(&self) -> usize {
+        self.ring.tail.load(Ordering::Relaxed)
+    }
+
+    /// Discard everything written before the producer position `index`
+    /// (from `RingProducer::written`). Positions already read are ignored.
+    pub fn skip_to(&mut self, index: usize) {
+        let ring = &*self.ring;
+        let tail = ring.tail.load(Ordering::Relaxed);
+        let head = ring.head.load(Ordering::Acquire);
+        let ahead = index.wrapping_sub(tail);
+        if ahead <= head.wrapping_sub(tail) {
+            ring.tail.store(index, Ordering::Release);
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps_and_respects_capacity() {
+        let (mut tx, mut rx) = ring(6); // rounded up to 8
+        assert_eq!(tx.push(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]), 6);
+        let mut out = [0.0; 4];
+        assert_eq!(rx.pop(&mut out), 4);
+        assert_eq!(out, [1.0, 2.0, 3.0, 4.0]);
+
+        // Wraps past the end of the slot array; only 6 of 7 fit
+        assert_eq!(tx.push(&[7.0, 8.0, 9.0, 10.0, 11.0, 12.0, 13.0]), 6);
+        assert_eq!(tx.free(), 0);
+
+        let mark = tx.written();
+        tx.push(&[0.0]); // full: dropped
+        rx.skip_to(mark - 2);
+        let mut rest = [0.0; 8];
+        assert_eq!(rx.pop(&mut rest), 2);
+        assert_eq!(&rest[..2], &[11.0, 12.0]);
+
+        // Stale positions behind the reader are ignored
+        rx.skip_to(0);
+        assert!(rx.is_empty());
+    }
+
+    #[test]
+    fn preserves_order_across_threads() {
+        let (mut tx, mut rx) = ring(64);
+        let producer = std::thread::spawn(move || {
+            let mut next = 0u32;
+            while next < 10_000 {
+                if tx.push(&[next as f32]) == 1 {
+                    next += 1;
+                }
+            }
+        });
+        let mut expected = 0u32;
+        let mut buf = [0.0; 16];
+        while expected < 10_000 {
+            let n = rx.pop(&mut buf);
+            for &v in &buf[..n] {
+                assert_eq!(v, expected as f32);
+                expected += 1;
+            }
+        }
+        producer.join().unwrap();
+    }
+}
@@ -0,0 +1,335 @@
+//! Streaming audio decoding.
+//!
+//! `StreamingDecoder` decodes a file packet by packet, so memory use stays
+//! bounded by the consumer's buffer regardless of file length — a 2-hour
+//! medley video or a 24-bit FLAC costs the same few megabytes as a pop song.
+//! Playback and analysis both pull audio through the `PcmSource` trait;
+//! generated buffers (test tones) implement it via `MemorySource`.
+
+use std::fs::File;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{Decoder, DecoderOptions};
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::{FormatOptions, FormatReader, SeekMode, SeekTo};
+use symphonia::core::io::{MediaSource, MediaSourceStream};
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use symphonia::core::units::Time;
+
+use super::player::DecodedAudio;
+use crate::library::archive;
+
+/// A pull-based source of interleaved f32 audio.
+pub trait PcmSource: Send {
+    fn sample_rate(&self) -> u32;
+    fn channels(&self) -> u16;
+    fn duration_ms(&self) -> u64;
+    /// Append the next chunk of interleaved samples to `out`.
+    /// Returns `false` once the end of the stream is reached.
+    fn read_chunk(&mut self, out: &mut Vec<f32>) -> Result<bool, String>;
+    /// Reposition so the next chunk starts at `position_ms`.
+    fn seek(&mut self, position_ms: u64) -> Result<(), String>;
+}
+
+// ---------------------------------------------------------------------------
+// File decoder
+// ---------------------------------------------------------------------------
+
+pub struct StreamingDecoder {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    sample_rate: u32,
+    channels: u16,
+    duration_ms: u64,
+    /// After an accurate seek, frames before this timestamp are trimmed.
+    skip_until_ts: u64,
+}
+
+impl StreamingDecoder {
+    pub fn open(file_path: &str) -> Result<Self, String> {
+        let (mut format, hint_ext) = open_format(file_path)?;
+
+        // Find a decodable audio track.
+        // For audio files the default track is fine; for video containers (MP4, MKV)
+        // the default track may be video, so we fall back to searching all tracks.
+        let decoder_opts = DecoderOptions::default();
+        let codecs = symphonia::default::get_codecs();
+        let candidates = format
+            .default_track()
+            .into_iter()
+            .chain(format.tracks().iter().filter(|t| {
+                // Audio tracks have at least a sample rate or channels set
+                t.codec_params.sample_rate.is_some() || t.codec_params.channels.is_some()
+            }));
+
+        let mut found = None;
+        for track in candidates {
+            if let Ok(decoder) = codecs.make(&track.codec_params, &decoder_opts) {
+                let params = &track.codec_params;
+                let sample_rate = params.sample_rate.unwrap_or(44100);
+                let channels = params.channels.map(|c| c.count() as u16).unwrap_or(2);
+                let frames = params.n_frames;
+                found = Some((track.id, decoder, sample_rate, channels, frames));
+                break;
+            }
+        }
+        let (track_id, decoder, sample_rate, channels, n_frames) = found.ok_or_else(|| {
+            format!("No decodable audio track found in file (format: {})", hint_ext.as_deref().unwrap_or("?"))
+        })?;
+
+        let duration_ms = match n_frames {
+            Some(frames) if sample_rate > 0 => frames * 1000 / sample_rate as u64,
+            // No frame count in the header (e.g. MP3 without Xing): sum packet
+            // durations — a demux-only pass, no decoding
+            _ => {
+                let frames = count_frames(&mut *format, track_id);
+                format = open_format(file_path)?.0;
+                if sample_rate > 0 { frames * 1000 / sample_rate as u64 } else { 0 }
+            }
+        };
+
+        Ok(Self {
+            format,
+            decoder,
+            track_id,
+            sample_rate,
+            channels,
+            duration_ms,
+            skip_until_ts: 0,
+        })
+    }
+}
+
+impl PcmSource for StreamingDecoder {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn duration_ms(&self) -> u64 {
+        self.duration_ms
+    }
+
+    fn read_chunk(&mut self, out: &mut Vec<f32>) -> Result<bool, String> {
+        loop {
+            let packet = match self.format.next_packet() {
+                Ok(p) => p,
+                Err(SymphoniaError::ResetRequired) => continue,
+                Err(SymphoniaError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    return Ok(false)
+                }
+                Err(e) => return Err(format!("Error reading packet: {}", e)),
+            };
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+
+            let audio_buf = match self.decoder.decode(&packet) {
+                Ok(buf) => buf,
+                Err(SymphoniaError::DecodeError(_)) => continue,
+                Err(e) => return Err(format!("Decode error: {}", e)),
+            };
+
+            let frames = audio_buf.frames() as u64;
+            let skip = self.skip_until_ts.saturating_sub(packet.ts()).min(frames);
+            if skip == frames {
+                continue;
+            }
+            self.skip_until_ts = 0;
+
+            let mut conv_buf: SampleBuffer<f32> = SampleBuffer::new(audio_buf.capacity() as u64, *audio_buf.spec());
+            conv_buf.copy_interleaved_ref(audio_buf);
+            let start = skip as usize * self.channels as usize;
+            out.extend_from_slice(&conv_buf.samples()[start..]);
+            return Ok(true);
+        }
+    }
+
+    fn seek(&mut self, position_ms: u64) -> Result<(), String> {
+        let time = Time::new(position_ms / 1000, (position_ms % 1000) as f64 / 1000.0);
+        let seeked = self
+            .format
+            .seek(SeekMode::Accurate, SeekTo::Time { time, track_id: Some(self.track_id) })
+            .map_err(|e| format!("Seek failed: {}", e))?;
+        self.decoder.reset();
+        self.skip_until_ts = seeked.required_ts;
+        Ok(())
+    }
+}
+
+/// Open `file_path` (a file, or a zip member as built by
+/// `archive::member_path`) and probe its container. Also returns the
+/// extension used as the probe hint.
+fn open_format(file_path: &str) -> Result<(Box<dyn FormatReader>, Option<String>), String> {
+    // symphonia 0.5 expects Box<dyn MediaSource>; std::fs::File implements MediaSource.
+    let (source, resolved_path): (Box<dyn MediaSource>, PathBuf) = match archive::split_member_path(file_path) {
+        // A track inside a karaoke zip, read in place
+        Some((zip, member)) => (Box::new(archive::open_member(&zip, &member)?), PathBuf::from(member)),
+        None => {
+            let (file, path) = open_media_file(file_path)?;
+            (Box::new(file), path)
+        }
+    };
+    let mss = MediaSourceStream::new(source, Default::default());
+
+    let ext = resolved_path.extension().and_then(|e| e.to_str()).map(str::to_string);
+    let mut hint = Hint::new();
+    if let Some(ext) = &ext {
+        hint.with_extension(ext);
+    }
+
+    let probe_result = symphonia::default::get_probe()
+        .format(&hint, mss, &FormatOptions::default(), &MetadataOptions::default())
+        .map_err(|e| format!("Unsupported format: {}", e))?;
+    Ok((probe_result.format, ext))
+}
+
+impl MediaSource for archive::MemberReader {
+    fn is_seekable(&self) -> bool {
+        true
+    }
+
+    fn byte_len(&self) -> Option<u64> {
+        Some(self.len())
+    }
+}
+
+fn count_frames(format: &mut dyn FormatReader, track_id: u32) -> u64 {
+    let mut frames = 0;
+    loop {
+        match format.next_packet() {
+            Ok(packet) if packet.track_id() == track_id => frames += packet.dur(),
+            Ok(_) | Err(SymphoniaError::ResetRequired) => continue,
+            Err(_) => return frames,
+        }
+    }
+}
+
+/// Open a media file with fallback path strategies; returns the file and
+/// the path actually used (for extension detection).
+/// Mirrors lib.rs native_read_file_bytes but also adds canonicalize-parent.
+pub(crate) fn open_media_file(p: &str) -> Result<(File, PathBuf), String> {
+    // Attempt 1: as-is
+    let path = PathBuf::from(p);
+    if let Ok(f) = File::open(&path) {
+        return Ok((f, path));
+    }
+    // Attempt 2: OS-native separators
+    let normalized = PathBuf::from(p.replace('/', std::path::MAIN_SEPARATOR_STR));
+    if let Ok(f) = File::open(&normalized) {
+        return Ok((f, normalized));
+    }
+    // Attempt 3: Windows extended-length prefix + canonicalize parent
+    #[cfg(target_os = "windows")]
+    {
+        if let Ok(f) = File::open(crate::paths::long_path(&normalized)) {
+            return Ok((f, normalized));
+        }
+        // Attempt 4: Canonicalize the parent directory to resolve symlinks,
+        // junctions, or case mismatches (same as lib.rs native_read_file_bytes).
+        if let (Some(parent), Some(file_name)) = (normalized.parent(), normalized.file_name()) {
+            if let Ok(canonical_parent) = parent.canonicalize() {
+                let canonical_path = canonical_parent.join(file_name);
+                if let Ok(f) = File::open(&canonical_path) {
+                    return Ok((f, canonical_path));
+                }
+            }
+        }
+    }
+    // Attempt 5: a differently normalized (NFC/NFD) name on disk
+    if let Some(found) = crate::paths::find_on_disk(&normalized) {
+        if let Ok(f) = File::open(crate::paths::long_path(&found)) {
+            return Ok((f, found));
+        }
+    }
+    Err(format!("Audio file not found: {}", p))
+}
+
+// ---------------------------------------------------------------------------
+// In-memory source
+// ---------------------------------------------------------------------------
+
+/// Frames handed out per `read_chunk` call.
+const MEMORY_CHUNK_FRAMES: usize = 4096;
+
+/// A fully decoded buffer exposed as a `PcmSource`.
+pub struct MemorySource {
+    audio: Arc<DecodedAudio>,
+    /// Next sample index (interleaved).
+    pos: usize,
+}
+
+impl MemorySource {
+    pub fn new(audio: Arc<DecodedAudio>) -> Self {
+        Self { audio, pos: 0 }
+    }
+}
+
+impl PcmSource for MemorySource {
+    fn sample_rate(&self) -> u32 {
+        self.audio.sample_rate
+    }
+
+    fn channels(&self) -> u16 {
+        self.audio.channels
+    }
+
+    fn duration_ms(&self) -> u64 {
+        self.audio.duration_ms
+    }
+
+    fn read_chunk(&mut self, out: &mut Vec<f32>) -> Result<bool, String> {
+        let samples = &self.audio.samples;
+        if self.pos >= samples.len() {
+            return Ok(false);
+        }
+        let end = (self.pos + MEMORY_CHUNK_FRAMES * self.audio.channels.max(1) as usize).min(samples.len());
+        out.extend_from_slice(&samples[self.pos..end]);
+        self.pos = end;
+        Ok(true)
+    }
+
+    fn seek(&mut self, position_ms: u64) -> Result<(), String> {
+        let frame = (position_ms as f64 / 1000.0 * self.audio.sample_rate as f64) as usize;
+        self.pos = (frame * self.audio.channels as usize).min(self.audio.samples.len());
+        Ok(())
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_source_chunks_and_seeks_by_frame() {
+        let audio = Arc::new(DecodedAudio {
+            samples: (0..20_000).map(|i| i as f32).collect(),
+            sample_rate: 1000,
+            channels: 2,
+            duration_ms: 10_000,
+        });
+        let mut source = MemorySource::new(audio);
+
+        let mut out = Vec::new();
+        assert!(source.read_chunk(&mut out).unwrap());
+        assert_eq!(out.len(), MEMORY_CHUNK_FRAMES * 2);
+
+        source.seek(9_000).unwrap();
+        out.clear();
+        assert!(source.read_chunk(&mut out).unwrap());
+        assert_eq!(out[0], 18_000.0);
+        assert_eq!(out.len(), 2_000);
+        assert!(!source.read_chunk(&mut out).unwrap());
+    }
+}
@@ -0,0 +1,112 @@
+//! Test-tone and speaker-check signal generators.
+//!
+//! Used before a show to verify left/right/sub wiring and output routing:
+//! a sine tone (e.g. 1 kHz for level, 50 Hz for the sub) or pink noise
+//! (equal energy per octave, the standard signal for checking speakers by ear).
+//! Signals are generated as mono buffers and routed to a single device
+//! channel — or all channels — by the player.
+
+use serde::Deserialize;
+
+/// Sample rate the generators render at (the player resamples if needed).
+pub const TEST_TONE_SAMPLE_RATE: u32 = 48_000;
+
+/// Default output level: -18 dBFS, loud enough to hear, safe for tweeters.
+const DEFAULT_AMPLITUDE: f32 = 0.125;
+
+/// Fade in/out length in ms, avoids clicks at the start and end.
+const FADE_MS: u64 = 10;
+
+/// Signal to generate.
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TestSignal {
+    Sine,
+    PinkNoise,
+}
+
+/// Generate a mono sine tone.
+pub fn sine(frequency: f64, duration_ms: u64, sample_rate: u32) -> Vec<f32> {
+    let frames = frames_for(duration_ms, sample_rate);
+    let step = 2.0 * std::f64::consts::PI * frequency / sample_rate as f64;
+    let mut out: Vec<f32> = (0..frames)
+        .map(|i| ((i as f64 * step).sin() as f32) * DEFAULT_AMPLITUDE)
+        .collect();
+    apply_fades(&mut out, sample_rate);
+    out
+}
+
+/// Generate mono pink noise using Paul Kellet's refined filter over a
+/// xorshift white-noise source (deterministic, no RNG dependency).
+pub fn pink_noise(duration_ms: u64, sample_rate: u32) -> Vec<f32> {
+    let frames = frames_for(duration_ms, sample_rate);
+    let mut rng: u32 = 0x9E37_79B9;
+    let (mut b0, mut b1, mut b2, mut b3, mut b4, mut b5, mut b6) = (0.0f32, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0);
+
+    let mut out = Vec::with_capacity(frames);
+    for _ in 0..frames {
+        // xorshift32 → uniform white noise in [-1, 1)
+        rng ^= rng << 13;
+        rng ^= rng >> 17;
+        rng ^= rng << 5;
+        let white = (rng as f32 / u32::MAX as f32) * 2.0 - 1.0;
+
+        b0 = 0.99886 * b0 + white * 0.0555179;
+        b1 = 0.99332 * b1 + white * 0.0750759;
+        b2 = 0.96900 * b2 + white * 0.1538520;
+        b3 = 0.86650 * b3 + white * 0.3104856;
+        b4 = 0.55000 * b4 + white * 0.5329522;
+        b5 = -0.7616 * b5 - white * 0.0168980;
+        let pink = b0 + b1 + b2 + b3 + b4 + b5 + b6 + white * 0.5362;
+        b6 = white * 0.115926;
+
+        // The filter has a gain of roughly 5 — normalise to the test level
+        out.push((pink * 0.2 * DEFAULT_AMPLITUDE).clamp(-1.0, 1.0));
+    }
+    apply_fades(&mut out, sample_rate);
+    out
+}
+
+fn frames_for(duration_ms: u64, sample_rate: u32) -> usize {
+    (duration_ms as f64 / 1000.0 * sample_rate as f64) as usize
+}
+
+/// Linear fade-in and fade-out of `FADE_MS` each.
+fn apply_fades(samples: &mut [f32], sample_rate: u32) {
+    let fade = frames_for(FADE_MS, sample_rate).min(samples.len() / 2);
+    if fade == 0 {
+        return;
+    }
+    let len = samples.len();
+    for i in 0..fade {
+        let gain = i as f32 / fade as f32;
+        samples[i] *= gain;
+        samples[len - 1 - i] *= gain;
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sine_has_expected_zero_crossings() {
+        let tone = sine(1000.0, 1000, TEST_TONE_SAMPLE_RATE);
+        assert_eq!(tone.len(), TEST_TONE_SAMPLE_RATE as usize);
+        let crossings = tone.windows(2).filter(|w| w[0] * w[1] < 0.0).count();
+        // 1 kHz for one second → ~2000 sign changes
+        assert!((1990..=2010).contains(&crossings), "got {} crossings", crossings);
+    }
+
+    #[test]
+    fn pink_noise_is_bounded_and_not_silent() {
+        let noise = pink_noise(500, TEST_TONE_SAMPLE_RATE);
+        let peak = noise.iter().fold(0.0f32, |m, &s| m.max(s.abs()));
+        assert!(peak > 0.01, "noise too quiet: {}", peak);
+        assert!(peak <= DEFAULT_AMPLITUDE * 2.0, "noise too loud: {}", peak);
+    }
+}
@@ -0,0 +1,115 @@
+//! Transitions between queue entries: gapless, a fixed gap, or a crossfade.
+//!
+//! The frontend stages the next song with `audio_preload_next`; the
+//! player opens a second decoder for it (the second deck) and hands it to
+//! the primary feeder, which switches decks itself — at the end of the
+//! track after `seconds` of silence (`Gap`, 0 = gapless), or `seconds`
+//! before the end with an equal-power crossfade (`Crossfade`). The switch
+//! is sample-accurate because it happens where the audio is produced, not
+//! in the callback. Once the callback reaches the new song, it resets the
+//! position and duration and `audio://track-changed` is published.
+//!
+//! `Off` keeps the old behaviour: playback ends and the frontend starts
+//! the next song. Persisted in `app_settings` under `audio_transition`.
+
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+
+pub const TRACK_CHANGED_EVENT: &str = "audio://track-changed";
+const SETTINGS_KEY: &str = "audio_transition";
+pub const MAX_SECONDS: f32 = 12.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransitionMode {
+    #[default]
+    Off,
+    Gap,
+    Crossfade,
+}
+
+impl TransitionMode {
+    pub fn parse(mode: &str) -> Result<Self, String> {
+        match mode {
+            "off" => Ok(Self::Off),
+            "gap" | "gapless" => Ok(Self::Gap),
+            "crossfade" => Ok(Self::Crossfade),
+            other => Err(format!("Unknown transition mode '{}'", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Transition {
+    pub mode: TransitionMode,
+    /// Gap or crossfade length, 0 – `MAX_SECONDS`.
+    pub seconds: f32,
+}
+
+impl Transition {
+    /// Length in frames at `sample_rate`.
+    pub fn frames(&self, sample_rate: u32) -> usize {
+        (self.seconds.clamp(0.0, MAX_SECONDS) as f64 * sample_rate as f64) as usize
+    }
+}
+
+/// Payload of `audio://track-changed`.
+#[derive(Debug, Clone, Serialize)]
+pub struct TrackChanged {
+    pub file_path: String,
+    pub duration_ms: u64,
+}
+
+/// (outgoing, incoming) gains `progress` (0..=1) into a crossfade; equal
+/// power, so the level does not dip in the middle.
+pub fn crossfade_gains(progress: f32) -> (f32, f32) {
+    let angle = progress.clamp(0.0, 1.0) * std::f32::consts::FRAC_PI_2;
+    (angle.cos(), angle.sin())
+}
+
+pub fn load(conn: &Connection) -> Transition {
+    conn.query_row("SELECT value FROM app_settings WHERE key = ?1", [SETTINGS_KEY], |row| {
+        row.get::<_, String>(0)
+    })
+    .ok()
+    .and_then(|json| match serde_json::from_str::<Transition>(&json) {
+        Ok(transition) => Some(transition),
+        Err(e) => {
+            tracing::warn!("[audio] Ignoring invalid transition setting: {}", e);
+            None
+        }
+    })
+    .unwrap_or_default()
+}
+
+pub fn save(conn: &Connection, transition: &Transition) -> Result<(), String> {
+    let json = serde_json::to_string(transition).map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT OR REPLACE INTO app_settings (key, value) VALUES (?1, ?2)",
+        (SETTINGS_KEY, &json),
+    )
+    .map_err(|e| format!("Failed to save transition: {}", e))?;
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crossfade_keeps_power_and_modes_parse() {
+        for step in 0..=10 {
+            let (out, incoming) = crossfade_gains(step as f32 / 10.0);
+            assert!((out * out + incoming * incoming - 1.0).abs() < 1e-5);
+        }
+        assert_eq!(crossfade_gains(0.0), (1.0, 0.0));
+        assert_eq!(TransitionMode::parse("gapless").unwrap(), TransitionMode::Gap);
+        assert!(TransitionMode::parse("fade").is_err());
+        assert_eq!(Transition { mode: TransitionMode::Crossfade, seconds: 60.0 }.frames(48_000), 576_000);
+    }
+}
@@ -0,0 +1,122 @@
+//! Live vocal removal for stereo tracks without an instrumental.
+//!
+//! Lead vocals are almost always panned centre, so they sit in the mid
+//! signal (L+R) and not in the side (L−R). Cancelling the mid removes
+//! them — along with the bass and kick, which are centred too. With the
+//! band-pass on (the default), only the mid's vocal band (150 Hz – 7 kHz)
+//! is cancelled, so the low end survives. `MixProfile::vocal_reduction`
+//! is the per-output, persisted counterpart; this stage applies to every
+//! output and is switched from the player (`set_vocal_removal`).
+
+use std::f32::consts::PI;
+
+const BAND_LOW_HZ: f32 = 150.0;
+const BAND_HIGH_HZ: f32 = 7_000.0;
+const BUTTERWORTH_Q: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+/// RBJ biquad, transposed direct form II.
+#[derive(Clone, Copy)]
+pub(super) struct Biquad {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+    z1: f32,
+    z2: f32,
+}
+
+impl Biquad {
+    /// Coefficients normalised by a0.
+    pub(super) fn from_coefficients(b0: f32, b1: f32, b2: f32, a1: f32, a2: f32) -> Self {
+        Self { b0, b1, b2, a1, a2, z1: 0.0, z2: 0.0 }
+    }
+
+    fn new(highpass: bool, frequency: f32, sample_rate: u32) -> Self {
+        let w0 = 2.0 * PI * frequency.min(sample_rate as f32 * 0.45) / sample_rate.max(1) as f32;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * BUTTERWORTH_Q);
+        let a0 = 1.0 + alpha;
+        let (b0, b1) = if highpass { ((1.0 + cos) / 2.0, -(1.0 + cos)) } else { ((1.0 - cos) / 2.0, 1.0 - cos) };
+        Self::from_coefficients(b0 / a0, b1 / a0, b0 / a0, -2.0 * cos / a0, (1.0 - alpha) / a0)
+    }
+
+    pub(super) fn process(&mut self, x: f32) -> f32 {
+        let y = self.b0 * x + self.z1;
+        self.z1 = self.b1 * x - self.a1 * y + self.z2;
+        self.z2 = self.b2 * x - self.a2 * y;
+        y
+    }
+}
+
+pub struct VocalRemover {
+    strength: f32,
+    /// High-pass then low-pass isolating the vocal band of the mid.
+    band: Option<(Biquad, Biquad)>,
+}
+
+impl VocalRemover {
+    /// `strength` 0..=1 (1 = centre band cancelled completely).
+    pub fn new(strength: f32, band_limited: bool, sample_rate: u32) -> Self {
+        Self {
+            strength: strength.clamp(0.0, 1.0),
+            band: band_limited.then(|| (Biquad::new(true, BAND_LOW_HZ, sample_rate), Biquad::new(false, BAND_HIGH_HZ, sample_rate))),
+        }
+    }
+
+    /// Process interleaved stereo in place.
+    pub fn process(&mut self, samples: &mut [f32]) {
+        for frame in samples.chunks_exact_mut(2) {
+            let mid = (frame[0] + frame[1]) * 0.5;
+            let side = (frame[0] - frame[1]) * 0.5;
+            let removed = match self.band.as_mut() {
+                Some((highpass, lowpass)) => lowpass.process(highpass.process(mid)),
+                None => mid,
+            };
+            let mid = mid - removed * self.strength;
+            frame[0] = mid + side;
+            frame[1] = mid - side;
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn centred(frequency: f32, sr: u32) -> Vec<f32> {
+        (0..sr as usize / 2)
+            .flat_map(|i| {
+                let s = 0.5 * (2.0 * PI * frequency * i as f32 / sr as f32).sin();
+                [s, s]
+            })
+            .collect()
+    }
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn cancels_centred_vocal_band_but_keeps_bass_and_sides() {
+        let sr = 48_000;
+        let mut voice = centred(1_000.0, sr);
+        VocalRemover::new(1.0, true, sr).process(&mut voice);
+        assert!(rms(&voice[sr as usize / 2..]) < 0.1 * rms(&centred(1_000.0, sr)));
+
+        let mut bass = centred(50.0, sr);
+        let before = rms(&bass);
+        VocalRemover::new(1.0, true, sr).process(&mut bass);
+        assert!(rms(&bass[sr as usize / 2..]) > 0.7 * before);
+
+        // Hard-left guitar without the band-pass: its centre half is removed,
+        // leaving its side half, in opposite phase on the right
+        let mut side = vec![0.4, 0.0];
+        VocalRemover::new(1.0, false, sr).process(&mut side);
+        assert!((side[0] - 0.2).abs() < 1e-6 && (side[1] + 0.2).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,168 @@
+//! Waveform peaks for seek bars and cue editors.
+//!
+//! `generate_waveform` streams the file through the decoder and keeps the
+//! minimum and maximum sample (over all channels) of fixed 256-frame
+//! blocks, then folds those into `resolution` buckets — so the decoded
+//! audio is never held in memory and the container's (possibly wrong)
+//! duration does not matter. Results are cached per resolution in the
+//! analysis cache; the webview only ever receives the two peak arrays.
+//! A miss is drawn by a `High` job on the job queue, so a waveform asked
+//! for during playback shares the one lane the queue keeps working then.
+
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+
+use super::analysis_cache::{AnalysisCache, CacheKind};
+use super::stream_decoder::{PcmSource, StreamingDecoder};
+use crate::access::{require_webview, Capability};
+use crate::library::roots::require_in_library;
+
+const BLOCK_FRAMES: usize = 256;
+const MAX_RESOLUTION: usize = 20_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Waveform {
+    pub resolution: usize,
+    pub duration_ms: u64,
+    /// Per bucket, -1.0 ..= 1.0.
+    pub min: Vec<f32>,
+    pub max: Vec<f32>,
+}
+
+/// Min/max of every `BLOCK_FRAMES` frames of interleaved audio.
+struct BlockPeaks {
+    channels: usize,
+    frames_in_block: usize,
+    current: (f32, f32),
+    blocks: Vec<(f32, f32)>,
+}
+
+impl BlockPeaks {
+    fn new(channels: u16) -> Self {
+        Self { channels: channels.max(1) as usize, frames_in_block: 0, current: (0.0, 0.0), blocks: Vec::new() }
+    }
+
+    fn push(&mut self, samples: &[f32]) {
+        for frame in samples.chunks_exact(self.channels) {
+            for &s in frame {
+                self.current = (self.current.0.min(s), self.current.1.max(s));
+            }
+            self.frames_in_block += 1;
+            if self.frames_in_block == BLOCK_FRAMES {
+                self.blocks.push(std::mem::take(&mut self.current));
+                self.frames_in_block = 0;
+            }
+        }
+    }
+
+    /// Fold the blocks into `resolution` buckets (fewer for very short audio).
+    fn finish(mut self, resolution: usize) -> (Vec<f32>, Vec<f32>) {
+        if self.frames_in_block > 0 {
+            self.blocks.push(self.current);
+        }
+        let buckets = resolution.min(self.blocks.len());
+        let (mut min, mut max) = (vec![0.0; buckets], vec![0.0f32; buckets]);
+        for (i, &(lo, hi)) in self.blocks.iter().enumerate() {
+            let bucket = i * buckets / self.blocks.len();
+            min[bucket] = min[bucket].min(lo);
+            max[bucket] = max[bucket].max(hi);
+        }
+        (min, max)
+    }
+}
+
+fn generate(path: &str, resolution: usize) -> Result<Waveform, String> {
+    let mut source = StreamingDecoder::open(path)?;
+    let sample_rate = source.sample_rate().max(1) as u64;
+    let mut peaks = BlockPeaks::new(source.channels());
+    let mut chunk = Vec::new();
+    let mut frames = 0u64;
+    loop {
+        chunk.clear();
+        let more = source.read_chunk(&mut chunk)?;
+        frames += (chunk.len() / peaks.channels) as u64;
+        peaks.push(&chunk);
+        if !more {
+            break;
+        }
+    }
+    let (min, max) = peaks.finish(resolution);
+    Ok(Waveform { resolution: min.len(), duration_ms: frames * 1000 / sample_rate, min, max })
+}
+
+fn cached(cache: &AnalysisCache, path: &str, resolution: usize) -> Option<Waveform> {
+    let key = AnalysisCache::key_for(path).ok()?;
+    cache.get::<Waveform>(&key, CacheKind::Waveform, Some(&resolution.to_string()))
+}
+
+/// Draw the waveform of a job into the analysis cache; blocking.
+pub(crate) fn run_job(app: &AppHandle, path: &str, resolution: usize) -> Result<(), String> {
+    let cache = AnalysisCache::from_app(app).ok_or("Analysis cache unavailable")?;
+    let key = AnalysisCache::key_for(path)?;
+    let waveform = generate(path, resolution)?;
+    cache.put(&key, CacheKind::Waveform, Some(&resolution.to_string()), &waveform)
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+/// Min/max peaks of `path` in `resolution` buckets (at most 20 000),
+/// from the cache when this file was drawn at this resolution before.
+#[tauri::command]
+pub async fn generate_waveform(
+    app: AppHandle,
+    webview: tauri::Webview,
+    path: String,
+    resolution: usize,
+) -> Result<Waveform, String> {
+    require_webview(&webview, Capability::ControlPlayback)?;
+    require_in_library(&app, &path)?;
+    let resolution = resolution.clamp(1, MAX_RESOLUTION);
+    let lookup = |app: AppHandle, path: String| {
+        tauri::async_runtime::spawn_blocking(move || AnalysisCache::from_app(&app).map(|cache| cached(&cache, &path, resolution)))
+    };
+    match lookup(app.clone(), path.clone()).await.map_err(|e| e.to_string())? {
+        Some(Some(waveform)) => return Ok(waveform),
+        Some(None) => {}
+        // Nowhere for a job to leave it: draw it here
+        None => {
+            return tauri::async_runtime::spawn_blocking(move || generate(&path, resolution))
+                .await
+                .map_err(|e| e.to_string())?
+        }
+    }
+    crate::jobs::run_to_end(&app, crate::jobs::JobSpec::Waveform { path: path.clone(), resolution }).await?;
+    lookup(app, path.clone())
+        .await
+        .map_err(|e| e.to_string())?
+        .flatten()
+        .ok_or_else(|| format!("No waveform was drawn for {}", path))
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn folds_blocks_into_buckets() {
+        // Stereo: a quiet first half, a loud second half
+        let mut peaks = BlockPeaks::new(2);
+        let quiet: Vec<f32> = (0..BLOCK_FRAMES * 4).flat_map(|i| if i % 2 == 0 { [0.1, -0.1] } else { [-0.1, 0.1] }).collect();
+        let loud: Vec<f32> = (0..BLOCK_FRAMES * 4).flat_map(|_| [0.9, -0.8]).collect();
+        peaks.push(&quiet);
+        peaks.push(&loud[..loud.len() - 2]);
+        let (min, max) = peaks.finish(2);
+        assert_eq!(max, vec![0.1, 0.9]);
+        assert_eq!(min, vec![-0.1, -0.8]);
+
+        // Asking for more buckets than there is audio
+        let mut short = BlockPeaks::new(1);
+        short.push(&[0.5; 10]);
+        assert_eq!(short.finish(100).1, vec![0.5]);
+    }
+}
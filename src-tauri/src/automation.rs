@@ -0,0 +1,185 @@
+//! Startup flags for unattended venue installs.
+//!
+//! A venue PC that boots into the app is set up by its startup script
+//! instead of someone clicking through the settings:
+//!
+//! ```text
+//! karaoke --kiosk --port 3100 --library D:\Songs --player-display 2 --autoplay-queue tonight.csv
+//! ```
+//!
+//! `cli::init_gui_flags` reads the flags before the app is built, so a bad
+//! value stops the launch with a message instead of half an install.
+//! `--kiosk` and `--port` are read where kiosk mode and the server start;
+//! a port given with `--port` is used or the server does not start, it is
+//! never swapped for another one. The rest is carried out here:
+//!   - `--library <dir>` (repeatable) adds a root folder — watched like the
+//!     others from now on — and scans it on the import worker;
+//!   - `--autoplay-queue <file>` appends the songs of a queue file (see
+//!     `interchange::read_queue_file`) in its order and, once the frontend
+//!     listens, asks it to start the first one (`queue://autoplay`). With
+//!     `--library` it waits for that scan, so songs it brings can be
+//!     queued. A file is applied on the first launch that sees it: the
+//!     same file, unchanged, is skipped on every later launch (a restart
+//!     for an update, the PC rebooting mid-show), editing it applies it
+//!     again;
+//!   - `--player-display <n>` opens the player window on the n-th display,
+//!     counted as `list_monitors` lists them, once the server is ready.
+
+use std::path::{Path, PathBuf};
+
+use tauri::{AppHandle, Manager};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::cli;
+use crate::db::DbState;
+use crate::desktop::player_window;
+use crate::events::{AppEvent, EventBus};
+use crate::library::import_queue::ImportQueue;
+use crate::runtime::TaskSupervisor;
+use crate::server::launch_plan::FileStamp;
+use crate::{interchange, launch, queue};
+
+/// The last `--autoplay-queue` file applied, as `stamp_of` describes it.
+const APPLIED_QUEUE_SETTING: &str = "automation_applied_queue";
+
+/// Add the `--library` folders and queue the `--autoplay-queue` songs.
+/// Call once the database, the import worker and the task supervisor are
+/// managed, before the library watcher starts.
+pub fn apply(app: &AppHandle) {
+    let flags = cli::gui_flags();
+    let queue_file = flags.autoplay_queue.clone().filter(|file| {
+        let fresh = !already_applied(app, file);
+        if !fresh {
+            tracing::info!("[automation] --autoplay-queue: {} was applied on an earlier launch", file.display());
+        }
+        fresh
+    });
+    // Subscribed before the scan is queued, so its end cannot be missed
+    let events = queue_file.as_ref().map(|_| app.state::<EventBus>().subscribe());
+    let mut scan = None;
+    if !flags.library.is_empty() {
+        match add_library(app, &flags.library) {
+            Ok(job_id) => scan = Some(job_id),
+            Err(e) => tracing::error!("[automation] --library: {}", e),
+        }
+    }
+    let (Some(file), Some(mut events)) = (queue_file, events) else { return };
+    let Some(job_id) = scan else {
+        load_queue_logged(app, &file);
+        return;
+    };
+    let task_app = app.clone();
+    app.state::<TaskSupervisor>().spawn("autoplay-queue", move |token| async move {
+        loop {
+            tokio::select! {
+                _ = token.cancelled() => return,
+                received = events.recv() => match received {
+                    Ok(envelope) => match envelope.event {
+                        AppEvent::ImportComplete(done) if done.job_id == job_id => break,
+                        _ => continue,
+                    },
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return,
+                },
+            }
+        }
+        load_queue_logged(&task_app, &file);
+    });
+}
+
+/// Add `dirs` as root folders and scan them; returns the scan's import job id.
+fn add_library(app: &AppHandle, dirs: &[PathBuf]) -> Result<u64, String> {
+    {
+        let db = app.state::<DbState>();
+        let conn = db.conn.lock().map_err(|e| e.to_string())?;
+        for dir in dirs {
+            conn.execute("INSERT OR IGNORE INTO root_folders (path) VALUES (?1)", [dir.to_string_lossy()])
+                .map_err(|e| format!("Failed to add library folder {}: {}", dir.display(), e))?;
+        }
+    }
+    // Inside a root folder now, so indexed where the songs lie
+    let job_id = app.state::<ImportQueue>().enqueue_into_library(dirs.to_vec())?;
+    tracing::info!("[automation] Scanning {} library folder(s)", dirs.len());
+    Ok(job_id)
+}
+
+/// What identifies one version of a queue file: its path, size and
+/// modification time.
+fn stamp_of(file: &Path) -> Option<String> {
+    let stamp = FileStamp::of(file)?;
+    let path = std::fs::canonicalize(file).unwrap_or_else(|_| file.to_path_buf());
+    Some(format!("{}|{}|{}", path.display(), stamp.len, stamp.modified))
+}
+
+fn already_applied(app: &AppHandle, file: &Path) -> bool {
+    let Some(stamp) = stamp_of(file) else { return false };
+    let db = app.state::<DbState>();
+    let Ok(conn) = db.conn.lock() else { return false };
+    crate::scheduler::read_setting(&conn, APPLIED_QUEUE_SETTING).as_deref() == Some(stamp.as_str())
+}
+
+fn mark_applied(app: &AppHandle, file: &Path) -> Result<(), String> {
+    let Some(stamp) = stamp_of(file) else { return Ok(()) };
+    let db = app.state::<DbState>();
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT OR REPLACE INTO app_settings (key, value) VALUES (?1, ?2)",
+        (APPLIED_QUEUE_SETTING, stamp),
+    )
+    .map_err(|e| format!("Failed to remember the applied queue file: {}", e))?;
+    Ok(())
+}
+
+fn load_queue_logged(app: &AppHandle, file: &Path) {
+    if let Err(e) = load_queue(app, file) {
+        tracing::error!("[automation] --autoplay-queue: {}", e);
+    }
+}
+
+fn load_queue(app: &AppHandle, file: &Path) -> Result<(), String> {
+    let songs = interchange::read_queue_file(file)?;
+    let (added, errors) = queue::enqueue_all(app, &songs)?;
+    for error in &errors {
+        tracing::warn!("[automation] Skipped a queued song: {}", error);
+    }
+    if added.is_empty() {
+        return Err(format!("None of the {} song(s) in {} could be queued", songs.len(), file.display()));
+    }
+    if let Err(e) = mark_applied(app, file) {
+        tracing::warn!("[automation] {}", e);
+    }
+    launch::autoplay(app, added.len(), file.display().to_string());
+    Ok(())
+}
+
+/// Open the player window on the `--player-display` once the server is
+/// ready. Call once the event bus is managed, before the server starts.
+pub fn spawn_player_display(app: AppHandle) {
+    let Some(display) = cli::gui_flags().player_display else { return };
+    let mut events = app.state::<EventBus>().subscribe();
+    let task_app = app.clone();
+    app.state::<TaskSupervisor>().spawn("player-display", move |token| async move {
+        loop {
+            tokio::select! {
+                _ = token.cancelled() => return,
+                received = events.recv() => match received {
+                    Ok(envelope) if matches!(envelope.event, AppEvent::ServerReady { .. }) => break,
+                    Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return,
+                },
+            }
+        }
+        if let Err(e) = open_on_display(&task_app, display) {
+            tracing::error!("[automation] --player-display: {}", e);
+        }
+    });
+}
+
+/// Player window on display `n`, 1-based.
+fn open_on_display(app: &AppHandle, n: usize) -> Result<(), String> {
+    let monitors = player_window::monitors(app)?;
+    let monitor = monitors
+        .get(n - 1)
+        .ok_or_else(|| format!("Display {} not found ({} connected)", n, monitors.len()))?;
+    player_window::open(app, Some(&monitor.id)).map(|_| ())
+}
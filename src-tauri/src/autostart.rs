@@ -0,0 +1,320 @@
+//! Start the app when the user logs in.
+//!
+//! `set_autostart` writes or removes the OS's own login item, which starts
+//! the app with `--autostart`:
+//!   - Windows: a value named after the app under
+//!     `HKCU\Software\Microsoft\Windows\CurrentVersion\Run`;
+//!   - macOS: a launch agent, `~/Library/LaunchAgents/<identifier>.plist`;
+//!   - Linux: an XDG desktop entry, `~/.config/autostart/<identifier>.desktop`,
+//!     pointing at the AppImage rather than its mount point when run as one.
+//!
+//! The entry is the only record of the setting, so turning it off in the
+//! OS settings sticks. While it is on, every start rewrites it, so it
+//! follows the executable when an update moved it.
+//!
+//! A kiosk machine thus reboots straight into the app: kiosk mode comes
+//! from `config.toml`, the queue from the database, and the live session
+//! from its autosave (see `session`), which the frontend resumes without
+//! asking when `--autostart` started it.
+
+use std::path::{Path, PathBuf};
+
+use tauri::{AppHandle, Manager};
+
+use crate::access::{require_webview, Capability};
+
+pub const AUTOSTART_FLAG: &str = "--autostart";
+
+/// Names of the login item.
+struct Entry {
+    /// `Karaoke ZERO`, shown in the OS's list of login items.
+    name: String,
+    /// The bundle identifier, for file names.
+    identifier: String,
+}
+
+impl Entry {
+    fn of(app: &AppHandle) -> Self {
+        let config = app.config();
+        Self {
+            name: config.product_name.clone().unwrap_or_else(|| app.package_info().name.clone()),
+            identifier: config.identifier.clone(),
+        }
+    }
+}
+
+/// What the login item starts: the AppImage when run as one (the
+/// executable lives in a mount that is gone after a reboot), else this
+/// executable.
+fn launcher() -> Result<PathBuf, String> {
+    if cfg!(target_os = "linux") {
+        if let Some(appimage) = std::env::var_os("APPIMAGE").map(PathBuf::from).filter(|p| p.is_file()) {
+            return Ok(appimage);
+        }
+    }
+    std::env::current_exe().map_err(|e| format!("Cannot locate the app executable: {}", e))
+}
+
+/// Windows command line: the program quoted, then the flag.
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+fn run_command(program: &Path) -> String {
+    format!("\"{}\" {}", program.display(), AUTOSTART_FLAG)
+}
+
+/// `Exec=` of a desktop entry. The program is quoted as the spec asks,
+/// and backslashes are escaped once more for the string value.
+fn desktop_exec(program: &Path) -> String {
+    let mut quoted = String::from("\"");
+    for c in program.to_string_lossy().chars() {
+        if matches!(c, '"' | '`' | '$' | '\\') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    format!("{} {}", quoted.replace('\\', "\\\\"), AUTOSTART_FLAG)
+}
+
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn desktop_entry(entry: &Entry, program: &Path) -> String {
+    format!(
+        "[Desktop Entry]\nType=Application\nName={}\nExec={}\nTerminal=false\nX-GNOME-Autostart-enabled=true\n",
+        entry.name.replace(['\n', '\r'], " "),
+        desktop_exec(program)
+    )
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn launch_agent(entry: &Entry, program: &Path) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+  <key>Label</key>
+  <string>{}</string>
+  <key>ProgramArguments</key>
+  <array>
+    <string>{}</string>
+    <string>{}</string>
+  </array>
+  <key>RunAtLoad</key>
+  <true/>
+</dict>
+</plist>
+"#,
+        xml_escape(&entry.identifier),
+        xml_escape(&program.to_string_lossy()),
+        AUTOSTART_FLAG
+    )
+}
+
+/// File of the login item on macOS and Linux.
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+fn entry_file(app: &AppHandle, entry: &Entry) -> Result<PathBuf, String> {
+    #[cfg(target_os = "macos")]
+    let file = app
+        .path()
+        .home_dir()
+        .map(|home| home.join("Library").join("LaunchAgents").join(format!("{}.plist", entry.identifier)));
+    #[cfg(target_os = "linux")]
+    let file = app
+        .path()
+        .config_dir()
+        .map(|config| config.join("autostart").join(format!("{}.desktop", entry.identifier)));
+    file.map_err(|e| format!("No folder for login items: {}", e))
+}
+
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+fn write_entry(app: &AppHandle, entry: &Entry, program: &Path) -> Result<(), String> {
+    let file = entry_file(app, entry)?;
+    #[cfg(target_os = "macos")]
+    let contents = launch_agent(entry, program);
+    #[cfg(target_os = "linux")]
+    let contents = desktop_entry(entry, program);
+    if let Some(dir) = file.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    // Temp file + rename: the OS never reads half an entry
+    let tmp = file.with_extension("tmp");
+    std::fs::write(&tmp, contents).map_err(|e| format!("Failed to write {}: {}", file.display(), e))?;
+    std::fs::rename(&tmp, &file).map_err(|e| format!("Failed to write {}: {}", file.display(), e))
+}
+
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+fn remove_entry(app: &AppHandle, entry: &Entry) -> Result<(), String> {
+    let file = entry_file(app, entry)?;
+    match std::fs::remove_file(&file) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(format!("Failed to remove {}: {}", file.display(), e)),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+fn has_entry(app: &AppHandle, entry: &Entry) -> bool {
+    entry_file(app, entry).is_ok_and(|file| file.is_file())
+}
+
+#[cfg(target_os = "windows")]
+mod registry {
+    use windows_sys::Win32::Foundation::{ERROR_FILE_NOT_FOUND, ERROR_SUCCESS};
+    use windows_sys::Win32::System::Registry::{
+        RegDeleteKeyValueW, RegGetValueW, RegSetKeyValueW, HKEY_CURRENT_USER, REG_SZ, RRF_RT_REG_SZ,
+    };
+
+    const RUN_KEY: &str = r"Software\Microsoft\Windows\CurrentVersion\Run";
+
+    fn wide(text: &str) -> Vec<u16> {
+        text.encode_utf16().chain(Some(0)).collect()
+    }
+
+    pub fn set(name: &str, command: &str) -> Result<(), String> {
+        let (key, name, data) = (wide(RUN_KEY), wide(name), wide(command));
+        // SAFETY: NUL-terminated buffers that outlive the call; the size
+        // includes the terminator, as REG_SZ requires
+        let status = unsafe {
+            RegSetKeyValueW(
+                HKEY_CURRENT_USER,
+                key.as_ptr(),
+                name.as_ptr(),
+                REG_SZ,
+                data.as_ptr().cast(),
+                (data.len() * std::mem::size_of::<u16>()) as u32,
+            )
+        };
+        if status != ERROR_SUCCESS {
+            return Err(format!("Failed to write the Run key (error {})", status));
+        }
+        Ok(())
+    }
+
+    pub fn delete(name: &str) -> Result<(), String> {
+        let (key, name) = (wide(RUN_KEY), wide(name));
+        // SAFETY: NUL-terminated buffers that outlive the call
+        let status = unsafe { RegDeleteKeyValueW(HKEY_CURRENT_USER, key.as_ptr(), name.as_ptr()) };
+        if status != ERROR_SUCCESS && status != ERROR_FILE_NOT_FOUND {
+            return Err(format!("Failed to remove the Run key value (error {})", status));
+        }
+        Ok(())
+    }
+
+    pub fn exists(name: &str) -> bool {
+        let (key, name) = (wide(RUN_KEY), wide(name));
+        // SAFETY: NUL-terminated buffers; null output pointers only ask
+        // whether the value exists
+        let status = unsafe {
+            RegGetValueW(
+                HKEY_CURRENT_USER,
+                key.as_ptr(),
+                name.as_ptr(),
+                RRF_RT_REG_SZ,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            )
+        };
+        status == ERROR_SUCCESS
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn write_entry(_app: &AppHandle, entry: &Entry, program: &Path) -> Result<(), String> {
+    registry::set(&entry.name, &run_command(program))
+}
+
+#[cfg(target_os = "windows")]
+fn remove_entry(_app: &AppHandle, entry: &Entry) -> Result<(), String> {
+    registry::delete(&entry.name)
+}
+
+#[cfg(target_os = "windows")]
+fn has_entry(_app: &AppHandle, entry: &Entry) -> bool {
+    registry::exists(&entry.name)
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+fn write_entry(_app: &AppHandle, _entry: &Entry, _program: &Path) -> Result<(), String> {
+    Err("Starting at login is not supported on this platform".to_string())
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+fn remove_entry(_app: &AppHandle, _entry: &Entry) -> Result<(), String> {
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+fn has_entry(_app: &AppHandle, _entry: &Entry) -> bool {
+    false
+}
+
+/// Whether the app starts at login.
+pub fn is_enabled(app: &AppHandle) -> bool {
+    has_entry(app, &Entry::of(app))
+}
+
+pub fn set_enabled(app: &AppHandle, enabled: bool) -> Result<(), String> {
+    let entry = Entry::of(app);
+    if enabled {
+        write_entry(app, &entry, &launcher()?)
+    } else {
+        remove_entry(app, &entry)
+    }
+}
+
+/// Point an existing login item at this executable. Call once at startup.
+pub fn refresh(app: &AppHandle) {
+    if !is_enabled(app) {
+        return;
+    }
+    if let Err(e) = set_enabled(app, true) {
+        tracing::warn!("[autostart] Failed to update the login item: {}", e);
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+#[tauri::command]
+pub fn get_autostart(app: AppHandle) -> bool {
+    is_enabled(&app)
+}
+
+/// Start the app at login (`true`) or not. Returns the new state.
+#[tauri::command]
+pub fn set_autostart(app: AppHandle, webview: tauri::Webview, enabled: bool) -> Result<bool, String> {
+    require_webview(&webview, Capability::ChangeSettings)?;
+    set_enabled(&app, enabled)?;
+    tracing::info!("[autostart] Start at login {}", if enabled { "on" } else { "off" });
+    Ok(is_enabled(&app))
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_login_items_for_each_platform() {
+        let entry = Entry { name: "Karaoke ZERO".to_string(), identifier: "com.karaoke.successor".to_string() };
+        let program = Path::new("/opt/Karaoke $ZERO/karaoke");
+
+        assert_eq!(desktop_exec(program), r#""/opt/Karaoke \\$ZERO/karaoke" --autostart"#);
+        let desktop = desktop_entry(&entry, program);
+        assert!(desktop.starts_with("[Desktop Entry]\nType=Application\nName=Karaoke ZERO\n"));
+
+        let plist = launch_agent(&entry, Path::new("/Applications/A&B.app/Contents/MacOS/karaoke"));
+        assert!(plist.contains("<string>com.karaoke.successor</string>"));
+        assert!(plist.contains("<string>/Applications/A&amp;B.app/Contents/MacOS/karaoke</string>"));
+        assert!(plist.contains("<key>RunAtLoad</key>\n  <true/>"));
+
+        assert_eq!(run_command(Path::new(r"C:\Program Files\Karaoke ZERO\karaoke.exe")), r#""C:\Program Files\Karaoke ZERO\karaoke.exe" --autostart"#);
+    }
+}
@@ -0,0 +1,366 @@
+//! Backup and restore of the whole setup in one file.
+//!
+//! `create_backup` writes a zip archive holding:
+//!   - `manifest.json` — backup format, app and schema version, time;
+//!   - `karaoke.db` — a `VACUUM INTO` snapshot of the database: library
+//!     cache, profiles, history, queue and settings;
+//!   - `config.toml` — the config in effect;
+//!   - `thumbnails/` — the video thumbnail cache.
+//!
+//! `restore_backup` checks everything before touching anything: the
+//! manifest, the database's integrity and its schema version — newer than
+//! this build is refused, older is migrated. It then swaps the database in,
+//! applies the config and unpacks the thumbnails. The replaced database is
+//! kept next to the live one as `karaoke.db.pre-restore` until the next
+//! restore. Song files are not part of a backup: their folders need the
+//! same paths on the new machine, or have to be added again.
+
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::access::{require_webview, Capability};
+use crate::db::{self, schema, DbState};
+use crate::media::thumbnails;
+use crate::paths::long_path;
+use crate::scheduler::now_ms;
+
+/// Bump when the archive layout changes incompatibly.
+const FORMAT_VERSION: u32 = 1;
+const MANIFEST_ENTRY: &str = "manifest.json";
+const DATABASE_ENTRY: &str = "karaoke.db";
+const CONFIG_ENTRY: &str = "config.toml";
+const THUMBNAILS_PREFIX: &str = "thumbnails/";
+/// Largest database a restore unpacks.
+const MAX_DATABASE_SIZE: u64 = 4 * 1024 * 1024 * 1024;
+const MAX_CONFIG_SIZE: u64 = 1024 * 1024;
+const MAX_THUMBNAIL_SIZE: u64 = 16 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupManifest {
+    pub format: u32,
+    pub app_version: String,
+    pub schema_version: i32,
+    /// Epoch ms.
+    pub created_at: i64,
+    pub thumbnails: usize,
+}
+
+/// Why a backup cannot be restored by this build, if it cannot.
+fn check_manifest(manifest: &BackupManifest) -> Result<(), String> {
+    if manifest.format > FORMAT_VERSION {
+        return Err(format!(
+            "This backup was made by a newer version of the app ({}); update before restoring it",
+            manifest.app_version
+        ));
+    }
+    check_schema(manifest.schema_version, &manifest.app_version)
+}
+
+fn check_schema(version: i32, app_version: &str) -> Result<(), String> {
+    if version > schema::SCHEMA_VERSION {
+        return Err(format!(
+            "The backup's database (schema {}, app {}) is newer than this app supports (schema {})",
+            version,
+            app_version,
+            schema::SCHEMA_VERSION
+        ));
+    }
+    Ok(())
+}
+
+fn data_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    app.path().app_data_dir().map_err(|e| format!("Failed to get app data dir: {}", e))
+}
+
+fn thumbnails_dir(app: &AppHandle) -> Option<PathBuf> {
+    app.path().app_cache_dir().ok().map(|dir| dir.join(thumbnails::CACHE_SUBDIR))
+}
+
+// ---------------------------------------------------------------------------
+// Backup
+// ---------------------------------------------------------------------------
+
+fn write_archive(target: &Path, snapshot: &Path, config: &str, thumbnails: Option<&Path>) -> Result<BackupManifest, String> {
+    let file = File::create(long_path(target)).map_err(|e| format!("Failed to create {}: {}", target.display(), e))?;
+    let mut zip = ZipWriter::new(file);
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated).large_file(true);
+    // Thumbnails are JPEGs already
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let failed = |e: &dyn std::fmt::Display| format!("Failed to write backup: {}", e);
+
+    zip.start_file(DATABASE_ENTRY, deflated).map_err(|e| failed(&e))?;
+    let mut db = File::open(snapshot).map_err(|e| failed(&e))?;
+    io::copy(&mut db, &mut zip).map_err(|e| failed(&e))?;
+
+    zip.start_file(CONFIG_ENTRY, deflated).map_err(|e| failed(&e))?;
+    zip.write_all(config.as_bytes()).map_err(|e| failed(&e))?;
+
+    let mut count = 0;
+    if let Some(entries) = thumbnails.and_then(|dir| fs::read_dir(dir).ok()) {
+        for path in entries.flatten().map(|e| e.path()).filter(|p| p.is_file()) {
+            let Some(name) = path.file_name().and_then(|n| n.to_str()) else { continue };
+            let Ok(mut thumbnail) = File::open(&path) else { continue };
+            zip.start_file(format!("{}{}", THUMBNAILS_PREFIX, name), stored).map_err(|e| failed(&e))?;
+            io::copy(&mut thumbnail, &mut zip).map_err(|e| failed(&e))?;
+            count += 1;
+        }
+    }
+
+    // Written last: it is what a restore checks first
+    let manifest = BackupManifest {
+        format: FORMAT_VERSION,
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        schema_version: schema::SCHEMA_VERSION,
+        created_at: now_ms(),
+        thumbnails: count,
+    };
+    zip.start_file(MANIFEST_ENTRY, deflated).map_err(|e| failed(&e))?;
+    let json = serde_json::to_vec_pretty(&manifest).map_err(|e| failed(&e))?;
+    zip.write_all(&json).map_err(|e| failed(&e))?;
+    zip.finish().map_err(|e| failed(&e))?;
+    Ok(manifest)
+}
+
+fn create(app: &AppHandle, target: &Path) -> Result<BackupManifest, String> {
+    let snapshot = data_dir(app)?.join(format!("backup-{}.db.tmp", now_ms()));
+    {
+        let db = app.state::<DbState>();
+        let conn = db.conn.lock().map_err(|e| e.to_string())?;
+        // VACUUM INTO produces a compact, consistent copy even in WAL mode
+        conn.execute("VACUUM INTO ?1", [snapshot.to_string_lossy().as_ref()])
+            .map_err(|e| format!("Backup failed: {}", e))?;
+    }
+    let config = toml::to_string_pretty(&crate::config::current(app)).map_err(|e| format!("Failed to encode config: {}", e));
+
+    // Written beside the target and renamed, so a failed backup never
+    // replaces a good one
+    let partial = target.with_extension("partial");
+    let written = config.and_then(|config| write_archive(&partial, &snapshot, &config, thumbnails_dir(app).as_deref()));
+    let _ = fs::remove_file(&snapshot);
+    let manifest = written.and_then(|manifest| {
+        fs::rename(long_path(&partial), long_path(target))
+            .map_err(|e| format!("Failed to write {}: {}", target.display(), e))?;
+        Ok(manifest)
+    });
+    match &manifest {
+        Ok(manifest) => tracing::info!("[backup] Backup written to {} ({} thumbnails)", target.display(), manifest.thumbnails),
+        Err(_) => {
+            let _ = fs::remove_file(long_path(&partial));
+        }
+    }
+    manifest
+}
+
+// ---------------------------------------------------------------------------
+// Restore
+// ---------------------------------------------------------------------------
+
+fn read_entry(archive: &mut ZipArchive<File>, name: &str, limit: u64) -> Result<Vec<u8>, String> {
+    let member = archive.by_name(name).map_err(|_| format!("Not a backup: {} is missing", name))?;
+    let mut bytes = Vec::new();
+    member.take(limit + 1).read_to_end(&mut bytes).map_err(|e| format!("Failed to read {}: {}", name, e))?;
+    if bytes.len() as u64 > limit {
+        return Err(format!("{} is too large", name));
+    }
+    Ok(bytes)
+}
+
+fn extract_entry(archive: &mut ZipArchive<File>, name: &str, target: &Path, limit: u64) -> Result<(), String> {
+    let member = archive.by_name(name).map_err(|_| format!("Not a backup: {} is missing", name))?;
+    let mut out = File::create(target).map_err(|e| format!("Failed to create {}: {}", target.display(), e))?;
+    // The declared size may lie; count what actually inflates
+    let written = io::copy(&mut member.take(limit + 1), &mut out).map_err(|e| format!("Failed to extract {}: {}", name, e))?;
+    if written > limit {
+        return Err(format!("{} is too large", name));
+    }
+    Ok(())
+}
+
+/// Open the unpacked database and bring it to this build's schema;
+/// returns the version it had.
+fn prepare_database(path: &Path, app_version: &str) -> Result<i32, String> {
+    let conn = Connection::open(path).map_err(|e| format!("The backup's database cannot be opened: {}", e))?;
+    let integrity: String = conn
+        .query_row("PRAGMA integrity_check", [], |row| row.get(0))
+        .map_err(|e| format!("The backup's database cannot be read: {}", e))?;
+    if integrity != "ok" {
+        return Err(format!("The backup's database is damaged: {}", integrity));
+    }
+    let version = schema::version(&conn);
+    check_schema(version, app_version)?;
+    conn.execute_batch("PRAGMA recursive_triggers=ON;").map_err(|e| e.to_string())?;
+    schema::migrate(&conn)?;
+    Ok(version)
+}
+
+/// Swap the live database for `restored`, keeping the old one as
+/// `*.pre-restore`; on failure the old one is put back.
+fn swap_database(db: &DbState, restored: &Path) -> Result<(), String> {
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    // Closing the live connection checkpoints and removes its WAL
+    *conn = Connection::open_in_memory().map_err(|e| e.to_string())?;
+
+    let previous = db.db_path.with_extension("db.pre-restore");
+    let _ = fs::remove_file(&previous);
+    let swapped = fs::rename(&db.db_path, &previous)
+        .and_then(|_| fs::rename(restored, &db.db_path))
+        .map_err(|e| format!("Failed to replace the database: {}", e));
+    let opened = swapped.and_then(|_| db::open_connection(&db.db_path));
+    match opened {
+        Ok(restored) => {
+            *conn = restored;
+            Ok(())
+        }
+        Err(e) => {
+            if previous.exists() {
+                let _ = fs::remove_file(&db.db_path);
+                let _ = fs::rename(&previous, &db.db_path);
+            }
+            *conn = db::open_connection(&db.db_path)?;
+            Err(e)
+        }
+    }
+}
+
+/// Reload the state the audio engine read from the database at startup.
+fn reload_audio_settings(app: &AppHandle) {
+    let (Some(audio), Some(db)) = (app.try_state::<crate::audio::commands::AudioState>(), app.try_state::<DbState>()) else {
+        return;
+    };
+    let loaded = audio
+        .load_channel_maps(&db)
+        .and_then(|_| audio.load_output_config(&db))
+        .and_then(|_| audio.load_transition(&db));
+    if let Err(e) = loaded {
+        tracing::warn!("[backup] Restored audio settings not applied: {}", e);
+    }
+}
+
+fn restore(app: &AppHandle, source: &Path) -> Result<BackupManifest, String> {
+    let file = File::open(long_path(source)).map_err(|e| format!("Failed to open {}: {}", source.display(), e))?;
+    let mut archive = ZipArchive::new(file).map_err(|e| format!("Not a backup {}: {}", source.display(), e))?;
+    let manifest: BackupManifest = serde_json::from_slice(&read_entry(&mut archive, MANIFEST_ENTRY, MAX_CONFIG_SIZE)?)
+        .map_err(|e| format!("Not a backup: unreadable manifest: {}", e))?;
+    check_manifest(&manifest)?;
+    let config = String::from_utf8(read_entry(&mut archive, CONFIG_ENTRY, MAX_CONFIG_SIZE)?)
+        .map_err(|_| "The backup's config is not text".to_string())
+        .and_then(|text| crate::config::from_toml(&text))?;
+
+    let staged = data_dir(app)?.join("restore.db.tmp");
+    let prepared = extract_entry(&mut archive, DATABASE_ENTRY, &staged, MAX_DATABASE_SIZE)
+        .and_then(|_| prepare_database(&staged, &manifest.app_version));
+    let swapped = prepared.and_then(|_| swap_database(&app.state::<DbState>(), &staged));
+    let _ = fs::remove_file(&staged);
+    swapped?;
+
+    reload_audio_settings(app);
+    // Applying the config compares the restored search index with it
+    if let Err(e) = crate::config::update(app, config) {
+        tracing::warn!("[backup] Restored config not applied: {}", e);
+        crate::db::search::sync(app, &crate::config::current(app).search);
+    }
+
+    let mut thumbnails = 0;
+    if let Some(dir) = thumbnails_dir(app).filter(|dir| fs::create_dir_all(dir).is_ok()) {
+        let names: Vec<String> =
+            archive.file_names().filter(|n| n.starts_with(THUMBNAILS_PREFIX)).map(str::to_string).collect();
+        for name in names {
+            // Flat file names only; anything else is not ours
+            let file_name = &name[THUMBNAILS_PREFIX.len()..];
+            if file_name.is_empty() || file_name.contains(['/', '\\']) || file_name.starts_with('.') {
+                continue;
+            }
+            match extract_entry(&mut archive, &name, &dir.join(file_name), MAX_THUMBNAIL_SIZE) {
+                Ok(()) => thumbnails += 1,
+                Err(e) => tracing::warn!("[backup] {}", e),
+            }
+        }
+    }
+    tracing::info!(
+        "[backup] Restored {} (schema {}, {} thumbnails)",
+        source.display(),
+        manifest.schema_version,
+        thumbnails
+    );
+    Ok(BackupManifest { thumbnails, ..manifest })
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+/// Write a backup of the database, config and thumbnails to `path`.
+#[tauri::command]
+pub async fn create_backup(app: AppHandle, webview: tauri::Webview, path: String) -> Result<BackupManifest, String> {
+    require_webview(&webview, Capability::ChangeSettings)?;
+    tauri::async_runtime::spawn_blocking(move || create(&app, Path::new(&path)))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Replace the database, config and thumbnails with those in the backup
+/// at `path`. The frontend should reload its data afterwards.
+#[tauri::command]
+pub async fn restore_backup(app: AppHandle, webview: tauri::Webview, path: String) -> Result<BackupManifest, String> {
+    require_webview(&webview, Capability::ChangeSettings)?;
+    tauri::async_runtime::spawn_blocking(move || restore(&app, Path::new(&path)))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_an_archive_and_refuses_newer_schemas() {
+        let dir = crate::paths::test_dir("backup");
+        let snapshot = dir.join("snapshot.db");
+        {
+            let conn = Connection::open(&snapshot).unwrap();
+            conn.execute_batch("PRAGMA recursive_triggers=ON;").unwrap();
+            schema::migrate(&conn).unwrap();
+        }
+        let thumbs = dir.join("thumbs");
+        fs::create_dir_all(&thumbs).unwrap();
+        fs::write(thumbs.join("abc.jpg"), b"jpeg").unwrap();
+
+        let archive_path = dir.join("backup.zip");
+        let manifest = write_archive(&archive_path, &snapshot, "[server]\n", Some(&thumbs)).unwrap();
+        assert_eq!((manifest.schema_version, manifest.thumbnails), (schema::SCHEMA_VERSION, 1));
+
+        let mut archive = ZipArchive::new(File::open(&archive_path).unwrap()).unwrap();
+        let read: BackupManifest = serde_json::from_slice(&read_entry(&mut archive, MANIFEST_ENTRY, MAX_CONFIG_SIZE).unwrap()).unwrap();
+        assert_eq!(read, manifest);
+        assert!(check_manifest(&read).is_ok());
+
+        let staged = dir.join("staged.db");
+        extract_entry(&mut archive, DATABASE_ENTRY, &staged, MAX_DATABASE_SIZE).unwrap();
+        assert_eq!(prepare_database(&staged, "test").unwrap(), schema::SCHEMA_VERSION);
+
+        // A database from a newer build is refused before anything changes
+        Connection::open(&staged)
+            .unwrap()
+            .execute("UPDATE _schema_meta SET value = ?1 WHERE key = 'version'", [(schema::SCHEMA_VERSION + 1).to_string()])
+            .unwrap();
+        assert!(prepare_database(&staged, "test").is_err());
+        assert!(check_manifest(&BackupManifest { format: FORMAT_VERSION + 1, ..manifest }).is_err());
+
+        for path in [&snapshot, &archive_path, &staged] {
+            let _ = fs::remove_file(path);
+        }
+        let _ = fs::remove_dir_all(&thumbs);
+    }
+}
@@ -0,0 +1,437 @@
+//! Chromecast: found by mDNS (`_googlecast._tcp`), driven over the Cast v2
+//! protocol — length-prefixed protobuf `CastMessage`s with JSON payloads
+//! over TLS on port 8009 — with the Default Media Receiver app.
+//!
+//! Devices present a self-signed certificate; it is not checked (nor is
+//! anything a Cast sender could check without Google's device auth).
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt, ReadHalf, WriteHalf};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use tokio_rustls::rustls::crypto::{ring, CryptoProvider};
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use tokio_rustls::rustls::{ClientConfig, DigitallySignedStruct, SignatureScheme};
+use tokio_rustls::TlsConnector;
+
+pub const SERVICE_TYPE: &str = "_googlecast._tcp.local.";
+/// The Default Media Receiver: plays a URL, no app of our own needed.
+const MEDIA_RECEIVER: &str = "CC1AD845";
+const NS_CONNECTION: &str = "urn:x-cast:com.google.cast.tp.connection";
+const NS_HEARTBEAT: &str = "urn:x-cast:com.google.cast.tp.heartbeat";
+const NS_RECEIVER: &str = "urn:x-cast:com.google.cast.receiver";
+const NS_MEDIA: &str = "urn:x-cast:com.google.cast.media";
+const SENDER: &str = "sender-0";
+const RECEIVER: &str = "receiver-0";
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const REPLY_TIMEOUT: Duration = Duration::from_secs(10);
+/// Launching the receiver app takes a few seconds on older devices.
+const LAUNCH_TIMEOUT: Duration = Duration::from_secs(20);
+const MAX_MESSAGE: usize = 64 * 1024;
+
+/// A Chromecast from its mDNS announcement.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Device {
+    pub id: String,
+    pub name: String,
+    pub model: Option<String>,
+    pub address: SocketAddr,
+}
+
+/// Chromecasts announcing themselves within `timeout`; blocks.
+pub fn discover(timeout: Duration) -> Result<Vec<Device>, String> {
+    let daemon = mdns_sd::ServiceDaemon::new().map_err(|e| format!("Failed to start mDNS: {}", e))?;
+    let receiver = daemon.browse(SERVICE_TYPE).map_err(|e| format!("Failed to browse {}: {}", SERVICE_TYPE, e))?;
+    let deadline = Instant::now() + timeout;
+    let mut found: Vec<Device> = Vec::new();
+    while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+        let Ok(event) = receiver.recv_timeout(remaining) else { break };
+        let mdns_sd::ServiceEvent::ServiceResolved(info) = event else { continue };
+        let addresses = info.get_addresses();
+        let Some(ip) = addresses.iter().find(|ip| ip.is_ipv4()).or(addresses.iter().next()) else { continue };
+        let id = info.get_property_val_str("id").unwrap_or(info.get_fullname()).to_string();
+        if found.iter().any(|device| device.id == id) {
+            continue;
+        }
+        let name = info.get_property_val_str("fn").unwrap_or("Chromecast").to_string();
+        let model = info.get_property_val_str("md").map(str::to_string);
+        found.push(Device { id, name, model, address: SocketAddr::new(*ip, info.get_port()) });
+    }
+    let _ = daemon.shutdown();
+    Ok(found)
+}
+
+// ---------------------------------------------------------------------------
+// CastMessage framing
+// ---------------------------------------------------------------------------
+
+/// A `CastMessage` with a string payload, the only kind used here.
+#[derive(Debug, Clone, PartialEq)]
+struct CastMessage {
+    source: String,
+    destination: String,
+    namespace: String,
+    payload: String,
+}
+
+fn put_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn put_string(out: &mut Vec<u8>, field: u8, text: &str) {
+    out.push((field << 3) | 2);
+    put_varint(out, text.len() as u64);
+    out.extend_from_slice(text.as_bytes());
+}
+
+fn take_varint(bytes: &[u8], at: &mut usize) -> Option<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *bytes.get(*at)?;
+        *at += 1;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+impl CastMessage {
+    /// Length prefix and message.
+    fn encode(&self) -> Vec<u8> {
+        let mut body = Vec::with_capacity(64 + self.payload.len());
+        // protocol_version CASTV2_1_0
+        body.extend_from_slice(&[1 << 3, 0]);
+        put_string(&mut body, 2, &self.source);
+        put_string(&mut body, 3, &self.destination);
+        put_string(&mut body, 4, &self.namespace);
+        // payload_type STRING
+        body.extend_from_slice(&[5 << 3, 0]);
+        put_string(&mut body, 6, &self.payload);
+        let mut framed = (body.len() as u32).to_be_bytes().to_vec();
+        framed.extend_from_slice(&body);
+        framed
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        let mut message = Self { source: String::new(), destination: String::new(), namespace: String::new(), payload: String::new() };
+        let mut at = 0;
+        while at < bytes.len() {
+            let key = take_varint(bytes, &mut at)?;
+            match key & 7 {
+                0 => {
+                    take_varint(bytes, &mut at)?;
+                }
+                2 => {
+                    let len = take_varint(bytes, &mut at)? as usize;
+                    let value = bytes.get(at..at.checked_add(len)?)?;
+                    at += len;
+                    let text = || String::from_utf8_lossy(value).into_owned();
+                    match key >> 3 {
+                        2 => message.source = text(),
+                        3 => message.destination = text(),
+                        4 => message.namespace = text(),
+                        6 => message.payload = text(),
+                        // Binary payloads: nothing here sends for one
+                        _ => {}
+                    }
+                }
+                _ => return None,
+            }
+        }
+        Some(message)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Session
+// ---------------------------------------------------------------------------
+
+#[derive(Debug)]
+struct AnyCertificate(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for AnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, tokio_rustls::rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, tokio_rustls::rustls::Error> {
+        tokio_rustls::rustls::crypto::verify_tls12_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, tokio_rustls::rustls::Error> {
+        tokio_rustls::rustls::crypto::verify_tls13_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+async fn read_frames(mut reader: ReadHalf<TlsStream<TcpStream>>, incoming: mpsc::Sender<CastMessage>) {
+    loop {
+        let mut len = [0u8; 4];
+        if reader.read_exact(&mut len).await.is_err() {
+            break;
+        }
+        let len = u32::from_be_bytes(len) as usize;
+        if len > MAX_MESSAGE {
+            tracing::warn!("[cast] Dropped a {} byte message from the Chromecast", len);
+            break;
+        }
+        let mut body = vec![0u8; len];
+        if reader.read_exact(&mut body).await.is_err() {
+            break;
+        }
+        if let Some(message) = CastMessage::decode(&body) {
+            if incoming.send(message).await.is_err() {
+                break;
+            }
+        }
+    }
+}
+
+/// A running Default Media Receiver on a Chromecast, and the media loaded
+/// on it.
+pub struct Session {
+    name: String,
+    writer: WriteHalf<TlsStream<TcpStream>>,
+    incoming: mpsc::Receiver<CastMessage>,
+    request_id: u64,
+    /// The receiver app's session and transport (its connection id).
+    app_session: String,
+    transport: String,
+    media_session: Option<u64>,
+}
+
+impl Session {
+    /// Connect to `device` and launch the media receiver on it.
+    pub async fn open(device: &Device) -> Result<Self, String> {
+        let provider = Arc::new(ring::default_provider());
+        let config = ClientConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(|e| e.to_string())?
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(AnyCertificate(provider)))
+            .with_no_client_auth();
+        let tcp = tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(device.address))
+            .await
+            .map_err(|_| format!("Timed out connecting to {}", device.name))?
+            .map_err(|e| format!("Cannot reach {}: {}", device.name, e))?;
+        let _ = tcp.set_nodelay(true);
+        let tls = TlsConnector::from(Arc::new(config))
+            .connect(ServerName::IpAddress(device.address.ip().into()), tcp)
+            .await
+            .map_err(|e| format!("TLS with {} failed: {}", device.name, e))?;
+        let (reader, writer) = tokio::io::split(tls);
+        let (tx, incoming) = mpsc::channel(32);
+        tauri::async_runtime::spawn(read_frames(reader, tx));
+        let mut session = Self {
+            name: device.name.clone(),
+            writer,
+            incoming,
+            request_id: 0,
+            app_session: String::new(),
+            transport: String::new(),
+            media_session: None,
+        };
+
+        session.send(RECEIVER, NS_CONNECTION, json!({ "type": "CONNECT" })).await?;
+        let status = session.request(RECEIVER, NS_RECEIVER, json!({ "type": "LAUNCH", "appId": MEDIA_RECEIVER }), LAUNCH_TIMEOUT).await?;
+        let app = status["status"]["applications"]
+            .as_array()
+            .and_then(|apps| apps.iter().find(|app| app["appId"] == MEDIA_RECEIVER))
+            .ok_or_else(|| format!("{} did not start the media receiver", device.name))?;
+        session.app_session = app["sessionId"].as_str().unwrap_or_default().to_string();
+        session.transport = app["transportId"].as_str().unwrap_or_default().to_string();
+        let transport = session.transport.clone();
+        session.send(&transport, NS_CONNECTION, json!({ "type": "CONNECT" })).await?;
+        tracing::info!("[cast] Media receiver running on {}", device.name);
+        Ok(session)
+    }
+
+    async fn send(&mut self, destination: &str, namespace: &str, payload: Value) -> Result<(), String> {
+        let message = CastMessage {
+            source: SENDER.to_string(),
+            destination: destination.to_string(),
+            namespace: namespace.to_string(),
+            payload: payload.to_string(),
+        };
+        self.writer.write_all(&message.encode()).await.map_err(|e| format!("Lost {}: {}", self.name, e))
+    }
+
+    /// Note the media session from a status; the parsed payload.
+    fn settle(&mut self, message: &CastMessage) -> Option<Value> {
+        let payload: Value = serde_json::from_str(&message.payload).ok()?;
+        if message.namespace == NS_MEDIA {
+            if let Some(id) = payload["status"][0]["mediaSessionId"].as_u64() {
+                self.media_session = Some(id);
+            }
+        }
+        Some(payload)
+    }
+
+    /// Send with a fresh `requestId` and wait for the reply carrying it.
+    async fn request(&mut self, destination: &str, namespace: &str, mut payload: Value, timeout: Duration) -> Result<Value, String> {
+        self.request_id += 1;
+        let id = self.request_id;
+        payload["requestId"] = json!(id);
+        self.send(destination, namespace, payload).await?;
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let received = tokio::time::timeout_at(deadline, self.incoming.recv())
+                .await
+                .map_err(|_| format!("{} did not answer", self.name))?
+                .ok_or_else(|| format!("{} closed the connection", self.name))?;
+            if self.pong(&received).await? {
+                continue;
+            }
+            let Some(reply) = self.settle(&received) else { continue };
+            if reply["requestId"].as_u64() != Some(id) {
+                continue;
+            }
+            match reply["type"].as_str() {
+                Some("LOAD_FAILED" | "LOAD_CANCELLED" | "INVALID_REQUEST" | "LAUNCH_ERROR") => {
+                    return Err(format!("{} refused: {}", self.name, reply["reason"].as_str().unwrap_or("no reason given")));
+                }
+                _ => return Ok(reply),
+            }
+        }
+    }
+
+    /// Answer a heartbeat; `true` when `message` was one.
+    async fn pong(&mut self, message: &CastMessage) -> Result<bool, String> {
+        if message.namespace != NS_HEARTBEAT {
+            return Ok(false);
+        }
+        if message.payload.contains("\"PING\"") {
+            let destination = message.source.clone();
+            self.send(&destination, NS_HEARTBEAT, json!({ "type": "PONG" })).await?;
+        }
+        Ok(true)
+    }
+
+    /// Handle what the device sent meanwhile: heartbeats, status, the
+    /// receiver being closed from the TV remote.
+    pub async fn poll(&mut self) -> Result<(), String> {
+        loop {
+            let received = match self.incoming.try_recv() {
+                Ok(received) => received,
+                Err(mpsc::error::TryRecvError::Empty) => return Ok(()),
+                Err(mpsc::error::TryRecvError::Disconnected) => return Err(format!("{} closed the connection", self.name)),
+            };
+            if self.pong(&received).await? {
+                continue;
+            }
+            if received.namespace == NS_CONNECTION && received.payload.contains("\"CLOSE\"") {
+                return Err(format!("The media receiver on {} was closed", self.name));
+            }
+            self.settle(&received);
+        }
+    }
+
+    /// Play `url` from `position_ms`, or cue it up paused.
+    pub async fn load(&mut self, url: &str, mime: &str, title: &str, position_ms: u64, playing: bool) -> Result<(), String> {
+        let transport = self.transport.clone();
+        let load = json!({
+            "type": "LOAD",
+            "media": {
+                "contentId": url,
+                "contentType": mime,
+                "streamType": "BUFFERED",
+                "metadata": { "metadataType": 0, "title": title },
+            },
+            "autoplay": playing,
+            "currentTime": position_ms as f64 / 1000.0,
+        });
+        self.request(&transport, NS_MEDIA, load, REPLY_TIMEOUT).await?;
+        Ok(())
+    }
+
+    async fn media_request(&mut self, kind: &str, extra: Value) -> Result<(), String> {
+        let Some(media_session) = self.media_session else { return Ok(()) };
+        let transport = self.transport.clone();
+        let mut payload = json!({ "type": kind, "mediaSessionId": media_session });
+        if let (Some(payload), Value::Object(extra)) = (payload.as_object_mut(), extra) {
+            payload.extend(extra);
+        }
+        self.request(&transport, NS_MEDIA, payload, REPLY_TIMEOUT).await?;
+        Ok(())
+    }
+
+    pub async fn seek(&mut self, position_ms: u64) -> Result<(), String> {
+        self.media_request("SEEK", json!({ "currentTime": position_ms as f64 / 1000.0 })).await
+    }
+
+    pub async fn set_paused(&mut self, paused: bool) -> Result<(), String> {
+        self.media_request(if paused { "PAUSE" } else { "PLAY" }, Value::Null).await
+    }
+
+    /// Quit the receiver app, back to the TV's idle screen.
+    pub async fn stop(mut self) {
+        let stop = json!({ "type": "STOP", "sessionId": self.app_session });
+        if let Err(e) = self.request(RECEIVER, NS_RECEIVER, stop, REPLY_TIMEOUT).await {
+            tracing::debug!("[cast] {}", e);
+        }
+        let _ = self.send(RECEIVER, NS_CONNECTION, json!({ "type": "CLOSE" })).await;
+        let _ = self.writer.shutdown().await;
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_cast_messages() {
+        let message = CastMessage {
+            source: SENDER.to_string(),
+            destination: RECEIVER.to_string(),
+            namespace: NS_HEARTBEAT.to_string(),
+            payload: r#"{"type":"PING"}"#.to_string(),
+        };
+        let framed = message.encode();
+        let len = u32::from_be_bytes(framed[..4].try_into().unwrap()) as usize;
+        assert_eq!(len, framed.len() - 4);
+        assert_eq!(&framed[4..8], &[0x08, 0x00, 0x12, 0x08]);
+        assert_eq!(CastMessage::decode(&framed[4..]), Some(message));
+
+        let mut long = Vec::new();
+        put_varint(&mut long, 300);
+        assert_eq!(long, vec![0xac, 0x02]);
+        assert_eq!(take_varint(&long, &mut 0), Some(300));
+        assert_eq!(CastMessage::decode(&[0x12, 0x05, b'a']), None);
+    }
+}
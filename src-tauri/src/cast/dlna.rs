@@ -0,0 +1,224 @@
+//! DLNA / UPnP AV media renderers: found by SSDP, driven over SOAP on
+//! their `AVTransport` service.
+
+use std::time::{Duration, Instant};
+
+use tokio::net::UdpSocket;
+
+use super::stream::DLNA_FEATURES;
+
+const SSDP_ADDR: &str = "239.255.255.250:1900";
+const RENDERER: &str = "urn:schemas-upnp-org:device:MediaRenderer:1";
+const AV_TRANSPORT: &str = "urn:schemas-upnp-org:service:AVTransport:1";
+const DESCRIPTION_TIMEOUT: Duration = Duration::from_secs(3);
+const SOAP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A renderer from its device description.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Renderer {
+    pub udn: String,
+    pub name: String,
+    pub model: Option<String>,
+    /// Absolute URL of its `AVTransport` control endpoint.
+    pub control_url: String,
+}
+
+/// Text of the first `<tag>` in `xml`, namespace prefixes ignored.
+fn element<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    let mut rest = xml;
+    loop {
+        let open = rest.find('<')?;
+        rest = &rest[open + 1..];
+        let end = rest.find('>')?;
+        let name = rest[..end].split_whitespace().next().unwrap_or("");
+        let local = name.rsplit(':').next().unwrap_or(name);
+        if local == tag && !rest[..end].ends_with('/') {
+            let body = &rest[end + 1..];
+            let close = body.find("</")?;
+            return Some(body[..close].trim());
+        }
+        rest = &rest[end + 1..];
+    }
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"").replace("&apos;", "'").replace("&amp;", "&")
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;").replace('\'', "&apos;")
+}
+
+/// The renderer a device description at `location` describes; `None` for
+/// devices without `AVTransport`.
+fn parse_description(xml: &str, location: &str) -> Option<Renderer> {
+    let udn = element(xml, "UDN")?.to_string();
+    let name = unescape(element(xml, "friendlyName").unwrap_or("Media renderer"));
+    let model = element(xml, "modelName").map(unescape);
+    let control = xml.split("<service>").skip(1).find(|service| element(service, "serviceType") == Some(AV_TRANSPORT))?;
+    let control = unescape(element(control, "controlURL")?);
+    let base = element(xml, "URLBase").map(unescape).unwrap_or_else(|| location.to_string());
+    let control_url = reqwest::Url::parse(&base).ok()?.join(&control).ok()?.to_string();
+    Some(Renderer { udn, name, model, control_url })
+}
+
+/// `LOCATION` of an SSDP response.
+fn location(response: &str) -> Option<&str> {
+    response
+        .lines()
+        .find_map(|line| line.split_once(':').filter(|(name, _)| name.trim().eq_ignore_ascii_case("location")))
+        .map(|(_, value)| value.trim())
+}
+
+/// Media renderers answering an SSDP search within `timeout`.
+pub async fn discover(timeout: Duration) -> Result<Vec<Renderer>, String> {
+    let socket = UdpSocket::bind("0.0.0.0:0").await.map_err(|e| format!("Failed to open an SSDP socket: {}", e))?;
+    let search = format!("M-SEARCH * HTTP/1.1\r\nHOST: {}\r\nMAN: \"ssdp:discover\"\r\nMX: 2\r\nST: {}\r\n\r\n", SSDP_ADDR, RENDERER);
+    socket.send_to(search.as_bytes(), SSDP_ADDR).await.map_err(|e| format!("SSDP search failed: {}", e))?;
+
+    let deadline = Instant::now() + timeout;
+    let mut locations = Vec::new();
+    let mut buf = [0u8; 2048];
+    while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+        let Ok(Ok((n, _))) = tokio::time::timeout(remaining, socket.recv_from(&mut buf)).await else { break };
+        let response = String::from_utf8_lossy(&buf[..n]);
+        if let Some(location) = location(&response).map(str::to_string).filter(|l| !locations.contains(l)) {
+            locations.push(location);
+        }
+    }
+
+    let client = reqwest::Client::builder().timeout(DESCRIPTION_TIMEOUT).build().map_err(|e| e.to_string())?;
+    let mut renderers: Vec<Renderer> = Vec::new();
+    for location in locations {
+        let xml = match client.get(&location).send().await.and_then(|r| r.error_for_status()) {
+            Ok(response) => response.text().await.unwrap_or_default(),
+            Err(e) => {
+                tracing::debug!("[cast] No description at {}: {}", location, e);
+                continue;
+            }
+        };
+        match parse_description(&xml, &location) {
+            Some(renderer) if !renderers.iter().any(|r| r.udn == renderer.udn) => renderers.push(renderer),
+            _ => {}
+        }
+    }
+    Ok(renderers)
+}
+
+/// `hh:mm:ss`, as UPnP writes times.
+fn clock_time(ms: u64) -> String {
+    let secs = ms / 1000;
+    format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+/// DIDL-Lite metadata for the item; several TVs refuse a URI without it.
+fn metadata(url: &str, mime: &str, title: &str) -> String {
+    let class = if mime.starts_with("video/") { "object.item.videoItem" } else { "object.item.audioItem.musicTrack" };
+    format!(
+        r#"<DIDL-Lite xmlns="urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:upnp="urn:schemas-upnp-org:metadata-1-0/upnp/"><item id="0" parentID="-1" restricted="1"><dc:title>{}</dc:title><upnp:class>{}</upnp:class><res protocolInfo="http-get:*:{}:{}">{}</res></item></DIDL-Lite>"#,
+        escape(title),
+        class,
+        mime,
+        DLNA_FEATURES,
+        escape(url)
+    )
+}
+
+fn envelope(action: &str, args: &[(&str, String)]) -> String {
+    let args: String = args.iter().map(|(name, value)| format!("<{0}>{1}</{0}>", name, escape(value))).collect();
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?><s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/"><s:Body><u:{0} xmlns:u="{1}"><InstanceID>0</InstanceID>{2}</u:{0}></s:Body></s:Envelope>"#,
+        action, AV_TRANSPORT, args
+    )
+}
+
+impl Renderer {
+    async fn action(&self, action: &str, args: &[(&str, String)]) -> Result<(), String> {
+        let response = reqwest::Client::new()
+            .post(&self.control_url)
+            .timeout(SOAP_TIMEOUT)
+            .header("Content-Type", "text/xml; charset=\"utf-8\"")
+            .header("SOAPAction", format!("\"{}#{}\"", AV_TRANSPORT, action))
+            .body(envelope(action, args))
+            .send()
+            .await
+            .map_err(|e| format!("{} did not answer: {}", self.name, e))?;
+        if !response.status().is_success() {
+            let body = response.text().await.unwrap_or_default();
+            let reason = element(&body, "errorDescription").or(element(&body, "faultstring")).unwrap_or("refused");
+            return Err(format!("{} refused {}: {}", self.name, action, reason));
+        }
+        Ok(())
+    }
+
+    /// Play `url` from `position_ms`, or cue it up paused.
+    pub async fn load(&self, url: &str, mime: &str, title: &str, position_ms: u64, playing: bool) -> Result<(), String> {
+        let _ = self.action("Stop", &[]).await;
+        self.action("SetAVTransportURI", &[("CurrentURI", url.to_string()), ("CurrentURIMetaData", metadata(url, mime, title))])
+            .await?;
+        self.action("Play", &[("Speed", "1".to_string())]).await?;
+        if position_ms >= 1000 {
+            // Renderers that cannot seek yet still play from the top
+            if let Err(e) = self.seek(position_ms).await {
+                tracing::debug!("[cast] {}", e);
+            }
+        }
+        if !playing {
+            self.set_paused(true).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn seek(&self, position_ms: u64) -> Result<(), String> {
+        self.action("Seek", &[("Unit", "REL_TIME".to_string()), ("Target", clock_time(position_ms))]).await
+    }
+
+    pub async fn set_paused(&self, paused: bool) -> Result<(), String> {
+        if paused {
+            self.action("Pause", &[]).await
+        } else {
+            self.action("Play", &[("Speed", "1".to_string())]).await
+        }
+    }
+
+    pub async fn stop(&self) -> Result<(), String> {
+        self.action("Stop", &[]).await
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_device_descriptions_and_writes_soap() {
+        let xml = r#"<?xml version="1.0"?>
+<root xmlns="urn:schemas-upnp-org:device-1-0">
+  <device>
+    <deviceType>urn:schemas-upnp-org:device:MediaRenderer:1</deviceType>
+    <friendlyName>Living Room TV &amp; Bar</friendlyName>
+    <modelName>UE55</modelName>
+    <UDN>uuid:1234</UDN>
+    <serviceList>
+      <service><serviceType>urn:schemas-upnp-org:service:RenderingControl:1</serviceType><controlURL>/rc</controlURL></service>
+      <service><serviceType>urn:schemas-upnp-org:service:AVTransport:1</serviceType><controlURL>upnp/control/AVTransport1</controlURL></service>
+    </serviceList>
+  </device>
+</root>"#;
+        let renderer = parse_description(xml, "http://192.168.1.40:9197/dmr/desc.xml").unwrap();
+        assert_eq!(renderer.name, "Living Room TV & Bar");
+        assert_eq!(renderer.udn, "uuid:1234");
+        assert_eq!(renderer.control_url, "http://192.168.1.40:9197/dmr/upnp/control/AVTransport1");
+        assert_eq!(parse_description("<root><UDN>uuid:9</UDN></root>", "http://x/"), None);
+
+        let response = "HTTP/1.1 200 OK\r\nCACHE-CONTROL: max-age=1800\r\nLocation: http://192.168.1.40:9197/dmr\r\n\r\n";
+        assert_eq!(location(response), Some("http://192.168.1.40:9197/dmr"));
+        assert_eq!(clock_time(3_723_500), "01:02:03");
+        let soap = envelope("Seek", &[("Target", "<0>".to_string())]);
+        assert!(soap.contains("<u:Seek xmlns:u=\"urn:schemas-upnp-org:service:AVTransport:1\"><InstanceID>0</InstanceID><Target>&lt;0&gt;</Target></u:Seek>"));
+    }
+}
@@ -0,0 +1,454 @@
+//! Casting the player output to a TV: Chromecast and DLNA renderers on the
+//! LAN, for living rooms without an HDMI run to the screen.
+//!
+//! `list_cast_targets` looks for both for `DISCOVERY_TIMEOUT` — Chromecasts
+//! by mDNS, DLNA media renderers by SSDP — and `cast_to` sends the song on
+//! the native player to one of them. The renderer fetches the file from
+//! `stream`, a small HTTP server of our own, and plays it itself; the
+//! session then follows the native player: a new song is loaded on the TV,
+//! pause and resume go along, and a seek is repeated there. The local
+//! player keeps running, so scoring and lyrics stay in time with what the
+//! KJ hears; route its output to nothing for a TV-only room.
+//!
+//! Some files cannot be cast (CDG graphics, KAR): those songs are skipped
+//! with a warning. Session changes are published as `cast://status`.
+
+mod chromecast;
+mod dlna;
+mod stream;
+
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+use tokio::time::MissedTickBehavior;
+use tokio_util::sync::CancellationToken;
+
+use crate::access::{require_webview, Capability};
+use crate::audio::commands::AudioState;
+use crate::events::{publish, AppEvent};
+use crate::runtime::TaskSupervisor;
+use stream::{media_type, MediaServer};
+
+pub const STATUS_EVENT: &str = "cast://status";
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(3);
+/// The native player is checked this often while casting.
+const POLL: Duration = Duration::from_millis(500);
+/// A position this far off the extrapolated one is a seek.
+const JUMP_MS: i64 = 1_500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CastKind {
+    Chromecast,
+    Dlna,
+}
+
+/// A TV or speaker `cast_to` can send to.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CastTarget {
+    /// `chromecast:<device id>` or `dlna:<UDN>`.
+    pub id: String,
+    pub name: String,
+    pub kind: CastKind,
+    pub model: Option<String>,
+}
+
+/// Payload of `cast://status`, and what `cast_status` reports.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CastStatus {
+    /// Cast to right now.
+    pub target: Option<CastTarget>,
+    /// The song playing there.
+    pub track: Option<String>,
+    /// Why the last session ended, when it was not stopped.
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+enum Endpoint {
+    Chromecast(chromecast::Device),
+    Dlna(dlna::Renderer),
+}
+
+impl Endpoint {
+    /// The renderer's address, to pick the interface it reaches us on.
+    fn ip(&self) -> Option<IpAddr> {
+        match self {
+            Self::Chromecast(device) => Some(device.address.ip()),
+            Self::Dlna(renderer) => {
+                let url = reqwest::Url::parse(&renderer.control_url).ok()?;
+                url.host_str()?.trim_start_matches('[').trim_end_matches(']').parse().ok()
+            }
+        }
+    }
+}
+
+/// Managed state: the targets last found, the session and its server.
+#[derive(Default)]
+pub struct CastState {
+    targets: Mutex<Vec<(CastTarget, Endpoint)>>,
+    /// The running session: its number, target id and token.
+    session: Mutex<Option<(u64, String, CancellationToken)>>,
+    sessions: AtomicU64,
+    server: tokio::sync::Mutex<Option<Arc<MediaServer>>>,
+    status: Mutex<CastStatus>,
+}
+
+impl CastState {
+    fn set_status(&self, app: &AppHandle, update: impl FnOnce(&mut CastStatus)) {
+        let status = {
+            let mut status = self.status.lock().unwrap_or_else(|e| e.into_inner());
+            update(&mut status);
+            status.clone()
+        };
+        publish(app, AppEvent::CastStatus(status));
+    }
+}
+
+/// This machine's address on the route to `target`.
+fn local_ip_for(target: IpAddr) -> Option<IpAddr> {
+    let bind = if target.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+    let socket = std::net::UdpSocket::bind(bind).ok()?;
+    socket.connect((target, 9)).ok()?;
+    Some(socket.local_addr().ok()?.ip())
+}
+
+// ---------------------------------------------------------------------------
+// Session
+// ---------------------------------------------------------------------------
+
+enum Renderer {
+    Chromecast(chromecast::Session),
+    Dlna(dlna::Renderer),
+}
+
+impl Renderer {
+    async fn open(endpoint: &Endpoint) -> Result<Self, String> {
+        Ok(match endpoint {
+            Endpoint::Chromecast(device) => Self::Chromecast(chromecast::Session::open(device).await?),
+            Endpoint::Dlna(renderer) => Self::Dlna(renderer.clone()),
+        })
+    }
+
+    async fn load(&mut self, url: &str, mime: &str, title: &str, position_ms: u64, playing: bool) -> Result<(), String> {
+        match self {
+            Self::Chromecast(session) => session.load(url, mime, title, position_ms, playing).await,
+            Self::Dlna(renderer) => renderer.load(url, mime, title, position_ms, playing).await,
+        }
+    }
+
+    async fn set_paused(&mut self, paused: bool) -> Result<(), String> {
+        match self {
+            Self::Chromecast(session) => session.set_paused(paused).await,
+            Self::Dlna(renderer) => renderer.set_paused(paused).await,
+        }
+    }
+
+    async fn seek(&mut self, position_ms: u64) -> Result<(), String> {
+        match self {
+            Self::Chromecast(session) => session.seek(position_ms).await,
+            Self::Dlna(renderer) => renderer.seek(position_ms).await,
+        }
+    }
+
+    /// Whether the device is still there to cast to.
+    async fn poll(&mut self) -> Result<(), String> {
+        match self {
+            Self::Chromecast(session) => session.poll().await,
+            Self::Dlna(_) => Ok(()),
+        }
+    }
+
+    async fn stop(self) {
+        match self {
+            Self::Chromecast(session) => session.stop().await,
+            Self::Dlna(renderer) => {
+                if let Err(e) = renderer.stop().await {
+                    tracing::debug!("[cast] {}", e);
+                }
+            }
+        }
+    }
+}
+
+/// What the renderer is told to match the native player.
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    Load { path: PathBuf, position_ms: u64, playing: bool },
+    Pause,
+    Resume,
+    Seek { position_ms: u64 },
+    Nothing,
+}
+
+/// The native player as last passed on.
+#[derive(Debug, Default)]
+struct Follow {
+    track: Option<String>,
+    playing: bool,
+    position_ms: u64,
+    at: Option<Instant>,
+}
+
+impl Follow {
+    fn next(&mut self, track: Option<String>, playing: bool, position_ms: u64, now: Instant) -> Step {
+        let expected = match self.at {
+            Some(at) if self.playing => self.position_ms + now.duration_since(at).as_millis() as u64,
+            _ => self.position_ms,
+        };
+        let (was_playing, previous) = (self.playing, std::mem::replace(&mut self.track, track.clone()));
+        (self.playing, self.position_ms, self.at) = (playing, position_ms, Some(now));
+        if track != previous {
+            return match track {
+                Some(track) => Step::Load { path: PathBuf::from(track), position_ms, playing },
+                // Stopped: keep the picture, nothing plays
+                None if was_playing => Step::Pause,
+                None => Step::Nothing,
+            };
+        }
+        if track.is_none() {
+            return Step::Nothing;
+        }
+        if (position_ms as i64 - expected as i64).abs() > JUMP_MS {
+            return Step::Seek { position_ms };
+        }
+        match (was_playing, playing) {
+            (true, false) => Step::Pause,
+            (false, true) => Step::Resume,
+            _ => Step::Nothing,
+        }
+    }
+}
+
+fn title_of(path: &Path) -> String {
+    path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+/// Follow the native player on `renderer` until `token` fires or the
+/// device goes away.
+async fn run(app: &AppHandle, renderer: &mut Renderer, server: &MediaServer, host: IpAddr, token: &CancellationToken) -> Result<(), String> {
+    let state = app.state::<CastState>();
+    let mut follow = Follow::default();
+    let mut seq = 0;
+    let mut ticks = tokio::time::interval(POLL);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    loop {
+        tokio::select! {
+            _ = token.cancelled() => return Ok(()),
+            _ = ticks.tick() => {}
+        }
+        renderer.poll().await?;
+        let (track, position) = {
+            let audio = app.state::<AudioState>();
+            (audio.current_path(), audio.position())
+        };
+        let step = follow.next(track, position.is_playing, position.position_ms, Instant::now());
+        let done = match step {
+            Step::Nothing => continue,
+            Step::Pause => renderer.set_paused(true).await,
+            Step::Resume => renderer.set_paused(false).await,
+            Step::Seek { position_ms } => renderer.seek(position_ms).await,
+            Step::Load { path, position_ms, playing } => {
+                let Some(mime) = media_type(&path) else {
+                    tracing::warn!("[cast] {} cannot be cast", path.display());
+                    server.serve(None, 0);
+                    state.set_status(app, |status| status.track = None);
+                    continue;
+                };
+                seq += 1;
+                let title = title_of(&path);
+                let url_path = server.serve(Some(path.clone()), seq).unwrap_or_default();
+                let url = format!("http://{}{}", SocketAddr::new(host, server.port), url_path);
+                tracing::info!("[cast] Casting {}", path.display());
+                state.set_status(app, |status| status.track = Some(path.to_string_lossy().into_owned()));
+                renderer.load(&url, mime, &title, position_ms, playing).await
+            }
+        };
+        if let Err(e) = done {
+            tracing::warn!("[cast] {}", e);
+        }
+    }
+}
+
+async fn media_server(app: &AppHandle) -> Result<Arc<MediaServer>, String> {
+    let state = app.state::<CastState>();
+    let mut server = state.server.lock().await;
+    if let Some(server) = server.as_ref() {
+        return Ok(server.clone());
+    }
+    let started = Arc::new(MediaServer::start(app.state::<TaskSupervisor>().token()).await?);
+    *server = Some(started.clone());
+    Ok(started)
+}
+
+fn stop_session(state: &CastState) {
+    if let Some((_, _, token)) = state.session.lock().unwrap_or_else(|e| e.into_inner()).take() {
+        token.cancel();
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+/// Chromecasts and DLNA renderers on the LAN; takes `DISCOVERY_TIMEOUT`.
+#[tauri::command]
+pub async fn list_cast_targets(app: AppHandle, webview: tauri::Webview) -> Result<Vec<CastTarget>, String> {
+    require_webview(&webview, Capability::ConfigureAudio)?;
+    let chromecasts = tauri::async_runtime::spawn_blocking(|| chromecast::discover(DISCOVERY_TIMEOUT));
+    let renderers = dlna::discover(DISCOVERY_TIMEOUT).await.unwrap_or_else(|e| {
+        tracing::warn!("[cast] {}", e);
+        Vec::new()
+    });
+    let chromecasts = chromecasts.await.map_err(|e| e.to_string())?.unwrap_or_else(|e| {
+        tracing::warn!("[cast] {}", e);
+        Vec::new()
+    });
+
+    let mut found = Vec::new();
+    for device in chromecasts {
+        let target = CastTarget {
+            id: format!("chromecast:{}", device.id),
+            name: device.name.clone(),
+            kind: CastKind::Chromecast,
+            model: device.model.clone(),
+        };
+        found.push((target, Endpoint::Chromecast(device)));
+    }
+    for renderer in renderers {
+        let target = CastTarget {
+            id: format!("dlna:{}", renderer.udn),
+            name: renderer.name.clone(),
+            kind: CastKind::Dlna,
+            model: renderer.model.clone(),
+        };
+        found.push((target, Endpoint::Dlna(renderer)));
+    }
+    // Chromecast TVs often answer SSDP too; the Cast protocol does more
+    let mut targets: Vec<(CastTarget, Endpoint)> = Vec::new();
+    for (target, endpoint) in found {
+        let same_device = targets
+            .iter()
+            .any(|(_, known)| matches!(known, Endpoint::Chromecast(_)) && known.ip().is_some() && known.ip() == endpoint.ip());
+        if !same_device {
+            targets.push((target, endpoint));
+        }
+    }
+    tracing::info!("[cast] Found {} cast target(s)", targets.len());
+    let listed = targets.iter().map(|(target, _)| target.clone()).collect();
+    *app.state::<CastState>().targets.lock().unwrap_or_else(|e| e.into_inner()) = targets;
+    Ok(listed)
+}
+
+/// Cast the native player to `target_id` (from `list_cast_targets`),
+/// ending a cast already running.
+#[tauri::command]
+pub async fn cast_to(app: AppHandle, webview: tauri::Webview, target_id: String) -> Result<CastStatus, String> {
+    require_webview(&webview, Capability::ConfigureAudio)?;
+    crate::telemetry::feature(&app, "cast");
+    let state = app.state::<CastState>();
+    let (target, endpoint) = state
+        .targets
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .find(|(target, _)| target.id == target_id)
+        .cloned()
+        .ok_or_else(|| format!("Unknown cast target '{}'; look for targets again", target_id))?;
+    stop_session(&state);
+    let host = endpoint
+        .ip()
+        .and_then(local_ip_for)
+        .or_else(crate::server::discovery::route_ip)
+        .ok_or("No network interface reaches the cast target")?;
+    let server = media_server(&app).await?;
+    let mut renderer = Renderer::open(&endpoint).await?;
+    tracing::info!("[cast] Casting to {}", target.name);
+
+    let token = app.state::<TaskSupervisor>().token();
+    let id = state.sessions.fetch_add(1, Ordering::Relaxed);
+    *state.session.lock().unwrap_or_else(|e| e.into_inner()) = Some((id, target.id.clone(), token.clone()));
+    let target_id = target.id.clone();
+    state.set_status(&app, |status| *status = CastStatus { target: Some(target), ..Default::default() });
+    let started = state.status.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    let handle = app.clone();
+    app.state::<TaskSupervisor>().spawn("cast", move |_| async move {
+        let result = run(&handle, &mut renderer, &server, host, &token).await;
+        let state = handle.state::<CastState>();
+        // A newer session serves its own song and reports for itself; on
+        // the same device, it has taken over the receiver too
+        let replaced_by = {
+            let mut running = state.session.lock().unwrap_or_else(|e| e.into_inner());
+            if running.as_ref().is_some_and(|(running, _, _)| *running == id) {
+                *running = None;
+            }
+            running.as_ref().map(|(_, target, _)| target.clone())
+        };
+        let replaced = replaced_by.is_some();
+        if !replaced {
+            server.serve(None, 0);
+        }
+        if replaced_by.as_deref() != Some(target_id.as_str()) {
+            renderer.stop().await;
+        }
+        if let Err(e) = &result {
+            tracing::warn!("[cast] {}", e);
+        }
+        if !replaced {
+            state.set_status(&handle, |status| *status = CastStatus { error: result.err(), ..Default::default() });
+        }
+    });
+    Ok(started)
+}
+
+/// End the cast; the TV goes back to its own screen.
+#[tauri::command]
+pub fn stop_cast(app: AppHandle, webview: tauri::Webview) -> Result<(), String> {
+    require_webview(&webview, Capability::ConfigureAudio)?;
+    stop_session(&app.state::<CastState>());
+    Ok(())
+}
+
+#[tauri::command]
+pub fn cast_status(app: AppHandle) -> CastStatus {
+    app.state::<CastState>().status.lock().map(|status| status.clone()).unwrap_or_default()
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follows_the_native_player() {
+        let now = Instant::now();
+        let at = |ms: u64| now + Duration::from_millis(ms);
+        let mut follow = Follow::default();
+        assert_eq!(follow.next(None, false, 0, now), Step::Nothing);
+        let song = Some("/music/Abba - SOS.mp4".to_string());
+        assert_eq!(
+            follow.next(song.clone(), true, 0, now),
+            Step::Load { path: PathBuf::from("/music/Abba - SOS.mp4"), position_ms: 0, playing: true }
+        );
+        assert_eq!(follow.next(song.clone(), true, 500, at(500)), Step::Nothing);
+        assert_eq!(follow.next(song.clone(), false, 1_000, at(1_000)), Step::Pause);
+        assert_eq!(follow.next(song.clone(), false, 1_000, at(5_000)), Step::Nothing);
+        assert_eq!(follow.next(song.clone(), true, 1_000, at(5_500)), Step::Resume);
+        // Seeked ahead locally
+        assert_eq!(follow.next(song.clone(), true, 60_000, at(6_000)), Step::Seek { position_ms: 60_000 });
+        assert_eq!(follow.next(None, false, 0, at(7_000)), Step::Pause);
+        assert_eq!(follow.next(None, false, 0, at(8_000)), Step::Nothing);
+
+        assert_eq!(title_of(Path::new("/music/Abba - SOS.mp4")), "Abba - SOS");
+    }
+}
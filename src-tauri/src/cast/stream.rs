@@ -0,0 +1,205 @@
+//! The HTTP side of casting: the song being cast, served to the renderer.
+//!
+//! A TV fetches the media itself, with range requests to seek. Nothing
+//! else is served: the only path answered is `/cast/<token>/…` for the file
+//! currently cast, with a token drawn once per run; a stopped cast answers
+//! `404`. One request per connection, which renderers cope with fine.
+
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_util::sync::CancellationToken;
+
+const MAX_HEADER: usize = 8 * 1024;
+const HEADER_TIMEOUT: Duration = Duration::from_secs(10);
+/// DLNA renderers refuse media without these (streaming, byte seeks).
+const DLNA_TRANSFER_MODE: &str = "Streaming";
+pub const DLNA_FEATURES: &str = "DLNA.ORG_OP=01;DLNA.ORG_CI=0;DLNA.ORG_FLAGS=01700000000000000000000000000000";
+
+/// MIME type renderers expect for a song file; `None` for what they cannot
+/// play (CDG graphics, KAR files).
+pub fn media_type(path: &Path) -> Option<&'static str> {
+    let ext = path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase)?;
+    Some(match ext.as_str() {
+        "mp3" => "audio/mpeg",
+        "m4a" | "aac" => "audio/mp4",
+        "flac" => "audio/flac",
+        "ogg" | "opus" => "audio/ogg",
+        "wav" => "audio/wav",
+        "mp4" | "m4v" => "video/mp4",
+        "webm" => "video/webm",
+        "mkv" => "video/x-matroska",
+        _ => return None,
+    })
+}
+
+/// A parsed `Range: bytes=…` against a file of `len` bytes; `Err` when it
+/// cannot be satisfied.
+fn byte_range(header: Option<&str>, len: u64) -> Result<Option<(u64, u64)>, ()> {
+    let Some(spec) = header.and_then(|h| h.trim().strip_prefix("bytes=")) else { return Ok(None) };
+    // Several ranges at once: real players never ask, answer the whole file
+    if spec.contains(',') {
+        return Ok(None);
+    }
+    let (start, end) = spec.split_once('-').ok_or(())?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let suffix = suffix.parse::<u64>().map_err(|_| ())?.min(len);
+            (len - suffix, len.checked_sub(1).ok_or(())?)
+        }
+        (start, "") => (start.parse().map_err(|_| ())?, len.checked_sub(1).ok_or(())?),
+        (start, end) => (start.parse().map_err(|_| ())?, end.parse::<u64>().map_err(|_| ())?.min(len.saturating_sub(1))),
+    };
+    if start > end || start >= len {
+        return Err(());
+    }
+    Ok(Some((start, end)))
+}
+
+/// The running server and the file it serves.
+pub struct MediaServer {
+    pub port: u16,
+    token: String,
+    current: Arc<Mutex<Option<PathBuf>>>,
+}
+
+impl MediaServer {
+    /// Listen on every interface until `cancel` fires.
+    pub async fn start(cancel: CancellationToken) -> Result<Self, String> {
+        let listener = TcpListener::bind(("0.0.0.0", 0))
+            .await
+            .map_err(|e| format!("Failed to open the cast media server: {}", e))?;
+        let port = listener.local_addr().map_err(|e| e.to_string())?.port();
+        let token = crate::server::security::generate_token();
+        let current = Arc::new(Mutex::new(None));
+        tracing::info!("[cast] Media server on port {}", port);
+        let (prefix, served) = (format!("/cast/{}/", token), current.clone());
+        tauri::async_runtime::spawn(async move {
+            loop {
+                let accepted = tokio::select! {
+                    _ = cancel.cancelled() => break,
+                    accepted = listener.accept() => accepted,
+                };
+                let Ok((stream, client)) = accepted else { continue };
+                let file = served.lock().unwrap_or_else(|e| e.into_inner()).clone();
+                let prefix = prefix.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = serve(stream, client, &prefix, file).await {
+                        tracing::debug!("[cast] {}: {}", client, e);
+                    }
+                });
+            }
+        });
+        Ok(Self { port, token, current })
+    }
+
+    /// Serve `path` from now on, or nothing; returns the path part of its
+    /// URL (a new one per song, so renderers do not replay a cached one).
+    pub fn serve(&self, path: Option<PathBuf>, seq: u64) -> Option<String> {
+        let ext = path.as_deref().and_then(|p| p.extension()).map(|e| e.to_string_lossy().to_ascii_lowercase());
+        *self.current.lock().unwrap_or_else(|e| e.into_inner()) = path;
+        ext.map(|ext| format!("/cast/{}/{}.{}", self.token, seq, ext))
+    }
+}
+
+async fn respond(stream: &mut TcpStream, status: &str, headers: &[(&str, String)]) -> std::io::Result<()> {
+    let mut head = format!("HTTP/1.1 {}\r\nConnection: close\r\n", status);
+    for (name, value) in headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes()).await
+}
+
+async fn serve(mut stream: TcpStream, client: SocketAddr, prefix: &str, file: Option<PathBuf>) -> Result<(), String> {
+    let mut reader = BufReader::new(&mut stream);
+    let mut lines = Vec::new();
+    let read_head = async {
+        let mut total = 0;
+        loop {
+            let mut line = String::new();
+            let n = reader.read_line(&mut line).await.map_err(|e| e.to_string())?;
+            total += n;
+            if n == 0 || total > MAX_HEADER {
+                return Err("Incomplete request".to_string());
+            }
+            let line = line.trim_end().to_string();
+            if line.is_empty() {
+                return Ok(());
+            }
+            lines.push(line);
+        }
+    };
+    tokio::time::timeout(HEADER_TIMEOUT, read_head).await.map_err(|_| "Request timed out".to_string())??;
+    drop(reader);
+
+    let mut request_line = lines.first().map(|l| l.split_whitespace()).ok_or("Empty request")?;
+    let (method, target) = (request_line.next().unwrap_or(""), request_line.next().unwrap_or(""));
+    let header =
+        |name: &str| lines[1..].iter().find_map(|l| l.split_once(':').filter(|(n, _)| n.trim().eq_ignore_ascii_case(name)).map(|(_, v)| v.trim()));
+    let head = method == "HEAD";
+    if !head && method != "GET" {
+        return respond(&mut stream, "405 Method Not Allowed", &[("Content-Length", "0".into())]).await.map_err(|e| e.to_string());
+    }
+    let file = file.filter(|_| target.starts_with(prefix));
+    let Some((path, mime)) = file.as_ref().and_then(|path| Some((path, media_type(path)?))) else {
+        return respond(&mut stream, "404 Not Found", &[("Content-Length", "0".into())]).await.map_err(|e| e.to_string());
+    };
+    let mut media = tokio::fs::File::open(crate::paths::long_path(path)).await.map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let len = media.metadata().await.map_err(|e| e.to_string())?.len();
+    let mut headers = vec![
+        ("Content-Type", mime.to_string()),
+        ("Accept-Ranges", "bytes".to_string()),
+        ("transferMode.dlna.org", DLNA_TRANSFER_MODE.to_string()),
+        ("contentFeatures.dlna.org", DLNA_FEATURES.to_string()),
+    ];
+    let (status, start, count) = match byte_range(header("range"), len) {
+        Ok(None) => ("200 OK", 0, len),
+        Ok(Some((start, end))) => {
+            headers.push(("Content-Range", format!("bytes {}-{}/{}", start, end, len)));
+            ("206 Partial Content", start, end - start + 1)
+        }
+        Err(()) => {
+            let headers = [("Content-Range", format!("bytes */{}", len)), ("Content-Length", "0".into())];
+            return respond(&mut stream, "416 Range Not Satisfiable", &headers).await.map_err(|e| e.to_string());
+        }
+    };
+    headers.push(("Content-Length", count.to_string()));
+    tracing::debug!("[cast] {} {} bytes {}+{}", client, method, start, count);
+    respond(&mut stream, status, &headers).await.map_err(|e| e.to_string())?;
+    if head {
+        return Ok(());
+    }
+    media.seek(std::io::SeekFrom::Start(start)).await.map_err(|e| e.to_string())?;
+    // A renderer closing the connection mid-song is how it seeks
+    let _ = tokio::io::copy(&mut media.take(count), &mut stream).await;
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_range_requests() {
+        assert_eq!(byte_range(None, 1000), Ok(None));
+        assert_eq!(byte_range(Some("bytes=0-"), 1000), Ok(Some((0, 999))));
+        assert_eq!(byte_range(Some("bytes=100-199"), 1000), Ok(Some((100, 199))));
+        assert_eq!(byte_range(Some("bytes=900-5000"), 1000), Ok(Some((900, 999))));
+        assert_eq!(byte_range(Some("bytes=-100"), 1000), Ok(Some((900, 999))));
+        assert_eq!(byte_range(Some("bytes=0-1,5-9"), 1000), Ok(None));
+        assert_eq!(byte_range(Some("bytes=1000-"), 1000), Err(()));
+        assert_eq!(byte_range(Some("bytes=abc"), 1000), Err(()));
+
+        assert_eq!(media_type(Path::new("/m/Song.MP4")), Some("video/mp4"));
+        assert_eq!(media_type(Path::new("/m/Song.cdg")), None);
+    }
+}
@@ -0,0 +1,282 @@
+//! CD+G subcode decoding.
+//!
+//! A `.cdg` file is a stream of 24-byte subcode packets, 300 per second of
+//! audio. Packets with command `0x09` carry graphics instructions that
+//! paint a 300×216 screen of 4-bit palette indices; everything else is
+//! padding. The visible area is the inner 288×192, which scroll offsets
+//! shift by up to one tile for smooth scrolling.
+
+pub const WIDTH: usize = 300;
+pub const HEIGHT: usize = 216;
+pub const PACKET_SIZE: usize = 24;
+pub const PACKETS_PER_SECOND: u64 = 300;
+
+const TILE_WIDTH: usize = 6;
+const TILE_HEIGHT: usize = 12;
+const COMMAND_MASK: u8 = 0x3F;
+const CDG_COMMAND: u8 = 0x09;
+
+const MEMORY_PRESET: u8 = 1;
+const BORDER_PRESET: u8 = 2;
+const TILE_BLOCK: u8 = 6;
+const SCROLL_PRESET: u8 = 20;
+const SCROLL_COPY: u8 = 24;
+const LOAD_COLORS_LOW: u8 = 30;
+const LOAD_COLORS_HIGH: u8 = 31;
+const TILE_BLOCK_XOR: u8 = 38;
+
+/// Screen state after some number of packets.
+#[derive(Clone)]
+pub struct Screen {
+    pixels: Vec<u8>,
+    /// RGBA per palette index.
+    palette: [[u8; 4]; 16],
+    border: u8,
+    h_offset: usize,
+    v_offset: usize,
+}
+
+impl Default for Screen {
+    fn default() -> Self {
+        Self {
+            pixels: vec![0; WIDTH * HEIGHT],
+            palette: [[0, 0, 0, 255]; 16],
+            border: 0,
+            h_offset: 0,
+            v_offset: 0,
+        }
+    }
+}
+
+impl Screen {
+    /// Apply one packet; returns whether the picture may have changed.
+    pub fn apply(&mut self, packet: &[u8]) -> bool {
+        if packet.len() < PACKET_SIZE || packet[0] & COMMAND_MASK != CDG_COMMAND {
+            return false;
+        }
+        let data = &packet[4..20];
+        match packet[1] & COMMAND_MASK {
+            MEMORY_PRESET => {
+                self.pixels.fill(data[0] & 0x0F);
+                self.h_offset = 0;
+                self.v_offset = 0;
+            }
+            BORDER_PRESET => self.border_preset(data[0] & 0x0F),
+            TILE_BLOCK => self.tile_block(data, false),
+            TILE_BLOCK_XOR => self.tile_block(data, true),
+            SCROLL_PRESET => self.scroll(data, Some(data[0] & 0x0F)),
+            SCROLL_COPY => self.scroll(data, None),
+            LOAD_COLORS_LOW => self.load_colors(data, 0),
+            LOAD_COLORS_HIGH => self.load_colors(data, 8),
+            // Transparency only matters when mixing with video; shown opaque
+            _ => return false,
+        }
+        true
+    }
+
+    fn border_preset(&mut self, color: u8) {
+        self.border = color;
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                if !(TILE_WIDTH..WIDTH - TILE_WIDTH).contains(&x) || !(TILE_HEIGHT..HEIGHT - TILE_HEIGHT).contains(&y) {
+                    self.pixels[y * WIDTH + x] = color;
+                }
+            }
+        }
+    }
+
+    fn tile_block(&mut self, data: &[u8], xor: bool) {
+        let (color0, color1) = (data[0] & 0x0F, data[1] & 0x0F);
+        let top = (data[2] & 0x1F) as usize * TILE_HEIGHT;
+        let left = (data[3] & 0x3F) as usize * TILE_WIDTH;
+        if top + TILE_HEIGHT > HEIGHT || left + TILE_WIDTH > WIDTH {
+            return;
+        }
+        for (row, bits) in data[4..16].iter().enumerate() {
+            for col in 0..TILE_WIDTH {
+                let on = bits & (0x20 >> col) != 0;
+                let color = if on { color1 } else { color0 };
+                let pixel = &mut self.pixels[(top + row) * WIDTH + left + col];
+                *pixel = if xor { (*pixel ^ color) & 0x0F } else { color };
+            }
+        }
+    }
+
+    /// Scroll by whole tiles, filling with `fill` (preset) or wrapping
+    /// (copy), and take the fine offsets.
+    fn scroll(&mut self, data: &[u8], fill: Option<u8>) {
+        let (h, v) = (data[1] & 0x3F, data[2] & 0x3F);
+        self.h_offset = ((h & 0x07) as usize).min(TILE_WIDTH - 1);
+        self.v_offset = ((v & 0x0F) as usize).min(TILE_HEIGHT - 1);
+        let dx: isize = match (h & 0x30) >> 4 {
+            1 => TILE_WIDTH as isize,
+            2 => -(TILE_WIDTH as isize),
+            _ => 0,
+        };
+        let dy: isize = match (v & 0x30) >> 4 {
+            1 => TILE_HEIGHT as isize,
+            2 => -(TILE_HEIGHT as isize),
+            _ => 0,
+        };
+        if dx == 0 && dy == 0 {
+            return;
+        }
+        let source = self.pixels.clone();
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                let sx = x as isize - dx;
+                let sy = y as isize - dy;
+                let inside = (0..WIDTH as isize).contains(&sx) && (0..HEIGHT as isize).contains(&sy);
+                self.pixels[y * WIDTH + x] = match fill {
+                    Some(color) if !inside => color,
+                    _ => {
+                        let sx = sx.rem_euclid(WIDTH as isize) as usize;
+                        let sy = sy.rem_euclid(HEIGHT as isize) as usize;
+                        source[sy * WIDTH + sx]
+                    }
+                };
+            }
+        }
+    }
+
+    fn load_colors(&mut self, data: &[u8], first: usize) {
+        for i in 0..8 {
+            let (high, low) = (data[2 * i] & 0x3F, data[2 * i + 1] & 0x3F);
+            let red = (high & 0x3C) >> 2;
+            let green = ((high & 0x03) << 2) | ((low & 0x30) >> 4);
+            let blue = low & 0x0F;
+            // 4-bit channels: 0x0F -> 0xFF
+            self.palette[first + i] = [red * 17, green * 17, blue * 17, 255];
+        }
+    }
+
+    /// Render to RGBA (`WIDTH * HEIGHT * 4` bytes). The inner area is shifted
+    /// by the scroll offsets; the border keeps its preset color.
+    pub fn render_rgba(&self, out: &mut Vec<u8>) {
+        out.clear();
+        out.reserve(WIDTH * HEIGHT * 4);
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                let inner = (TILE_WIDTH..WIDTH - TILE_WIDTH).contains(&x) && (TILE_HEIGHT..HEIGHT - TILE_HEIGHT).contains(&y);
+                let index = if inner {
+                    let sx = (x + self.h_offset).min(WIDTH - 1);
+                    let sy = (y + self.v_offset).min(HEIGHT - 1);
+                    self.pixels[sy * WIDTH + sx]
+                } else {
+                    self.border
+                };
+                out.extend_from_slice(&self.palette[index as usize]);
+            }
+        }
+    }
+}
+
+/// A whole `.cdg` file with a playhead.
+pub struct CdgStream {
+    data: Vec<u8>,
+    screen: Screen,
+    /// Packets applied so far.
+    next_packet: usize,
+    /// Bumped whenever the picture may have changed.
+    generation: u64,
+}
+
+impl CdgStream {
+    pub fn new(data: Vec<u8>) -> Self {
+        Self { data, screen: Screen::default(), next_packet: 0, generation: 0 }
+    }
+
+    pub fn packet_count(&self) -> usize {
+        self.data.len() / PACKET_SIZE
+    }
+
+    pub fn duration_ms(&self) -> u64 {
+        self.packet_count() as u64 * 1000 / PACKETS_PER_SECOND
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn screen(&self) -> &Screen {
+        &self.screen
+    }
+
+    /// Bring the screen to `position_ms` of the audio. Forward moves decode
+    /// only the packets in between; moving back replays from the start,
+    /// which is a few milliseconds even for long songs.
+    pub fn seek(&mut self, position_ms: u64) {
+        let target = ((position_ms * PACKETS_PER_SECOND / 1000) as usize).min(self.packet_count());
+        if target < self.next_packet {
+            self.screen = Screen::default();
+            self.next_packet = 0;
+            self.generation += 1;
+        }
+        let mut changed = false;
+        for packet in self.data[self.next_packet * PACKET_SIZE..target * PACKET_SIZE].chunks_exact(PACKET_SIZE) {
+            changed |= self.screen.apply(packet);
+        }
+        self.next_packet = target;
+        if changed {
+            self.generation += 1;
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(instruction: u8, data: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0u8; PACKET_SIZE];
+        packet[0] = CDG_COMMAND;
+        packet[1] = instruction;
+        packet[4..4 + data.len()].copy_from_slice(data);
+        packet
+    }
+
+    #[test]
+    fn paints_tiles_with_the_loaded_palette() {
+        let mut colors = [0u8; 16];
+        // Index 1 = pure red (0xF, 0, 0)
+        colors[2] = 0x3C;
+        let mut tile = vec![0, 1, 1, 2];
+        tile.extend([0x20; 12]);
+        let mut screen = Screen::default();
+        assert!(screen.apply(&packet(LOAD_COLORS_LOW, &colors)));
+        assert!(screen.apply(&packet(TILE_BLOCK, &tile)));
+        assert!(!screen.apply(&[0u8; PACKET_SIZE]));
+
+        let mut rgba = Vec::new();
+        screen.render_rgba(&mut rgba);
+        let at = |x: usize, y: usize| &rgba[(y * WIDTH + x) * 4..(y * WIDTH + x) * 4 + 4];
+        assert_eq!(at(12, 12), &[255, 0, 0, 255]);
+        assert_eq!(at(13, 12), &[0, 0, 0, 255]);
+
+        // XOR with the same tile clears it again
+        screen.apply(&packet(TILE_BLOCK_XOR, &tile));
+        screen.render_rgba(&mut rgba);
+        assert_eq!(at(12, 12), &[0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn seeking_back_replays_from_the_start() {
+        let mut data = vec![0u8; PACKET_SIZE * 600];
+        data[PACKET_SIZE * 300..PACKET_SIZE * 301].copy_from_slice(&packet(MEMORY_PRESET, &[3]));
+        let mut stream = CdgStream::new(data);
+        assert_eq!(stream.duration_ms(), 2000);
+
+        stream.seek(500);
+        assert_eq!(stream.generation(), 0);
+        stream.seek(1500);
+        assert_eq!(stream.screen().pixels[0], 3);
+        let generation = stream.generation();
+        stream.seek(200);
+        assert!(stream.generation() > generation);
+        assert_eq!(stream.screen().pixels[0], 0);
+    }
+}
@@ -0,0 +1,178 @@
+//! CD+G playback for the frontend.
+//!
+//! `cdg_open` loads a `.cdg` file (or a zip member, see
+//! `library::archive`) into a session; the renderer then fetches frames
+//! for the current audio position from the `cdg://` protocol:
+//!
+//!   `cdg://localhost/<session>?t=<position ms>&since=<generation>`
+//!
+//! The response is the raw 300×216 RGBA frame, ready for `putImageData`,
+//! with its generation in `X-Cdg-Generation`. While the picture has not
+//! changed since `since` the answer is an empty `204`, so polling every
+//! animation frame costs next to nothing. Seeking is just asking for
+//! another `t`. Decoding stays in Rust; no JS decoder ships with the app.
+//! A session nobody fetched from for `SESSION_IDLE` (a renderer that
+//! reloaded without `cdg_close`) is dropped by the next `cdg_open`.
+
+pub mod decoder;
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tauri::http::{Request, Response, StatusCode};
+use tauri::{AppHandle, Manager};
+
+use crate::access::{require_webview, Capability};
+use crate::library::archive;
+use crate::paths::long_path;
+use decoder::CdgStream;
+
+pub const CDG_SCHEME: &str = "cdg";
+/// `.cdg` files are ~1.7 MB per 4 minutes; anything far larger is not one.
+const MAX_CDG_SIZE: u64 = 64 * 1024 * 1024;
+const SESSION_IDLE: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Default)]
+pub struct CdgState {
+    next_id: AtomicU64,
+    sessions: Mutex<HashMap<u64, Session>>,
+}
+
+struct Session {
+    stream: CdgStream,
+    last_used: Instant,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CdgTrack {
+    pub session_id: u64,
+    pub width: usize,
+    pub height: usize,
+    pub duration_ms: u64,
+}
+
+fn read_cdg(path: &str) -> Result<Vec<u8>, String> {
+    let mut data = Vec::new();
+    match archive::split_member_path(path) {
+        Some((zip, member)) => {
+            let reader = archive::open_member(&zip, &member)?;
+            if reader.len() > MAX_CDG_SIZE {
+                return Err(format!("{} is too large for a CD+G file", member));
+            }
+            reader.take(MAX_CDG_SIZE).read_to_end(&mut data)
+        }
+        None => {
+            let file = std::fs::File::open(long_path(Path::new(path))).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+            file.take(MAX_CDG_SIZE).read_to_end(&mut data)
+        }
+    }
+    .map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    Ok(data)
+}
+
+/// `t` is whatever the renderer's clock says, fractions of a millisecond
+/// included (`currentTime * 1000`).
+fn parse_query(query: &str) -> (u64, Option<u64>) {
+    let mut position = 0;
+    let mut since = None;
+    for pair in query.split('&') {
+        match pair.split_once('=') {
+            Some(("t", v)) => {
+                position = v.parse::<f64>().ok().filter(|t| t.is_finite()).map_or(0, |t| t.max(0.0) as u64)
+            }
+            Some(("since", v)) => since = v.parse().ok(),
+            _ => {}
+        }
+    }
+    (position, since)
+}
+
+fn empty(status: StatusCode) -> Response<Vec<u8>> {
+    Response::builder().status(status).body(Vec::new()).expect("static CD+G response")
+}
+
+/// Handler of the `cdg://` protocol (see module docs).
+pub fn protocol_response(app: &AppHandle, request: &Request<Vec<u8>>) -> Response<Vec<u8>> {
+    let Some(session_id) = request.uri().path().trim_matches('/').parse::<u64>().ok() else {
+        return empty(StatusCode::BAD_REQUEST);
+    };
+    let (position_ms, since) = parse_query(request.uri().query().unwrap_or(""));
+    let state = app.state::<CdgState>();
+    let Ok(mut sessions) = state.sessions.lock() else {
+        return empty(StatusCode::INTERNAL_SERVER_ERROR);
+    };
+    let Some(session) = sessions.get_mut(&session_id) else {
+        return empty(StatusCode::NOT_FOUND);
+    };
+    session.last_used = Instant::now();
+    let stream = &mut session.stream;
+    stream.seek(position_ms);
+    let generation = stream.generation();
+    if since == Some(generation) {
+        return empty(StatusCode::NO_CONTENT);
+    }
+    let mut frame = Vec::new();
+    stream.screen().render_rgba(&mut frame);
+    Response::builder()
+        .header("Content-Type", "application/octet-stream")
+        .header("X-Cdg-Generation", generation.to_string())
+        .header("Access-Control-Expose-Headers", "X-Cdg-Generation")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(frame)
+        .unwrap_or_else(|_| empty(StatusCode::INTERNAL_SERVER_ERROR))
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+/// Load a CD+G file for playback; frames are then served by `cdg://`.
+#[tauri::command]
+pub async fn cdg_open(app: AppHandle, webview: tauri::Webview, path: String) -> Result<CdgTrack, String> {
+    require_webview(&webview, Capability::ControlPlayback)?;
+    let data = tauri::async_runtime::spawn_blocking(move || read_cdg(&path))
+        .await
+        .map_err(|e| e.to_string())??;
+    let stream = CdgStream::new(data);
+    let state = app.state::<CdgState>();
+    let session_id = state.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+    let track = CdgTrack {
+        session_id,
+        width: decoder::WIDTH,
+        height: decoder::HEIGHT,
+        duration_ms: stream.duration_ms(),
+    };
+    let mut sessions = state.sessions.lock().map_err(|e| e.to_string())?;
+    sessions.retain(|_, session| session.last_used.elapsed() < SESSION_IDLE);
+    sessions.insert(session_id, Session { stream, last_used: Instant::now() });
+    Ok(track)
+}
+
+#[tauri::command]
+pub fn cdg_close(app: AppHandle, webview: tauri::Webview, session_id: u64) -> Result<(), String> {
+    require_webview(&webview, Capability::ControlPlayback)?;
+    app.state::<CdgState>().sessions.lock().map_err(|e| e.to_string())?.remove(&session_id);
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positions_may_be_fractional() {
+        assert_eq!(parse_query("t=1234.56&since=7"), (1234, Some(7)));
+        assert_eq!(parse_query("t=90"), (90, None));
+        assert_eq!(parse_query("t=-5&since=x"), (0, None));
+        assert_eq!(parse_query("t=NaN"), (0, None));
+    }
+}
@@ -13,6 +13,7 @@ use serde::Serialize;
 use tauri::{AppHandle, Manager};
 
 use super::sources::{self, ChartEntry};
+use crate::access::{require_webview, Capability};
 use crate::db::DbState;
 use crate::try_log;
 
@@ -165,12 +166,14 @@ fn word_overlap(a: &str, b: &str) -> f64 {
 #[tauri::command]
 pub async fn viral_refresh_charts(
     app: AppHandle,
+    webview: tauri::Webview,
     country: Option<String>,
 ) -> Result<serde_json::Value, String> {
+    require_webview(&webview, Capability::ManageLibrary)?;
     let country = country.unwrap_or_else(|| "de".to_string());
     let country_lower = country.to_lowercase();
 
-    println!("[ViralCharts] Starting chart refresh for country: {}", country);
+    tracing::info!("[ViralCharts] Starting chart refresh for country: {}", country);
 
     // Fetch from all sources concurrently
     let (apple_result, deezer_result, itunes_result) = tokio::join!(
@@ -189,26 +192,26 @@ pub async fn viral_refresh_charts(
     // Collect successful results
     match apple_result {
         Ok(entries) => {
-            println!("[ViralCharts] Apple Music: {} entries", entries.len());
+            tracing::info!("[ViralCharts] Apple Music: {} entries", entries.len());
             all_entries.extend(entries);
         }
-        Err(ref e) => println!("[ViralCharts] Apple Music failed: {}", e),
+        Err(ref e) => tracing::error!("[ViralCharts] Apple Music failed: {}", e),
     }
 
     match deezer_result {
         Ok(entries) => {
-            println!("[ViralCharts] Deezer: {} entries", entries.len());
+            tracing::info!("[ViralCharts] Deezer: {} entries", entries.len());
             all_entries.extend(entries);
         }
-        Err(ref e) => println!("[ViralCharts] Deezer failed: {}", e),
+        Err(ref e) => tracing::error!("[ViralCharts] Deezer failed: {}", e),
     }
 
     match itunes_result {
         Ok(entries) => {
-            println!("[ViralCharts] iTunes: {} entries", entries.len());
+            tracing::info!("[ViralCharts] iTunes: {} entries", entries.len());
             all_entries.extend(entries);
         }
-        Err(ref e) => println!("[ViralCharts] iTunes failed: {}", e),
+        Err(ref e) => tracing::error!("[ViralCharts] iTunes failed: {}", e),
     }
 
     if all_entries.is_empty() {
@@ -270,7 +273,7 @@ pub async fn viral_refresh_charts(
 
         tx.commit().map_err(|e| format!("Commit failed: {}", e))?;
 
-        println!("[ViralCharts] Stored {} unique entries for country {}", c, country);
+        tracing::info!("[ViralCharts] Stored {} unique entries for country {}", c, country);
         Ok::<u32, String>(c)
     })?;
 
@@ -294,8 +297,10 @@ pub async fn viral_refresh_charts(
 #[tauri::command]
 pub fn viral_match_library(
     app: AppHandle,
+    webview: tauri::Webview,
     songs_json: String,
 ) -> Result<Vec<ViralMatchResult>, String> {
+    require_webview(&webview, Capability::ManageLibrary)?;
     let songs: Vec<serde_json::Value> = serde_json::from_str(&songs_json)
         .map_err(|e| format!("Failed to parse songs JSON: {}", e))?;
 
@@ -336,7 +341,7 @@ pub fn viral_match_library(
         return Ok(Vec::new());
     }
 
-    println!(
+    tracing::info!(
         "[ViralCharts] Matching {} chart entries against {} library songs",
         chart_entries.len(),
         songs.len()
@@ -402,7 +407,7 @@ pub fn viral_match_library(
         tx.commit().map_err(|e| format!("Commit failed: {}", e))?;
     }
 
-    println!("[ViralCharts] Found {} matches", results.len());
+    tracing::info!("[ViralCharts] Found {} matches", results.len());
     Ok(results)
 }
 
@@ -515,7 +520,8 @@ pub fn viral_get_status(app: AppHandle) -> Result<ViralStatus, String> {
 
 /// Clear all cached viral hits data.
 #[tauri::command]
-pub fn viral_clear(app: AppHandle) -> Result<serde_json::Value, String> {
+pub fn viral_clear(app: AppHandle, webview: tauri::Webview) -> Result<serde_json::Value, String> {
+    require_webview(&webview, Capability::ManageLibrary)?;
     let state = app.state::<DbState>();
     let conn = state.conn.lock().map_err(|e| e.to_string())?;
 
@@ -531,7 +537,8 @@ pub fn viral_clear(app: AppHandle) -> Result<serde_json::Value, String> {
 
 /// Set the viral charts country setting.
 #[tauri::command]
-pub fn viral_set_country(app: AppHandle, country: String) -> Result<serde_json::Value, String> {
+pub fn viral_set_country(app: AppHandle, webview: tauri::Webview, country: String) -> Result<serde_json::Value, String> {
+    require_webview(&webview, Capability::ChangeSettings)?;
     let state = app.state::<DbState>();
     let conn = state.conn.lock().map_err(|e| e.to_string())?;
 
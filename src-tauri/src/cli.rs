@@ -0,0 +1,480 @@
+//! Headless command-line subcommands.
+//!
+//! `karaoke scan <dir>`, `karaoke import <file>` and `karaoke export-scores`
+//! run the native library code against the app database without creating a
+//! window — useful for maintaining a library on a NAS or from scripts.
+//! Any other invocation (no arguments, a file path from a double-click, a
+//! deep link) starts the GUI as usual.
+//!
+//! The GUI takes flags of its own, so that a venue PC can be set up by its
+//! startup script: `--kiosk`, `--port <n>`, `--library <dir>`,
+//! `--player-display <n>` and `--autoplay-queue <file>`. `parse_gui_flags`
+//! reads them before any window exists and stops the launch on a bad
+//! value; see `automation` for what they do.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::config;
+use crate::db::{self, DbState};
+use crate::library::scan_pool::{self, ScanOptions, ScanPhase, ScanProgress};
+use crate::library::scanner;
+
+const USAGE: &str = "\
+Usage:
+  karaoke scan <dir>...              Scan folders for UltraStar songs and add them to the library
+  karaoke import <file>...           Import song files (.txt, audio) or folders
+  karaoke export-scores [options]    Export highscores
+  karaoke [flags] [file|link]...     Start the app
+
+Flags:
+  --kiosk                            Start in kiosk mode
+  --autostart                        Started at login: resume the last session without asking
+  --port <n>                         Serve the UI on this port instead of the configured one
+  --library <dir>                    Add a song folder to the library and scan it (repeatable)
+  --player-display <n>               Open the player window on display n (1 = the first)
+  --autoplay-queue <file>            Queue the songs of a queue export (CSV, JSON or song ids) and start
+
+Options:
+  --db <path>                        Database file (default: the app's database)
+  --format <json|csv>                Export format (default: json)
+  --output <file>                    Write the export to a file instead of stdout";
+
+/// GUI flags that take a value; the value is not a file to open.
+pub const GUI_VALUE_FLAGS: [&str; 4] = ["--port", "--library", "--player-display", "--autoplay-queue"];
+
+/// Flags the GUI was started with.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GuiFlags {
+    pub kiosk: bool,
+    /// Started by the login item (see `autostart`).
+    pub autostarted: bool,
+    pub port: Option<u16>,
+    pub library: Vec<PathBuf>,
+    /// 1-based, as displays are numbered in the OS settings.
+    pub player_display: Option<usize>,
+    pub autoplay_queue: Option<PathBuf>,
+}
+
+static GUI_FLAGS: OnceLock<GuiFlags> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ExportFormat {
+    Json,
+    Csv,
+}
+
+#[derive(Debug, PartialEq)]
+enum CliCommand {
+    Scan { dirs: Vec<PathBuf> },
+    Import { paths: Vec<PathBuf> },
+    ExportScores { format: ExportFormat, output: Option<PathBuf> },
+    Help,
+}
+
+#[derive(Debug, PartialEq)]
+struct CliArgs {
+    command: CliCommand,
+    db_path: Option<PathBuf>,
+}
+
+/// Run a CLI subcommand if the process arguments name one.
+///
+/// Returns `Some(exit_code)` when a subcommand was handled (the caller should
+/// exit), or `None` to continue with the normal GUI startup.
+pub fn run_from_args() -> Option<i32> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let parsed = match parse_args(&args) {
+        Ok(Some(parsed)) => parsed,
+        Ok(None) => return None,
+        Err(e) => {
+            attach_console();
+            eprintln!("error: {}\n\n{}", e, USAGE);
+            return Some(2);
+        }
+    };
+
+    attach_console();
+    match execute(parsed) {
+        Ok(()) => Some(0),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            Some(1)
+        }
+    }
+}
+
+/// Read the GUI flags of this process. On a bad value, prints it with the
+/// usage and returns the exit code; the caller should exit.
+pub fn init_gui_flags() -> Result<(), i32> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let cwd = std::env::current_dir().unwrap_or_default();
+    match parse_gui_flags(&args, &cwd) {
+        Ok(flags) => {
+            let _ = GUI_FLAGS.set(flags);
+            Ok(())
+        }
+        Err(e) => {
+            attach_console();
+            eprintln!("error: {}\n\n{}", e, USAGE);
+            Err(2)
+        }
+    }
+}
+
+/// The flags read by `init_gui_flags` (none before that).
+pub fn gui_flags() -> &'static GuiFlags {
+    GUI_FLAGS.get_or_init(GuiFlags::default)
+}
+
+/// GUI flags in `args` (without argv[0]); relative paths are resolved
+/// against `cwd`. Anything else — files, links, flags of other parts such
+/// as `--debug-console` — is left to its handler.
+fn parse_gui_flags(args: &[String], cwd: &Path) -> Result<GuiFlags, String> {
+    let mut flags = GuiFlags::default();
+    let resolve = |value: &str| {
+        let path = Path::new(value);
+        if path.is_absolute() { path.to_path_buf() } else { cwd.join(path) }
+    };
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let flag = arg.as_str();
+        if flag == crate::desktop::kiosk::KIOSK_FLAG {
+            flags.kiosk = true;
+            continue;
+        }
+        if flag == crate::autostart::AUTOSTART_FLAG {
+            flags.autostarted = true;
+            continue;
+        }
+        if !GUI_VALUE_FLAGS.contains(&flag) {
+            continue;
+        }
+        let value = iter.next().filter(|v| !v.starts_with("--")).ok_or_else(|| format!("{} requires a value", flag))?;
+        match flag {
+            "--port" => {
+                let port = value.parse::<u16>().ok().filter(|&p| p >= 1024);
+                flags.port = Some(port.ok_or_else(|| format!("--port: '{}' is not a usable port (1024-65535)", value))?);
+            }
+            "--library" => {
+                let dir = resolve(value);
+                if !dir.is_dir() {
+                    return Err(format!("--library: {} is not a folder", dir.display()));
+                }
+                flags.library.push(dir);
+            }
+            "--player-display" => {
+                let n = value.parse::<usize>().ok().filter(|&n| n > 0);
+                flags.player_display = Some(n.ok_or_else(|| format!("--player-display: '{}' is not a display number (1 = the first)", value))?);
+            }
+            _ => {
+                let file = resolve(value);
+                if !file.is_file() {
+                    return Err(format!("--autoplay-queue: {} not found", file.display()));
+                }
+                flags.autoplay_queue = Some(file);
+            }
+        }
+    }
+    Ok(flags)
+}
+
+/// Parse arguments (without argv[0]). `Ok(None)` = not a CLI invocation.
+fn parse_args(args: &[String]) -> Result<Option<CliArgs>, String> {
+    let Some(subcommand) = args.first() else {
+        return Ok(None);
+    };
+    // GUI flags such as `--kiosk` may follow a file path; leave them alone
+    if !matches!(subcommand.as_str(), "scan" | "import" | "export-scores" | "help" | "--help" | "-h") {
+        return Ok(None);
+    }
+
+    let mut db_path = None;
+    let mut format = ExportFormat::Json;
+    let mut output = None;
+    let mut positional: Vec<PathBuf> = Vec::new();
+
+    let mut iter = args[1..].iter();
+    while let Some(arg) = iter.next() {
+        let mut value = |flag: &str| -> Result<String, String> {
+            iter.next().cloned().ok_or_else(|| format!("{} requires a value", flag))
+        };
+        match arg.as_str() {
+            "--db" => db_path = Some(PathBuf::from(value("--db")?)),
+            "--output" | "-o" => output = Some(PathBuf::from(value("--output")?)),
+            "--format" => {
+                format = match value("--format")?.as_str() {
+                    "json" => ExportFormat::Json,
+                    "csv" => ExportFormat::Csv,
+                    other => return Err(format!("unknown export format '{}'", other)),
+                }
+            }
+            flag if flag.starts_with("--") => return Err(format!("unknown option '{}'", flag)),
+            path => positional.push(PathBuf::from(path)),
+        }
+    }
+
+    let command = match subcommand.as_str() {
+        "scan" => {
+            if positional.is_empty() {
+                return Err("scan requires at least one directory".into());
+            }
+            CliCommand::Scan { dirs: positional }
+        }
+        "import" => {
+            if positional.is_empty() {
+                return Err("import requires at least one file".into());
+            }
+            CliCommand::Import { paths: positional }
+        }
+        "export-scores" => {
+            if !positional.is_empty() {
+                return Err("export-scores takes no positional arguments".into());
+            }
+            CliCommand::ExportScores { format, output }
+        }
+        "help" | "--help" | "-h" => CliCommand::Help,
+        _ => return Ok(None),
+    };
+
+    Ok(Some(CliArgs { command, db_path }))
+}
+
+fn open_db(db_path: Option<PathBuf>) -> Result<DbState, String> {
+    let path = match db_path {
+        Some(p) => p,
+        None => db::headless_db_path()?,
+    };
+    let db = DbState::new(path)?;
+    // Index like the app does, or CLI scans and searches disagree with it
+    let search = config::load_headless()?.search;
+    {
+        let conn = db.conn.lock().map_err(|e| e.to_string())?;
+        if let Some(songs) = db::search::sync_now(&conn, &search)? {
+            eprintln!("Search index rebuilt for the current settings ({} songs)", songs);
+        }
+    }
+    Ok(db)
+}
+
+fn execute(args: CliArgs) -> Result<(), String> {
+    match args.command {
+        CliCommand::Help => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        CliCommand::Scan { dirs } => {
+            let db = open_db(args.db_path)?;
+            let mut total = 0;
+            for dir in dirs {
+                let report = scan_pool::scan_into_db(&db, &dir, &ScanOptions::default(), print_progress)?;
+                for error in &report.errors {
+                    eprintln!("warning: {}", error);
+                }
+                println!("{}: {} songs ({} files skipped)", dir.display(), report.songs_found, report.files_skipped);
+                total += report.songs_found;
+            }
+            println!("{} songs in library updated", total);
+            Ok(())
+        }
+        CliCommand::Import { paths } => {
+            let db = open_db(args.db_path)?;
+            let mut total = 0;
+            for path in paths {
+                match scanner::import_path(&path) {
+                    Ok(report) => total += save_report(&db, &path, report)?,
+                    Err(e) => eprintln!("{}", e),
+                }
+            }
+            println!("{} songs imported", total);
+            Ok(())
+        }
+        CliCommand::ExportScores { format, output } => {
+            let db = open_db(args.db_path)?;
+            let conn = db.conn.lock().map_err(|e| e.to_string())?;
+            let text = match format {
+                ExportFormat::Json => export_scores_json(&conn)?,
+                ExportFormat::Csv => export_scores_csv(&conn)?,
+            };
+            match output {
+                Some(path) => std::fs::write(&path, text)
+                    .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?,
+                None => std::io::stdout()
+                    .write_all(text.as_bytes())
+                    .map_err(|e| e.to_string())?,
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Single-line progress on stderr while a folder is parsed.
+fn print_progress(progress: &ScanProgress) {
+    if progress.phase == ScanPhase::Parsing {
+        eprint!("\r{} / {} files", progress.files_done, progress.files_total);
+    } else if progress.phase == ScanPhase::Done && progress.files_total > 0 {
+        eprintln!("\r{} / {} files", progress.files_done, progress.files_total);
+    }
+}
+
+fn save_report(db: &DbState, source: &std::path::Path, report: scanner::ScanReport) -> Result<usize, String> {
+    for error in &report.errors {
+        eprintln!("warning: {}", error);
+    }
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let saved = scanner::save_songs(&mut conn, &report.songs)?;
+    println!(
+        "{}: {} songs ({} files skipped)",
+        source.display(),
+        saved,
+        report.files_skipped
+    );
+    Ok(saved)
+}
+
+/// All highscores as a JSON array (the stored frontend records).
+fn export_scores_json(conn: &rusqlite::Connection) -> Result<String, String> {
+    let mut stmt = conn
+        .prepare("SELECT json_data FROM highscores WHERE json_data IS NOT NULL ORDER BY played_at ASC")
+        .map_err(|e| format!("export scores prepare failed: {}", e))?;
+    let scores: Vec<serde_json::Value> = stmt
+        .query_map([], |row| row.get::<_, String>(0))
+        .map_err(|e| format!("export scores query failed: {}", e))?
+        .filter_map(|r| crate::try_log(r, "export_scores row"))
+        .filter_map(|s| crate::try_log(serde_json::from_str(&s), "export_scores JSON parse"))
+        .collect();
+    let mut text = serde_json::to_string_pretty(&scores).map_err(|e| e.to_string())?;
+    text.push('\n');
+    Ok(text)
+}
+
+/// Highscores as CSV with a header row.
+fn export_scores_csv(conn: &rusqlite::Connection) -> Result<String, String> {
+    const COLUMNS: [&str; 10] = [
+        "player_name", "song_id", "song_title", "score", "accuracy",
+        "max_combo", "difficulty", "game_mode", "rank_title", "played_at",
+    ];
+    let mut stmt = conn
+        .prepare(&format!("SELECT {} FROM highscores ORDER BY played_at ASC", COLUMNS.join(", ")))
+        .map_err(|e| format!("export scores prepare failed: {}", e))?;
+
+    let mut out = COLUMNS.join(",");
+    out.push('\n');
+    let mut rows = stmt.query([]).map_err(|e| format!("export scores query failed: {}", e))?;
+    while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+        let fields: Vec<String> = (0..COLUMNS.len())
+            .map(|i| match row.get_ref(i) {
+                Ok(rusqlite::types::ValueRef::Text(t)) => csv_field(&String::from_utf8_lossy(t)),
+                Ok(rusqlite::types::ValueRef::Integer(n)) => n.to_string(),
+                Ok(rusqlite::types::ValueRef::Real(f)) => f.to_string(),
+                _ => String::new(),
+            })
+            .collect();
+        out.push_str(&fields.join(","));
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+/// Quote a CSV field if it contains a delimiter, quote or newline.
+pub(crate) fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Release builds use the Windows GUI subsystem, which has no console;
+/// attach to the parent terminal so CLI output is visible.
+#[cfg(target_os = "windows")]
+fn attach_console() {
+    extern "system" {
+        fn AttachConsole(process_id: u32) -> i32;
+    }
+    const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+    // SAFETY: plain Win32 call without pointers; failure (already attached,
+    // no parent console) is harmless.
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+fn attach_console() {}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn non_subcommand_starts_gui() {
+        assert_eq!(parse_args(&args(&[])).unwrap(), None);
+        assert_eq!(parse_args(&args(&["C:\\Songs\\song.txt"])).unwrap(), None);
+        assert_eq!(parse_args(&args(&["karaoke://enqueue?id=1"])).unwrap(), None);
+        assert_eq!(parse_args(&args(&["C:\\Songs\\song.txt", "--kiosk"])).unwrap(), None);
+    }
+
+    #[test]
+    fn parses_export_options() {
+        let parsed = parse_args(&args(&["export-scores", "--format", "csv", "--output", "s.csv", "--db", "k.db"]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            parsed,
+            CliArgs {
+                command: CliCommand::ExportScores { format: ExportFormat::Csv, output: Some("s.csv".into()) },
+                db_path: Some("k.db".into()),
+            }
+        );
+        assert!(parse_args(&args(&["scan"])).is_err());
+        assert!(parse_args(&args(&["export-scores", "--format", "xml"])).is_err());
+    }
+
+    #[test]
+    fn parses_gui_flags() {
+        let cwd = std::env::temp_dir();
+        let queue = cwd.join(format!("karaoke-cli-queue-{}.csv", std::process::id()));
+        std::fs::write(&queue, "singer,song_id\n").unwrap();
+        let queue_name = queue.file_name().unwrap().to_str().unwrap();
+
+        let flags = parse_gui_flags(
+            &args(&["--kiosk", "--autostart", "--port", "3100", "--library", ".", "--player-display", "2", "--autoplay-queue", queue_name, "song.txt"]),
+            &cwd,
+        );
+        std::fs::remove_file(&queue).ok();
+        assert_eq!(
+            flags.unwrap(),
+            GuiFlags {
+                kiosk: true,
+                autostarted: true,
+                port: Some(3100),
+                library: vec![cwd.join(".")],
+                player_display: Some(2),
+                autoplay_queue: Some(queue),
+            }
+        );
+        assert_eq!(parse_gui_flags(&args(&["--debug-console"]), &cwd).unwrap(), GuiFlags::default());
+        assert!(parse_gui_flags(&args(&["--port", "70000"]), &cwd).is_err());
+        assert!(parse_gui_flags(&args(&["--player-display", "0"]), &cwd).is_err());
+        assert!(parse_gui_flags(&args(&["--port", "--kiosk"]), &cwd).is_err());
+        assert!(parse_gui_flags(&args(&["--library", "karaoke-missing-dir"]), &cwd).is_err());
+    }
+
+    #[test]
+    fn csv_fields_are_quoted() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+}
@@ -0,0 +1,216 @@
+//! Opt-in clipboard monitor for quick YouTube/music adds.
+//!
+//! While enabled (`clipboard_watch_enabled` setting) and the main window is
+//! focused, the clipboard is polled once a second. A newly copied YouTube /
+//! SoundCloud / Vimeo link emits `clipboard://media-url`; the frontend asks
+//! "add this to the queue?" and calls `clipboard_confirm_add`, which hands
+//! the link to the download pipeline as a normal enqueue request, subject
+//! to the deep-link enqueue policy and host allowlist (`deep_link`).
+//!
+//! Polling only while focused keeps us from reading clipboard contents the
+//! user copied for other apps.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+use crate::access::{require_webview, Capability};
+use crate::db::DbState;
+use crate::deep_link::{EnqueueLink, EnqueueTarget};
+use crate::events::{publish, AppEvent};
+
+/// Event emitted when a media URL was copied.
+pub const MEDIA_URL_EVENT: &str = "clipboard://media-url";
+
+const ENABLED_KEY: &str = "clipboard_watch_enabled";
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Longest clipboard text considered a URL candidate.
+const MAX_URL_LEN: usize = 2048;
+
+/// Managed state shared between the poll thread, window events and commands.
+#[derive(Default)]
+pub struct ClipboardWatchState {
+    enabled: AtomicBool,
+    focused: AtomicBool,
+    last_seen: Mutex<Option<String>>,
+}
+
+impl ClipboardWatchState {
+    pub fn set_focused(&self, focused: bool) {
+        self.focused.store(focused, Ordering::Relaxed);
+    }
+}
+
+/// A detected media link.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MediaUrl {
+    pub url: String,
+    /// `"youtube"`, `"soundcloud"` or `"vimeo"`.
+    pub service: &'static str,
+}
+
+/// Recognise a single media URL in clipboard text. `http://` links are
+/// upgraded to `https://`; text with anything besides the URL is ignored.
+pub fn detect_media_url(text: &str) -> Option<MediaUrl> {
+    let text = text.trim();
+    if text.is_empty() || text.len() > MAX_URL_LEN || text.contains(char::is_whitespace) {
+        return None;
+    }
+    let rest = text
+        .strip_prefix("https://")
+        .or_else(|| text.strip_prefix("http://"))?;
+    let (host, path) = rest.split_once('/').unwrap_or((rest, ""));
+    let host = host.to_ascii_lowercase();
+    let host = host.strip_prefix("www.").unwrap_or(&host);
+    let host = host.strip_prefix("m.").unwrap_or(host);
+
+    let service = match host {
+        "youtube.com" | "music.youtube.com"
+            if path.starts_with("watch?") || path.starts_with("shorts/") || path.starts_with("playlist?") =>
+        {
+            "youtube"
+        }
+        "youtu.be" if !path.is_empty() => "youtube",
+        "soundcloud.com" if path.contains('/') => "soundcloud",
+        "vimeo.com" if path.chars().next().is_some_and(|c| c.is_ascii_digit()) => "vimeo",
+        _ => return None,
+    };
+
+    Some(MediaUrl {
+        url: format!("https://{}", rest),
+        service,
+    })
+}
+
+/// Read the persisted opt-in flag into `state` and start the poll thread.
+pub fn spawn_clipboard_watch(app: AppHandle) -> Result<(), String> {
+    let enabled = {
+        let db = app.state::<DbState>();
+        let conn = db.conn.lock().map_err(|e| e.to_string())?;
+        conn.query_row("SELECT value FROM app_settings WHERE key = ?1", [ENABLED_KEY], |row| row.get::<_, String>(0))
+            .map(|v| v == "true")
+            .unwrap_or(false)
+    };
+    app.state::<ClipboardWatchState>().enabled.store(enabled, Ordering::Relaxed);
+
+    thread::Builder::new()
+        .name("karaoke-clipboard-watch".into())
+        .spawn(move || run_watch(app))
+        .map_err(|e| format!("Failed to spawn clipboard watcher: {}", e))?;
+    Ok(())
+}
+
+fn run_watch(app: AppHandle) {
+    // Opened lazily: on some Linux setups the clipboard is unavailable
+    let mut clipboard: Option<arboard::Clipboard> = None;
+
+    loop {
+        thread::sleep(POLL_INTERVAL);
+        let state = app.state::<ClipboardWatchState>();
+        if !state.enabled.load(Ordering::Relaxed) || !state.focused.load(Ordering::Relaxed) {
+            continue;
+        }
+
+        if clipboard.is_none() {
+            match arboard::Clipboard::new() {
+                Ok(c) => clipboard = Some(c),
+                Err(e) => {
+                    tracing::warn!("[clipboard] Clipboard unavailable, watcher stopped: {}", e);
+                    return;
+                }
+            }
+        }
+        let Some(text) = clipboard.as_mut().and_then(|c| c.get_text().ok()) else {
+            continue;
+        };
+
+        {
+            let mut last = state.last_seen.lock().unwrap_or_else(|e| e.into_inner());
+            if last.as_deref() == Some(text.as_str()) {
+                continue;
+            }
+            *last = Some(text.clone());
+        }
+
+        if let Some(media) = detect_media_url(&text) {
+            tracing::info!("[clipboard] Detected {} link", media.service);
+            publish(&app, AppEvent::ClipboardMediaUrl(media));
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tauri Commands
+// ---------------------------------------------------------------------------
+
+/// Turn the clipboard watcher on or off (persisted).
+#[tauri::command]
+pub fn clipboard_watch_set_enabled(app: AppHandle, webview: tauri::Webview, enabled: bool) -> Result<(), String> {
+    require_webview(&webview, Capability::ChangeSettings)?;
+    {
+        let db = app.state::<DbState>();
+        let conn = db.conn.lock().map_err(|e| e.to_string())?;
+        conn.execute(
+            "INSERT OR REPLACE INTO app_settings (key, value) VALUES (?1, ?2)",
+            (ENABLED_KEY, enabled.to_string()),
+        )
+        .map_err(|e| format!("Failed to save clipboard setting: {}", e))?;
+    }
+    app.state::<ClipboardWatchState>().enabled.store(enabled, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Whether the clipboard watcher is enabled.
+#[tauri::command]
+pub fn clipboard_watch_get_enabled(app: AppHandle) -> bool {
+    app.state::<ClipboardWatchState>().enabled.load(Ordering::Relaxed)
+}
+
+/// The user confirmed "add this to the queue?" — hand the link to the
+/// download pipeline as an already-confirmed enqueue request, unless the
+/// enqueue policy refuses it.
+#[tauri::command]
+pub fn clipboard_confirm_add(app: AppHandle, webview: tauri::Webview, url: String, singer: Option<String>) -> Result<(), String> {
+    require_webview(&webview, Capability::RequestSong)?;
+    let media = detect_media_url(&url).ok_or("Not a supported media URL")?;
+    let link = EnqueueLink {
+        target: EnqueueTarget::Url { url: media.url },
+        singer,
+        play_next: false,
+    };
+    crate::deep_link::enqueue_confirmed(&app, link).map(|_| ())
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_supported_links() {
+        let yt = detect_media_url(" https://www.youtube.com/watch?v=dQw4w9WgXcQ \n").unwrap();
+        assert_eq!(yt.service, "youtube");
+        assert_eq!(yt.url, "https://www.youtube.com/watch?v=dQw4w9WgXcQ");
+
+        assert_eq!(detect_media_url("http://youtu.be/abc").unwrap().url, "https://youtu.be/abc");
+        assert_eq!(detect_media_url("https://music.youtube.com/watch?v=x").unwrap().service, "youtube");
+        assert_eq!(detect_media_url("https://soundcloud.com/artist/track").unwrap().service, "soundcloud");
+        assert_eq!(detect_media_url("https://vimeo.com/123456").unwrap().service, "vimeo");
+    }
+
+    #[test]
+    fn ignores_other_text() {
+        assert!(detect_media_url("https://www.youtube.com/").is_none());
+        assert!(detect_media_url("https://example.com/watch?v=1").is_none());
+        assert!(detect_media_url("see https://youtu.be/abc").is_none());
+        assert!(detect_media_url("password123").is_none());
+    }
+}
@@ -0,0 +1,635 @@
+//! `config.toml` in the app config directory.
+//!
+//! Holds what an operator wants to pin by hand or roll out to several
+//! machines: the server port and LAN access, library folders and search,
+//! kiosk mode, audio devices, global hotkeys, MIDI controls, party
+//! lighting, multi-room playback, online fetching, the log level, the
+//! update channel and opt-in usage metrics.
+//! Everything else stays in `app_settings`. A missing file means defaults;
+//! unknown keys are ignored.
+//!
+//! `set_config` writes the file; edits made in a text editor are picked up
+//! by a watcher polling the file every `WATCH_INTERVAL`. Either way the new
+//! config is applied to the Rust subsystems (`apply`) and published as
+//! `config://changed`, so the web UI can refresh its view. The launch
+//! flags `--port` and `--kiosk` override the file for one run, and
+//! `--library` adds folders to it (see `automation`).
+//!
+//! ```toml
+//! [server]
+//! preferred_port = 3000
+//! lan_access = false
+//! runtime = "auto"            # or "node", "bun"
+//! static_shell = true
+//!
+//! [library]
+//! paths = ["D:/Karaoke"]
+//!
+//! [search]
+//! transliterate = true        # also index kana, Cyrillic, Greek romanized
+//!
+//! [search.tokenizers]
+//! korean = "words"            # by song language; "auto" pairs Han, kana, Thai
+//!
+//! [kiosk]
+//! enabled = false
+//!
+//! [audio]
+//! output_device = "Speakers (USB Audio)"
+//! input_device = "Microphone (USB Audio)"
+//!
+//! [hotkeys]
+//! next_song = "F13"
+//! pause_resume = "MediaPlayPause"
+//!
+//! [midi]
+//! device = "nanoKONTROL2"
+//!
+//! [[midi.mappings]]
+//! control = "note:1:41"       # or "cc:<channel>:<controller>"
+//! action = "next_singer"
+//!
+//! [lighting]
+//! enabled = true
+//! protocol = "artnet"         # or "sacn"
+//! target = "192.168.1.60"     # unset: broadcast (Art-Net) / multicast (sACN)
+//! universe = 0
+//!
+//! [[lighting.scenes]]
+//! cue = "chorus"              # idle, song_start, song_end, score_reveal
+//! start_channel = 1
+//! values = [255, 0, 128, 255]
+//! hold_ms = 4000              # a flash; unset keeps the scene
+//!
+//! [multiroom]
+//! leader = "auto"             # or "192.168.1.20[:47822]"; unset leads
+//! token = "…"                 # the leader's guest token
+//! offset_ms = 0
+//!
+//! [online]
+//! enabled = true              # fetch tracks with yt-dlp, off by default
+//!
+//! [logging]
+//! level = "info"
+//!
+//! [updates]
+//! channel = "stable"
+//!
+//! [telemetry]
+//! enabled = false             # anonymous usage metrics, see `telemetry`
+//! endpoint = "https://…"      # unset: collected for inspection, never sent
+//! ```
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+use crate::access::{require_webview, Capability, Principal};
+use crate::db::normalize::Tokenizer;
+use crate::db::DbState;
+use crate::desktop::hotkeys::HotkeyAction;
+use crate::events::{self, AppEvent};
+use crate::lighting::{LightingCue, LightingProtocol};
+use crate::midi_control::MidiAction;
+use crate::runtime::{sleep_or_cancel, TaskSupervisor};
+use crate::server::runtime::RuntimeChoice;
+use crate::updater::UpdateChannel;
+
+pub const CONFIG_CHANGED_EVENT: &str = "config://changed";
+const CONFIG_FILE: &str = "config.toml";
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+/// Stands in for secrets (`[multiroom] token`) in `config://changed` and
+/// in what `get_config` shows webviews that may not change settings;
+/// `set_config` keeps the stored secret when it gets this back.
+pub const REDACTED: &str = "<redacted>";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    /// First port tried; the next free one in 3000–3099 is used if taken.
+    pub preferred_port: u16,
+    /// Let other devices reach the server, with the access token (see
+    /// `server::security`); off binds it to localhost.
+    pub lan_access: bool,
+    /// JavaScript runtime for the server (see `server::runtime`).
+    pub runtime: RuntimeChoice,
+    /// Show the UI from the bundle before the server is up (see
+    /// `server::shell`).
+    pub static_shell: bool,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            preferred_port: crate::server::port::PREFERRED_PORT,
+            lan_access: false,
+            runtime: RuntimeChoice::Auto,
+            static_shell: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LibraryConfig {
+    /// Added to the library's root folders; folders added in the app are kept.
+    pub paths: Vec<String>,
+}
+
+/// How song titles are indexed for search (see `db::normalize`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchConfig {
+    pub transliterate: bool,
+    /// By song language, case-insensitive; others use `auto`.
+    pub tokenizers: BTreeMap<String, Tokenizer>,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self { transliterate: true, tokenizers: BTreeMap::new() }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KioskConfig {
+    /// Also forced on for one run by `--kiosk`.
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioConfig {
+    /// Primary output; `None` leaves the choice made in the app.
+    pub output_device: Option<String>,
+    pub input_device: Option<String>,
+}
+
+/// Accelerators (`"Ctrl+Alt+N"`) per action; `None` leaves it unbound.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HotkeysConfig {
+    pub next_song: Option<String>,
+    pub pause_resume: Option<String>,
+    pub key_up: Option<String>,
+    pub key_down: Option<String>,
+    pub toggle_filler: Option<String>,
+}
+
+impl HotkeysConfig {
+    fn slot(&mut self, action: HotkeyAction) -> &mut Option<String> {
+        match action {
+            HotkeyAction::NextSong => &mut self.next_song,
+            HotkeyAction::PauseResume => &mut self.pause_resume,
+            HotkeyAction::KeyUp => &mut self.key_up,
+            HotkeyAction::KeyDown => &mut self.key_down,
+            HotkeyAction::ToggleFiller => &mut self.toggle_filler,
+        }
+    }
+
+    pub fn set(&mut self, action: HotkeyAction, accelerator: Option<String>) {
+        *self.slot(action) = accelerator;
+    }
+
+    /// The bound actions with their accelerators.
+    pub fn bindings(&self) -> Vec<(HotkeyAction, &str)> {
+        [
+            (HotkeyAction::NextSong, &self.next_song),
+            (HotkeyAction::PauseResume, &self.pause_resume),
+            (HotkeyAction::KeyUp, &self.key_up),
+            (HotkeyAction::KeyDown, &self.key_down),
+            (HotkeyAction::ToggleFiller, &self.toggle_filler),
+        ]
+        .into_iter()
+        .filter_map(|(action, accelerator)| accelerator.as_deref().map(|a| (action, a)))
+        .collect()
+    }
+}
+
+/// A control surface: the input port and what its controls do.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MidiConfig {
+    /// Input port, by name or part of it; `None` listens to none.
+    pub device: Option<String>,
+    pub mappings: Vec<MidiMapping>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MidiMapping {
+    /// `note:<channel>:<note>` or `cc:<channel>:<controller>`, channels 1–16.
+    pub control: String,
+    pub action: MidiAction,
+}
+
+impl MidiConfig {
+    /// Map `control` to `action`, or unmap it with `None`.
+    pub fn set(&mut self, control: String, action: Option<MidiAction>) {
+        self.mappings.retain(|mapping| mapping.control != control);
+        if let Some(action) = action {
+            self.mappings.push(MidiMapping { control, action });
+        }
+    }
+}
+
+/// DMX output and the scene per cue (see `lighting`).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LightingConfig {
+    pub enabled: bool,
+    pub protocol: LightingProtocol,
+    /// Node address or host name, optionally with a port.
+    pub target: Option<String>,
+    pub universe: u16,
+    pub scenes: Vec<LightingScene>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LightingScene {
+    pub cue: LightingCue,
+    /// First channel `values` are written to, 1-based.
+    #[serde(default = "default_start_channel")]
+    pub start_channel: u16,
+    pub values: Vec<u8>,
+    /// Return to the previous scene after this long.
+    #[serde(default)]
+    pub hold_ms: Option<u64>,
+}
+
+fn default_start_channel() -> u16 {
+    1
+}
+
+/// The instance this room mirrors (see `multiroom`).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MultiroomConfig {
+    /// `host[:port]` of the leader's WebSocket bridge, or `"auto"`; `None`
+    /// leads or plays alone.
+    pub leader: Option<String>,
+    /// The leader's guest token (`get_connection_info` there); its bridge
+    /// turns LAN clients away without one.
+    pub token: Option<String>,
+    /// Extra delay for this room's speakers.
+    pub offset_ms: i64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OnlineConfig {
+    /// Allow fetching tracks from video sites with yt-dlp.
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoggingConfig {
+    /// `EnvFilter` directive; `None` uses the `log_level` setting.
+    pub level: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UpdatesConfig {
+    /// Where `check_for_updates` looks (see `updater`).
+    pub channel: UpdateChannel,
+}
+
+/// Anonymous usage metrics (see `telemetry`); off by default.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TelemetryConfig {
+    pub enabled: bool,
+    /// HTTPS URL reports are POSTed to; `None` never sends them.
+    pub endpoint: Option<String>,
+}
+
+/// Mirrors `config.toml`; the web UI gets the same snake_case keys.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppConfig {
+    pub server: ServerConfig,
+    pub library: LibraryConfig,
+    pub search: SearchConfig,
+    pub kiosk: KioskConfig,
+    pub audio: AudioConfig,
+    pub hotkeys: HotkeysConfig,
+    pub midi: MidiConfig,
+    pub lighting: LightingConfig,
+    pub multiroom: MultiroomConfig,
+    pub online: OnlineConfig,
+    pub logging: LoggingConfig,
+    pub updates: UpdatesConfig,
+    pub telemetry: TelemetryConfig,
+}
+
+impl AppConfig {
+    fn parse(text: &str) -> Result<Self, String> {
+        toml::from_str(text).map_err(|e| format!("Invalid {}: {}", CONFIG_FILE, e))
+    }
+
+    /// This config with its secrets replaced by `REDACTED`.
+    pub fn redacted(&self) -> Self {
+        let mut config = self.clone();
+        if config.multiroom.token.is_some() {
+            config.multiroom.token = Some(REDACTED.to_string());
+        }
+        config
+    }
+
+    /// This config with `REDACTED` placeholders taken from `stored`.
+    fn unredacted(mut self, stored: &AppConfig) -> Self {
+        if self.multiroom.token.as_deref() == Some(REDACTED) {
+            self.multiroom.token = stored.multiroom.token.clone();
+        }
+        self
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.server.preferred_port < 1024 {
+            return Err(format!("Preferred port {} is reserved (use 1024–65535)", self.server.preferred_port));
+        }
+        if let Some(level) = &self.logging.level {
+            crate::logging::parse_level(level)?;
+        }
+        if self.library.paths.iter().any(|p| p.trim().is_empty()) {
+            return Err("Library paths must not be empty".to_string());
+        }
+        if self.search.tokenizers.keys().any(|language| language.trim().is_empty()) {
+            return Err("Search tokenizer languages must not be empty".to_string());
+        }
+        crate::desktop::hotkeys::parse_bindings(&self.hotkeys)?;
+        crate::midi_control::parse_mappings(&self.midi)?;
+        crate::lighting::validate(&self.lighting)?;
+        crate::multiroom::validate(&self.multiroom)?;
+        crate::telemetry::validate(&self.telemetry)?;
+        Ok(())
+    }
+}
+
+pub struct ConfigState {
+    path: Option<PathBuf>,
+    config: Mutex<AppConfig>,
+    /// Modification time of the file as last read or written by us.
+    modified: Mutex<Option<SystemTime>>,
+}
+
+fn modified_at(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn read_file(path: &Path) -> Result<AppConfig, String> {
+    match std::fs::read_to_string(path) {
+        Ok(text) => {
+            let config = AppConfig::parse(&text)?;
+            config.validate()?;
+            Ok(config)
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(AppConfig::default()),
+        Err(e) => Err(format!("Failed to read {}: {}", path.display(), e)),
+    }
+}
+
+/// Write via a temporary file so a crash never leaves half a config.
+fn write_file(path: &Path, config: &AppConfig) -> Result<(), String> {
+    let text = toml::to_string_pretty(config).map_err(|e| format!("Failed to encode config: {}", e))?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    let tmp = path.with_extension("toml.tmp");
+    std::fs::write(&tmp, text).map_err(|e| format!("Failed to write {}: {}", tmp.display(), e))?;
+    std::fs::rename(&tmp, path).map_err(|e| format!("Failed to replace {}: {}", path.display(), e))
+}
+
+/// `config.toml` without a running Tauri app (CLI mode), from where
+/// Tauri's `app_config_dir()` puts it; defaults if there is none.
+pub fn load_headless() -> Result<AppConfig, String> {
+    let base = if cfg!(target_os = "windows") {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        std::env::var_os("HOME").map(|h| PathBuf::from(h).join("Library").join("Application Support"))
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|v| !v.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))
+    };
+    let dir = base.ok_or("Cannot determine the app config directory")?.join(crate::db::APP_IDENTIFIER);
+    read_file(&dir.join(CONFIG_FILE))
+}
+
+/// Load `config.toml` and manage it. Call once the database is managed;
+/// an unreadable file is reported and defaults are used.
+pub fn init(app: &AppHandle) {
+    let path = app.path().app_config_dir().ok().map(|dir| dir.join(CONFIG_FILE));
+    let config = match &path {
+        Some(path) => read_file(path).unwrap_or_else(|e| {
+            tracing::error!("[config] {} — using defaults", e);
+            AppConfig::default()
+        }),
+        None => AppConfig::default(),
+    };
+    apply(app, &config);
+    app.manage(ConfigState {
+        modified: Mutex::new(path.as_deref().and_then(modified_at)),
+        path,
+        config: Mutex::new(config),
+    });
+}
+
+/// The config currently in effect (defaults before `init`).
+pub fn current(app: &AppHandle) -> AppConfig {
+    app.try_state::<ConfigState>()
+        .and_then(|state| state.config.lock().ok().map(|c| c.clone()))
+        .unwrap_or_default()
+}
+
+/// The config in effect, unless its lock is taken (for the panic hook,
+/// which must not wait on a lock the panicking thread may hold).
+pub(crate) fn try_current(app: &AppHandle) -> Option<AppConfig> {
+    let state = app.try_state::<ConfigState>()?;
+    let config = state.config.try_lock().ok()?;
+    Some(config.clone())
+}
+
+/// Push `config` into the subsystems that keep their own copy. Failures
+/// are logged; the rest still applies.
+fn apply(app: &AppHandle, config: &AppConfig) {
+    if let Some(level) = &config.logging.level {
+        if let Err(e) = crate::logging::apply_level(app, level) {
+            tracing::warn!("[config] {}", e);
+        }
+    }
+    if let Some(db) = app.try_state::<DbState>() {
+        if let Ok(conn) = db.conn.lock() {
+            for path in &config.library.paths {
+                if let Err(e) = conn.execute("INSERT OR IGNORE INTO root_folders (path) VALUES (?1)", [path]) {
+                    tracing::warn!("[config] Failed to add library folder {}: {}", path, e);
+                }
+            }
+            if let Some(input) = &config.audio.input_device {
+                let saved = conn.execute(
+                    "INSERT OR REPLACE INTO app_settings (key, value) VALUES (?1, ?2)",
+                    (crate::audio::device_offsets::SELECTED_INPUT_KEY, input),
+                );
+                if let Err(e) = saved {
+                    tracing::warn!("[config] Failed to select input {}: {}", input, e);
+                }
+            }
+        }
+    }
+    if let Some(output) = &config.audio.output_device {
+        if let Err(e) = crate::audio::commands::set_primary_output(app, output) {
+            tracing::warn!("[config] {}", e);
+        }
+    }
+    crate::db::search::sync(app, &config.search);
+    crate::desktop::kiosk::sync(app, config.kiosk.enabled);
+    crate::desktop::hotkeys::sync(app, &config.hotkeys);
+    crate::midi_control::sync(app, &config.midi);
+    crate::lighting::sync(app, &config.lighting);
+    crate::multiroom::sync(app, &config.multiroom);
+    crate::server::security::sync(app, config.server.lan_access);
+    crate::telemetry::sync(app, &config.telemetry);
+    // The port is read where it is used (server start)
+}
+
+/// Parse and validate the text of a `config.toml` (e.g. from a backup).
+pub fn from_toml(text: &str) -> Result<AppConfig, String> {
+    let config = AppConfig::parse(text)?;
+    config.validate()?;
+    Ok(config)
+}
+
+/// Replace the config in memory and on disk, apply and announce it.
+pub fn update(app: &AppHandle, config: AppConfig) -> Result<AppConfig, String> {
+    config.validate()?;
+    let state = app.state::<ConfigState>();
+    let path = state.path.as_ref().ok_or("No config directory on this platform")?;
+    write_file(path, &config)?;
+    *state.modified.lock().map_err(|e| e.to_string())? = modified_at(path);
+    *state.config.lock().map_err(|e| e.to_string())? = config.clone();
+    apply(app, &config);
+    events::publish(app, AppEvent::ConfigChanged(config.redacted()));
+    Ok(config)
+}
+
+/// Watch `config.toml` for edits made outside the app.
+pub fn spawn_watcher(app: AppHandle) {
+    let supervisor = app.state::<TaskSupervisor>();
+    supervisor.spawn("config-watch", move |token| async move {
+        while sleep_or_cancel(&token, WATCH_INTERVAL).await {
+            reload_if_changed(&app);
+        }
+    });
+}
+
+fn reload_if_changed(app: &AppHandle) {
+    let state = app.state::<ConfigState>();
+    let Some(path) = &state.path else { return };
+    let modified = modified_at(path);
+    {
+        let Ok(mut last) = state.modified.lock() else { return };
+        if *last == modified {
+            return;
+        }
+        *last = modified;
+    }
+    let config = match read_file(path) {
+        Ok(config) => config,
+        Err(e) => {
+            // Keep the last good config; the next save is picked up again
+            tracing::warn!("[config] Ignoring edit: {}", e);
+            return;
+        }
+    };
+    {
+        let Ok(mut current) = state.config.lock() else { return };
+        if *current == config {
+            return;
+        }
+        *current = config.clone();
+    }
+    tracing::info!("[config] Reloaded {}", path.display());
+    apply(app, &config);
+    events::publish(app, AppEvent::ConfigChanged(config.redacted()));
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+/// The config in effect; secrets are redacted unless the caller may change
+/// settings.
+#[tauri::command]
+pub fn get_config(app: AppHandle, webview: tauri::Webview) -> AppConfig {
+    let config = current(&app);
+    if Principal::from_webview(&webview).role.allows(Capability::ChangeSettings) {
+        config
+    } else {
+        config.redacted()
+    }
+}
+
+/// Save the whole config; returns it as stored. Secrets sent back as
+/// `REDACTED` keep their stored value.
+#[tauri::command]
+pub fn set_config(app: AppHandle, webview: tauri::Webview, config: AppConfig) -> Result<AppConfig, String> {
+    require_webview(&webview, Capability::ChangeSettings)?;
+    let config = config.unredacted(&current(&app));
+    update(&app, config)
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partial_file_keeps_defaults() {
+        let config = AppConfig::parse("[kiosk]\nenabled = true\n[library]\npaths = [\"/songs\"]\n").unwrap();
+        assert!(config.kiosk.enabled);
+        assert_eq!(config.library.paths, vec!["/songs".to_string()]);
+        assert_eq!(config.server.preferred_port, crate::server::port::PREFERRED_PORT);
+        assert_eq!(config.logging.level, None);
+        assert!(!config.online.enabled);
+        assert_eq!(AppConfig::parse("").unwrap(), AppConfig::default());
+    }
+
+    #[test]
+    fn round_trips_and_validates() {
+        let mut config = AppConfig::default();
+        config.audio.output_device = Some("PA".to_string());
+        config.logging.level = Some("debug".to_string());
+        let text = toml::to_string_pretty(&config).unwrap();
+        assert_eq!(AppConfig::parse(&text).unwrap(), config);
+
+        config.server.preferred_port = 80;
+        assert!(config.validate().is_err());
+        config.server.preferred_port = 3000;
+        config.logging.level = Some("info,audio=shouting".to_string());
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn secrets_are_redacted_and_kept_on_save() {
+        let mut stored = AppConfig::default();
+        stored.multiroom.token = Some("guest-token".to_string());
+        let shown = stored.redacted();
+        assert_eq!(shown.multiroom.token.as_deref(), Some(REDACTED));
+        assert_eq!(shown.clone().unredacted(&stored), stored);
+
+        let mut cleared = shown;
+        cleared.multiroom.token = None;
+        assert_eq!(cleared.unredacted(&stored).multiroom.token, None);
+        assert_eq!(AppConfig::default().redacted(), AppConfig::default());
+    }
+}
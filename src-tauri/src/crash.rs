@@ -0,0 +1,305 @@
+//! Crash reports.
+//!
+//! A panic anywhere in the process, and every unexpected exit of the Node
+//! server (reported by `server::watchdog`), writes a plain-text report to
+//! `<log dir>/crashes/`:
+//!   - what crashed: the panic message, location and thread, or the
+//!     server's exit reason;
+//!   - the backtrace of the panicking thread, or the server's last output;
+//!   - the last `RECENT_LINES` log lines (kept in memory by a layer that
+//!     `logging::init` installs);
+//!   - the config in effect and the OS, app version and architecture.
+//!
+//! The newest `MAX_REPORTS` are kept. `export_crash_report` bundles them
+//! with a report of the current state into one zip for a bug report.
+
+use std::backtrace::Backtrace;
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+use tracing_subscriber::fmt::MakeWriter;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::access::{require_webview, Capability};
+
+/// Log lines kept for reports.
+pub const RECENT_LINES: usize = 200;
+const MAX_REPORTS: usize = 20;
+const CRASH_SUBDIR: &str = "crashes";
+const REPORT_PREFIX: &str = "crash-";
+
+static RECENT: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CrashKind {
+    Panic,
+    Server,
+}
+
+impl CrashKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            CrashKind::Panic => "panic",
+            CrashKind::Server => "server",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CrashReportInfo {
+    pub file: String,
+    pub kind: String,
+    /// Epoch ms.
+    pub created_at: i64,
+    /// First line of what crashed.
+    pub summary: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CrashExport {
+    pub path: String,
+    /// Crash reports in the bundle, besides the current state.
+    pub reports: usize,
+}
+
+// ---------------------------------------------------------------------------
+// Recent log lines
+// ---------------------------------------------------------------------------
+
+/// `MakeWriter` for a `fmt` layer that keeps the last lines in memory.
+#[derive(Clone, Copy, Default)]
+pub struct RecentLog;
+
+impl Write for RecentLog {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        // Never block or fail while the lines are taken for a report
+        if let Ok(mut recent) = RECENT.try_lock() {
+            for line in String::from_utf8_lossy(buf).lines().filter(|l| !l.trim().is_empty()) {
+                if recent.len() >= RECENT_LINES {
+                    recent.pop_front();
+                }
+                recent.push_back(line.to_string());
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for RecentLog {
+    type Writer = RecentLog;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RecentLog
+    }
+}
+
+fn recent_lines() -> Vec<String> {
+    RECENT.try_lock().map(|recent| recent.iter().cloned().collect()).unwrap_or_default()
+}
+
+// ---------------------------------------------------------------------------
+// Reports
+// ---------------------------------------------------------------------------
+
+fn crash_dir(app: &AppHandle) -> Option<PathBuf> {
+    app.path().app_log_dir().ok().map(|dir| dir.join(CRASH_SUBDIR))
+}
+
+fn section(report: &mut String, title: &str, body: &str) {
+    let _ = write!(report, "\n== {} ==\n{}\n", title, body.trim_end());
+}
+
+/// Full text of a report: `headline`, then the system, `detail` (title
+/// and body), the recent log and the config.
+fn report_text(app: &AppHandle, headline: &str, detail: (&str, &str)) -> String {
+    let mut report = format!("{}\n", headline);
+    let system = format!(
+        "app: {} {}\nos: {} ({} {})\nkernel: {}\ntime: {}",
+        app.package_info().name,
+        app.package_info().version,
+        sysinfo::System::long_os_version().unwrap_or_else(|| std::env::consts::OS.to_string()),
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        sysinfo::System::kernel_version().unwrap_or_default(),
+        crate::scheduler::now_ms(),
+    );
+    section(&mut report, "System", &system);
+    section(&mut report, detail.0, detail.1);
+    section(&mut report, &format!("Last {} log lines", RECENT_LINES), &recent_lines().join("\n"));
+    let config = crate::config::try_current(app)
+        .map(|config| toml::to_string_pretty(&config).unwrap_or_else(|e| format!("(not encodable: {})", e)))
+        .unwrap_or_else(|| "(unavailable)".to_string());
+    section(&mut report, "Config", &config);
+    report
+}
+
+/// Write a report and drop the oldest beyond `MAX_REPORTS`.
+fn save(app: &AppHandle, kind: CrashKind, text: &str) -> Result<PathBuf, String> {
+    let dir = crash_dir(app).ok_or("No log directory on this platform")?;
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let path = dir.join(format!("{}{}-{}.txt", REPORT_PREFIX, crate::scheduler::now_ms(), kind.as_str()));
+    fs::write(&path, text).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+
+    let reports = list_reports(&dir);
+    for old in reports.iter().skip(MAX_REPORTS) {
+        let _ = fs::remove_file(dir.join(&old.file));
+    }
+    Ok(path)
+}
+
+/// Reports in `dir`, newest first.
+fn list_reports(dir: &Path) -> Vec<CrashReportInfo> {
+    let Ok(entries) = fs::read_dir(dir) else { return Vec::new() };
+    let mut reports: Vec<CrashReportInfo> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let file = entry.file_name().to_string_lossy().to_string();
+            let (created_at, kind) = parse_name(&file)?;
+            let summary = fs::read_to_string(entry.path())
+                .ok()
+                .and_then(|text| text.lines().next().map(str::to_string))
+                .unwrap_or_default();
+            Some(CrashReportInfo { file, kind, created_at, summary })
+        })
+        .collect();
+    reports.sort_by(|a, b| b.created_at.cmp(&a.created_at).then_with(|| b.file.cmp(&a.file)));
+    reports
+}
+
+/// Time and kind from `crash-<ms>-<kind>.txt`.
+fn parse_name(file: &str) -> Option<(i64, String)> {
+    let stem = file.strip_prefix(REPORT_PREFIX)?.strip_suffix(".txt")?;
+    let (ms, kind) = stem.split_once('-')?;
+    Some((ms.parse().ok()?, kind.to_string()))
+}
+
+/// Install the panic hook. Call right after `logging::init`; the default
+/// hook still runs afterwards.
+pub fn install(app: &AppHandle) {
+    let app = app.clone();
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let message = info
+            .payload()
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| info.payload().downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "(no message)".to_string());
+        let location = info.location().map(|l| format!("{}:{}", l.file(), l.line())).unwrap_or_default();
+        let thread = std::thread::current().name().unwrap_or("unnamed").to_string();
+        let what = format!("{} at {} (thread {})", message, location, thread);
+        tracing::error!("[crash] Panic: {}", what);
+
+        let backtrace = Backtrace::force_capture().to_string();
+        let text = report_text(&app, &format!("panic: {}", what), ("Backtrace", &backtrace));
+        match save(&app, CrashKind::Panic, &text) {
+            Ok(path) => eprintln!("[crash] Report written to {}", path.display()),
+            Err(e) => eprintln!("[crash] Failed to write crash report: {}", e),
+        }
+        previous(info);
+    }));
+}
+
+/// Record an unexpected exit of the Node server with its last output.
+pub fn record_server_crash(app: &AppHandle, reason: &str, output: &[String]) {
+    let text = report_text(app, &format!("server crash: {}", reason), ("Server output", &output.join("\n")));
+    match save(app, CrashKind::Server, &text) {
+        Ok(path) => tracing::info!("[crash] Server crash report written to {}", path.display()),
+        Err(e) => tracing::warn!("[crash] {}", e),
+    }
+}
+
+fn export(app: &AppHandle, path: &Path) -> Result<CrashExport, String> {
+    let dir = crash_dir(app).ok_or("No log directory on this platform")?;
+    let reports = list_reports(&dir);
+    let file = File::create(path).map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+    let mut zip = ZipWriter::new(file);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    let files: Vec<&str> = reports.iter().map(|r| r.file.as_str()).collect();
+    let current = report_text(app, "state at export", ("Crash reports", &files.join("\n")));
+    let mut entries = vec![("current.txt".to_string(), current.into_bytes())];
+    for report in &reports {
+        if let Ok(bytes) = fs::read(dir.join(&report.file)) {
+            entries.push((report.file.clone(), bytes));
+        }
+    }
+    for (name, bytes) in entries {
+        zip.start_file(name.as_str(), options).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        zip.write_all(&bytes).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    }
+    zip.finish().map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(CrashExport { path: path.to_string_lossy().to_string(), reports: reports.len() })
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+/// Saved crash reports, newest first.
+#[tauri::command]
+pub fn get_crash_reports(app: AppHandle, webview: tauri::Webview) -> Result<Vec<CrashReportInfo>, String> {
+    require_webview(&webview, Capability::ChangeSettings)?;
+    Ok(crash_dir(&app).map(|dir| list_reports(&dir)).unwrap_or_default())
+}
+
+/// Zip the crash reports and the current state (log lines, config, OS) to
+/// `path`, by default `karaoke-crash-report-<ms>.zip` in the downloads folder.
+#[tauri::command]
+pub async fn export_crash_report(app: AppHandle, webview: tauri::Webview, path: Option<String>) -> Result<CrashExport, String> {
+    require_webview(&webview, Capability::ChangeSettings)?;
+    let path = match path {
+        Some(path) => PathBuf::from(path),
+        None => app
+            .path()
+            .download_dir()
+            .map_err(|e| format!("No downloads folder: {}", e))?
+            .join(format!("karaoke-crash-report-{}.zip", crate::scheduler::now_ms())),
+    };
+    tauri::async_runtime::spawn_blocking(move || export(&app, &path))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_recent_lines_and_lists_reports_newest_first() {
+        let mut writer = RecentLog.make_writer();
+        for i in 0..RECENT_LINES + 5 {
+            writer.write_all(format!("line {}\n", i).as_bytes()).unwrap();
+        }
+        let lines = recent_lines();
+        assert_eq!(lines.len(), RECENT_LINES);
+        assert_eq!(lines.last().map(String::as_str), Some(format!("line {}", RECENT_LINES + 4).as_str()));
+
+        let dir = crate::paths::test_dir("crash");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("crash-1000-panic.txt"), "panic: boom\n").unwrap();
+        fs::write(dir.join("crash-2000-server.txt"), "server crash: exit code 1\n").unwrap();
+        fs::write(dir.join("notes.txt"), "").unwrap();
+        let reports = list_reports(&dir);
+        assert_eq!(reports.iter().map(|r| r.kind.as_str()).collect::<Vec<_>>(), vec!["server", "panic"]);
+        assert_eq!(reports[1].summary, "panic: boom");
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -8,6 +8,8 @@ use serde::Serialize;
 use tauri::{AppHandle, Manager};
 
 use super::DbState;
+use crate::access::{require_webview, Capability};
+use crate::paths::nfc;
 use crate::try_log;
 
 // ====================================================================
@@ -25,8 +27,22 @@ pub struct DbResult {
 // Generic key-value settings
 // ====================================================================
 
+/// Settings with their own commands, kept out of the generic ones: the
+/// kiosk exit PIN may only change outside kiosk mode
+/// (`set_kiosk_exit_pin`).
+const PROTECTED_SETTINGS: &[&str] = &[crate::desktop::kiosk::PIN_SETTING];
+
+fn check_unprotected(key: &str) -> Result<(), String> {
+    if PROTECTED_SETTINGS.contains(&key) {
+        return Err(format!("Setting '{}' cannot be accessed here", key));
+    }
+    Ok(())
+}
+
 #[tauri::command]
-pub fn db_get_setting(app: AppHandle, key: String) -> Result<Option<String>, String> {
+pub fn db_get_setting(app: AppHandle, webview: tauri::Webview, key: String) -> Result<Option<String>, String> {
+    require_webview(&webview, Capability::ChangeSettings)?;
+    check_unprotected(&key)?;
     let state = app.state::<DbState>();
     let conn = state.conn.lock().map_err(|e| e.to_string())?;
     let result = conn.query_row(
@@ -42,7 +58,9 @@ pub fn db_get_setting(app: AppHandle, key: String) -> Result<Option<String>, Str
 }
 
 #[tauri::command]
-pub fn db_set_setting(app: AppHandle, key: String, value: String) -> Result<DbResult, String> {
+pub fn db_set_setting(app: AppHandle, webview: tauri::Webview, key: String, value: String) -> Result<DbResult, String> {
+    require_webview(&webview, Capability::ChangeSettings)?;
+    check_unprotected(&key)?;
     let state = app.state::<DbState>();
     let conn = state.conn.lock().map_err(|e| e.to_string())?;
     let rows = conn.execute(
@@ -57,7 +75,9 @@ pub fn db_set_setting(app: AppHandle, key: String, value: String) -> Result<DbRe
 }
 
 #[tauri::command]
-pub fn db_delete_setting(app: AppHandle, key: String) -> Result<DbResult, String> {
+pub fn db_delete_setting(app: AppHandle, webview: tauri::Webview, key: String) -> Result<DbResult, String> {
+    require_webview(&webview, Capability::ChangeSettings)?;
+    check_unprotected(&key)?;
     let state = app.state::<DbState>();
     let conn = state.conn.lock().map_err(|e| e.to_string())?;
     let rows = conn.execute(
@@ -72,7 +92,8 @@ pub fn db_delete_setting(app: AppHandle, key: String) -> Result<DbResult, String
 }
 
 #[tauri::command]
-pub fn db_get_all_settings(app: AppHandle) -> Result<Vec<(String, String)>, String> {
+pub fn db_get_all_settings(app: AppHandle, webview: tauri::Webview) -> Result<Vec<(String, String)>, String> {
+    require_webview(&webview, Capability::ChangeSettings)?;
     let state = app.state::<DbState>();
     let conn = state.conn.lock().map_err(|e| e.to_string())?;
     let mut stmt = conn
@@ -83,7 +104,7 @@ pub fn db_get_all_settings(app: AppHandle) -> Result<Vec<(String, String)>, Stri
         .map_err(|e| format!("db_get_all_settings query failed: {}", e))?
         .collect::<Result<Vec<_>, _>>()
         .map_err(|e| format!("db_get_all_settings collect failed: {}", e))?;
-    Ok(rows)
+    Ok(rows.into_iter().filter(|(key, _)| check_unprotected(key).is_ok()).collect())
 }
 
 // ====================================================================
@@ -91,7 +112,8 @@ pub fn db_get_all_settings(app: AppHandle) -> Result<Vec<(String, String)>, Stri
 // ====================================================================
 
 #[tauri::command]
-pub fn db_save_songs(app: AppHandle, songs_json: String) -> Result<DbResult, String> {
+pub fn db_save_songs(app: AppHandle, webview: tauri::Webview, songs_json: String) -> Result<DbResult, String> {
+    require_webview(&webview, Capability::ManageLibrary)?;
     let state = app.state::<DbState>();
     let mut conn = state.conn.lock().map_err(|e| e.to_string())?;
 
@@ -114,48 +136,7 @@ pub fn db_save_songs(app: AppHandle, songs_json: String) -> Result<DbResult, Str
             continue;
         }
 
-        tx.execute(
-            "INSERT OR REPLACE INTO songs (
-                id, title, artist, album, year, genre, duration, bpm,
-                difficulty, rating, gap, cover_image, video_background,
-                audio_url, has_embedded_audio, preview_start, preview_duration,
-                folder, folder_path, date_added, last_played, play_count,
-                audio_file_name, video_file_name, txt_file_name, cover_file_name, json_data
-            ) VALUES (
-                ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13,
-                ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24,
-                ?25, ?26, ?27
-            )",
-            rusqlite::params![
-                song.get("id").and_then(|v| v.as_str()).unwrap_or(""),
-                song.get("title").and_then(|v| v.as_str()).unwrap_or(""),
-                song.get("artist").and_then(|v| v.as_str()).unwrap_or(""),
-                song.get("album").and_then(|v| v.as_str()),
-                song.get("year").and_then(|v| v.as_i64()),
-                song.get("genre").and_then(|v| v.as_str()),
-                song.get("duration").and_then(|v| v.as_i64()).unwrap_or(0),
-                song.get("bpm").and_then(|v| v.as_f64()).unwrap_or(120.0),
-                song.get("difficulty").and_then(|v| v.as_str()).unwrap_or("medium"),
-                song.get("rating").and_then(|v| v.as_f64()).unwrap_or(0.0),
-                song.get("gap").and_then(|v| v.as_f64()).unwrap_or(0.0),
-                song.get("coverImage").and_then(|v| v.as_str()),
-                song.get("videoBackground").and_then(|v| v.as_str()),
-                song.get("audioUrl").and_then(|v| v.as_str()),
-                song.get("hasEmbeddedAudio").and_then(|v| v.as_i64()).unwrap_or(0),
-                song.get("preview").and_then(|v| v.get("startTime")).and_then(|v| v.as_i64()),
-                song.get("preview").and_then(|v| v.get("duration")).and_then(|v| v.as_i64()),
-                song.get("folder").and_then(|v| v.as_str()).unwrap_or(""),
-                song.get("folderPath").and_then(|v| v.as_str()).unwrap_or(""),
-                song.get("dateAdded").and_then(|v| v.as_i64()).unwrap_or(0),
-                song.get("lastPlayed").and_then(|v| v.as_i64()),
-                song.get("playCount").and_then(|v| v.as_i64()).unwrap_or(0),
-                song.get("audioFileName").and_then(|v| v.as_str()),
-                song.get("videoFileName").and_then(|v| v.as_str()),
-                song.get("txtFileName").and_then(|v| v.as_str()),
-                song.get("coverFileName").and_then(|v| v.as_str()),
-                song.to_string(), // store individual song JSON as json_data
-            ],
-        ).map_err(|e| format!("Failed to insert song: {}", e))?;
+        upsert_song(&tx, song)?;
         count += 1;
     }
 
@@ -167,6 +148,60 @@ pub fn db_save_songs(app: AppHandle, songs_json: String) -> Result<DbResult, Str
     })
 }
 
+/// Insert or replace one song row from its frontend JSON representation.
+/// Shared by `db_save_songs` and the native library scanner.
+///
+/// Text columns used for searching and path matching are NFC-normalized so
+/// songs synced from macOS (NFD names) match the same songs from Windows.
+pub(crate) fn upsert_song(conn: &rusqlite::Connection, song: &serde_json::Value) -> Result<usize, String> {
+    let text = |key: &str| song.get(key).and_then(|v| v.as_str()).map(nfc);
+    conn.execute(
+        "INSERT OR REPLACE INTO songs (
+            id, title, artist, album, year, genre, duration, bpm,
+            difficulty, rating, gap, cover_image, video_background,
+            audio_url, has_embedded_audio, preview_start, preview_duration,
+            folder, folder_path, date_added, last_played, play_count,
+            audio_file_name, video_file_name, txt_file_name, cover_file_name, json_data,
+            format, language
+        ) VALUES (
+            ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13,
+            ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24,
+            ?25, ?26, ?27, ?28, ?29
+        )",
+        rusqlite::params![
+            song.get("id").and_then(|v| v.as_str()).unwrap_or(""),
+            text("title").unwrap_or_default(),
+            text("artist").unwrap_or_default(),
+            text("album"),
+            song.get("year").and_then(|v| v.as_i64()),
+            text("genre"),
+            song.get("duration").and_then(|v| v.as_i64()).unwrap_or(0),
+            song.get("bpm").and_then(|v| v.as_f64()).unwrap_or(120.0),
+            song.get("difficulty").and_then(|v| v.as_str()).unwrap_or("medium"),
+            song.get("rating").and_then(|v| v.as_f64()).unwrap_or(0.0),
+            song.get("gap").and_then(|v| v.as_f64()).unwrap_or(0.0),
+            song.get("coverImage").and_then(|v| v.as_str()),
+            song.get("videoBackground").and_then(|v| v.as_str()),
+            song.get("audioUrl").and_then(|v| v.as_str()),
+            song.get("hasEmbeddedAudio").and_then(|v| v.as_i64()).unwrap_or(0),
+            song.get("preview").and_then(|v| v.get("startTime")).and_then(|v| v.as_i64()),
+            song.get("preview").and_then(|v| v.get("duration")).and_then(|v| v.as_i64()),
+            text("folder").unwrap_or_default(),
+            text("folderPath").unwrap_or_default(),
+            song.get("dateAdded").and_then(|v| v.as_i64()).unwrap_or(0),
+            song.get("lastPlayed").and_then(|v| v.as_i64()),
+            song.get("playCount").and_then(|v| v.as_i64()).unwrap_or(0),
+            text("audioFileName"),
+            text("videoFileName"),
+            text("txtFileName"),
+            text("coverFileName"),
+            song.to_string(), // store individual song JSON as json_data
+            text("format"),
+            text("language"),
+        ],
+    ).map_err(|e| format!("Failed to insert song: {}", e))
+}
+
 #[tauri::command]
 pub fn db_load_songs(app: AppHandle) -> Result<Vec<serde_json::Value>, String> {
     let state = app.state::<DbState>();
@@ -198,13 +233,29 @@ pub fn db_search_songs(app: AppHandle, query: String, limit: Option<i64>) -> Res
     let state = app.state::<DbState>();
     let conn = state.conn.lock().map_err(|e| e.to_string())?;
     let limit_val = limit.unwrap_or(100);
+
+    // Word-prefix matches from the full-text index come first, ranked
+    let mut seen = std::collections::HashSet::new();
+    let mut results: Vec<serde_json::Value> = Vec::new();
+    for (id, json_str) in super::search::ranked_matches(&conn, &query, limit_val)? {
+        if let Some(song) = try_log(serde_json::from_str(&json_str), "db_search_songs JSON parse") {
+            seen.insert(id);
+            results.push(song);
+        }
+    }
+    if results.len() as i64 >= limit_val {
+        return Ok(results);
+    }
+
+    // Then substring matches inside words ("mile" → "Smiles"), which the
+    // token index cannot answer
     // Escape LIKE wildcards (% and _) in user input to prevent pattern injection
-    let escaped_query = query.replace('%', r"\%").replace('_', r"\_");
+    let escaped_query = nfc(&query).replace('%', r"\%").replace('_', r"\_");
     let like_pattern = format!("%{}%", escaped_query);
 
     let mut stmt = conn
         .prepare(
-            "SELECT json_data FROM songs
+            "SELECT id, json_data FROM songs
              WHERE title LIKE ?1 ESCAPE '\\' OR artist LIKE ?1 ESCAPE '\\' OR album LIKE ?1 ESCAPE '\\'
              ORDER BY artist ASC, title ASC
              LIMIT ?2"
@@ -212,12 +263,15 @@ pub fn db_search_songs(app: AppHandle, query: String, limit: Option<i64>) -> Res
         .map_err(|e| format!("db_search_songs prepare failed: {}", e))?;
 
     let rows = stmt
-        .query_map(rusqlite::params![like_pattern, limit_val], |row| row.get::<_, String>(0))
+        .query_map(rusqlite::params![like_pattern, limit_val], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })
         .map_err(|e| format!("db_search_songs query failed: {}", e))?
         .filter_map(|r| try_log(r, "db_search_songs row"))
-        .filter_map(|json_str| try_log(serde_json::from_str(&json_str), "db_search_songs JSON parse"))
-        .collect();
-    Ok(rows)
+        .filter(|(id, _)| !seen.contains(id))
+        .filter_map(|(_, json_str)| try_log(serde_json::from_str(&json_str), "db_search_songs JSON parse"));
+    results.extend(rows.take((limit_val as usize).saturating_sub(results.len())));
+    Ok(results)
 }
 
 // ====================================================================
@@ -225,7 +279,8 @@ pub fn db_search_songs(app: AppHandle, query: String, limit: Option<i64>) -> Res
 // ====================================================================
 
 #[tauri::command]
-pub fn db_save_folders(app: AppHandle, folders_json: String) -> Result<DbResult, String> {
+pub fn db_save_folders(app: AppHandle, webview: tauri::Webview, folders_json: String) -> Result<DbResult, String> {
+    require_webview(&webview, Capability::ManageLibrary)?;
     let state = app.state::<DbState>();
     let mut conn = state.conn.lock().map_err(|e| e.to_string())?;
     let folders: Vec<serde_json::Value> = serde_json::from_str(&folders_json)
@@ -289,7 +344,8 @@ pub fn db_load_folders(app: AppHandle) -> Result<Vec<serde_json::Value>, String>
 // ====================================================================
 
 #[tauri::command]
-pub fn db_save_root_folders(app: AppHandle, paths: Vec<String>) -> Result<DbResult, String> {
+pub fn db_save_root_folders(app: AppHandle, webview: tauri::Webview, paths: Vec<String>) -> Result<DbResult, String> {
+    require_webview(&webview, Capability::ManageLibrary)?;
     let state = app.state::<DbState>();
     let mut conn = state.conn.lock().map_err(|e| e.to_string())?;
     let tx = conn.transaction()
@@ -330,21 +386,32 @@ pub fn db_load_root_folders(app: AppHandle) -> Result<Vec<String>, String> {
 // ====================================================================
 
 #[tauri::command]
-pub fn db_save_profile(app: AppHandle, profile_json: String) -> Result<DbResult, String> {
+pub fn db_save_profile(app: AppHandle, webview: tauri::Webview, profile_json: String) -> Result<DbResult, String> {
+    require_webview(&webview, Capability::ChangeSettings)?;
     let state = app.state::<DbState>();
     let conn = state.conn.lock().map_err(|e| e.to_string())?;
     let profile: serde_json::Value = serde_json::from_str(&profile_json)
         .map_err(|e| format!("Failed to parse profile JSON: {}", e))?;
 
+    // An upsert rather than a replace keeps the singer settings the
+    // frontend does not know about (see `profiles`)
     let rows = conn.execute(
-        "INSERT OR REPLACE INTO profiles (
+        "INSERT INTO profiles (
             id, name, avatar, color, total_score, games_played, songs_completed,
             achievements, stats, created_at, xp, level, is_guest, sync_token,
             last_sync_at, device_id, is_active, sync_code, json_data
         ) VALUES (
             ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14,
             ?15, ?16, ?17, ?18, ?19
-        )",
+        ) ON CONFLICT(id) DO UPDATE SET
+            name = excluded.name, avatar = excluded.avatar, color = excluded.color,
+            total_score = excluded.total_score, games_played = excluded.games_played,
+            songs_completed = excluded.songs_completed, achievements = excluded.achievements,
+            stats = excluded.stats, created_at = excluded.created_at, xp = excluded.xp,
+            level = excluded.level, is_guest = excluded.is_guest, sync_token = excluded.sync_token,
+            last_sync_at = excluded.last_sync_at, device_id = excluded.device_id,
+            is_active = excluded.is_active, sync_code = excluded.sync_code,
+            json_data = excluded.json_data",
         rusqlite::params![
             profile.get("id").and_then(|v| v.as_str()).unwrap_or(""),
             profile.get("name").and_then(|v| v.as_str()).unwrap_or(""),
@@ -391,7 +458,8 @@ pub fn db_load_profiles(app: AppHandle) -> Result<Vec<serde_json::Value>, String
 }
 
 #[tauri::command]
-pub fn db_delete_profile(app: AppHandle, profile_id: String) -> Result<DbResult, String> {
+pub fn db_delete_profile(app: AppHandle, webview: tauri::Webview, profile_id: String) -> Result<DbResult, String> {
+    require_webview(&webview, Capability::ChangeSettings)?;
     let state = app.state::<DbState>();
     let conn = state.conn.lock().map_err(|e| e.to_string())?;
     let rows = conn.execute("DELETE FROM profiles WHERE id = ?1", [&profile_id])
@@ -408,13 +476,24 @@ pub fn db_delete_profile(app: AppHandle, profile_id: String) -> Result<DbResult,
 // ====================================================================
 
 #[tauri::command]
-pub fn db_save_highscore(app: AppHandle, highscore_json: String) -> Result<DbResult, String> {
+pub fn db_save_highscore(app: AppHandle, webview: tauri::Webview, highscore_json: String) -> Result<DbResult, String> {
+    require_webview(&webview, Capability::ControlPlayback)?;
     let state = app.state::<DbState>();
     let conn = state.conn.lock().map_err(|e| e.to_string())?;
     let hs: serde_json::Value = serde_json::from_str(&highscore_json)
         .map_err(|e| format!("Failed to parse highscore JSON: {}", e))?;
+    let rows = insert_highscore(&conn, &hs, &highscore_json)?;
+    Ok(DbResult {
+        success: true,
+        rows_affected: rows,
+        message: "Highscore saved".to_string(),
+    })
+}
 
-    let rows = conn.execute(
+/// Insert a highscore in the frontend's JSON shape; `highscore_json` is
+/// stored verbatim as `json_data`.
+pub(crate) fn insert_highscore(conn: &rusqlite::Connection, hs: &serde_json::Value, highscore_json: &str) -> Result<usize, String> {
+    conn.execute(
         "INSERT INTO highscores (
             id, player_id, player_name, song_id, song_title, score, accuracy,
             max_combo, perfect_notes, good_notes, miss_notes, difficulty,
@@ -440,12 +519,7 @@ pub fn db_save_highscore(app: AppHandle, highscore_json: String) -> Result<DbRes
             hs.get("playedAt").and_then(|v| v.as_i64()).unwrap_or_else(|| chrono_now_ms() as i64),
             highscore_json,
         ],
-    ).map_err(|e| format!("db_save_highscore failed: {}", e))?;
-    Ok(DbResult {
-        success: true,
-        rows_affected: rows,
-        message: "Highscore saved".to_string(),
-    })
+    ).map_err(|e| format!("db_save_highscore failed: {}", e))
 }
 
 #[tauri::command]
@@ -507,7 +581,8 @@ pub fn db_load_highscores(
 // ====================================================================
 
 #[tauri::command]
-pub fn db_save_playlist(app: AppHandle, playlist_json: String) -> Result<DbResult, String> {
+pub fn db_save_playlist(app: AppHandle, webview: tauri::Webview, playlist_json: String) -> Result<DbResult, String> {
+    require_webview(&webview, Capability::ManageQueue)?;
     let state = app.state::<DbState>();
     let conn = state.conn.lock().map_err(|e| e.to_string())?;
     let pl: serde_json::Value = serde_json::from_str(&playlist_json)
@@ -564,7 +639,8 @@ pub fn db_load_playlists(app: AppHandle) -> Result<Vec<serde_json::Value>, Strin
 }
 
 #[tauri::command]
-pub fn db_delete_playlist(app: AppHandle, playlist_id: String) -> Result<DbResult, String> {
+pub fn db_delete_playlist(app: AppHandle, webview: tauri::Webview, playlist_id: String) -> Result<DbResult, String> {
+    require_webview(&webview, Capability::ManageQueue)?;
     let state = app.state::<DbState>();
     let conn = state.conn.lock().map_err(|e| e.to_string())?;
     let rows = conn.execute("DELETE FROM playlists WHERE id = ?1", [&playlist_id])
@@ -581,7 +657,8 @@ pub fn db_delete_playlist(app: AppHandle, playlist_id: String) -> Result<DbResul
 // ====================================================================
 
 #[tauri::command]
-pub fn db_clear_all(app: AppHandle) -> Result<DbResult, String> {
+pub fn db_clear_all(app: AppHandle, webview: tauri::Webview) -> Result<DbResult, String> {
+    require_webview(&webview, Capability::ChangeSettings)?;
     let state = app.state::<DbState>();
     let conn = state.conn.lock().map_err(|e| e.to_string())?;
     conn.execute_batch(
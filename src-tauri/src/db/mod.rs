@@ -9,9 +9,11 @@
 
 pub mod schema;
 pub mod commands;
+pub mod normalize;
+pub mod search;
 
 use std::sync::Mutex;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use rusqlite::Connection;
 use tauri::Manager;
 
@@ -25,17 +27,7 @@ impl DbState {
     /// Open (or create) the SQLite database at `db_path`.
     /// Runs migrations to ensure the schema is up to date.
     pub fn new(db_path: PathBuf) -> Result<Self, String> {
-        let conn = Connection::open(&db_path)
-            .map_err(|e| format!("Failed to open SQLite database: {}", e))?;
-
-        // Enable WAL mode for better concurrent read performance
-        conn.execute_batch("PRAGMA journal_mode=WAL; PRAGMA synchronous=NORMAL;")
-            .map_err(|e| format!("Failed to set SQLite pragmas: {}", e))?;
-
-        // Run schema migrations
-        schema::migrate(&conn)
-            .map_err(|e| format!("Schema migration failed: {}", e))?;
-
+        let conn = open_connection(&db_path)?;
         Ok(Self {
             conn: Mutex::new(conn),
             db_path,
@@ -43,6 +35,25 @@ impl DbState {
     }
 }
 
+/// Open the database at `db_path` with the app's pragmas and migrate it.
+/// Also used to reopen the database after a restore.
+pub fn open_connection(db_path: &Path) -> Result<Connection, String> {
+    let conn = Connection::open(db_path)
+        .map_err(|e| format!("Failed to open SQLite database: {}", e))?;
+
+    // Enable WAL mode for better concurrent read performance.
+    // recursive_triggers makes INSERT OR REPLACE fire the delete trigger
+    // for the replaced row, which keeps songs_fts consistent.
+    conn.execute_batch("PRAGMA journal_mode=WAL; PRAGMA synchronous=NORMAL; PRAGMA recursive_triggers=ON;")
+        .map_err(|e| format!("Failed to set SQLite pragmas: {}", e))?;
+
+    // Run schema migrations
+    schema::migrate(&conn)
+        .map_err(|e| format!("Schema migration failed: {}", e))?;
+
+    Ok(conn)
+}
+
 /// Determine the database file path using Tauri's app data directory.
 pub fn default_db_path(app: &tauri::AppHandle) -> Result<PathBuf, String> {
     let data_dir = app.path().app_data_dir()
@@ -54,3 +65,43 @@ pub fn default_db_path(app: &tauri::AppHandle) -> Result<PathBuf, String> {
 
     Ok(data_dir.join("karaoke.db"))
 }
+
+/// Bundle identifier from `tauri.conf.json`; names the app data directory.
+pub(crate) const APP_IDENTIFIER: &str = "com.karaoke.successor";
+
+/// Database path without a running Tauri app (CLI mode).
+///
+/// Mirrors Tauri's `app_data_dir()` resolution so the CLI and the GUI share
+/// the same database:
+///   - Windows: `%APPDATA%\<identifier>`
+///   - macOS:   `~/Library/Application Support/<identifier>`
+///   - Linux:   `$XDG_DATA_HOME/<identifier>` or `~/.local/share/<identifier>`
+pub fn headless_db_path() -> Result<PathBuf, String> {
+    let base = if cfg!(target_os = "windows") {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        std::env::var_os("HOME").map(|h| PathBuf::from(h).join("Library").join("Application Support"))
+    } else {
+        std::env::var_os("XDG_DATA_HOME")
+            .filter(|v| !v.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".local").join("share")))
+    };
+
+    let data_dir = base
+        .ok_or("Cannot determine the app data directory")?
+        .join(APP_IDENTIFIER);
+    std::fs::create_dir_all(&data_dir)
+        .map_err(|e| format!("Failed to create app data dir: {}", e))?;
+
+    Ok(data_dir.join("karaoke.db"))
+}
+
+/// An in-memory database with the app's pragmas and schema, for tests.
+#[cfg(test)]
+pub(crate) fn test_conn() -> Connection {
+    let conn = Connection::open_in_memory().unwrap();
+    conn.execute_batch("PRAGMA recursive_triggers=ON;").unwrap();
+    schema::migrate(&conn).unwrap();
+    conn
+}
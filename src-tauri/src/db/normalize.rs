@@ -0,0 +1,409 @@
+//! Text normalization for the search index.
+//!
+//! Titles are indexed through the `search_index` SQL function (registered
+//! on every connection by `schema::migrate`) instead of as stored, and
+//! queries go through the same folding, so:
+//!   - compatibility forms, case, katakana versus hiragana and
+//!     Latin/Greek/Cyrillic accents do not matter: "celine" finds "Céline",
+//!     "ＡＢＢＡ" finds "ABBA", "strasse" finds "Straße", "ｶﾗｵｹ" finds
+//!     "からおけ". Marks of other scripts (Devanagari vowel signs, kana
+//!     voicing) are kept, they change the word;
+//!   - runs of Han, kana or Thai, which are written without spaces, are
+//!     split into overlapping character pairs, so "事変" finds "東京事変".
+//!     A Han character is a word of its own too, so "愛" finds "恋愛";
+//!   - kana, Cyrillic and Greek are also indexed romanized: "arigatou" and
+//!     "arigato" find "ありがとう", "kalinka" finds "Калинка". Kanji need
+//!     a dictionary and stay as they are.
+//!
+//! The pair splitting (`Tokenizer`) can be set per song language and the
+//! romanization turned off in `[search]` of `config.toml`; a change
+//! rebuilds the index (see `search::sync`).
+
+use std::collections::BTreeMap;
+use std::sync::{LazyLock, RwLock};
+
+use rusqlite::functions::FunctionFlags;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::config::SearchConfig;
+
+/// How the words of a song's titles are split into index terms.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Tokenizer {
+    /// Words, with runs of Han, kana or Thai split into character pairs.
+    #[default]
+    Auto,
+    /// Words between spaces and punctuation only.
+    Words,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Settings {
+    transliterate: bool,
+    /// By lower-cased song language.
+    tokenizers: BTreeMap<String, Tokenizer>,
+}
+
+static SETTINGS: LazyLock<RwLock<Settings>> =
+    LazyLock::new(|| RwLock::new(Settings { transliterate: true, tokenizers: BTreeMap::new() }));
+
+/// Index new and changed songs with `config` from now on.
+pub fn configure(config: &SearchConfig) {
+    let settings = Settings {
+        transliterate: config.transliterate,
+        tokenizers: config.tokenizers.iter().map(|(language, t)| (language.trim().to_lowercase(), *t)).collect(),
+    };
+    *SETTINGS.write().unwrap_or_else(|e| e.into_inner()) = settings;
+}
+
+/// Register `search_index(text, language)` on `conn`; the FTS triggers
+/// call it.
+pub(crate) fn register(conn: &Connection) -> Result<(), String> {
+    conn.create_scalar_function("search_index", 2, FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_INNOCUOUS, |ctx| {
+        let text: Option<String> = ctx.get(0)?;
+        let language: Option<String> = ctx.get(1)?;
+        Ok(text.map(|text| index_text(&text, language.as_deref())))
+    })
+    .map_err(|e| format!("Failed to register search_index: {}", e))
+}
+
+// ---------------------------------------------------------------------------
+// Folding and splitting
+// ---------------------------------------------------------------------------
+
+/// Scripts whose accents are spelling variants, not different letters.
+fn strips_marks(base: char) -> bool {
+    matches!(base as u32, 0x0000..=0x052F | 0x1E00..=0x1FFF)
+}
+
+/// Written without spaces between words.
+fn is_dense(c: char) -> bool {
+    matches!(c as u32, 0x3040..=0x30FF | 0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xF900..=0xFAFF | 0x20000..=0x2FFFF | 0x0E00..=0x0E7F)
+}
+
+/// Han characters, which carry a meaning on their own.
+fn is_han(c: char) -> bool {
+    matches!(c as u32, 0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xF900..=0xFAFF | 0x20000..=0x2FFFF)
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || is_combining_mark(c)
+}
+
+/// Compatibility-decomposed, case-folded, katakana as hiragana, without
+/// Latin, Greek and Cyrillic accents.
+pub fn fold(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut strip = false;
+    for c in text.nfkd() {
+        if is_combining_mark(c) {
+            if !strip {
+                stripped.push(c);
+            }
+        } else {
+            strip = strips_marks(c);
+            stripped.push(c);
+        }
+    }
+    let mut folded = String::with_capacity(stripped.len());
+    for c in stripped.nfc() {
+        match c {
+            'ß' | 'ẞ' => folded.push_str("ss"),
+            'ς' => folded.push('σ'),
+            'ァ'..='ヶ' => folded.extend(hiragana(c)),
+            c => folded.extend(c.to_lowercase()),
+        }
+    }
+    folded
+}
+
+/// Words of folded text, each split where it changes between dense and
+/// spaced scripts.
+fn segments(folded: &str) -> Vec<&str> {
+    let mut segments = Vec::new();
+    for word in folded.split(|c: char| !is_word_char(c)).filter(|w| !w.is_empty()) {
+        let mut start = 0;
+        let mut dense = None;
+        for (i, c) in word.char_indices() {
+            // Marks belong to the letter before them
+            let this = if is_combining_mark(c) { dense } else { Some(is_dense(c)) };
+            if dense.is_some() && this != dense {
+                segments.push(&word[start..i]);
+                start = i;
+            }
+            dense = this;
+        }
+        segments.push(&word[start..]);
+    }
+    segments
+}
+
+/// Overlapping character pairs of a dense run longer than two characters;
+/// empty otherwise.
+pub fn bigrams(segment: &str) -> Vec<String> {
+    let chars: Vec<char> = segment.chars().collect();
+    if chars.len() <= 2 || !chars.iter().copied().any(is_dense) {
+        return Vec::new();
+    }
+    chars.windows(2).map(|pair| pair.iter().collect()).collect()
+}
+
+/// The single Han characters of a dense run longer than one character,
+/// each once.
+fn unigrams(segment: &str) -> Vec<String> {
+    let mut singles: Vec<String> = Vec::new();
+    if segment.chars().count() > 1 {
+        for c in segment.chars().filter(|&c| is_han(c)) {
+            let single = c.to_string();
+            if !singles.contains(&single) {
+                singles.push(single);
+            }
+        }
+    }
+    singles
+}
+
+/// Folded query words, split like indexed text; dense runs stay whole (see
+/// `bigrams` for their pairs).
+pub fn query_words(query: &str) -> Vec<String> {
+    segments(&fold(query)).into_iter().map(str::to_string).collect()
+}
+
+// ---------------------------------------------------------------------------
+// Romanization
+// ---------------------------------------------------------------------------
+
+/// Hepburn for U+3041–U+3096; katakana map onto it.
+const HIRAGANA: [&str; 86] = [
+    "a", "a", "i", "i", "u", "u", "e", "e", "o", "o", // ぁ–お
+    "ka", "ga", "ki", "gi", "ku", "gu", "ke", "ge", "ko", "go", // か–ご
+    "sa", "za", "shi", "ji", "su", "zu", "se", "ze", "so", "zo", // さ–ぞ
+    "ta", "da", "chi", "ji", "", "tsu", "zu", "te", "de", "to", "do", // た–ど
+    "na", "ni", "nu", "ne", "no", // な–の
+    "ha", "ba", "pa", "hi", "bi", "pi", "fu", "bu", "pu", "he", "be", "pe", "ho", "bo", "po", // は–ぽ
+    "ma", "mi", "mu", "me", "mo", // ま–も
+    "ya", "ya", "yu", "yu", "yo", "yo", // ゃ–よ
+    "ra", "ri", "ru", "re", "ro", // ら–ろ
+    "wa", "wa", "i", "e", "o", "n", "vu", "ka", "ke", // ゎ–ゖ
+];
+
+const SMALL_TSU: char = 'っ';
+const PROLONGED: char = 'ー';
+
+fn hiragana(c: char) -> Option<char> {
+    match c as u32 {
+        0x3041..=0x3096 => Some(c),
+        0x30A1..=0x30F6 => char::from_u32(c as u32 - 0x60),
+        _ => None,
+    }
+}
+
+fn is_kana(c: char) -> bool {
+    c == PROLONGED || hiragana(c).is_some()
+}
+
+fn is_vowel(c: char) -> bool {
+    matches!(c, 'a' | 'i' | 'u' | 'e' | 'o')
+}
+
+fn romaji(kana: &str) -> String {
+    let mut out = String::with_capacity(kana.len());
+    let mut double_next = false;
+    for c in kana.chars() {
+        if c == PROLONGED {
+            if let Some(vowel) = out.chars().last().filter(|c| is_vowel(*c)) {
+                out.push(vowel);
+            }
+            continue;
+        }
+        let Some(h) = hiragana(c) else { continue };
+        if h == SMALL_TSU {
+            double_next = true;
+            continue;
+        }
+        let syllable = HIRAGANA[(h as u32 - 0x3041) as usize];
+        let after_vowel = out.chars().last().is_some_and(is_vowel);
+        match h {
+            // きゃ kya, しゃ sha, ちゃ cha, じゃ ja
+            'ゃ' | 'ゅ' | 'ょ' if out.len() >= 2 && out.ends_with('i') => {
+                out.pop();
+                let palatal = out.ends_with("sh") || out.ends_with("ch") || out.ends_with('j');
+                out.push_str(if palatal { &syllable[1..] } else { syllable });
+            }
+            // ファ fa, ティ ti, シェ she, ウィ wi
+            'ぁ' | 'ぃ' | 'ぅ' | 'ぇ' | 'ぉ' if after_vowel => {
+                out.pop();
+                if out.chars().last().is_none_or(is_vowel) {
+                    out.push('w');
+                }
+                out.push_str(syllable);
+            }
+            _ => {
+                if std::mem::take(&mut double_next) {
+                    match syllable.chars().next() {
+                        Some('c') => out.push('t'),
+                        Some(first) if !is_vowel(first) && first != 'n' => out.push(first),
+                        _ => {}
+                    }
+                }
+                out.push_str(syllable);
+            }
+        }
+    }
+    out
+}
+
+/// Lower-case Cyrillic (Russian, Ukrainian, Belarusian) and Greek
+/// letters, as usually spelled in Latin.
+const LETTERS: &[(char, &str)] = &[
+    ('а', "a"), ('б', "b"), ('в', "v"), ('г', "g"), ('ґ', "g"), ('д', "d"), ('е', "e"), ('ё', "e"), ('є', "ye"),
+    ('ж', "zh"), ('з', "z"), ('и', "i"), ('і', "i"), ('ї', "yi"), ('й', "y"), ('к', "k"), ('л', "l"), ('м', "m"),
+    ('н', "n"), ('о', "o"), ('п', "p"), ('р', "r"), ('с', "s"), ('т', "t"), ('у', "u"), ('ў', "u"), ('ф', "f"),
+    ('х', "kh"), ('ц', "ts"), ('ч', "ch"), ('ш', "sh"), ('щ', "shch"), ('ъ', ""), ('ы', "y"), ('ь', ""), ('э', "e"),
+    ('ю', "yu"), ('я', "ya"),
+    ('α', "a"), ('β', "v"), ('γ', "g"), ('δ', "d"), ('ε', "e"), ('ζ', "z"), ('η', "i"), ('θ', "th"), ('ι', "i"),
+    ('κ', "k"), ('λ', "l"), ('μ', "m"), ('ν', "n"), ('ξ', "x"), ('ο', "o"), ('π', "p"), ('ρ', "r"), ('σ', "s"),
+    ('ς', "s"), ('τ', "t"), ('υ', "y"), ('φ', "f"), ('χ', "ch"), ('ψ', "ps"), ('ω', "o"),
+];
+
+fn latin(c: char) -> Option<&'static str> {
+    let find = |c: char| LETTERS.iter().find(|(letter, _)| *letter == c).map(|(_, latin)| *latin);
+    match find(c) {
+        Some(latin) => Some(latin),
+        // Greek tonos and dialytika off; й and ё are letters of their own
+        None if matches!(c as u32, 0x0370..=0x03FF | 0x1F00..=0x1FFF) => c.nfd().next().and_then(find),
+        None => None,
+    }
+}
+
+/// `text` with kana, Cyrillic and Greek romanized; `None` if it has none.
+pub fn romanize(text: &str) -> Option<String> {
+    let lower: String = text.nfkc().flat_map(char::to_lowercase).collect();
+    let mut out = String::with_capacity(lower.len());
+    let mut kana = String::new();
+    let mut changed = false;
+    for c in lower.chars() {
+        if is_kana(c) {
+            kana.push(c);
+            continue;
+        }
+        if !kana.is_empty() {
+            out.push_str(&romaji(&std::mem::take(&mut kana)));
+            changed = true;
+        }
+        match latin(c) {
+            Some(latin) => {
+                out.push_str(latin);
+                changed = true;
+            }
+            None => out.push(c),
+        }
+    }
+    if !kana.is_empty() {
+        out.push_str(&romaji(&kana));
+        changed = true;
+    }
+    changed.then_some(out)
+}
+
+/// Hepburn without long vowels, as titles are usually spelled: "toukyou"
+/// to "tokyo".
+fn short_vowels(word: &str) -> String {
+    word.replace("ou", "o").replace("uu", "u")
+}
+
+// ---------------------------------------------------------------------------
+// Index text
+// ---------------------------------------------------------------------------
+
+/// Index terms of `text`, space-separated, for a song in `language`.
+pub fn index_text(text: &str, language: Option<&str>) -> String {
+    let settings = SETTINGS.read().unwrap_or_else(|e| e.into_inner());
+    let tokenizer = language
+        .and_then(|language| settings.tokenizers.get(&language.trim().to_lowercase()))
+        .copied()
+        .unwrap_or_default();
+    index_text_with(text, tokenizer, settings.transliterate)
+}
+
+fn index_text_with(text: &str, tokenizer: Tokenizer, transliterate: bool) -> String {
+    let folded = fold(text);
+    let mut terms: Vec<String> = match tokenizer {
+        Tokenizer::Words => folded.split(|c: char| !is_word_char(c)).filter(|w| !w.is_empty()).map(str::to_string).collect(),
+        Tokenizer::Auto => segments(&folded)
+            .into_iter()
+            .flat_map(|segment| {
+                let pairs = bigrams(segment);
+                let mut terms = if pairs.is_empty() { vec![segment.to_string()] } else { pairs };
+                terms.extend(unigrams(segment));
+                terms
+            })
+            .collect(),
+    };
+    if let Some(roman) = romanize(text).filter(|_| transliterate) {
+        let roman = fold(&roman);
+        // Kanji left in the romanized text are indexed already
+        for segment in segments(&roman).into_iter().filter(|s| !s.chars().any(is_dense)) {
+            for term in [segment.to_string(), short_vowels(segment)] {
+                if !terms.contains(&term) {
+                    terms.push(term);
+                }
+            }
+        }
+    }
+    terms.join(" ")
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn folds_accents_case_and_compatibility_forms() {
+        assert_eq!(fold("Céline DION"), "celine dion");
+        assert_eq!(fold("ＡＢＢＡ"), "abba");
+        assert_eq!(fold("Straße"), "strasse");
+        assert_eq!(fold("Ёлка"), "елка");
+        assert_eq!(fold("ガッツ"), "がっつ");
+        // Halfwidth katakana too, voicing kept
+        assert_eq!(fold("ｶﾞｯﾂ"), "がっつ");
+        assert_eq!(fold("नमस्ते"), "नमस्ते");
+    }
+
+    #[test]
+    fn splits_dense_scripts_into_pairs() {
+        assert_eq!(index_text_with("東京事変", Tokenizer::Auto, false), "東京 京事 事変 東 京 事 変");
+        assert_eq!(index_text_with("恋愛", Tokenizer::Auto, false), "恋愛 恋 愛");
+        assert_eq!(index_text_with("東京事変", Tokenizer::Words, false), "東京事変");
+        assert_eq!(index_text_with("Love東京", Tokenizer::Auto, false), "love 東京 東 京");
+        assert_eq!(query_words("ＡＣ/ＤＣ 東京事変"), ["ac", "dc", "東京事変"]);
+        assert!(bigrams("abc").is_empty());
+    }
+
+    #[test]
+    fn romanizes_kana_cyrillic_and_greek() {
+        assert_eq!(romanize("ありがとう").as_deref(), Some("arigatou"));
+        assert_eq!(romanize("とうきょう").as_deref(), Some("toukyou"));
+        assert_eq!(romanize("チャーハン").as_deref(), Some("chaahan"));
+        assert_eq!(romanize("きって").as_deref(), Some("kitte"));
+        assert_eq!(romanize("まっちゃ").as_deref(), Some("matcha"));
+        assert_eq!(romanize("ファイト").as_deref(), Some("faito"));
+        assert_eq!(romanize("Калинка").as_deref(), Some("kalinka"));
+        assert_eq!(romanize("Мой").as_deref(), Some("moy"));
+        assert_eq!(romanize("Ελλάδα").as_deref(), Some("ellada"));
+        assert_eq!(romanize("Queen"), None);
+
+        let terms = index_text_with("とうきょう", Tokenizer::Auto, true);
+        assert_eq!(terms, "とう うき きょ ょう toukyou tokyo");
+        assert_eq!(index_text_with("君の名は", Tokenizer::Auto, true), "君の の名 名は 君 名 no ha");
+        assert_eq!(index_text_with("とうきょう", Tokenizer::Auto, false), "とう うき きょ ょう");
+    }
+}
@@ -4,14 +4,63 @@
 //! playlists, and app_settings tables.
 //!
 //! Version 2: Add viral_hits table for chart-matching feature.
+//!
+//! Version 3: Add deleted_songs_journal for undoing library deletions.
+//!
+//! Version 4: Add the songs_fts full-text index, kept in sync incrementally
+//! by triggers on songs (requires `PRAGMA recursive_triggers`, see `DbState`).
+//!
+//! Version 5: Add songs.format and songs.language (backfilled from
+//! json_data) with indexes for filtered library searches.
+//!
+//! Version 6: Weight title over artist over album in the FTS rank and add
+//! the songs_fts_vocab term table used for typo-tolerant search.
+//!
+//! Version 7: Add song_loudness with the measured loudness and playback
+//! gain of each song's audio (see `audio::loudness`).
+//!
+//! Version 8: Add queue_entries for the singer rotation (see `queue`).
+//!
+//! Version 9: Add performances, the history of who sang what (see `history`).
+//!
+//! Version 10: Add profiles.key_offset and profiles.mic_volume, the singer
+//! settings applied when their turn comes (see `profiles`).
+//!
+//! Version 11: Add downloads, the persistent download queue (see
+//! `downloads`).
+//!
+//! Version 12: Add jobs, the persistent background job queue (see `jobs`).
+//!
+//! Version 13: Rebuild songs_fts as a self-contained index of normalized
+//! terms, filled by the `search_index` function (see `normalize`).
+//!
+//! Version 14: Add song_codes, the song book numbers guests request by
+//! (see `library::songbook`).
 
 use rusqlite::Connection;
 
+use super::normalize;
+
 /// Current schema version. Increment for each migration.
-const SCHEMA_VERSION: i32 = 2;
+pub const SCHEMA_VERSION: i32 = 14;
+
+/// Schema version recorded in `conn`; 0 for a new database.
+pub fn version(conn: &Connection) -> i32 {
+    conn.query_row(
+        "SELECT COALESCE(
+            (SELECT CAST(value AS INTEGER) FROM _schema_meta WHERE key = 'version'),
+            0
+        )",
+        [],
+        |row| row.get(0),
+    ).unwrap_or(0)
+}
 
 /// Run all pending migrations.
 pub fn migrate(conn: &Connection) -> Result<(), String> {
+    // The FTS triggers call it on every write to songs
+    normalize::register(conn)?;
+
     // Create a metadata table to track schema version
     conn.execute_batch(
         "CREATE TABLE IF NOT EXISTS _schema_meta (
@@ -20,14 +69,7 @@ pub fn migrate(conn: &Connection) -> Result<(), String> {
         );"
     ).map_err(|e| format!("Failed to create _schema_meta: {}", e))?;
 
-    let current_version: i32 = conn.query_row(
-        "SELECT COALESCE(
-            (SELECT CAST(value AS INTEGER) FROM _schema_meta WHERE key = 'version'),
-            0
-        )",
-        [],
-        |row| row.get(0),
-    ).unwrap_or(0);
+    let current_version = version(conn);
 
     if current_version < 1 {
         migrate_v1(conn)?;
@@ -37,6 +79,53 @@ pub fn migrate(conn: &Connection) -> Result<(), String> {
         migrate_v2(conn)?;
     }
 
+    if current_version < 3 {
+        migrate_v3(conn)?;
+    }
+
+    if current_version < 4 {
+        migrate_v4(conn)?;
+    }
+
+    if current_version < 5 {
+        migrate_v5(conn)?;
+    }
+
+    if current_version < 6 {
+        migrate_v6(conn)?;
+    }
+
+    if current_version < 7 {
+        migrate_v7(conn)?;
+    }
+
+    if current_version < 8 {
+        migrate_v8(conn)?;
+    }
+
+    if current_version < 9 {
+        migrate_v9(conn)?;
+    }
+
+    if current_version < 10 {
+        migrate_v10(conn)?;
+    }
+
+    if current_version < 11 {
+        migrate_v11(conn)?;
+    }
+
+    if current_version < 12 {
+        migrate_v12(conn)?;
+    }
+
+    if current_version < 13 {
+        migrate_v13(conn)?;
+    }
+    if current_version < 14 {
+        migrate_v14(conn)?;
+    }
+
     // Update schema version
     conn.execute(
         "INSERT OR REPLACE INTO _schema_meta (key, value) VALUES ('version', ?1)",
@@ -211,3 +300,323 @@ fn migrate_v2(conn: &Connection) -> Result<(), String> {
 
     Ok(())
 }
+
+fn migrate_v3(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(
+        "
+        -- ============================================================
+        -- Undo journal for songs deleted from the library
+        -- ============================================================
+        CREATE TABLE IF NOT EXISTS deleted_songs_journal (
+            batch_id    INTEGER NOT NULL,
+            song_id     TEXT    NOT NULL,
+            song_json   TEXT    NOT NULL,
+            files       TEXT    NOT NULL DEFAULT '[]',
+            deleted_at  INTEGER NOT NULL,
+            PRIMARY KEY (batch_id, song_id)
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_deleted_songs_batch ON deleted_songs_journal(batch_id DESC);
+        "
+    ).map_err(|e| format!("Migration v3 failed: {}", e))?;
+
+    Ok(())
+}
+
+fn migrate_v4(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(
+        "
+        -- ============================================================
+        -- Full-text search index over songs (external content table)
+        -- ============================================================
+        -- remove_diacritics 2: 'celine' matches 'Céline'
+        CREATE VIRTUAL TABLE IF NOT EXISTS songs_fts USING fts5(
+            title, artist, album,
+            content = 'songs',
+            content_rowid = 'rowid',
+            tokenize = 'unicode61 remove_diacritics 2'
+        );
+
+        -- Each library change touches only its own rows: no full rebuilds,
+        -- so search stays warm while imports are running
+        CREATE TRIGGER IF NOT EXISTS songs_fts_insert AFTER INSERT ON songs BEGIN
+            INSERT INTO songs_fts(rowid, title, artist, album)
+            VALUES (new.rowid, new.title, new.artist, new.album);
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS songs_fts_delete AFTER DELETE ON songs BEGIN
+            INSERT INTO songs_fts(songs_fts, rowid, title, artist, album)
+            VALUES ('delete', old.rowid, old.title, old.artist, old.album);
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS songs_fts_update AFTER UPDATE ON songs BEGIN
+            INSERT INTO songs_fts(songs_fts, rowid, title, artist, album)
+            VALUES ('delete', old.rowid, old.title, old.artist, old.album);
+            INSERT INTO songs_fts(rowid, title, artist, album)
+            VALUES (new.rowid, new.title, new.artist, new.album);
+        END;
+
+        -- One-time backfill of the existing library
+        INSERT INTO songs_fts(songs_fts) VALUES ('rebuild');
+        "
+    ).map_err(|e| format!("Migration v4 failed: {}", e))?;
+
+    Ok(())
+}
+
+fn migrate_v5(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(
+        "
+        -- ============================================================
+        -- Filterable song columns (format from the native scanner)
+        -- ============================================================
+        ALTER TABLE songs ADD COLUMN format TEXT;
+        ALTER TABLE songs ADD COLUMN language TEXT;
+
+        UPDATE songs SET
+            format   = json_extract(json_data, '$.format'),
+            language = json_extract(json_data, '$.language')
+        WHERE json_valid(json_data);
+
+        CREATE INDEX IF NOT EXISTS idx_songs_genre    ON songs(genre);
+        CREATE INDEX IF NOT EXISTS idx_songs_language ON songs(language);
+        CREATE INDEX IF NOT EXISTS idx_songs_format   ON songs(format);
+        CREATE INDEX IF NOT EXISTS idx_songs_year     ON songs(year);
+        "
+    ).map_err(|e| format!("Migration v5 failed: {}", e))?;
+
+    Ok(())
+}
+
+fn migrate_v6(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(
+        "
+        -- ============================================================
+        -- Search ranking and vocabulary
+        -- ============================================================
+        -- Persistent rank function: a title hit outranks an album hit
+        INSERT INTO songs_fts(songs_fts, rank) VALUES ('rank', 'bm25(10.0, 5.0, 1.0)');
+
+        -- Every indexed term with its document count, read-only view of songs_fts
+        CREATE VIRTUAL TABLE IF NOT EXISTS songs_fts_vocab USING fts5vocab('songs_fts', 'row');
+        "
+    ).map_err(|e| format!("Migration v6 failed: {}", e))?;
+
+    Ok(())
+}
+
+fn migrate_v7(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(
+        "
+        -- ============================================================
+        -- Loudness normalisation (EBU R128)
+        -- ============================================================
+        -- Separate from songs: library saves replace whole song rows
+        CREATE TABLE IF NOT EXISTS song_loudness (
+            song_id          TEXT PRIMARY KEY,
+            audio_path       TEXT    NOT NULL,
+            integrated_lufs  REAL,
+            peak_dbfs        REAL    NOT NULL,
+            gain_db          REAL    NOT NULL DEFAULT 0,
+            analyzed_at      INTEGER NOT NULL
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_song_loudness_path ON song_loudness(audio_path);
+        "
+    ).map_err(|e| format!("Migration v7 failed: {}", e))?;
+
+    Ok(())
+}
+
+fn migrate_v8(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(
+        "
+        -- ============================================================
+        -- Singer rotation queue
+        -- ============================================================
+        -- state: waiting | current | done (only the last one is kept)
+        CREATE TABLE IF NOT EXISTS queue_entries (
+            id          INTEGER PRIMARY KEY AUTOINCREMENT,
+            singer      TEXT    NOT NULL,
+            song_id     TEXT    NOT NULL,
+            position    INTEGER NOT NULL DEFAULT 0,
+            state       TEXT    NOT NULL DEFAULT 'waiting',
+            added_at    INTEGER NOT NULL,
+            started_at  INTEGER
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_queue_state ON queue_entries(state, position);
+        "
+    ).map_err(|e| format!("Migration v8 failed: {}", e))?;
+
+    Ok(())
+}
+
+fn migrate_v9(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(
+        "
+        -- ============================================================
+        -- Performance history
+        -- ============================================================
+        -- Song title and artist are copied: history outlives the library
+        CREATE TABLE IF NOT EXISTS performances (
+            id              INTEGER PRIMARY KEY AUTOINCREMENT,
+            song_id         TEXT    NOT NULL,
+            song_title      TEXT    NOT NULL,
+            song_artist     TEXT,
+            singer          TEXT    NOT NULL,
+            started_at      INTEGER NOT NULL,
+            ended_at        INTEGER,
+            score           REAL,
+            key_offset      INTEGER NOT NULL DEFAULT 0,
+            queue_entry_id  INTEGER
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_performances_started ON performances(started_at);
+        CREATE INDEX IF NOT EXISTS idx_performances_song    ON performances(song_id);
+        CREATE INDEX IF NOT EXISTS idx_performances_singer  ON performances(singer COLLATE NOCASE);
+        "
+    ).map_err(|e| format!("Migration v9 failed: {}", e))?;
+
+    Ok(())
+}
+
+fn migrate_v10(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(
+        "
+        -- ============================================================
+        -- Singer settings on profiles
+        -- ============================================================
+        ALTER TABLE profiles ADD COLUMN key_offset INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE profiles ADD COLUMN mic_volume REAL    NOT NULL DEFAULT 1.0;
+
+        CREATE INDEX IF NOT EXISTS idx_profiles_name_nocase ON profiles(name COLLATE NOCASE);
+        "
+    ).map_err(|e| format!("Migration v10 failed: {}", e))?;
+
+    Ok(())
+}
+
+fn migrate_v11(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(
+        "
+        -- ============================================================
+        -- Download queue
+        -- ============================================================
+        -- file_name stays NULL until the first response names the file;
+        -- validator is the ETag or Last-Modified resumes are checked with
+        CREATE TABLE IF NOT EXISTS downloads (
+            id           INTEGER PRIMARY KEY AUTOINCREMENT,
+            url          TEXT    NOT NULL,
+            dest_dir     TEXT    NOT NULL,
+            file_name    TEXT,
+            sha256       TEXT,
+            status       TEXT    NOT NULL DEFAULT 'queued',
+            bytes_done   INTEGER NOT NULL DEFAULT 0,
+            bytes_total  INTEGER,
+            validator    TEXT,
+            import       INTEGER NOT NULL DEFAULT 1,
+            error        TEXT,
+            created_at   INTEGER NOT NULL,
+            updated_at   INTEGER NOT NULL
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_downloads_status ON downloads(status);
+        "
+    ).map_err(|e| format!("Migration v11 failed: {}", e))?;
+
+    Ok(())
+}
+
+fn migrate_v12(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(
+        "
+        -- ============================================================
+        -- Background job queue
+        -- ============================================================
+        -- spec is the job as JSON; finished jobs are deleted
+        CREATE TABLE IF NOT EXISTS jobs (
+            id           INTEGER PRIMARY KEY AUTOINCREMENT,
+            kind         TEXT    NOT NULL,
+            spec         TEXT    NOT NULL,
+            priority     INTEGER NOT NULL DEFAULT 0,
+            status       TEXT    NOT NULL DEFAULT 'queued',
+            done         INTEGER NOT NULL DEFAULT 0,
+            total        INTEGER,
+            created_at   INTEGER NOT NULL,
+            updated_at   INTEGER NOT NULL
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_jobs_status ON jobs(status, priority);
+        "
+    ).map_err(|e| format!("Migration v12 failed: {}", e))?;
+
+    Ok(())
+}
+
+fn migrate_v13(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(
+        "
+        -- ============================================================
+        -- Normalized search index
+        -- ============================================================
+        -- Terms are computed in Rust, so the index keeps its own copy
+        -- instead of reading songs (external content would need the
+        -- exact old terms to delete a row)
+        DROP TRIGGER IF EXISTS songs_fts_insert;
+        DROP TRIGGER IF EXISTS songs_fts_delete;
+        DROP TRIGGER IF EXISTS songs_fts_update;
+        DROP TABLE IF EXISTS songs_fts_vocab;
+        DROP TABLE IF EXISTS songs_fts;
+
+        CREATE VIRTUAL TABLE songs_fts USING fts5(
+            title, artist, album,
+            tokenize = 'unicode61 remove_diacritics 2'
+        );
+
+        CREATE TRIGGER songs_fts_insert AFTER INSERT ON songs BEGIN
+            INSERT INTO songs_fts(rowid, title, artist, album)
+            VALUES (new.rowid, search_index(new.title, new.language),
+                    search_index(new.artist, new.language), search_index(new.album, new.language));
+        END;
+
+        CREATE TRIGGER songs_fts_delete AFTER DELETE ON songs BEGIN
+            DELETE FROM songs_fts WHERE rowid = old.rowid;
+        END;
+
+        CREATE TRIGGER songs_fts_update AFTER UPDATE ON songs BEGIN
+            DELETE FROM songs_fts WHERE rowid = old.rowid;
+            INSERT INTO songs_fts(rowid, title, artist, album)
+            VALUES (new.rowid, search_index(new.title, new.language),
+                    search_index(new.artist, new.language), search_index(new.album, new.language));
+        END;
+
+        INSERT INTO songs_fts(rowid, title, artist, album)
+        SELECT rowid, search_index(title, language), search_index(artist, language), search_index(album, language)
+        FROM songs;
+
+        INSERT INTO songs_fts(songs_fts, rank) VALUES ('rank', 'bm25(10.0, 5.0, 1.0)');
+        CREATE VIRTUAL TABLE songs_fts_vocab USING fts5vocab('songs_fts', 'row');
+        "
+    ).map_err(|e| format!("Migration v13 failed: {}", e))?;
+
+    Ok(())
+}
+
+fn migrate_v14(conn: &Connection) -> Result<(), String> {
+    conn.execute_batch(
+        "
+        -- ============================================================
+        -- Song book codes
+        -- ============================================================
+        -- Given on first printing and never reused; a row outlives its
+        -- song, so a song that is rescanned gets its code back
+        CREATE TABLE IF NOT EXISTS song_codes (
+            code         INTEGER PRIMARY KEY AUTOINCREMENT,
+            song_id      TEXT    NOT NULL UNIQUE
+        );
+        "
+    ).map_err(|e| format!("Migration v14 failed: {}", e))?;
+
+    Ok(())
+}
@@ -0,0 +1,444 @@
+//! Song search over the `songs_fts` full-text index.
+//!
+//! The index is maintained by triggers (schema v4), so every upsert or
+//! delete updates only its own rows and searches never wait for a rebuild.
+//! `filtered_search` combines it with the filter columns added in v5.
+//!
+//! `fast_search` adds typo tolerance for large libraries: when the prefix
+//! match comes up short, each query word is widened with indexed terms a
+//! small edit distance away (looked up in `songs_fts_vocab`, schema v6) and
+//! the search runs again. Ranking is bm25 weighted title > artist > album.
+//!
+//! Since v13 the index holds folded, split and romanized terms (see
+//! `normalize`) and queries are folded the same way; `sync` rebuilds it
+//! when `[search]` in `config.toml` changes how titles are indexed.
+
+use std::collections::HashSet;
+
+use rusqlite::types::ToSql;
+use rusqlite::Connection;
+use serde::Deserialize;
+use tauri::{AppHandle, Manager};
+
+use super::normalize;
+use super::DbState;
+use crate::config::SearchConfig;
+use crate::paths::nfc;
+use crate::scheduler::read_setting;
+
+/// Similar terms tried per query word.
+const MAX_ALTERNATIVES: usize = 3;
+/// `[search]` the index was last built with, see `index_settings`.
+const INDEX_SETTINGS_KEY: &str = "search_index_settings";
+/// Bumped when `normalize` indexes the same settings differently.
+const INDEX_VERSION: u32 = 2;
+
+/// MATCH term for one folded query word: a prefix, or for a run of Han,
+/// kana or Thai, its character pairs (songs indexed without pairs still
+/// match the whole run).
+fn match_term(word: &str) -> String {
+    // Quoted, so FTS5 operators (AND, NEAR, -) in user input stay literal
+    let prefix = format!("\"{}\"*", word);
+    let pairs = normalize::bigrams(word);
+    if pairs.is_empty() {
+        return prefix;
+    }
+    let pairs: Vec<String> = pairs.iter().map(|pair| format!("\"{}\"*", pair)).collect();
+    format!("({} OR ({}))", prefix, pairs.join(" "))
+}
+
+/// FTS5 MATCH expression for a user query: every word must match as a
+/// prefix of some title/artist/album token. `None` if the query has no
+/// searchable words.
+pub fn fts_query(query: &str) -> Option<String> {
+    let terms: Vec<String> = normalize::query_words(query).iter().map(|w| match_term(w)).collect();
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+/// `(id, json_data)` of matching songs, best match first (bm25 rank).
+pub fn ranked_matches(conn: &Connection, query: &str, limit: i64) -> Result<Vec<(String, String)>, String> {
+    match fts_query(query) {
+        Some(expr) => run_match(conn, &expr, limit),
+        None => Ok(Vec::new()),
+    }
+}
+
+fn run_match(conn: &Connection, expr: &str, limit: i64) -> Result<Vec<(String, String)>, String> {
+    let mut stmt = conn
+        .prepare_cached(
+            "SELECT s.id, s.json_data FROM songs_fts f JOIN songs s ON s.rowid = f.rowid
+             WHERE songs_fts MATCH ?1
+             ORDER BY f.rank
+             LIMIT ?2",
+        )
+        .map_err(|e| format!("search prepare failed: {}", e))?;
+    let rows = stmt
+        .query_map(rusqlite::params![expr, limit], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?.unwrap_or_default()))
+        })
+        .map_err(|e| format!("search query failed: {}", e))?
+        .flatten()
+        .collect();
+    Ok(rows)
+}
+
+/// Folded like indexed terms.
+pub(crate) fn fold_term(word: &str) -> String {
+    normalize::fold(word)
+}
+
+/// Typos tolerated in a word of `len` characters.
+fn max_typos(len: usize) -> usize {
+    match len {
+        0..=3 => 0,
+        4..=6 => 1,
+        _ => 2,
+    }
+}
+
+fn levenshtein(a: &[char], b: &[char]) -> usize {
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut row = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = prev[j] + usize::from(ca != cb);
+            row[j + 1] = substitute.min(prev[j + 1] + 1).min(row[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut row);
+    }
+    prev[b.len()]
+}
+
+/// Indexed terms within the typo budget of `word` (folded), closest and
+/// most common first. Only terms with the same first letter are read:
+/// typos rarely hit it, and it keeps the vocabulary scan to one range.
+fn similar_terms(conn: &Connection, word: &str) -> Result<Vec<String>, String> {
+    let chars: Vec<char> = word.chars().collect();
+    let budget = max_typos(chars.len());
+    let Some(first) = chars.first().copied().filter(|_| budget > 0) else {
+        return Ok(Vec::new());
+    };
+    let after = char::from_u32(first as u32 + 1).map(String::from).unwrap_or_default();
+    let mut stmt = conn
+        .prepare_cached("SELECT term, doc FROM songs_fts_vocab WHERE term >= ?1 AND term < ?2")
+        .map_err(|e| format!("vocabulary lookup failed: {}", e))?;
+    let mut similar: Vec<(usize, i64, String)> = stmt
+        .query_map(rusqlite::params![first.to_string(), after], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+        })
+        .map_err(|e| format!("vocabulary lookup failed: {}", e))?
+        .flatten()
+        .filter_map(|(term, docs)| {
+            let term_chars: Vec<char> = term.chars().collect();
+            if term_chars.len().abs_diff(chars.len()) > budget {
+                return None;
+            }
+            let distance = levenshtein(&chars, &term_chars);
+            (distance > 0 && distance <= budget).then_some((distance, -docs, term))
+        })
+        .collect();
+    similar.sort();
+    Ok(similar.into_iter().take(MAX_ALTERNATIVES).map(|(_, _, term)| term).collect())
+}
+
+/// MATCH expression accepting, for each word, its prefix or a similar
+/// indexed term. `None` if no word has a similar term.
+fn fuzzy_query(conn: &Connection, query: &str) -> Result<Option<String>, String> {
+    let mut widened = false;
+    let mut groups = Vec::new();
+    for word in normalize::query_words(query) {
+        // Character pairs are not misspelt
+        let alternatives =
+            if normalize::bigrams(&word).is_empty() { similar_terms(conn, &word)? } else { Vec::new() };
+        widened |= !alternatives.is_empty();
+        let options: Vec<String> = std::iter::once(match_term(&word))
+            .chain(alternatives.iter().map(|t| format!("\"{}\"", t)))
+            .collect();
+        groups.push(format!("({})", options.join(" OR ")));
+    }
+    Ok(widened.then(|| groups.join(" ")))
+}
+
+/// Result of `fast_search`.
+pub struct FastMatches {
+    pub rows: Vec<(String, String)>,
+    /// Some rows only matched after typo correction.
+    pub fuzzy: bool,
+}
+
+/// Prefix search, topped up with typo-tolerant matches when it finds fewer
+/// than `limit` songs.
+pub fn fast_search(conn: &Connection, query: &str, limit: i64) -> Result<FastMatches, String> {
+    let mut rows = ranked_matches(conn, query, limit)?;
+    let mut fuzzy = false;
+    if (rows.len() as i64) < limit {
+        if let Some(expr) = fuzzy_query(conn, query)? {
+            let seen: HashSet<String> = rows.iter().map(|(id, _)| id.clone()).collect();
+            let extra: Vec<_> = run_match(conn, &expr, limit)?
+                .into_iter()
+                .filter(|(id, _)| !seen.contains(id))
+                .take(limit as usize - rows.len())
+                .collect();
+            fuzzy = !extra.is_empty();
+            rows.extend(extra);
+        }
+    }
+    Ok(FastMatches { rows, fuzzy })
+}
+
+/// Index every song again with the current `normalize` settings.
+pub fn reindex(conn: &Connection) -> Result<usize, String> {
+    conn.execute("DELETE FROM songs_fts", []).map_err(|e| format!("Failed to clear the search index: {}", e))?;
+    conn.execute(
+        "INSERT INTO songs_fts(rowid, title, artist, album)
+         SELECT rowid, search_index(title, language), search_index(artist, language), search_index(album, language)
+         FROM songs",
+        [],
+    )
+    .map_err(|e| format!("Failed to rebuild the search index: {}", e))
+}
+
+/// How an index built with `config` is recorded, as JSON.
+fn index_settings(config: &SearchConfig) -> String {
+    serde_json::json!({ "version": INDEX_VERSION, "search": config }).to_string()
+}
+
+/// Whether the index in `conn` was built other than `wanted`, or by a
+/// version that did not record it.
+fn index_outdated(conn: &Connection, wanted: &str) -> bool {
+    read_setting(conn, INDEX_SETTINGS_KEY).as_deref() != Some(wanted)
+}
+
+fn rebuild(conn: &Connection, wanted: &str) -> Result<usize, String> {
+    let songs = reindex(conn)?;
+    conn.execute("INSERT OR REPLACE INTO app_settings (key, value) VALUES (?1, ?2)", (INDEX_SETTINGS_KEY, wanted))
+        .map_err(|e| format!("Failed to save search settings: {}", e))?;
+    Ok(songs)
+}
+
+/// Apply `[search]`; rebuilds the index in the background when it was
+/// built with other settings. Call again after the database is replaced.
+pub fn sync(app: &AppHandle, config: &SearchConfig) {
+    normalize::configure(config);
+    let wanted = index_settings(config);
+    let Some(db) = app.try_state::<DbState>() else { return };
+    {
+        let Ok(conn) = db.conn.lock() else { return };
+        if !index_outdated(&conn, &wanted) {
+            return;
+        }
+    }
+    let app = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let db = app.state::<DbState>();
+        let Ok(conn) = db.conn.lock() else { return };
+        match rebuild(&conn, &wanted) {
+            Ok(songs) => tracing::info!("[search] Reindexed {} songs", songs),
+            Err(e) => tracing::warn!("[search] {}", e),
+        }
+    });
+}
+
+/// `sync` without an app (CLI): rebuilds right away. Returns the songs
+/// indexed, if it had to.
+pub fn sync_now(conn: &Connection, config: &SearchConfig) -> Result<Option<usize>, String> {
+    normalize::configure(config);
+    let wanted = index_settings(config);
+    if !index_outdated(conn, &wanted) {
+        return Ok(None);
+    }
+    rebuild(conn, &wanted).map(Some)
+}
+
+/// Narrows a library search; unset fields match everything.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SongFilters {
+    /// Exact artist (case-insensitive).
+    pub artist: Option<String>,
+    pub genre: Option<String>,
+    pub language: Option<String>,
+    /// `ultrastar`, `cdg`, `zip` or `video`.
+    pub format: Option<String>,
+    pub year_from: Option<i64>,
+    pub year_to: Option<i64>,
+}
+
+/// Append `filters` to `sql` as ` AND ...` clauses, their values to
+/// `params`. Placeholders are numbered after the params already there.
+pub(crate) fn push_filters(sql: &mut String, params: &mut Vec<Box<dyn ToSql>>, filters: &SongFilters) {
+    // `clause` has a `?` where its parameter goes
+    let mut push = |clause: &str, value: Box<dyn ToSql>| {
+        params.push(value);
+        sql.push_str(" AND ");
+        sql.push_str(&clause.replace('?', &format!("?{}", params.len())));
+    };
+    if let Some(artist) = &filters.artist {
+        push("s.artist = ? COLLATE NOCASE", Box::new(nfc(artist)));
+    }
+    if let Some(genre) = &filters.genre {
+        push("s.genre = ? COLLATE NOCASE", Box::new(nfc(genre)));
+    }
+    if let Some(language) = &filters.language {
+        push("s.language = ? COLLATE NOCASE", Box::new(nfc(language)));
+    }
+    if let Some(format) = &filters.format {
+        push("s.format = ?", Box::new(format.clone()));
+    }
+    if let Some(from) = filters.year_from {
+        push("s.year >= ?", Box::new(from));
+    }
+    if let Some(to) = filters.year_to {
+        push("s.year <= ?", Box::new(to));
+    }
+}
+
+/// `json_data` of songs matching `query` (FTS prefix match, ranked; all
+/// songs by artist/title for an empty query) and `filters`.
+pub fn filtered_search(
+    conn: &Connection,
+    query: &str,
+    filters: &SongFilters,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<String>, String> {
+    let expr = fts_query(query);
+    let mut sql = String::from(match expr {
+        Some(_) => "SELECT s.json_data FROM songs_fts f JOIN songs s ON s.rowid = f.rowid WHERE songs_fts MATCH ?1",
+        None => "SELECT s.json_data FROM songs s WHERE 1 = 1",
+    });
+    let mut params: Vec<Box<dyn ToSql>> = Vec::new();
+    if let Some(expr) = expr.clone() {
+        params.push(Box::new(expr));
+    }
+    push_filters(&mut sql, &mut params, filters);
+    sql.push_str(if expr.is_some() { " ORDER BY f.rank" } else { " ORDER BY s.artist, s.title" });
+    params.push(Box::new(limit));
+    params.push(Box::new(offset));
+    sql.push_str(&format!(" LIMIT ?{} OFFSET ?{}", params.len() - 1, params.len()));
+
+    let mut stmt = conn.prepare(&sql).map_err(|e| format!("search prepare failed: {}", e))?;
+    let param_refs: Vec<&dyn ToSql> = params.iter().map(|p| p.as_ref()).collect();
+    let rows = stmt
+        .query_map(param_refs.as_slice(), |row| row.get::<_, Option<String>>(0))
+        .map_err(|e| format!("search query failed: {}", e))?
+        .flatten()
+        .flatten()
+        .collect();
+    Ok(rows)
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn search_ids(conn: &Connection, query: &str) -> Vec<String> {
+        ranked_matches(conn, query, 10).unwrap().into_iter().map(|(id, _)| id).collect()
+    }
+
+    #[test]
+    fn builds_quoted_prefix_terms() {
+        assert_eq!(fts_query("queen bohem").as_deref(), Some("\"queen\"* \"bohem\"*"));
+        assert_eq!(fts_query("AC/DC - NEAR").as_deref(), Some("\"ac\"* \"dc\"* \"near\"*"));
+        assert_eq!(fts_query("事変").as_deref(), Some("\"事変\"*"));
+        assert_eq!(fts_query("東京事変").as_deref(), Some("(\"東京事変\"* OR (\"東京\"* \"京事\"* \"事変\"*))"));
+        assert_eq!(fts_query("  -- "), None);
+    }
+
+    #[test]
+    fn index_follows_upserts_and_deletes() {
+        let conn = crate::db::test_conn();
+
+        let insert = "INSERT OR REPLACE INTO songs (id, title, artist, folder, folder_path, date_added)
+                      VALUES (?1, ?2, ?3, '', '', 0)";
+        conn.execute(insert, ["a", "Bohemian Rhapsody", "Queen"]).unwrap();
+        conn.execute(insert, ["b", "Pour que tu m'aimes encore", "Céline Dion"]).unwrap();
+        assert_eq!(search_ids(&conn, "celine"), vec!["b"]);
+
+        // Replacing a row must not leave the old title searchable
+        conn.execute(insert, ["a", "Radio Ga Ga", "Queen"]).unwrap();
+        assert!(search_ids(&conn, "bohemian").is_empty());
+        assert_eq!(search_ids(&conn, "radio"), vec!["a"]);
+
+        conn.execute("DELETE FROM songs WHERE id = 'a'", []).unwrap();
+        assert!(search_ids(&conn, "queen").is_empty());
+    }
+
+    #[test]
+    fn typos_are_corrected_after_exact_matches() {
+        let conn = crate::db::test_conn();
+
+        let insert = "INSERT INTO songs (id, title, artist, folder, folder_path, date_added) VALUES (?1, ?2, ?3, '', '', 0)";
+        conn.execute(insert, ["a", "Bohemian Rhapsody", "Queen"]).unwrap();
+        conn.execute(insert, ["b", "Queen of the Night", "Whitney Houston"]).unwrap();
+        conn.execute(insert, ["c", "Pour que tu m'aimes encore", "Céline Dion"]).unwrap();
+
+        let ids = |query: &str| -> (Vec<String>, bool) {
+            let found = fast_search(&conn, query, 10).unwrap();
+            (found.rows.into_iter().map(|(id, _)| id).collect(), found.fuzzy)
+        };
+        // Title hit ranks above the artist hit
+        assert_eq!(ids("queen"), (vec!["b".to_string(), "a".to_string()], false));
+        assert_eq!(ids("bohemain rapsody"), (vec!["a".to_string()], true));
+        assert_eq!(ids("selene"), (Vec::new(), false));
+        assert_eq!(ids("cline"), (vec!["c".to_string()], true));
+        assert_eq!(levenshtein(&['a', 'b'], &['b', 'a']), 2);
+    }
+
+    #[test]
+    fn matches_across_scripts_and_spellings() {
+        let conn = crate::db::test_conn();
+
+        let insert = "INSERT INTO songs (id, title, artist, language, folder, folder_path, date_added)
+                      VALUES (?1, ?2, ?3, ?4, '', '', 0)";
+        conn.execute(insert, ["a", "群青日和", "東京事変", "Japanese"]).unwrap();
+        conn.execute(insert, ["b", "ありがとう", "いきものがかり", "Japanese"]).unwrap();
+        conn.execute(insert, ["c", "Калинка", "Хор Александрова", "Russian"]).unwrap();
+        conn.execute(insert, ["d", "Die Straße", "ＡＢＢＡ", "German"]).unwrap();
+        conn.execute(insert, ["e", "恋愛", "", "Japanese"]).unwrap();
+
+        assert_eq!(search_ids(&conn, "事変"), vec!["a"]);
+        assert_eq!(search_ids(&conn, "東京事変"), vec!["a"]);
+        assert_eq!(search_ids(&conn, "愛"), vec!["e"]);
+        assert_eq!(search_ids(&conn, "arigato"), vec!["b"]);
+        assert_eq!(search_ids(&conn, "ikimono"), vec!["b"]);
+        assert_eq!(search_ids(&conn, "ｱﾘｶﾞﾄｳ"), vec!["b"]);
+        assert_eq!(search_ids(&conn, "kalinka"), vec!["c"]);
+        assert_eq!(search_ids(&conn, "калинка"), vec!["c"]);
+        assert_eq!(search_ids(&conn, "strasse abba"), vec!["d"]);
+
+        conn.execute("UPDATE songs SET title = 'Lovers' WHERE id = 'b'", []).unwrap();
+        assert!(search_ids(&conn, "arigato").is_empty());
+        assert_eq!(reindex(&conn).unwrap(), 5);
+        assert_eq!(search_ids(&conn, "lovers"), vec!["b"]);
+
+        // Built by the migration without a record: rebuilt once
+        assert_eq!(sync_now(&conn, &SearchConfig::default()).unwrap(), Some(5));
+        assert_eq!(sync_now(&conn, &SearchConfig::default()).unwrap(), None);
+    }
+
+    #[test]
+    fn filters_combine_with_and_without_query() {
+        let conn = crate::db::test_conn();
+
+        let insert = "INSERT INTO songs (id, title, artist, year, format, folder, folder_path, date_added, json_data)
+                      VALUES (?1, ?2, 'Queen', ?3, ?4, '', '', 0, ?1)";
+        conn.execute(insert, rusqlite::params!["a", "Radio Ga Ga", 1984, "cdg"]).unwrap();
+        conn.execute(insert, rusqlite::params!["b", "Bohemian Rhapsody", 1975, "ultrastar"]).unwrap();
+        conn.execute(insert, rusqlite::params!["c", "Radio Song", 1991, "ultrastar"]).unwrap();
+
+        let cdg = SongFilters { format: Some("cdg".to_string()), ..SongFilters::default() };
+        assert_eq!(filtered_search(&conn, "", &cdg, 10, 0).unwrap(), vec!["a"]);
+        let queen_70s = SongFilters { artist: Some("queen".to_string()), year_to: Some(1980), ..SongFilters::default() };
+        assert_eq!(filtered_search(&conn, "", &queen_70s, 10, 0).unwrap(), vec!["b"]);
+        let ultrastar = SongFilters { format: Some("ultrastar".to_string()), ..SongFilters::default() };
+        assert_eq!(filtered_search(&conn, "radio", &ultrastar, 10, 0).unwrap(), vec!["c"]);
+        // Paging over the whole library, artist then title
+        assert_eq!(filtered_search(&conn, "", &SongFilters::default(), 2, 1).unwrap(), vec!["a", "c"]);
+    }
+}
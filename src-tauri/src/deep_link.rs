@@ -0,0 +1,479 @@
+//! `karaoke://` deep-link handling.
+//!
+//! # Enqueue grammar
+//!
+//! Community song sites can offer "sing this now" buttons that target a
+//! locally running host:
+//!
+//! ```text
+//! karaoke://enqueue?<target>[&singer=<name>]
+//! karaoke://queue?<target>[&singer=<name>]    same as enqueue
+//! karaoke://play?<target>[&singer=<name>]     sung next, ahead of the rotation
+//!
+//! <target> := song=<song id>        library song id   [A-Za-z0-9_.-]{1,128}
+//!           | songId=<song id>      same as song
+//!           | code=<song code>      songbook code     [A-Za-z0-9-]{1,16}
+//!           | url=<external url>    https URL on an allowed host (max 2048)
+//!           | file=<absolute path>  notes, audio or video file of a library song
+//! <name>   := display name, max 64 characters, control characters stripped
+//! ```
+//!
+//! Exactly one target is required; values are percent-decoded. Unknown
+//! parameters are rejected so typos do not silently change meaning.
+//!
+//! The scheme is registered with the OS through the deep-link plugin. A
+//! link that starts the app arrives in its argv (on macOS as an open-URL
+//! event), one for a running app through the single-instance forwarding;
+//! both go through `launch`, which holds them until the frontend listens.
+//!
+//! # Authorization
+//!
+//! Links can be triggered by any web page, so they are not trusted:
+//!   - `deep_link_enqueue_policy` setting: `ask` (default — the frontend
+//!     shows a confirmation), `allow`, or `deny`;
+//!   - external URLs must be on `deep_link_allowed_hosts` (comma separated,
+//!     defaults to YouTube/Vimeo/SoundCloud). The host as `Url` parses it
+//!     must equal an entry: name every subdomain you trust;
+//!   - song ids and files must belong to the local library.
+//!
+//! With the `allow` policy library songs go straight into the singer
+//! rotation (see `queue`), as "Guest" when the link names no singer.
+//! `play` links still need a confirmation then: putting a song ahead of
+//! everyone waiting is not for any web page to decide.
+//! Accepted requests are emitted as `deep-link://enqueue` either way, with
+//! the queue entry when one was added; rejected ones as
+//! `deep-link://rejected` with the reason. Links confirmed inside the app
+//! (the clipboard prompt) go through the same checks via
+//! `enqueue_confirmed`; only the confirmation is skipped.
+
+use rusqlite::OptionalExtension;
+use serde::Serialize;
+use tauri::{AppHandle, Manager, Url};
+
+use crate::db::DbState;
+use crate::events::{publish, AppEvent};
+use crate::launch::URL_SCHEME;
+use crate::paths::path_key;
+use crate::queue::{self, QueueEntry};
+
+pub const ENQUEUE_EVENT: &str = "deep-link://enqueue";
+pub const REJECTED_EVENT: &str = "deep-link://rejected";
+
+const POLICY_KEY: &str = "deep_link_enqueue_policy";
+const ALLOWED_HOSTS_KEY: &str = "deep_link_allowed_hosts";
+const DEFAULT_ALLOWED_HOSTS: &[&str] = &[
+    "youtube.com",
+    "www.youtube.com",
+    "m.youtube.com",
+    "music.youtube.com",
+    "youtu.be",
+    "vimeo.com",
+    "www.vimeo.com",
+    "soundcloud.com",
+    "www.soundcloud.com",
+    "m.soundcloud.com",
+];
+
+const MAX_SONG_ID_LEN: usize = 128;
+const MAX_CODE_LEN: usize = 16;
+const MAX_URL_LEN: usize = 2048;
+const MAX_SINGER_LEN: usize = 64;
+const MAX_PATH_LEN: usize = 4096;
+/// Queued under this name when a link names no singer.
+const DEFAULT_SINGER: &str = "Guest";
+
+/// What to enqueue.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum EnqueueTarget {
+    Song { id: String },
+    Code { code: String },
+    Url { url: String },
+    /// Resolved to the library song it belongs to before it is published.
+    File { path: String },
+}
+
+/// A parsed `karaoke://enqueue` link.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EnqueueLink {
+    pub target: EnqueueTarget,
+    pub singer: Option<String>,
+    /// `play` links: sung next instead of at the fair place.
+    pub play_next: bool,
+}
+
+/// Payload of `deep-link://enqueue`.
+#[derive(Debug, Clone, Serialize)]
+pub struct EnqueueRequest {
+    #[serde(flatten)]
+    pub link: EnqueueLink,
+    /// True when the policy is `ask`: the frontend must confirm first.
+    pub requires_confirmation: bool,
+    /// Set when the song was queued already.
+    pub queue_entry: Option<QueueEntry>,
+}
+
+/// Payload of `deep-link://rejected`.
+#[derive(Debug, Clone, Serialize)]
+pub struct RejectedLink {
+    pub url: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Policy {
+    Ask,
+    Allow,
+    Deny,
+}
+
+/// Handle one `karaoke://` URL from a launch or deep-link event.
+pub fn dispatch(app: &AppHandle, url: &str) {
+    let result = parse_enqueue(url).and_then(|link| authorize(app, link)).and_then(|request| deliver(app, request));
+    match result {
+        Ok(request) => {
+            tracing::info!("[deep-link] Enqueue request: {:?}", request.link.target);
+            publish(app, AppEvent::EnqueueRequest(request));
+        }
+        Err(reason) => {
+            tracing::warn!("[deep-link] Rejected '{}': {}", url, reason);
+            publish(app, AppEvent::DeepLinkRejected(RejectedLink { url: url.to_string(), reason }));
+        }
+    }
+}
+
+/// Enqueue a link the user already confirmed in the app. The policy and
+/// the host and library checks still apply; `deny` refuses it.
+pub(crate) fn enqueue_confirmed(app: &AppHandle, link: EnqueueLink) -> Result<EnqueueRequest, String> {
+    let mut request = authorize(app, link)?;
+    request.requires_confirmation = false;
+    let request = deliver(app, request)?;
+    tracing::info!("[deep-link] Confirmed enqueue: {:?}", request.link.target);
+    publish(app, AppEvent::EnqueueRequest(request.clone()));
+    Ok(request)
+}
+
+/// Parse and validate a `karaoke://enqueue|queue|play?...` link (syntax
+/// only).
+pub fn parse_enqueue(url: &str) -> Result<EnqueueLink, String> {
+    let rest = url
+        .get(..URL_SCHEME.len())
+        .filter(|scheme| scheme.eq_ignore_ascii_case(URL_SCHEME))
+        .map(|_| &url[URL_SCHEME.len()..])
+        .ok_or("Not a karaoke:// link")?;
+
+    let (action, query) = rest.split_once('?').unwrap_or((rest, ""));
+    let action = action.trim_end_matches('/').to_ascii_lowercase();
+    let play_next = match action.as_str() {
+        "enqueue" | "queue" => false,
+        "play" => true,
+        _ => return Err(format!("Unknown deep-link action '{}'", action)),
+    };
+
+    let mut target: Option<EnqueueTarget> = None;
+    let mut singer: Option<String> = None;
+
+    for pair in query.split('&').filter(|p| !p.is_empty()) {
+        let (key, raw) = pair.split_once('=').unwrap_or((pair, ""));
+        let value = percent_decode(raw)?;
+        let parsed = match key {
+            "song" | "songId" => {
+                validate_token(&value, MAX_SONG_ID_LEN, |c| c.is_ascii_alphanumeric() || "_.-".contains(c), "song id")?;
+                EnqueueTarget::Song { id: value }
+            }
+            "code" => {
+                validate_token(&value, MAX_CODE_LEN, |c| c.is_ascii_alphanumeric() || c == '-', "song code")?;
+                EnqueueTarget::Code { code: value }
+            }
+            "url" => {
+                validate_external_url(&value)?;
+                EnqueueTarget::Url { url: value }
+            }
+            "file" => {
+                validate_file_path(&value)?;
+                EnqueueTarget::File { path: value }
+            }
+            "singer" => {
+                let name: String = value.chars().filter(|c| !c.is_control()).collect();
+                let name = name.trim();
+                if name.chars().count() > MAX_SINGER_LEN {
+                    return Err(format!("singer name longer than {} characters", MAX_SINGER_LEN));
+                }
+                singer = (!name.is_empty()).then(|| name.to_string());
+                continue;
+            }
+            other => return Err(format!("Unknown parameter '{}'", other)),
+        };
+        if target.replace(parsed).is_some() {
+            return Err("Only one of song, code, url or file may be given".into());
+        }
+    }
+
+    let target = target.ok_or("Missing target: song, code, url or file")?;
+    Ok(EnqueueLink { target, singer, play_next })
+}
+
+/// Apply the enqueue policy and library/host checks.
+fn authorize(app: &AppHandle, mut link: EnqueueLink) -> Result<EnqueueRequest, String> {
+    let db = app.try_state::<DbState>().ok_or("Database not available")?;
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let setting = |key: &str| -> Option<String> {
+        conn.query_row("SELECT value FROM app_settings WHERE key = ?1", [key], |row| row.get(0))
+            .ok()
+    };
+
+    let policy = match setting(POLICY_KEY).as_deref() {
+        Some("allow") => Policy::Allow,
+        Some("deny") => Policy::Deny,
+        _ => Policy::Ask,
+    };
+    if policy == Policy::Deny {
+        return Err("Enqueue links are disabled in settings".into());
+    }
+
+    match &link.target {
+        EnqueueTarget::Song { id } => {
+            let exists: bool = conn
+                .query_row("SELECT EXISTS(SELECT 1 FROM songs WHERE id = ?1)", [id], |row| row.get(0))
+                .map_err(|e| format!("song lookup failed: {}", e))?;
+            if !exists {
+                return Err(format!("Song '{}' is not in the library", id));
+            }
+        }
+        EnqueueTarget::Url { url } => {
+            let allowed: Vec<String> = match setting(ALLOWED_HOSTS_KEY) {
+                Some(list) => list.split(',').map(|h| h.trim().to_ascii_lowercase()).filter(|h| !h.is_empty()).collect(),
+                None => DEFAULT_ALLOWED_HOSTS.iter().map(|h| h.to_string()).collect(),
+            };
+            let host = url_host(url).unwrap_or_default();
+            if !host_allowed(&host, &allowed) {
+                return Err(format!("Host '{}' is not on the allowed list", host));
+            }
+        }
+        EnqueueTarget::File { path } => {
+            let id = song_for_file(&conn, path)?.ok_or_else(|| format!("'{}' is not part of a library song", path))?;
+            link.target = EnqueueTarget::Song { id };
+        }
+        EnqueueTarget::Code { .. } => {}
+    }
+
+    let requires_confirmation = policy == Policy::Ask || link.play_next;
+    Ok(EnqueueRequest { link, requires_confirmation, queue_entry: None })
+}
+
+/// The library song with `path` as its notes, audio or video file. The
+/// link may spell it in another Unicode normalization or with other
+/// separators than the scan stored.
+pub(crate) fn song_for_file(conn: &rusqlite::Connection, path: &str) -> Result<Option<String>, String> {
+    let path = std::path::Path::new(path);
+    let (Some(folder), Some(name)) = (path.parent().and_then(|p| p.to_str()), path.file_name().and_then(|n| n.to_str()))
+    else {
+        return Ok(None);
+    };
+    let exact = conn
+        .query_row(
+            "SELECT id FROM songs WHERE folder_path = ?1
+               AND (txt_file_name = ?2 OR audio_file_name = ?2 OR video_file_name = ?2)
+             LIMIT 1",
+            [folder, name],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| format!("song lookup failed: {}", e))?;
+    if exact.is_some() {
+        return Ok(exact);
+    }
+
+    let (folder, name) = (path_key(folder), path_key(name));
+    let mut stmt = conn
+        .prepare("SELECT id, folder_path, txt_file_name, audio_file_name, video_file_name FROM songs")
+        .map_err(|e| format!("song lookup failed: {}", e))?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, Option<String>>(1)?,
+                [row.get::<_, Option<String>>(2)?, row.get::<_, Option<String>>(3)?, row.get::<_, Option<String>>(4)?],
+            ))
+        })
+        .map_err(|e| format!("song lookup failed: {}", e))?;
+    for row in rows {
+        let (id, song_folder, files) = row.map_err(|e| format!("song lookup failed: {}", e))?;
+        if song_folder.is_some_and(|f| path_key(&f) == folder) && files.iter().flatten().any(|f| path_key(f) == name) {
+            return Ok(Some(id));
+        }
+    }
+    Ok(None)
+}
+
+/// Queue allowed library songs right away; the rest is left to the
+/// frontend (songbook codes, downloads, confirmations).
+fn deliver(app: &AppHandle, mut request: EnqueueRequest) -> Result<EnqueueRequest, String> {
+    let EnqueueTarget::Song { id } = &request.link.target else { return Ok(request) };
+    if request.requires_confirmation {
+        return Ok(request);
+    }
+    let singer = request.link.singer.as_deref().unwrap_or(DEFAULT_SINGER);
+    request.queue_entry = Some(queue::enqueue(app, singer, id, request.link.play_next)?);
+    Ok(request)
+}
+
+fn validate_token(value: &str, max_len: usize, allowed: impl Fn(char) -> bool, label: &str) -> Result<(), String> {
+    if value.is_empty() || value.len() > max_len {
+        return Err(format!("{} must be 1–{} characters", label, max_len));
+    }
+    if !value.chars().all(allowed) {
+        return Err(format!("{} contains invalid characters", label));
+    }
+    Ok(())
+}
+
+fn validate_file_path(path: &str) -> Result<(), String> {
+    if path.len() > MAX_PATH_LEN {
+        return Err(format!("file path longer than {} characters", MAX_PATH_LEN));
+    }
+    if path.chars().any(char::is_control) {
+        return Err("file path contains invalid characters".into());
+    }
+    let path = std::path::Path::new(path);
+    if !path.is_absolute() {
+        return Err("file path must be absolute".into());
+    }
+    if path.components().any(|c| c == std::path::Component::ParentDir) {
+        return Err("file path must not contain '..'".into());
+    }
+    Ok(())
+}
+
+fn validate_external_url(url: &str) -> Result<(), String> {
+    if url.len() > MAX_URL_LEN {
+        return Err(format!("url longer than {} characters", MAX_URL_LEN));
+    }
+    if url.chars().any(|c| c.is_control() || c.is_whitespace()) {
+        return Err("url contains invalid characters".into());
+    }
+    let parsed = Url::parse(url).map_err(|e| format!("invalid url: {}", e))?;
+    if parsed.scheme() != "https" {
+        return Err("url must use https".into());
+    }
+    if url_host(url).is_none() {
+        return Err("url has no host".into());
+    }
+    Ok(())
+}
+
+/// Host of a URL as `Url` parses it (lower-cased, IDNA-encoded, without
+/// userinfo and port); `None` without one.
+fn url_host(url: &str) -> Option<String> {
+    let parsed = Url::parse(url).ok()?;
+    parsed.host_str().filter(|host| !host.is_empty()).map(str::to_string)
+}
+
+/// Exact matches only: `music.youtube.com` needs its own entry.
+fn host_allowed(host: &str, allowed: &[String]) -> bool {
+    allowed.iter().any(|a| host == a)
+}
+
+/// Decode `%XX` escapes and `+` (space) in a query value.
+fn percent_decode(value: &str) -> Result<String, String> {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let hex = value.get(i + 1..i + 3).ok_or("Truncated percent escape")?;
+                let byte = u8::from_str_radix(hex, 16).map_err(|_| "Invalid percent escape")?;
+                out.push(byte);
+                i += 3;
+            }
+            b'+' => {
+                out.push(b' ');
+                i += 1;
+            }
+            b => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(out).map_err(|_| "Link is not valid UTF-8".to_string())
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_each_target_kind() {
+        let link = parse_enqueue("karaoke://enqueue?song=native-00ff&singer=Ana%20Mar%C3%ADa").unwrap();
+        assert_eq!(link.target, EnqueueTarget::Song { id: "native-00ff".into() });
+        assert_eq!(link.singer.as_deref(), Some("Ana María"));
+
+        let link = parse_enqueue("KARAOKE://enqueue/?code=A-1234").unwrap();
+        assert_eq!(link.target, EnqueueTarget::Code { code: "A-1234".into() });
+
+        let link = parse_enqueue("karaoke://enqueue?url=https%3A%2F%2Fwww.youtube.com%2Fwatch%3Fv%3Dabc").unwrap();
+        assert_eq!(link.target, EnqueueTarget::Url { url: "https://www.youtube.com/watch?v=abc".into() });
+        assert!(!link.play_next);
+    }
+
+    #[test]
+    fn parses_queue_and_play_actions() {
+        let link = parse_enqueue("karaoke://queue?songId=123").unwrap();
+        assert_eq!((link.target, link.play_next), (EnqueueTarget::Song { id: "123".into() }, false));
+
+        let file = std::env::temp_dir().join("Song").join("song.txt");
+        let link = parse_enqueue(&format!("karaoke://play?file={}", file.display())).unwrap();
+        assert_eq!(link.target, EnqueueTarget::File { path: file.display().to_string() });
+        assert!(link.play_next);
+
+        assert!(parse_enqueue("karaoke://play?file=song.txt").is_err());
+        let sneaky = std::env::temp_dir().join("..").join("song.txt");
+        assert!(parse_enqueue(&format!("karaoke://play?file={}", sneaky.display())).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_links() {
+        assert!(parse_enqueue("karaoke://enqueue").is_err());
+        assert!(parse_enqueue("karaoke://delete?song=x").is_err());
+        assert!(parse_enqueue("karaoke://enqueue?song=a&code=b").is_err());
+        assert!(parse_enqueue("karaoke://enqueue?song=../etc").is_err());
+        assert!(parse_enqueue("karaoke://enqueue?song=a%2Fb").is_err());
+        assert!(parse_enqueue("karaoke://enqueue?url=http%3A%2F%2Fyoutube.com").is_err());
+        assert!(parse_enqueue("karaoke://enqueue?song=a&evil=1").is_err());
+        assert!(parse_enqueue("karaoke://enqueue?song=%ZZ").is_err());
+    }
+
+    #[test]
+    fn host_allowlist_matches_exact_hosts() {
+        let allowed = vec!["youtube.com".to_string()];
+        assert!(host_allowed("youtube.com", &allowed));
+        assert!(!host_allowed("music.youtube.com", &allowed));
+        assert!(!host_allowed("evilyoutube.com", &allowed));
+        assert_eq!(url_host("https://user@www.YouTube.com:443/x").as_deref(), Some("www.youtube.com"));
+        // Parsed as a browser would open it
+        assert_eq!(url_host("https://youtube.com\\@evil.example/").as_deref(), Some("youtube.com"));
+        assert_eq!(url_host("https://evil.example#@youtube.com").as_deref(), Some("evil.example"));
+        assert_eq!(url_host("https://[::1]/").as_deref(), Some("[::1]"));
+    }
+
+    #[test]
+    fn finds_files_however_the_link_spells_them() {
+        let conn = crate::db::test_conn();
+        conn.execute(
+            "INSERT INTO songs (id, title, artist, folder_path, txt_file_name, audio_file_name)
+             VALUES ('s1', 'Cafe', 'X', '/music/Beyonce\u{301}', 'song.txt', 'song.mp3')",
+            [],
+        )
+        .unwrap();
+        assert_eq!(song_for_file(&conn, "/music/Beyonce\u{301}/song.txt").unwrap().as_deref(), Some("s1"));
+        // Composed instead of decomposed, a doubled separator
+        assert_eq!(song_for_file(&conn, "/music//Beyonc\u{e9}/song.mp3").unwrap().as_deref(), Some("s1"));
+        assert_eq!(song_for_file(&conn, "/music/Beyonce/song.mp3").unwrap(), None);
+    }
+}
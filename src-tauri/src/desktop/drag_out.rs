@@ -0,0 +1,103 @@
+//! Native drag-out of recordings and exports to Finder / Explorer.
+//!
+//! The frontend calls `drag_out_files` from a `mousedown`/`dragstart`
+//! handler; the OS drag session is started on the main thread with the
+//! files attached, so dropping onto the desktop or a folder copies them
+//! there. The outcome is reported via the `on_result` channel.
+
+use std::path::PathBuf;
+
+use serde::Serialize;
+use tauri::{ipc::Channel, WebviewWindow};
+
+use crate::access::{require_webview, Capability};
+use crate::validate_safe_path;
+
+/// Preview shown under the cursor while dragging.
+const DRAG_ICON: &[u8] = include_bytes!("../../icons/32x32.png");
+
+/// File types that may be dragged out (recordings, exports, song files).
+const ALLOWED_EXTENSIONS: &[&str] = &[
+    "wav", "mp3", "ogg", "opus", "m4a", "flac", "webm", "mp4", "mkv", "mov",
+    "txt", "json", "csv", "pdf", "zip",
+];
+
+/// Result of a drag-out gesture.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DragOutResult {
+    Dropped,
+    Cancelled,
+}
+
+fn validate_drag_paths(paths: &[String]) -> Result<Vec<PathBuf>, String> {
+    if paths.is_empty() {
+        return Err("No files to drag".into());
+    }
+    paths
+        .iter()
+        .map(|raw| {
+            let path = validate_safe_path(raw)?;
+            if !path.is_file() {
+                return Err(format!("Not a file: {}", raw));
+            }
+            let allowed = path
+                .extension()
+                .and_then(|e| e.to_str())
+                .map(|e| ALLOWED_EXTENSIONS.iter().any(|x| e.eq_ignore_ascii_case(x)))
+                .unwrap_or(false);
+            if !allowed {
+                return Err(format!("File type cannot be dragged out: {}", raw));
+            }
+            Ok(path)
+        })
+        .collect()
+}
+
+/// Start a native drag session carrying `paths` out of the window.
+#[tauri::command]
+pub fn drag_out_files(
+    webview: tauri::Webview,
+    window: WebviewWindow,
+    paths: Vec<String>,
+    on_result: Channel<DragOutResult>,
+) -> Result<(), String> {
+    require_webview(&webview, Capability::ManageLibrary)?;
+    let files = validate_drag_paths(&paths)?;
+    let drag_window = window.clone();
+
+    // The OS drag APIs (NSDraggingSession, DoDragDrop, GTK) must be driven
+    // from the main/UI thread.
+    window
+        .run_on_main_thread(move || {
+            // GTK drags start from the window's GtkApplicationWindow
+            #[cfg(target_os = "linux")]
+            let source = drag_window.gtk_window();
+            #[cfg(not(target_os = "linux"))]
+            let source = tauri::Result::Ok(drag_window);
+            let source = match source {
+                Ok(source) => source,
+                Err(e) => {
+                    tracing::error!("[drag] Failed to start drag-out: {}", e);
+                    return;
+                }
+            };
+            let started = drag::start_drag(
+                &source,
+                drag::DragItem::Files(files),
+                drag::Image::Raw(DRAG_ICON.to_vec()),
+                move |result, _cursor| {
+                    let outcome = match result {
+                        drag::DragResult::Dropped => DragOutResult::Dropped,
+                        drag::DragResult::Cancel => DragOutResult::Cancelled,
+                    };
+                    let _ = on_result.send(outcome);
+                },
+                drag::Options::default(),
+            );
+            if let Err(e) = started {
+                tracing::error!("[drag] Failed to start drag-out: {}", e);
+            }
+        })
+        .map_err(|e| format!("Failed to start drag-out: {}", e))
+}
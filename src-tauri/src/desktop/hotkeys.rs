@@ -0,0 +1,179 @@
+//! System-wide hotkeys, so the KJ can drive the show from a wireless
+//! keypad while another app has focus.
+//!
+//! Bindings live in `[hotkeys]` of `config.toml` as accelerators
+//! (`"Ctrl+Alt+N"`, `"MediaPlayPause"`, `"F13"`) and are re-registered
+//! whenever the config is applied. Pause/resume and key up/down act on the
+//! native player directly; next song and the filler music belong to the
+//! frontend and are published as `hotkey://pressed`.
+
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use tauri::plugin::TauriPlugin;
+use tauri::{AppHandle, Manager, Wry};
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut, ShortcutState};
+
+use crate::access::{require_webview, Capability};
+use crate::audio::commands::AudioState;
+use crate::config::{self, HotkeysConfig};
+use crate::events::{publish, AppEvent};
+
+pub const HOTKEY_EVENT: &str = "hotkey://pressed";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HotkeyAction {
+    NextSong,
+    PauseResume,
+    KeyUp,
+    KeyDown,
+    ToggleFiller,
+}
+
+/// Payload of `hotkey://pressed`.
+#[derive(Debug, Clone, Serialize)]
+pub struct HotkeyPressed {
+    pub action: HotkeyAction,
+}
+
+/// Managed state: the shortcuts registered by the last `sync`.
+#[derive(Default)]
+pub struct HotkeyState {
+    bindings: Mutex<Vec<(Shortcut, HotkeyAction)>>,
+    /// Held for a whole `sync`, so two never interleave. The press handler
+    /// only takes `bindings`, which is never held across a plugin call.
+    syncing: Mutex<()>,
+}
+
+/// Parse every binding, rejecting invalid accelerators and one shortcut
+/// bound to two actions.
+pub fn parse_bindings(config: &HotkeysConfig) -> Result<Vec<(Shortcut, HotkeyAction)>, String> {
+    let mut bindings: Vec<(Shortcut, HotkeyAction)> = Vec::new();
+    for (action, accelerator) in config.bindings() {
+        let shortcut: Shortcut = accelerator
+            .parse()
+            .map_err(|e| format!("Invalid hotkey '{}' for {:?}: {}", accelerator, action, e))?;
+        if let Some((_, other)) = bindings.iter().find(|(s, _)| *s == shortcut) {
+            return Err(format!("Hotkey '{}' is bound to both {:?} and {:?}", accelerator, other, action));
+        }
+        bindings.push((shortcut, action));
+    }
+    Ok(bindings)
+}
+
+/// Global shortcut plugin, dispatching presses of the registered bindings.
+pub fn plugin() -> TauriPlugin<Wry> {
+    tauri_plugin_global_shortcut::Builder::new()
+        .with_handler(|app, shortcut, event| {
+            if event.state() != ShortcutState::Pressed {
+                return;
+            }
+            let Some(state) = app.try_state::<HotkeyState>() else { return };
+            let action = state
+                .bindings
+                .lock()
+                .ok()
+                .and_then(|bindings| bindings.iter().find(|(s, _)| s == shortcut).map(|(_, a)| *a));
+            if let Some(action) = action {
+                run(app, action);
+            }
+        })
+        .build()
+}
+
+/// Register the bindings of `config`, replacing the previous ones. A
+/// shortcut another app already holds is skipped with a warning.
+pub fn sync(app: &AppHandle, config: &HotkeysConfig) {
+    let bindings = match parse_bindings(config) {
+        Ok(bindings) => bindings,
+        Err(e) => {
+            tracing::warn!("[hotkeys] {}", e);
+            return;
+        }
+    };
+    let Some(state) = app.try_state::<HotkeyState>() else { return };
+    let Ok(_syncing) = state.syncing.lock() else { return };
+    let shortcuts = app.global_shortcut();
+    if let Err(e) = shortcuts.unregister_all() {
+        tracing::warn!("[hotkeys] Failed to release hotkeys: {}", e);
+    }
+    let mut registered = Vec::with_capacity(bindings.len());
+    for (shortcut, action) in bindings {
+        match shortcuts.register(shortcut) {
+            Ok(()) => registered.push((shortcut, action)),
+            Err(e) => tracing::warn!("[hotkeys] Cannot register {} for {:?}: {}", shortcut, action, e),
+        }
+    }
+    if !registered.is_empty() {
+        tracing::info!("[hotkeys] {} hotkey(s) registered", registered.len());
+    }
+    if let Ok(mut current) = state.bindings.lock() {
+        *current = registered;
+    }
+}
+
+fn run(app: &AppHandle, action: HotkeyAction) {
+    let audio = app.state::<AudioState>();
+    let result = match action {
+        HotkeyAction::PauseResume => audio.toggle_playback().map(|_| ()),
+        HotkeyAction::KeyUp | HotkeyAction::KeyDown => {
+            let step = if action == HotkeyAction::KeyUp { 1 } else { -1 };
+            audio.set_key(audio.key() + step);
+            Ok(())
+        }
+        HotkeyAction::NextSong | HotkeyAction::ToggleFiller => {
+            publish(app, AppEvent::HotkeyPressed(HotkeyPressed { action }));
+            Ok(())
+        }
+    };
+    if let Err(e) = result {
+        tracing::warn!("[hotkeys] {:?} failed: {}", action, e);
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+/// Bind `action` to `accelerator`, or unbind it with `None`. Saved to
+/// `config.toml` and registered at once; returns all bindings.
+#[tauri::command]
+pub fn set_hotkey(
+    app: AppHandle,
+    webview: tauri::Webview,
+    action: HotkeyAction,
+    accelerator: Option<String>,
+) -> Result<HotkeysConfig, String> {
+    require_webview(&webview, Capability::ChangeSettings)?;
+    let mut updated = config::current(&app);
+    updated.hotkeys.set(action, accelerator.map(|a| a.trim().to_string()).filter(|a| !a.is_empty()));
+    Ok(config::update(&app, updated)?.hotkeys)
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_invalid_and_duplicate_bindings() {
+        let mut config = HotkeysConfig::default();
+        config.set(HotkeyAction::NextSong, Some("Ctrl+Alt+N".to_string()));
+        config.set(HotkeyAction::PauseResume, Some("MediaPlayPause".to_string()));
+        let bindings = parse_bindings(&config).unwrap();
+        assert_eq!(bindings.len(), 2);
+        assert_eq!(bindings[0].1, HotkeyAction::NextSong);
+
+        // Same shortcut, spelled differently
+        config.set(HotkeyAction::KeyUp, Some("control+alt+n".to_string()));
+        assert!(parse_bindings(&config).is_err());
+        config.set(HotkeyAction::KeyUp, Some("Ctrl+Banana".to_string()));
+        assert!(parse_bindings(&config).is_err());
+        config.set(HotkeyAction::KeyUp, None);
+        assert_eq!(parse_bindings(&config).unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,78 @@
+//! Native import dialogs with karaoke-specific format filters.
+//!
+//! Selections go straight into the background import queue; the command
+//! returns the chosen paths and the job id so the UI can track progress.
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+use tauri_plugin_dialog::DialogExt;
+
+use crate::access::{require_webview, Capability};
+use crate::library::formats::VIDEO_EXTENSIONS as VIDEO;
+use crate::library::import_queue::ImportQueue;
+use crate::library::scanner::AUDIO_EXTENSIONS as AUDIO;
+
+// Only what `scanner::import_path` turns into songs
+const SONG_FILES: &[&str] = &["txt", "cdg", "kar"];
+const ARCHIVES: &[&str] = &["zip"];
+
+/// What the dialog selects.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportDialogMode {
+    /// One or more files, filtered by type.
+    #[default]
+    Files,
+    /// One or more song folders (scanned recursively).
+    Folders,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportDialogResult {
+    pub paths: Vec<String>,
+    /// Import job id, `None` if the dialog was cancelled.
+    pub job_id: Option<u64>,
+}
+
+/// Show the import dialog and queue the selection for import.
+#[tauri::command]
+pub async fn open_import_dialog(app: AppHandle, webview: tauri::Webview, mode: Option<ImportDialogMode>) -> Result<ImportDialogResult, String> {
+    require_webview(&webview, Capability::ManageLibrary)?;
+    let mode = mode.unwrap_or_default();
+    let dialog_app = app.clone();
+
+    // Blocking dialogs must not run on the main thread
+    let selected: Vec<PathBuf> = tauri::async_runtime::spawn_blocking(move || {
+        let builder = dialog_app.dialog().file().set_title("Import songs");
+        let picked = match mode {
+            ImportDialogMode::Files => {
+                let all: Vec<&str> = [AUDIO, VIDEO, SONG_FILES, ARCHIVES].concat();
+                builder
+                    .add_filter("Karaoke files", &all)
+                    .add_filter("Audio", AUDIO)
+                    .add_filter("Video", VIDEO)
+                    .add_filter("Song files (TXT, CDG, KAR)", SONG_FILES)
+                    .add_filter("Karaoke archives (ZIP)", ARCHIVES)
+                    .blocking_pick_files()
+            }
+            ImportDialogMode::Folders => builder.blocking_pick_folders(),
+        };
+        picked
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|p| p.into_path().ok())
+            .collect()
+    })
+    .await
+    .map_err(|e| format!("Import dialog failed: {}", e))?;
+
+    if selected.is_empty() {
+        return Ok(ImportDialogResult { paths: Vec::new(), job_id: None });
+    }
+
+    let paths = selected.iter().map(|p| p.to_string_lossy().to_string()).collect();
+    let job_id = app.state::<ImportQueue>().enqueue(selected)?;
+    Ok(ImportDialogResult { paths, job_id: Some(job_id) })
+}
@@ -0,0 +1,268 @@
+//! Kiosk mode for public terminals.
+//!
+//! Enabled by `[kiosk] enabled = true` in `config.toml` or by starting the
+//! app with `--kiosk`. The main window goes fullscreen without decorations
+//! and cannot be closed (which also swallows Alt+F4 on Windows); the
+//! webview may not navigate away from the local server and app schemes,
+//! and every page gets a script that blocks the context menu and the
+//! devtools shortcuts (release builds ship devtools). Leaving kiosk mode
+//! takes the exit PIN set beforehand with `set_kiosk_exit_pin`, or an
+//! edit of the config file: while kiosk mode is on the main window only
+//! has operator rights (see `access`), so `set_config` and the other
+//! settings commands refuse it.
+//!
+//! The PIN is stored as a salted SHA-256 hash and cannot be read or
+//! written through the generic settings commands (`db::commands`). After
+//! `FREE_ATTEMPTS` wrong PINs each further attempt waits twice as long as
+//! the one before, up to `MAX_BACKOFF`.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use sha2::{Digest, Sha256};
+
+use tauri::plugin::{Builder as PluginBuilder, TauriPlugin};
+use tauri::{AppHandle, Manager, Runtime, Url};
+
+use crate::access::{require_webview, Capability};
+use crate::db::DbState;
+
+pub const KIOSK_FLAG: &str = "--kiosk";
+pub(crate) const PIN_SETTING: &str = "kiosk_exit_pin";
+const HASH_PREFIX: &str = "sha256$";
+/// Wrong PINs allowed before the back-off starts.
+const FREE_ATTEMPTS: u32 = 3;
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Injected on every page load while kiosk mode is on.
+const LOCKDOWN_SCRIPT: &str = r#"(() => {
+  if (window.__kioskLocked) return;
+  window.__kioskLocked = true;
+  window.addEventListener('contextmenu', (e) => e.preventDefault(), true);
+  window.addEventListener('keydown', (e) => {
+    const k = e.key.toUpperCase();
+    if (k === 'F12' || ((e.ctrlKey || e.metaKey) && e.shiftKey && ['I', 'J', 'C'].includes(k)) || ((e.ctrlKey || e.metaKey) && k === 'U')) {
+      e.preventDefault();
+      e.stopPropagation();
+    }
+  }, true);
+})();"#;
+
+/// Managed state: whether kiosk mode is on, and whether `--kiosk` forces
+/// it (until the exit PIN is entered).
+pub struct KioskState {
+    active: AtomicBool,
+    forced: AtomicBool,
+    /// Wrong exit PINs in a row, and when the last one was entered.
+    failures: Mutex<(u32, Option<Instant>)>,
+}
+
+impl KioskState {
+    pub fn from_flags() -> Self {
+        let forced = crate::cli::gui_flags().kiosk;
+        Self { active: AtomicBool::new(false), forced: AtomicBool::new(forced), failures: Mutex::new((0, None)) }
+    }
+}
+
+pub fn is_active<R: Runtime>(app: &AppHandle<R>) -> bool {
+    app.try_state::<KioskState>().is_some_and(|state| state.active.load(Ordering::Relaxed))
+}
+
+/// Switch kiosk mode to `enabled` (or on anyway with `--kiosk`) and lock
+/// or release the main window. Called whenever the config is applied.
+pub fn sync(app: &AppHandle, enabled: bool) {
+    let Some(state) = app.try_state::<KioskState>() else { return };
+    let enabled = enabled || state.forced.load(Ordering::Relaxed);
+    if state.active.swap(enabled, Ordering::Relaxed) != enabled {
+        lock_main_window(app, enabled);
+    }
+}
+
+/// Turn kiosk mode on as if started with `--kiosk` (a second launch
+/// with the flag).
+pub fn force(app: &AppHandle) {
+    let Some(state) = app.try_state::<KioskState>() else { return };
+    state.forced.store(true, Ordering::Relaxed);
+    sync(app, true);
+}
+
+fn lock_main_window(app: &AppHandle, enabled: bool) {
+    if let Some(window) = app.get_webview_window("main") {
+        let applied = window
+            .set_decorations(!enabled)
+            .and_then(|_| window.set_fullscreen(enabled))
+            .and_then(|_| window.set_closable(!enabled));
+        if let Err(e) = applied {
+            tracing::warn!("[kiosk] Failed to update the main window: {}", e);
+        }
+        if enabled {
+            window.close_devtools();
+            let _ = window.eval(LOCKDOWN_SCRIPT);
+        }
+    }
+    tracing::info!("[kiosk] Kiosk mode {}", if enabled { "on" } else { "off" });
+}
+
+/// Local origins the app's own pages are served from.
+fn is_local(url: &Url) -> bool {
+    match url.scheme() {
+        "tauri" | "asset" | "splash" | "cdg" | "cache" | "about" | "data" | "blob" => true,
+        // Custom schemes are served from http://<scheme>.localhost on Windows
+        "http" | "https" => url
+            .host_str()
+            .is_some_and(|host| matches!(host, "localhost" | "127.0.0.1" | "[::1]") || host.ends_with(".localhost")),
+        _ => false,
+    }
+}
+
+/// Navigation guard and page lockdown; registered for every webview and
+/// inert while kiosk mode is off.
+pub fn plugin<R: Runtime>() -> TauriPlugin<R> {
+    PluginBuilder::new("kiosk")
+        .on_navigation(|webview, url| {
+            if !is_active(webview.app_handle()) || is_local(url) {
+                return true;
+            }
+            tracing::warn!("[kiosk] Blocked navigation to {}", url);
+            false
+        })
+        .on_page_load(|webview, _payload| {
+            if is_active(webview.app_handle()) {
+                let _ = webview.eval(LOCKDOWN_SCRIPT);
+            }
+        })
+        .build()
+}
+
+/// How long to wait after `failures` wrong PINs in a row.
+fn backoff(failures: u32) -> Duration {
+    if failures < FREE_ATTEMPTS {
+        return Duration::ZERO;
+    }
+    Duration::from_secs(1u64 << (failures - FREE_ATTEMPTS).min(16)).min(MAX_BACKOFF)
+}
+
+fn hash_pin(pin: &str, salt: &str) -> String {
+    let digest = Sha256::digest(format!("{}:{}", salt, pin).as_bytes());
+    let hex: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{}{}${}", HASH_PREFIX, salt, hex)
+}
+
+/// Whether `pin` matches the stored value; PINs saved before hashing
+/// are still compared as they are.
+fn pin_matches(stored: &str, pin: &str) -> bool {
+    let expected = match stored.strip_prefix(HASH_PREFIX).and_then(|rest| rest.split_once('$')) {
+        Some((salt, _)) => hash_pin(pin, salt),
+        None => pin.to_string(),
+    };
+    expected.len() == stored.len() && expected.bytes().zip(stored.bytes()).fold(0u8, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+fn stored_pin(app: &AppHandle) -> Result<Option<String>, String> {
+    let db = app.state::<DbState>();
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    Ok(crate::scheduler::read_setting(&conn, PIN_SETTING).filter(|pin| !pin.is_empty()))
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+/// Set (or with `None`, remove) the PIN that leaves kiosk mode. Only
+/// outside kiosk mode, so guests at the terminal cannot change it.
+#[tauri::command]
+pub fn set_kiosk_exit_pin(app: AppHandle, webview: tauri::Webview, pin: Option<String>) -> Result<(), String> {
+    require_webview(&webview, Capability::ChangeSettings)?;
+    if is_active(&app) {
+        return Err("The exit PIN cannot be changed in kiosk mode".to_string());
+    }
+    let pin = pin.map(|p| p.trim().to_string()).filter(|p| !p.is_empty());
+    if pin.as_ref().is_some_and(|p| p.len() < 4) {
+        return Err("The exit PIN needs at least 4 characters".to_string());
+    }
+    let stored = pin.map(|p| hash_pin(&p, &crate::server::security::generate_token())).unwrap_or_default();
+    let db = app.state::<DbState>();
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT OR REPLACE INTO app_settings (key, value) VALUES (?1, ?2)",
+        (PIN_SETTING, stored),
+    )
+    .map_err(|e| format!("Failed to save kiosk PIN: {}", e))?;
+    Ok(())
+}
+
+/// Leave kiosk mode until the next start (or config change).
+#[tauri::command]
+pub fn exit_kiosk(app: AppHandle, webview: tauri::Webview, pin: String) -> Result<(), String> {
+    // The kiosk main window is an operator; the PIN is the real check
+    require_webview(&webview, Capability::ControlPlayback)?;
+    if !is_active(&app) {
+        return Ok(());
+    }
+    let Some(expected) = stored_pin(&app)? else {
+        return Err("No exit PIN is set; disable kiosk mode in config.toml".to_string());
+    };
+    let state = app.state::<KioskState>();
+    let mut failures = state.failures.lock().map_err(|e| e.to_string())?;
+    if let (count, Some(last)) = *failures {
+        let wait = backoff(count).saturating_sub(last.elapsed());
+        if !wait.is_zero() {
+            return Err(format!("Too many wrong PINs; try again in {} s", wait.as_secs().max(1)));
+        }
+    }
+    if !pin_matches(&expected, pin.trim()) {
+        *failures = (failures.0.saturating_add(1), Some(Instant::now()));
+        tracing::warn!("[kiosk] Wrong exit PIN entered ({} in a row)", failures.0);
+        return Err("Wrong PIN".to_string());
+    }
+    *failures = (0, None);
+    drop(failures);
+    // Otherwise the next config reload would lock a `--kiosk` session again
+    state.forced.store(false, Ordering::Relaxed);
+    state.active.store(false, Ordering::Relaxed);
+    lock_main_window(&app, false);
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_local_origins_are_allowed() {
+        let allowed = |url: &str| is_local(&url.parse().unwrap());
+        assert!(allowed("http://localhost:3000/queue"));
+        assert!(allowed("http://127.0.0.1:3001/"));
+        assert!(allowed("http://splash.localhost/"));
+        assert!(allowed("tauri://localhost/index.html"));
+        assert!(!allowed("https://www.youtube.com/watch?v=x"));
+        assert!(!allowed("http://localhost.evil.com/"));
+        assert!(!allowed("file:///C:/Windows/System32/"));
+    }
+
+    #[test]
+    fn pins_are_stored_salted_and_checked_in_full() {
+        let stored = hash_pin("4711", "salt-a");
+        assert!(!stored.contains("4711"));
+        assert_ne!(stored, hash_pin("4711", "salt-b"));
+        assert!(pin_matches(&stored, "4711"));
+        assert!(!pin_matches(&stored, "4712"));
+        assert!(!pin_matches(&stored, ""));
+        // Saved before hashing
+        assert!(pin_matches("4711", "4711"));
+        assert!(!pin_matches("4711", "471"));
+    }
+
+    #[test]
+    fn wrong_pins_back_off_exponentially() {
+        assert_eq!(backoff(FREE_ATTEMPTS - 1), Duration::ZERO);
+        assert_eq!(backoff(FREE_ATTEMPTS), Duration::from_secs(1));
+        assert_eq!(backoff(FREE_ATTEMPTS + 3), Duration::from_secs(8));
+        assert_eq!(backoff(u32::MAX), MAX_BACKOFF);
+    }
+}
@@ -0,0 +1,14 @@
+//! Desktop shell integration: dragging files out to the OS file manager,
+//! revealing paths, native dialogs, trash handling, the startup splash,
+//! the second-screen player window, kiosk mode, the tray icon, global
+//! hotkeys and keeping the screen on during playback.
+
+pub mod drag_out;
+pub mod hotkeys;
+pub mod import_dialog;
+pub mod kiosk;
+pub mod player_window;
+pub mod power;
+pub mod reveal;
+pub mod splash;
+pub mod tray;
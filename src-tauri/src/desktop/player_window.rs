@@ -0,0 +1,174 @@
+//! Second-screen player window for a projector or TV.
+//!
+//! `open_player_window` puts a borderless fullscreen window on the chosen
+//! display, showing the lyric / CDG view (`/?view=player` on the server),
+//! while the main window stays on the operator's screen as the console.
+//! Without a choice it takes the first display the main window is not on.
+//! The window has the guest role and its own capability (`player.json`):
+//! it only listens to the event bus. Opening it again moves it. Started
+//! with `--player-display <n>`, the app opens it by itself (`automation`).
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager, Monitor, WebviewUrl, WebviewWindowBuilder};
+
+use crate::access::{require_webview, Capability};
+use crate::server;
+
+pub const PLAYER_LABEL: &str = "player";
+const PLAYER_PATH: &str = "/?view=player";
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MonitorInfo {
+    /// The display's name, or `display-<n>` when the OS reports none.
+    pub id: String,
+    pub name: Option<String>,
+    /// Physical pixels, in the virtual desktop.
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub scale_factor: f64,
+    pub is_primary: bool,
+}
+
+impl MonitorInfo {
+    fn new(index: usize, monitor: &Monitor, primary: Option<&Monitor>) -> Self {
+        let name = monitor.name().cloned();
+        let (position, size) = (monitor.position(), monitor.size());
+        Self {
+            id: name.clone().unwrap_or_else(|| format!("display-{}", index + 1)),
+            is_primary: primary.is_some_and(|p| p.name() == monitor.name() && p.position() == position),
+            name,
+            x: position.x,
+            y: position.y,
+            width: size.width,
+            height: size.height,
+            scale_factor: monitor.scale_factor(),
+        }
+    }
+}
+
+/// The display to use: `requested` by id, else the first one the console
+/// is not on, else the only one there is.
+fn pick_monitor<'a>(monitors: &'a [MonitorInfo], requested: Option<&str>, console: Option<&str>) -> Result<&'a MonitorInfo, String> {
+    if let Some(id) = requested {
+        return monitors.iter().find(|m| m.id == id).ok_or_else(|| format!("Display '{}' not found", id));
+    }
+    monitors
+        .iter()
+        .find(|m| Some(m.id.as_str()) != console)
+        .or_else(|| monitors.first())
+        .ok_or_else(|| "No display found".to_string())
+}
+
+pub fn monitors(app: &AppHandle) -> Result<Vec<MonitorInfo>, String> {
+    let primary = app.primary_monitor().map_err(|e| e.to_string())?;
+    let monitors = app.available_monitors().map_err(|e| format!("Cannot list displays: {}", e))?;
+    Ok(monitors.iter().enumerate().map(|(i, m)| MonitorInfo::new(i, m, primary.as_ref())).collect())
+}
+
+/// Id of the display the main window is on.
+fn console_monitor(app: &AppHandle, monitors: &[MonitorInfo]) -> Option<String> {
+    let main = app.get_webview_window("main")?;
+    let current = main.current_monitor().ok()??;
+    let position = current.position();
+    monitors.iter().find(|m| m.x == position.x && m.y == position.y).map(|m| m.id.clone())
+}
+
+/// Open (or move) the player window fullscreen on display `monitor_id`,
+/// the first one the console is not on without one.
+pub fn open(app: &AppHandle, monitor_id: Option<&str>) -> Result<MonitorInfo, String> {
+    let monitors = monitors(app)?;
+    let console = console_monitor(app, &monitors);
+    let monitor = pick_monitor(&monitors, monitor_id, console.as_deref())?.clone();
+    let position = tauri::PhysicalPosition::new(monitor.x, monitor.y);
+
+    if let Some(window) = app.get_webview_window(PLAYER_LABEL) {
+        // Leave fullscreen first, or the window stays on the old display
+        let moved = window
+            .set_fullscreen(false)
+            .and_then(|_| window.set_position(position))
+            .and_then(|_| window.set_fullscreen(true));
+        moved.map_err(|e| format!("Failed to move player window: {}", e))?;
+    } else {
+        let url = format!("{}{}", server::port::server_url(server::port::current()), PLAYER_PATH);
+        let url = url.parse().map_err(|e| format!("Invalid player URL: {}", e))?;
+        let window = WebviewWindowBuilder::new(app, PLAYER_LABEL, WebviewUrl::External(url))
+            .title("Karaoke ZERO — Player")
+            .decorations(false)
+            .focused(false)
+            .visible(false)
+            .build()
+            .map_err(|e| format!("Failed to open player window: {}", e))?;
+        let shown = window
+            .set_position(position)
+            .and_then(|_| window.set_fullscreen(true))
+            .and_then(|_| window.show());
+        shown.map_err(|e| format!("Failed to show player window: {}", e))?;
+    }
+    tracing::info!("[player] Player window on {} ({}x{})", monitor.id, monitor.width, monitor.height);
+    Ok(monitor)
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+#[tauri::command]
+pub fn list_monitors(app: AppHandle) -> Result<Vec<MonitorInfo>, String> {
+    monitors(&app)
+}
+
+/// Open (or move) the player window fullscreen on `monitor_id`, an id from
+/// `list_monitors`. Returns the display it went to.
+#[tauri::command]
+pub async fn open_player_window(
+    app: AppHandle,
+    webview: tauri::Webview,
+    monitor_id: Option<String>,
+) -> Result<MonitorInfo, String> {
+    require_webview(&webview, Capability::ControlPlayback)?;
+    open(&app, monitor_id.as_deref())
+}
+
+/// Close the player window; false if none was open.
+#[tauri::command]
+pub fn close_player_window(app: AppHandle, webview: tauri::Webview) -> Result<bool, String> {
+    require_webview(&webview, Capability::ControlPlayback)?;
+    let Some(window) = app.get_webview_window(PLAYER_LABEL) else { return Ok(false) };
+    window.destroy().map_err(|e| format!("Failed to close player window: {}", e))?;
+    Ok(true)
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn display(id: &str, x: i32) -> MonitorInfo {
+        MonitorInfo {
+            id: id.to_string(),
+            name: Some(id.to_string()),
+            x,
+            y: 0,
+            width: 1920,
+            height: 1080,
+            scale_factor: 1.0,
+            is_primary: x == 0,
+        }
+    }
+
+    #[test]
+    fn prefers_the_display_the_console_is_not_on() {
+        let monitors = vec![display("laptop", 0), display("projector", 1920)];
+        assert_eq!(pick_monitor(&monitors, None, Some("laptop")).unwrap().id, "projector");
+        assert_eq!(pick_monitor(&monitors, Some("laptop"), Some("laptop")).unwrap().id, "laptop");
+        assert!(pick_monitor(&monitors, Some("tv"), None).is_err());
+        // A single display is better than none
+        assert_eq!(pick_monitor(&monitors[..1], None, Some("laptop")).unwrap().id, "laptop");
+        assert!(pick_monitor(&[], None, None).is_err());
+    }
+}
@@ -0,0 +1,307 @@
+//! Keep the screen on and the machine awake while a song plays.
+//!
+//! Nobody touches the keyboard or mouse during a karaoke night, so some
+//! machines blank the projector mid-song. While the audio engine reports
+//! playing, an OS inhibitor is held:
+//!   - Windows: `SetThreadExecutionState` with the display and the system
+//!     required;
+//!   - macOS: an IOKit `PreventUserIdleDisplaySleep` assertion;
+//!   - Linux: `org.freedesktop.ScreenSaver.Inhibit` on the session bus and
+//!     an `idle:sleep` lock from logind on the system bus, whichever of
+//!     them answers.
+//!
+//! It is released once playback has been stopped for `RELEASE_AFTER`, so
+//! the screensaver does not get in between two songs either.
+//!
+//! Windows ties the execution state to the thread that set it, so the
+//! inhibitor lives on a thread of its own; the supervised task that
+//! watches playback only tells it when to hold and when to let go.
+
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use tauri::{AppHandle, Manager};
+
+use crate::audio::commands::AudioState;
+use crate::runtime::{sleep_or_cancel, TaskSupervisor};
+
+const POLL: Duration = Duration::from_secs(1);
+/// Playback must have been stopped this long before the inhibitor goes.
+const RELEASE_AFTER: Duration = Duration::from_secs(30);
+const REASON: &str = "Playing a karaoke song";
+
+/// Whether the inhibitor should be held.
+#[derive(Debug, Default)]
+struct Hold {
+    held: bool,
+    stopped_since: Option<Instant>,
+}
+
+impl Hold {
+    /// Note `playing` at `now`; `Some(hold)` when the inhibitor should be
+    /// acquired (`true`) or released.
+    fn update(&mut self, playing: bool, now: Instant) -> Option<bool> {
+        let wanted = if playing {
+            self.stopped_since = None;
+            true
+        } else {
+            let since = *self.stopped_since.get_or_insert(now);
+            self.held && now.duration_since(since) < RELEASE_AFTER
+        };
+        if wanted == self.held {
+            return None;
+        }
+        self.held = wanted;
+        Some(wanted)
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use windows_sys::Win32::System::Power::{SetThreadExecutionState, ES_CONTINUOUS, ES_DISPLAY_REQUIRED, ES_SYSTEM_REQUIRED};
+
+    /// The execution state of the inhibitor thread; reset on drop.
+    pub struct Inhibitor;
+
+    impl Inhibitor {
+        pub fn acquire(_app: &str, _reason: &str) -> Result<Self, String> {
+            // SAFETY: plain Win32 call on this thread's execution state
+            let previous = unsafe { SetThreadExecutionState(ES_CONTINUOUS | ES_SYSTEM_REQUIRED | ES_DISPLAY_REQUIRED) };
+            if previous == 0 {
+                return Err("SetThreadExecutionState failed".to_string());
+            }
+            Ok(Self)
+        }
+    }
+
+    impl Drop for Inhibitor {
+        fn drop(&mut self) {
+            // SAFETY: as above; the same thread set the state
+            unsafe {
+                SetThreadExecutionState(ES_CONTINUOUS);
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::ffi::{c_char, c_void, CString};
+
+    type CFStringRef = *const c_void;
+    type IOPMAssertionID = u32;
+
+    const CF_STRING_ENCODING_UTF8: u32 = 0x0800_0100;
+    const IOPM_ASSERTION_LEVEL_ON: u32 = 255;
+    const IO_RETURN_SUCCESS: i32 = 0;
+    const ASSERTION_TYPE: &str = "PreventUserIdleDisplaySleep";
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    extern "C" {
+        fn CFStringCreateWithCString(alloc: *const c_void, c_str: *const c_char, encoding: u32) -> CFStringRef;
+        fn CFRelease(cf: *const c_void);
+    }
+
+    #[link(name = "IOKit", kind = "framework")]
+    extern "C" {
+        fn IOPMAssertionCreateWithName(
+            assertion_type: CFStringRef,
+            level: u32,
+            name: CFStringRef,
+            id: *mut IOPMAssertionID,
+        ) -> i32;
+        fn IOPMAssertionRelease(id: IOPMAssertionID) -> i32;
+    }
+
+    /// An owned CFString.
+    struct CfString(CFStringRef);
+
+    impl CfString {
+        fn new(text: &str) -> Option<Self> {
+            let c_text = CString::new(text).ok()?;
+            // SAFETY: NUL-terminated UTF-8 that outlives the call
+            let string = unsafe { CFStringCreateWithCString(std::ptr::null(), c_text.as_ptr(), CF_STRING_ENCODING_UTF8) };
+            (!string.is_null()).then_some(Self(string))
+        }
+    }
+
+    impl Drop for CfString {
+        fn drop(&mut self) {
+            // SAFETY: created by CFStringCreateWithCString, released once
+            unsafe { CFRelease(self.0) }
+        }
+    }
+
+    /// An IOKit power assertion; released on drop.
+    pub struct Inhibitor(IOPMAssertionID);
+
+    impl Inhibitor {
+        pub fn acquire(_app: &str, reason: &str) -> Result<Self, String> {
+            let (kind, name) = CfString::new(ASSERTION_TYPE)
+                .zip(CfString::new(reason))
+                .ok_or("Failed to create the assertion strings")?;
+            let mut id: IOPMAssertionID = 0;
+            // SAFETY: valid CFStrings and an out pointer to a local
+            let status = unsafe { IOPMAssertionCreateWithName(kind.0, IOPM_ASSERTION_LEVEL_ON, name.0, &mut id) };
+            if status != IO_RETURN_SUCCESS {
+                return Err(format!("IOPMAssertionCreateWithName failed (0x{:x})", status));
+            }
+            Ok(Self(id))
+        }
+    }
+
+    impl Drop for Inhibitor {
+        fn drop(&mut self) {
+            // SAFETY: an assertion created by acquire, released once
+            unsafe {
+                IOPMAssertionRelease(self.0);
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use zbus::blocking::Connection;
+    use zbus::zvariant::OwnedFd;
+
+    const SCREENSAVER: &str = "org.freedesktop.ScreenSaver";
+    const SCREENSAVER_PATH: &str = "/org/freedesktop/ScreenSaver";
+
+    /// A screensaver cookie and a logind lock; released on drop.
+    pub struct Inhibitor {
+        screensaver: Option<(Connection, u32)>,
+        /// logind holds the lock while this descriptor is open.
+        _sleep_lock: Option<OwnedFd>,
+    }
+
+    fn inhibit_screensaver(app: &str, reason: &str) -> zbus::Result<(Connection, u32)> {
+        let conn = Connection::session()?;
+        let reply = conn.call_method(Some(SCREENSAVER), SCREENSAVER_PATH, Some(SCREENSAVER), "Inhibit", &(app, reason))?;
+        let cookie: u32 = reply.body().deserialize()?;
+        Ok((conn, cookie))
+    }
+
+    fn lock_sleep(app: &str, reason: &str) -> zbus::Result<OwnedFd> {
+        let conn = Connection::system()?;
+        let reply = conn.call_method(
+            Some("org.freedesktop.login1"),
+            "/org/freedesktop/login1",
+            Some("org.freedesktop.login1.Manager"),
+            "Inhibit",
+            &("idle:sleep", app, reason, "block"),
+        )?;
+        let lock: OwnedFd = reply.body().deserialize()?;
+        Ok(lock)
+    }
+
+    impl Inhibitor {
+        pub fn acquire(app: &str, reason: &str) -> Result<Self, String> {
+            let screensaver = inhibit_screensaver(app, reason);
+            let sleep_lock = lock_sleep(app, reason);
+            if let (Err(saver), Err(logind)) = (&screensaver, &sleep_lock) {
+                return Err(format!("no inhibitor answered (screensaver: {}; logind: {})", saver, logind));
+            }
+            Ok(Self { screensaver: screensaver.ok(), _sleep_lock: sleep_lock.ok() })
+        }
+    }
+
+    impl Drop for Inhibitor {
+        fn drop(&mut self) {
+            if let Some((conn, cookie)) = self.screensaver.take() {
+                let _ = conn.call_method(Some(SCREENSAVER), SCREENSAVER_PATH, Some(SCREENSAVER), "UnInhibit", &(cookie,));
+            }
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+mod platform {
+    pub struct Inhibitor;
+
+    impl Inhibitor {
+        pub fn acquire(_app: &str, _reason: &str) -> Result<Self, String> {
+            Err("not supported on this platform".to_string())
+        }
+    }
+}
+
+/// Thread that holds the inhibitor while told to (`true`); it releases
+/// it and ends when the sender is dropped.
+fn spawn_holder(app_name: String) -> Result<mpsc::Sender<bool>, String> {
+    let (tx, rx) = mpsc::channel::<bool>();
+    std::thread::Builder::new()
+        .name("power-inhibit".to_string())
+        .spawn(move || {
+            let mut inhibitor = None;
+            for hold in rx {
+                if !hold {
+                    if inhibitor.take().is_some() {
+                        tracing::info!("[power] Screen and sleep allowed again");
+                    }
+                    continue;
+                }
+                if inhibitor.is_none() {
+                    match platform::Inhibitor::acquire(&app_name, REASON) {
+                        Ok(acquired) => {
+                            tracing::info!("[power] Keeping the screen on while playing");
+                            inhibitor = Some(acquired);
+                        }
+                        Err(e) => tracing::warn!("[power] Cannot keep the screen on: {}", e),
+                    }
+                }
+            }
+        })
+        .map_err(|e| format!("Failed to start the power inhibitor: {}", e))?;
+    Ok(tx)
+}
+
+/// Hold the inhibitor while the audio engine plays; runs for the lifetime
+/// of the app.
+pub fn spawn_inhibitor(app: AppHandle) {
+    let app_name = app.config().product_name.clone().unwrap_or_else(|| app.package_info().name.clone());
+    let holder = match spawn_holder(app_name) {
+        Ok(holder) => holder,
+        Err(e) => {
+            tracing::error!("[power] {}", e);
+            return;
+        }
+    };
+    let supervisor = app.state::<TaskSupervisor>();
+    supervisor.spawn("power-inhibit", move |token| async move {
+        let mut hold = Hold::default();
+        while sleep_or_cancel(&token, POLL).await {
+            let Some(audio) = app.try_state::<AudioState>() else { continue };
+            if let Some(wanted) = hold.update(audio.is_playing(), Instant::now()) {
+                if holder.send(wanted).is_err() {
+                    break;
+                }
+            }
+        }
+        // Dropping the sender releases the inhibitor
+    });
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn holds_while_playing_and_through_short_gaps() {
+        let mut hold = Hold::default();
+        let start = Instant::now();
+        assert_eq!(hold.update(false, start), None);
+        assert_eq!(hold.update(true, start), Some(true));
+        assert_eq!(hold.update(true, start + POLL), None);
+        // The gap between two songs keeps it
+        assert_eq!(hold.update(false, start + Duration::from_secs(2)), None);
+        assert_eq!(hold.update(true, start + Duration::from_secs(20)), None);
+        assert_eq!(hold.update(false, start + Duration::from_secs(21)), None);
+        assert_eq!(hold.update(false, start + Duration::from_secs(21) + RELEASE_AFTER), Some(false));
+        assert_eq!(hold.update(false, start + Duration::from_secs(22) + RELEASE_AFTER), None);
+    }
+}
@@ -0,0 +1,122 @@
+//! Reveal files and folders in the OS file manager.
+//!
+//! Files are shown selected in their parent folder; folders are opened.
+//!   - Windows: `explorer.exe /select,"<file>"` / `explorer.exe "<dir>"`
+//!   - macOS:   `open -R <file>` / `open <dir>`
+//!   - Linux:   org.freedesktop.FileManager1 `ShowItems` over D-Bus (Nautilus,
+//!              Dolphin, Nemo, Thunar…), falling back to `xdg-open` on the folder
+
+use std::path::Path;
+use std::process::Command;
+
+use crate::access::{require_webview, Capability};
+use crate::paths::{display_path, long_path};
+use crate::validate_safe_path;
+
+/// Show `path` in the platform file manager.
+pub fn reveal(path: &Path) -> Result<(), String> {
+    if !long_path(path).exists() {
+        return Err(format!("Path does not exist: {}", path.display()));
+    }
+    let path = display_path(path);
+    platform_reveal(&path)
+}
+
+#[cfg(target_os = "windows")]
+fn platform_reveal(path: &Path) -> Result<(), String> {
+    use std::os::windows::process::CommandExt;
+
+    let mut cmd = Command::new("explorer.exe");
+    // explorer parses its own command line: the path must be quoted after
+    // the comma, which std's argument escaping would not produce.
+    if path.is_dir() {
+        cmd.raw_arg(format!("\"{}\"", path.display()));
+    } else {
+        cmd.raw_arg(format!("/select,\"{}\"", path.display()));
+    }
+    // explorer.exe exits with 1 even on success, so only spawn errors count
+    cmd.spawn()
+        .map(|_| ())
+        .map_err(|e| format!("Failed to open Explorer: {}", e))
+}
+
+#[cfg(target_os = "macos")]
+fn platform_reveal(path: &Path) -> Result<(), String> {
+    let mut cmd = Command::new("open");
+    if !path.is_dir() {
+        cmd.arg("-R");
+    }
+    let status = cmd
+        .arg(path)
+        .status()
+        .map_err(|e| format!("Failed to open Finder: {}", e))?;
+    if !status.success() {
+        return Err(format!("Finder could not reveal {}", path.display()));
+    }
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn platform_reveal(path: &Path) -> Result<(), String> {
+    if !path.is_dir() {
+        let shown = Command::new("dbus-send")
+            .args([
+                "--session",
+                "--print-reply",
+                "--dest=org.freedesktop.FileManager1",
+                "/org/freedesktop/FileManager1",
+                "org.freedesktop.FileManager1.ShowItems",
+            ])
+            .arg(format!("array:string:{}", file_uri(path)))
+            .arg("string:")
+            .output()
+            .map(|out| out.status.success())
+            .unwrap_or(false);
+        if shown {
+            return Ok(());
+        }
+    }
+
+    // No FileManager1 service: open the containing folder without selection
+    let folder = if path.is_dir() { path } else { path.parent().unwrap_or(path) };
+    Command::new("xdg-open")
+        .arg(folder)
+        .spawn()
+        .map(|_| ())
+        .map_err(|e| format!("Failed to run xdg-open: {}", e))
+}
+
+/// `file://` URI with percent-encoding, as expected by FileManager1.
+#[cfg_attr(any(target_os = "windows", target_os = "macos"), allow(dead_code))]
+fn file_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for byte in path.to_string_lossy().as_bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => uri.push(*byte as char),
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+/// Show a file (selected) or folder in Explorer / Finder / the Linux file manager.
+#[tauri::command]
+pub fn reveal_path(webview: tauri::Webview, path: String) -> Result<(), String> {
+    require_webview(&webview, Capability::ManageLibrary)?;
+    let validated = validate_safe_path(&path)?;
+    reveal(&validated)
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_uri_is_percent_encoded() {
+        assert_eq!(file_uri(Path::new("/home/me/My Songs/Björk.txt")), "file:///home/me/My%20Songs/Bj%C3%B6rk.txt");
+    }
+}
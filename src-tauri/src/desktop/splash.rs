@@ -0,0 +1,147 @@
+//! Native splash window shown while the server boots.
+//!
+//! The main window starts hidden (`tauri.conf.json`); the splash is a small
+//! undecorated window whose page is compiled into the binary and served
+//! over the `splash://` scheme, so it works before the server (or any
+//! frontend build) exists. The startup task reports phases through
+//! `set_phase` / `fail`, and a ticker pushes them with the server's log
+//! tail into the page via `eval`, which needs no IPC permissions.
+//! `close` swaps in the main window once the server answers.
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager, WebviewUrl, WebviewWindowBuilder};
+
+use crate::runtime::{sleep_or_cancel, TaskSupervisor};
+use crate::server::ServerManager;
+
+pub const SPLASH_LABEL: &str = "splash";
+pub const SPLASH_SCHEME: &str = "splash";
+const SPLASH_HTML: &str = include_str!("splash.html");
+const REFRESH_INTERVAL: Duration = Duration::from_millis(300);
+const LOG_LINES: usize = 12;
+
+#[derive(Debug, Clone, Serialize)]
+struct SplashView {
+    phase: String,
+    error: Option<String>,
+    logs: Vec<String>,
+}
+
+pub struct SplashState {
+    phase: Mutex<String>,
+    error: Mutex<Option<String>>,
+}
+
+impl Default for SplashState {
+    fn default() -> Self {
+        Self {
+            phase: Mutex::new("Starting…".to_string()),
+            error: Mutex::new(None),
+        }
+    }
+}
+
+/// Response for every `splash://` request.
+pub fn protocol_response() -> tauri::http::Response<&'static [u8]> {
+    tauri::http::Response::builder()
+        .header("Content-Type", "text/html; charset=utf-8")
+        .body(SPLASH_HTML.as_bytes())
+        .expect("static splash response")
+}
+
+fn splash_url() -> Result<tauri::Url, String> {
+    // Custom schemes are served from http://<scheme>.localhost on Windows/Android
+    let url = if cfg!(any(windows, target_os = "android")) {
+        format!("http://{}.localhost/", SPLASH_SCHEME)
+    } else {
+        format!("{}://localhost/", SPLASH_SCHEME)
+    };
+    url.parse().map_err(|e| format!("Invalid splash URL: {}", e))
+}
+
+/// Open the splash and start refreshing it.
+pub fn show(app: &AppHandle) {
+    let window = splash_url().and_then(|url| {
+        WebviewWindowBuilder::new(app, SPLASH_LABEL, WebviewUrl::External(url))
+            .title("Karaoke ZERO")
+            .inner_size(520.0, 320.0)
+            .resizable(false)
+            .decorations(false)
+            .center()
+            .build()
+            .map_err(|e| e.to_string())
+    });
+    if let Err(e) = window {
+        tracing::error!("[splash] Failed to open splash window: {}", e);
+        // Without a splash, show the (still empty) main window right away
+        show_main(app);
+        return;
+    }
+
+    let supervisor = app.state::<TaskSupervisor>();
+    let app = app.clone();
+    supervisor.spawn("splash", move |token| async move {
+        while sleep_or_cancel(&token, REFRESH_INTERVAL).await {
+            let Some(window) = app.get_webview_window(SPLASH_LABEL) else {
+                return;
+            };
+            let view = current_view(&app);
+            let Ok(json) = serde_json::to_string(&view) else { continue };
+            let _ = window.eval(&format!("window.__splash && window.__splash({})", json));
+        }
+    });
+}
+
+fn current_view(app: &AppHandle) -> SplashView {
+    let state = app.state::<SplashState>();
+    SplashView {
+        phase: state.phase.lock().map(|p| p.clone()).unwrap_or_default(),
+        error: state.error.lock().ok().and_then(|e| e.clone()),
+        logs: app.try_state::<ServerManager>().map(|m| m.logs(LOG_LINES)).unwrap_or_default(),
+    }
+}
+
+pub fn set_phase(app: &AppHandle, phase: impl Into<String>) {
+    if let Some(state) = app.try_state::<SplashState>() {
+        if let Ok(mut current) = state.phase.lock() {
+            *current = phase.into();
+        }
+    }
+}
+
+/// Startup failed: the splash stays up showing `error` and the log tail.
+pub fn fail(app: &AppHandle, error: impl Into<String>) {
+    if let Some(state) = app.try_state::<SplashState>() {
+        if let Ok(mut current) = state.error.lock() {
+            *current = Some(error.into());
+        }
+    }
+}
+
+fn show_main(app: &AppHandle) {
+    if let Some(main) = app.get_webview_window("main") {
+        let _ = main.show();
+        let _ = main.set_focus();
+    }
+}
+
+/// Replace the splash with the main window.
+pub fn close(app: &AppHandle) {
+    show_main(app);
+    if let Some(splash) = app.get_webview_window(SPLASH_LABEL) {
+        // `destroy` skips CloseRequested, which would quit the app
+        if let Err(e) = splash.destroy() {
+            tracing::error!("[splash] Failed to close splash window: {}", e);
+        }
+    }
+}
+
+/// The user closed the splash before the main window appeared.
+pub fn is_startup_abort(app: &AppHandle) -> bool {
+    app.get_webview_window("main")
+        .map(|main| !main.is_visible().unwrap_or(true))
+        .unwrap_or(false)
+}
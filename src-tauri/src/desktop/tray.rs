@@ -0,0 +1,111 @@
+//! System tray icon with playback and server controls.
+//!
+//! The menu works without the webview, so the operator can recover the
+//! show when the UI is frozen or sits on the projector: Play/Pause drives
+//! the native player, Restart server goes through the server manager,
+//! Open logs reveals the log folder and Quit shuts down like closing the
+//! main window. In kiosk mode Restart server, Open logs and Quit are
+//! ignored: a file manager or a dead server is a way out of the kiosk.
+//! Skip singer belongs to the queue,
+//! which the frontend runs; it is published as `tray://action`. A left
+//! click on the icon brings the main window forward.
+
+use serde::Serialize;
+use tauri::menu::{Menu, MenuEvent, MenuItem, PredefinedMenuItem};
+use tauri::tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent};
+use tauri::{AppHandle, Manager};
+
+use crate::audio::commands::AudioState;
+use crate::events::{publish, AppEvent};
+use crate::server;
+
+pub const TRAY_ACTION_EVENT: &str = "tray://action";
+const TRAY_ID: &str = "main-tray";
+
+const PLAY_PAUSE: &str = "play_pause";
+const SKIP_SINGER: &str = "skip_singer";
+const RESTART_SERVER: &str = "restart_server";
+const OPEN_LOGS: &str = "open_logs";
+const QUIT: &str = "quit";
+
+/// Payload of `tray://action`: a menu action the frontend carries out.
+#[derive(Debug, Clone, Serialize)]
+pub struct TrayAction {
+    pub action: String,
+}
+
+/// Create the tray icon. Failure is logged; the app runs without it.
+pub fn install(app: &AppHandle) {
+    if let Err(e) = build(app) {
+        tracing::error!("[tray] Failed to create tray icon: {}", e);
+    }
+}
+
+fn build(app: &AppHandle) -> tauri::Result<()> {
+    let menu = Menu::with_items(
+        app,
+        &[
+            &MenuItem::with_id(app, PLAY_PAUSE, "Play / Pause", true, None::<&str>)?,
+            &MenuItem::with_id(app, SKIP_SINGER, "Skip singer", true, None::<&str>)?,
+            &PredefinedMenuItem::separator(app)?,
+            &MenuItem::with_id(app, RESTART_SERVER, "Restart server", true, None::<&str>)?,
+            &MenuItem::with_id(app, OPEN_LOGS, "Open logs", true, None::<&str>)?,
+            &PredefinedMenuItem::separator(app)?,
+            &MenuItem::with_id(app, QUIT, "Quit", true, None::<&str>)?,
+        ],
+    )?;
+    let mut tray = TrayIconBuilder::with_id(TRAY_ID)
+        .tooltip("Karaoke ZERO")
+        .menu(&menu)
+        .show_menu_on_left_click(false)
+        .on_menu_event(on_menu_event)
+        .on_tray_icon_event(|tray, event| {
+            if let TrayIconEvent::Click { button: MouseButton::Left, button_state: MouseButtonState::Up, .. } = event {
+                if let Some(window) = tray.app_handle().get_webview_window("main") {
+                    let _ = window.unminimize();
+                    let _ = window.show();
+                    let _ = window.set_focus();
+                }
+            }
+        });
+    if let Some(icon) = app.default_window_icon() {
+        tray = tray.icon(icon.clone());
+    }
+    tray.build(app)?;
+    Ok(())
+}
+
+fn on_menu_event(app: &AppHandle, event: MenuEvent) {
+    match event.id().as_ref() {
+        PLAY_PAUSE => {
+            if let Err(e) = app.state::<AudioState>().toggle_playback() {
+                tracing::warn!("[tray] Play/Pause failed: {}", e);
+            }
+        }
+        SKIP_SINGER => publish(app, AppEvent::TrayAction(TrayAction { action: SKIP_SINGER.to_string() })),
+        RESTART_SERVER | OPEN_LOGS | QUIT if super::kiosk::is_active(app) => {
+            tracing::warn!("[tray] {} ignored in kiosk mode", event.id().as_ref());
+        }
+        RESTART_SERVER => {
+            crate::telemetry::server_restart(app, "manual");
+            let app = app.clone();
+            tauri::async_runtime::spawn(async move {
+                match server::restart_and_announce(app).await {
+                    Ok(_) => tracing::info!("[tray] Server restarted"),
+                    Err(e) => tracing::error!("[tray] {}", e),
+                }
+            });
+        }
+        OPEN_LOGS => {
+            let opened = app.path().app_log_dir().map_err(|e| e.to_string()).and_then(|dir| super::reveal::reveal(&dir));
+            if let Err(e) = opened {
+                tracing::warn!("[tray] Cannot open the log folder: {}", e);
+            }
+        }
+        QUIT => {
+            crate::shutdown_background(app);
+            app.exit(0);
+        }
+        other => tracing::debug!("[tray] Unknown menu item {}", other),
+    }
+}
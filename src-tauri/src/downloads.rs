@@ -0,0 +1,855 @@
+//! Download manager for songs and karaoke packs.
+//!
+//! Packs bought or found online are downloaded inside the app instead of a
+//! browser. `download_song` adds a download to a persistent queue (the
+//! `downloads` table) that one worker fetches in order:
+//!   - streamed to `<file>.part`, so a half-written file is never mistaken
+//!     for a song;
+//!   - resumed with a `Range` request after a pause, a dropped connection
+//!     (retried up to `MAX_RETRIES` times) or an app restart. `If-Range`
+//!     carries the validator (ETag or Last-Modified) of the first response,
+//!     so a file changed on the server starts over instead of being spliced;
+//!   - verified against the SHA-256 given with it before the part is
+//!     renamed into place (the hash of every finished download is kept);
+//!   - reported as `downloads://progress` with the transfer speed, at most
+//!     four times a second, and `downloads://changed` on status changes.
+//!
+//! Finished song files and packs go to the import worker like dropped
+//! files (see `library::drop_import`) unless `import` is off. Without a
+//! destination, downloads land in the downloads directory (see
+//! `library::quota`), named by the server's `Content-Disposition` or the
+//! URL.
+
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use reqwest::header::{self, HeaderMap};
+use reqwest::{StatusCode, Url};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Manager};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::Notify;
+use tokio_util::sync::CancellationToken;
+
+use crate::access::{require_webview, Capability};
+use crate::db::DbState;
+use crate::events::{publish, AppEvent};
+use crate::library::formats::VIDEO_EXTENSIONS;
+use crate::library::import_queue::ImportQueue;
+use crate::library::quota::{storage_dir, StorageKind};
+use crate::library::scanner::{has_extension, AUDIO_EXTENSIONS};
+use crate::paths::{long_path, sanitize_file_name};
+use crate::runtime::{sleep_or_cancel, TaskSupervisor};
+use crate::scheduler::now_ms;
+
+pub const PROGRESS_EVENT: &str = "downloads://progress";
+pub const CHANGED_EVENT: &str = "downloads://changed";
+
+const PART_EXTENSION: &str = "part";
+const MAX_RETRIES: u32 = 3;
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+/// How often the byte count is written back while downloading.
+const PERSIST_INTERVAL: Duration = Duration::from_secs(2);
+const MAX_URL_LEN: usize = 4096;
+/// Extensions handed to the importer once downloaded.
+const SONG_EXTENSIONS: &[&str] = &["zip", "txt", "cdg", "kar"];
+
+static HTTP_CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(20))
+        // No overall timeout: packs take as long as they take. A stalled
+        // connection fails the read instead.
+        .read_timeout(Duration::from_secs(60))
+        .user_agent(concat!("karaoke-successor/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .expect("Failed to build downloads HTTP client")
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DownloadStatus {
+    Queued,
+    Downloading,
+    Paused,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl DownloadStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Queued => "queued",
+            Self::Downloading => "downloading",
+            Self::Paused => "paused",
+            Self::Completed => "completed",
+            Self::Failed => "failed",
+            Self::Cancelled => "cancelled",
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        match value {
+            "downloading" => Self::Downloading,
+            "paused" => Self::Paused,
+            "completed" => Self::Completed,
+            "failed" => Self::Failed,
+            "cancelled" => Self::Cancelled,
+            _ => Self::Queued,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Download {
+    pub id: i64,
+    pub url: String,
+    pub dest_dir: String,
+    /// `None` until the server has named the file.
+    pub file_name: Option<String>,
+    /// Expected hash while downloading, actual hash once completed.
+    pub sha256: Option<String>,
+    pub status: DownloadStatus,
+    pub bytes_done: u64,
+    /// `None` when the server sends no length.
+    pub bytes_total: Option<u64>,
+    pub import: bool,
+    pub error: Option<String>,
+    /// Epoch ms.
+    pub created_at: i64,
+    pub updated_at: i64,
+    #[serde(skip)]
+    validator: Option<String>,
+}
+
+impl Download {
+    /// The finished file, once named.
+    pub fn path(&self) -> Option<PathBuf> {
+        self.file_name.as_ref().map(|name| Path::new(&self.dest_dir).join(name))
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DownloadProgress {
+    pub id: i64,
+    pub bytes_done: u64,
+    pub bytes_total: Option<u64>,
+    pub bytes_per_sec: u64,
+}
+
+/// Managed state: wakes the worker and lets commands stop the active
+/// download.
+#[derive(Default)]
+pub struct DownloadState {
+    wake: Notify,
+    active: Mutex<Option<(i64, CancellationToken)>>,
+}
+
+impl DownloadState {
+    fn stop_if_active(&self, id: i64) -> bool {
+        let Ok(active) = self.active.lock() else { return false };
+        match active.as_ref() {
+            Some((active_id, token)) if *active_id == id => {
+                token.cancel();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn set_active(&self, active: Option<(i64, CancellationToken)>) {
+        if let Ok(mut current) = self.active.lock() {
+            *current = active;
+        }
+    }
+}
+
+/// Why a transfer stopped early.
+enum FetchError {
+    /// Paused, cancelled or shutting down.
+    Stopped,
+    /// Worth retrying (connection dropped, server hiccup).
+    Network(String),
+    Failed(String),
+}
+
+// ---------------------------------------------------------------------------
+// Queue
+// ---------------------------------------------------------------------------
+
+const COLUMNS: &str =
+    "id, url, dest_dir, file_name, sha256, status, bytes_done, bytes_total, import, error, created_at, updated_at, validator";
+
+fn download_from_row(row: &rusqlite::Row) -> rusqlite::Result<Download> {
+    Ok(Download {
+        id: row.get(0)?,
+        url: row.get(1)?,
+        dest_dir: row.get(2)?,
+        file_name: row.get(3)?,
+        sha256: row.get(4)?,
+        status: DownloadStatus::parse(&row.get::<_, String>(5)?),
+        bytes_done: row.get::<_, i64>(6)?.max(0) as u64,
+        bytes_total: row.get::<_, Option<i64>>(7)?.map(|n| n.max(0) as u64),
+        import: row.get(8)?,
+        error: row.get(9)?,
+        created_at: row.get(10)?,
+        updated_at: row.get(11)?,
+        validator: row.get(12)?,
+    })
+}
+
+pub fn list(conn: &Connection) -> Result<Vec<Download>, String> {
+    let mut stmt = conn
+        .prepare(&format!("SELECT {} FROM downloads ORDER BY id", COLUMNS))
+        .map_err(|e| format!("Failed to read downloads: {}", e))?;
+    let rows = stmt
+        .query_map([], download_from_row)
+        .map_err(|e| format!("Failed to read downloads: {}", e))?;
+    rows.collect::<Result<_, _>>().map_err(|e| format!("Failed to read downloads: {}", e))
+}
+
+fn get(conn: &Connection, id: i64) -> Result<Download, String> {
+    conn.query_row(&format!("SELECT {} FROM downloads WHERE id = ?1", COLUMNS), [id], download_from_row)
+        .optional()
+        .map_err(|e| format!("Failed to read download: {}", e))?
+        .ok_or_else(|| format!("No download {}", id))
+}
+
+fn next_queued(conn: &Connection) -> Result<Option<Download>, String> {
+    conn.query_row(
+        &format!("SELECT {} FROM downloads WHERE status = 'queued' ORDER BY id LIMIT 1", COLUMNS),
+        [],
+        download_from_row,
+    )
+    .optional()
+    .map_err(|e| format!("Failed to read downloads: {}", e))
+}
+
+/// A URL the manager may fetch: http(s) only.
+pub(crate) fn validate_url(url: &str) -> Result<Url, String> {
+    if url.len() > MAX_URL_LEN {
+        return Err("URL is too long".to_string());
+    }
+    let parsed = Url::parse(url.trim()).map_err(|e| format!("Invalid URL: {}", e))?;
+    if !matches!(parsed.scheme(), "http" | "https") || parsed.host_str().is_none() {
+        return Err(format!("Only http and https downloads are supported: {}", url));
+    }
+    Ok(parsed)
+}
+
+fn validate_sha256(sha256: &str) -> Result<String, String> {
+    let sha256 = sha256.trim().to_ascii_lowercase();
+    if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err("SHA-256 must be 64 hex digits".to_string());
+    }
+    Ok(sha256)
+}
+
+pub fn add(
+    conn: &Connection,
+    url: &Url,
+    dest_dir: &Path,
+    file_name: Option<&str>,
+    sha256: Option<&str>,
+    import: bool,
+) -> Result<Download, String> {
+    let now = now_ms();
+    conn.execute(
+        "INSERT INTO downloads (url, dest_dir, file_name, sha256, import, created_at, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?6)",
+        params![url.as_str(), dest_dir.to_string_lossy(), file_name, sha256, import, now],
+    )
+    .map_err(|e| format!("Failed to queue download: {}", e))?;
+    get(conn, conn.last_insert_rowid())
+}
+
+/// Move a download to `status`; only from one of `from`. Returns whether
+/// it moved.
+fn transition(conn: &Connection, id: i64, from: &[DownloadStatus], status: DownloadStatus) -> Result<bool, String> {
+    let from: Vec<String> = from.iter().map(|s| format!("'{}'", s.as_str())).collect();
+    conn.execute(
+        &format!(
+            "UPDATE downloads SET status = ?1, error = NULL, updated_at = ?2 WHERE id = ?3 AND status IN ({})",
+            from.join(", ")
+        ),
+        params![status.as_str(), now_ms(), id],
+    )
+    .map(|n| n > 0)
+    .map_err(|e| format!("Failed to update download: {}", e))
+}
+
+fn set_progress(conn: &Connection, id: i64, bytes_done: u64) -> Result<(), String> {
+    conn.execute(
+        "UPDATE downloads SET bytes_done = ?1, updated_at = ?2 WHERE id = ?3",
+        params![bytes_done as i64, now_ms(), id],
+    )
+    .map(|_| ())
+    .map_err(|e| format!("Failed to update download: {}", e))
+}
+
+fn finish(conn: &Connection, id: i64, status: DownloadStatus, error: Option<&str>) -> Result<(), String> {
+    conn.execute(
+        "UPDATE downloads SET status = ?1, error = ?2, updated_at = ?3 WHERE id = ?4",
+        params![status.as_str(), error, now_ms(), id],
+    )
+    .map(|_| ())
+    .map_err(|e| format!("Failed to update download: {}", e))
+}
+
+/// Run `f` with the database locked.
+fn with_conn<T>(app: &AppHandle, f: impl FnOnce(&Connection) -> Result<T, String>) -> Result<T, String> {
+    let db = app.state::<DbState>();
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    f(&conn)
+}
+
+fn announce(app: &AppHandle, id: i64) {
+    match with_conn(app, |conn| get(conn, id)) {
+        Ok(download) => publish(app, AppEvent::DownloadChanged(download)),
+        Err(e) => tracing::warn!("[downloads] {}", e),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Transfer
+// ---------------------------------------------------------------------------
+
+fn part_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(PART_EXTENSION);
+    path.with_file_name(name)
+}
+
+/// Decode `%XX` escapes in a URL path segment.
+fn decode_segment(segment: &str) -> String {
+    let bytes = segment.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| segment.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                out.push(byte);
+                i += 3;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
+
+/// `filename="…"` (or the RFC 5987 `filename*=UTF-8''…`) of a
+/// `Content-Disposition` header.
+fn disposition_name(value: &str) -> Option<String> {
+    let params: Vec<(&str, &str)> = value
+        .split(';')
+        .filter_map(|part| part.split_once('='))
+        .map(|(key, value)| (key.trim(), value.trim()))
+        .collect();
+    if let Some((_, value)) = params.iter().find(|(key, _)| key.eq_ignore_ascii_case("filename*")) {
+        if let Some((_, encoded)) = value.split_once("''") {
+            return Some(decode_segment(encoded));
+        }
+    }
+    params
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case("filename"))
+        .map(|(_, value)| value.trim_matches('"').to_string())
+}
+
+/// Name for the downloaded file: the server's, else the URL's last path
+/// segment, made safe; never a path.
+fn file_name_for(url: &Url, headers: &HeaderMap, id: i64) -> String {
+    let from_header = headers
+        .get(header::CONTENT_DISPOSITION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(disposition_name);
+    let from_url = || url.path_segments().and_then(|mut s| s.next_back()).map(decode_segment);
+    from_header
+        .or_else(from_url)
+        .map(|name| name.rsplit(['/', '\\']).next().unwrap_or_default().trim().to_string())
+        .filter(|name| !name.is_empty() && name != "." && name != "..")
+        .map(|name| sanitize_file_name(&name))
+        .unwrap_or_else(|| format!("download-{}", id))
+}
+
+/// `dir/name`, numbered like `name (2).zip` when taken (or being written).
+fn free_path(dir: &Path, name: &str) -> PathBuf {
+    let path = Path::new(name);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+    let ext = path.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+    (1..)
+        .map(|n| if n == 1 { dir.join(name) } else { dir.join(format!("{} ({}){}", stem, n, ext)) })
+        .find(|candidate| !long_path(candidate).exists() && !long_path(&part_path(candidate)).exists())
+        .unwrap_or_else(|| dir.join(name))
+}
+
+/// Total size from `Content-Range: bytes 100-199/200`, and its start.
+fn content_range(headers: &HeaderMap) -> Option<(u64, Option<u64>)> {
+    let value = headers.get(header::CONTENT_RANGE)?.to_str().ok()?;
+    let range = value.trim().strip_prefix("bytes ")?;
+    let (span, total) = range.split_once('/')?;
+    let start = span.split_once('-')?.0.trim().parse().ok()?;
+    Some((start, total.trim().parse().ok()))
+}
+
+/// Whether a 206 reply continues the part at `offset`: only a range that
+/// starts there, of a file whose size is known.
+fn continues_at(headers: &HeaderMap, offset: u64) -> bool {
+    content_range(headers).is_some_and(|(start, total)| start == offset && total.is_some())
+}
+
+async fn hash_existing(path: &Path, hasher: &mut Sha256) -> Result<u64, String> {
+    let mut file = match tokio::fs::File::open(long_path(path)).await {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(format!("Failed to open {}: {}", path.display(), e)),
+    };
+    let mut buffer = vec![0u8; 64 * 1024];
+    let mut total = 0;
+    loop {
+        let n = file.read(&mut buffer).await.map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        if n == 0 {
+            return Ok(total);
+        }
+        hasher.update(&buffer[..n]);
+        total += n as u64;
+    }
+}
+
+/// One attempt at `download`; returns the finished file and its hash.
+async fn fetch(app: &AppHandle, download: &Download, stop: &CancellationToken) -> Result<(PathBuf, String), FetchError> {
+    let url = validate_url(&download.url).map_err(FetchError::Failed)?;
+    let mut hasher = Sha256::new();
+    let part = download.path().map(|path| part_path(&path));
+    let mut offset = match &part {
+        Some(part) => hash_existing(part, &mut hasher).await.map_err(FetchError::Failed)?,
+        None => 0,
+    };
+
+    let mut request = HTTP_CLIENT.get(url.clone());
+    if offset > 0 {
+        request = request.header(header::RANGE, format!("bytes={}-", offset));
+        if let Some(validator) = &download.validator {
+            request = request.header(header::IF_RANGE, validator);
+        }
+    }
+    let response = tokio::select! {
+        _ = stop.cancelled() => return Err(FetchError::Stopped),
+        response = request.send() => response.map_err(|e| FetchError::Network(e.to_string()))?,
+    };
+
+    let status = response.status();
+    let headers = response.headers().clone();
+    let resumed = status == StatusCode::PARTIAL_CONTENT && continues_at(&headers, offset);
+    if status == StatusCode::PARTIAL_CONTENT && !resumed {
+        // Some other part of the file, or of unknown size: never take a
+        // fragment for the whole file. Start over without a range
+        if let Some(part) = &part {
+            let _ = tokio::fs::remove_file(long_path(part)).await;
+        }
+        return Err(FetchError::Network(format!("Server sent a range other than bytes {}- of a known size; restarting", offset)));
+    }
+    if status == StatusCode::RANGE_NOT_SATISFIABLE && offset > 0 {
+        // The part is complete or stale; either way, start over cleanly
+        if let Some(part) = &part {
+            let _ = tokio::fs::remove_file(long_path(part)).await;
+        }
+        return Err(FetchError::Network("Server refused to resume; restarting".to_string()));
+    }
+    if !status.is_success() {
+        let error = format!("Server answered {}", status);
+        return Err(if status.is_server_error() { FetchError::Network(error) } else { FetchError::Failed(error) });
+    }
+    if !resumed && offset > 0 {
+        // The server sent the whole file (changed since, or no ranges)
+        hasher = Sha256::new();
+        offset = 0;
+    }
+    let total = if resumed {
+        content_range(&headers).and_then(|(_, total)| total)
+    } else {
+        response.content_length()
+    };
+
+    // Named once, on the first response; later attempts resume that file
+    let path = match download.path() {
+        Some(path) => path,
+        None => {
+            let dir = PathBuf::from(&download.dest_dir);
+            tokio::fs::create_dir_all(long_path(&dir))
+                .await
+                .map_err(|e| FetchError::Failed(format!("Failed to create {}: {}", dir.display(), e)))?;
+            free_path(&dir, &file_name_for(&url, &headers, download.id))
+        }
+    };
+    let validator = headers
+        .get(header::ETAG)
+        .or_else(|| headers.get(header::LAST_MODIFIED))
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    let file_name = path.file_name().map(|n| n.to_string_lossy().to_string());
+    with_conn(app, |conn| {
+        conn.execute(
+            "UPDATE downloads SET file_name = ?1, bytes_total = ?2, validator = COALESCE(?3, validator), bytes_done = ?4,
+                 updated_at = ?5 WHERE id = ?6",
+            params![file_name, total.map(|t| t as i64), validator, offset as i64, now_ms(), download.id],
+        )
+        .map_err(|e| format!("Failed to update download: {}", e))
+    })
+    .map_err(FetchError::Failed)?;
+
+    let part = part_path(&path);
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(resumed)
+        .truncate(!resumed)
+        .open(long_path(&part))
+        .await
+        .map_err(|e| FetchError::Failed(format!("Failed to create {}: {}", part.display(), e)))?;
+
+    let mut response = response;
+    let mut done = offset;
+    let (mut reported_at, mut reported_bytes, mut persisted_at) = (Instant::now(), done, Instant::now());
+    loop {
+        let chunk = tokio::select! {
+            _ = stop.cancelled() => None,
+            chunk = response.chunk() => Some(chunk),
+        };
+        let Some(chunk) = chunk else {
+            let _ = file.flush().await;
+            let _ = with_conn(app, |conn| set_progress(conn, download.id, done));
+            return Err(FetchError::Stopped);
+        };
+        let Some(bytes) = chunk.map_err(|e| FetchError::Network(e.to_string()))? else { break };
+        file.write_all(&bytes)
+            .await
+            .map_err(|e| FetchError::Failed(format!("Failed to write {}: {}", part.display(), e)))?;
+        hasher.update(&bytes);
+        done += bytes.len() as u64;
+
+        let elapsed = reported_at.elapsed();
+        if elapsed >= PROGRESS_INTERVAL {
+            let bytes_per_sec = ((done - reported_bytes) as f64 / elapsed.as_secs_f64()) as u64;
+            publish(
+                app,
+                AppEvent::DownloadProgress(DownloadProgress { id: download.id, bytes_done: done, bytes_total: total, bytes_per_sec }),
+            );
+            (reported_at, reported_bytes) = (Instant::now(), done);
+        }
+        if persisted_at.elapsed() >= PERSIST_INTERVAL {
+            let _ = with_conn(app, |conn| set_progress(conn, download.id, done));
+            persisted_at = Instant::now();
+        }
+    }
+    file.flush().await.map_err(|e| FetchError::Failed(format!("Failed to write {}: {}", part.display(), e)))?;
+    file.sync_all().await.map_err(|e| FetchError::Failed(format!("Failed to write {}: {}", part.display(), e)))?;
+    drop(file);
+    let _ = with_conn(app, |conn| set_progress(conn, download.id, done));
+    if let Some(total) = total.filter(|&total| done < total) {
+        return Err(FetchError::Network(format!("Connection closed after {} of {} bytes", done, total)));
+    }
+
+    let actual: String = hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect();
+    if let Some(expected) = &download.sha256 {
+        if *expected != actual {
+            let _ = tokio::fs::remove_file(long_path(&part)).await;
+            return Err(FetchError::Failed(format!("Checksum mismatch: expected {}, got {}", expected, actual)));
+        }
+    }
+    tokio::fs::rename(long_path(&part), long_path(&path))
+        .await
+        .map_err(|e| FetchError::Failed(format!("Failed to move {} into place: {}", path.display(), e)))?;
+    Ok((path, actual))
+}
+
+/// Run one download to its end: completed, failed, or stopped by a
+/// command or shutdown.
+async fn run(app: &AppHandle, id: i64, shutdown: &CancellationToken) {
+    let state = app.state::<DownloadState>();
+    let stop = shutdown.child_token();
+    state.set_active(Some((id, stop.clone())));
+    let started = with_conn(app, |conn| transition(conn, id, &[DownloadStatus::Queued], DownloadStatus::Downloading));
+    if !matches!(started, Ok(true)) {
+        state.set_active(None);
+        return;
+    }
+    announce(app, id);
+
+    let mut attempt = 0;
+    let result = loop {
+        let download = match with_conn(app, |conn| get(conn, id)) {
+            Ok(download) => download,
+            Err(e) => break Err(FetchError::Failed(e)),
+        };
+        match fetch(app, &download, &stop).await {
+            Err(FetchError::Network(e)) if attempt < MAX_RETRIES => {
+                attempt += 1;
+                tracing::warn!("[downloads] Download {} interrupted ({}); retry {} of {}", id, e, attempt, MAX_RETRIES);
+                if !sleep_or_cancel(&stop, RETRY_DELAY * attempt).await {
+                    break Err(FetchError::Stopped);
+                }
+            }
+            result => break result.map(|done| (download, done)),
+        }
+    };
+    state.set_active(None);
+
+    let finished = match result {
+        Ok((download, (path, sha256))) => {
+            tracing::info!("[downloads] Download {} finished: {}", id, path.display());
+            let saved = with_conn(app, |conn| {
+                conn.execute("UPDATE downloads SET sha256 = ?1 WHERE id = ?2", params![sha256, id])
+                    .map_err(|e| format!("Failed to update download: {}", e))?;
+                finish(conn, id, DownloadStatus::Completed, None)
+            });
+            if download.import && is_importable(&path) {
+                if let Err(e) = app.state::<ImportQueue>().enqueue_into_library(vec![path]) {
+                    tracing::error!("[downloads] Failed to queue import: {}", e);
+                }
+            }
+            saved
+        }
+        // Shutdown: left as downloading, the next start queues it again
+        Err(FetchError::Stopped) if shutdown.is_cancelled() => return,
+        // Paused or cancelled by a command, which set the status
+        Err(FetchError::Stopped) => {
+            if let Ok(download) = with_conn(app, |conn| get(conn, id)) {
+                if download.status == DownloadStatus::Cancelled {
+                    remove_part(&download);
+                }
+            }
+            Ok(())
+        }
+        Err(FetchError::Network(e) | FetchError::Failed(e)) => {
+            tracing::error!("[downloads] Download {} failed: {}", id, e);
+            with_conn(app, |conn| finish(conn, id, DownloadStatus::Failed, Some(&e)))
+        }
+    };
+    if let Err(e) = finished {
+        tracing::error!("[downloads] {}", e);
+    }
+    announce(app, id);
+}
+
+fn is_importable(path: &Path) -> bool {
+    has_extension(path, SONG_EXTENSIONS) || has_extension(path, AUDIO_EXTENSIONS) || has_extension(path, VIDEO_EXTENSIONS)
+}
+
+fn remove_part(download: &Download) {
+    if let Some(path) = download.path() {
+        let _ = std::fs::remove_file(long_path(&part_path(&path)));
+    }
+}
+
+/// Start the download worker. Downloads cut off by the last quit are
+/// queued again and resume where they stopped.
+pub fn spawn_worker(app: AppHandle) {
+    let requeued = with_conn(&app, |conn| {
+        conn.execute("UPDATE downloads SET status = 'queued' WHERE status = 'downloading'", [])
+            .map_err(|e| format!("Failed to requeue downloads: {}", e))
+    });
+    match requeued {
+        Ok(0) => {}
+        Ok(n) => tracing::info!("[downloads] Resuming {} interrupted download(s)", n),
+        Err(e) => tracing::error!("[downloads] {}", e),
+    }
+
+    let supervisor = app.state::<TaskSupervisor>();
+    let worker_app = app.clone();
+    supervisor.spawn("downloads", move |token| async move {
+        let app = worker_app;
+        let state = app.state::<DownloadState>();
+        loop {
+            match with_conn(&app, next_queued) {
+                Ok(Some(download)) => run(&app, download.id, &token).await,
+                Ok(None) => {
+                    tokio::select! {
+                        _ = token.cancelled() => return,
+                        _ = state.wake.notified() => {}
+                    }
+                }
+                Err(e) => {
+                    tracing::error!("[downloads] {}", e);
+                    if !sleep_or_cancel(&token, RETRY_DELAY).await {
+                        return;
+                    }
+                }
+            }
+            if token.is_cancelled() {
+                return;
+            }
+        }
+    });
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+/// Queue `url` for download into `dest` — a directory, or a file path to
+/// name the file — defaulting to the downloads directory. `sha256` is
+/// checked when given; `import` (default on) imports song files and packs
+/// once they are complete.
+#[tauri::command]
+pub fn download_song(
+    app: AppHandle,
+    webview: tauri::Webview,
+    url: String,
+    dest: Option<String>,
+    sha256: Option<String>,
+    import: Option<bool>,
+) -> Result<Download, String> {
+    require_webview(&webview, Capability::ManageLibrary)?;
+    let url = validate_url(&url)?;
+    let sha256 = sha256.filter(|s| !s.trim().is_empty()).map(|s| validate_sha256(&s)).transpose()?;
+    let dest = dest.map(|d| d.trim().to_string()).filter(|d| !d.is_empty());
+    let names_dir = dest.as_ref().is_some_and(|d| d.ends_with(['/', '\\']));
+    let (dest_dir, file_name) = match dest.map(PathBuf::from) {
+        Some(dest) if !dest.is_absolute() => return Err(format!("Destination must be an absolute path: {}", dest.display())),
+        Some(dest) if names_dir || long_path(&dest).is_dir() => (dest, None),
+        Some(dest) => {
+            let name = dest.file_name().map(|n| sanitize_file_name(&n.to_string_lossy())).ok_or("Invalid destination")?;
+            (dest.parent().map(Path::to_path_buf).ok_or("Invalid destination")?, Some(name))
+        }
+        None => (storage_dir(&app, StorageKind::Downloads)?, None),
+    };
+
+    let download = with_conn(&app, |conn| {
+        add(conn, &url, &dest_dir, file_name.as_deref(), sha256.as_deref(), import.unwrap_or(true))
+    })?;
+    tracing::info!("[downloads] Queued download {} from {}", download.id, url.host_str().unwrap_or_default());
+    publish(&app, AppEvent::DownloadChanged(download.clone()));
+    app.state::<DownloadState>().wake.notify_one();
+    Ok(download)
+}
+
+#[tauri::command]
+pub fn list_downloads(app: AppHandle, webview: tauri::Webview) -> Result<Vec<Download>, String> {
+    require_webview(&webview, Capability::ManageLibrary)?;
+    with_conn(&app, list)
+}
+
+/// Stop a queued or running download; `resume_download` continues it.
+#[tauri::command]
+pub fn pause_download(app: AppHandle, webview: tauri::Webview, id: i64) -> Result<Download, String> {
+    require_webview(&webview, Capability::ManageLibrary)?;
+    with_conn(&app, |conn| {
+        transition(conn, id, &[DownloadStatus::Queued, DownloadStatus::Downloading], DownloadStatus::Paused)
+    })?;
+    app.state::<DownloadState>().stop_if_active(id);
+    announce(&app, id);
+    with_conn(&app, |conn| get(conn, id))
+}
+
+/// Queue a paused or failed download again, continuing from its part file.
+#[tauri::command]
+pub fn resume_download(app: AppHandle, webview: tauri::Webview, id: i64) -> Result<Download, String> {
+    require_webview(&webview, Capability::ManageLibrary)?;
+    let resumed = with_conn(&app, |conn| {
+        transition(conn, id, &[DownloadStatus::Paused, DownloadStatus::Failed], DownloadStatus::Queued)
+    })?;
+    if resumed {
+        app.state::<DownloadState>().wake.notify_one();
+        announce(&app, id);
+    }
+    with_conn(&app, |conn| get(conn, id))
+}
+
+/// Stop a download for good and delete what it had downloaded.
+#[tauri::command]
+pub fn cancel_download(app: AppHandle, webview: tauri::Webview, id: i64) -> Result<Download, String> {
+    require_webview(&webview, Capability::ManageLibrary)?;
+    let from = [DownloadStatus::Queued, DownloadStatus::Downloading, DownloadStatus::Paused, DownloadStatus::Failed];
+    with_conn(&app, |conn| transition(conn, id, &from, DownloadStatus::Cancelled))?;
+    // A running download removes its part itself once it has stopped
+    if !app.state::<DownloadState>().stop_if_active(id) {
+        remove_part(&with_conn(&app, |conn| get(conn, id))?);
+    }
+    announce(&app, id);
+    with_conn(&app, |conn| get(conn, id))
+}
+
+/// Forget completed, failed and cancelled downloads (their files stay).
+/// Returns how many were removed.
+#[tauri::command]
+pub fn clear_finished_downloads(app: AppHandle, webview: tauri::Webview) -> Result<usize, String> {
+    require_webview(&webview, Capability::ManageLibrary)?;
+    with_conn(&app, |conn| {
+        conn.execute("DELETE FROM downloads WHERE status IN ('completed', 'failed', 'cancelled')", [])
+            .map_err(|e| format!("Failed to clear downloads: {}", e))
+    })
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn names_files_safely() {
+        let url = Url::parse("https://shop.example/packs/Best%20Of%2080s.zip?sig=1").unwrap();
+        let mut headers = HeaderMap::new();
+        assert_eq!(file_name_for(&url, &headers, 1), "Best Of 80s.zip");
+
+        headers.insert(header::CONTENT_DISPOSITION, HeaderValue::from_static("attachment; filename=\"../../evil.zip\""));
+        assert_eq!(file_name_for(&url, &headers, 1), "evil.zip");
+        headers.insert(header::CONTENT_DISPOSITION, HeaderValue::from_static("attachment; filename*=UTF-8''K%C3%B6ln.zip"));
+        assert_eq!(file_name_for(&url, &headers, 1), "Köln.zip");
+
+        let bare = Url::parse("https://shop.example/").unwrap();
+        assert_eq!(file_name_for(&bare, &HeaderMap::new(), 7), "download-7");
+    }
+
+    #[test]
+    fn parses_content_ranges_and_validates_input() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_RANGE, HeaderValue::from_static("bytes 100-199/200"));
+        assert_eq!(content_range(&headers), Some((100, Some(200))));
+        headers.insert(header::CONTENT_RANGE, HeaderValue::from_static("bytes 5-9/*"));
+        assert_eq!(content_range(&headers), Some((5, None)));
+        assert!(!continues_at(&headers, 5));
+        headers.insert(header::CONTENT_RANGE, HeaderValue::from_static("bytes 0-99/200"));
+        assert!(continues_at(&headers, 0));
+        assert!(!continues_at(&headers, 100));
+
+        assert!(validate_url("file:///etc/passwd").is_err());
+        assert!(validate_url("https://example.com/a.zip").is_ok());
+        assert!(validate_sha256("abc").is_err());
+        assert_eq!(validate_sha256(&"AB".repeat(32)).unwrap(), "ab".repeat(32));
+    }
+
+    #[test]
+    fn queue_survives_and_moves_through_states() {
+        let conn = crate::db::test_conn();
+
+        let url = Url::parse("https://example.com/a.zip").unwrap();
+        let first = add(&conn, &url, Path::new("/dl"), None, None, true).unwrap();
+        let second = add(&conn, &url, Path::new("/dl"), Some("b.zip"), None, false).unwrap();
+        assert_eq!(next_queued(&conn).unwrap().map(|d| d.id), Some(first.id));
+
+        assert!(transition(&conn, first.id, &[DownloadStatus::Queued], DownloadStatus::Paused).unwrap());
+        assert!(!transition(&conn, first.id, &[DownloadStatus::Queued], DownloadStatus::Downloading).unwrap());
+        assert_eq!(next_queued(&conn).unwrap().map(|d| d.id), Some(second.id));
+        assert_eq!(get(&conn, second.id).unwrap().path(), Some(PathBuf::from("/dl/b.zip")));
+        assert_eq!(get(&conn, first.id).unwrap().status, DownloadStatus::Paused);
+    }
+}
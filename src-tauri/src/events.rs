@@ -0,0 +1,326 @@
+//! Typed event bus between Rust subsystems and the webview.
+//!
+//! Subsystems publish an `AppEvent` instead of calling `app.emit` with ad-hoc
+//! payloads. `publish` fans each event out to:
+//!   - its per-topic Tauri event (`library://scan-progress`, …) with the bare
+//!     payload, unchanged for existing listeners;
+//!   - `app://event`, carrying a versioned `EventEnvelope` for consumers that
+//!     want a single typed stream;
+//!   - in-process subscribers (`EventBus::subscribe`), e.g. the WebSocket hub
+//!     broadcasting to phones.
+//!
+//! Bump `EVENT_SCHEMA_VERSION` when a payload changes incompatibly.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::broadcast;
+
+use crate::audio::fingerprint::{
+    DuplicateReport, FingerprintProgress, COMPLETE_EVENT as DUPLICATES_COMPLETE_EVENT, PROGRESS_EVENT as DUPLICATES_PROGRESS_EVENT,
+};
+use crate::audio::hotplug::{DeviceChangedEvent, DEVICE_CHANGED_EVENT};
+use crate::audio::live_pitch::{PitchFrame, PITCH_EVENT};
+use crate::audio::loudness::{
+    NormalizeComplete, NormalizeProgress, COMPLETE_EVENT as LOUDNESS_COMPLETE_EVENT, PROGRESS_EVENT as LOUDNESS_PROGRESS_EVENT,
+};
+use crate::audio::mic::{MicLevel, LEVEL_EVENT as MIC_LEVEL_EVENT};
+use crate::audio::position::{AudioPosition, POSITION_EVENT as AUDIO_POSITION_EVENT};
+use crate::audio::transition::{TrackChanged, TRACK_CHANGED_EVENT as AUDIO_TRACK_CHANGED_EVENT};
+use crate::cast::{CastStatus, STATUS_EVENT as CAST_STATUS_EVENT};
+use crate::clipboard_watch::{MediaUrl, MEDIA_URL_EVENT};
+use crate::config::{AppConfig, CONFIG_CHANGED_EVENT};
+use crate::deep_link::{EnqueueRequest, RejectedLink, ENQUEUE_EVENT, REJECTED_EVENT};
+use crate::desktop::hotkeys::{HotkeyPressed, HOTKEY_EVENT};
+use crate::downloads::{Download, DownloadProgress, CHANGED_EVENT as DOWNLOAD_CHANGED_EVENT, PROGRESS_EVENT as DOWNLOAD_PROGRESS_EVENT};
+use crate::desktop::tray::{TrayAction, TRAY_ACTION_EVENT};
+use crate::jobs::{Job, JobProgress, CHANGED_EVENT as JOB_CHANGED_EVENT, PROGRESS_EVENT as JOB_PROGRESS_EVENT};
+use crate::launch::{OpenRequest, OPEN_REQUEST_EVENT};
+use crate::lyrics::{LineEvent, WordEvent, LINE_EVENT as LYRICS_LINE_EVENT, WORD_EVENT as LYRICS_WORD_EVENT};
+use crate::library::commands::{ScanBatch, SCAN_BATCH_EVENT};
+use crate::library::data_migration::{MigrationProgress, PROGRESS_EVENT as DATA_MIGRATION_PROGRESS_EVENT};
+use crate::library::import_queue::{
+    ImportComplete, ImportProgress, IMPORT_COMPLETE_EVENT, IMPORT_PROGRESS_EVENT, SCAN_PROGRESS_EVENT,
+};
+use crate::library::quota::{DirUsage, QUOTA_EXCEEDED_EVENT};
+use crate::midi_control::{MidiActionEvent, MidiInputEvent, ACTION_EVENT as MIDI_ACTION_EVENT, INPUT_EVENT as MIDI_INPUT_EVENT};
+use crate::multiroom::{RoomCue, ROOM_CUE_EVENT};
+use crate::library::scan_pool::ScanProgress;
+use crate::library::watcher::{LibraryChange, LIBRARY_CHANGED_EVENT};
+use crate::media::native_video::{NativeVideo, NATIVE_EVENT as NATIVE_VIDEO_EVENT};
+use crate::media::online::{FetchProgress, PROGRESS_EVENT as ONLINE_PROGRESS_EVENT};
+use crate::media::thumbnails::{ThumbnailEvent, THUMBNAIL_FAILED_EVENT, THUMBNAIL_READY_EVENT};
+use crate::media::transcode::{
+    TranscodeEvent, COMPLETE_EVENT as TRANSCODE_COMPLETE_EVENT, FAILED_EVENT as TRANSCODE_FAILED_EVENT,
+    PROGRESS_EVENT as TRANSCODE_PROGRESS_EVENT,
+};
+use crate::party::{PartyCue, PARTY_CUE_EVENT};
+use crate::queue::{AutoplayRequest, QueueSnapshot, AUTOPLAY_EVENT, QUEUE_CHANGED_EVENT};
+use crate::remote::{RemoteCommand, REMOTE_COMMAND_EVENT};
+use crate::scoring::{LineScore, ScoringResult, LINE_EVENT as SCORING_LINE_EVENT, RESULT_EVENT as SCORING_RESULT_EVENT};
+use crate::server::node_check::{RuntimeIncompatible, INCOMPATIBLE_EVENT as SERVER_RUNTIME_INCOMPATIBLE_EVENT};
+use crate::server::watchdog::{ServerRecovery, GAVE_UP_EVENT, RECOVERED_EVENT, RESTARTING_EVENT};
+use crate::updater::{
+    UpdateFailed, UpdateProgress, UpdateReady, FAILED_EVENT as UPDATE_FAILED_EVENT, PROGRESS_EVENT as UPDATE_PROGRESS_EVENT,
+    READY_EVENT as UPDATE_READY_EVENT,
+};
+use crate::watch_party::{WatchPartyEvent, WATCH_PARTY_EVENT};
+
+pub const EVENT_SCHEMA_VERSION: u32 = 1;
+
+/// Tauri event carrying every `EventEnvelope`.
+pub const BUS_EVENT: &str = "app://event";
+pub const SERVER_STARTING_EVENT: &str = "server://starting";
+pub const SERVER_PORT_OPEN_EVENT: &str = "server://port-open";
+pub const SERVER_READY_EVENT: &str = "server://ready";
+pub const SERVER_ERROR_EVENT: &str = "server://error";
+pub const SERVER_TIMEOUT_EVENT: &str = "server://timeout";
+
+/// Envelopes buffered per slow subscriber before it starts missing events.
+const SUBSCRIBER_BUFFER: usize = 256;
+
+/// Every event a subsystem can publish.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", content = "data", rename_all = "camelCase")]
+pub enum AppEvent {
+    ScanProgress(ScanProgress),
+    ScanBatch(ScanBatch),
+    LibraryChanged(LibraryChange),
+    ImportProgress(ImportProgress),
+    ImportComplete(ImportComplete),
+    DownloadProgress(DownloadProgress),
+    DownloadChanged(Download),
+    JobChanged(Job),
+    JobProgress(JobProgress),
+    OnlineFetchProgress(FetchProgress),
+    NativeVideo(NativeVideo),
+    ThumbnailReady(ThumbnailEvent),
+    ThumbnailFailed(ThumbnailEvent),
+    TranscodeProgress(TranscodeEvent),
+    TranscodeComplete(TranscodeEvent),
+    TranscodeFailed(TranscodeEvent),
+    AudioDeviceChanged(DeviceChangedEvent),
+    AudioPosition(AudioPosition),
+    AudioTrackChanged(TrackChanged),
+    CastStatus(CastStatus),
+    MicLevel(MicLevel),
+    PitchFrame(PitchFrame),
+    LoudnessProgress(NormalizeProgress),
+    LoudnessComplete(NormalizeComplete),
+    FingerprintProgress(FingerprintProgress),
+    DuplicatesComplete(DuplicateReport),
+    ClipboardMediaUrl(MediaUrl),
+    TrayAction(TrayAction),
+    HotkeyPressed(HotkeyPressed),
+    MidiAction(MidiActionEvent),
+    MidiInput(MidiInputEvent),
+    RoomCue(RoomCue),
+    RemoteCommand(RemoteCommand),
+    EnqueueRequest(EnqueueRequest),
+    DeepLinkRejected(RejectedLink),
+    OpenRequest(OpenRequest),
+    ServerStarting { port: u16 },
+    /// The port accepts connections; the app may still be compiling.
+    ServerPortOpen { port: u16 },
+    ServerReady { url: String },
+    /// The server could not be started or exited while starting.
+    ServerError { reason: String },
+    /// Started but never answered its health check within `seconds`;
+    /// `port_open` tells a hung app from one that never listened.
+    ServerTimeout {
+        seconds: u64,
+        #[serde(rename = "portOpen")]
+        port_open: bool,
+    },
+    /// The chosen Node binary is too old or built for another CPU.
+    ServerRuntimeIncompatible(RuntimeIncompatible),
+    WatchParty(WatchPartyEvent),
+    StorageQuotaExceeded(DirUsage),
+    DataMigrationProgress(MigrationProgress),
+    PartyCue(PartyCue),
+    QueueChanged(QueueSnapshot),
+    QueueAutoplay(AutoplayRequest),
+    ServerRestarting(ServerRecovery),
+    ServerRecovered(ServerRecovery),
+    ServerGaveUp(ServerRecovery),
+    UpdateProgress(UpdateProgress),
+    UpdateReady(UpdateReady),
+    UpdateFailed(UpdateFailed),
+    ConfigChanged(AppConfig),
+    LyricsLine(LineEvent),
+    LyricsWord(WordEvent),
+    ScoringLine(LineScore),
+    ScoringResult(ScoringResult),
+}
+
+impl AppEvent {
+    /// Per-topic Tauri event name.
+    pub fn topic(&self) -> &'static str {
+        match self {
+            Self::ScanProgress(_) => SCAN_PROGRESS_EVENT,
+            Self::ScanBatch(_) => SCAN_BATCH_EVENT,
+            Self::LibraryChanged(_) => LIBRARY_CHANGED_EVENT,
+            Self::ImportProgress(_) => IMPORT_PROGRESS_EVENT,
+            Self::ImportComplete(_) => IMPORT_COMPLETE_EVENT,
+            Self::DownloadProgress(_) => DOWNLOAD_PROGRESS_EVENT,
+            Self::DownloadChanged(_) => DOWNLOAD_CHANGED_EVENT,
+            Self::JobChanged(_) => JOB_CHANGED_EVENT,
+            Self::JobProgress(_) => JOB_PROGRESS_EVENT,
+            Self::OnlineFetchProgress(_) => ONLINE_PROGRESS_EVENT,
+            Self::NativeVideo(_) => NATIVE_VIDEO_EVENT,
+            Self::ThumbnailReady(_) => THUMBNAIL_READY_EVENT,
+            Self::ThumbnailFailed(_) => THUMBNAIL_FAILED_EVENT,
+            Self::TranscodeProgress(_) => TRANSCODE_PROGRESS_EVENT,
+            Self::TranscodeComplete(_) => TRANSCODE_COMPLETE_EVENT,
+            Self::TranscodeFailed(_) => TRANSCODE_FAILED_EVENT,
+            Self::AudioDeviceChanged(_) => DEVICE_CHANGED_EVENT,
+            Self::AudioPosition(_) => AUDIO_POSITION_EVENT,
+            Self::AudioTrackChanged(_) => AUDIO_TRACK_CHANGED_EVENT,
+            Self::CastStatus(_) => CAST_STATUS_EVENT,
+            Self::MicLevel(_) => MIC_LEVEL_EVENT,
+            Self::PitchFrame(_) => PITCH_EVENT,
+            Self::LoudnessProgress(_) => LOUDNESS_PROGRESS_EVENT,
+            Self::LoudnessComplete(_) => LOUDNESS_COMPLETE_EVENT,
+            Self::FingerprintProgress(_) => DUPLICATES_PROGRESS_EVENT,
+            Self::DuplicatesComplete(_) => DUPLICATES_COMPLETE_EVENT,
+            Self::ClipboardMediaUrl(_) => MEDIA_URL_EVENT,
+            Self::TrayAction(_) => TRAY_ACTION_EVENT,
+            Self::HotkeyPressed(_) => HOTKEY_EVENT,
+            Self::MidiAction(_) => MIDI_ACTION_EVENT,
+            Self::MidiInput(_) => MIDI_INPUT_EVENT,
+            Self::RoomCue(_) => ROOM_CUE_EVENT,
+            Self::RemoteCommand(_) => REMOTE_COMMAND_EVENT,
+            Self::EnqueueRequest(_) => ENQUEUE_EVENT,
+            Self::DeepLinkRejected(_) => REJECTED_EVENT,
+            Self::OpenRequest(_) => OPEN_REQUEST_EVENT,
+            Self::ServerStarting { .. } => SERVER_STARTING_EVENT,
+            Self::ServerPortOpen { .. } => SERVER_PORT_OPEN_EVENT,
+            Self::ServerReady { .. } => SERVER_READY_EVENT,
+            Self::ServerError { .. } => SERVER_ERROR_EVENT,
+            Self::ServerTimeout { .. } => SERVER_TIMEOUT_EVENT,
+            Self::ServerRuntimeIncompatible(_) => SERVER_RUNTIME_INCOMPATIBLE_EVENT,
+            Self::WatchParty(_) => WATCH_PARTY_EVENT,
+            Self::StorageQuotaExceeded(_) => QUOTA_EXCEEDED_EVENT,
+            Self::DataMigrationProgress(_) => DATA_MIGRATION_PROGRESS_EVENT,
+            Self::PartyCue(_) => PARTY_CUE_EVENT,
+            Self::QueueChanged(_) => QUEUE_CHANGED_EVENT,
+            Self::QueueAutoplay(_) => AUTOPLAY_EVENT,
+            Self::ServerRestarting(_) => RESTARTING_EVENT,
+            Self::ServerRecovered(_) => RECOVERED_EVENT,
+            Self::ServerGaveUp(_) => GAVE_UP_EVENT,
+            Self::UpdateProgress(_) => UPDATE_PROGRESS_EVENT,
+            Self::UpdateReady(_) => UPDATE_READY_EVENT,
+            Self::UpdateFailed(_) => UPDATE_FAILED_EVENT,
+            Self::ConfigChanged(_) => CONFIG_CHANGED_EVENT,
+            Self::LyricsLine(_) => LYRICS_LINE_EVENT,
+            Self::LyricsWord(_) => LYRICS_WORD_EVENT,
+            Self::ScoringLine(_) => SCORING_LINE_EVENT,
+            Self::ScoringResult(_) => SCORING_RESULT_EVENT,
+        }
+    }
+}
+
+/// What `app://event` listeners and bus subscribers receive.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EventEnvelope {
+    pub version: u32,
+    /// Increases by one per published event; gaps mean a subscriber lagged.
+    pub seq: u64,
+    /// Epoch ms.
+    pub timestamp: i64,
+    pub topic: &'static str,
+    #[serde(flatten)]
+    pub event: AppEvent,
+}
+
+/// Managed state: sequence counter and in-process fan-out.
+pub struct EventBus {
+    seq: AtomicU64,
+    tx: broadcast::Sender<Arc<EventEnvelope>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(SUBSCRIBER_BUFFER);
+        Self {
+            seq: AtomicU64::new(0),
+            tx,
+        }
+    }
+
+    /// Receive every envelope published from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<EventEnvelope>> {
+        self.tx.subscribe()
+    }
+
+    fn envelope(&self, event: AppEvent) -> EventEnvelope {
+        EventEnvelope {
+            version: EVENT_SCHEMA_VERSION,
+            seq: self.seq.fetch_add(1, Ordering::Relaxed) + 1,
+            timestamp: now_ms(),
+            topic: event.topic(),
+            event,
+        }
+    }
+}
+
+/// Publish `event` to the webview and all subscribers.
+/// Works before `EventBus` is managed (only the per-topic event is sent).
+pub fn publish(app: &AppHandle, event: AppEvent) {
+    let topic = event.topic();
+    let emitted = match serde_json::to_value(&event) {
+        Ok(mut value) => app.emit(topic, value.get_mut("data").map(serde_json::Value::take)),
+        Err(e) => {
+            tracing::error!("[events] Failed to serialize {}: {}", topic, e);
+            return;
+        }
+    };
+    if let Err(e) = emitted {
+        tracing::error!("[events] Failed to emit {}: {}", topic, e);
+    }
+
+    let Some(bus) = app.try_state::<EventBus>() else { return };
+    let envelope = bus.envelope(event);
+    if let Err(e) = app.emit(BUS_EVENT, &envelope) {
+        tracing::error!("[events] Failed to emit {}: {}", BUS_EVENT, e);
+    }
+    // No subscribers is not an error
+    let _ = bus.tx.send(Arc::new(envelope));
+}
+
+fn now_ms() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn envelope_is_versioned_and_tagged() {
+        let bus = EventBus::new();
+        let mut rx = bus.subscribe();
+        let envelope = bus.envelope(AppEvent::ServerReady {
+            url: "http://localhost:3000".into(),
+        });
+        bus.tx.send(Arc::new(envelope)).unwrap();
+
+        let received = rx.try_recv().unwrap();
+        let json = serde_json::to_value(&*received).unwrap();
+        assert_eq!(json["version"], EVENT_SCHEMA_VERSION);
+        assert_eq!(json["seq"], 1);
+        assert_eq!(json["topic"], SERVER_READY_EVENT);
+        assert_eq!(json["type"], "serverReady");
+        assert_eq!(json["data"]["url"], "http://localhost:3000");
+    }
+}
@@ -0,0 +1,296 @@
+//! Performance history and statistics.
+//!
+//! Every performance gets a row in `performances`: one opens when
+//! `queue_next` puts a queue entry on stage and closes, with the key offset
+//! it was sung in, when the next one starts. A scoring result fills in the
+//! score of the open performance of that song, or is recorded on its own
+//! when the song was started outside the queue. `get_history` and
+//! `get_stats` feed leaderboards and "most popular this month" screens.
+
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+use crate::db::DbState;
+use crate::queue::QueueEntry;
+use crate::scoring::ScoringResult;
+
+const DEFAULT_LIMIT: u32 = 100;
+const MAX_LIMIT: u32 = 1000;
+const TOP_SONGS: u32 = 10;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Performance {
+    pub id: i64,
+    pub song_id: String,
+    pub song_title: String,
+    pub song_artist: Option<String>,
+    pub singer: String,
+    /// Epoch ms.
+    pub started_at: i64,
+    /// `None` while on stage.
+    pub ended_at: Option<i64>,
+    /// `None` when nobody scored it.
+    pub score: Option<f64>,
+    pub key_offset: i32,
+}
+
+/// All fields optional; times are epoch ms, `until` exclusive.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct HistoryFilter {
+    pub singer: Option<String>,
+    pub song_id: Option<String>,
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    /// Newest first, default 100.
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SongCount {
+    pub song_id: String,
+    pub song_title: String,
+    pub song_artist: Option<String>,
+    pub performances: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SingerStats {
+    pub singer: String,
+    pub performances: u32,
+    /// Over scored performances only.
+    pub average_score: Option<f64>,
+    pub best_score: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Stats {
+    pub performances: u32,
+    /// Most sung first.
+    pub top_songs: Vec<SongCount>,
+    /// Best average first; unscored singers last.
+    pub singers: Vec<SingerStats>,
+}
+
+/// Open a performance for the entry going on stage.
+pub fn start(conn: &Connection, entry: &QueueEntry) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO performances (song_id, song_title, song_artist, singer, started_at, queue_entry_id)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            entry.song_id,
+            entry.song_title.as_deref().unwrap_or(&entry.song_id),
+            entry.song_artist,
+            entry.singer,
+            entry.started_at.unwrap_or_else(crate::scheduler::now_ms),
+            entry.id,
+        ],
+    )
+    .map(|_| ())
+    .map_err(|e| format!("Failed to record performance: {}", e))
+}
+
+/// Close the open performance, sung in `key_offset`.
+pub fn finish(conn: &Connection, key_offset: i32) -> Result<(), String> {
+    conn.execute(
+        "UPDATE performances SET ended_at = ?1, key_offset = ?2 WHERE ended_at IS NULL",
+        params![crate::scheduler::now_ms(), key_offset],
+    )
+    .map(|_| ())
+    .map_err(|e| format!("Failed to close performance: {}", e))
+}
+
+/// Attach `result` to the open performance of its song, or record it as a
+/// performance of its own.
+pub fn record_score(conn: &Connection, result: &ScoringResult, key_offset: i32) -> Result<(), String> {
+    let updated = conn
+        .execute(
+            "UPDATE performances SET score = ?1 WHERE id = (
+                 SELECT id FROM performances WHERE ended_at IS NULL AND song_id = ?2
+                 ORDER BY started_at DESC LIMIT 1)",
+            params![result.score, result.song_id],
+        )
+        .map_err(|e| format!("Failed to record score: {}", e))?;
+    if updated > 0 {
+        return Ok(());
+    }
+    let now = crate::scheduler::now_ms();
+    conn.execute(
+        "INSERT INTO performances (song_id, song_title, singer, started_at, ended_at, score, key_offset)
+         VALUES (?1, ?2, ?3, ?4, ?4, ?5, ?6)",
+        params![result.song_id, result.song_title, result.player_name, now, result.score, key_offset],
+    )
+    .map(|_| ())
+    .map_err(|e| format!("Failed to record performance: {}", e))
+}
+
+pub fn history(conn: &Connection, filter: &HistoryFilter) -> Result<Vec<Performance>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, song_id, song_title, song_artist, singer, started_at, ended_at, score, key_offset
+             FROM performances
+             WHERE (?1 IS NULL OR lower(singer) = lower(trim(?1)))
+               AND (?2 IS NULL OR song_id = ?2)
+               AND (?3 IS NULL OR started_at >= ?3)
+               AND (?4 IS NULL OR started_at < ?4)
+             ORDER BY started_at DESC, id DESC
+             LIMIT ?5",
+        )
+        .map_err(|e| format!("Failed to read history: {}", e))?;
+    let limit = filter.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let rows = stmt
+        .query_map(params![filter.singer, filter.song_id, filter.since, filter.until, limit], |row| {
+            Ok(Performance {
+                id: row.get(0)?,
+                song_id: row.get(1)?,
+                song_title: row.get(2)?,
+                song_artist: row.get(3)?,
+                singer: row.get(4)?,
+                started_at: row.get(5)?,
+                ended_at: row.get(6)?,
+                score: row.get(7)?,
+                key_offset: row.get(8)?,
+            })
+        })
+        .map_err(|e| format!("Failed to read history: {}", e))?;
+    rows.collect::<Result<_, _>>().map_err(|e| format!("Failed to read history: {}", e))
+}
+
+/// Statistics over performances started since `since` (epoch ms; all
+/// time with `None`).
+pub fn stats(conn: &Connection, since: Option<i64>) -> Result<Stats, String> {
+    let since = since.unwrap_or(0);
+    let performances: u32 = conn
+        .query_row("SELECT COUNT(*) FROM performances WHERE started_at >= ?1", [since], |row| row.get(0))
+        .map_err(|e| format!("Failed to read stats: {}", e))?;
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT song_id, MAX(song_title), MAX(song_artist), COUNT(*) AS n
+             FROM performances WHERE started_at >= ?1
+             GROUP BY song_id ORDER BY n DESC, MAX(started_at) DESC LIMIT ?2",
+        )
+        .map_err(|e| format!("Failed to read stats: {}", e))?;
+    let top_songs = stmt
+        .query_map(params![since, TOP_SONGS], |row| {
+            Ok(SongCount {
+                song_id: row.get(0)?,
+                song_title: row.get(1)?,
+                song_artist: row.get(2)?,
+                performances: row.get(3)?,
+            })
+        })
+        .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("Failed to read stats: {}", e))?;
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT MIN(singer), COUNT(*), AVG(score), MAX(score)
+             FROM performances WHERE started_at >= ?1
+             GROUP BY lower(trim(singer)) ORDER BY AVG(score) DESC NULLS LAST, COUNT(*) DESC",
+        )
+        .map_err(|e| format!("Failed to read stats: {}", e))?;
+    let singers = stmt
+        .query_map([since], |row| {
+            Ok(SingerStats {
+                singer: row.get(0)?,
+                performances: row.get(1)?,
+                average_score: row.get(2)?,
+                best_score: row.get(3)?,
+            })
+        })
+        .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("Failed to read stats: {}", e))?;
+
+    Ok(Stats { performances, top_songs, singers })
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+#[tauri::command]
+pub fn get_history(app: AppHandle, filters: Option<HistoryFilter>) -> Result<Vec<Performance>, String> {
+    let db = app.state::<DbState>();
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    history(&conn, &filters.unwrap_or_default())
+}
+
+#[tauri::command]
+pub fn get_stats(app: AppHandle, since: Option<i64>) -> Result<Stats, String> {
+    let db = app.state::<DbState>();
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    stats(&conn, since)
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::queue::EntryState;
+
+    fn entry(id: i64, singer: &str, song: &str) -> QueueEntry {
+        QueueEntry {
+            id,
+            singer: singer.to_string(),
+            song_id: song.to_string(),
+            song_title: Some(song.to_uppercase()),
+            song_artist: None,
+            state: EntryState::Current,
+            added_at: 0,
+            started_at: Some(id * 1000),
+        }
+    }
+
+    fn scored(song: &str, singer: &str, score: f64) -> ScoringResult {
+        ScoringResult {
+            song_id: song.to_string(),
+            song_title: song.to_uppercase(),
+            player_id: singer.to_lowercase(),
+            player_name: singer.to_string(),
+            difficulty: "medium".to_string(),
+            score,
+            note_score: score,
+            golden_score: 0.0,
+            line_bonus: 0.0,
+            accuracy: 0.0,
+            rating: "",
+            perfect_notes: 0,
+            good_notes: 0,
+            miss_notes: 0,
+            max_combo: 0,
+            lines: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn records_queue_performances_and_scores() {
+        let conn = crate::db::test_conn();
+
+        start(&conn, &entry(1, "Alice", "s1")).unwrap();
+        record_score(&conn, &scored("s1", "Alice", 8000.0), 0).unwrap();
+        finish(&conn, -2).unwrap();
+        start(&conn, &entry(2, "Bob", "s1")).unwrap();
+        finish(&conn, 0).unwrap();
+        // Scored outside the queue
+        record_score(&conn, &scored("s2", "alice", 6000.0), 1).unwrap();
+
+        let alice = history(&conn, &HistoryFilter { singer: Some("ALICE".to_string()), ..Default::default() }).unwrap();
+        assert_eq!(alice.len(), 2);
+        assert_eq!((alice[1].score, alice[1].key_offset), (Some(8000.0), -2));
+
+        let stats = stats(&conn, None).unwrap();
+        assert_eq!(stats.performances, 3);
+        assert_eq!((stats.top_songs[0].song_id.as_str(), stats.top_songs[0].performances), ("s1", 2));
+        assert_eq!(stats.singers[0].average_score, Some(7000.0));
+        assert_eq!((stats.singers[1].singer.as_str(), stats.singers[1].average_score), ("Bob", None));
+    }
+}
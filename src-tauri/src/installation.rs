@@ -0,0 +1,379 @@
+//! Checks the bundled resources, and repairs them from a release archive.
+//!
+//! `scripts/prepare-bundle.mjs` writes `bundled/server/resource-manifest.json`
+//! with the size and SHA-256 of every file under `bundled/server`,
+//! `bundled/node`, `bundled/bun` and `bundled/native`.
+//! `validate_installation` compares the installed files against it and
+//! reports exactly which are missing or damaged. A quick check compares
+//! sizes and hashes only the server entry point and the runtime binaries;
+//! `deep` hashes every file.
+//!
+//! Installed builds cannot unpack their installer again, but the portable
+//! release zip carries the same `bundled/` tree: `repair_installation`
+//! takes the files that failed the check from such an archive, verifies
+//! them against the manifest and puts them in place. Writing into a
+//! per-machine install directory may need the app to run elevated.
+//!
+//! Dev builds have no manifest; the check then only looks for the server
+//! and the runtime the way startup does.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Manager};
+use zip::ZipArchive;
+
+use crate::access::{require_webview, Capability};
+use crate::server::runtime::RuntimeKind;
+
+/// Manifest location relative to the resource directory.
+const MANIFEST_PATH: &str = "bundled/server/resource-manifest.json";
+const MANIFEST_FORMAT: u32 = 1;
+/// Files hashed even by a quick check; startup fails without them.
+const CRITICAL_FILES: &[&str] = &["server/server.js", "node/node.exe", "node/bin/node", "bun/bun.exe", "bun/bun"];
+/// Problems named in the startup error before it says "and N more".
+const SUMMARY_FILES: usize = 3;
+
+#[derive(Debug, Clone, Deserialize)]
+struct Manifest {
+    format: u32,
+    version: String,
+    files: Vec<ManifestFile>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ManifestFile {
+    /// Relative to `bundled/`, with forward slashes.
+    path: String,
+    size: u64,
+    sha256: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DamagedFile {
+    pub path: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InstallationReport {
+    pub manifest_found: bool,
+    /// Version the manifest was written for, when it differs from the app.
+    pub manifest_version: Option<String>,
+    /// Files compared against the manifest.
+    pub checked: usize,
+    /// Paths relative to `bundled/`.
+    pub missing: Vec<String>,
+    pub corrupt: Vec<DamagedFile>,
+    /// Server entry point startup would use.
+    pub server: Option<String>,
+    /// Bundled Node (or else Bun) binary, if any; otherwise startup looks
+    /// for a system one.
+    pub node: Option<String>,
+    pub healthy: bool,
+    /// The damaged files are listed in the manifest, so a release archive
+    /// can restore them.
+    pub repairable: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RepairReport {
+    pub restored: Vec<String>,
+    pub failed: Vec<DamagedFile>,
+    /// State after the repair.
+    pub report: InstallationReport,
+}
+
+fn read_manifest(res_dir: &Path) -> Result<Option<Manifest>, String> {
+    let path = res_dir.join(MANIFEST_PATH);
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+    };
+    let manifest: Manifest = serde_json::from_str(&text).map_err(|e| format!("Invalid resource manifest {}: {}", path.display(), e))?;
+    if manifest.format != MANIFEST_FORMAT {
+        return Err(format!("Unsupported resource manifest format {}", manifest.format));
+    }
+    Ok(Some(manifest))
+}
+
+fn bundled_path(bundled: &Path, relative: &str) -> PathBuf {
+    relative.split('/').fold(bundled.to_path_buf(), |path, part| path.join(part))
+}
+
+fn hash_file(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            return Ok(format!("{:x}", hasher.finalize()));
+        }
+        hasher.update(&buf[..n]);
+    }
+}
+
+/// Compare the files under `bundled` with `manifest`.
+fn check_files(bundled: &Path, manifest: &Manifest, deep: bool) -> (Vec<String>, Vec<DamagedFile>) {
+    let mut missing = Vec::new();
+    let mut corrupt = Vec::new();
+    for file in &manifest.files {
+        let path = bundled_path(bundled, &file.path);
+        let size = match fs::metadata(&path) {
+            Ok(meta) if meta.is_file() => meta.len(),
+            Ok(_) => {
+                corrupt.push(DamagedFile { path: file.path.clone(), reason: "not a file".to_string() });
+                continue;
+            }
+            Err(_) => {
+                missing.push(file.path.clone());
+                continue;
+            }
+        };
+        if size != file.size {
+            corrupt.push(DamagedFile { path: file.path.clone(), reason: format!("{} bytes, expected {}", size, file.size) });
+            continue;
+        }
+        if !deep && !CRITICAL_FILES.contains(&file.path.as_str()) {
+            continue;
+        }
+        match hash_file(&path) {
+            Ok(hash) if hash.eq_ignore_ascii_case(&file.sha256) => {}
+            Ok(_) => corrupt.push(DamagedFile { path: file.path.clone(), reason: "checksum mismatch".to_string() }),
+            Err(e) => corrupt.push(DamagedFile { path: file.path.clone(), reason: format!("unreadable: {}", e) }),
+        }
+    }
+    (missing, corrupt)
+}
+
+fn validate(res_dir: &PathBuf, app_version: &str, deep: bool) -> Result<InstallationReport, String> {
+    let manifest = read_manifest(res_dir)?;
+    let server = crate::get_server_path(res_dir);
+    let node = crate::server::runtime::bundled(res_dir, RuntimeKind::Node)
+        .or_else(|| crate::server::runtime::bundled(res_dir, RuntimeKind::Bun));
+    let (checked, mut missing, corrupt, manifest_version) = match &manifest {
+        Some(manifest) => {
+            let (missing, corrupt) = check_files(&res_dir.join("bundled"), manifest, deep);
+            let stale = (manifest.version != app_version).then(|| manifest.version.clone());
+            (manifest.files.len(), missing, corrupt, stale)
+        }
+        None => (0, Vec::new(), Vec::new(), None),
+    };
+    if server.is_none() && !missing.iter().any(|path| path == "server/server.js") {
+        missing.push("server/server.js".to_string());
+    }
+    let damaged = missing.len() + corrupt.len();
+    let listed = |path: &str| manifest.as_ref().is_some_and(|m| m.files.iter().any(|f| f.path == path));
+    Ok(InstallationReport {
+        manifest_found: manifest.is_some(),
+        manifest_version,
+        checked,
+        repairable: damaged > 0 && missing.iter().all(|path| listed(path)),
+        healthy: damaged == 0,
+        server: server.map(|p| p.to_string_lossy().to_string()),
+        node: node.map(|p| p.to_string_lossy().to_string()),
+        missing,
+        corrupt,
+    })
+}
+
+/// One line naming what is wrong with the installation, for the startup
+/// error; `None` when the quick check finds nothing.
+pub fn problem_summary(res_dir: &PathBuf, app_version: &str) -> Option<String> {
+    let report = match validate(res_dir, app_version, false) {
+        Ok(report) => report,
+        Err(e) => return Some(e),
+    };
+    if report.healthy {
+        return None;
+    }
+    let mut problems: Vec<String> = report.missing.iter().map(|path| format!("{} is missing", path)).collect();
+    problems.extend(report.corrupt.iter().map(|file| format!("{} is damaged ({})", file.path, file.reason)));
+    let more = problems.len().saturating_sub(SUMMARY_FILES);
+    problems.truncate(SUMMARY_FILES);
+    let mut summary = format!("The installation is incomplete: {}", problems.join(", "));
+    if more > 0 {
+        summary.push_str(&format!(" and {} more", more));
+    }
+    Some(summary)
+}
+
+/// `bundled/`-relative path of an archive member, e.g. `server/server.js`
+/// for `karaoke-successor/bundled/server/server.js`.
+fn member_path(name: &str) -> Option<String> {
+    let name = name.replace('\\', "/");
+    let start = if name.starts_with("bundled/") { 0 } else { name.find("/bundled/")? + 1 };
+    let relative = &name[start + "bundled/".len()..];
+    (!relative.is_empty() && !relative.split('/').any(|part| part == "..")).then(|| relative.to_string())
+}
+
+/// Extract one member next to `target`, verify it and move it into place.
+fn restore_file(member: &mut impl Read, unix_mode: Option<u32>, target: &Path, expected: &ManifestFile) -> Result<(), String> {
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    let partial = target.with_extension("repair");
+    let result = (|| -> Result<(), String> {
+        let mut out = File::create(&partial).map_err(|e| format!("Failed to create {}: {}", partial.display(), e))?;
+        let mut hasher = Sha256::new();
+        let mut buf = vec![0u8; 64 * 1024];
+        let mut written = 0u64;
+        loop {
+            let n = member.read(&mut buf).map_err(|e| format!("Failed to extract: {}", e))?;
+            if n == 0 {
+                break;
+            }
+            written += n as u64;
+            // The declared size may lie; never inflate past the manifest
+            if written > expected.size {
+                return Err("larger than the manifest says".to_string());
+            }
+            hasher.update(&buf[..n]);
+            out.write_all(&buf[..n]).map_err(|e| format!("Failed to write {}: {}", partial.display(), e))?;
+        }
+        if written != expected.size || !format!("{:x}", hasher.finalize()).eq_ignore_ascii_case(&expected.sha256) {
+            return Err("the archive holds a different version of this file".to_string());
+        }
+        drop(out);
+        #[cfg(not(unix))]
+        let _ = unix_mode;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = unix_mode.unwrap_or(0o644) & 0o777;
+            fs::set_permissions(&partial, fs::Permissions::from_mode(mode.max(0o400)))
+                .map_err(|e| format!("Failed to set permissions on {}: {}", partial.display(), e))?;
+        }
+        fs::rename(&partial, target).map_err(|e| format!("Failed to replace {}: {}", target.display(), e))
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&partial);
+    }
+    result
+}
+
+fn repair(res_dir: &PathBuf, app_version: &str, archive: &Path) -> Result<RepairReport, String> {
+    let manifest = read_manifest(res_dir)?.ok_or("This build has no resource manifest to repair against")?;
+    let report = validate(res_dir, app_version, true)?;
+    let mut wanted: HashMap<&str, &ManifestFile> = HashMap::new();
+    for path in report.missing.iter().chain(report.corrupt.iter().map(|file| &file.path)) {
+        if let Some(file) = manifest.files.iter().find(|file| &file.path == path) {
+            wanted.insert(file.path.as_str(), file);
+        }
+    }
+    if wanted.is_empty() {
+        return Ok(RepairReport { restored: Vec::new(), failed: Vec::new(), report });
+    }
+
+    let file = File::open(archive).map_err(|e| format!("Failed to open {}: {}", archive.display(), e))?;
+    let mut zip = ZipArchive::new(file).map_err(|e| format!("Not a readable zip archive {}: {}", archive.display(), e))?;
+    let bundled = res_dir.join("bundled");
+    let mut restored = Vec::new();
+    let mut failed = Vec::new();
+    for index in 0..zip.len() {
+        let mut member = zip.by_index(index).map_err(|e| format!("Failed to read {}: {}", archive.display(), e))?;
+        if member.is_dir() {
+            continue;
+        }
+        let Some(path) = member_path(member.name()) else { continue };
+        let Some(expected) = wanted.remove(path.as_str()) else { continue };
+        let unix_mode = member.unix_mode();
+        match restore_file(&mut member, unix_mode, &bundled_path(&bundled, &path), expected) {
+            Ok(()) => {
+                tracing::info!("[installation] Restored {}", path);
+                restored.push(path);
+            }
+            Err(reason) => {
+                tracing::warn!("[installation] Could not restore {}: {}", path, reason);
+                failed.push(DamagedFile { path, reason });
+            }
+        }
+    }
+    failed.extend(wanted.into_keys().map(|path| DamagedFile { path: path.to_string(), reason: "not in the archive".to_string() }));
+    failed.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(RepairReport { restored, failed, report: validate(res_dir, app_version, true)? })
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+/// Check the bundled server, Node runtime and native files against the
+/// resource manifest. `deep` hashes every file rather than just the
+/// critical ones.
+#[tauri::command]
+pub async fn validate_installation(app: AppHandle, deep: Option<bool>) -> Result<InstallationReport, String> {
+    let res_dir = app.path().resource_dir().map_err(|e| format!("No resource directory: {}", e))?;
+    let version = app.package_info().version.to_string();
+    let report = tauri::async_runtime::spawn_blocking(move || validate(&res_dir, &version, deep.unwrap_or(false)))
+        .await
+        .map_err(|e| e.to_string())??;
+    if !report.healthy {
+        tracing::warn!("[installation] {} missing, {} damaged", report.missing.len(), report.corrupt.len());
+    }
+    Ok(report)
+}
+
+/// Restore missing or damaged resources from a portable release zip of
+/// the same version.
+#[tauri::command]
+pub async fn repair_installation(app: AppHandle, webview: tauri::Webview, archive: String) -> Result<RepairReport, String> {
+    require_webview(&webview, Capability::ChangeSettings)?;
+    let res_dir = app.path().resource_dir().map_err(|e| format!("No resource directory: {}", e))?;
+    let version = app.package_info().version.to_string();
+    tauri::async_runtime::spawn_blocking(move || repair(&res_dir, &version, Path::new(&archive)))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str, bytes: &[u8]) -> ManifestFile {
+        ManifestFile { path: path.to_string(), size: bytes.len() as u64, sha256: format!("{:x}", Sha256::digest(bytes)) }
+    }
+
+    #[test]
+    fn reports_missing_and_damaged_files() {
+        let bundled = crate::paths::test_dir("installation");
+        fs::create_dir_all(bundled.join("server/public")).unwrap();
+        fs::write(bundled.join("server/server.js"), b"require('x')").unwrap();
+        fs::write(bundled.join("server/public/app.js"), b"ok").unwrap();
+        let manifest = Manifest {
+            format: MANIFEST_FORMAT,
+            version: "1.0.0".to_string(),
+            files: vec![
+                entry("server/server.js", b"require('next')"),
+                entry("server/public/app.js", b"no"),
+                entry("node/bin/node", b"ELF"),
+            ],
+        };
+
+        // Same size, different bytes: only a deep check hashes app.js
+        let (missing, corrupt) = check_files(&bundled, &manifest, false);
+        assert_eq!(missing, vec!["node/bin/node"]);
+        assert_eq!(corrupt.iter().map(|f| f.path.as_str()).collect::<Vec<_>>(), vec!["server/server.js"]);
+        let (_, corrupt) = check_files(&bundled, &manifest, true);
+        assert_eq!(corrupt.len(), 2);
+        let _ = fs::remove_dir_all(&bundled);
+
+        assert_eq!(member_path("karaoke-successor/bundled/server/server.js").as_deref(), Some("server/server.js"));
+        assert_eq!(member_path("bundled\\node\\node.exe").as_deref(), Some("node/node.exe"));
+        assert_eq!(member_path("other/server.js"), None);
+        assert_eq!(member_path("bundled/../evil"), None);
+    }
+}
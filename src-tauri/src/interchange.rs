@@ -0,0 +1,635 @@
+//! Exchange with other karaoke hosting tools.
+//!
+//! `export_data` writes the song index, the queue or the performance
+//! history as CSV (header row, one record per line) or as a JSON array of
+//! objects with the same keys.
+//!
+//! `import_song_list` reads the song list of another host and adds the
+//! songs it points at to the library:
+//!   - an OpenKJ database (SQLite, table `dbSongs`);
+//!   - a CSV or tab-separated song list with a header row, as Karma and
+//!     most other hosts export it. Columns are recognised by name: artist,
+//!     title, path (or file / location), disc id (or song code) and
+//!     duration.
+//!
+//! Each listed file goes through the scanner like a file of a scanned
+//! folder, so its entry has everything a scan would give it; the list's
+//! artist and title win over file names and tags, and its disc id is kept
+//! as `songCode`. Songs whose file is gone are counted, not imported.
+//!
+//! `read_queue_file` reads a running order back for `--autoplay-queue`:
+//! a queue export (CSV or JSON, singer and `song_id` per entry), any CSV
+//! with a song id column, or plain song ids one per line.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use rusqlite::types::ValueRef;
+use rusqlite::{Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use tauri::{AppHandle, Manager};
+
+use crate::access::{require_webview, Capability};
+use crate::cli::csv_field;
+use crate::db::DbState;
+use crate::library::formats::{self, Candidate};
+use crate::library::scanner::{self, has_extension, AUDIO_EXTENSIONS};
+use crate::paths::{long_path, nfc};
+
+const SQLITE_MAGIC: &[u8] = b"SQLite format 3\0";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportKind {
+    Songs,
+    Queue,
+    History,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+impl ExportKind {
+    /// Query whose column names become the CSV header and JSON keys.
+    fn query(self) -> &'static str {
+        match self {
+            ExportKind::Songs => {
+                "SELECT id, artist, title, album, year, genre, language, format, duration,
+                        folder_path, txt_file_name, audio_file_name, video_file_name,
+                        date_added, last_played, play_count
+                 FROM songs ORDER BY artist COLLATE NOCASE, title COLLATE NOCASE"
+            }
+            ExportKind::Queue => {
+                "SELECT q.id, q.singer, q.song_id, s.artist AS song_artist, s.title AS song_title,
+                        q.state, q.added_at, q.started_at
+                 FROM queue_entries q LEFT JOIN songs s ON s.id = q.song_id
+                 WHERE q.state IN ('current', 'waiting')
+                 ORDER BY q.state = 'waiting', q.position, q.id"
+            }
+            ExportKind::History => {
+                "SELECT id, song_id, song_title, song_artist, singer, started_at, ended_at, score, key_offset
+                 FROM performances ORDER BY started_at"
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportReport {
+    pub path: String,
+    pub records: usize,
+}
+
+/// One song as another host lists it.
+#[derive(Debug, Clone, PartialEq)]
+struct ListedSong {
+    artist: String,
+    title: String,
+    code: Option<String>,
+    duration_ms: Option<i64>,
+    path: PathBuf,
+    plays: i64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SongListImport {
+    /// Songs in the list.
+    pub listed: usize,
+    /// Listed songs new to the library.
+    pub songs_added: usize,
+    /// Listed songs the library had, refreshed from the list.
+    pub songs_updated: usize,
+    /// Listed files that no longer exist.
+    pub missing: usize,
+    /// Listed files that are no song this app can play.
+    pub unsupported: usize,
+    pub errors: Vec<String>,
+}
+
+// ---------------------------------------------------------------------------
+// Export
+// ---------------------------------------------------------------------------
+
+/// Rows of `sql` as column names and JSON values.
+fn query_rows(conn: &Connection, sql: &str) -> Result<(Vec<String>, Vec<Vec<Value>>), String> {
+    let mut stmt = conn.prepare(sql).map_err(|e| format!("Export query failed: {}", e))?;
+    let columns: Vec<String> = stmt.column_names().into_iter().map(str::to_string).collect();
+    let mut rows = stmt.query([]).map_err(|e| format!("Export query failed: {}", e))?;
+    let mut out = Vec::new();
+    while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+        let values = (0..columns.len())
+            .map(|i| match row.get_ref(i) {
+                Ok(ValueRef::Text(t)) => json!(String::from_utf8_lossy(t)),
+                Ok(ValueRef::Integer(n)) => json!(n),
+                Ok(ValueRef::Real(f)) => json!(f),
+                _ => Value::Null,
+            })
+            .collect();
+        out.push(values);
+    }
+    Ok((columns, out))
+}
+
+/// Text that a spreadsheet would run as a formula, made literal with a
+/// leading `'`.
+fn defuse_formula(text: &str) -> std::borrow::Cow<'_, str> {
+    if text.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{}", text).into()
+    } else {
+        text.into()
+    }
+}
+
+fn to_csv(columns: &[String], rows: &[Vec<Value>]) -> String {
+    let mut out = columns.join(",");
+    out.push('\n');
+    for row in rows {
+        let fields: Vec<String> = row
+            .iter()
+            .map(|value| match value {
+                // Titles and singer names are anyone's input
+                Value::String(s) => csv_field(&defuse_formula(s)),
+                Value::Null => String::new(),
+                other => other.to_string(),
+            })
+            .collect();
+        out.push_str(&fields.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+fn to_json(columns: &[String], rows: Vec<Vec<Value>>) -> Result<String, String> {
+    let records: Vec<Map<String, Value>> =
+        rows.into_iter().map(|row| columns.iter().cloned().zip(row).collect()).collect();
+    let mut text = serde_json::to_string_pretty(&records).map_err(|e| e.to_string())?;
+    text.push('\n');
+    Ok(text)
+}
+
+fn export(conn: &Connection, kind: ExportKind, format: ExportFormat, path: &Path) -> Result<ExportReport, String> {
+    let (columns, rows) = query_rows(conn, kind.query())?;
+    let records = rows.len();
+    let text = match format {
+        ExportFormat::Csv => to_csv(&columns, &rows),
+        ExportFormat::Json => to_json(&columns, rows)?,
+    };
+    std::fs::write(path, text).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(ExportReport { path: path.to_string_lossy().to_string(), records })
+}
+
+// ---------------------------------------------------------------------------
+// Reading song lists
+// ---------------------------------------------------------------------------
+
+/// Records of CSV `text` (RFC 4180 quoting) split on `delimiter`.
+fn parse_delimited(text: &str, delimiter: char) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.trim_start_matches('\u{feff}').chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            c if c == delimiter && !quoted => record.push(std::mem::take(&mut field)),
+            '\r' if !quoted => {}
+            '\n' if !quoted => {
+                record.push(std::mem::take(&mut field));
+                if record.iter().any(|f| !f.is_empty()) {
+                    records.push(std::mem::take(&mut record));
+                }
+                record.clear();
+            }
+            c => field.push(c),
+        }
+    }
+    record.push(field);
+    if record.iter().any(|f| !f.is_empty()) {
+        records.push(record);
+    }
+    records
+}
+
+/// Column header as a bare lower-case word: `Disc ID` → `discid`.
+fn header_key(name: &str) -> String {
+    name.chars().filter(|c| c.is_alphanumeric()).flat_map(char::to_lowercase).collect()
+}
+
+/// Index of the first column named like one of `aliases`.
+fn column(headers: &[String], aliases: &[&str]) -> Option<usize> {
+    let keys: Vec<String> = headers.iter().map(|h| header_key(h)).collect();
+    aliases.iter().find_map(|alias| keys.iter().position(|k| k == alias))
+}
+
+/// Milliseconds from `m:ss`, `h:mm:ss`, seconds, or milliseconds (values
+/// of ten hours and up are taken as milliseconds).
+fn parse_duration(value: &str) -> Option<i64> {
+    let value = value.trim();
+    if value.contains(':') {
+        let mut seconds = 0.0;
+        for part in value.split(':') {
+            seconds = seconds * 60.0 + part.trim().parse::<f64>().ok()?;
+        }
+        return Some((seconds * 1000.0) as i64);
+    }
+    let n: f64 = value.parse().ok()?;
+    Some(if n >= 36_000.0 && !value.contains('.') { n as i64 } else { (n * 1000.0) as i64 })
+}
+
+/// Delimiter of a CSV, semicolon- or tab-separated list, from its first line.
+fn detect_delimiter(text: &str) -> char {
+    let first_line = text.lines().next().unwrap_or_default();
+    if first_line.contains('\t') && !first_line.contains(',') {
+        '\t'
+    } else if first_line.matches(';').count() > first_line.matches(',').count() {
+        ';'
+    } else {
+        ','
+    }
+}
+
+/// Songs of a CSV or tab-separated list.
+fn read_delimited(text: &str) -> Result<Vec<ListedSong>, String> {
+    let delimiter = detect_delimiter(text);
+    let mut records = parse_delimited(text, delimiter).into_iter();
+    let headers = records.next().ok_or("The song list is empty")?;
+    let path = column(&headers, &["path", "filepath", "file", "filename", "location"])
+        .ok_or("The song list has no file path column; only lists pointing at song files can be imported")?;
+    let artist = column(&headers, &["artist", "singer"]);
+    let title = column(&headers, &["title", "song", "songtitle"]);
+    let code = column(&headers, &["discid", "songcode", "code", "songid", "id"]);
+    let duration = column(&headers, &["duration", "length", "time"]);
+    let plays = column(&headers, &["plays", "playcount"]);
+
+    let field = |record: &[String], index: Option<usize>| {
+        index.and_then(|i| record.get(i)).map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+    };
+    Ok(records
+        .filter_map(|record| {
+            Some(ListedSong {
+                path: PathBuf::from(field(&record, Some(path))?),
+                artist: field(&record, artist).unwrap_or_default(),
+                title: field(&record, title).unwrap_or_default(),
+                code: field(&record, code),
+                duration_ms: field(&record, duration).and_then(|d| parse_duration(&d)),
+                plays: field(&record, plays).and_then(|p| p.parse().ok()).unwrap_or(0),
+            })
+        })
+        .collect())
+}
+
+/// Songs of an OpenKJ database.
+fn read_openkj(path: &Path) -> Result<Vec<ListedSong>, String> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let (columns, rows) = query_rows(&conn, "SELECT * FROM dbSongs")
+        .map_err(|_| format!("{} is not an OpenKJ database (no dbSongs table)", path.display()))?;
+    let path_col = column(&columns, &["path"]).ok_or("The dbSongs table has no path column")?;
+    let (artist, title) = (column(&columns, &["artist"]), column(&columns, &["title"]));
+    let (code, duration, plays) = (column(&columns, &["discid"]), column(&columns, &["duration"]), column(&columns, &["plays"]));
+
+    let text = |row: &[Value], index: Option<usize>| match index.map(|i| &row[i]) {
+        Some(Value::String(s)) if !s.trim().is_empty() => Some(s.trim().to_string()),
+        _ => None,
+    };
+    let number = |row: &[Value], index: Option<usize>| index.and_then(|i| row[i].as_i64());
+    Ok(rows
+        .iter()
+        .filter_map(|row| {
+            Some(ListedSong {
+                path: PathBuf::from(text(row, Some(path_col))?),
+                artist: text(row, artist).unwrap_or_default(),
+                title: text(row, title).unwrap_or_default(),
+                code: text(row, code),
+                // OpenKJ stores milliseconds
+                duration_ms: number(row, duration).filter(|&d| d > 0),
+                plays: number(row, plays).unwrap_or(0),
+            })
+        })
+        .collect())
+}
+
+fn read_song_list(path: &Path) -> Result<Vec<ListedSong>, String> {
+    let bytes = std::fs::read(long_path(path)).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    if bytes.starts_with(SQLITE_MAGIC) {
+        return read_openkj(path);
+    }
+    read_delimited(&String::from_utf8_lossy(&bytes))
+}
+
+// ---------------------------------------------------------------------------
+// Reading queue files
+// ---------------------------------------------------------------------------
+
+/// Singer of entries whose file names none.
+const DEFAULT_SINGER: &str = "Guest";
+
+/// `(singer, song id)` entries of a queue file's `text`.
+fn parse_queue(text: &str) -> Result<Vec<(String, String)>, String> {
+    let text = text.trim_start_matches('\u{feff}').trim();
+    let entry = |singer: Option<&str>, song_id: &str| {
+        let singer = singer.map(str::trim).filter(|s| !s.is_empty()).unwrap_or(DEFAULT_SINGER);
+        (singer.to_string(), song_id.trim().to_string())
+    };
+    if text.starts_with('[') {
+        let items: Vec<Value> = serde_json::from_str(text).map_err(|e| format!("Invalid queue JSON: {}", e))?;
+        return Ok(items
+            .iter()
+            .filter_map(|item| match item {
+                Value::String(song_id) => Some(entry(None, song_id)),
+                Value::Object(fields) => {
+                    let song_id = fields.get("song_id").or_else(|| fields.get("songId"))?.as_str()?;
+                    Some(entry(fields.get("singer").and_then(Value::as_str), song_id))
+                }
+                _ => None,
+            })
+            .filter(|(_, song_id)| !song_id.is_empty())
+            .collect());
+    }
+
+    let records = parse_delimited(text, detect_delimiter(text));
+    let headers = records.first().ok_or("The queue file is empty")?;
+    let entries: Vec<(String, String)> = match column(headers, &["songid", "song"]) {
+        Some(song) => {
+            let singer = column(headers, &["singer", "name", "player"]);
+            records[1..]
+                .iter()
+                .filter_map(|record| Some(entry(singer.and_then(|i| record.get(i)).map(String::as_str), record.get(song)?)))
+                .collect()
+        }
+        // No header: one song id per line
+        None => records.iter().map(|record| entry(None, &record[0])).collect(),
+    };
+    Ok(entries.into_iter().filter(|(_, song_id)| !song_id.is_empty()).collect())
+}
+
+/// `(singer, song id)` entries of the queue file at `path`, in its order.
+pub fn read_queue_file(path: &Path) -> Result<Vec<(String, String)>, String> {
+    let text = std::fs::read_to_string(long_path(path)).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let entries = parse_queue(&text)?;
+    if entries.is_empty() {
+        return Err(format!("{} lists no songs", path.display()));
+    }
+    Ok(entries)
+}
+
+// ---------------------------------------------------------------------------
+// Mapping into the library
+// ---------------------------------------------------------------------------
+
+/// Library entries for one listed file; `files` are the files of its
+/// directory with the same stem, for pairing a `.cdg` with its audio.
+fn songs_for(listed: &ListedSong, files: &[PathBuf]) -> Result<Option<Vec<Value>>, String> {
+    let candidates = formats::classify_dir(files);
+    let candidate = candidates.iter().find(|c| match c {
+        Candidate::Cdg { cdg, audio } => *cdg == listed.path || *audio == listed.path,
+        other => other.path() == listed.path,
+    });
+    let mut songs = match candidate {
+        Some(candidate) => scanner::song_from_candidate(candidate)?,
+        None if has_extension(&listed.path, AUDIO_EXTENSIONS) => vec![scanner::song_from_audio(&listed.path)],
+        None => return Ok(None),
+    };
+    // Names in a pack's list are the pack's, not the tracks'
+    if songs.len() == 1 {
+        let song = &mut songs[0];
+        if !listed.artist.is_empty() {
+            song["artist"] = json!(nfc(&listed.artist));
+        }
+        if !listed.title.is_empty() {
+            song["title"] = json!(nfc(&listed.title));
+        }
+        if let Some(code) = &listed.code {
+            song["songCode"] = json!(code);
+        }
+        if let (Some(ms), None) = (listed.duration_ms, song.get("duration")) {
+            song["duration"] = json!(ms);
+        }
+        song["playCount"] = json!(listed.plays.max(0));
+    }
+    Ok(Some(songs))
+}
+
+/// The file of `files` a list names as `path`: that very path, else one
+/// whose name differs only in case.
+fn listed_file<'a>(files: &'a [PathBuf], path: &Path) -> Option<&'a PathBuf> {
+    let name = |p: &Path| p.file_name().map(|n| n.to_string_lossy().to_lowercase());
+    files.iter().find(|f| f.as_path() == path).or_else(|| files.iter().find(|f| name(f) == name(path)))
+}
+
+/// How many of `songs` the library already has.
+fn known_songs(conn: &Connection, songs: &[Value]) -> Result<usize, String> {
+    let mut stmt = conn
+        .prepare_cached("SELECT EXISTS(SELECT 1 FROM songs WHERE id = ?1)")
+        .map_err(|e| format!("Song lookup failed: {}", e))?;
+    let mut known = 0;
+    for id in songs.iter().filter_map(|song| song.get("id").and_then(Value::as_str)) {
+        if stmt.query_row([id], |row| row.get::<_, bool>(0)).map_err(|e| format!("Song lookup failed: {}", e))? {
+            known += 1;
+        }
+    }
+    Ok(known)
+}
+
+fn import(app: &AppHandle, path: &Path) -> Result<SongListImport, String> {
+    let started = crate::scheduler::now_ms();
+    let listed = read_song_list(path)?;
+    let mut report = SongListImport { listed: listed.len(), ..SongListImport::default() };
+
+    // Each directory is listed once, however many songs it holds
+    let mut by_dir: HashMap<PathBuf, Vec<&ListedSong>> = HashMap::new();
+    for song in &listed {
+        by_dir.entry(song.path.parent().unwrap_or(Path::new("")).to_path_buf()).or_default().push(song);
+    }
+    let mut songs = Vec::new();
+    for (dir, listed) in by_dir {
+        let mut by_stem: HashMap<String, Vec<PathBuf>> = HashMap::new();
+        for entry in std::fs::read_dir(long_path(&dir)).into_iter().flatten().flatten() {
+            let file = dir.join(entry.file_name());
+            let stem = file.file_stem().map(|s| s.to_string_lossy().to_ascii_lowercase()).unwrap_or_default();
+            by_stem.entry(stem).or_default().push(file);
+        }
+        for song in listed {
+            let stem = song.path.file_stem().map(|s| s.to_string_lossy().to_ascii_lowercase()).unwrap_or_default();
+            let files = by_stem.get(&stem).map(Vec::as_slice).unwrap_or_default();
+            // Lists often come from Windows hosts, which ignore case
+            let Some(file) = listed_file(files, &song.path) else {
+                report.missing += 1;
+                continue;
+            };
+            let song = ListedSong { path: file.clone(), ..song.clone() };
+            match songs_for(&song, files) {
+                Ok(Some(entries)) => songs.extend(entries),
+                Ok(None) => report.unsupported += 1,
+                Err(e) => report.errors.push(e),
+            }
+        }
+    }
+
+    let db = app.state::<DbState>();
+    {
+        let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+        let known = known_songs(&conn, &songs)?;
+        let saved = scanner::save_songs(&mut conn, &songs)?;
+        report.songs_updated = known.min(saved);
+        report.songs_added = saved - report.songs_updated;
+    }
+    tracing::info!(
+        "[interchange] {}: {} of {} listed songs added, {} updated ({} missing, {} unsupported)",
+        path.display(),
+        report.songs_added,
+        report.listed,
+        report.songs_updated,
+        report.missing,
+        report.unsupported
+    );
+    if report.songs_added > 0 {
+        crate::media::transcode::queue_new_songs(app, started);
+    }
+    Ok(report)
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+/// Write the song index, queue or history to `path`.
+#[tauri::command]
+pub async fn export_data(
+    app: AppHandle,
+    webview: tauri::Webview,
+    kind: ExportKind,
+    format: ExportFormat,
+    path: String,
+) -> Result<ExportReport, String> {
+    require_webview(&webview, Capability::ManageLibrary)?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let db = app.state::<DbState>();
+        let conn = db.conn.lock().map_err(|e| e.to_string())?;
+        export(&conn, kind, format, Path::new(&path))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Add the songs of another host's song list (OpenKJ database, or a CSV
+/// list with file paths) to the library.
+#[tauri::command]
+pub async fn import_song_list(app: AppHandle, webview: tauri::Webview, path: String) -> Result<SongListImport, String> {
+    require_webview(&webview, Capability::ManageLibrary)?;
+    tauri::async_runtime::spawn_blocking(move || import(&app, Path::new(&path)))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_song_lists_of_other_hosts() {
+        let list = "\u{feff}Disc ID;Artist;Title;Length;File Path\r\n\
+                    SC8123-05;Queen;\"Radio \"\"Ga Ga\"\"\";5:48;C:\\Karaoke\\SC8123-05.zip\r\n\
+                    ;;No Path;;\r\n";
+        let songs = read_delimited(list).unwrap();
+        assert_eq!(
+            songs,
+            vec![ListedSong {
+                artist: "Queen".to_string(),
+                title: "Radio \"Ga Ga\"".to_string(),
+                code: Some("SC8123-05".to_string()),
+                duration_ms: Some(348_000),
+                path: PathBuf::from("C:\\Karaoke\\SC8123-05.zip"),
+                plays: 0,
+            }]
+        );
+        assert!(read_delimited("Artist,Title\nQueen,Radio Ga Ga\n").is_err());
+        assert_eq!(parse_delimited("a,\"b,\nc\"\n\nd", ','), vec![vec!["a", "b,\nc"], vec!["d"]]);
+        assert_eq!((parse_duration("215"), parse_duration("215000"), parse_duration("1:02:03")), (Some(215_000), Some(215_000), Some(3_723_000)));
+
+        let dir = crate::paths::test_dir("interchange");
+        std::fs::create_dir_all(&dir).unwrap();
+        let db_path = dir.join("openkj.sqlite");
+        let conn = Connection::open(&db_path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE dbSongs (songid INTEGER PRIMARY KEY, Artist, Title, DiscId, 'Duration' INTEGER,
+                                   path VARCHAR(700) NOT NULL UNIQUE, filename, plays INT DEFAULT(0));
+             INSERT INTO dbSongs (Artist, Title, DiscId, Duration, path, plays)
+             VALUES ('ABBA', 'Waterloo', 'SF001-01', 165000, '/k/SF001-01.cdg', 7);",
+        )
+        .unwrap();
+        drop(conn);
+        let songs = read_song_list(&db_path).unwrap();
+        assert_eq!((songs[0].title.as_str(), songs[0].duration_ms, songs[0].plays), ("Waterloo", Some(165_000), 7));
+
+        // A listed .cdg pairs with its audio; the list names the song
+        let files = vec![PathBuf::from("/k/SF001-01.cdg"), PathBuf::from("/k/SF001-01.mp3")];
+        let entries = songs_for(&songs[0], &files).unwrap().unwrap();
+        assert_eq!(listed_file(&files, Path::new("/k/sf001-01.CDG")), Some(&files[0]));
+        assert_eq!(listed_file(&files, Path::new("/k/SF001-02.cdg")), None);
+        assert_eq!((entries[0]["artist"].as_str(), entries[0]["songCode"].as_str()), (Some("ABBA"), Some("SF001-01")));
+        assert_eq!(entries[0]["format"], "cdg");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn reads_queue_files() {
+        let export = "id,singer,song_id,song_artist,song_title,state,added_at,started_at\n\
+                      4,\"Sam, Jo\",a1,Queen,Radio Ga Ga,current,1000,2000\n\
+                      5,,b2,ABBA,Waterloo,waiting,1001,\n";
+        let expected = vec![("Sam, Jo".to_string(), "a1".to_string()), ("Guest".to_string(), "b2".to_string())];
+        assert_eq!(parse_queue(export).unwrap(), expected);
+        assert_eq!(parse_queue(r#"[{"singer":"Sam, Jo","song_id":"a1"},{"songId":"b2"},{"singer":"x"}]"#).unwrap(), expected);
+        assert_eq!(parse_queue("a1\r\nb2\n").unwrap().len(), 2);
+        assert!(parse_queue("").is_err());
+    }
+
+    #[test]
+    fn exports_rows_as_csv_and_json() {
+        let conn = crate::db::test_conn();
+        conn.execute(
+            "INSERT INTO performances (song_id, song_title, song_artist, singer, started_at, score)
+             VALUES ('a', 'Radio Ga Ga', 'Queen', 'Sam, Jo', 1000, 87.5)",
+            [],
+        )
+        .unwrap();
+        let (columns, rows) = query_rows(&conn, ExportKind::History.query()).unwrap();
+        let csv = to_csv(&columns, &rows);
+        assert_eq!(
+            csv.lines().collect::<Vec<_>>(),
+            vec![
+                "id,song_id,song_title,song_artist,singer,started_at,ended_at,score,key_offset",
+                "1,a,Radio Ga Ga,Queen,\"Sam, Jo\",1000,,87.5,0",
+            ]
+        );
+        let json: Value = serde_json::from_str(&to_json(&columns, rows).unwrap()).unwrap();
+        assert_eq!(json[0]["singer"], "Sam, Jo");
+        assert_eq!(json[0]["ended_at"], Value::Null);
+        // Names a spreadsheet would run stay text
+        assert_eq!(defuse_formula("=HYPERLINK(\"x\")"), "'=HYPERLINK(\"x\")");
+        assert_eq!(defuse_formula("@me"), "'@me");
+        assert_eq!(defuse_formula("-1"), "'-1");
+        assert_eq!(defuse_formula("Radio Ga Ga"), "Radio Ga Ga");
+        // The other exports run against the current schema
+        assert!(query_rows(&conn, ExportKind::Songs.query()).is_ok());
+        assert!(query_rows(&conn, ExportKind::Queue.query()).is_ok());
+    }
+}
@@ -0,0 +1,636 @@
+//! Background analysis jobs: one queue for the CPU-heavy work that runs
+//! behind the UI.
+//!
+//! Loudness measurement (`audio::loudness`), duplicate fingerprinting
+//! (`audio::fingerprint`), waveforms (`audio::waveform`) and video
+//! thumbnails (`media::thumbnails`) add jobs here instead of each starting
+//! its own task. The queue (the `jobs`
+//! table) is worked by a small pool:
+//!   - `background_workers` supervised workers take the highest-priority,
+//!     then oldest, queued job; one more takes only `High` jobs, so a
+//!     thumbnail the library view waits for never queues behind a run over
+//!     the whole library;
+//!   - while a song plays, only that last worker starts jobs, and a
+//!     running job below `High` pauses at its next `JobContext::checkpoint`
+//!     (status `waiting`) until playback stops, so analysis never competes
+//!     with the audio thread for more than one core;
+//!   - pending jobs survive a restart: a job interrupted by shutdown is
+//!     queued again and starts over. Thumbnail grabs and waveforms are
+//!     dropped instead, whoever asked for them is gone.
+//!
+//! Changes are published as `jobs://changed` (a finished job is removed
+//! from the table and announced one last time as completed, failed or
+//! cancelled) and progress as `jobs://progress`. Each job kind keeps its own
+//! events as well; `generate_waveform` waits for its job's last
+//! `jobs://changed` and answers from the analysis cache the job filled.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::Notify;
+use tokio_util::sync::CancellationToken;
+
+use crate::access::{require_webview, Capability};
+use crate::audio::commands::AudioState;
+use crate::db::DbState;
+use crate::events::{publish, AppEvent, EventBus};
+use crate::media::thumbnails::ThumbnailJob;
+use crate::runtime::{sleep_or_cancel, TaskSupervisor};
+use crate::scheduler::now_ms;
+
+pub const CHANGED_EVENT: &str = "jobs://changed";
+pub const PROGRESS_EVENT: &str = "jobs://progress";
+
+const MAX_BACKGROUND_WORKERS: usize = 4;
+/// How often idle workers and waiting jobs look at playback again.
+const PLAYBACK_POLL: Duration = Duration::from_secs(2);
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    /// Library-wide upkeep nobody is waiting for.
+    Low,
+    /// Started by the user, who expects a result eventually.
+    Normal,
+    /// Someone is looking at the screen waiting; runs during playback.
+    High,
+}
+
+impl Priority {
+    fn as_i64(self) -> i64 {
+        match self {
+            Self::Low => 0,
+            Self::Normal => 1,
+            Self::High => 2,
+        }
+    }
+
+    fn from_i64(value: i64) -> Self {
+        match value {
+            i64::MIN..=0 => Self::Low,
+            1 => Self::Normal,
+            _ => Self::High,
+        }
+    }
+}
+
+/// What a job does, stored as JSON in `jobs.spec`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum JobSpec {
+    /// Measure songs without a loudness (all of them with `force`).
+    Loudness { force: bool },
+    /// Fingerprint the library and report duplicates.
+    Duplicates,
+    /// Grab one video frame into the thumbnail cache.
+    Thumbnail(ThumbnailJob),
+    /// Draw the waveform of `path` into the analysis cache.
+    Waveform { path: String, resolution: usize },
+}
+
+impl JobSpec {
+    /// `jobs.kind`, for finding a job of a kind.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Loudness { .. } => "loudness",
+            Self::Duplicates => "duplicates",
+            Self::Thumbnail(_) => "thumbnail",
+            Self::Waveform { .. } => "waveform",
+        }
+    }
+
+    pub fn priority(&self) -> Priority {
+        match self {
+            Self::Loudness { .. } => Priority::Low,
+            Self::Duplicates => Priority::Normal,
+            Self::Thumbnail(_) | Self::Waveform { .. } => Priority::High,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    /// Started, paused until playback stops.
+    Waiting,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Queued => "queued",
+            Self::Running => "running",
+            Self::Waiting => "waiting",
+            Self::Completed => "completed",
+            Self::Failed => "failed",
+            Self::Cancelled => "cancelled",
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        match value {
+            "running" => Self::Running,
+            "waiting" => Self::Waiting,
+            _ => Self::Queued,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Job {
+    pub id: i64,
+    pub spec: JobSpec,
+    pub priority: Priority,
+    pub status: JobStatus,
+    pub done: u64,
+    /// `None` until the job knows how much there is to do.
+    pub total: Option<u64>,
+    /// Why a job failed; only in its last `jobs://changed`.
+    pub error: Option<String>,
+    /// Epoch ms.
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+/// Payload of `jobs://progress`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobProgress {
+    pub id: i64,
+    pub done: u64,
+    pub total: u64,
+}
+
+/// Managed state: wakes idle workers and lets `cancel_job` stop a running
+/// job.
+#[derive(Default)]
+pub struct JobQueue {
+    wake: Notify,
+    active: Mutex<HashMap<i64, CancellationToken>>,
+}
+
+impl JobQueue {
+    /// Wake one idle worker. If none is waiting right now the wake is kept
+    /// for the next one that does, so a job queued while every worker is
+    /// between claims still starts at once.
+    fn wake(&self) {
+        self.wake.notify_one();
+    }
+
+    fn set_active(&self, id: i64, token: Option<CancellationToken>) {
+        let Ok(mut active) = self.active.lock() else { return };
+        match token {
+            Some(token) => active.insert(id, token),
+            None => active.remove(&id),
+        };
+    }
+
+    fn stop_if_active(&self, id: i64) {
+        if let Some(token) = self.active.lock().ok().and_then(|active| active.get(&id).cloned()) {
+            token.cancel();
+        }
+    }
+}
+
+/// What a running job is handed: its cancellation and progress reporting.
+pub struct JobContext {
+    app: AppHandle,
+    id: i64,
+    priority: Priority,
+    token: CancellationToken,
+}
+
+impl JobContext {
+    pub fn app(&self) -> &AppHandle {
+        &self.app
+    }
+
+    /// Cancelled from `cancel_job`, or shutting down.
+    pub fn is_cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    /// Call between units of work: waits while a song plays (unless the
+    /// job is `High`). Returns `false` once the job should stop.
+    pub async fn checkpoint(&self) -> bool {
+        if self.token.is_cancelled() {
+            return false;
+        }
+        if self.priority >= Priority::High || !playing(&self.app) {
+            return true;
+        }
+        self.set_status(JobStatus::Waiting);
+        while playing(&self.app) {
+            if !sleep_or_cancel(&self.token, PLAYBACK_POLL).await {
+                return false;
+            }
+        }
+        self.set_status(JobStatus::Running);
+        true
+    }
+
+    /// `done` of `total` units finished.
+    pub fn progress(&self, done: usize, total: usize) {
+        let (done, total) = (done as u64, total as u64);
+        if let Err(e) = with_conn(&self.app, |conn| set_progress(conn, self.id, done, total)) {
+            tracing::warn!("[jobs] {}", e);
+        }
+        publish(&self.app, AppEvent::JobProgress(JobProgress { id: self.id, done, total }));
+    }
+
+    fn set_status(&self, status: JobStatus) {
+        let from = [JobStatus::Running, JobStatus::Waiting];
+        if let Ok(true) = with_conn(&self.app, |conn| transition(conn, self.id, &from, status)) {
+            announce(&self.app, self.id);
+        }
+    }
+}
+
+fn playing(app: &AppHandle) -> bool {
+    app.try_state::<AudioState>().is_some_and(|audio| audio.is_playing())
+}
+
+// ---------------------------------------------------------------------------
+// Queue
+// ---------------------------------------------------------------------------
+
+const COLUMNS: &str = "id, spec, priority, status, done, total, created_at, updated_at";
+
+fn job_from_row(row: &rusqlite::Row) -> rusqlite::Result<Job> {
+    let spec: String = row.get(1)?;
+    let spec = serde_json::from_str(&spec)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(1, rusqlite::types::Type::Text, Box::new(e)))?;
+    Ok(Job {
+        id: row.get(0)?,
+        spec,
+        priority: Priority::from_i64(row.get(2)?),
+        status: JobStatus::parse(&row.get::<_, String>(3)?),
+        done: row.get::<_, i64>(4)?.max(0) as u64,
+        total: row.get::<_, Option<i64>>(5)?.map(|n| n.max(0) as u64),
+        error: None,
+        created_at: row.get(6)?,
+        updated_at: row.get(7)?,
+    })
+}
+
+/// Pending and running jobs, in the order they run.
+pub fn list(conn: &Connection) -> Result<Vec<Job>, String> {
+    let mut stmt = conn
+        .prepare(&format!("SELECT {} FROM jobs ORDER BY priority DESC, id", COLUMNS))
+        .map_err(|e| format!("Failed to read jobs: {}", e))?;
+    let rows = stmt.query_map([], job_from_row).map_err(|e| format!("Failed to read jobs: {}", e))?;
+    rows.collect::<Result<_, _>>().map_err(|e| format!("Failed to read jobs: {}", e))
+}
+
+fn get(conn: &Connection, id: i64) -> Result<Option<Job>, String> {
+    conn.query_row(&format!("SELECT {} FROM jobs WHERE id = ?1", COLUMNS), [id], job_from_row)
+        .optional()
+        .map_err(|e| format!("Failed to read job: {}", e))
+}
+
+/// Whether a job of `kind` is queued or running.
+pub fn has_pending(conn: &Connection, kind: &str) -> Result<bool, String> {
+    conn.query_row("SELECT EXISTS(SELECT 1 FROM jobs WHERE kind = ?1)", [kind], |row| row.get(0))
+        .map_err(|e| format!("Failed to read jobs: {}", e))
+}
+
+fn add(conn: &Connection, spec: &JobSpec) -> Result<Job, String> {
+    let json = serde_json::to_string(spec).map_err(|e| format!("Failed to queue job: {}", e))?;
+    let now = now_ms();
+    conn.execute(
+        "INSERT INTO jobs (kind, spec, priority, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?4)",
+        params![spec.kind(), json, spec.priority().as_i64(), now],
+    )
+    .map_err(|e| format!("Failed to queue job: {}", e))?;
+    get(conn, conn.last_insert_rowid())?.ok_or_else(|| "Failed to queue job".to_string())
+}
+
+/// The next queued job of at least `min` priority, marked running.
+fn claim_next(conn: &Connection, min: Priority) -> Result<Option<Job>, String> {
+    let next = conn
+        .query_row(
+            &format!(
+                "SELECT {} FROM jobs WHERE status = 'queued' AND priority >= ?1 ORDER BY priority DESC, id LIMIT 1",
+                COLUMNS
+            ),
+            [min.as_i64()],
+            job_from_row,
+        )
+        .optional()
+        .map_err(|e| format!("Failed to read jobs: {}", e))?;
+    let Some(mut job) = next else { return Ok(None) };
+    transition(conn, job.id, &[JobStatus::Queued], JobStatus::Running)?;
+    job.status = JobStatus::Running;
+    Ok(Some(job))
+}
+
+/// Move a job to `status`; only from one of `from`. Returns whether it
+/// moved.
+fn transition(conn: &Connection, id: i64, from: &[JobStatus], status: JobStatus) -> Result<bool, String> {
+    let from: Vec<String> = from.iter().map(|s| format!("'{}'", s.as_str())).collect();
+    conn.execute(
+        &format!("UPDATE jobs SET status = ?1, updated_at = ?2 WHERE id = ?3 AND status IN ({})", from.join(", ")),
+        params![status.as_str(), now_ms(), id],
+    )
+    .map(|n| n > 0)
+    .map_err(|e| format!("Failed to update job: {}", e))
+}
+
+fn set_progress(conn: &Connection, id: i64, done: u64, total: u64) -> Result<(), String> {
+    conn.execute(
+        "UPDATE jobs SET done = ?1, total = ?2, updated_at = ?3 WHERE id = ?4",
+        params![done as i64, total as i64, now_ms(), id],
+    )
+    .map(|_| ())
+    .map_err(|e| format!("Failed to update job: {}", e))
+}
+
+/// Take a job off the queue; `None` if it was not there.
+fn remove(conn: &Connection, id: i64) -> Result<Option<Job>, String> {
+    let job = get(conn, id)?;
+    if job.is_some() {
+        conn.execute("DELETE FROM jobs WHERE id = ?1", [id]).map_err(|e| format!("Failed to remove job: {}", e))?;
+    }
+    Ok(job)
+}
+
+/// Requeue jobs a shutdown interrupted and drop the ones someone waited
+/// for. Returns how many are resumed.
+fn recover(conn: &Connection) -> Result<usize, String> {
+    conn.execute("DELETE FROM jobs WHERE kind IN ('thumbnail', 'waveform')", [])
+        .map_err(|e| format!("Failed to recover jobs: {}", e))?;
+    conn.execute("UPDATE jobs SET status = 'queued' WHERE status IN ('running', 'waiting')", [])
+        .map_err(|e| format!("Failed to recover jobs: {}", e))
+}
+
+/// Run `f` with the database locked.
+fn with_conn<T>(app: &AppHandle, f: impl FnOnce(&Connection) -> Result<T, String>) -> Result<T, String> {
+    let db = app.state::<DbState>();
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    f(&conn)
+}
+
+fn announce(app: &AppHandle, id: i64) {
+    match with_conn(app, |conn| get(conn, id)) {
+        Ok(Some(job)) => publish(app, AppEvent::JobChanged(job)),
+        Ok(None) => {}
+        Err(e) => tracing::warn!("[jobs] {}", e),
+    }
+}
+
+/// Queue `spec` and wake a worker.
+pub fn enqueue(app: &AppHandle, spec: JobSpec) -> Result<Job, String> {
+    let job = with_conn(app, |conn| add(conn, &spec))?;
+    tracing::debug!("[jobs] Queued {} job {}", spec.kind(), job.id);
+    publish(app, AppEvent::JobChanged(job.clone()));
+    if let Some(queue) = app.try_state::<JobQueue>() {
+        queue.wake();
+    }
+    Ok(job)
+}
+
+// ---------------------------------------------------------------------------
+// Workers
+// ---------------------------------------------------------------------------
+
+/// Half the cores, within 1–`MAX_BACKGROUND_WORKERS`: the rest stays with
+/// the UI and the audio thread.
+fn background_workers() -> usize {
+    let cores = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(2);
+    (cores / 2).clamp(1, MAX_BACKGROUND_WORKERS)
+}
+
+async fn execute(ctx: &JobContext, spec: JobSpec) -> Result<(), String> {
+    match spec {
+        JobSpec::Loudness { force } => crate::audio::loudness::run_job(ctx, force).await,
+        JobSpec::Duplicates => crate::audio::fingerprint::run_job(ctx).await,
+        JobSpec::Thumbnail(thumbnail) => {
+            let app = ctx.app.clone();
+            tauri::async_runtime::spawn_blocking(move || crate::media::thumbnails::run_job(&app, thumbnail))
+                .await
+                .map_err(|e| e.to_string())?
+        }
+        JobSpec::Waveform { path, resolution } => {
+            let app = ctx.app.clone();
+            tauri::async_runtime::spawn_blocking(move || crate::audio::waveform::run_job(&app, &path, resolution))
+                .await
+                .map_err(|e| e.to_string())?
+        }
+    }
+}
+
+/// Lowest priority a worker claims; `None` while it must stay idle. Only
+/// the dedicated lane works during playback.
+fn lane_minimum(dedicated: bool, playing: bool) -> Option<Priority> {
+    match (dedicated, playing) {
+        (true, _) => Some(Priority::High),
+        (false, true) => None,
+        (false, false) => Some(Priority::Low),
+    }
+}
+
+async fn run(app: &AppHandle, mut job: Job, worker: &CancellationToken) {
+    let queue = app.state::<JobQueue>();
+    let token = worker.child_token();
+    queue.set_active(job.id, Some(token.clone()));
+    publish(app, AppEvent::JobChanged(job.clone()));
+
+    let ctx = JobContext { app: app.clone(), id: job.id, priority: job.priority, token: token.clone() };
+    let result = if ctx.checkpoint().await { execute(&ctx, job.spec.clone()).await } else { Ok(()) };
+    queue.set_active(job.id, None);
+    // Shutting down: the job stays running and is queued again on start
+    if worker.is_cancelled() {
+        return;
+    }
+
+    // Gone already when `cancel_job` took it off the queue
+    match with_conn(app, |conn| remove(conn, job.id)) {
+        Ok(Some(last)) => {
+            job = Job { status: JobStatus::Completed, ..last };
+            if let Err(e) = result {
+                tracing::warn!("[jobs] {} job {} failed: {}", job.spec.kind(), job.id, e);
+                job.status = JobStatus::Failed;
+                job.error = Some(e);
+            }
+            publish(app, AppEvent::JobChanged(job));
+        }
+        Ok(None) => {}
+        Err(e) => tracing::error!("[jobs] {}", e),
+    }
+}
+
+/// Queue `spec` and wait until it leaves the queue: `Err` if it failed or
+/// was cancelled. A job whose end was missed (the subscriber lagged) counts
+/// as done; the caller checks what it produced.
+pub async fn run_to_end(app: &AppHandle, spec: JobSpec) -> Result<(), String> {
+    let mut events = app.state::<EventBus>().subscribe();
+    let id = enqueue(app, spec)?.id;
+    loop {
+        match events.recv().await {
+            Ok(envelope) => match &envelope.event {
+                AppEvent::JobChanged(job) if job.id == id => match job.status {
+                    JobStatus::Completed => return Ok(()),
+                    JobStatus::Failed => return Err(job.error.clone().unwrap_or_else(|| "Job failed".to_string())),
+                    JobStatus::Cancelled => return Err("Cancelled".to_string()),
+                    _ => {}
+                },
+                _ => {}
+            },
+            Err(RecvError::Lagged(_)) => {
+                if with_conn(app, |conn| get(conn, id))?.is_none() {
+                    return Ok(());
+                }
+            }
+            Err(RecvError::Closed) => return Err("Shutting down".to_string()),
+        }
+    }
+}
+
+/// Requeue interrupted jobs and start the worker pool.
+pub fn spawn_workers(app: AppHandle) {
+    match with_conn(&app, recover) {
+        Ok(0) => {}
+        Ok(n) => tracing::info!("[jobs] Resuming {} interrupted job(s)", n),
+        Err(e) => tracing::error!("[jobs] {}", e),
+    }
+
+    let workers = background_workers();
+    let supervisor = app.state::<TaskSupervisor>();
+    // The last worker only takes high-priority jobs
+    for lane in 0..=workers {
+        let worker_app = app.clone();
+        supervisor.spawn("jobs", move |token| async move {
+            let app = worker_app;
+            let queue = app.state::<JobQueue>();
+            loop {
+                let claimed = match lane_minimum(lane == workers, playing(&app)) {
+                    Some(min) => with_conn(&app, |conn| claim_next(conn, min)),
+                    None => Ok(None),
+                };
+                match claimed {
+                    Ok(Some(job)) => run(&app, job, &token).await,
+                    Ok(None) => {
+                        tokio::select! {
+                            _ = token.cancelled() => return,
+                            _ = queue.wake.notified() => {}
+                            _ = tokio::time::sleep(PLAYBACK_POLL) => {}
+                        }
+                    }
+                    Err(e) => {
+                        tracing::error!("[jobs] {}", e);
+                        if !sleep_or_cancel(&token, RETRY_DELAY).await {
+                            return;
+                        }
+                    }
+                }
+                if token.is_cancelled() {
+                    return;
+                }
+            }
+        });
+    }
+    tracing::info!("[jobs] {} background worker(s)", workers);
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+/// Queued, running and waiting jobs, in the order they run.
+#[tauri::command]
+pub fn get_jobs(app: AppHandle) -> Result<Vec<Job>, String> {
+    with_conn(&app, list)
+}
+
+/// Take a job off the queue, stopping it if it runs.
+#[tauri::command]
+pub fn cancel_job(app: AppHandle, webview: tauri::Webview, id: i64) -> Result<Job, String> {
+    require_webview(&webview, Capability::ManageLibrary)?;
+    let mut job = with_conn(&app, |conn| remove(conn, id))?.ok_or_else(|| format!("No job {}", id))?;
+    app.state::<JobQueue>().stop_if_active(id);
+    if let JobSpec::Thumbnail(thumbnail) = &job.spec {
+        crate::media::thumbnails::forget(&app, &thumbnail.target);
+    }
+    job.status = JobStatus::Cancelled;
+    publish(&app, AppEvent::JobChanged(job.clone()));
+    Ok(job)
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn thumbnail(name: &str) -> JobSpec {
+        JobSpec::Thumbnail(ThumbnailJob {
+            video: PathBuf::from(format!("/v/{}.mp4", name)),
+            time_sec: 10.0,
+            width: 320,
+            target: PathBuf::from(format!("/c/{}.jpg", name)),
+        })
+    }
+
+    #[test]
+    fn runs_by_priority_and_survives_restart() {
+        let conn = crate::db::test_conn();
+
+        let loudness = add(&conn, &JobSpec::Loudness { force: false }).unwrap();
+        let duplicates = add(&conn, &JobSpec::Duplicates).unwrap();
+        let thumbnail = add(&conn, &thumbnail("a")).unwrap();
+        let waveform = add(&conn, &JobSpec::Waveform { path: "/s/a.mp3".into(), resolution: 800 }).unwrap();
+        assert_eq!(
+            list(&conn).unwrap().iter().map(|j| j.id).collect::<Vec<_>>(),
+            [thumbnail.id, waveform.id, duplicates.id, loudness.id]
+        );
+        assert!(has_pending(&conn, "duplicates").unwrap());
+
+        // During playback only the dedicated lane starts anything, and only these
+        assert_eq!(lane_minimum(false, true), None);
+        assert_eq!(lane_minimum(true, true), Some(Priority::High));
+        assert_eq!(claim_next(&conn, Priority::High).unwrap().map(|j| j.id), Some(thumbnail.id));
+        assert_eq!(claim_next(&conn, Priority::High).unwrap().map(|j| j.id), Some(waveform.id));
+        assert_eq!(claim_next(&conn, Priority::High).unwrap(), None);
+        let next = claim_next(&conn, Priority::Low).unwrap().unwrap();
+        assert_eq!((next.id, next.status), (duplicates.id, JobStatus::Running));
+        assert!(transition(&conn, duplicates.id, &[JobStatus::Running], JobStatus::Waiting).unwrap());
+        set_progress(&conn, duplicates.id, 3, 10).unwrap();
+
+        assert_eq!(recover(&conn).unwrap(), 1);
+        let resumed = get(&conn, duplicates.id).unwrap().unwrap();
+        assert_eq!((resumed.status, resumed.done, resumed.total), (JobStatus::Queued, 3, Some(10)));
+        assert_eq!(get(&conn, thumbnail.id).unwrap(), None);
+        assert_eq!(get(&conn, waveform.id).unwrap(), None);
+        assert_eq!(resumed.spec, JobSpec::Duplicates);
+
+        assert!(remove(&conn, loudness.id).unwrap().is_some());
+        assert!(remove(&conn, loudness.id).unwrap().is_none());
+        assert!(!has_pending(&conn, "loudness").unwrap());
+    }
+
+    #[test]
+    fn a_wake_before_any_worker_waits_is_kept() {
+        let queue = JobQueue::default();
+        queue.wake();
+        let woke = tauri::async_runtime::block_on(async {
+            tokio::time::timeout(Duration::from_millis(100), queue.wake.notified()).await.is_ok()
+        });
+        // Well before the `PLAYBACK_POLL` fallback
+        assert!(woke);
+    }
+}
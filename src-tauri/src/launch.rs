@@ -0,0 +1,275 @@
+//! Files and links handed to the app on launch.
+//!
+//! When the app is already running, double-clicking a song file or opening
+//! a `karaoke://` link starts a second process. The single-instance plugin
+//! hands that process's argv to the running session, which comes to the
+//! front (the splash while it is still starting), takes over `--kiosk` and
+//! imports and forwards the files and links instead of dropping them; the
+//! second process exits before it starts a server of its own.
+//!
+//! What the first process was started with is handled the same way
+//! (`handle_startup`), as is the start of a queue `--autoplay-queue`
+//! loaded (`autoplay`). Items are only published once the frontend listens
+//! for them:
+//! until its page calls `launch_ready` they are held, and they are held
+//! again while the main window loads a new page.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use serde::Serialize;
+use tauri::plugin::{Builder as PluginBuilder, TauriPlugin};
+use tauri::webview::PageLoadEvent;
+use tauri::{AppHandle, Manager, Runtime};
+
+use crate::cli;
+use crate::db::DbState;
+use crate::deep_link;
+use crate::desktop::{kiosk, splash};
+use crate::events::{publish, AppEvent};
+use crate::library::scanner;
+use crate::queue::AutoplayRequest;
+
+/// Event emitted when another launch forwarded files or links.
+pub const OPEN_REQUEST_EVENT: &str = "app://open-request";
+
+/// URL scheme registered for deep links.
+pub const URL_SCHEME: &str = "karaoke://";
+
+/// One thing the user asked the app to open.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LaunchTarget {
+    File(PathBuf),
+    Url(String),
+    /// Start the queue, `queued` songs of which came from `source`.
+    Autoplay { queued: usize, source: String },
+}
+
+/// Payload of `app://open-request`: songs imported from forwarded files,
+/// ready to be queued. Links are handled by `deep_link` instead.
+#[derive(Debug, Clone, Serialize)]
+pub struct OpenRequest {
+    pub songs: Vec<serde_json::Value>,
+    pub errors: Vec<String>,
+}
+
+/// Managed state: whether the frontend listens, and what waits for it.
+#[derive(Default)]
+pub struct LaunchState {
+    /// Guarded by `pending`, so nothing is queued after a flush.
+    ready: AtomicBool,
+    pending: Mutex<Vec<LaunchTarget>>,
+}
+
+/// Extract launch targets from a process argv (argv[0] is skipped).
+/// Relative paths are resolved against `cwd`, the launching process's
+/// working directory; flags (with their values) and missing files are
+/// ignored.
+pub fn parse_launch_args(argv: &[String], cwd: &Path) -> Vec<LaunchTarget> {
+    let mut targets = Vec::new();
+    let mut args = argv.iter().skip(1);
+    while let Some(arg) = args.next() {
+        if arg.to_ascii_lowercase().starts_with(URL_SCHEME) {
+            targets.push(LaunchTarget::Url(arg.clone()));
+            continue;
+        }
+        if arg.starts_with('-') {
+            // `--library <dir>` names a folder to add, not one to import
+            if cli::GUI_VALUE_FLAGS.contains(&arg.as_str()) {
+                args.next();
+            }
+            continue;
+        }
+        let path = Path::new(arg);
+        let path = if path.is_absolute() { path.to_path_buf() } else { cwd.join(path) };
+        if path.exists() {
+            targets.push(LaunchTarget::File(path));
+        }
+    }
+    targets
+}
+
+/// Single-instance callback: bring the app forward, then import and
+/// forward whatever the second launch was asked to open.
+pub fn handle_second_instance(app: &AppHandle, argv: Vec<String>, cwd: String) {
+    focus_existing(app);
+    if argv.iter().skip(1).any(|arg| arg == kiosk::KIOSK_FLAG) {
+        kiosk::force(app);
+    }
+
+    let targets = parse_launch_args(&argv, Path::new(&cwd));
+    if targets.is_empty() {
+        return;
+    }
+    tracing::info!("[launch] Second instance forwarded {} item(s)", targets.len());
+    dispatch(app, targets);
+}
+
+/// Files and links this process was started with (a double-click or a
+/// link that launched the app), held like forwarded ones.
+pub fn handle_startup(app: &AppHandle) {
+    let argv: Vec<String> = std::env::args().collect();
+    let cwd = std::env::current_dir().unwrap_or_default();
+    let targets = parse_launch_args(&argv, &cwd);
+    if targets.is_empty() {
+        return;
+    }
+    tracing::info!("[launch] Started with {} item(s)", targets.len());
+    dispatch(app, targets);
+}
+
+/// Ask the frontend to start the queue once it listens, after
+/// `--autoplay-queue` queued `queued` songs from `source`.
+pub fn autoplay(app: &AppHandle, queued: usize, source: String) {
+    dispatch(app, vec![LaunchTarget::Autoplay { queued, source }]);
+}
+
+/// `karaoke://` URLs the OS opened the app with (macOS sends them as
+/// events rather than in argv).
+pub fn open_urls(app: &AppHandle, urls: impl IntoIterator<Item = String>) {
+    let targets: Vec<LaunchTarget> = urls
+        .into_iter()
+        .filter(|url| url.to_ascii_lowercase().starts_with(URL_SCHEME))
+        .map(LaunchTarget::Url)
+        .collect();
+    if !targets.is_empty() {
+        dispatch(app, targets);
+    }
+}
+
+/// The splash while starting up (the main window is still empty), the
+/// main window after, restored from the tray or the taskbar.
+fn focus_existing(app: &AppHandle) {
+    let Some(window) = app.get_webview_window(splash::SPLASH_LABEL).or_else(|| app.get_webview_window("main")) else {
+        return;
+    };
+    let _ = window.unminimize();
+    let _ = window.show();
+    let _ = window.set_focus();
+}
+
+/// Handle `targets` now, or hold them until the frontend is ready.
+fn dispatch(app: &AppHandle, targets: Vec<LaunchTarget>) {
+    if let Some(state) = app.try_state::<LaunchState>() {
+        let Ok(mut pending) = state.pending.lock() else { return };
+        if !state.ready.load(Ordering::Relaxed) {
+            tracing::debug!("[launch] Holding {} item(s) until the frontend is ready", targets.len());
+            pending.extend(targets);
+            return;
+        }
+    }
+    let request = import_targets(app, targets);
+    if request.songs.is_empty() && request.errors.is_empty() {
+        return;
+    }
+    publish(app, AppEvent::OpenRequest(request));
+}
+
+/// Marks the frontend as not listening while the main window loads a page.
+pub fn plugin<R: Runtime>() -> TauriPlugin<R> {
+    PluginBuilder::new("launch")
+        .on_page_load(|webview, payload| {
+            if webview.label() != "main" || payload.event() != PageLoadEvent::Started {
+                return;
+            }
+            if let Some(state) = webview.app_handle().try_state::<LaunchState>() {
+                if let Ok(_pending) = state.pending.lock() {
+                    state.ready.store(false, Ordering::Relaxed);
+                }
+            }
+        })
+        .build()
+}
+
+/// Import file targets into the library; dispatch links to `deep_link`
+/// and the start of the queue as `queue://autoplay`.
+fn import_targets(app: &AppHandle, targets: Vec<LaunchTarget>) -> OpenRequest {
+    let mut request = OpenRequest { songs: Vec::new(), errors: Vec::new() };
+
+    for target in targets {
+        match target {
+            LaunchTarget::Url(url) => deep_link::dispatch(app, &url),
+            LaunchTarget::Autoplay { queued, source } => {
+                publish(app, AppEvent::QueueAutoplay(AutoplayRequest { queued, source }));
+            }
+            LaunchTarget::File(path) => match scanner::import_path(&path) {
+                Ok(report) => {
+                    request.errors.extend(report.errors);
+                    request.songs.extend(report.songs);
+                }
+                Err(e) => request.errors.push(e),
+            },
+        }
+    }
+
+    if !request.songs.is_empty() {
+        let Some(db) = app.try_state::<DbState>() else {
+            request.errors.push("Database not available".to_string());
+            return request;
+        };
+        let saved = db
+            .conn
+            .lock()
+            .map_err(|e| e.to_string())
+            .and_then(|mut conn| scanner::save_songs(&mut conn, &request.songs));
+        if let Err(e) = saved {
+            request.errors.push(e);
+        }
+    }
+
+    request
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+/// Called by the frontend once it listens for `app://open-request` and the
+/// deep-link events; publishes what was held until then.
+#[tauri::command]
+pub fn launch_ready(app: AppHandle) {
+    let state = app.state::<LaunchState>();
+    let held = match state.pending.lock() {
+        Ok(mut pending) => {
+            state.ready.store(true, Ordering::Relaxed);
+            std::mem::take(&mut *pending)
+        }
+        Err(_) => return,
+    };
+    if !held.is_empty() {
+        tracing::info!("[launch] Forwarding {} held item(s)", held.len());
+        dispatch(&app, held);
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_urls_and_resolves_relative_files() {
+        let cwd = std::env::temp_dir();
+        let file = cwd.join("karaoke-launch-test.txt");
+        std::fs::write(&file, "#TITLE:x\n").unwrap();
+
+        let argv: Vec<String> = ["karaoke", "--flag", "karaoke-launch-test.txt", "KARAOKE://enqueue?id=1", "missing.txt", "--autoplay-queue", "karaoke-launch-test.txt"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let targets = parse_launch_args(&argv, &cwd);
+        std::fs::remove_file(&file).ok();
+
+        assert_eq!(
+            targets,
+            vec![
+                LaunchTarget::File(file),
+                LaunchTarget::Url("KARAOKE://enqueue?id=1".to_string()),
+            ]
+        );
+    }
+}
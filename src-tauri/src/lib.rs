@@ -1,19 +1,56 @@
-use std::net::{TcpStream, UdpSocket};
-use std::process::{Command, Child};
 
-use std::sync::Mutex;
-use std::thread;
 use std::time::Duration;
 use std::env;
 use std::path::PathBuf;
 use std::fs;
 
 use tauri::Manager;
+use tauri_plugin_deep_link::DeepLinkExt;
 use serde::Serialize;
 
+mod access;
 mod audio;
+mod automation;
+mod autostart;
+mod backup;
+mod cast;
+mod cdg;
 mod db;
 mod charts;
+mod cli;
+mod clipboard_watch;
+mod config;
+mod crash;
+mod deep_link;
+mod desktop;
+mod downloads;
+mod events;
+mod history;
+mod installation;
+mod interchange;
+mod jobs;
+mod launch;
+mod library;
+mod lighting;
+mod logging;
+mod lyrics;
+mod media;
+mod midi;
+mod midi_control;
+mod multiroom;
+mod party;
+mod paths;
+mod profiles;
+mod queue;
+mod remote;
+mod runtime;
+mod scheduler;
+mod scoring;
+mod server;
+mod session;
+mod telemetry;
+mod updater;
+mod watch_party;
 
 /// Shared utility: try to convert a `Result<T, E>` into `Option<T>`, logging
 /// errors instead of silently discarding them via `.ok()`.
@@ -22,7 +59,7 @@ pub(crate) fn try_log<T, E: std::fmt::Display>(result: Result<T, E>, context: &s
     match result {
         Ok(v) => Some(v),
         Err(e) => {
-            eprintln!("[error] {}: {}", context, e);
+            tracing::error!("{}: {}", context, e);
             None
         }
     }
@@ -77,12 +114,18 @@ fn resolve_path_candidates(file_path: &str, validated: &PathBuf) -> Vec<PathBuf>
         }
     }
 
+    // Last resort: the stored path uses a different Unicode normalization
+    // than the file system (library copied between macOS and Windows/Linux)
+    if let Some(on_disk) = paths::find_on_disk(&PathBuf::from(file_path)) {
+        candidates.push(on_disk);
+    }
+
     candidates
 }
 
 /// Check whether a resolved path points to a critical system directory
 /// or its parent. Returns Ok(canonical path) if safe, Err otherwise.
-fn validate_safe_path(raw_path: &str) -> Result<PathBuf, String> {
+pub(crate) fn validate_safe_path(raw_path: &str) -> Result<PathBuf, String> {
     let path = PathBuf::from(raw_path);
 
     // Reject paths containing traversal components (..) before canonicalization.
@@ -96,15 +139,17 @@ fn validate_safe_path(raw_path: &str) -> Result<PathBuf, String> {
     // canonicalize the parent instead.
     let canonical = if path.exists() {
         path.canonicalize().map_err(|e| format!("Cannot resolve path '{}': {}", raw_path, e))?
-    } else if let Some(parent) = path.parent() {
-        if parent.exists() {
-            parent.canonicalize().map_err(|e| format!("Cannot resolve parent of '{}': {}", raw_path, e))?
-                .join(path.file_name().unwrap_or_default())
-        } else {
-            return Err(format!("Cannot resolve path '{}': parent directory does not exist", raw_path));
-        }
     } else {
-        return Err(format!("Cannot resolve path '{}': path has no parent and does not exist", raw_path));
+        // Canonicalize the nearest existing ancestor and re-attach the rest, so
+        // deep trees that do not exist yet (or exceed MAX_PATH) can be created.
+        let ancestor = path
+            .ancestors()
+            .skip(1)
+            .find(|a| !a.as_os_str().is_empty() && paths::long_path(a).exists())
+            .ok_or_else(|| format!("Cannot resolve path '{}': no existing parent directory", raw_path))?;
+        let rest = path.strip_prefix(ancestor).map_err(|e| e.to_string())?;
+        ancestor.canonicalize().map_err(|e| format!("Cannot resolve parent of '{}': {}", raw_path, e))?
+            .join(rest)
     };
 
     // Double-check that the canonical path also doesn't escape (belt-and-suspenders)
@@ -150,7 +195,8 @@ fn validate_safe_path(raw_path: &str) -> Result<PathBuf, String> {
 /// Tries multiple path strategies to handle Windows edge cases
 /// with special characters (&, parentheses, Unicode) in folder names.
 #[tauri::command]
-fn native_read_file_bytes(file_path: String) -> Result<String, String> {
+fn native_read_file_bytes(webview: tauri::Webview, file_path: String) -> Result<String, String> {
+    access::require_webview(&webview, access::Capability::ManageLibrary)?;
     // validate_safe_path canonicalizes the path and checks against system dirs.
     // We use the returned canonical path for the first read attempt so that
     // symlink resolution is honored. The fallback attempts use the raw path
@@ -185,7 +231,8 @@ fn native_read_file_bytes(file_path: String) -> Result<String, String> {
 /// Many UltraStar TXT files are encoded in Latin-1, especially older ones
 /// with French/German/Spanish accented characters.
 #[tauri::command]
-fn native_read_file_text(file_path: String) -> Result<String, String> {
+fn native_read_file_text(webview: tauri::Webview, file_path: String) -> Result<String, String> {
+    access::require_webview(&webview, access::Capability::ManageLibrary)?;
     let validated = validate_safe_path(&file_path)?;
 
     // Helper: try to read a file as text with encoding fallback.
@@ -231,7 +278,10 @@ fn native_read_file_text(file_path: String) -> Result<String, String> {
 
 /// Check if a file or directory exists
 #[tauri::command]
-fn native_file_exists(file_path: String) -> bool {
+fn native_file_exists(webview: tauri::Webview, file_path: String) -> bool {
+    if access::require_webview(&webview, access::Capability::ManageLibrary).is_err() {
+        return false;
+    }
     let validated = match validate_safe_path(&file_path) {
         Ok(p) => p,
         Err(_) => return false,
@@ -259,7 +309,8 @@ struct NativeDirEntry {
 }
 
 #[tauri::command]
-fn native_read_dir(dir_path: String) -> Result<Vec<NativeDirEntry>, String> {
+fn native_read_dir(webview: tauri::Webview, dir_path: String) -> Result<Vec<NativeDirEntry>, String> {
+    access::require_webview(&webview, access::Capability::ManageLibrary)?;
     let validated = validate_safe_path(&dir_path)?;
     // Try the validated (canonicalized) path first
     if validated.exists() && validated.is_dir() {
@@ -365,18 +416,24 @@ fn native_confirm(title: String, message: String) -> bool {
     )
 }
 
-/// Create a directory (recursive)
+/// Create a directory (recursive).
+/// New folder names are sanitized (reserved names, trailing dots); returns
+/// the path actually created.
 #[tauri::command]
-fn native_mkdir(dir_path: String) -> Result<(), String> {
-    let validated = validate_safe_path(&dir_path)?;
-    fs::create_dir_all(&validated)
-        .map_err(|e| format!("Failed to create directory '{}': {}", validated.display(), e))
+fn native_mkdir(webview: tauri::Webview, dir_path: String) -> Result<String, String> {
+    access::require_webview(&webview, access::Capability::ManageLibrary)?;
+    let validated = paths::sanitize_new_components(&validate_safe_path(&dir_path)?);
+    fs::create_dir_all(paths::long_path(&validated))
+        .map_err(|e| format!("Failed to create directory '{}': {}", validated.display(), e))?;
+    Ok(paths::display_path(&validated).to_string_lossy().to_string())
 }
 
-/// Write bytes to a file (decoded from base64)
+/// Write bytes to a file (decoded from base64).
+/// New path components are sanitized; returns the path actually written.
 #[tauri::command]
-fn native_write_file_bytes(file_path: String, data_base64: String) -> Result<(), String> {
-    let validated = validate_safe_path(&file_path)?;
+fn native_write_file_bytes(webview: tauri::Webview, file_path: String, data_base64: String) -> Result<String, String> {
+    access::require_webview(&webview, access::Capability::ManageLibrary)?;
+    let validated = paths::sanitize_new_components(&validate_safe_path(&file_path)?);
     let bytes = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, &data_base64)
         .map_err(|e| format!("Failed to decode base64: {}", e))?;
 
@@ -389,18 +446,21 @@ fn native_write_file_bytes(file_path: String, data_base64: String) -> Result<(),
     }
 
     if let Some(parent) = validated.parent() {
-        fs::create_dir_all(parent)
+        fs::create_dir_all(paths::long_path(parent))
             .map_err(|e| format!("Failed to create parent directory: {}", e))?;
     }
 
-    fs::write(&validated, &bytes)
-        .map_err(|e| format!("Failed to write '{}': {}", validated.display(), e))
+    fs::write(paths::long_path(&validated), &bytes)
+        .map_err(|e| format!("Failed to write '{}': {}", validated.display(), e))?;
+    Ok(paths::display_path(&validated).to_string_lossy().to_string())
 }
 
-/// Write text to a file
+/// Write text to a file.
+/// New path components are sanitized; returns the path actually written.
 #[tauri::command]
-fn native_write_file_text(file_path: String, content: String) -> Result<(), String> {
-    let validated = validate_safe_path(&file_path)?;
+fn native_write_file_text(webview: tauri::Webview, file_path: String, content: String) -> Result<String, String> {
+    access::require_webview(&webview, access::Capability::ManageLibrary)?;
+    let validated = paths::sanitize_new_components(&validate_safe_path(&file_path)?);
 
     let byte_len = content.as_bytes().len();
     if byte_len > MAX_FILE_SIZE {
@@ -412,83 +472,85 @@ fn native_write_file_text(file_path: String, content: String) -> Result<(), Stri
     }
 
     if let Some(parent) = validated.parent() {
-        fs::create_dir_all(parent)
+        fs::create_dir_all(paths::long_path(parent))
             .map_err(|e| format!("Failed to create parent directory: {}", e))?;
     }
 
-    fs::write(&validated, &content)
-        .map_err(|e| format!("Failed to write '{}': {}", validated.display(), e))
+    fs::write(paths::long_path(&validated), &content)
+        .map_err(|e| format!("Failed to write '{}': {}", validated.display(), e))?;
+    Ok(paths::display_path(&validated).to_string_lossy().to_string())
 }
 
 /// Remove a file
 #[tauri::command]
-fn native_remove_file(file_path: String) -> Result<(), String> {
+fn native_remove_file(webview: tauri::Webview, file_path: String) -> Result<(), String> {
+    access::require_webview(&webview, access::Capability::ManageLibrary)?;
     let validated = validate_safe_path(&file_path)?;
-    fs::remove_file(&validated)
+    fs::remove_file(paths::long_path(&validated))
         .map_err(|e| format!("Failed to remove '{}': {}", validated.display(), e))
 }
 
 /// Remove a directory (recursive)
 #[tauri::command]
-fn native_remove_dir(dir_path: String) -> Result<(), String> {
+fn native_remove_dir(webview: tauri::Webview, dir_path: String) -> Result<(), String> {
+    access::require_webview(&webview, access::Capability::ManageLibrary)?;
     let validated = validate_safe_path(&dir_path)?;
-    fs::remove_dir_all(&validated)
+    fs::remove_dir_all(paths::long_path(&validated))
         .map_err(|e| format!("Failed to remove directory '{}': {}", validated.display(), e))
 }
 
-/// Get the local network IP address using UDP socket trick.
-/// This detects which interface would be used to reach the internet,
-/// which is typically the LAN interface companions need to connect to.
+/// Get the local network IP address: the interface that would be used to
+/// reach the internet, typically the LAN interface companions need to
+/// connect to. `get_connection_info` lists all of them.
 #[tauri::command]
 fn network_get_local_ip() -> Option<String> {
-    let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
-    // Connecting to an external address determines the routing interface
-    // without actually sending any data (UDP connect just sets the route)
-    socket.connect("8.8.8.8:80").ok()?;
-    let local_addr = socket.local_addr().ok()?;
-    let ip = local_addr.ip().to_string();
-    // Filter out loopback and unspecified addresses
-    if ip.starts_with("127.") || ip == "0.0.0.0" || ip.starts_with("169.254.") {
-        None
-    } else {
-        println!("[network_get_local_ip] Detected LAN IP: {}", ip);
-        Some(ip)
-    }
+    let ip = server::discovery::route_ip()?.to_string();
+    tracing::info!("[network_get_local_ip] Detected LAN IP: {}", ip);
+    Some(ip)
 }
 
-/// Server process handle protected by a Mutex for thread-safe access.
-/// Replaces the previous `static mut` which was undefined behavior.
-static SERVER_PROCESS: Mutex<Option<Child>> = Mutex::new(None);
-
+/// Whether one of our servers (any session) answers on the current port.
 fn check_server_running() -> bool {
-    TcpStream::connect("127.0.0.1:3000").is_ok()
+    let port = server::port::current();
+    tauri::async_runtime::block_on(server::health::probe(port, None)) == server::health::Identity::Ours
 }
 
-#[cfg(target_os = "windows")]
-fn get_node_path(resource_dir: &PathBuf) -> Option<PathBuf> {
-    // Windows: bundled/node/node.exe
-    let bundled_node = resource_dir.join("bundled").join("node").join("node.exe");
-    if bundled_node.exists() {
-        println!("Found bundled Node.js at: {:?}", bundled_node);
-        return Some(bundled_node);
+/// Point the main window at the running server and announce it on the bus.
+fn open_server_ui(app: &tauri::AppHandle) {
+    let port = server::port::current();
+    events::publish(app, events::AppEvent::ServerReady { url: server::port::server_url(port) });
+    // Not logged: it carries the access token in LAN mode
+    let url = server::security::ui_url(port);
+    // The shell is already up and forwards to the server from now on
+    if let Some(window) = app.get_webview_window("main").filter(|_| !server::shell::is_active()) {
+        match url.parse() {
+            Ok(parsed) => {
+                if let Err(e) = window.navigate(parsed) {
+                    tracing::error!("Failed to navigate to the server on port {}: {}", port, e);
+                }
+            }
+            Err(e) => tracing::error!("Invalid server URL for port {}: {}", port, e),
+        }
+        // Re-open DevTools after navigation (debug builds only; redirect may close them)
+        #[cfg(debug_assertions)]
+        if !desktop::kiosk::is_active(app) {
+            let _ = window.open_devtools();
+        }
     }
-    None
+    desktop::splash::close(app);
 }
 
-#[cfg(any(target_os = "macos", target_os = "linux"))]
-fn get_node_path(resource_dir: &PathBuf) -> Option<PathBuf> {
-    // macOS / Linux: bundled/node/bin/node
-    let bundled_node = resource_dir.join("bundled").join("node").join("bin").join("node");
-    if bundled_node.exists() {
-        println!("Found bundled Node.js at: {:?}", bundled_node);
-        return Some(bundled_node);
+/// Stop background tasks first (so no watchdog restarts the server being
+/// killed), then the server itself.
+pub(crate) fn shutdown_background(app: &tauri::AppHandle) {
+    if let Some(supervisor) = app.try_state::<runtime::TaskSupervisor>() {
+        supervisor.shutdown(Duration::from_secs(3));
     }
-    None
-}
-
-#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
-fn get_node_path(_resource_dir: &PathBuf) -> Option<PathBuf> {
-    None
+    if let Some(transcode) = app.try_state::<media::transcode::TranscodeService>() {
+        transcode.shutdown();
+    }
+    media::native_video::close(app);
+    server::shutdown_server(app);
 }
 
 fn get_server_path(resource_dir: &PathBuf) -> Option<PathBuf> {
@@ -500,79 +562,210 @@ fn get_server_path(resource_dir: &PathBuf) -> Option<PathBuf> {
     
     for path in possible_paths {
         if path.exists() {
-            println!("Found server at: {:?}", path);
+            tracing::info!("Found server at: {:?}", path);
             return Some(path);
         }
     }
     None
 }
 
-/// Try to find any available Node.js runtime: bundled node, system node, or bun.
-/// Returns (path_to_runtime, runtime_name) for logging.
-fn find_node_runtime(resource_dir: &PathBuf) -> Option<PathBuf> {
-    // 1. Bundled Node.js (portable)
-    if let Some(p) = get_node_path(resource_dir) {
-        return Some(p);
-    }
-    // 2. System Node.js
-    if let Ok(output) = Command::new("where").arg("node.exe").output() {
-        if output.status.success() {
-            let path_str = String::from_utf8_lossy(&output.stdout);
-            if let Some(first_line) = path_str.lines().next() {
-                let p = PathBuf::from(first_line.trim());
-                if p.exists() {
-                    println!("Found system Node.js at: {:?}", p);
-                    return Some(p);
+fn get_server_cwd(server_path: &PathBuf) -> PathBuf {
+    // The working directory should be the server directory
+    server_path.parent().unwrap_or(server_path).to_path_buf()
+}
+
+/// How `launch_server` got the server process up, for the readiness wait,
+/// the launch plan and `telemetry`.
+struct LaunchAttempt {
+    started: bool,
+    path: &'static str,
+    runtime: Option<&'static str>,
+    bundled_runtime: bool,
+    /// The plan for this launch, saved once the server is ready.
+    plan: Option<server::launch_plan::LaunchPlan>,
+    /// Last spawn failure, reported if no attempt succeeds.
+    error: Option<String>,
+}
+
+/// Spawn the server: as `cached_plan` says if there is one that starts,
+/// otherwise by discovery (bundled server, system Node.js, `bun`/`npm run
+/// dev`). Blocking: it lists directories, runs `<runtime> --version` and
+/// spawns processes, so it runs on a blocking worker.
+#[allow(clippy::too_many_arguments)]
+fn launch_server(
+    handle: &tauri::AppHandle,
+    resource_dir: Option<&PathBuf>,
+    config_dir: Option<&std::path::Path>,
+    cached_plan: Option<&server::launch_plan::LaunchPlan>,
+    choice: server::runtime::RuntimeChoice,
+    app_version: &str,
+    preferred_port: u16,
+    port_env: &str,
+    limits: &server::limits::ServerLimits,
+) -> LaunchAttempt {
+    let manager = handle.state::<server::ServerManager>();
+    let mut attempt = LaunchAttempt { started: false, path: "none", runtime: None, bundled_runtime: false, plan: None, error: None };
+    // Start what the cached plan says, without looking it up again
+    if let Some(plan) = cached_plan {
+        let cwd = get_server_cwd(&plan.server_path);
+        let runtime = plan.runtime();
+        tracing::info!(
+            "Starting server from the cached launch plan: {} {:?} ({}), {:?}",
+            runtime.kind.name(), runtime.program, plan.version, plan.server_path
+        );
+        match manager.start(&runtime.command(&plan.server_path, &cwd, port_env, limits), limits) {
+            Ok(()) => {
+                attempt.started = true;
+                attempt.path = "cached_plan";
+                attempt.runtime = Some(runtime.kind.name());
+                attempt.bundled_runtime = runtime.bundled;
+                attempt.plan = Some(plan.clone());
+            }
+            Err(e) => {
+                tracing::warn!("Cached launch plan failed ({}) — discovering the server", e);
+                if let Some(config_dir) = config_dir {
+                    server::launch_plan::clear(config_dir);
                 }
             }
         }
     }
-    if let Ok(output) = Command::new("which").arg("node").output() {
-        if output.status.success() {
-            let path_str = String::from_utf8_lossy(&output.stdout);
-            if let Some(first_line) = path_str.lines().next() {
-                let p = PathBuf::from(first_line.trim());
-                if p.exists() {
-                    println!("Found system Node.js at: {:?}", p);
-                    return Some(p);
+
+    // Try bundled server with any available runtime (bundled node > system node > system bun)
+    if let (false, Some(res_dir)) = (attempt.started, resource_dir) {
+        if let Some(server_path) = get_server_path(res_dir) {
+            let cwd = get_server_cwd(&server_path);
+            
+            let runtime = server::runtime::resolve(res_dir, choice);
+            
+            if let Some(runtime) = runtime {
+                match server::node_check::check(&runtime) {
+                    Ok(checked) => {
+                        tracing::info!("Starting server with runtime...");
+                        tracing::info!(
+                            "Runtime: {} {:?} ({}, {})",
+                            runtime.kind.name(), runtime.program, checked.version, checked.arch.unwrap_or("unknown arch")
+                        );
+                        tracing::info!("Server: {:?}", server_path);
+                        tracing::info!("Working dir: {:?}", cwd);
+                
+                        let recipe = runtime.command(&server_path, &cwd, port_env, limits);
+                        let result = manager.start(&recipe, limits);
+                
+                        match result {
+                            Ok(()) => {
+                                attempt.started = true;
+                                attempt.path = "bundled";
+                                attempt.runtime = Some(runtime.kind.name());
+                                attempt.bundled_runtime = runtime.bundled;
+                                attempt.plan = server::launch_plan::LaunchPlan::new(
+                                    app_version, choice, preferred_port, &server_path, &runtime, &checked.version,
+                                );
+                                tracing::info!("Server process started successfully");
+                            }
+                            Err(e) => {
+                                tracing::error!("Failed to start server: {:?}", e);
+                                attempt.error = Some(format!("Failed to start bundled server: {}", e));
+                            }
+                        }
+                    }
+                    Err(problem) => {
+                        tracing::error!("Not starting the server: {}", problem.reason);
+                        attempt.error = Some(problem.reason.clone());
+                        events::publish(handle, events::AppEvent::ServerRuntimeIncompatible(problem));
+                    }
                 }
+            } else {
+                tracing::warn!("No {:?} runtime found — bundled server available but no runtime", choice);
             }
+        } else {
+            tracing::warn!("Server not found in bundled resources");
         }
     }
-    // 3. System Bun (can run Node.js scripts)
-    if let Ok(output) = Command::new("where").arg("bun.exe").output() {
-        if output.status.success() {
-            let path_str = String::from_utf8_lossy(&output.stdout);
-            if let Some(first_line) = path_str.lines().next() {
-                let p = PathBuf::from(first_line.trim());
-                if p.exists() {
-                    println!("Found system Bun at: {:?}", p);
-                    return Some(p);
+    
+    // Fallback: Try system Node.js
+    if !attempt.started {
+        tracing::info!("Trying system Node.js...");
+        
+        // Try to find server.js in common locations
+        let cwd = env::current_dir().unwrap_or_default();
+        let possible_servers = [
+            cwd.join("server.js"),
+            cwd.join("bundled").join("server").join("server.js"),
+        ];
+        
+        for server in &possible_servers {
+            if server.exists() {
+                tracing::info!("Trying server at: {:?}", server);
+                if let Err(problem) = server::node_check::check(&server::runtime::Runtime {
+                    kind: server::runtime::RuntimeKind::Node,
+                    program: PathBuf::from("node"),
+                    bundled: false,
+                }) {
+                    tracing::error!("Not starting the server: {}", problem.reason);
+                    attempt.error = Some(problem.reason.clone());
+                    events::publish(handle, events::AppEvent::ServerRuntimeIncompatible(problem));
+                    break;
+                }
+                if let Some(parent) = server.parent() {
+                    let recipe = server::ServerCommand::new("node", parent)
+                        .arg(server.to_string_lossy())
+                        .env("PORT", port_env);
+                    match manager.start(&recipe, limits) {
+                        Ok(()) => {
+                            attempt.started = true;
+                            attempt.path = "system_node";
+                            break;
+                        }
+                        Err(e) => attempt.error = Some(format!("Failed to start system Node.js: {}", e)),
+                    }
                 }
             }
         }
     }
-    if let Ok(output) = Command::new("which").arg("bun").output() {
-        if output.status.success() {
-            let path_str = String::from_utf8_lossy(&output.stdout);
-            if let Some(first_line) = path_str.lines().next() {
-                let p = PathBuf::from(first_line.trim());
-                if p.exists() {
-                    println!("Found system Bun at: {:?}", p);
-                    return Some(p);
+    
+    // Fallback: Try bun/npm in current directory
+    if !attempt.started {
+        let current_dir = env::current_dir().unwrap_or_default();
+        if current_dir.join("package.json").exists() {
+            tracing::info!("Trying bun/npm run dev...");
+            // The dev script pins `-p 3000`
+            server::port::set(server::port::PREFERRED_PORT);
+            
+            let dev_command = |program: &str| {
+                server::ServerCommand::new(program, &current_dir)
+                    .arg("run")
+                    .arg("dev")
+                    .env("PORT", &server::port::PREFERRED_PORT.to_string())
+            };
+            let result = manager
+                .start(&dev_command("bun"), limits)
+                .or_else(|_| manager.start(&dev_command("npm"), limits));
+            
+            match result {
+                Ok(()) => {
+                    attempt.started = true;
+                    attempt.path = "dev_server";
                 }
+                Err(e) => attempt.error = Some(format!("Failed to start dev server: {}", e)),
             }
         }
     }
-    None
+    
+    attempt
 }
 
-fn get_server_cwd(server_path: &PathBuf) -> PathBuf {
-    // The working directory should be the server directory
-    server_path.parent().unwrap_or(server_path).to_path_buf()
+/// Handle headless CLI subcommands (`scan`, `import`, `export-scores`).
+/// Returns the exit code if one ran; `None` means start the GUI.
+pub fn run_cli() -> Option<i32> {
+    cli::run_from_args()
 }
 
 pub fn run() {
+    // `--port`, `--library` & co. are checked before anything starts
+    if let Err(code) = cli::init_gui_flags() {
+        std::process::exit(code);
+    }
+
     // ── Windows: Make bundled native DLLs discoverable ──
     // On a clean Windows install, the Microsoft Visual C++ Runtime
     // DLLs (msvcp140.dll, vcruntime140.dll) and ONNX Runtime
@@ -580,6 +773,9 @@ pub fn run() {
     // bundled/native/ via tauri.conf.json resources. Adding
     // this directory to PATH + ORT_LIB_PATH ensures both
     // Windows' DLL loader and ort's libloading can find them.
+    // Logged from `setup`, once the logger is installed
+    #[allow(unused_mut)]
+    let mut bundled_native_dir: Option<std::path::PathBuf> = None;
     #[cfg(target_os = "windows")]
     {
         if let Ok(exe) = std::env::current_exe() {
@@ -591,16 +787,40 @@ pub fn run() {
                     // Windows DLL loader searches PATH directories for dependencies
                     let path = std::env::var("PATH").unwrap_or_default();
                     std::env::set_var("PATH", format!("{};{}", native_dir.display(), path));
-                    println!("[standalone] Added to PATH: {}", native_dir.display());
+                    bundled_native_dir = Some(native_dir);
                 }
             }
         }
     }
 
     tauri::Builder::default()
+        // Must be registered first: a second launch forwards its argv here and exits
+        .plugin(tauri_plugin_single_instance::init(|app, argv, cwd| {
+            launch::handle_second_instance(app, argv, cwd);
+        }))
+        // Before setup: a second launch may arrive while the app starts
+        .manage(launch::LaunchState::default())
+        .plugin(launch::plugin())
+        .plugin(tauri_plugin_deep_link::init())
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_dialog::init())
+        .plugin(updater::plugin())
+        .plugin(desktop::kiosk::plugin())
+        .plugin(desktop::hotkeys::plugin())
+        .plugin(server::security::plugin())
+        .register_uri_scheme_protocol(desktop::splash::SPLASH_SCHEME, |_ctx, _request| {
+            desktop::splash::protocol_response()
+        })
+        .register_asynchronous_uri_scheme_protocol(server::shell::SHELL_SCHEME, |ctx, request, responder| {
+            server::shell::handle(ctx.app_handle().clone(), request, responder)
+        })
+        .register_asynchronous_uri_scheme_protocol(media::cache_scheme::CACHE_SCHEME, |ctx, request, responder| {
+            media::cache_scheme::handle(ctx.app_handle().clone(), request, responder)
+        })
+        .register_uri_scheme_protocol(cdg::CDG_SCHEME, |ctx, request| {
+            cdg::protocol_response(ctx.app_handle(), &request)
+        })
         .invoke_handler(tauri::generate_handler![
             // Native file system commands (bypass ACL)
             native_read_file_bytes,
@@ -618,10 +838,28 @@ pub fn run() {
             native_pick_file_save,
             native_message,
             native_confirm,
+            // Desktop integration
+            desktop::drag_out::drag_out_files,
+            desktop::reveal::reveal_path,
+            desktop::import_dialog::open_import_dialog,
+            desktop::player_window::list_monitors,
+            desktop::player_window::open_player_window,
+            desktop::player_window::close_player_window,
+            desktop::kiosk::set_kiosk_exit_pin,
+            desktop::kiosk::exit_kiosk,
+            launch::launch_ready,
             // Native audio commands (ASIO / WASAPI)
             audio::commands::audio_list_devices,
+            audio::commands::audio_list_input_devices,
             audio::commands::audio_get_default_device,
             audio::commands::audio_play_file,
+            audio::commands::audio_load,
+            audio::commands::audio_play,
+            audio::commands::audio_position,
+            audio::commands::set_key_offset,
+            audio::commands::set_vocal_removal,
+            audio::commands::audio_preload_next,
+            audio::commands::set_transition,
             audio::commands::audio_pause,
             audio::commands::audio_resume,
             audio::commands::audio_seek,
@@ -629,10 +867,51 @@ pub fn run() {
             audio::commands::audio_stop,
             audio::commands::audio_get_position,
             audio::commands::audio_get_state,
+            audio::commands::audio_get_channel_map,
+            audio::commands::audio_set_channel_map,
+            audio::commands::audio_play_test_tone,
+            audio::commands::audio_get_device_offset,
+            audio::commands::audio_set_device_offset,
+            audio::commands::audio_get_device_offsets,
+            audio::commands::audio_select_input_device,
+            audio::commands::audio_get_active_offset,
+            audio::commands::audio_run_level_calibration,
+            audio::commands::audio_get_reference_level,
+            audio::latency_calibration::run_latency_calibration,
+            audio::commands::audio_get_outputs,
+            audio::commands::configure_outputs,
+            audio::commands::audio_set_click_track,
+            audio::loudness::normalize_library,
+            audio::fingerprint::find_duplicates,
+            audio::fingerprint::get_duplicate_report,
+            audio::mic::list_audio_inputs,
+            audio::mic::start_mic_capture,
+            audio::mic::stop_mic_capture,
+            audio::rt_priority::audio_get_rt_priority_status,
+            audio::waveform::generate_waveform,
+            access::access_get_role_capabilities,
+            access::access_whoami,
+            session::save_session,
+            session::load_session,
+            session::autosave_session,
+            session::load_autosave,
+            // Internet watch party (two hosts, one queue)
+            watch_party::watch_party_host,
+            watch_party::watch_party_join,
+            watch_party::watch_party_leave,
+            watch_party::watch_party_share_queue,
+            watch_party::watch_party_start_song,
+            watch_party::watch_party_send_score,
+            watch_party::watch_party_status,
+            watch_party::relay::watch_party_run_relay,
+            watch_party::relay::watch_party_stop_relay,
+            audio::rt_priority::audio_set_rt_priority_enabled,
             // Audio analysis commands (pitch detection, BPM estimation)
             audio::analysis_commands::audio_analyze_pitch,
             audio::analysis_commands::audio_detect_bpm,
             audio::analysis_commands::audio_crepe_info,
+            audio::analysis_cache::audio_cache_get,
+            audio::analysis_cache::audio_cache_put,
             // SQLite offline database commands
             db::commands::db_get_setting,
             db::commands::db_set_setting,
@@ -656,6 +935,55 @@ pub fn run() {
             db::commands::db_delete_playlist,
             db::commands::db_clear_all,
             db::commands::db_get_stats,
+            // Native library management
+            library::commands::scan_library,
+            library::commands::cancel_scan,
+            library::commands::search_songs,
+            library::commands::search_songs_fast,
+            library::commands::get_song,
+            library::commands::upsert_song,
+            library::metadata::get_track_metadata,
+            library::artwork::get_artwork,
+            library::archive::extract_track,
+            library::ultrastar::load_ultrastar_song,
+            // CD+G graphics (frames served by the cdg:// protocol)
+            cdg::cdg_open,
+            cdg::cdg_close,
+            // Timed lyrics (LRC) following the native player
+            lyrics::load_lyrics,
+            lyrics::lyrics_follow,
+            lyrics::lyrics_stop,
+            // KAR / MIDI karaoke
+            midi::load_kar,
+            midi::render_midi,
+            scoring::start_scoring,
+            scoring::stop_scoring,
+            library::deletion::library_delete_songs,
+            library::deletion::restore_last_deleted,
+            library::quota::get_storage_usage,
+            library::quota::set_storage_quota,
+            library::quota::get_cleanup_suggestions,
+            library::quota::apply_cleanup,
+            library::data_migration::migrate_data_dir,
+            library::songbook::export_songbook,
+            library::songbook::lookup_song_code,
+            // Download manager
+            downloads::download_song,
+            downloads::list_downloads,
+            downloads::pause_download,
+            downloads::resume_download,
+            downloads::cancel_download,
+            downloads::clear_finished_downloads,
+            // Background jobs
+            jobs::get_jobs,
+            jobs::cancel_job,
+            party::party_start,
+            party::party_stop,
+            party::party_status,
+            party::party_record_line_score,
+            party::party_claim_player,
+            party::party_submit_guess,
+            party::party_reveal,
             // Viral / trending charts
             charts::commands::viral_refresh_charts,
             charts::commands::viral_match_library,
@@ -666,12 +994,113 @@ pub fn run() {
             charts::commands::viral_set_country,
             // Network
             network_get_local_ip,
+            server::discovery::get_connection_info,
+            server::remote_qr::generate_remote_qr,
+            server::security::regenerate_access_token,
+            remote::remote_status,
+            server::port::get_server_port,
+            server::server_status,
+            server::restart_server,
+            server::stop_server,
+            server::server_restart,
+            server::server_stop,
+            server::server_logs,
+            server::stats::get_server_stats,
+            // Singer rotation
+            queue::queue_list,
+            queue::queue_add,
+            queue::queue_remove,
+            queue::queue_reorder,
+            queue::queue_next,
+            // Performance history
+            history::get_history,
+            history::get_stats,
+            // Singer profiles
+            profiles::profile_list,
+            profiles::profile_get,
+            profiles::profile_create,
+            profiles::profile_update,
+            profiles::profile_delete,
+            // Clipboard watcher (opt-in quick adds)
+            clipboard_watch::clipboard_watch_set_enabled,
+            clipboard_watch::clipboard_watch_get_enabled,
+            clipboard_watch::clipboard_confirm_add,
+            // Video thumbnails
+            media::thumbnails::get_video_thumbnail,
+            // Transcoding of media the webview cannot play
+            media::transcode::prepare_media,
+            media::transcode::get_transcode_jobs,
+            // Native (mpv) video in the player window
+            media::native_video::get_native_video,
+            // External tool versions (yt-dlp, ffmpeg)
+            media::tools::get_tool_versions,
+            media::tools::check_tool_updates,
+            media::tools::update_tool,
+            media::tools::set_tool_channel,
+            // Online tracks (yt-dlp)
+            media::online::fetch_online_track,
+            media::online::cancel_online_fetch,
+            // Scheduled maintenance
+            scheduler::get_scheduled_tasks,
+            scheduler::set_scheduled_task,
+            scheduler::run_scheduled_task,
+            // Backup and restore of the whole setup
+            backup::create_backup,
+            backup::restore_backup,
+            interchange::export_data,
+            interchange::import_song_list,
+            // Logging
+            logging::set_log_level,
+            // App updates
+            updater::check_for_updates,
+            updater::install_update,
+            updater::restart_for_update,
+            // Start at login
+            autostart::get_autostart,
+            autostart::set_autostart,
+            // Bundled resources
+            installation::validate_installation,
+            installation::repair_installation,
+            // Crash reports
+            crash::get_crash_reports,
+            crash::export_crash_report,
+            // config.toml
+            config::get_config,
+            config::set_config,
+            desktop::hotkeys::set_hotkey,
+            // MIDI control surface
+            midi_control::list_midi_inputs,
+            midi_control::set_midi_device,
+            midi_control::set_midi_mapping,
+            // Party lighting
+            lighting::test_lighting_scene,
+            // Multi-room playback
+            multiroom::multiroom_status,
+            multiroom::find_rooms,
+            multiroom::set_multiroom_leader,
+            // Casting to a TV
+            cast::list_cast_targets,
+            cast::cast_to,
+            cast::stop_cast,
+            cast::cast_status,
+            // Usage metrics
+            telemetry::get_pending_telemetry,
         ])
-        .setup(|app| {
+        .setup(move |app| {
+            logging::init(app.handle());
+            crash::install(app.handle());
+            if let Some(native_dir) = &bundled_native_dir {
+                tracing::info!("[standalone] Added to PATH: {}", native_dir.display());
+            }
+            // Typed event bus; registered first so every subsystem can publish
+            app.manage(events::EventBus::new());
             // Register the audio state (dedicated audio thread uses Channel IPC)
             let audio_state = audio::commands::AudioState::new()
                 .map_err(|e| Box::new(std::io::Error::new(std::io::ErrorKind::Other, e)))?;
             app.manage(audio_state);
+            // Watch for audio devices being plugged in / removed
+            audio::hotplug::spawn_device_monitor(app.handle().clone())
+                .map_err(|e| Box::new(std::io::Error::new(std::io::ErrorKind::Other, e)))?;
             // Register the analysis state (needs AppHandle for the dedicated analysis thread)
             let analysis_state = audio::analysis_commands::AnalysisState::new()
                 .map_err(|e| Box::new(std::io::Error::new(std::io::ErrorKind::Other, e)))?;
@@ -679,182 +1108,395 @@ pub fn run() {
             // Register the SQLite offline database
             let db_path = db::default_db_path(&app.handle().clone())?;
             app.manage(db::DbState::new(db_path)?);
-            println!("SQLite database initialized at: {:?}", app.state::<db::DbState>().db_path);
+            tracing::info!("SQLite database initialized at: {:?}", app.state::<db::DbState>().db_path);
+            logging::apply_saved_level(app.handle());
+            app.manage(desktop::kiosk::KioskState::from_flags());
+            app.manage(desktop::hotkeys::HotkeyState::default());
+            app.manage(midi_control::MidiControlState::default());
+            let product_name = app.config().product_name.clone().unwrap_or_else(|| app.package_info().name.clone());
+            app.manage(lighting::LightingState::new(&product_name, &app.config().identifier));
+            app.manage(multiroom::MultiroomState::default());
+            app.manage(cast::CastState::default());
+            app.manage(telemetry::TelemetryState::default());
+            config::init(app.handle());
+            // Restore per-device channel routing now that settings are readable
+            if let Err(e) = app.state::<audio::commands::AudioState>().load_channel_maps(&app.state::<db::DbState>()) {
+                tracing::error!("[audio] Failed to load channel maps: {}", e);
+            }
+            if let Err(e) = app.state::<audio::commands::AudioState>().load_output_config(&app.state::<db::DbState>()) {
+                tracing::error!("[audio] Failed to load output config: {}", e);
+            }
+            if let Err(e) = app.state::<audio::commands::AudioState>().load_transition(&app.state::<db::DbState>()) {
+                tracing::error!("[audio] Failed to load transition setting: {}", e);
+            }
+            if let Ok(conn) = app.state::<db::DbState>().conn.lock() {
+                audio::rt_priority::load_setting(&conn);
+            }
+            // Background import worker (dialogs, forwarded files)
+            app.manage(library::import_queue::ImportQueue::new(app.handle().clone())?);
+            app.manage(library::commands::ScanState::default());
+            app.manage(library::data_migration::MigrationState::default());
+            app.manage(updater::UpdateState::default());
+            app.manage(cdg::CdgState::default());
+            app.manage(lyrics::LyricsState::default());
+            app.manage(audio::mic::MicState::default());
+            app.manage(audio::fingerprint::DuplicateState::default());
+            app.manage(scoring::ScoringState::default());
+            // Background ffmpeg frame grabs for video thumbnails
+            app.manage(media::thumbnails::ThumbnailService::new(app.handle().clone())?);
+            // Background ffmpeg conversions of unplayable media
+            app.manage(media::transcode::TranscodeService::new(app.handle().clone())?);
+            app.manage(media::native_video::NativeVideoState::default());
+            app.manage(media::tools::ToolsState::default());
+            app.manage(media::online::OnlineState::default());
+            // Opt-in clipboard watcher for quick YouTube adds
+            app.manage(clipboard_watch::ClipboardWatchState::default());
+            if let Err(e) = clipboard_watch::spawn_clipboard_watch(app.handle().clone()) {
+                tracing::warn!("[clipboard] {}", e);
+            }
+            // Periodic maintenance (rescans, cache pruning, backups, logs)
+            app.manage(runtime::TaskSupervisor::new());
+            // `--library` and `--autoplay-queue` from a venue's startup script
+            automation::apply(app.handle());
+            app.manage(downloads::DownloadState::default());
+            app.manage(jobs::JobQueue::default());
+            app.manage(scheduler::SchedulerState::default());
+            app.manage(watch_party::WatchPartyState::new());
+            app.manage(watch_party::relay::RelayServerState::default());
+            app.manage(party::PartyState::default());
+            app.manage(remote::RemoteState::default());
+            app.manage(server::ServerManager::default());
+            app.manage(server::stats::ServerStatsState::default());
+            app.manage(desktop::splash::SplashState::default());
+            // The bundled UI shell replaces the splash when there is one
+            if !server::shell::open(app.handle()) {
+                desktop::splash::show(app.handle());
+            }
+            desktop::tray::install(app.handle());
+            autostart::refresh(app.handle());
+            scheduler::spawn_scheduler(app.handle().clone());
+            config::spawn_watcher(app.handle().clone());
+            library::watcher::spawn_watcher(app.handle().clone());
+            downloads::spawn_worker(app.handle().clone());
+            jobs::spawn_workers(app.handle().clone());
+            audio::position::spawn_position_publisher(app.handle().clone());
+            desktop::power::spawn_inhibitor(app.handle().clone());
+            midi_control::spawn_listener(app.handle().clone());
+            lighting::spawn_cues(app.handle().clone());
+            multiroom::spawn_cue_publisher(app.handle().clone());
+            server::discovery::spawn_advertiser(app.handle().clone());
+            server::shell::spawn_status_watch(app.handle().clone());
+            automation::spawn_player_display(app.handle().clone());
+            remote::spawn_server(app.handle().clone());
+            telemetry::spawn_reporter(app.handle().clone());
+
+            // karaoke:// links: a link that starts the app arrives in argv,
+            // except on macOS, where the OS sends open-URL events
+            #[cfg(any(windows, target_os = "linux"))]
+            if let Err(e) = app.deep_link().register_all() {
+                tracing::warn!("[launch] Failed to register the karaoke:// scheme: {}", e);
+            }
+            #[cfg(target_os = "macos")]
+            {
+                let handle = app.handle().clone();
+                app.deep_link().on_open_url(move |event| {
+                    launch::open_urls(&handle, event.urls().into_iter().map(|url| url.to_string()));
+                });
+            }
+            launch::handle_startup(app.handle());
 
             // Get the main window and open DevTools (debug builds only)
             #[cfg(debug_assertions)]
-            if let Some(window) = app.handle().get_webview_window("main") {
+            if let Some(window) = app.handle().get_webview_window("main").filter(|_| !desktop::kiosk::is_active(app.handle())) {
                 let _ = window.open_devtools();
             }
             
-            // Check if server is already running
-            if check_server_running() {
-                println!("Server already running on port 3000");
-                if let Some(window) = app.handle().get_webview_window("main") {
-                    let _ = window.eval("window.location.href = 'http://localhost:3000'");
-                    // Re-open DevTools after navigation (debug builds only; redirect may close them)
-                    #[cfg(debug_assertions)]
-                    let _ = window.open_devtools();
-                }
+            // Dev builds: `beforeDevCommand` already serves the UI on the
+            // dev port. Release builds never adopt whatever sits on 3000.
+            if cfg!(debug_assertions) && check_server_running() {
+                tracing::info!("Server already running on port {}", server::port::current());
+                open_server_ui(app.handle());
                 return Ok(());
             }
             
             let handle = app.handle().clone();
             
             // Start server in background
-            thread::spawn(move || {
-                thread::sleep(Duration::from_millis(500));
-                
+            let supervisor = app.state::<runtime::TaskSupervisor>();
+            supervisor.spawn("server-start", move |token| async move {
                 // Get resource directory
                 let resource_dir = handle.path().resource_dir();
-                println!("Resource directory: {:?}", resource_dir);
+                tracing::info!("Resource directory: {:?}", resource_dir);
                 
                 if let Err(ref e) = resource_dir {
-                    println!("Error getting resource directory: {:?}", e);
+                    tracing::error!("Error getting resource directory: {:?}", e);
                 }
-                
-                let mut server_started = false;
-                
-                // Try bundled server with any available runtime (bundled node > system node > system bun)
-                if let Ok(ref res_dir) = resource_dir {
-                    if let Some(server_path) = get_server_path(res_dir) {
-                        let cwd = get_server_cwd(&server_path);
-                        
-                        // Find the best available runtime
-                        let runtime = find_node_runtime(res_dir);
-                        
-                        if let Some(node) = runtime {
-                            println!("Starting server with runtime...");
-                            println!("Runtime: {:?}", node);
-                            println!("Server: {:?}", server_path);
-                            println!("Working dir: {:?}", cwd);
-                            
-                            let result = Command::new(&node)
-                                .arg(&server_path)
-                                .current_dir(&cwd)
-                                .env("PORT", "3000")
-                                .env("HOSTNAME", "0.0.0.0")
-                                .env("NODE_ENV", "production")
-                                .spawn();
-                            
-                            match result {
-                                Ok(child) => {
-                                    if let Ok(mut proc) = SERVER_PROCESS.lock() {
-                                        *proc = Some(child);
+
+                let preferred_port = cli::gui_flags().port.unwrap_or(config::current(&handle).server.preferred_port);
+                // Find the runtime config.toml asks for (see server::runtime)
+                let choice = config::current(&handle).server.runtime;
+                let app_version = handle.package_info().version.to_string();
+                // How the last good launch went, if that still holds (see
+                // server::launch_plan)
+                let config_dir = handle.path().app_config_dir().ok();
+                let cached_plan = match (config_dir.clone(), resource_dir.as_ref().ok().cloned()) {
+                    (Some(config_dir), Some(res_dir)) => {
+                        let app_version = app_version.clone();
+                        // Reads the plan and stats the files it names
+                        tauri::async_runtime::spawn_blocking(move || {
+                            server::launch_plan::load(&config_dir).filter(|plan| {
+                                match plan.stale_reason(&app_version, choice, preferred_port, &res_dir) {
+                                    None => true,
+                                    Some(reason) => {
+                                        tracing::info!("Cached launch plan is stale ({}) — discovering the server", reason);
+                                        server::launch_plan::clear(&config_dir);
+                                        false
                                     }
-                                    server_started = true;
-                                    println!("Server process started successfully");
-                                }
-                                Err(e) => {
-                                    println!("Failed to start server: {:?}", e);
                                 }
-                            }
-                        } else {
-                            println!("No Node.js/Bun runtime found — bundled server available but no runtime");
-                        }
-                    } else {
-                        println!("Server not found in bundled resources");
+                            })
+                        })
+                        .await
+                        .unwrap_or(None)
                     }
+                    _ => None,
+                };
+                // The plan for this launch, saved once the server is ready
+                let mut launched_plan: Option<server::launch_plan::LaunchPlan> = None;
+                // Full discovery keeps its initial delay; a valid plan goes straight on
+                if cached_plan.is_none() && !runtime::sleep_or_cancel(&token, Duration::from_millis(500)).await {
+                    return;
                 }
                 
-                // Fallback: Try system Node.js
-                if !server_started {
-                    println!("Trying system Node.js...");
-                    
-                    // Try to find server.js in common locations
-                    let cwd = env::current_dir().unwrap_or_default();
-                    let possible_servers = [
-                        cwd.join("server.js"),
-                        cwd.join("bundled").join("server").join("server.js"),
-                    ];
-                    
-                    for server in &possible_servers {
-                        if server.exists() {
-                            println!("Trying server at: {:?}", server);
-                            if let Some(parent) = server.parent() {
-                                if let Ok(child) = Command::new("node")
-                                    .arg(server)
-                                    .current_dir(parent)
-                                    .env("PORT", "3000")
-                                    .spawn()
-                                {
-                                    if let Ok(mut proc) = SERVER_PROCESS.lock() {
-                                        *proc = Some(child);
-                                    }
+                let manager = handle.state::<server::ServerManager>();
+                let mut server_started = false;
+                // Which of the paths below got the server up, for `telemetry`
+                let mut start_path = "none";
+                let mut start_runtime: Option<&'static str> = None;
+                let mut bundled_runtime = false;
+                // Memory / priority limits from settings
+                let limits = server::limits::ServerLimits::load(&handle);
+
+                // A `--port` from the startup script is that port or nothing:
+                // companions were set up for it
+                if let Some(port) = cli::gui_flags().port.filter(|&port| !server::port::port_free(port)) {
+                    let reason = format!("Port {} given with --port is in use", port);
+                    tracing::error!("{}", reason);
+                    desktop::splash::fail(&handle, reason.clone());
+                    events::publish(&handle, events::AppEvent::ServerError { reason });
+                    return;
+                }
+                // Always from the preferred port: the one the last launch fell
+                // back to is not ours to keep
+                let port = match server::port::pick_free_port(preferred_port) {
+                    Ok(port) => port,
+                    Err(e) => {
+                        tracing::info!("{} — falling back to {}", e, preferred_port);
+                        preferred_port
+                    }
+                };
+                server::port::set(port);
+                if port != preferred_port {
+                    tracing::info!("Port {} is in use, server will listen on {}", preferred_port, port);
+                }
+                let port_env = port.to_string();
+                desktop::splash::set_phase(&handle, format!("Starting server on port {}…", port));
+                events::publish(&handle, events::AppEvent::ServerStarting { port });
+                // Last spawn failure, reported if no attempt succeeds
+                let mut start_error: Option<String> = None;
+
+                // Never spawn a second server next to one another instance
+                // (or a previous crashed run that is still starting) manages
+                match handle.path().app_data_dir() {
+                    // Reads the lock file and checks its processes
+                    Ok(data_dir) => match tauri::async_runtime::spawn_blocking(move || server::lock::acquire(&data_dir, port))
+                        .await
+                        .map_err(|e| e.to_string())
+                        .and_then(|acquired| acquired)
+                    {
+                        Ok(server::lock::AcquireResult::Held(info)) => {
+                            // A server still booting is unreachable; anything
+                            // else on its port means the lock is lying
+                            match server::health::probe(info.port, info.instance.as_deref()).await {
+                                server::health::Identity::Foreign(reason) => {
+                                    tracing::info!("Server lock held by PID {}, but port {} is {} — spawning our own", info.owner_pid, info.port, reason);
+                                }
+                                _ => {
+                                    tracing::info!(
+                                        "Server lock held by PID {} (server PID {}, port {}) — not spawning",
+                                        info.owner_pid, info.server_pid, info.port
+                                    );
+                                    manager.adopt_external(info.server_pid, info.port, info.instance.clone());
                                     server_started = true;
-                                    break;
+                                    start_path = "external";
                                 }
                             }
                         }
-                    }
+                        Ok(server::lock::AcquireResult::Acquired) => {}
+                        Err(e) => tracing::warn!("Server lock unavailable, continuing without: {}", e),
+                    },
+                    Err(e) => tracing::error!("Error getting app data directory: {:?}", e),
                 }
                 
-                // Fallback: Try bun/npm in current directory
                 if !server_started {
-                    let current_dir = env::current_dir().unwrap_or_default();
-                    if current_dir.join("package.json").exists() {
-                        println!("Trying bun/npm run dev...");
-                        
-                        let result = Command::new("bun")
-                            .args(["run", "dev"])
-                            .env("PORT", "3000")
-                            .env("HOSTNAME", "0.0.0.0")
-                            .current_dir(&current_dir)
-                            .spawn()
-                            .or_else(|_| {
-                                Command::new("npm")
-                                    .args(["run", "dev"])
-                                    .env("PORT", "3000")
-                                    .env("HOSTNAME", "0.0.0.0")
-                                    .current_dir(&current_dir)
-                                    .spawn()
-                            });
-                        
-                        if let Ok(child) = result {
-                            if let Ok(mut proc) = SERVER_PROCESS.lock() {
-                                *proc = Some(child);
-                            }
-                            server_started = true;
+                    let (handle, resource_dir, config_dir, cached_plan) =
+                        (handle.clone(), resource_dir.as_ref().ok().cloned(), config_dir.clone(), cached_plan.clone());
+                    let (app_version, port_env, limits) = (app_version.clone(), port_env.clone(), limits.clone());
+                    let attempt = tauri::async_runtime::spawn_blocking(move || {
+                        launch_server(
+                            &handle,
+                            resource_dir.as_ref(),
+                            config_dir.as_deref(),
+                            cached_plan.as_ref(),
+                            choice,
+                            &app_version,
+                            preferred_port,
+                            &port_env,
+                            &limits,
+                        )
+                    })
+                    .await;
+                    match attempt {
+                        Ok(attempt) => {
+                            server_started = attempt.started;
+                            start_path = attempt.path;
+                            start_runtime = attempt.runtime;
+                            bundled_runtime = attempt.bundled_runtime;
+                            launched_plan = attempt.plan;
+                            start_error = attempt.error;
                         }
+                        Err(e) => start_error = Some(format!("Server launch failed: {}", e)),
                     }
                 }
-                
+
                 // Wait for server to be ready
-                if server_started {
-                    println!("Waiting for server to be ready...");
-                    for i in 0..120 {
-                        if check_server_running() {
-                            println!("Server is ready after {} attempts!", i);
-                            
-                            if let Some(window) = handle.get_webview_window("main") {
-                                let _ = window.eval("window.location.href = 'http://localhost:3000'");
-                                // Re-open DevTools after navigation (debug builds only)
-                                #[cfg(debug_assertions)]
-                                let _ = window.open_devtools();
-                            }
-                            return;
+                let outcome = if server_started {
+                    server::limits::spawn_rss_watchdog(handle.clone(), limits.clone());
+                    server::watchdog::spawn_watchdog(handle.clone());
+                    tracing::info!("Waiting for server to be ready...");
+                    desktop::splash::set_phase(&handle, "Waiting for the server…");
+                    match server::wait_until_ready(&handle, &token).await {
+                        server::Readiness::Ready => {
+                            open_server_ui(&handle);
+                            "ready"
+                        }
+                        server::Readiness::Exited(reason) => {
+                            desktop::splash::fail(&handle, format!("The server stopped: {}", reason));
+                            events::publish(&handle, events::AppEvent::ServerError { reason });
+                            "exited"
+                        }
+                        server::Readiness::Foreign(reason) => {
+                            let reason = format!("Port {} belongs to another service ({})", server::port::current(), reason);
+                            desktop::splash::fail(&handle, reason.clone());
+                            events::publish(&handle, events::AppEvent::ServerError { reason });
+                            "foreign"
                         }
-                        thread::sleep(Duration::from_millis(500));
+                        server::Readiness::TimedOut { seconds, port_open } => {
+                            let message = if port_open {
+                                format!("The server is listening but did not become ready within {} s", seconds)
+                            } else {
+                                format!("The server did not open its port within {} s", seconds)
+                            };
+                            desktop::splash::fail(&handle, message);
+                            events::publish(&handle, events::AppEvent::ServerTimeout { seconds, port_open });
+                            "timed_out"
+                        }
+                        server::Readiness::Cancelled => return,
                     }
-                    println!("Server startup timeout after 60 seconds");
                 } else {
-                    println!("Could not start server - no Node.js or bun found");
+                    tracing::error!("Could not start server - no Node.js or bun found");
+                    // A damaged install explains more than the spawn error it causes
+                    let reason = resource_dir
+                        .as_ref()
+                        .ok()
+                        .and_then(|dir| installation::problem_summary(dir, &app_version))
+                        .or(start_error)
+                        .unwrap_or_else(|| "No Node.js or Bun runtime found".to_string());
+                    desktop::splash::fail(&handle, reason.clone());
+                    events::publish(&handle, events::AppEvent::ServerError { reason });
+                    "not_started"
+                };
+                // Only a launch that became ready is worth repeating
+                if let Some(config_dir) = config_dir {
+                    let keep = match (outcome, launched_plan) {
+                        ("ready", Some(plan)) if cached_plan.as_ref() != Some(&plan) => Some(Some(plan)),
+                        ("ready", _) => None,
+                        _ => Some(None),
+                    };
+                    if let Some(plan) = keep {
+                        let _ = tauri::async_runtime::spawn_blocking(move || match plan {
+                            Some(plan) => {
+                                if let Err(e) = server::launch_plan::save(&config_dir, &plan) {
+                                    tracing::warn!("[server] {}", e);
+                                }
+                            }
+                            None => server::launch_plan::clear(&config_dir),
+                        })
+                        .await;
+                    }
                 }
+                telemetry::record(&handle, telemetry::Metric::Startup {
+                    startup_ms: telemetry::since_launch(&handle).as_millis() as u64,
+                    path: start_path.to_string(),
+                    runtime: start_runtime.map(str::to_string),
+                    bundled_runtime,
+                    port_fallback: port != preferred_port,
+                    outcome: outcome.to_string(),
+                });
             });
             
             Ok(())
         })
-        .on_window_event(|_window, event| {
-            if let tauri::WindowEvent::CloseRequested { .. } = event {
+        .on_window_event(|window, event| {
+            // Files dragged onto the window; kiosk guests cannot import
+            if let tauri::WindowEvent::DragDrop(tauri::DragDropEvent::Drop { paths, .. }) = event {
+                if window.label() == "main" && !paths.is_empty() && !desktop::kiosk::is_active(window.app_handle()) {
+                    library::drop_import::on_drop(window.app_handle(), paths.clone());
+                }
+            }
+            if let tauri::WindowEvent::Focused(focused) = event {
+                if window.label() == "main" {
+                    if let Some(state) = window.app_handle().try_state::<clipboard_watch::ClipboardWatchState>() {
+                        state.set_focused(*focused);
+                    }
+                }
+            }
+            // The native picture lives in the player window
+            if window.label() == desktop::player_window::PLAYER_LABEL {
+                match event {
+                    tauri::WindowEvent::Destroyed => media::native_video::close(window.app_handle()),
+                    tauri::WindowEvent::Moved(_) | tauri::WindowEvent::Resized(_) => {
+                        media::native_video::placed(window.app_handle())
+                    }
+                    _ => {}
+                }
+            }
+            if let tauri::WindowEvent::CloseRequested { api, .. } = event {
+                // Kiosk mode: the main window stays (Alt+F4 included)
+                if window.label() == "main" && desktop::kiosk::is_active(window.app_handle()) {
+                    api.prevent_close();
+                    return;
+                }
                 // Kill server process when window is closed
-                if let Ok(mut proc) = SERVER_PROCESS.lock() {
-                    if let Some(ref mut child) = *proc {
-                        let _ = child.kill();
-                        println!("Server process killed");
+                if window.label() == "main" {
+                    shutdown_background(window.app_handle());
+                    // The player window alone would keep the app running
+                    if let Some(player) = window.app_handle().get_webview_window(desktop::player_window::PLAYER_LABEL) {
+                        let _ = player.destroy();
                     }
                 }
+                // Closing the splash before startup finished quits the app
+                if window.label() == desktop::splash::SPLASH_LABEL && desktop::splash::is_startup_abort(window.app_handle()) {
+                    window.app_handle().exit(0);
+                }
             }
         })
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            // Every way out (window, tray, menu, `app.exit`) passes through
+            // ExitRequested; Exit is the last chance. Shutdown is idempotent.
+            if let tauri::RunEvent::ExitRequested { .. } | tauri::RunEvent::Exit = event {
+                shutdown_background(app);
+            }
+        });
 }
@@ -0,0 +1,312 @@
+//! Karaoke tracks inside zip archives (MP3+G bundles).
+//!
+//! Commercial tracks usually ship as `Artist - Title.zip` holding an `.mp3`
+//! and a `.cdg` with the same stem; disc packs hold many such pairs. The
+//! scanner indexes every pair from the archive's central directory without
+//! extracting anything.
+//!
+//! Playback reads members in place: `member_path` builds
+//! `<archive>.zip!/<member>`, which the audio decoder opens through
+//! `open_member`. Stored members (the usual case — MP3s do not compress)
+//! are a seekable window into the archive file; deflated ones are inflated
+//! into memory. Consumers that need a real file (ffmpeg, the webview) call
+//! `extract_track`, which copies the member once into the app cache.
+
+use std::fs::File;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use tauri::{AppHandle, Manager};
+use zip::{CompressionMethod, ZipArchive};
+
+use super::formats::CDG_AUDIO_EXTENSIONS;
+use super::scanner::{fnv1a64, has_extension};
+use crate::access::{require_webview, Capability};
+use crate::paths::{long_path, nfc};
+
+/// Between the archive path and the member name in a member path.
+pub const MEMBER_SEPARATOR: &str = "!/";
+const EXTRACT_SUBDIR: &str = "archives";
+/// Deflated members are inflated into memory; refuse anything larger.
+const MAX_INFLATED_SIZE: u64 = 512 * 1024 * 1024;
+
+/// One song inside an archive: member names of its audio and graphics.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchiveTrack {
+    pub audio: String,
+    pub cdg: String,
+}
+
+fn member_stem(name: &str) -> &str {
+    let file = name.rsplit('/').next().unwrap_or(name);
+    file.rsplit_once('.').map_or(file, |(stem, _)| stem)
+}
+
+fn member_dir(name: &str) -> &str {
+    name.rsplit_once('/').map_or("", |(dir, _)| dir)
+}
+
+/// Pair every `.cdg` member with the audio member of the same folder and
+/// stem. macOS resource forks (`__MACOSX/`) are ignored.
+pub fn pair_members(names: &[String]) -> Vec<ArchiveTrack> {
+    let names: Vec<&String> = names.iter().filter(|n| !n.starts_with("__MACOSX/") && !n.ends_with('/')).collect();
+    let mut tracks: Vec<ArchiveTrack> = names
+        .iter()
+        .filter(|n| has_extension(Path::new(n.as_str()), &["cdg"]))
+        .filter_map(|cdg| {
+            let audio = names.iter().find(|a| {
+                has_extension(Path::new(a.as_str()), CDG_AUDIO_EXTENSIONS)
+                    && member_dir(a) == member_dir(cdg)
+                    && member_stem(a).eq_ignore_ascii_case(member_stem(cdg))
+            })?;
+            Some(ArchiveTrack { audio: audio.to_string(), cdg: cdg.to_string() })
+        })
+        .collect();
+    tracks.sort_by(|a, b| a.cdg.cmp(&b.cdg));
+    tracks
+}
+
+fn open_archive(zip: &Path) -> Result<ZipArchive<File>, String> {
+    let file = File::open(long_path(zip)).map_err(|e| format!("Failed to open {}: {}", zip.display(), e))?;
+    ZipArchive::new(file).map_err(|e| format!("Not a readable zip archive {}: {}", zip.display(), e))
+}
+
+/// Tracks in `zip`, from its central directory only.
+pub fn list_tracks(zip: &Path) -> Result<Vec<ArchiveTrack>, String> {
+    let archive = open_archive(zip)?;
+    let names: Vec<String> = archive.file_names().map(str::to_string).collect();
+    Ok(pair_members(&names))
+}
+
+/// `<zip>!/<member>`, the path the audio decoder accepts for archive members.
+pub fn member_path(zip: &Path, member: &str) -> String {
+    format!("{}{}{}", zip.to_string_lossy(), MEMBER_SEPARATOR, member)
+}
+
+/// Split a member path into archive and member; `None` for plain paths.
+pub fn split_member_path(path: &str) -> Option<(PathBuf, String)> {
+    path.match_indices(MEMBER_SEPARATOR).find_map(|(at, _)| {
+        let zip = Path::new(&path[..at]);
+        let member = &path[at + MEMBER_SEPARATOR.len()..];
+        (has_extension(zip, &["zip"]) && !member.is_empty()).then(|| (zip.to_path_buf(), member.to_string()))
+    })
+}
+
+/// Seekable reader over one archive member.
+pub enum MemberReader {
+    /// Uncompressed member: a window into the archive file.
+    Stored { file: File, start: u64, len: u64, pos: u64 },
+    Inflated(Cursor<Vec<u8>>),
+}
+
+impl MemberReader {
+    pub fn len(&self) -> u64 {
+        match self {
+            Self::Stored { len, .. } => *len,
+            Self::Inflated(cursor) => cursor.get_ref().len() as u64,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Read for MemberReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Stored { file, start, len, pos } => {
+                let remaining = len.saturating_sub(*pos);
+                if remaining == 0 {
+                    return Ok(0);
+                }
+                let want = (buf.len() as u64).min(remaining) as usize;
+                file.seek(SeekFrom::Start(*start + *pos))?;
+                let n = file.read(&mut buf[..want])?;
+                *pos += n as u64;
+                Ok(n)
+            }
+            Self::Inflated(cursor) => cursor.read(buf),
+        }
+    }
+}
+
+impl Seek for MemberReader {
+    fn seek(&mut self, to: SeekFrom) -> std::io::Result<u64> {
+        match self {
+            Self::Stored { len, pos, .. } => {
+                let target = match to {
+                    SeekFrom::Start(n) => n as i64,
+                    SeekFrom::End(n) => *len as i64 + n,
+                    SeekFrom::Current(n) => *pos as i64 + n,
+                };
+                if target < 0 {
+                    return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "seek before start of member"));
+                }
+                *pos = target as u64;
+                Ok(*pos)
+            }
+            Self::Inflated(cursor) => cursor.seek(to),
+        }
+    }
+}
+
+/// Index of `member` in `archive`. Names are compared in NFC: archives
+/// made on macOS store NFD names, song entries keep NFC ones.
+fn member_index(archive: &ZipArchive<File>, member: &str) -> Option<usize> {
+    if let Some(index) = archive.index_for_name(member) {
+        return Some(index);
+    }
+    let wanted = nfc(member);
+    (0..archive.len()).find(|&i| archive.name_for_index(i).is_some_and(|name| nfc(name) == wanted))
+}
+
+/// Open `member` of `zip` for reading without extracting it.
+pub fn open_member(zip: &Path, member: &str) -> Result<MemberReader, String> {
+    let mut archive = open_archive(zip)?;
+    let index = member_index(&archive, member).ok_or_else(|| format!("{} has no member {}", zip.display(), member))?;
+    let mut entry = archive
+        .by_index(index)
+        .map_err(|e| format!("Failed to read {} from {}: {}", member, zip.display(), e))?;
+    if entry.compression() == CompressionMethod::Stored && !entry.encrypted() {
+        let (start, len) = (entry.data_start(), entry.size());
+        drop(entry);
+        let file = File::open(long_path(zip)).map_err(|e| format!("Failed to open {}: {}", zip.display(), e))?;
+        return Ok(MemberReader::Stored { file, start, len, pos: 0 });
+    }
+    if entry.size() > MAX_INFLATED_SIZE {
+        return Err(format!("{} in {} is too large to inflate ({} MB)", member, zip.display(), entry.size() >> 20));
+    }
+    let mut data = Vec::with_capacity(entry.size() as usize);
+    entry
+        .read_to_end(&mut data)
+        .map_err(|e| format!("Failed to read {} from {}: {}", member, zip.display(), e))?;
+    Ok(MemberReader::Inflated(Cursor::new(data)))
+}
+
+/// Copy `member` into `dir` (once; later calls reuse the copy). The copy
+/// is named after the member's file name as `enclosed_name` sanitizes it,
+/// so no member name (`../..`, an absolute path) can write outside `dir`.
+pub fn extract_member(zip: &Path, member: &str, dir: &Path) -> Result<PathBuf, String> {
+    let file_name = {
+        let mut archive = open_archive(zip)?;
+        let index = member_index(&archive, member).ok_or_else(|| format!("{} has no member {}", zip.display(), member))?;
+        let entry = archive
+            .by_index(index)
+            .map_err(|e| format!("Failed to read {} from {}: {}", member, zip.display(), e))?;
+        entry
+            .enclosed_name()
+            .and_then(|path| path.file_name().map(|name| name.to_owned()))
+            .ok_or_else(|| format!("Unsafe member name {} in {}", member, zip.display()))?
+    };
+    let key = format!("{:016x}", fnv1a64(member_path(zip, member).as_bytes()));
+    let target_dir = dir.join(key);
+    let target = target_dir.join(file_name);
+
+    let mut reader = open_member(zip, member)?;
+    if std::fs::metadata(&target).is_ok_and(|m| m.len() == reader.len()) {
+        return Ok(target);
+    }
+    std::fs::create_dir_all(&target_dir).map_err(|e| format!("Failed to create {}: {}", target_dir.display(), e))?;
+    let tmp = target.with_extension("part");
+    let mut out = File::create(&tmp).map_err(|e| format!("Failed to create {}: {}", tmp.display(), e))?;
+    std::io::copy(&mut reader, &mut out).map_err(|e| format!("Failed to extract {}: {}", member, e))?;
+    drop(out);
+    std::fs::rename(&tmp, &target).map_err(|e| format!("Failed to extract {}: {}", member, e))?;
+    Ok(target)
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+/// Extract one member of a karaoke archive into the app cache and return
+/// its path, for players that need a plain file.
+#[tauri::command]
+pub async fn extract_track(app: AppHandle, webview: tauri::Webview, zip_path: String, member: String) -> Result<String, String> {
+    require_webview(&webview, Capability::ControlPlayback)?;
+    let dir = app
+        .path()
+        .app_cache_dir()
+        .map_err(|e| format!("No cache directory: {}", e))?
+        .join(EXTRACT_SUBDIR);
+    tauri::async_runtime::spawn_blocking(move || extract_member(Path::new(&zip_path), &member, &dir))
+        .await
+        .map_err(|e| e.to_string())?
+        .map(|path| path.to_string_lossy().to_string())
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn pairs_members_and_splits_member_paths() {
+        let names: Vec<String> = ["Disc 1/01 - A.cdg", "Disc 1/01 - A.MP3", "Disc 1/02 - B.cdg", "__MACOSX/Disc 1/01 - A.cdg", "02 - B.mp3"]
+            .map(String::from)
+            .to_vec();
+        assert_eq!(
+            pair_members(&names),
+            vec![ArchiveTrack { audio: "Disc 1/01 - A.MP3".into(), cdg: "Disc 1/01 - A.cdg".into() }]
+        );
+        let path = member_path(Path::new("/k/Hits!/pack.zip"), "Disc 1/01 - A.MP3");
+        assert_eq!(split_member_path(&path), Some((PathBuf::from("/k/Hits!/pack.zip"), "Disc 1/01 - A.MP3".into())));
+        assert_eq!(split_member_path("/k/song.mp3"), None);
+    }
+
+    #[test]
+    fn reads_stored_and_deflated_members_in_place() {
+        let zip_path = crate::paths::test_dir("archive").join("songs.zip");
+        {
+            let mut writer = zip::ZipWriter::new(File::create(&zip_path).unwrap());
+            for (name, method) in [("a.mp3", CompressionMethod::Stored), ("a.cdg", CompressionMethod::Deflated)] {
+                let options = zip::write::SimpleFileOptions::default().compression_method(method);
+                writer.start_file(name, options).unwrap();
+                writer.write_all(format!("{} contents", name).as_bytes()).unwrap();
+            }
+            writer.finish().unwrap();
+        }
+        let mut stored = open_member(&zip_path, "a.mp3").unwrap();
+        assert!(matches!(stored, MemberReader::Stored { .. }));
+        stored.seek(SeekFrom::Start(6)).unwrap();
+        let mut text = String::new();
+        stored.read_to_string(&mut text).unwrap();
+        let mut deflated = String::new();
+        open_member(&zip_path, "a.cdg").unwrap().read_to_string(&mut deflated).unwrap();
+        let tracks = list_tracks(&zip_path).unwrap();
+        let _ = std::fs::remove_file(&zip_path);
+
+        assert_eq!(text, "contents");
+        assert_eq!(deflated, "a.cdg contents");
+        assert_eq!(tracks, vec![ArchiveTrack { audio: "a.mp3".into(), cdg: "a.cdg".into() }]);
+    }
+
+    #[test]
+    fn extraction_stays_in_the_cache_and_matches_nfd_names() {
+        let dir = crate::paths::test_dir("archive-extract");
+        let zip_path = dir.join("songs.zip");
+        {
+            let mut writer = zip::ZipWriter::new(File::create(&zip_path).unwrap());
+            // "Björk.mp3" with a decomposed ö, as macOS writes it
+            for name in ["Bjo\u{308}rk.mp3", "../../escaped.mp3"] {
+                writer.start_file(name, zip::write::SimpleFileOptions::default()).unwrap();
+                writer.write_all(b"audio").unwrap();
+            }
+            writer.finish().unwrap();
+        }
+        let cache = dir.join("cache");
+        let extracted = extract_member(&zip_path, "Bj\u{f6}rk.mp3", &cache).unwrap();
+        let escaped = extract_member(&zip_path, "../../escaped.mp3", &cache);
+        let outside = dir.parent().unwrap().join("escaped.mp3").exists();
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert!(extracted.starts_with(&cache));
+        assert!(escaped.is_err());
+        assert!(!outside);
+    }
+}
@@ -0,0 +1,210 @@
+//! Cover art thumbnails in a content-addressed cache.
+//!
+//! Covers come from the song's cover file (UltraStar `#COVER`) or the
+//! picture embedded in its audio/video file. The source image is hashed,
+//! downscaled once to a `MASTER_SIZE` JPEG, and smaller sizes are derived
+//! from that master on demand:
+//!
+//!   `<app cache dir>/artwork/<sha256 of source>-<size>.jpg`
+//!
+//! Identical covers (every track of an album) share one set of files, and
+//! the song only records the hash (`artworkHash`). Scans fill the cache as
+//! they go when given `ScanOptions::artwork_dir`; `get_artwork` extracts
+//! lazily for songs scanned without it. Scans hand over the picture of the
+//! tags they read anyway (`Embedded`), so a media file is opened once.
+//! `get_artwork` returns a `cache://` URL (see `media::cache_scheme`), so
+//! full-size images never cross IPC.
+
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use image::codecs::jpeg::JpegEncoder;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Manager};
+
+use super::metadata;
+use crate::access::{require_webview, Capability};
+use crate::db::DbState;
+use crate::media::cache_scheme;
+use crate::paths::long_path;
+
+const CACHE_SUBDIR: &str = "artwork";
+/// Thumbnail edge lengths; requests are rounded up to the next one.
+const SIZES: &[u32] = &[64, 128, 256, 512];
+const MASTER_SIZE: u32 = 512;
+const JPEG_QUALITY: u8 = 85;
+
+/// Numbers temporary files, so two scans writing the same cover (another
+/// track of the album) never share one.
+static NEXT_TMP: AtomicU64 = AtomicU64::new(0);
+
+/// The embedded picture of a song, as far as its scan knows.
+pub enum Embedded {
+    /// The scan read the tags of the song's media: their picture, if any.
+    Read(Option<Vec<u8>>),
+    /// Tags not read yet; `cache_for_song` reads them itself.
+    Unread,
+}
+
+pub fn cache_dir(app: &AppHandle) -> Option<PathBuf> {
+    app.path().app_cache_dir().ok().map(|dir| dir.join(CACHE_SUBDIR))
+}
+
+fn bucket(size: u32) -> u32 {
+    SIZES.iter().copied().find(|&s| s >= size).unwrap_or(MASTER_SIZE)
+}
+
+fn thumbnail_path(dir: &Path, hash: &str, size: u32) -> PathBuf {
+    dir.join(format!("{}-{}.jpg", hash, size))
+}
+
+/// Raw cover image of a song entry: its cover file, else an embedded picture.
+fn source_image(song: &Value, embedded: Embedded) -> Option<Vec<u8>> {
+    let field = |key: &str| song.get(key).and_then(|v| v.as_str()).filter(|v| !v.is_empty());
+    let folder = PathBuf::from(field("folderPath")?);
+    if let Some(cover) = field("coverFileName") {
+        if let Ok(bytes) = std::fs::read(long_path(&folder.join(cover))) {
+            return Some(bytes);
+        }
+    }
+    if let Embedded::Read(picture) = embedded {
+        return picture;
+    }
+    ["audioFileName", "videoFileName"]
+        .iter()
+        .filter_map(|key| field(key))
+        .find_map(|media| metadata::read(&folder.join(media)).ok()?.artwork)
+        .map(|artwork| artwork.data)
+}
+
+fn encode_thumbnail(image: &image::DynamicImage, size: u32, target: &Path) -> Result<(), String> {
+    let mut jpeg = Vec::new();
+    let encoder = JpegEncoder::new_with_quality(Cursor::new(&mut jpeg), JPEG_QUALITY);
+    image
+        .thumbnail(size, size)
+        .to_rgb8()
+        .write_with_encoder(encoder)
+        .map_err(|e| format!("Failed to encode thumbnail: {}", e))?;
+    let tmp = target.with_extension(format!("{}-{}.tmp", std::process::id(), NEXT_TMP.fetch_add(1, Ordering::Relaxed)));
+    std::fs::write(&tmp, &jpeg).map_err(|e| format!("Failed to write {}: {}", tmp.display(), e))?;
+    std::fs::rename(&tmp, target).map_err(|e| {
+        let _ = std::fs::remove_file(&tmp);
+        format!("Failed to store thumbnail: {}", e)
+    })
+}
+
+/// Extract, hash and cache the cover of `song`, recording `artworkHash` in
+/// it. Returns the hash, `None` if the song has no cover.
+pub fn cache_for_song(dir: &Path, song: &mut Value, embedded: Embedded) -> Result<Option<String>, String> {
+    let Some(bytes) = source_image(song, embedded) else {
+        return Ok(None);
+    };
+    let hash = format!("{:x}", Sha256::digest(&bytes));
+    let master = thumbnail_path(dir, &hash, MASTER_SIZE);
+    if !master.is_file() {
+        let image = image::load_from_memory(&bytes).map_err(|e| format!("Unreadable cover image: {}", e))?;
+        std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create artwork cache: {}", e))?;
+        encode_thumbnail(&image, MASTER_SIZE, &master)?;
+    }
+    song["artworkHash"] = json!(hash);
+    Ok(Some(hash))
+}
+
+/// Path of the `size` thumbnail for `hash`, derived from the master if needed.
+fn sized(dir: &Path, hash: &str, size: u32) -> Result<Option<PathBuf>, String> {
+    let target = thumbnail_path(dir, hash, size);
+    if target.is_file() {
+        return Ok(Some(target));
+    }
+    let master = thumbnail_path(dir, hash, MASTER_SIZE);
+    let Ok(bytes) = std::fs::read(&master) else {
+        return Ok(None);
+    };
+    let image = image::load_from_memory(&bytes).map_err(|e| format!("Corrupt artwork cache entry: {}", e))?;
+    encode_thumbnail(&image, size, &target)?;
+    Ok(Some(target))
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+/// Cover thumbnail of `song_id` no smaller than `size` (default 256) as a
+/// `cache://` URL; `None` if the song has no cover.
+#[tauri::command]
+pub async fn get_artwork(
+    app: AppHandle,
+    webview: tauri::Webview,
+    song_id: String,
+    size: Option<u32>,
+) -> Result<Option<String>, String> {
+    require_webview(&webview, Capability::ManageLibrary)?;
+    let size = bucket(size.unwrap_or(256));
+    tauri::async_runtime::spawn_blocking(move || {
+        let dir = cache_dir(&app).ok_or("No cache directory")?;
+        let db = app.state::<DbState>();
+        let json: Option<String> = {
+            let conn = db.conn.lock().map_err(|e| e.to_string())?;
+            conn.query_row("SELECT json_data FROM songs WHERE id = ?1", [&song_id], |row| row.get(0))
+                .map_err(|e| format!("Song {} not found: {}", song_id, e))?
+        };
+        let mut song: Value = serde_json::from_str(&json.unwrap_or_default()).map_err(|e| e.to_string())?;
+
+        if let Some(hash) = song.get("artworkHash").and_then(|v| v.as_str()) {
+            if let Some(path) = sized(&dir, hash, size)? {
+                return Ok(cache_scheme::url_for(&app, &path));
+            }
+        }
+        // Not cached yet (or the cache was cleared): extract now and remember the hash
+        let Some(hash) = cache_for_song(&dir, &mut song, Embedded::Unread)? else {
+            return Ok(None);
+        };
+        {
+            let conn = db.conn.lock().map_err(|e| e.to_string())?;
+            conn.execute("UPDATE songs SET json_data = ?1 WHERE id = ?2", (song.to_string(), &song_id))
+                .map_err(|e| format!("Failed to save artwork hash: {}", e))?;
+        }
+        Ok(sized(&dir, &hash, size)?.and_then(|path| cache_scheme::url_for(&app, &path)))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn covers_are_shared_and_resized_from_the_master() {
+        let root = crate::paths::test_dir("artwork");
+        let songs = root.join("songs");
+        std::fs::create_dir_all(&songs).unwrap();
+        let mut png = Vec::new();
+        image::DynamicImage::new_rgb8(800, 600)
+            .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        std::fs::write(songs.join("cover.png"), &png).unwrap();
+
+        let dir = root.join("cache");
+        let song = || json!({ "folderPath": songs.to_string_lossy(), "coverFileName": "cover.png" });
+        let (mut a, mut b) = (song(), song());
+        let hash = cache_for_song(&dir, &mut a, Embedded::Unread).unwrap().unwrap();
+        assert_eq!(cache_for_song(&dir, &mut b, Embedded::Read(None)).unwrap(), Some(hash.clone()));
+        // Without a cover file only the picture handed over counts
+        let mut bare = json!({ "folderPath": songs.to_string_lossy(), "audioFileName": "missing.mp3" });
+        assert_eq!(cache_for_song(&dir, &mut bare, Embedded::Read(Some(png.clone()))).unwrap(), Some(hash.clone()));
+        assert_eq!(cache_for_song(&dir, &mut bare, Embedded::Read(None)).unwrap(), None);
+        assert_eq!(a["artworkHash"], hash);
+
+        let small = sized(&dir, &hash, bucket(100)).unwrap().unwrap();
+        let thumb = image::open(&small).unwrap();
+        let _ = std::fs::remove_dir_all(&root);
+        assert_eq!((thumb.width(), thumb.height()), (128, 96));
+    }
+}
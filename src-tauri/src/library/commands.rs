@@ -0,0 +1,235 @@
+//! Library commands for the frontend.
+//!
+//! `scan_library` walks folders on the parallel scan pool and streams what
+//! it finds as `library://scan-batch` events, so huge libraries fill the UI
+//! progressively, and upserts it into the `songs` table, so the next start
+//! needs no rescan; `cancel_scan` stops a running scan without losing the
+//! batches already saved. `search_songs`, `get_song` and `upsert_song` read
+//! and write that table directly; `search_songs_fast` is the typo-tolerant
+//! search-as-you-type over the full-text index.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use serde::Serialize;
+use serde_json::Value;
+use tauri::{AppHandle, Manager};
+use tokio_util::sync::CancellationToken;
+
+use super::artwork;
+use super::scan_pool::{self, ScanOptions};
+use super::scanner;
+use crate::access::{require_webview, Capability};
+use crate::db::search::{self, SongFilters};
+use crate::db::DbState;
+use crate::events::{publish, AppEvent};
+use crate::runtime::TaskSupervisor;
+
+/// Songs found by a running scan, one event per pool batch.
+pub const SCAN_BATCH_EVENT: &str = "library://scan-batch";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScanBatch {
+    pub scan_id: u64,
+    pub root: String,
+    pub songs: Vec<Value>,
+}
+
+/// Result of `scan_library`. The songs themselves went out as
+/// `library://scan-batch` events; holding them all again for the reply
+/// would cost an 80k-song library its memory twice over.
+#[derive(Debug, Default, Serialize)]
+pub struct ScanSummary {
+    pub scan_id: u64,
+    pub roots: Vec<String>,
+    pub songs_found: usize,
+    pub files_skipped: usize,
+    pub errors: Vec<String>,
+    pub cancelled: bool,
+}
+
+/// Managed state: cancellation tokens of running scans.
+#[derive(Default)]
+pub struct ScanState {
+    next_id: AtomicU64,
+    running: Mutex<HashMap<u64, CancellationToken>>,
+}
+
+impl ScanState {
+    fn register(&self, token: CancellationToken) -> Result<u64, String> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        self.running.lock().map_err(|e| e.to_string())?.insert(id, token);
+        Ok(id)
+    }
+
+    fn finish(&self, id: u64) {
+        if let Ok(mut running) = self.running.lock() {
+            running.remove(&id);
+        }
+    }
+}
+
+/// Walk `paths` for songs of every supported format and save them. Progress
+/// is published per root as `library://scan-progress` and songs as
+/// `library://scan-batch`; a summary is returned at the end.
+#[tauri::command]
+pub async fn scan_library(app: AppHandle, webview: tauri::Webview, paths: Vec<String>) -> Result<ScanSummary, String> {
+    require_webview(&webview, Capability::ManageLibrary)?;
+    if paths.is_empty() {
+        return Err("No folders to scan".to_string());
+    }
+    // Child of the supervisor: scans also stop when the app shuts down
+    let cancel = app.state::<TaskSupervisor>().token();
+    let scan_id = app.state::<ScanState>().register(cancel.clone())?;
+
+    let task_app = app.clone();
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        let app = task_app;
+        let mut index = ScanSummary { scan_id, ..ScanSummary::default() };
+        let options = ScanOptions {
+            cancel: Some(cancel),
+            artwork_dir: artwork::cache_dir(&app),
+            ..ScanOptions::default()
+        };
+        for root in paths {
+            if index.cancelled {
+                break;
+            }
+            let report = scan_pool::scan(
+                &PathBuf::from(&root),
+                &options,
+                |batch| {
+                    let n = {
+                        let db = app.state::<DbState>();
+                        let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+                        scanner::save_songs(&mut conn, &batch)?
+                    };
+                    index.songs_found += batch.len();
+                    publish(&app, AppEvent::ScanBatch(ScanBatch { scan_id, root: root.clone(), songs: batch }));
+                    Ok(n)
+                },
+                |progress| publish(&app, AppEvent::ScanProgress(progress.clone())),
+            );
+            match report {
+                Ok(report) => {
+                    index.files_skipped += report.files_skipped;
+                    index.errors.extend(report.errors);
+                    index.cancelled = report.cancelled;
+                }
+                // One unreadable root does not fail the others
+                Err(e) => index.errors.push(e),
+            }
+            index.roots.push(root);
+        }
+        tracing::info!(
+            "[library] Scan {} of {} folders: {} songs{}",
+            scan_id,
+            index.roots.len(),
+            index.songs_found,
+            if index.cancelled { " (cancelled)" } else { "" }
+        );
+        index
+    })
+    .await
+    .map_err(|e| format!("Library scan failed: {}", e));
+    app.state::<ScanState>().finish(scan_id);
+    result
+}
+
+/// Stop the scan `scan_id`, or every running scan. Returns how many were
+/// cancelled.
+#[tauri::command]
+pub fn cancel_scan(app: AppHandle, webview: tauri::Webview, scan_id: Option<u64>) -> Result<usize, String> {
+    require_webview(&webview, Capability::ManageLibrary)?;
+    let state = app.state::<ScanState>();
+    let running = state.running.lock().map_err(|e| e.to_string())?;
+    let mut cancelled = 0;
+    for (id, token) in running.iter() {
+        if scan_id.unwrap_or(*id) == *id {
+            token.cancel();
+            cancelled += 1;
+        }
+    }
+    Ok(cancelled)
+}
+
+fn parse_rows(rows: Vec<String>) -> Vec<Value> {
+    rows.iter()
+        .filter_map(|json| crate::try_log(serde_json::from_str(json), "search_songs JSON parse"))
+        .collect()
+}
+
+/// Library search: ranked full-text match on title/artist/album (all songs
+/// for an empty query) narrowed by `filters`. `limit` defaults to 100.
+#[tauri::command]
+pub fn search_songs(
+    app: AppHandle,
+    query: String,
+    filters: Option<SongFilters>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+) -> Result<Vec<Value>, String> {
+    let db = app.state::<DbState>();
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let rows = search::filtered_search(
+        &conn,
+        &query,
+        &filters.unwrap_or_default(),
+        limit.unwrap_or(100).clamp(1, 10_000),
+        offset.unwrap_or(0).max(0),
+    )?;
+    Ok(parse_rows(rows))
+}
+
+#[tauri::command]
+pub fn get_song(app: AppHandle, id: String) -> Result<Option<Value>, String> {
+    let db = app.state::<DbState>();
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let json: Option<String> = match conn.query_row("SELECT json_data FROM songs WHERE id = ?1", [&id], |row| row.get(0)) {
+        Ok(json) => json,
+        Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(None),
+        Err(e) => return Err(format!("get_song failed: {}", e)),
+    };
+    Ok(json.and_then(|json| crate::try_log(serde_json::from_str(&json), "get_song JSON parse")))
+}
+
+#[derive(Debug, Serialize)]
+pub struct FastSearchResult {
+    pub songs: Vec<Value>,
+    /// Some songs only matched after typo correction.
+    pub fuzzy: bool,
+    pub elapsed_ms: f64,
+}
+
+/// Ranked prefix search over title/artist/album that falls back to
+/// typo-tolerant matching. `limit` defaults to 50.
+#[tauri::command]
+pub fn search_songs_fast(app: AppHandle, query: String, limit: Option<i64>) -> Result<FastSearchResult, String> {
+    let started = std::time::Instant::now();
+    let db = app.state::<DbState>();
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let found = search::fast_search(&conn, &query, limit.unwrap_or(50).clamp(1, 1_000))?;
+    let songs = parse_rows(found.rows.into_iter().map(|(_, json)| json).collect());
+    Ok(FastSearchResult {
+        songs,
+        fuzzy: found.fuzzy,
+        elapsed_ms: started.elapsed().as_secs_f64() * 1000.0,
+    })
+}
+
+/// Insert or replace one song (frontend JSON shape). Returns its id.
+#[tauri::command]
+pub fn upsert_song(app: AppHandle, webview: tauri::Webview, song: Value) -> Result<String, String> {
+    require_webview(&webview, Capability::ManageLibrary)?;
+    let field = |key: &str| song.get(key).and_then(|v| v.as_str()).filter(|v| !v.trim().is_empty());
+    let id = field("id").ok_or("Song needs an id")?.to_string();
+    if field("title").is_none() || field("artist").is_none() {
+        return Err("Song needs a title and an artist".to_string());
+    }
+    let db = app.state::<DbState>();
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    crate::db::commands::upsert_song(&conn, &song)?;
+    Ok(id)
+}
@@ -0,0 +1,477 @@
+//! Moving the data directories to another drive.
+//!
+//! Recordings, downloads and stems (see `quota`) default to the app data
+//! directory, usually on the system drive, which they fill up over months
+//! of shows. `migrate_data_dir(new_path)` moves every one of them that has
+//! files to `<new_path>/<kind>`:
+//!
+//!   1. checks that the target directories are empty, that no download is
+//!      queued or running, and that the target drive has room;
+//!   2. copies each file, hashing it on the way, then reads the copy back
+//!      and compares SHA-256 digests;
+//!   3. in one transaction, points the `storage_dir:<kind>` settings at the
+//!      new directories and rewrites the paths of past downloads;
+//!   4. only then removes the originals, and moves over whatever was
+//!      written to the old directories meanwhile (a recording finished
+//!      during the copy), so nothing stays behind where the app no longer
+//!      looks.
+//!
+//! Any failure before step 3 deletes what was copied and leaves settings
+//! and files as they were. Progress is published as
+//! `storage://migration-progress`.
+
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Manager};
+
+use super::quota::{self, StorageKind, ALL_KINDS};
+use crate::access::{require_webview, Capability};
+use crate::db::DbState;
+use crate::events::{publish, AppEvent};
+use crate::paths::long_path;
+
+pub const PROGRESS_EVENT: &str = "storage://migration-progress";
+
+const CHUNK: usize = 1024 * 1024;
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+/// Room left on the target drive after the copy.
+const FREE_SPACE_MARGIN: u64 = 512 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MigrationPhase {
+    Copying,
+    Committing,
+    RemovingOriginals,
+    RolledBack,
+}
+
+/// Payload of `storage://migration-progress`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MigrationProgress {
+    pub phase: MigrationPhase,
+    pub files_done: usize,
+    pub files_total: usize,
+    pub bytes_done: u64,
+    pub bytes_total: u64,
+    pub current: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MovedDir {
+    pub kind: StorageKind,
+    pub from: String,
+    pub to: String,
+    pub files: usize,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MigrationReport {
+    pub moved: Vec<MovedDir>,
+    /// Originals that could not be removed after the move; safe to delete.
+    pub leftovers: Vec<String>,
+}
+
+/// Managed state: whether a migration is running.
+#[derive(Default)]
+pub struct MigrationState {
+    running: AtomicBool,
+}
+
+/// One directory to move.
+struct DirPlan {
+    kind: StorageKind,
+    from: PathBuf,
+    to: PathBuf,
+    files: Vec<(PathBuf, u64)>,
+}
+
+// ---------------------------------------------------------------------------
+// Planning
+// ---------------------------------------------------------------------------
+
+fn is_empty_dir(dir: &Path) -> bool {
+    fs::read_dir(long_path(dir)).map_or(true, |mut entries| entries.next().is_none())
+}
+
+/// Directories to move into `target`, and why that cannot be done.
+fn plan(current: &[(StorageKind, PathBuf)], target: &Path) -> Result<Vec<DirPlan>, String> {
+    let mut plans = Vec::new();
+    for (kind, from) in current {
+        let to = target.join(kind.as_str());
+        if to == *from || !long_path(from).is_dir() {
+            continue;
+        }
+        if to.starts_with(from) || from.starts_with(&to) {
+            return Err(format!("{} cannot be moved into itself ({})", from.display(), to.display()));
+        }
+        let files: Vec<(PathBuf, u64)> = quota::list_files(from)
+            .into_iter()
+            .map(|file| (PathBuf::from(file.path), file.bytes))
+            .collect();
+        if files.is_empty() {
+            continue;
+        }
+        if !is_empty_dir(&to) {
+            return Err(format!("{} already exists and is not empty", to.display()));
+        }
+        plans.push(DirPlan { kind: *kind, from: from.clone(), to, files });
+    }
+    Ok(plans)
+}
+
+/// Free bytes on the drive holding `path` (its nearest existing ancestor).
+fn available_space(path: &Path) -> Option<u64> {
+    let existing = path.ancestors().find(|p| p.exists())?;
+    let existing = fs::canonicalize(existing).unwrap_or_else(|_| existing.to_path_buf());
+    let disks = sysinfo::Disks::new_with_refreshed_list();
+    disks
+        .iter()
+        .filter(|disk| existing.starts_with(disk.mount_point()))
+        .max_by_key(|disk| disk.mount_point().as_os_str().len())
+        .map(|disk| disk.available_space())
+}
+
+fn downloads_busy(conn: &Connection) -> bool {
+    conn.query_row("SELECT COUNT(*) FROM downloads WHERE status IN ('queued', 'downloading')", [], |row| row.get::<_, i64>(0))
+        .is_ok_and(|n| n > 0)
+}
+
+// ---------------------------------------------------------------------------
+// Copy and commit
+// ---------------------------------------------------------------------------
+
+fn digest_of(path: &Path) -> std::io::Result<Vec<u8>> {
+    let mut file = File::open(long_path(path))?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; CHUNK];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            return Ok(hasher.finalize().to_vec());
+        }
+        hasher.update(&buf[..n]);
+    }
+}
+
+/// Copy `from` to `to`, then read the copy back and compare digests.
+/// `on_bytes` is called with each chunk's size.
+fn copy_verified(from: &Path, to: &Path, mut on_bytes: impl FnMut(u64)) -> Result<(), String> {
+    let mut source = File::open(long_path(from)).map_err(|e| format!("Failed to open {}: {}", from.display(), e))?;
+    let mut out = File::create(long_path(to)).map_err(|e| format!("Failed to create {}: {}", to.display(), e))?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; CHUNK];
+    loop {
+        let n = source.read(&mut buf).map_err(|e| format!("Failed to read {}: {}", from.display(), e))?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        out.write_all(&buf[..n]).map_err(|e| format!("Failed to write {}: {}", to.display(), e))?;
+        on_bytes(n as u64);
+    }
+    out.sync_all().map_err(|e| format!("Failed to write {}: {}", to.display(), e))?;
+    drop(out);
+
+    let copied = digest_of(to).map_err(|e| format!("Failed to verify {}: {}", to.display(), e))?;
+    if copied != hasher.finalize().to_vec() {
+        return Err(format!("Checksum mismatch after copying {}", from.display()));
+    }
+    if let Ok(modified) = fs::metadata(long_path(from)).and_then(|m| m.modified()) {
+        // Quota cleanup goes by age; keep it
+        let _ = File::options().write(true).open(long_path(to)).and_then(|f| f.set_modified(modified));
+    }
+    Ok(())
+}
+
+/// `path` with its `from` prefix replaced by `to`.
+fn rebase(path: &Path, from: &Path, to: &Path) -> Option<PathBuf> {
+    path.strip_prefix(from).ok().map(|rest| to.join(rest))
+}
+
+/// Point the settings at the new directories and rewrite download paths,
+/// all or nothing.
+fn commit(conn: &mut Connection, plans: &[DirPlan]) -> Result<(), String> {
+    let tx = conn.transaction().map_err(|e| format!("Transaction failed: {}", e))?;
+    for plan in plans {
+        tx.execute(
+            "INSERT OR REPLACE INTO app_settings (key, value) VALUES (?1, ?2)",
+            params![quota::dir_key(plan.kind), plan.to.to_string_lossy()],
+        )
+        .map_err(|e| format!("Failed to save {}: {}", quota::dir_key(plan.kind), e))?;
+    }
+    let downloads: Vec<(i64, String)> = {
+        let mut stmt = tx.prepare("SELECT id, dest_dir FROM downloads").map_err(|e| e.to_string())?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?))).map_err(|e| e.to_string())?;
+        rows.collect::<Result<_, _>>().map_err(|e| e.to_string())?
+    };
+    for (id, dest_dir) in downloads {
+        let moved = plans.iter().find_map(|plan| rebase(Path::new(&dest_dir), &plan.from, &plan.to));
+        if let Some(moved) = moved {
+            tx.execute("UPDATE downloads SET dest_dir = ?1 WHERE id = ?2", params![moved.to_string_lossy(), id])
+                .map_err(|e| format!("Failed to update download {}: {}", id, e))?;
+        }
+    }
+    tx.commit().map_err(|e| format!("Commit failed: {}", e))
+}
+
+/// Remove copied files and the directories created for them.
+fn roll_back(copied: &[PathBuf], created_dirs: &[PathBuf]) {
+    for file in copied {
+        let _ = fs::remove_file(long_path(file));
+    }
+    for dir in created_dirs.iter().rev() {
+        let _ = fs::remove_dir(long_path(dir));
+    }
+}
+
+/// Move files that appeared under `plan.from` after it was planned, each
+/// verified before its original goes. Returns the files and bytes moved
+/// and the files left where they were; `skip` are known leftovers.
+fn move_late_files(plan: &DirPlan, skip: &[String]) -> (usize, u64, Vec<String>) {
+    let (mut files, mut bytes, mut leftovers) = (0, 0, Vec::new());
+    for file in quota::list_files(&plan.from) {
+        if skip.contains(&file.path) {
+            continue;
+        }
+        let from = PathBuf::from(&file.path);
+        let Some(to) = rebase(&from, &plan.from, &plan.to) else { continue };
+        let moved = match to.parent().map(|parent| fs::create_dir_all(long_path(parent))) {
+            Some(Err(e)) => Err(format!("Failed to create {}: {}", to.display(), e)),
+            _ if long_path(&to).exists() => Err(format!("{} already exists", to.display())),
+            _ => copy_verified(&from, &to, |_| {})
+                .inspect_err(|_| {
+                    let _ = fs::remove_file(long_path(&to));
+                })
+                .and_then(|_| {
+                    fs::remove_file(long_path(&from)).map_err(|e| format!("Failed to remove {}: {}", from.display(), e))
+                }),
+        };
+        match moved {
+            Ok(()) => {
+                files += 1;
+                bytes += file.bytes;
+            }
+            Err(e) => {
+                tracing::warn!("[storage] Could not move {}: {}", from.display(), e);
+                leftovers.push(file.path);
+            }
+        }
+    }
+    (files, bytes, leftovers)
+}
+
+/// Empty directories left under `dir` after its files were removed.
+fn remove_empty_dirs(dir: &Path) {
+    if let Ok(entries) = fs::read_dir(long_path(dir)) {
+        for entry in entries.flatten().filter(|e| e.file_type().is_ok_and(|t| t.is_dir())) {
+            remove_empty_dirs(&entry.path());
+        }
+    }
+    let _ = fs::remove_dir(long_path(dir));
+}
+
+fn migrate(app: &AppHandle, target: &Path) -> Result<MigrationReport, String> {
+    let current: Vec<(StorageKind, PathBuf)> =
+        ALL_KINDS.iter().map(|&kind| quota::storage_dir(app, kind).map(|dir| (kind, dir))).collect::<Result<_, _>>()?;
+    {
+        let db = app.state::<DbState>();
+        let conn = db.conn.lock().map_err(|e| e.to_string())?;
+        if downloads_busy(&conn) {
+            return Err("Pause or finish the downloads before moving the data".to_string());
+        }
+    }
+    let plans = plan(&current, target)?;
+    let files_total: usize = plans.iter().map(|p| p.files.len()).sum();
+    let bytes_total: u64 = plans.iter().flat_map(|p| &p.files).map(|(_, bytes)| bytes).sum();
+    if let Some(free) = available_space(target) {
+        if free < bytes_total + FREE_SPACE_MARGIN {
+            return Err(format!(
+                "Not enough space on the target drive: {} MiB needed, {} MiB free",
+                (bytes_total + FREE_SPACE_MARGIN) / (1024 * 1024),
+                free / (1024 * 1024)
+            ));
+        }
+    }
+    tracing::info!("[storage] Moving {} files ({} bytes) to {}", files_total, bytes_total, target.display());
+
+    let mut progress = MigrationProgress {
+        phase: MigrationPhase::Copying,
+        files_done: 0,
+        files_total,
+        bytes_done: 0,
+        bytes_total,
+        current: None,
+    };
+    let mut reported = Instant::now();
+    let (mut copied, mut created_dirs) = (Vec::new(), Vec::new());
+    let mut result = Ok(());
+    'copy: for plan in &plans {
+        for (from, _) in &plan.files {
+            let Some(to) = rebase(from, &plan.from, &plan.to) else { continue };
+            if let Some(parent) = to.parent() {
+                let missing: Vec<PathBuf> = parent.ancestors().take_while(|d| !d.exists()).map(Path::to_path_buf).collect();
+                if let Err(e) = fs::create_dir_all(long_path(parent)) {
+                    result = Err(format!("Failed to create {}: {}", parent.display(), e));
+                    break 'copy;
+                }
+                created_dirs.extend(missing.into_iter().rev());
+            }
+            progress.current = Some(from.to_string_lossy().to_string());
+            let copy = copy_verified(from, &to, |bytes| {
+                progress.bytes_done += bytes;
+                if reported.elapsed() >= PROGRESS_INTERVAL {
+                    reported = Instant::now();
+                    publish(app, AppEvent::DataMigrationProgress(progress.clone()));
+                }
+            });
+            copied.push(to);
+            if let Err(e) = copy {
+                result = Err(e);
+                break 'copy;
+            }
+            progress.files_done += 1;
+        }
+    }
+
+    let committed = result.and_then(|_| {
+        progress.phase = MigrationPhase::Committing;
+        progress.current = None;
+        publish(app, AppEvent::DataMigrationProgress(progress.clone()));
+        let db = app.state::<DbState>();
+        let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+        commit(&mut conn, &plans)
+    });
+    if let Err(e) = committed {
+        tracing::error!("[storage] Migration failed, rolling back: {}", e);
+        roll_back(&copied, &created_dirs);
+        progress.phase = MigrationPhase::RolledBack;
+        publish(app, AppEvent::DataMigrationProgress(progress));
+        return Err(e);
+    }
+
+    progress.phase = MigrationPhase::RemovingOriginals;
+    publish(app, AppEvent::DataMigrationProgress(progress));
+    let mut leftovers = Vec::new();
+    let mut moved = Vec::new();
+    for plan in &plans {
+        let mut kept = Vec::new();
+        for (from, _) in &plan.files {
+            if let Err(e) = fs::remove_file(long_path(from)) {
+                tracing::warn!("[storage] Could not remove {}: {}", from.display(), e);
+                kept.push(from.to_string_lossy().to_string());
+            }
+        }
+        let (late_files, late_bytes, late_kept) = move_late_files(plan, &kept);
+        if late_files > 0 {
+            tracing::info!("[storage] Also moved {} files written to {} during the copy", late_files, plan.from.display());
+        }
+        remove_empty_dirs(&plan.from);
+        moved.push(MovedDir {
+            kind: plan.kind,
+            from: plan.from.to_string_lossy().to_string(),
+            to: plan.to.to_string_lossy().to_string(),
+            files: plan.files.len() + late_files,
+            bytes: plan.files.iter().map(|(_, bytes)| bytes).sum::<u64>() + late_bytes,
+        });
+        leftovers.extend(kept);
+        leftovers.extend(late_kept);
+    }
+    tracing::info!("[storage] Moved {} directories to {}", moved.len(), target.display());
+    Ok(MigrationReport { moved, leftovers })
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+/// Move the recordings, downloads and stems directories under `new_path`.
+/// Directories already there, or without files, are left alone.
+#[tauri::command]
+pub async fn migrate_data_dir(app: AppHandle, webview: tauri::Webview, new_path: String) -> Result<MigrationReport, String> {
+    require_webview(&webview, Capability::ChangeSettings)?;
+    let target = PathBuf::from(new_path.trim());
+    if !target.is_absolute() {
+        return Err(format!("Not an absolute path: {}", new_path));
+    }
+    if app.state::<MigrationState>().running.swap(true, Ordering::AcqRel) {
+        return Err("A data migration is already running".to_string());
+    }
+    let job_app = app.clone();
+    let result = tauri::async_runtime::spawn_blocking(move || migrate(&job_app, &target))
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|r| r);
+    app.state::<MigrationState>().running.store(false, Ordering::Release);
+    result
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plans_copies_and_commits_a_move() {
+        let dir = crate::paths::test_dir("migration");
+        let (old, new) = (dir.join("old"), dir.join("new"));
+        let recordings = old.join("recordings");
+        fs::create_dir_all(recordings.join("2026")).unwrap();
+        fs::write(recordings.join("2026").join("take.wav"), b"take").unwrap();
+        fs::create_dir_all(old.join("stems")).unwrap();
+
+        let current = [(StorageKind::Recordings, recordings.clone()), (StorageKind::Stems, old.join("stems"))];
+        let plans = plan(&current, &new).unwrap();
+        // Empty directories are not moved
+        assert_eq!(plans.len(), 1);
+        assert_eq!(plans[0].to, new.join("recordings"));
+        assert!(plan(&current, &recordings.join("2026")).is_err());
+
+        let from = recordings.join("2026").join("take.wav");
+        let to = rebase(&from, &plans[0].from, &plans[0].to).unwrap();
+        fs::create_dir_all(to.parent().unwrap()).unwrap();
+        let mut bytes = 0;
+        copy_verified(&from, &to, |n| bytes += n).unwrap();
+        assert_eq!((fs::read(&to).unwrap(), bytes), (b"take".to_vec(), 4));
+
+        let mut conn = crate::db::test_conn();
+        conn.execute(
+            "INSERT INTO downloads (url, dest_dir, import, created_at, updated_at) VALUES ('https://x/a.wav', ?1, 0, 0, 0)",
+            [recordings.join("2026").to_string_lossy()],
+        )
+        .unwrap();
+        commit(&mut conn, &plans).unwrap();
+        let saved: String = conn
+            .query_row("SELECT value FROM app_settings WHERE key = ?1", [quota::dir_key(StorageKind::Recordings)], |r| r.get(0))
+            .unwrap();
+        assert_eq!(PathBuf::from(saved), new.join("recordings"));
+        let dest: String = conn.query_row("SELECT dest_dir FROM downloads", [], |r| r.get(0)).unwrap();
+        assert_eq!(PathBuf::from(dest), new.join("recordings").join("2026"));
+
+        // A take saved during the copy follows the rest
+        fs::remove_file(&from).unwrap();
+        let late = recordings.join("late").join("encore.wav");
+        fs::create_dir_all(late.parent().unwrap()).unwrap();
+        fs::write(&late, b"encore").unwrap();
+        assert_eq!(move_late_files(&plans[0], &[]), (1, 6, Vec::new()));
+        assert_eq!(fs::read(new.join("recordings").join("late").join("encore.wav")).unwrap(), b"encore");
+        assert!(!late.exists());
+
+        let _ = fs::remove_dir_all(&old);
+        let _ = fs::remove_dir_all(&new);
+    }
+}
@@ -0,0 +1,385 @@
+//! Deleting songs from the library, with undo.
+//!
+//! With "also delete files" the song's files are moved to the OS recycle
+//! bin / trash (never deleted permanently). Every deletion is recorded as a
+//! batch in `deleted_songs_journal` — the song's JSON plus the original file
+//! paths — so `restore_last_deleted` can put both the library entries and
+//! the files back.
+//!
+//! Restoring files from the trash is supported on Windows and Linux
+//! (freedesktop trash). macOS offers no API for it; there the entries are
+//! restored and the files have to be put back from the Trash by hand.
+//! Where it is supported, a batch whose files could not be put back stays
+//! in the journal (its entries restored already), so undoing again retries
+//! the files instead of losing track of them.
+
+use std::path::{Path, PathBuf};
+
+use rusqlite::{Connection, OptionalExtension};
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+use crate::access::{require_webview, Capability};
+use crate::db::commands::upsert_song;
+use crate::db::DbState;
+use crate::paths::{find_on_disk, long_path};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DeleteResult {
+    pub batch_id: i64,
+    pub songs_deleted: usize,
+    pub files_trashed: usize,
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RestoreResult {
+    pub batch_id: i64,
+    pub songs_restored: usize,
+    pub files_restored: usize,
+    pub errors: Vec<String>,
+}
+
+struct SongRow {
+    id: String,
+    json: String,
+    files: Vec<PathBuf>,
+}
+
+fn load_song(conn: &Connection, id: &str) -> Result<Option<SongRow>, String> {
+    conn.query_row(
+        "SELECT json_data, folder_path, txt_file_name, audio_file_name, video_file_name, cover_file_name
+         FROM songs WHERE id = ?1",
+        [id],
+        |row| {
+            let json: Option<String> = row.get(0)?;
+            let folder: String = row.get(1)?;
+            let mut files = Vec::new();
+            for idx in 2..=5 {
+                if let Some(name) = row.get::<_, Option<String>>(idx)? {
+                    if !name.is_empty() && !folder.is_empty() {
+                        files.push(Path::new(&folder).join(name));
+                    }
+                }
+            }
+            Ok(SongRow {
+                id: id.to_string(),
+                json: json.unwrap_or_else(|| "{}".to_string()),
+                files,
+            })
+        },
+    )
+    .optional()
+    .map_err(|e| format!("song lookup failed: {}", e))
+}
+
+/// Remove songs from the library, optionally moving their files to the trash.
+pub fn delete_songs(conn: &mut Connection, song_ids: &[String], delete_files: bool) -> Result<DeleteResult, String> {
+    delete_songs_with(conn, song_ids, delete_files, |file| trash::delete(file).map_err(|e| e.to_string()))
+}
+
+/// `delete_songs` with `trash_file` moving one file to the trash.
+///
+/// The undo journal is committed before any file is touched, listing every
+/// file about to be trashed, so nothing can end up in the trash without a
+/// way back. Only then are the files trashed and, in a second transaction,
+/// the songs removed. A song with a file that could not be trashed keeps
+/// its library entry; its journal row keeps only the files that did go,
+/// so undo still brings those back.
+fn delete_songs_with(
+    conn: &mut Connection,
+    song_ids: &[String],
+    delete_files: bool,
+    trash_file: impl Fn(&Path) -> Result<(), String>,
+) -> Result<DeleteResult, String> {
+    let mut errors = Vec::new();
+    let mut rows = Vec::new();
+    for id in song_ids {
+        match load_song(conn, id)? {
+            Some(row) => rows.push(row),
+            None => errors.push(format!("Song '{}' not found", id)),
+        }
+    }
+    for row in &mut rows {
+        if delete_files {
+            // Stored paths are NFC; map them to the on-disk spelling
+            row.files = row.files.iter().filter_map(|f| find_on_disk(f)).collect();
+        } else {
+            row.files.clear();
+        }
+    }
+
+    let now = now_ms();
+    let batch_id = {
+        let tx = conn.transaction().map_err(|e| format!("Transaction failed: {}", e))?;
+        let batch_id: i64 = tx
+            .query_row("SELECT COALESCE(MAX(batch_id), 0) + 1 FROM deleted_songs_journal", [], |row| row.get(0))
+            .map_err(|e| format!("journal lookup failed: {}", e))?;
+        for row in &rows {
+            tx.execute(
+                "INSERT INTO deleted_songs_journal (batch_id, song_id, song_json, files, deleted_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                rusqlite::params![batch_id, row.id, row.json, files_json(&row.files), now],
+            )
+            .map_err(|e| format!("Failed to write undo journal: {}", e))?;
+        }
+        tx.commit().map_err(|e| format!("Commit failed: {}", e))?;
+        batch_id
+    };
+
+    // (song id, files trashed, whether all of them went)
+    let mut outcomes = Vec::with_capacity(rows.len());
+    let mut files_trashed = 0;
+    for row in &rows {
+        let mut trashed = Vec::new();
+        let mut complete = true;
+        for file in &row.files {
+            match trash_file(file) {
+                Ok(()) => trashed.push(file.clone()),
+                Err(e) => {
+                    complete = false;
+                    errors.push(format!("{}: {}", file.display(), e));
+                }
+            }
+        }
+        files_trashed += trashed.len();
+        if complete {
+            remove_empty_folder(&trashed);
+        } else {
+            errors.push(format!("Song '{}' stays in the library: not all of its files could be trashed", row.id));
+        }
+        outcomes.push((&row.id, trashed, complete));
+    }
+
+    let tx = conn.transaction().map_err(|e| format!("Transaction failed: {}", e))?;
+    let mut songs_deleted = 0;
+    for (id, trashed, complete) in &outcomes {
+        if *complete {
+            tx.execute("DELETE FROM songs WHERE id = ?1", [id])
+                .map_err(|e| format!("Failed to delete song: {}", e))?;
+            songs_deleted += 1;
+        } else if trashed.is_empty() {
+            tx.execute("DELETE FROM deleted_songs_journal WHERE batch_id = ?1 AND song_id = ?2", rusqlite::params![batch_id, id])
+                .map_err(|e| format!("Failed to update undo journal: {}", e))?;
+        } else {
+            tx.execute(
+                "UPDATE deleted_songs_journal SET files = ?1 WHERE batch_id = ?2 AND song_id = ?3",
+                rusqlite::params![files_json(trashed), batch_id, id],
+            )
+            .map_err(|e| format!("Failed to update undo journal: {}", e))?;
+        }
+    }
+    tx.commit().map_err(|e| format!("Commit failed: {}", e))?;
+
+    Ok(DeleteResult {
+        batch_id,
+        songs_deleted,
+        files_trashed,
+        errors,
+    })
+}
+
+fn files_json(files: &[PathBuf]) -> String {
+    let files: Vec<String> = files.iter().map(|f| f.to_string_lossy().to_string()).collect();
+    serde_json::to_string(&files).unwrap_or_default()
+}
+
+/// Files can be taken back out of the trash on this OS.
+const TRASH_RESTORE: bool = cfg!(any(target_os = "windows", all(unix, not(target_os = "macos"))));
+
+/// Undo the most recent deletion batch. `Ok(None)` if the journal is empty.
+pub fn restore_last(conn: &mut Connection) -> Result<Option<RestoreResult>, String> {
+    let batch_id: Option<i64> = conn
+        .query_row("SELECT MAX(batch_id) FROM deleted_songs_journal", [], |row| row.get(0))
+        .map_err(|e| format!("journal lookup failed: {}", e))?;
+    let Some(batch_id) = batch_id else {
+        return Ok(None);
+    };
+
+    let entries: Vec<(String, String)> = {
+        let mut stmt = conn
+            .prepare("SELECT song_json, files FROM deleted_songs_journal WHERE batch_id = ?1")
+            .map_err(|e| format!("journal read failed: {}", e))?;
+        let rows = stmt
+            .query_map([batch_id], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(|e| format!("journal read failed: {}", e))?;
+        rows.flatten().collect()
+    };
+
+    let mut errors = Vec::new();
+    let mut files_failed = false;
+    let files: Vec<PathBuf> = entries
+        .iter()
+        .flat_map(|(_, files)| serde_json::from_str::<Vec<String>>(files).unwrap_or_default())
+        .map(PathBuf::from)
+        .collect();
+    let files_restored = if files.is_empty() {
+        0
+    } else {
+        match restore_from_trash(&files) {
+            Ok(n) => n,
+            Err(e) => {
+                errors.push(e);
+                files_failed = true;
+                0
+            }
+        }
+    };
+
+    let tx = conn.transaction().map_err(|e| format!("Transaction failed: {}", e))?;
+    let mut songs_restored = 0;
+    for (json, _) in &entries {
+        match serde_json::from_str::<serde_json::Value>(json) {
+            Ok(song) => {
+                upsert_song(&tx, &song)?;
+                songs_restored += 1;
+            }
+            Err(e) => errors.push(format!("Invalid journal entry: {}", e)),
+        }
+    }
+    if files_failed && TRASH_RESTORE {
+        errors.push("The deletion stays in the undo journal; undo again to retry its files".to_string());
+    } else {
+        tx.execute("DELETE FROM deleted_songs_journal WHERE batch_id = ?1", [batch_id])
+            .map_err(|e| format!("Failed to clear undo journal: {}", e))?;
+    }
+    tx.commit().map_err(|e| format!("Commit failed: {}", e))?;
+
+    Ok(Some(RestoreResult {
+        batch_id,
+        songs_restored,
+        files_restored,
+        errors,
+    }))
+}
+
+#[cfg(any(target_os = "windows", all(unix, not(target_os = "macos"))))]
+fn restore_from_trash(files: &[PathBuf]) -> Result<usize, String> {
+    let items = trash::os_limited::list().map_err(|e| format!("Cannot read trash: {}", e))?;
+
+    // Newest first, so a file trashed several times comes back as the latest copy
+    let mut wanted: Vec<trash::TrashItem> = Vec::new();
+    let mut sorted = items;
+    sorted.sort_by_key(|item| std::cmp::Reverse(item.time_deleted));
+    for item in sorted {
+        let original = item.original_path();
+        if files.contains(&original) && !wanted.iter().any(|w| w.original_path() == original) {
+            wanted.push(item);
+        }
+    }
+
+    for item in &wanted {
+        std::fs::create_dir_all(long_path(&item.original_parent))
+            .map_err(|e| format!("Cannot recreate {}: {}", item.original_parent.display(), e))?;
+    }
+    let count = wanted.len();
+    trash::os_limited::restore_all(wanted).map_err(|e| format!("Restore from trash failed: {}", e))?;
+    Ok(count)
+}
+
+#[cfg(not(any(target_os = "windows", all(unix, not(target_os = "macos")))))]
+fn restore_from_trash(files: &[PathBuf]) -> Result<usize, String> {
+    Err(format!(
+        "{} file(s) are in the Trash; restoring them automatically is not supported on this OS",
+        files.len()
+    ))
+}
+
+/// Drop the song folder if trashing left it empty.
+fn remove_empty_folder(files: &[PathBuf]) {
+    if let Some(folder) = files.first().and_then(|f| f.parent()) {
+        let empty = std::fs::read_dir(long_path(folder)).map(|mut d| d.next().is_none()).unwrap_or(false);
+        if empty {
+            let _ = std::fs::remove_dir(long_path(folder));
+        }
+    }
+}
+
+fn now_ms() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+// ---------------------------------------------------------------------------
+// Tauri Commands
+// ---------------------------------------------------------------------------
+
+/// Delete songs from the library. With `delete_files`, their files are
+/// moved to the recycle bin / trash.
+#[tauri::command]
+pub fn library_delete_songs(app: AppHandle, webview: tauri::Webview, song_ids: Vec<String>, delete_files: bool) -> Result<DeleteResult, String> {
+    require_webview(&webview, Capability::ManageLibrary)?;
+    let db = app.state::<DbState>();
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    delete_songs(&mut conn, &song_ids, delete_files)
+}
+
+/// Undo the most recent library deletion. Returns `None` if there is nothing to undo.
+#[tauri::command]
+pub fn restore_last_deleted(app: AppHandle, webview: tauri::Webview) -> Result<Option<RestoreResult>, String> {
+    require_webview(&webview, Capability::ManageLibrary)?;
+    let db = app.state::<DbState>();
+    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+    restore_last(&mut conn)
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delete_and_restore_round_trip_without_files() {
+        let mut conn = crate::db::test_conn();
+        let song = serde_json::json!({ "id": "s1", "title": "T", "artist": "A" });
+        upsert_song(&conn, &song).unwrap();
+
+        let deleted = delete_songs(&mut conn, &["s1".to_string(), "missing".to_string()], false).unwrap();
+        assert_eq!(deleted.songs_deleted, 1);
+        assert_eq!(deleted.errors.len(), 1);
+        let count: i64 = conn.query_row("SELECT COUNT(*) FROM songs", [], |r| r.get(0)).unwrap();
+        assert_eq!(count, 0);
+
+        let restored = restore_last(&mut conn).unwrap().unwrap();
+        assert_eq!(restored.songs_restored, 1);
+        let title: String = conn.query_row("SELECT title FROM songs WHERE id = 's1'", [], |r| r.get(0)).unwrap();
+        assert_eq!(title, "T");
+        assert!(restore_last(&mut conn).unwrap().is_none());
+    }
+
+    #[test]
+    fn a_song_whose_files_cannot_be_trashed_stays() {
+        let dir = crate::paths::test_dir("deletion");
+        std::fs::write(dir.join("song.txt"), b"#TITLE:T").unwrap();
+        std::fs::write(dir.join("song.mp3"), b"mp3").unwrap();
+        let mut conn = crate::db::test_conn();
+        let song = serde_json::json!({
+            "id": "s1", "title": "T", "artist": "A", "folderPath": dir.to_string_lossy(),
+            "txtFileName": "song.txt", "audioFileName": "song.mp3",
+        });
+        upsert_song(&conn, &song).unwrap();
+
+        let trash = |file: &Path| match file.extension().and_then(|e| e.to_str()) {
+            Some("mp3") => Err("in use".to_string()),
+            _ => Ok(()),
+        };
+        let deleted = delete_songs_with(&mut conn, &["s1".to_string()], true, trash).unwrap();
+        assert_eq!((deleted.songs_deleted, deleted.files_trashed), (0, 1));
+        assert_eq!(deleted.errors.len(), 2);
+        let count: i64 = conn.query_row("SELECT COUNT(*) FROM songs WHERE id = 's1'", [], |r| r.get(0)).unwrap();
+        assert_eq!(count, 1);
+        // Undo can still bring back the file that went
+        let files: String = conn.query_row("SELECT files FROM deleted_songs_journal", [], |r| r.get(0)).unwrap();
+        let files: Vec<String> = serde_json::from_str(&files).unwrap();
+        assert_eq!(files, vec![dir.join("song.txt").to_string_lossy().to_string()]);
+
+        let all = delete_songs_with(&mut conn, &["s1".to_string()], true, |_| Ok(())).unwrap();
+        assert_eq!((all.songs_deleted, all.files_trashed), (1, 2));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,501 @@
+//! Songs dragged onto the window.
+//!
+//! Files, folders and zips dropped on the main window go to the import
+//! worker like dialog selections, but end up in the library folder instead
+//! of being indexed where they lie — a Downloads folder gets emptied, and
+//! the library must not break when it does. For each dropped item:
+//!   1. its songs are found with the scanner's format detection and
+//!      metadata extraction (`formats`, `scanner`);
+//!   2. songs already in the library are skipped (see `is_duplicate`);
+//!   3. the files of every new song are hard-linked into the library folder
+//!      when it is on the same volume and copied otherwise. A dropped
+//!      folder keeps its structure; name clashes get a ` (2)` suffix (on
+//!      the folder of an UltraStar song, on the file names otherwise);
+//!   4. the songs are parsed again at their new place and saved.
+//!
+//! Items already inside a root folder are imported where they are. Zips
+//! without MP3+CDG pairs (zipped UltraStar folders) are extracted first.
+//!
+//! The library folder is the `import_library_dir` setting, else the first
+//! root folder, else `<app data>/songs`. It is added to the root folders
+//! unless one of them already contains it.
+
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde_json::Value;
+use tauri::{AppHandle, Manager};
+use zip::ZipArchive;
+
+use super::archive;
+use super::artwork;
+use super::import_queue::ImportQueue;
+use super::scan_pool::{self, ScanOptions};
+use super::scanner::{self, fnv1a64, has_extension, AUDIO_EXTENSIONS};
+use crate::db::DbState;
+use crate::paths::{display_path, find_on_disk, long_path, sanitize_new_components};
+
+const LIBRARY_DIR_SETTING: &str = "import_library_dir";
+const DEFAULT_LIBRARY_SUBDIR: &str = "songs";
+const EXTRACT_SUBDIR: &str = "drop-import";
+/// Zipped song folders inflating past this are refused.
+const MAX_EXTRACTED_SIZE: u64 = 4 * 1024 * 1024 * 1024;
+
+/// Song entry fields naming the file a song is identified by, in order.
+const PRIMARY_FIELDS: &[&str] = &["txtFileName", "cdgFileName", "archiveFileName", "karFileName", "videoFileName", "audioFileName"];
+/// Fields naming further files a song needs next to it.
+const COMPANION_FIELDS: &[&str] = &["audioFileName", "videoFileName", "coverFileName"];
+
+/// Outcome of one dropped item.
+#[derive(Debug, Default)]
+pub struct DropReport {
+    pub songs_added: usize,
+    pub duplicates: usize,
+    pub errors: Vec<String>,
+}
+
+/// Where the dropped songs' files come from.
+enum Source {
+    /// Loose files.
+    Files,
+    /// A folder, recreated below the library as `name`.
+    Folder { root: PathBuf, name: String },
+}
+
+/// The files of one song (several songs for a zip disc pack), the
+/// identifying one first.
+struct Unit {
+    songs: Vec<Value>,
+    files: Vec<PathBuf>,
+}
+
+impl Unit {
+    fn is_ultrastar(&self) -> bool {
+        has_extension(&self.files[0], &["txt"])
+    }
+}
+
+/// Queue files dropped on the main window for import into the library.
+pub fn on_drop(app: &AppHandle, paths: Vec<PathBuf>) {
+    let count = paths.len();
+    match app.state::<ImportQueue>().enqueue_into_library(paths) {
+        Ok(job_id) => tracing::info!("[import] {} dropped item(s) queued as job {}", count, job_id),
+        Err(e) => tracing::error!("[import] Failed to queue dropped items: {}", e),
+    }
+}
+
+/// Import one dropped item (on the import worker).
+pub fn import_item(app: &AppHandle, item: &Path) -> Result<DropReport, String> {
+    let item = display_path(item);
+    if !long_path(&item).exists() {
+        return Err(format!("File not found: {}", item.display()));
+    }
+    let db = app.state::<DbState>();
+    let options = ScanOptions { artwork_dir: artwork::cache_dir(app), ..ScanOptions::default() };
+
+    if is_zipped_folder(&item)? {
+        let name = item.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        let dir = app
+            .path()
+            .app_cache_dir()
+            .map_err(|e| format!("No cache directory: {}", e))?
+            .join(EXTRACT_SUBDIR)
+            .join(format!("{:016x}", fnv1a64(item.to_string_lossy().as_bytes())));
+        let _ = fs::remove_dir_all(long_path(&dir));
+        let result = extract_all(&item, &dir).and_then(|()| {
+            let library = library_dir(app)?;
+            let source = Source::Folder { root: dir.clone(), name };
+            import_from(&db.conn, &dir, Some((&library, &source)), &options)
+        });
+        let _ = fs::remove_dir_all(long_path(&dir));
+        return result;
+    }
+
+    let roots = {
+        let conn = db.conn.lock().map_err(|e| e.to_string())?;
+        root_folders(&conn)?
+    };
+    if roots.iter().any(|root| item.starts_with(root)) {
+        return import_from(&db.conn, &item, None, &options);
+    }
+    let library = library_dir(app)?;
+    let source = if long_path(&item).is_dir() {
+        let name = item.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        Source::Folder { root: item.clone(), name }
+    } else {
+        Source::Files
+    };
+    import_from(&db.conn, &item, Some((&library, &source)), &options)
+}
+
+/// Import the songs of `item`, into `library` when given and in place
+/// otherwise. The DB lock is only held per song.
+fn import_from(
+    db: &Mutex<Connection>,
+    item: &Path,
+    into: Option<(&Path, &Source)>,
+    options: &ScanOptions,
+) -> Result<DropReport, String> {
+    let mut report = DropReport::default();
+    let songs = if long_path(item).is_dir() {
+        let scan = scanner::scan_directory(item)?;
+        report.errors.extend(scan.errors);
+        scan.songs
+    } else {
+        songs_in_file(item, &ScanOptions::default())?
+    };
+    if songs.is_empty() {
+        return Err(format!("No songs found in {}", item.display()));
+    }
+
+    for unit in units(songs) {
+        let new = {
+            let conn = db.lock().map_err(|e| e.to_string())?;
+            let mut new = false;
+            for song in &unit.songs {
+                if is_duplicate(&conn, song)? {
+                    report.duplicates += 1;
+                } else {
+                    new = true;
+                }
+            }
+            new
+        };
+        if !new {
+            continue;
+        }
+
+        let primary = match into {
+            Some((library, source)) => match transfer(library, source, &unit) {
+                Ok(primary) => primary,
+                Err(e) => {
+                    report.errors.push(e);
+                    continue;
+                }
+            },
+            None => unit.files[0].clone(),
+        };
+        let songs = match songs_in_file(&primary, options) {
+            Ok(songs) => songs,
+            Err(e) => {
+                report.errors.push(e);
+                continue;
+            }
+        };
+        let mut conn = db.lock().map_err(|e| e.to_string())?;
+        let mut fresh = Vec::new();
+        for song in songs {
+            if !is_duplicate(&conn, &song)? {
+                fresh.push(song);
+            }
+        }
+        report.songs_added += scanner::save_songs(&mut conn, &fresh)?;
+    }
+    Ok(report)
+}
+
+/// A song is already in the library when a row has its id (the same file),
+/// its content hash (the same UltraStar file elsewhere) or its artist,
+/// title and format (the same track from another source).
+pub fn is_duplicate(conn: &Connection, song: &Value) -> Result<bool, String> {
+    conn.query_row(
+        "SELECT 1 FROM songs
+         WHERE id = ?1
+            OR (?2 IS NOT NULL AND json_extract(json_data, '$.contentHash') = ?2)
+            OR (lower(trim(artist)) = lower(trim(?3)) AND lower(trim(title)) = lower(trim(?4)) AND format IS ?5)
+         LIMIT 1",
+        params![field(song, "id"), field(song, "contentHash"), field(song, "artist"), field(song, "title"), field(song, "format")],
+        |_| Ok(()),
+    )
+    .optional()
+    .map(|row| row.is_some())
+    .map_err(|e| format!("Failed to check for duplicates: {}", e))
+}
+
+fn field<'a>(song: &'a Value, key: &str) -> Option<&'a str> {
+    song.get(key).and_then(|v| v.as_str()).filter(|v| !v.is_empty())
+}
+
+/// The files `song` consists of, the identifying one first. Only plain
+/// names next to the song count, so an entry never drags in (or writes to)
+/// anything outside its folder.
+fn song_files(song: &Value) -> Vec<PathBuf> {
+    let folder = PathBuf::from(field(song, "folderPath").unwrap_or_default());
+    let file = |key: &&str| {
+        let name = field(song, key)?;
+        (Path::new(name).file_name()? == name).then(|| {
+            let path = folder.join(name);
+            find_on_disk(&path).unwrap_or(path)
+        })
+    };
+    let Some(primary) = PRIMARY_FIELDS.iter().find_map(file) else { return Vec::new() };
+    let mut files = vec![primary];
+    for path in COMPANION_FIELDS.iter().filter_map(file) {
+        if !files.contains(&path) && long_path(&path).is_file() {
+            files.push(path);
+        }
+    }
+    files
+}
+
+/// Group songs by the file they are identified by (the tracks of a disc
+/// pack share their zip).
+fn units(songs: Vec<Value>) -> Vec<Unit> {
+    let mut units: Vec<Unit> = Vec::new();
+    for song in songs {
+        let files = song_files(&song);
+        if files.is_empty() {
+            continue;
+        }
+        match units.iter_mut().find(|unit| unit.files[0] == files[0]) {
+            Some(unit) => unit.songs.push(song),
+            None => units.push(Unit { songs: vec![song], files }),
+        }
+    }
+    units
+}
+
+/// Songs in one file, parsed like the scanner does; a loose audio file is
+/// a song of its own.
+fn songs_in_file(file: &Path, options: &ScanOptions) -> Result<Vec<Value>, String> {
+    let candidates = scanner::candidates_for(file);
+    if candidates.is_empty() {
+        if has_extension(file, AUDIO_EXTENSIONS) {
+            return Ok(vec![scanner::song_from_audio(file)]);
+        }
+        return Err(format!("Not a song file: {}", file.display()));
+    }
+    let mut songs = Vec::new();
+    for candidate in &candidates {
+        songs.extend(scan_pool::parse_candidate(candidate, options)?);
+    }
+    Ok(songs)
+}
+
+/// Bring `unit`'s files into the library; returns the new path of the
+/// identifying file.
+fn transfer(library: &Path, source: &Source, unit: &Unit) -> Result<PathBuf, String> {
+    let from_dir = unit.files[0].parent().unwrap_or(Path::new(""));
+    let dir = match source {
+        Source::Folder { root, name } => library.join(name).join(from_dir.strip_prefix(root).unwrap_or(Path::new(""))),
+        // An UltraStar song is a folder; other formats sit side by side
+        Source::Files if unit.is_ultrastar() => library.join(from_dir.file_name().unwrap_or_default()),
+        Source::Files => library.to_path_buf(),
+    };
+    let targets = place(&sanitize_new_components(&dir), &unit.files, unit.is_ultrastar());
+    for (from, to) in unit.files.iter().zip(&targets) {
+        link_or_copy(from, to)?;
+    }
+    Ok(targets[0].clone())
+}
+
+/// Free target paths for `files` in `dir`. When one is taken, numbering
+/// goes on the folder (`own_folder`) or on every file's stem, so files
+/// belonging together keep matching names.
+fn place(dir: &Path, files: &[PathBuf], own_folder: bool) -> Vec<PathBuf> {
+    let targets = |n: u32| -> Vec<PathBuf> {
+        let dir = if own_folder && n > 1 { numbered_dir(dir, n) } else { dir.to_path_buf() };
+        files
+            .iter()
+            .map(|file| {
+                let name = file.file_name().unwrap_or_default();
+                if own_folder || n == 1 {
+                    return dir.join(name);
+                }
+                let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+                match file.extension() {
+                    Some(ext) => dir.join(format!("{} ({}).{}", stem, n, ext.to_string_lossy())),
+                    None => dir.join(format!("{} ({})", stem, n)),
+                }
+            })
+            .collect()
+    };
+    (1..)
+        .map(targets)
+        .find(|paths| paths.iter().all(|path| !long_path(path).exists()))
+        .unwrap_or_default()
+}
+
+fn numbered_dir(dir: &Path, n: u32) -> PathBuf {
+    let name = dir.file_name().unwrap_or_default().to_string_lossy();
+    dir.with_file_name(format!("{} ({})", name, n))
+}
+
+/// Hard link when on the same volume (no extra space), copy otherwise.
+fn link_or_copy(from: &Path, to: &Path) -> Result<(), String> {
+    if let Some(dir) = to.parent() {
+        fs::create_dir_all(long_path(dir)).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    if fs::hard_link(long_path(from), long_path(to)).is_ok() {
+        return Ok(());
+    }
+    fs::copy(long_path(from), long_path(to))
+        .map(|_| ())
+        .map_err(|e| format!("Failed to copy {} to {}: {}", from.display(), to.display(), e))
+}
+
+/// A zip is a zipped folder rather than a karaoke archive when it holds no
+/// MP3+CDG pairs.
+fn is_zipped_folder(path: &Path) -> Result<bool, String> {
+    if !has_extension(path, &["zip"]) || !long_path(path).is_file() {
+        return Ok(false);
+    }
+    Ok(archive::list_tracks(path)?.is_empty())
+}
+
+/// Extract every member of `zip` into `dir`. Members that would land
+/// outside `dir` are skipped; archives inflating past
+/// `MAX_EXTRACTED_SIZE` are refused.
+fn extract_all(zip: &Path, dir: &Path) -> Result<(), String> {
+    let file = File::open(long_path(zip)).map_err(|e| format!("Failed to open {}: {}", zip.display(), e))?;
+    let mut archive = ZipArchive::new(file).map_err(|e| format!("Not a readable zip archive {}: {}", zip.display(), e))?;
+    let mut budget = MAX_EXTRACTED_SIZE;
+    for index in 0..archive.len() {
+        let mut member = archive.by_index(index).map_err(|e| format!("Failed to read {}: {}", zip.display(), e))?;
+        let Some(name) = member.enclosed_name() else { continue };
+        if member.is_dir() || name.starts_with("__MACOSX") {
+            continue;
+        }
+        let target = dir.join(name);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(long_path(parent)).map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        let mut out = File::create(long_path(&target)).map_err(|e| format!("Failed to create {}: {}", target.display(), e))?;
+        // The declared size may lie; count what actually inflates
+        let written = io::copy(&mut member.by_ref().take(budget + 1), &mut out)
+            .map_err(|e| format!("Failed to extract {}: {}", zip.display(), e))?;
+        if written > budget {
+            return Err(format!("{} is too large to extract", zip.display()));
+        }
+        budget -= written;
+    }
+    Ok(())
+}
+
+fn root_folders(conn: &Connection) -> Result<Vec<PathBuf>, String> {
+    let mut stmt = conn
+        .prepare("SELECT path FROM root_folders ORDER BY path")
+        .map_err(|e| format!("Failed to read root folders: {}", e))?;
+    let rows = stmt
+        .query_map([], |row| row.get::<_, String>(0))
+        .map_err(|e| format!("Failed to read root folders: {}", e))?;
+    rows.map(|row| row.map(PathBuf::from))
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("Failed to read root folders: {}", e))
+}
+
+/// Where dropped songs go (see the module docs); created and registered
+/// as a root folder if needed.
+pub fn library_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    let db = app.state::<DbState>();
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let roots = root_folders(&conn)?;
+    let dir = match crate::scheduler::read_setting(&conn, LIBRARY_DIR_SETTING).filter(|d| !d.trim().is_empty()) {
+        Some(dir) => PathBuf::from(dir.trim()),
+        None => match roots.first() {
+            Some(root) => root.clone(),
+            None => app
+                .path()
+                .app_data_dir()
+                .map(|dir| dir.join(DEFAULT_LIBRARY_SUBDIR))
+                .map_err(|e| format!("No app data dir: {}", e))?,
+        },
+    };
+    fs::create_dir_all(long_path(&dir)).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    if !roots.iter().any(|root| dir.starts_with(root)) {
+        conn.execute("INSERT OR IGNORE INTO root_folders (path) VALUES (?1)", [dir.to_string_lossy()])
+            .map_err(|e| format!("Failed to add root folder: {}", e))?;
+        tracing::info!("[import] Added {} to the root folders", dir.display());
+    }
+    Ok(dir)
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn library_db() -> Mutex<Connection> {
+        Mutex::new(crate::db::test_conn())
+    }
+
+    #[test]
+    fn drops_copy_new_songs_and_skip_duplicates() {
+        let root = crate::paths::test_dir("drop");
+        let dropped = root.join("Downloads").join("Party");
+        let library = root.join("Library");
+        for (dir, title) in [("Band - One", "One"), ("Band - Two", "Two")] {
+            fs::create_dir_all(dropped.join(dir)).unwrap();
+            fs::write(dropped.join(dir).join("song.txt"), format!("#TITLE:{}\n#ARTIST:Band\n#MP3:song.mp3\n", title)).unwrap();
+            fs::write(dropped.join(dir).join("song.mp3"), title).unwrap();
+        }
+        fs::write(root.join("Downloads").join("Band - Three.mp3"), "three").unwrap();
+
+        let db = library_db();
+        let source = Source::Folder { root: dropped.clone(), name: "Party".to_string() };
+        let first = import_from(&db, &dropped, Some((&library, &source)), &ScanOptions::default()).unwrap();
+        let again = import_from(&db, &dropped, Some((&library, &source)), &ScanOptions::default()).unwrap();
+        let loose = root.join("Downloads").join("Band - Three.mp3");
+        let single = import_from(&db, &loose, Some((&library, &Source::Files)), &ScanOptions::default()).unwrap();
+
+        let copied = library.join("Party").join("Band - One").join("song.mp3");
+        let copied_exists = copied.is_file();
+        let second_copy = library.join("Party").join("Band - One (2)").exists();
+        let loose_copied = library.join("Band - Three.mp3").is_file();
+        let folder_paths: Vec<String> = {
+            let conn = db.lock().unwrap();
+            let mut stmt = conn.prepare("SELECT folder_path FROM songs ORDER BY title").unwrap();
+            stmt.query_map([], |row| row.get(0)).unwrap().map(Result::unwrap).collect()
+        };
+        let _ = fs::remove_dir_all(&root);
+
+        assert_eq!((first.songs_added, first.duplicates), (2, 0));
+        assert_eq!((again.songs_added, again.duplicates), (0, 2));
+        assert_eq!(single.songs_added, 1);
+        assert!(copied_exists && loose_copied && !second_copy);
+        assert!(folder_paths.iter().all(|path| Path::new(path).starts_with(&library)));
+    }
+
+    #[test]
+    fn clashing_names_are_numbered_together() {
+        let root = crate::paths::test_dir("drop-place");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("A - B.cdg"), "").unwrap();
+        let files = [PathBuf::from("/src/A - B.cdg"), PathBuf::from("/src/A - B.mp3")];
+        let flat = place(&root, &files, false);
+        let folder = place(&root.join("Song"), &files, true);
+        let _ = fs::remove_dir_all(&root);
+
+        assert_eq!(flat, vec![root.join("A - B (2).cdg"), root.join("A - B (2).mp3")]);
+        assert_eq!(folder[0], root.join("Song").join("A - B.cdg"));
+    }
+
+    #[test]
+    fn extraction_stays_inside_the_target() {
+        let root = crate::paths::test_dir("drop-zip");
+        fs::create_dir_all(&root).unwrap();
+        let zip_path = root.join("song.zip");
+        {
+            let mut writer = zip::ZipWriter::new(File::create(&zip_path).unwrap());
+            for name in ["Song/song.txt", "../escaped.txt"] {
+                writer.start_file(name, zip::write::SimpleFileOptions::default()).unwrap();
+                writer.write_all(b"#TITLE:Song\n#ARTIST:Band\n").unwrap();
+            }
+            writer.finish().unwrap();
+        }
+        let zipped_folder = is_zipped_folder(&zip_path).unwrap();
+        extract_all(&zip_path, &root.join("out")).unwrap();
+        let extracted = root.join("out").join("Song").join("song.txt").is_file();
+        let escaped = root.join("escaped.txt").exists();
+        let _ = fs::remove_dir_all(&root);
+
+        assert!(zipped_folder && extracted && !escaped);
+    }
+}
@@ -0,0 +1,155 @@
+//! Karaoke formats recognised by the scanner.
+//!
+//! Detection works per directory, from file names alone, so discovery never
+//! opens a file:
+//!   - UltraStar: every `.txt` (parsing later rejects files without a header);
+//!   - CD+G: a `.cdg` next to an audio file with the same stem;
+//!   - zip: a karaoke archive of MP3+CDG pairs (listed later, see `archive`);
+//!   - KAR: a MIDI karaoke file; its audio is synthesized (see `crate::midi`).
+//!     Plain `.mid` files are not picked up: most are not karaoke;
+//!   - video: a karaoke video with burnt-in lyrics. Videos in a folder with an
+//!     UltraStar `.txt` are that song's background, not songs of their own.
+
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use super::scanner::has_extension;
+
+/// Audio formats found next to `.cdg` files.
+pub const CDG_AUDIO_EXTENSIONS: &[&str] = &["mp3", "ogg", "wav", "flac", "m4a"];
+pub const VIDEO_EXTENSIONS: &[&str] = &["mp4", "mkv", "avi", "webm", "mov"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SongFormat {
+    UltraStar,
+    Cdg,
+    Zip,
+    Kar,
+    Video,
+}
+
+impl SongFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::UltraStar => "ultrastar",
+            Self::Cdg => "cdg",
+            Self::Zip => "zip",
+            Self::Kar => "kar",
+            Self::Video => "video",
+        }
+    }
+}
+
+/// A file that may be a song, found during discovery.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Candidate {
+    UltraStar(PathBuf),
+    Cdg { cdg: PathBuf, audio: PathBuf },
+    Zip(PathBuf),
+    Kar(PathBuf),
+    Video(PathBuf),
+}
+
+impl Candidate {
+    pub fn format(&self) -> SongFormat {
+        match self {
+            Self::UltraStar(_) => SongFormat::UltraStar,
+            Self::Cdg { .. } => SongFormat::Cdg,
+            Self::Zip(_) => SongFormat::Zip,
+            Self::Kar(_) => SongFormat::Kar,
+            Self::Video(_) => SongFormat::Video,
+        }
+    }
+
+    /// The file the song is identified by (and whose mtime counts).
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::UltraStar(path) | Self::Zip(path) | Self::Kar(path) | Self::Video(path) => path,
+            Self::Cdg { cdg, .. } => cdg,
+        }
+    }
+}
+
+fn same_stem(a: &Path, b: &Path) -> bool {
+    match (a.file_stem().and_then(|s| s.to_str()), b.file_stem().and_then(|s| s.to_str())) {
+        (Some(a), Some(b)) => a.eq_ignore_ascii_case(b),
+        _ => false,
+    }
+}
+
+/// Candidates among the files (not subdirectories) of one directory.
+pub fn classify_dir(files: &[PathBuf]) -> Vec<Candidate> {
+    let has_ultrastar = files.iter().any(|f| has_extension(f, &["txt"]));
+    let mut candidates = Vec::new();
+    for file in files {
+        if has_extension(file, &["txt"]) {
+            candidates.push(Candidate::UltraStar(file.clone()));
+        } else if has_extension(file, &["cdg"]) {
+            // A .cdg without its audio cannot be played; not a song
+            let audio = files.iter().find(|a| has_extension(a, CDG_AUDIO_EXTENSIONS) && same_stem(a, file));
+            if let Some(audio) = audio {
+                candidates.push(Candidate::Cdg { cdg: file.clone(), audio: audio.clone() });
+            }
+        } else if has_extension(file, &["zip"]) {
+            candidates.push(Candidate::Zip(file.clone()));
+        } else if has_extension(file, &["kar"]) {
+            candidates.push(Candidate::Kar(file.clone()));
+        } else if has_extension(file, VIDEO_EXTENSIONS) && !has_ultrastar {
+            candidates.push(Candidate::Video(file.clone()));
+        }
+    }
+    candidates
+}
+
+/// Artist and title from a karaoke file name: `Artist - Title`, or
+/// `SC8123-05 - Artist - Title` with a disc/track code in front.
+pub fn split_karaoke_name(stem: &str) -> (Option<String>, String) {
+    let parts: Vec<&str> = stem.split(" - ").map(str::trim).filter(|p| !p.is_empty()).collect();
+    let is_code = |part: &str| !part.contains(' ') && part.chars().any(|c| c.is_ascii_digit());
+    match parts.as_slice() {
+        [code, artist, title @ ..] if is_code(code) && !title.is_empty() => {
+            (Some(artist.to_string()), title.join(" - "))
+        }
+        [artist, title @ ..] if !title.is_empty() => (Some(artist.to_string()), title.join(" - ")),
+        _ => (None, stem.trim().to_string()),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(names: &[&str]) -> Vec<PathBuf> {
+        names.iter().map(|n| PathBuf::from("/k").join(n)).collect()
+    }
+
+    #[test]
+    fn pairs_cdg_and_skips_ultrastar_backgrounds() {
+        let found = classify_dir(&paths(&["a.CDG", "a.mp3", "lonely.cdg", "b.zip", "d.kar", "e.mid", "c.mp4"]));
+        assert_eq!(
+            found,
+            vec![
+                Candidate::Cdg { cdg: "/k/a.CDG".into(), audio: "/k/a.mp3".into() },
+                Candidate::Zip("/k/b.zip".into()),
+                Candidate::Kar("/k/d.kar".into()),
+                Candidate::Video("/k/c.mp4".into()),
+            ]
+        );
+        let found = classify_dir(&paths(&["song.txt", "song.mp3", "video.mp4"]));
+        assert_eq!(found, vec![Candidate::UltraStar("/k/song.txt".into())]);
+    }
+
+    #[test]
+    fn splits_names_with_and_without_disc_code() {
+        assert_eq!(split_karaoke_name("SC8123-05 - Queen - Radio Ga Ga"), (Some("Queen".into()), "Radio Ga Ga".into()));
+        assert_eq!(split_karaoke_name("AC-DC - T.N.T."), (Some("AC-DC".into()), "T.N.T.".into()));
+        assert_eq!(split_karaoke_name("Queen - We Will Rock You - Live"), (Some("Queen".into()), "We Will Rock You - Live".into()));
+        assert_eq!(split_karaoke_name("Untitled"), (None, "Untitled".into()));
+    }
+}
@@ -0,0 +1,179 @@
+//! Background import job queue.
+//!
+//! Imports (dialog selections, forwarded files, CLI-free GUI imports) are
+//! queued to a dedicated worker thread so large folders never block the UI.
+//! Dropped files are queued the same way but brought into the library
+//! folder first (see `drop_import`).
+//! Progress is reported with `library://import-progress` per path and
+//! `library://import-complete` per job. Files of newly added songs the
+//! webview cannot play are then queued in `media::transcode`.
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+use super::artwork;
+use super::drop_import;
+use super::scan_pool::{self, ScanOptions};
+use super::scanner;
+use crate::db::DbState;
+use crate::events::{publish, AppEvent};
+use crate::paths::long_path;
+
+pub const IMPORT_PROGRESS_EVENT: &str = "library://import-progress";
+pub const IMPORT_COMPLETE_EVENT: &str = "library://import-complete";
+/// Per-folder scan progress (`scan_pool::ScanProgress`) while a job or
+/// `scan_library` runs.
+pub const SCAN_PROGRESS_EVENT: &str = "library://scan-progress";
+
+struct ImportJob {
+    id: u64,
+    paths: Vec<PathBuf>,
+    /// Copy into the library folder instead of indexing in place.
+    into_library: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportProgress {
+    pub job_id: u64,
+    pub done: usize,
+    pub total: usize,
+    pub path: String,
+    pub songs_added: usize,
+    /// Songs skipped because the library already has them.
+    pub duplicates: usize,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportComplete {
+    pub job_id: u64,
+    pub songs_added: usize,
+    pub duplicates: usize,
+    pub errors: Vec<String>,
+}
+
+/// Managed state: sender side of the import worker.
+pub struct ImportQueue {
+    tx: Mutex<mpsc::Sender<ImportJob>>,
+    next_id: AtomicU64,
+}
+
+impl ImportQueue {
+    pub fn new(app: AppHandle) -> Result<Self, String> {
+        let (tx, rx) = mpsc::channel::<ImportJob>();
+        thread::Builder::new()
+            .name("karaoke-import".into())
+            .spawn(move || {
+                for job in rx {
+                    run_job(&app, job);
+                }
+            })
+            .map_err(|e| format!("Failed to spawn import worker: {}", e))?;
+
+        Ok(Self {
+            tx: Mutex::new(tx),
+            next_id: AtomicU64::new(1),
+        })
+    }
+
+    /// Queue `paths` for import; returns the job id used in progress events.
+    pub fn enqueue(&self, paths: Vec<PathBuf>) -> Result<u64, String> {
+        self.send(paths, false)
+    }
+
+    /// Queue dropped `paths` for import into the library folder.
+    pub fn enqueue_into_library(&self, paths: Vec<PathBuf>) -> Result<u64, String> {
+        self.send(paths, true)
+    }
+
+    fn send(&self, paths: Vec<PathBuf>, into_library: bool) -> Result<u64, String> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let tx = self.tx.lock().map_err(|e| e.to_string())?;
+        tx.send(ImportJob { id, paths, into_library }).map_err(|e| e.to_string())?;
+        Ok(id)
+    }
+}
+
+fn run_job(app: &AppHandle, job: ImportJob) {
+    let started = crate::scheduler::now_ms();
+    let total = job.paths.len();
+    let mut songs_added = 0;
+    let mut duplicates = 0;
+    let mut errors = Vec::new();
+
+    for (idx, path) in job.paths.iter().enumerate() {
+        let db = app.state::<DbState>();
+        let mut skipped = 0;
+        let result = if job.into_library {
+            drop_import::import_item(app, path).map(|report| {
+                errors.extend(report.errors);
+                skipped = report.duplicates;
+                report.songs_added
+            })
+        } else if long_path(path).is_dir() {
+            // Folders go through the scan pool with batched writes
+            let options = ScanOptions { artwork_dir: artwork::cache_dir(app), ..ScanOptions::default() };
+            scan_pool::scan_into_db(&db, path, &options, |progress| {
+                publish(app, AppEvent::ScanProgress(progress.clone()));
+            })
+            .map(|report| {
+                errors.extend(report.errors.iter().cloned());
+                report.songs_found
+            })
+        } else {
+            scanner::import_path(path).and_then(|report| {
+                errors.extend(report.errors.iter().cloned());
+                let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+                scanner::save_songs(&mut conn, &report.songs)
+            })
+        };
+
+        let (added, error) = match result {
+            Ok(n) => (n, None),
+            Err(e) => {
+                errors.push(e.clone());
+                (0, Some(e))
+            }
+        };
+        songs_added += added;
+        duplicates += skipped;
+
+        publish(
+            app,
+            AppEvent::ImportProgress(ImportProgress {
+                job_id: job.id,
+                done: idx + 1,
+                total,
+                path: path.to_string_lossy().to_string(),
+                songs_added: added,
+                duplicates: skipped,
+                error,
+            }),
+        );
+    }
+
+    tracing::info!(
+        "[import] Job {} finished: {} songs, {} duplicates, {} errors",
+        job.id,
+        songs_added,
+        duplicates,
+        errors.len()
+    );
+    if songs_added > 0 {
+        crate::media::transcode::queue_new_songs(app, started);
+    }
+    publish(
+        app,
+        AppEvent::ImportComplete(ImportComplete {
+            job_id: job.id,
+            songs_added,
+            duplicates,
+            errors,
+        }),
+    );
+}
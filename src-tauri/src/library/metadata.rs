@@ -0,0 +1,178 @@
+//! Tags and duration of audio/video files (ID3, Vorbis comments, FLAC,
+//! MP4 atoms) read with lofty.
+//!
+//! The scanner uses them for songs without lyrics metadata of their own
+//! (CD+G pairs, videos, loose audio), where the file name is only a guess,
+//! and for the duration of every song. Only headers and tag blocks are
+//! read, never the audio stream.
+
+use std::path::Path;
+
+use base64::Engine;
+use lofty::file::{AudioFile, TaggedFileExt};
+use lofty::picture::PictureType;
+use lofty::tag::{Accessor, ItemKey};
+use serde::Serialize;
+use serde_json::{json, Value};
+use tauri::AppHandle;
+
+use super::roots::require_in_library;
+use crate::access::{require_webview, Capability};
+use crate::paths::{long_path, nfc};
+
+#[derive(Debug, Clone, Default)]
+pub struct Artwork {
+    pub mime_type: String,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TrackMetadata {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub genre: Option<String>,
+    pub year: Option<u32>,
+    pub language: Option<String>,
+    pub duration_ms: u64,
+    /// Front cover, or the first picture if none is marked as such.
+    pub artwork: Option<Artwork>,
+}
+
+fn tag_text(value: Option<std::borrow::Cow<'_, str>>) -> Option<String> {
+    value.map(|v| nfc(v.trim())).filter(|v| !v.is_empty())
+}
+
+/// Read the tags of `path`. Files without tags still yield their duration.
+pub fn read(path: &Path) -> Result<TrackMetadata, String> {
+    let tagged = lofty::read_from_path(long_path(path))
+        .map_err(|e| format!("Failed to read tags of {}: {}", path.display(), e))?;
+    let mut metadata = TrackMetadata {
+        duration_ms: tagged.properties().duration().as_millis() as u64,
+        ..TrackMetadata::default()
+    };
+    let Some(tag) = tagged.primary_tag().or_else(|| tagged.first_tag()) else {
+        return Ok(metadata);
+    };
+    metadata.title = tag_text(tag.title());
+    metadata.artist = tag_text(tag.artist());
+    metadata.album = tag_text(tag.album());
+    metadata.genre = tag_text(tag.genre());
+    metadata.year = tag.year().filter(|y| *y > 0);
+    metadata.language = tag.get_string(&ItemKey::Language).map(str::trim).filter(|l| !l.is_empty()).map(String::from);
+    let pictures = tag.pictures();
+    metadata.artwork = pictures
+        .iter()
+        .find(|p| p.pic_type() == PictureType::CoverFront)
+        .or_else(|| pictures.first())
+        .map(|p| Artwork {
+            mime_type: p.mime_type().map(|m| m.as_str().to_string()).unwrap_or_else(|| "image/jpeg".to_string()),
+            data: p.data().to_vec(),
+        });
+    Ok(metadata)
+}
+
+/// Fill a song entry from tags. With `names_are_guesses`, tag title and
+/// artist replace the ones parsed from the file name; tags never override
+/// what an UltraStar header says.
+pub fn apply_to_song(song: &mut Value, metadata: &TrackMetadata, names_are_guesses: bool) {
+    let mut set = |key: &str, value: Option<Value>, replace: bool| {
+        let missing = !matches!(song.get(key), Some(v) if !v.is_null());
+        if let Some(value) = value.filter(|_| replace || missing) {
+            song[key] = value;
+        }
+    };
+    set("title", metadata.title.clone().map(Value::from), names_are_guesses);
+    set("artist", metadata.artist.clone().map(Value::from), names_are_guesses);
+    set("album", metadata.album.clone().map(Value::from), false);
+    set("genre", metadata.genre.clone().map(Value::from), false);
+    set("year", metadata.year.map(|y| json!(y)), false);
+    set("language", metadata.language.clone().map(Value::from), false);
+    if metadata.duration_ms > 0 {
+        set("duration", Some(json!(metadata.duration_ms)), true);
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Serialize)]
+pub struct EmbeddedArtwork {
+    pub mime_type: String,
+    pub data_base64: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TrackMetadataResponse {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub genre: Option<String>,
+    pub year: Option<u32>,
+    pub language: Option<String>,
+    pub duration_ms: u64,
+    pub has_artwork: bool,
+    /// Only with `include_artwork`.
+    pub artwork: Option<EmbeddedArtwork>,
+}
+
+/// Tags, duration and (optionally) the embedded cover of an audio or video file.
+#[tauri::command]
+pub async fn get_track_metadata(
+    app: AppHandle,
+    webview: tauri::Webview,
+    path: String,
+    include_artwork: Option<bool>,
+) -> Result<TrackMetadataResponse, String> {
+    require_webview(&webview, Capability::ViewLibrary)?;
+    require_in_library(&app, &path)?;
+    let metadata = tauri::async_runtime::spawn_blocking(move || read(Path::new(&path)))
+        .await
+        .map_err(|e| e.to_string())??;
+    let artwork = metadata.artwork.as_ref().filter(|_| include_artwork.unwrap_or(false)).map(|a| EmbeddedArtwork {
+        mime_type: a.mime_type.clone(),
+        data_base64: base64::engine::general_purpose::STANDARD.encode(&a.data),
+    });
+    Ok(TrackMetadataResponse {
+        has_artwork: metadata.artwork.is_some(),
+        artwork,
+        title: metadata.title,
+        artist: metadata.artist,
+        album: metadata.album,
+        genre: metadata.genre,
+        year: metadata.year,
+        language: metadata.language,
+        duration_ms: metadata.duration_ms,
+    })
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_replace_guesses_but_not_headers() {
+        let metadata = TrackMetadata {
+            title: Some("Radio Ga Ga".to_string()),
+            artist: Some("Queen".to_string()),
+            year: Some(1984),
+            duration_ms: 343_000,
+            ..TrackMetadata::default()
+        };
+        let mut guessed = json!({ "title": "SC8123-05 radio", "artist": "Unknown Artist", "year": null });
+        apply_to_song(&mut guessed, &metadata, true);
+        assert_eq!(guessed["title"], "Radio Ga Ga");
+        assert_eq!(guessed["year"], 1984);
+        assert_eq!(guessed["duration"], 343_000);
+
+        let mut ultrastar = json!({ "title": "Radio Ga Ga (Live)", "artist": "Queen", "year": 1986 });
+        apply_to_song(&mut ultrastar, &metadata, false);
+        assert_eq!(ultrastar["title"], "Radio Ga Ga (Live)");
+        assert_eq!(ultrastar["year"], 1986);
+    }
+}
@@ -0,0 +1,22 @@
+//! Native song library management.
+//!
+//! Scanning and importing happen in Rust so they can run without the
+//! frontend — from the CLI (`karaoke scan <dir>`) as well as from the GUI.
+//! Results are written to the same `songs` table the frontend reads.
+
+pub mod archive;
+pub mod artwork;
+pub mod commands;
+pub mod data_migration;
+pub mod deletion;
+pub mod drop_import;
+pub mod formats;
+pub mod import_queue;
+pub mod metadata;
+pub mod quota;
+pub mod roots;
+pub mod scan_pool;
+pub mod scanner;
+pub mod songbook;
+pub mod ultrastar;
+pub mod watcher;
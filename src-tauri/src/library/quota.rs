@@ -0,0 +1,385 @@
+//! Disk quotas for the downloads, recordings and stems directories.
+//!
+//! Venue machines run for months; downloaded videos, recorded
+//! performances and separated stems pile up until the disk is full and the
+//! show stops. Each of these directories can get a quota. Usage is
+//! reported on demand and checked by the `storage_quota` scheduled task,
+//! which warns (`storage://quota-exceeded`) but never deletes anything.
+//!
+//! Cleanup is always a two-step, user-confirmed affair:
+//! `get_cleanup_suggestions` lists the oldest files that would bring the
+//! directory back under 90 % of its quota, skipping anything that belongs
+//! to a favorite song (its folder or its id in the file name);
+//! `apply_cleanup` moves the confirmed files to the trash.
+//!
+//! Settings (`app_settings`):
+//!   - `storage_dir:<kind>`      — directory, default `<app data>/<kind>`
+//!   - `storage_quota_mb:<kind>` — quota in MiB, absent or 0 = unlimited
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+use crate::access::{require_webview, Capability};
+use crate::db::DbState;
+use crate::events::{publish, AppEvent};
+use crate::paths::long_path;
+use crate::scheduler::read_setting;
+
+pub const QUOTA_EXCEEDED_EVENT: &str = "storage://quota-exceeded";
+
+/// Cleanup aims this far below the quota, so it is not needed again at
+/// the next download.
+const CLEANUP_TARGET: f64 = 0.9;
+const FAVORITES_PLAYLIST: &str = "system-favorites";
+const MIB: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageKind {
+    Downloads,
+    Recordings,
+    Stems,
+}
+
+pub const ALL_KINDS: &[StorageKind] = &[StorageKind::Downloads, StorageKind::Recordings, StorageKind::Stems];
+
+impl StorageKind {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            StorageKind::Downloads => "downloads",
+            StorageKind::Recordings => "recordings",
+            StorageKind::Stems => "stems",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DirUsage {
+    pub kind: StorageKind,
+    pub path: String,
+    pub used_bytes: u64,
+    pub file_count: usize,
+    /// `None` = unlimited.
+    pub quota_bytes: Option<u64>,
+    pub over_quota: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredFile {
+    pub path: String,
+    pub bytes: u64,
+    /// Epoch ms of the last modification.
+    pub modified: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CleanupSuggestion {
+    pub kind: StorageKind,
+    /// Oldest first; deleting all of them reaches the target.
+    pub files: Vec<StoredFile>,
+    pub reclaim_bytes: u64,
+    /// Bytes held by favorites, never suggested.
+    pub protected_bytes: u64,
+    /// False when even without every unprotected file the quota is exceeded.
+    pub reaches_target: bool,
+}
+
+// ---------------------------------------------------------------------------
+// Configuration
+// ---------------------------------------------------------------------------
+
+fn setting(app: &AppHandle, key: &str) -> Option<String> {
+    let db = app.try_state::<DbState>()?;
+    let conn = db.conn.lock().ok()?;
+    read_setting(&conn, key).filter(|v| !v.trim().is_empty())
+}
+
+/// Settings key holding the directory of `kind`.
+pub(crate) fn dir_key(kind: StorageKind) -> String {
+    format!("storage_dir:{}", kind.as_str())
+}
+
+pub fn storage_dir(app: &AppHandle, kind: StorageKind) -> Result<PathBuf, String> {
+    if let Some(dir) = setting(app, &dir_key(kind)) {
+        return Ok(PathBuf::from(dir.trim()));
+    }
+    app.path()
+        .app_data_dir()
+        .map(|dir| dir.join(kind.as_str()))
+        .map_err(|e| format!("No app data dir: {}", e))
+}
+
+fn quota_bytes(app: &AppHandle, kind: StorageKind) -> Option<u64> {
+    setting(app, &format!("storage_quota_mb:{}", kind.as_str()))
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .filter(|&mb| mb > 0)
+        .map(|mb| mb * MIB)
+}
+
+/// Folders and ids of favorite songs.
+fn favorites(app: &AppHandle) -> (Vec<PathBuf>, HashSet<String>) {
+    let Some(db) = app.try_state::<DbState>() else { return Default::default() };
+    let Ok(conn) = db.conn.lock() else { return Default::default() };
+    let ids: HashSet<String> = conn
+        .query_row("SELECT song_ids FROM playlists WHERE id = ?1", [FAVORITES_PLAYLIST], |row| {
+            row.get::<_, Option<String>>(0)
+        })
+        .ok()
+        .flatten()
+        .and_then(|json| serde_json::from_str::<Vec<String>>(&json).ok())
+        .unwrap_or_default()
+        .into_iter()
+        .collect();
+    let mut folders = Vec::new();
+    if let Ok(mut stmt) = conn.prepare("SELECT folder_path FROM songs WHERE id = ?1") {
+        for id in &ids {
+            if let Ok(folder) = stmt.query_row([id], |row| row.get::<_, String>(0)) {
+                if !folder.is_empty() {
+                    folders.push(PathBuf::from(folder));
+                }
+            }
+        }
+    }
+    (folders, ids)
+}
+
+fn is_protected(path: &Path, folders: &[PathBuf], ids: &HashSet<String>) -> bool {
+    if folders.iter().any(|f| path.starts_with(f)) {
+        return true;
+    }
+    let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    ids.iter().any(|id| !id.is_empty() && name.contains(id.as_str()))
+}
+
+// ---------------------------------------------------------------------------
+// Scanning and planning
+// ---------------------------------------------------------------------------
+
+/// All regular files under `dir`, recursively.
+pub(crate) fn list_files(dir: &Path) -> Vec<StoredFile> {
+    let mut files = Vec::new();
+    let mut stack = vec![dir.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        let Ok(entries) = std::fs::read_dir(long_path(&dir)) else { continue };
+        for entry in entries.flatten() {
+            let Ok(meta) = entry.metadata() else { continue };
+            let path = dir.join(entry.file_name());
+            if meta.is_dir() {
+                stack.push(path);
+            } else if meta.is_file() {
+                let modified = meta
+                    .modified()
+                    .ok()
+                    .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                    .map(|d| d.as_millis() as i64)
+                    .unwrap_or(0);
+                files.push(StoredFile {
+                    path: path.to_string_lossy().to_string(),
+                    bytes: meta.len(),
+                    modified,
+                });
+            }
+        }
+    }
+    files
+}
+
+/// Oldest unprotected files whose removal brings `files` down to
+/// `target_bytes`. Returns (suggested, protected bytes, target reached).
+fn plan_cleanup(
+    mut files: Vec<StoredFile>,
+    target_bytes: u64,
+    protected: impl Fn(&Path) -> bool,
+) -> (Vec<StoredFile>, u64, bool) {
+    let mut used: u64 = files.iter().map(|f| f.bytes).sum();
+    files.sort_by_key(|f| f.modified);
+    let mut suggested = Vec::new();
+    let mut protected_bytes = 0;
+    for file in files {
+        if protected(Path::new(&file.path)) {
+            protected_bytes += file.bytes;
+            continue;
+        }
+        if used <= target_bytes {
+            continue;
+        }
+        used -= file.bytes;
+        suggested.push(file);
+    }
+    (suggested, protected_bytes, used <= target_bytes)
+}
+
+fn usage(app: &AppHandle, kind: StorageKind) -> Result<(DirUsage, Vec<StoredFile>), String> {
+    let dir = storage_dir(app, kind)?;
+    let files = list_files(&dir);
+    let used_bytes = files.iter().map(|f| f.bytes).sum();
+    let quota = quota_bytes(app, kind);
+    Ok((
+        DirUsage {
+            kind,
+            path: dir.to_string_lossy().to_string(),
+            used_bytes,
+            file_count: files.len(),
+            quota_bytes: quota,
+            over_quota: quota.is_some_and(|q| used_bytes > q),
+        },
+        files,
+    ))
+}
+
+/// Scheduled task: warn about every directory over its quota.
+pub fn check_quotas(app: &AppHandle) -> Result<String, String> {
+    let mut over = Vec::new();
+    for &kind in ALL_KINDS {
+        let (usage, _) = usage(app, kind)?;
+        if usage.over_quota {
+            over.push(format!("{} {} MiB", kind.as_str(), usage.used_bytes / MIB));
+            publish(app, AppEvent::StorageQuotaExceeded(usage));
+        }
+    }
+    Ok(if over.is_empty() { "All directories within quota".to_string() } else { format!("Over quota: {}", over.join(", ")) })
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+#[tauri::command]
+pub async fn get_storage_usage(app: AppHandle) -> Result<Vec<DirUsage>, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        ALL_KINDS.iter().map(|&kind| usage(&app, kind).map(|(u, _)| u)).collect()
+    })
+    .await
+    .map_err(|e| format!("Storage scan failed: {}", e))?
+}
+
+/// Set the quota (MiB, `None` or 0 = unlimited) and optionally move the
+/// directory setting for `kind`.
+#[tauri::command]
+pub fn set_storage_quota(
+    app: AppHandle,
+    webview: tauri::Webview,
+    kind: StorageKind,
+    quota_mb: Option<u64>,
+    path: Option<String>,
+) -> Result<(), String> {
+    require_webview(&webview, Capability::ChangeSettings)?;
+    let db = app.state::<DbState>();
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT OR REPLACE INTO app_settings (key, value) VALUES (?1, ?2)",
+        (format!("storage_quota_mb:{}", kind.as_str()), quota_mb.unwrap_or(0).to_string()),
+    )
+    .map_err(|e| format!("Failed to save quota: {}", e))?;
+    if let Some(path) = path {
+        conn.execute(
+            "INSERT OR REPLACE INTO app_settings (key, value) VALUES (?1, ?2)",
+            (dir_key(kind), path.trim()),
+        )
+        .map_err(|e| format!("Failed to save storage dir: {}", e))?;
+    }
+    Ok(())
+}
+
+/// Oldest files that would bring `kind` back under its quota. Nothing is
+/// deleted.
+#[tauri::command]
+pub async fn get_cleanup_suggestions(app: AppHandle, kind: StorageKind) -> Result<CleanupSuggestion, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let (usage, files) = usage(&app, kind)?;
+        let target = usage
+            .quota_bytes
+            .map(|q| (q as f64 * CLEANUP_TARGET) as u64)
+            .unwrap_or(u64::MAX);
+        let (folders, ids) = favorites(&app);
+        let (files, protected_bytes, reaches_target) = plan_cleanup(files, target, |p| is_protected(p, &folders, &ids));
+        Ok(CleanupSuggestion {
+            kind,
+            reclaim_bytes: files.iter().map(|f| f.bytes).sum(),
+            files,
+            protected_bytes,
+            reaches_target,
+        })
+    })
+    .await
+    .map_err(|e| format!("Storage scan failed: {}", e))?
+}
+
+/// Move the confirmed files to the trash. Only files inside the `kind`
+/// directory that do not belong to a favorite are touched. Returns the
+/// bytes freed.
+#[tauri::command]
+pub fn apply_cleanup(app: AppHandle, webview: tauri::Webview, kind: StorageKind, paths: Vec<String>) -> Result<u64, String> {
+    require_webview(&webview, Capability::ManageLibrary)?;
+    let dir = storage_dir(&app, kind)?;
+    let (folders, ids) = favorites(&app);
+    let mut freed = 0;
+    let mut errors = Vec::new();
+    for path in paths {
+        let path = PathBuf::from(&path);
+        if !path.starts_with(&dir) || path.components().any(|c| matches!(c, std::path::Component::ParentDir)) {
+            errors.push(format!("{} is outside the {} directory", path.display(), kind.as_str()));
+            continue;
+        }
+        if is_protected(&path, &folders, &ids) {
+            errors.push(format!("{} belongs to a favorite", path.display()));
+            continue;
+        }
+        let bytes = std::fs::metadata(long_path(&path)).map(|m| m.len()).unwrap_or(0);
+        match trash::delete(&path) {
+            Ok(()) => freed += bytes,
+            Err(e) => errors.push(format!("{}: {}", path.display(), e)),
+        }
+    }
+    tracing::info!("[storage] Cleanup of {} freed {} MiB", kind.as_str(), freed / MIB);
+    if errors.is_empty() {
+        Ok(freed)
+    } else {
+        Err(format!("Freed {} MiB; skipped: {}", freed / MIB, errors.join("; ")))
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, bytes: u64, modified: i64) -> StoredFile {
+        StoredFile { path: path.into(), bytes, modified }
+    }
+
+    #[test]
+    fn suggests_oldest_first_until_under_target() {
+        let files = vec![file("/r/c", 30, 3), file("/r/a", 50, 1), file("/r/b", 40, 2)];
+        // 120 used, target 80 → dropping the oldest (50) is enough
+        let (suggested, protected, reaches) = plan_cleanup(files, 80, |_| false);
+        assert_eq!(suggested, vec![file("/r/a", 50, 1)]);
+        assert_eq!(protected, 0);
+        assert!(reaches);
+    }
+
+    #[test]
+    fn never_suggests_favorites() {
+        let ids: HashSet<String> = ["song-42".to_string()].into();
+        let folders = vec![PathBuf::from("/lib/Fav Song")];
+        let files = vec![
+            file("/r/song-42-take1.wav", 100, 1),
+            file("/lib/Fav Song/stems.zip", 100, 2),
+            file("/r/other.wav", 10, 3),
+        ];
+        let (suggested, protected, reaches) = plan_cleanup(files, 50, |p| is_protected(p, &folders, &ids));
+        assert_eq!(suggested, vec![file("/r/other.wav", 10, 3)]);
+        assert_eq!(protected, 200);
+        assert!(!reaches);
+    }
+}
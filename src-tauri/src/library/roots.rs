@@ -0,0 +1,81 @@
+//! Folders a webview may have files read from.
+//!
+//! Commands that take a path from the frontend (song files, lyrics, tags,
+//! analysis) only read inside the library: the `root_folders`, the data
+//! directories (`quota`) and the app cache (converted media, rendered
+//! `.kar` audio). Any other path is refused, so a guest window cannot
+//! use them to read the rest of the disk.
+
+use std::path::{Component, Path, PathBuf};
+
+use tauri::{AppHandle, Manager};
+
+use super::quota::{self, ALL_KINDS};
+use crate::db::DbState;
+use crate::paths::path_key;
+
+/// Every folder library files may lie under.
+pub fn allowed_roots(app: &AppHandle) -> Vec<PathBuf> {
+    let mut roots: Vec<PathBuf> = app
+        .try_state::<DbState>()
+        .and_then(|db| {
+            let conn = db.conn.lock().ok()?;
+            let mut stmt = conn.prepare("SELECT path FROM root_folders").ok()?;
+            let rows = stmt.query_map([], |row| row.get::<_, String>(0)).ok()?;
+            Some(rows.flatten().map(PathBuf::from).collect())
+        })
+        .unwrap_or_default();
+    roots.extend(ALL_KINDS.iter().filter_map(|&kind| quota::storage_dir(app, kind).ok()));
+    roots.extend(app.path().app_cache_dir().ok());
+    roots
+}
+
+/// Whether `path` lies in (or is) one of `roots`, compared as `path_key`s.
+/// Paths with `..` never do.
+fn is_within(roots: &[PathBuf], path: &Path) -> bool {
+    if path.components().any(|c| matches!(c, Component::ParentDir)) || !path.is_absolute() {
+        return false;
+    }
+    let key = path_key(&path.to_string_lossy());
+    roots.iter().any(|root| {
+        let root = path_key(&root.to_string_lossy());
+        if root.is_empty() {
+            return false;
+        }
+        let prefix = if root.ends_with('/') { root.clone() } else { format!("{}/", root) };
+        key == root || key.starts_with(&prefix)
+    })
+}
+
+/// `Err` unless `path` lies inside the library.
+pub fn require_in_library(app: &AppHandle, path: &str) -> Result<(), String> {
+    if is_within(&allowed_roots(app), Path::new(path)) {
+        Ok(())
+    } else {
+        tracing::warn!("[access] Refused to read {} outside the library", path);
+        Err(format!("Not in the library: {}", path))
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_paths_under_a_root_are_inside() {
+        let root = crate::paths::test_dir("library-roots");
+        let roots = [root.join("songs")];
+        assert!(is_within(&roots, &root.join("songs").join("a").join("song.txt")));
+        assert!(is_within(&roots, &root.join("songs")));
+        assert!(!is_within(&roots, &root.join("songs-private").join("x.txt")));
+        assert!(!is_within(&roots, &root.join("songs").join("..").join("secret.txt")));
+        assert!(!is_within(&roots, &root.join("elsewhere.txt")));
+        assert!(!is_within(&roots, Path::new("songs/a.txt")));
+        assert!(!is_within(&[], &root.join("songs").join("a.txt")));
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
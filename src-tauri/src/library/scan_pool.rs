@@ -0,0 +1,346 @@
+//! Parallel scan engine.
+//!
+//! A scan runs in two phases:
+//!   1. discovery — a single-threaded directory walk that collects every
+//!      candidate song file, so the total is known before parsing starts and
+//!      progress is exact;
+//!   2. parsing — files are read, hashed and probed on a rayon pool. Results
+//!      flow through a bounded channel to the calling thread, which hands
+//!      them to the sink in batches (one DB transaction per batch).
+//!
+//! The bounded channel gives backpressure: if the database falls behind,
+//! workers block instead of piling parsed songs up in memory.
+//!
+//! A scan stops early when `ScanOptions::cancel` fires: discovery stops
+//! descending, workers skip the remaining files, and the songs parsed so far
+//! still reach the sink.
+
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, Instant, SystemTime};
+
+use rayon::prelude::*;
+use serde::Serialize;
+use serde_json::Value;
+use tokio_util::sync::CancellationToken;
+
+use super::artwork;
+use super::formats::{self, Candidate};
+use super::scanner::{self, ScanReport};
+use crate::db::DbState;
+use crate::paths::{display_path, long_path};
+
+/// Songs per sink call / DB transaction.
+const DEFAULT_BATCH_SIZE: usize = 500;
+/// Parsed-but-unwritten results allowed in flight.
+const QUEUE_CAPACITY: usize = 2048;
+/// Minimum interval between progress callbacks.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone)]
+pub struct ScanOptions {
+    /// Only parse files modified after this (incremental rescans).
+    pub since: Option<SystemTime>,
+    /// Worker threads; 0 = one per core, minus one for the UI and audio.
+    pub threads: usize,
+    pub batch_size: usize,
+    pub cancel: Option<CancellationToken>,
+    /// Cache cover thumbnails here while parsing (see `artwork`).
+    pub artwork_dir: Option<PathBuf>,
+}
+
+impl ScanOptions {
+    fn cancelled(&self) -> bool {
+        self.cancel.as_ref().is_some_and(|c| c.is_cancelled())
+    }
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            since: None,
+            threads: 0,
+            batch_size: DEFAULT_BATCH_SIZE,
+            cancel: None,
+            artwork_dir: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanPhase {
+    Discovering,
+    Parsing,
+    Done,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScanProgress {
+    pub root: String,
+    pub phase: ScanPhase,
+    pub files_total: usize,
+    pub files_done: usize,
+    pub songs_found: usize,
+    pub songs_saved: usize,
+    pub errors: usize,
+}
+
+enum FileResult {
+    Songs(Vec<Value>),
+    Skipped,
+    Error(String),
+}
+
+/// Scan `root`, passing parsed songs to `on_batch` in batches and reporting
+/// progress to `on_progress`. The returned report carries counts and errors
+/// but no songs — those went to the sink.
+pub fn scan(
+    root: &Path,
+    options: &ScanOptions,
+    mut on_batch: impl FnMut(Vec<Value>) -> Result<usize, String>,
+    mut on_progress: impl FnMut(&ScanProgress),
+) -> Result<ScanReport, String> {
+    if !long_path(root).is_dir() {
+        return Err(format!("Not a directory: {}", root.display()));
+    }
+
+    let mut report = ScanReport::default();
+    let mut progress = ScanProgress {
+        root: root.to_string_lossy().to_string(),
+        phase: ScanPhase::Discovering,
+        files_total: 0,
+        files_done: 0,
+        songs_found: 0,
+        songs_saved: 0,
+        errors: 0,
+    };
+
+    // Phase 1: discovery
+    on_progress(&progress);
+    let files = discover(root, options, &mut report);
+    progress.phase = ScanPhase::Parsing;
+    progress.files_total = files.len();
+    progress.errors = report.errors.len();
+    on_progress(&progress);
+
+    // Phase 2: parallel parsing, batched sink on this thread
+    let pool = build_pool(options.threads)?;
+    let batch_size = options.batch_size.max(1);
+    let (tx, rx) = mpsc::sync_channel::<FileResult>(QUEUE_CAPACITY);
+
+    let sink_result = std::thread::scope(|s| {
+        s.spawn(|| {
+            pool.install(|| {
+                files.par_iter().for_each_with(tx, |tx, candidate| {
+                    if options.cancelled() {
+                        return;
+                    }
+                    let result = match parse_candidate(candidate, options) {
+                        Ok(songs) if songs.is_empty() => FileResult::Skipped,
+                        Ok(songs) => FileResult::Songs(songs),
+                        Err(e) => FileResult::Error(e),
+                    };
+                    // The receiver only goes away when the sink failed
+                    let _ = tx.send(result);
+                });
+            });
+        });
+
+        let mut batch = Vec::with_capacity(batch_size);
+        let mut last_report = Instant::now();
+        let mut sink_error = None;
+        for result in rx {
+            if options.cancelled() {
+                break;
+            }
+            progress.files_done += 1;
+            match result {
+                FileResult::Songs(songs) => {
+                    progress.songs_found += songs.len();
+                    batch.extend(songs);
+                }
+                FileResult::Skipped => report.files_skipped += 1,
+                FileResult::Error(e) => {
+                    report.errors.push(e);
+                    progress.errors += 1;
+                }
+            }
+            if batch.len() >= batch_size {
+                match on_batch(std::mem::take(&mut batch)) {
+                    Ok(n) => progress.songs_saved += n,
+                    Err(e) => {
+                        sink_error = Some(e);
+                        break;
+                    }
+                }
+            }
+            if last_report.elapsed() >= PROGRESS_INTERVAL {
+                on_progress(&progress);
+                last_report = Instant::now();
+            }
+        }
+        match sink_error {
+            Some(e) => Err(e),
+            None if batch.is_empty() => Ok(()),
+            None => on_batch(batch).map(|n| progress.songs_saved += n),
+        }
+    });
+    sink_result?;
+
+    report.cancelled = options.cancelled();
+    progress.phase = if report.cancelled { ScanPhase::Cancelled } else { ScanPhase::Done };
+    on_progress(&progress);
+    report.songs_found = progress.songs_found;
+    Ok(report)
+}
+
+/// Scan `root` straight into the database, one transaction per batch.
+/// The DB lock is only held while a batch is written.
+pub fn scan_into_db(
+    db: &DbState,
+    root: &Path,
+    options: &ScanOptions,
+    on_progress: impl FnMut(&ScanProgress),
+) -> Result<ScanReport, String> {
+    scan(
+        root,
+        options,
+        |batch| {
+            let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+            scanner::save_songs(&mut conn, &batch)
+        },
+        on_progress,
+    )
+}
+
+/// Song entries for `candidate`, with covers cached if `options` ask for it.
+pub(crate) fn parse_candidate(candidate: &Candidate, options: &ScanOptions) -> Result<Vec<Value>, String> {
+    let Some(dir) = &options.artwork_dir else { return scanner::song_from_candidate(candidate) };
+    let mut songs = Vec::new();
+    for (mut song, embedded) in scanner::songs_with_pictures(candidate)? {
+        // A broken cover never costs the song
+        if let Err(e) = artwork::cache_for_song(dir, &mut song, embedded) {
+            tracing::warn!("[library] Cover of {}: {}", display_path(candidate.path()).display(), e);
+        }
+        songs.push(song);
+    }
+    Ok(songs)
+}
+
+/// Walk `root` and collect song candidates (see `formats`), skipping files
+/// not modified since `options.since`.
+fn discover(root: &Path, options: &ScanOptions, report: &mut ScanReport) -> Vec<Candidate> {
+    let mut candidates = Vec::new();
+    let mut stack: Vec<PathBuf> = vec![root.to_path_buf()];
+
+    while let Some(dir) = stack.pop() {
+        if options.cancelled() {
+            break;
+        }
+        let entries = match std::fs::read_dir(long_path(&dir)) {
+            Ok(e) => e,
+            Err(e) => {
+                report.errors.push(format!("{}: {}", dir.display(), e));
+                continue;
+            }
+        };
+        let mut files = Vec::new();
+        for entry in entries.flatten() {
+            let path = display_path(&entry.path());
+            // file_type() does not follow symlinks, so linked dirs cannot loop
+            let Ok(file_type) = entry.file_type() else { continue };
+            if file_type.is_dir() {
+                stack.push(path);
+            } else {
+                files.push(path);
+            }
+        }
+        for candidate in formats::classify_dir(&files) {
+            if let Some(since) = options.since {
+                let modified = std::fs::metadata(long_path(candidate.path())).and_then(|m| m.modified());
+                if matches!(modified, Ok(t) if t <= since) {
+                    report.files_skipped += 1;
+                    continue;
+                }
+            }
+            candidates.push(candidate);
+        }
+    }
+    candidates
+}
+
+fn build_pool(threads: usize) -> Result<rayon::ThreadPool, String> {
+    let threads = match threads {
+        0 => std::thread::available_parallelism()
+            .map(|n| n.get().saturating_sub(1).max(1))
+            .unwrap_or(2),
+        n => n,
+    };
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .thread_name(|i| format!("karaoke-scan-{}", i))
+        .build()
+        .map_err(|e| format!("Failed to start scan pool: {}", e))
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn batches_every_song_and_reports_exact_totals() {
+        let root = crate::paths::test_dir("scan-pool");
+        for i in 0..7 {
+            let dir = root.join(format!("song{}", i));
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join("song.txt"), format!("#TITLE:Song {}\n#ARTIST:Band\n#MP3:a.mp3\n", i)).unwrap();
+        }
+        std::fs::write(root.join("notes.txt"), "just some notes\n").unwrap();
+
+        let options = ScanOptions { threads: 2, batch_size: 3, ..ScanOptions::default() };
+        let mut batches = Vec::new();
+        let mut last = None;
+        let report = scan(
+            &root,
+            &options,
+            |batch| {
+                batches.push(batch.len());
+                Ok(batch.len())
+            },
+            |p| last = Some(p.clone()),
+        )
+        .unwrap();
+        let _ = std::fs::remove_dir_all(&root);
+
+        assert_eq!(batches, vec![3, 3, 1]);
+        assert_eq!(report.songs_found, 7);
+        assert_eq!(report.files_skipped, 1);
+        let last = last.unwrap();
+        assert_eq!(last.phase, ScanPhase::Done);
+        assert_eq!((last.files_total, last.files_done, last.songs_saved), (8, 8, 7));
+    }
+
+    #[test]
+    fn cancelled_scan_stops_before_parsing() {
+        let root = crate::paths::test_dir("scan-cancel");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("song.txt"), "#TITLE:Song\n#ARTIST:Band\n").unwrap();
+
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let options = ScanOptions { cancel: Some(cancel), ..ScanOptions::default() };
+        let mut last = None;
+        let report = scan(&root, &options, |batch| Ok(batch.len()), |p| last = Some(p.clone())).unwrap();
+        let _ = std::fs::remove_dir_all(&root);
+
+        assert!(report.cancelled);
+        assert_eq!(report.songs_found, 0);
+        assert_eq!(last.unwrap().phase, ScanPhase::Cancelled);
+    }
+}
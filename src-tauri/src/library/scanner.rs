@@ -0,0 +1,404 @@
+//! Song library scanner.
+//!
+//! Turns every song of a directory tree — UltraStar `.txt` files and the
+//! karaoke formats in `formats` — into a song entry (same JSON shape the
+//! frontend stores via `db_save_songs`, plus `format`) and upserts it
+//! into the `songs` table. Existing songs outside the scanned tree are left
+//! untouched. Directory walks run on the parallel engine in `scan_pool`.
+
+use std::path::{Path, PathBuf};
+
+use rusqlite::Connection;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use super::archive;
+use super::artwork::Embedded;
+use super::formats::{self, Candidate, SongFormat};
+use super::metadata;
+use super::scan_pool::{self, ScanOptions};
+use super::ultrastar::{self, UltraStarHeader};
+use crate::paths::{display_path, long_path, nfc, normalize_path};
+use crate::db::commands::upsert_song;
+
+/// Audio extensions accepted by `import_path` for loose audio files.
+pub const AUDIO_EXTENSIONS: &[&str] = &["mp3", "m4a", "aac", "ogg", "opus", "flac", "wav"];
+
+/// Outcome of a scan or import.
+#[derive(Debug, Default, Serialize)]
+pub struct ScanReport {
+    /// Song entries found, in frontend JSON shape.
+    #[serde(skip)]
+    pub songs: Vec<Value>,
+    pub songs_found: usize,
+    pub files_skipped: usize,
+    pub errors: Vec<String>,
+    /// Stopped early through `ScanOptions::cancel`.
+    pub cancelled: bool,
+}
+
+impl ScanReport {
+    fn push(&mut self, song: Value) {
+        self.songs.push(song);
+        self.songs_found += 1;
+    }
+}
+
+/// Recursively scan `root` for songs.
+///
+/// Files are parsed on the scan pool and all songs are collected in the
+/// report. Large libraries should use [`scan_pool::scan_into_db`] instead,
+/// which writes in batches as it goes.
+pub fn scan_directory(root: &Path) -> Result<ScanReport, String> {
+    let options = ScanOptions::default();
+    let mut songs = Vec::new();
+    let mut report = scan_pool::scan(
+        root,
+        &options,
+        |batch| {
+            let n = batch.len();
+            songs.extend(batch);
+            Ok(n)
+        },
+        |_| {},
+    )?;
+    report.songs = songs;
+    Ok(report)
+}
+
+/// Import a single path: a directory is scanned, a file is parsed as the
+/// karaoke format it is (`formats`; a `.cdg` with the audio next to it),
+/// and a loose audio file becomes a song entry named after the file.
+pub fn import_path(path: &Path) -> Result<ScanReport, String> {
+    if long_path(path).is_dir() {
+        return scan_directory(path);
+    }
+    if !long_path(path).is_file() {
+        return Err(format!("File not found: {}", path.display()));
+    }
+
+    let mut report = ScanReport::default();
+    let candidates = candidates_for(path);
+    if candidates.is_empty() {
+        if !has_extension(path, AUDIO_EXTENSIONS) {
+            return Err(format!("Unsupported file type: {}", path.display()));
+        }
+        report.push(song_from_audio(path));
+    }
+    for candidate in &candidates {
+        let songs = song_from_candidate(candidate)?;
+        if songs.is_empty() && matches!(candidate, Candidate::UltraStar(_)) {
+            return Err(format!("Not an UltraStar song file: {}", path.display()));
+        }
+        songs.into_iter().for_each(|song| report.push(song));
+    }
+    Ok(report)
+}
+
+/// Candidates for one file, detected among its siblings like a scan does
+/// (a `.cdg` is only a song with its audio next to it).
+pub fn candidates_for(file: &Path) -> Vec<Candidate> {
+    let Some(name) = file.file_name() else { return Vec::new() };
+    let siblings: Vec<PathBuf> = file
+        .parent()
+        .and_then(|dir| std::fs::read_dir(long_path(dir)).ok())
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|entry| entry.file_type().is_ok_and(|t| t.is_file()))
+                .map(|entry| display_path(&entry.path()))
+                .collect()
+        })
+        .unwrap_or_default();
+    formats::classify_dir(&siblings)
+        .into_iter()
+        .filter(|candidate| match candidate {
+            Candidate::Cdg { cdg, audio } => cdg.file_name() == Some(name) || audio.file_name() == Some(name),
+            other => other.path().file_name() == Some(name),
+        })
+        .collect()
+}
+
+/// Upsert all songs of a report in one transaction. Returns the row count.
+pub fn save_songs(conn: &mut Connection, songs: &[Value]) -> Result<usize, String> {
+    let tx = conn.transaction().map_err(|e| format!("Transaction failed: {}", e))?;
+    let mut count = 0;
+    for song in songs {
+        upsert_song(&tx, song)?;
+        count += 1;
+    }
+    tx.commit().map_err(|e| format!("Commit failed: {}", e))?;
+    Ok(count)
+}
+
+/// Build a song entry from an UltraStar file; `None` if it has no valid header.
+///
+/// The file is read once: its bytes are hashed (`contentHash`, lets later
+/// stages detect moved or edited files) and the referenced audio file is
+/// probed (`audioAvailable`) so broken songs can be flagged in the library.
+pub fn song_from_txt(path: &Path) -> Result<Option<Value>, String> {
+    Ok(txt_song(path)?.map(|(song, _)| song))
+}
+
+fn txt_song(path: &Path) -> Result<Option<(Value, Embedded)>, String> {
+    let bytes = std::fs::read(long_path(path)).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let header = ultrastar::parse_header(&ultrastar::decode_text(&bytes));
+    if header.title().is_none() || header.artist().is_none() {
+        return Ok(None);
+    }
+
+    let mut song = song_json(path, &header);
+    let audio = header.audio_file().zip(path.parent()).map(|(audio, dir)| dir.join(audio));
+    let audio_available = audio.as_ref().is_some_and(|audio| long_path(audio).is_file());
+    let mut embedded = Embedded::Unread;
+    if let Some(audio) = audio.filter(|_| audio_available) {
+        // Duration (and tags the header lacks) from the audio file
+        embedded = apply_tags(&mut song, &audio, false);
+    }
+    // A video may still carry the picture the audio lacks
+    if matches!(embedded, Embedded::Read(None)) && song.get("videoFileName").is_some_and(|v| v.is_string()) {
+        embedded = Embedded::Unread;
+    }
+    song["contentHash"] = json!(format!("{:016x}", fnv1a64(&bytes)));
+    song["audioAvailable"] = json!(audio_available);
+    Ok(Some((song, embedded)))
+}
+
+/// Song entries for any discovered candidate: none if it turns out not to
+/// be a song, several for an archive holding several tracks.
+pub fn song_from_candidate(candidate: &Candidate) -> Result<Vec<Value>, String> {
+    Ok(songs_with_pictures(candidate)?.into_iter().map(|(song, _)| song).collect())
+}
+
+/// `song_from_candidate`, with the embedded picture of the tags read on
+/// the way, so caching the cover does not read them again.
+pub(crate) fn songs_with_pictures(candidate: &Candidate) -> Result<Vec<(Value, Embedded)>, String> {
+    match candidate {
+        Candidate::UltraStar(txt) => Ok(txt_song(txt)?.into_iter().collect()),
+        Candidate::Cdg { cdg, audio } => {
+            let mut song = song_from_karaoke_file(cdg, SongFormat::Cdg);
+            song["audioFileName"] = json!(file_name(&normalize_path(audio)));
+            song["cdgFileName"] = json!(file_name(&normalize_path(cdg)));
+            let embedded = apply_tags(&mut song, audio, true);
+            Ok(vec![(song, embedded)])
+        }
+        Candidate::Zip(zip) => {
+            let tracks = archive::list_tracks(zip)?;
+            let single = tracks.len() == 1;
+            Ok(tracks.iter().map(|track| (song_from_archive_track(zip, track, single), Embedded::Unread)).collect())
+        }
+        Candidate::Kar(kar) => {
+            let mut song = song_from_karaoke_file(kar, SongFormat::Kar);
+            song["karFileName"] = json!(file_name(&normalize_path(kar)));
+            // `@T` titles beat the file name; an unreadable file is no song
+            let bytes = std::fs::read(long_path(kar)).map_err(|e| format!("Failed to read {}: {}", kar.display(), e))?;
+            let kar_song = crate::midi::parse(&bytes)?;
+            if let Some(title) = kar_song.title {
+                song["title"] = json!(nfc(&title));
+            }
+            if let Some(artist) = kar_song.artist {
+                song["artist"] = json!(nfc(&artist));
+            }
+            if kar_song.duration_ms > 0 {
+                song["duration"] = json!(kar_song.duration_ms);
+            }
+            Ok(vec![(song, Embedded::Unread)])
+        }
+        Candidate::Video(video) => {
+            let mut song = song_from_karaoke_file(video, SongFormat::Video);
+            song["videoFileName"] = json!(file_name(&normalize_path(video)));
+            let embedded = apply_tags(&mut song, video, true);
+            Ok(vec![(song, embedded)])
+        }
+    }
+}
+
+/// Entry for one track of a karaoke archive. A single-track archive is
+/// usually the better-named file, so it names the song; tracks of a pack
+/// are named after their members. Tags are not read: that would mean
+/// inflating the audio of every archive on every scan.
+fn song_from_archive_track(zip: &Path, track: &archive::ArchiveTrack, single: bool) -> Value {
+    let named_after = if single { zip.to_path_buf() } else { PathBuf::from(archive::member_path(zip, &track.audio)) };
+    let mut song = song_from_karaoke_file(&named_after, SongFormat::Zip);
+    let zip = normalize_path(zip);
+    song["id"] = json!(song_id(Path::new(&archive::member_path(&zip, &track.audio))));
+    song["folder"] = json!(zip.parent().and_then(|p| p.file_name()).map(|n| n.to_string_lossy().to_string()).unwrap_or_default());
+    song["folderPath"] = json!(zip.parent().unwrap_or(Path::new("")).to_string_lossy());
+    song["archiveFileName"] = json!(file_name(&zip));
+    song["archiveMember"] = json!(nfc(&track.audio));
+    song["cdgMember"] = json!(nfc(&track.cdg));
+    song
+}
+
+/// Merge the tags of `media` into `song`; unreadable tags are not an error,
+/// the entry just keeps what it has. Returns their picture.
+fn apply_tags(song: &mut Value, media: &Path, names_are_guesses: bool) -> Embedded {
+    match metadata::read(media) {
+        Ok(tags) => {
+            metadata::apply_to_song(song, &tags, names_are_guesses);
+            Embedded::Read(tags.artwork.map(|artwork| artwork.data))
+        }
+        Err(_) => Embedded::Read(None),
+    }
+}
+
+/// Entry for a karaoke file without lyrics metadata; artist and title come
+/// from the file name (and tags, see `song_from_candidate`).
+fn song_from_karaoke_file(path: &Path, format: SongFormat) -> Value {
+    let path = normalize_path(path);
+    let stem = path.file_stem().map(|s| nfc(&s.to_string_lossy())).unwrap_or_default();
+    let (artist, title) = formats::split_karaoke_name(&stem);
+    let folder_path = path.parent().unwrap_or(Path::new(""));
+
+    json!({
+        "id": song_id(&path),
+        "title": title,
+        "artist": artist.unwrap_or_else(|| "Unknown Artist".to_string()),
+        "format": format.as_str(),
+        "folder": folder_path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default(),
+        "folderPath": folder_path.to_string_lossy(),
+        "dateAdded": now_ms(),
+        "playCount": 0,
+    })
+}
+
+fn song_json(txt_path: &Path, header: &UltraStarHeader) -> Value {
+    let txt_path = normalize_path(txt_path);
+    let folder_path = txt_path.parent().unwrap_or(Path::new(""));
+    let folder = folder_path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let tag = |key: &str| header.get(key).map(nfc);
+
+    json!({
+        "id": song_id(&txt_path),
+        "title": nfc(header.title().unwrap_or_default()),
+        "artist": nfc(header.artist().unwrap_or_default()),
+        "format": SongFormat::UltraStar.as_str(),
+        "album": tag("ALBUM"),
+        "year": header.number("YEAR").map(|y| y as i64),
+        "genre": tag("GENRE"),
+        "language": tag("LANGUAGE"),
+        "bpm": header.number("BPM").unwrap_or(120.0),
+        "gap": header.number("GAP").unwrap_or(0.0),
+        "folder": folder,
+        "folderPath": folder_path.to_string_lossy(),
+        "txtFileName": file_name(&txt_path),
+        "audioFileName": header.audio_file().map(nfc),
+        "videoFileName": tag("VIDEO"),
+        "coverFileName": tag("COVER"),
+        "dateAdded": now_ms(),
+        "playCount": 0,
+    })
+}
+
+/// Song entry for a loose audio file. Tags win; without them
+/// `Artist - Title.mp3` is split into artist and title, otherwise the file
+/// stem is the title.
+pub fn song_from_audio(path: &Path) -> Value {
+    let path = normalize_path(path);
+    let path = path.as_path();
+    let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    let (artist, title) = match stem.split_once(" - ") {
+        Some((a, t)) => (a.trim().to_string(), t.trim().to_string()),
+        None => ("Unknown Artist".to_string(), stem.trim().to_string()),
+    };
+    let folder_path = path.parent().unwrap_or(Path::new(""));
+
+    let mut song = json!({
+        "id": song_id(path),
+        "title": title,
+        "artist": artist,
+        "folder": folder_path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default(),
+        "folderPath": folder_path.to_string_lossy(),
+        "audioFileName": file_name(path),
+        "dateAdded": now_ms(),
+        "playCount": 0,
+    });
+    apply_tags(&mut song, path, true);
+    song
+}
+
+/// Stable id derived from the (NFC-normalized) file path, so rescans —
+/// including from a macOS copy of the library — replace instead of duplicate.
+fn song_id(path: &Path) -> String {
+    format!("native-{:016x}", fnv1a64(path.to_string_lossy().as_bytes()))
+}
+
+/// FNV-1a 64 — stable across runs and Rust versions (unlike DefaultHasher).
+pub(crate) fn fnv1a64(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+pub(crate) fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| extensions.iter().any(|x| e.eq_ignore_ascii_case(x)))
+        .unwrap_or(false)
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default()
+}
+
+fn now_ms() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn audio_file_name_is_split_into_artist_and_title() {
+        let song = song_from_audio(Path::new("/music/Queen - Bohemian Rhapsody.mp3"));
+        assert_eq!(song["artist"], "Queen");
+        assert_eq!(song["title"], "Bohemian Rhapsody");
+        assert_eq!(song["audioFileName"], "Queen - Bohemian Rhapsody.mp3");
+    }
+
+    #[test]
+    fn cdg_pair_takes_names_from_the_file() {
+        let candidate = Candidate::Cdg {
+            cdg: "/k/SC8123-05 - Queen - Radio Ga Ga.cdg".into(),
+            audio: "/k/SC8123-05 - Queen - Radio Ga Ga.mp3".into(),
+        };
+        let song = song_from_candidate(&candidate).unwrap().remove(0);
+        assert_eq!(song["artist"], "Queen");
+        assert_eq!(song["title"], "Radio Ga Ga");
+        assert_eq!(song["format"], "cdg");
+        assert_eq!(song["audioFileName"], "SC8123-05 - Queen - Radio Ga Ga.mp3");
+    }
+
+    #[test]
+    fn single_files_import_as_their_format() {
+        let dir = crate::paths::test_dir("import-file");
+        std::fs::write(dir.join("Queen - Radio Ga Ga.cdg"), "").unwrap();
+        std::fs::write(dir.join("Queen - Radio Ga Ga.mp3"), "").unwrap();
+        std::fs::write(dir.join("song.mid"), "").unwrap();
+        let cdg = import_path(&dir.join("Queen - Radio Ga Ga.cdg")).unwrap();
+        let mid = import_path(&dir.join("song.mid"));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert_eq!(cdg.songs.len(), 1);
+        assert_eq!(cdg.songs[0]["format"], "cdg");
+        assert!(mid.is_err());
+    }
+
+    #[test]
+    fn song_id_is_stable() {
+        let a = song_id(Path::new("/songs/a/a.txt"));
+        assert_eq!(a, song_id(Path::new("/songs/a/a.txt")));
+        assert_ne!(a, song_id(Path::new("/songs/b/b.txt")));
+    }
+}
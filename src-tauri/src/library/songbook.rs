@@ -0,0 +1,475 @@
+//! Printable song books.
+//!
+//! `export_songbook` lays the library (or the part `filter` selects) out
+//! as a PDF for the tables: artist and title per line, in columns, with a
+//! letter heading wherever the first letter changes and a page number at
+//! the bottom. Songs can be numbered so guests write a code on the request
+//! slip. A song's code is stored (`song_codes`) the first time a book
+//! prints it, in that book's order, and kept from then on: books printed
+//! months apart agree, and songs added later get new codes instead of
+//! shifting everyone else's. `lookup_song_code` finds the song of a code.
+//!
+//! The PDF uses the built-in Helvetica unless `layout.fontPath` names a
+//! TrueType font. Helvetica only covers Latin text, so accents are dropped
+//! and other scripts print as `?`; libraries with Japanese or Cyrillic
+//! titles need a font that has them.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use printpdf::{BuiltinFont, IndirectFontRef, Mm, PdfDocument, PdfLayerReference};
+use rusqlite::types::ToSql;
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{AppHandle, Manager};
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::access::{require_webview, Capability};
+use crate::db::search::{fold_term, push_filters, SongFilters};
+use crate::db::DbState;
+
+const MARGIN_MM: f32 = 12.0;
+const COLUMN_GAP_MM: f32 = 6.0;
+const HEADER_MM: f32 = 12.0;
+const FOOTER_MM: f32 = 8.0;
+const MM_PER_PT: f32 = 0.3528;
+/// Line height relative to the font size.
+const LEADING: f32 = 1.35;
+/// Average Helvetica glyph width in ems, for fitting text to a column.
+const AVG_CHAR_EM: f32 = 0.52;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PageSize {
+    A4,
+    Letter,
+}
+
+impl PageSize {
+    /// Width and height in millimetres.
+    fn dimensions(self) -> (f32, f32) {
+        match self {
+            PageSize::A4 => (210.0, 297.0),
+            PageSize::Letter => (215.9, 279.4),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SongbookSort {
+    /// "Artist – Title", by artist.
+    Artist,
+    /// "Title – Artist", by title.
+    Title,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SongbookLayout {
+    pub page_size: PageSize,
+    /// 1 to 3.
+    pub columns: u8,
+    pub sort: SongbookSort,
+    /// Points.
+    pub font_size: f32,
+    /// Printed at the top of every page.
+    pub title: String,
+    /// Number the songs.
+    pub codes: bool,
+    pub letter_headings: bool,
+    /// TrueType font to embed instead of Helvetica.
+    pub font_path: Option<String>,
+}
+
+impl Default for SongbookLayout {
+    fn default() -> Self {
+        Self {
+            page_size: PageSize::A4,
+            columns: 2,
+            sort: SongbookSort::Artist,
+            font_size: 9.0,
+            title: "Song Book".to_string(),
+            codes: true,
+            letter_headings: true,
+            font_path: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SongbookReport {
+    pub path: String,
+    pub songs: usize,
+    pub pages: usize,
+}
+
+/// A song as the book lists it.
+#[derive(Debug, Clone, PartialEq)]
+struct BookSong {
+    id: String,
+    artist: String,
+    title: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Line {
+    Heading(String),
+    Entry { code: Option<String>, text: String },
+}
+
+/// Lines of one page, column by column.
+type Page = Vec<Vec<Line>>;
+
+// ---------------------------------------------------------------------------
+// Layout
+// ---------------------------------------------------------------------------
+
+fn load_songs(conn: &Connection, filter: &SongFilters) -> Result<Vec<BookSong>, String> {
+    let mut sql = String::from("SELECT s.id, s.artist, s.title FROM songs s WHERE 1 = 1");
+    let mut params: Vec<Box<dyn ToSql>> = Vec::new();
+    push_filters(&mut sql, &mut params, filter);
+    let mut stmt = conn.prepare(&sql).map_err(|e| format!("Songbook query failed: {}", e))?;
+    let param_refs: Vec<&dyn ToSql> = params.iter().map(|p| p.as_ref()).collect();
+    let rows = stmt
+        .query_map(param_refs.as_slice(), |row| Ok(BookSong { id: row.get(0)?, artist: row.get(1)?, title: row.get(2)? }))
+        .map_err(|e| format!("Songbook query failed: {}", e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Songbook query failed: {}", e))?;
+    Ok(rows)
+}
+
+/// The stored codes of `songs`; songs without one get the next codes, in
+/// the order given.
+fn assign_codes(conn: &Connection, songs: &[BookSong]) -> Result<HashMap<String, i64>, String> {
+    let tx = conn.unchecked_transaction().map_err(|e| format!("Transaction failed: {}", e))?;
+    let mut codes = HashMap::with_capacity(songs.len());
+    {
+        let mut insert = tx
+            .prepare_cached("INSERT OR IGNORE INTO song_codes (song_id) VALUES (?1)")
+            .map_err(|e| format!("Failed to assign song codes: {}", e))?;
+        let mut select = tx
+            .prepare_cached("SELECT code FROM song_codes WHERE song_id = ?1")
+            .map_err(|e| format!("Failed to assign song codes: {}", e))?;
+        for song in songs {
+            insert.execute([&song.id]).map_err(|e| format!("Failed to assign song codes: {}", e))?;
+            let code: i64 =
+                select.query_row([&song.id], |row| row.get(0)).map_err(|e| format!("Failed to assign song codes: {}", e))?;
+            codes.insert(song.id.clone(), code);
+        }
+    }
+    tx.commit().map_err(|e| format!("Commit failed: {}", e))?;
+    Ok(codes)
+}
+
+/// The song printed as `code`, as its JSON entry.
+fn song_for_code(conn: &Connection, code: &str) -> Result<Option<Value>, String> {
+    let Ok(code) = code.trim().parse::<i64>() else { return Ok(None) };
+    let json: Option<Option<String>> = conn
+        .query_row(
+            "SELECT s.json_data FROM song_codes c JOIN songs s ON s.id = c.song_id WHERE c.code = ?1",
+            [code],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| format!("Song code lookup failed: {}", e))?;
+    match json {
+        Some(json) => serde_json::from_str(&json.unwrap_or_default()).map(Some).map_err(|e| e.to_string()),
+        None => Ok(None),
+    }
+}
+
+/// Heading for the letter `text` is filed under; `#` for digits and symbols.
+fn initial(text: &str) -> String {
+    match fold_term(text).chars().find(|c| !c.is_whitespace()) {
+        Some(c) if c.is_alphabetic() => c.to_uppercase().collect(),
+        _ => "#".to_string(),
+    }
+}
+
+/// What a line of the book leads with, and what follows.
+fn line_parts(song: &BookSong, sort: SongbookSort) -> (&str, &str) {
+    match sort {
+        SongbookSort::Artist => (&song.artist, &song.title),
+        SongbookSort::Title => (&song.title, &song.artist),
+    }
+}
+
+/// `songs` in book order.
+fn sort_songs(songs: &mut [BookSong], sort: SongbookSort) {
+    songs.sort_by_cached_key(|song| {
+        let (primary, secondary) = line_parts(song, sort);
+        (fold_term(primary), fold_term(secondary))
+    });
+}
+
+/// Book lines for `songs`, sorted already, numbered with `codes`.
+fn book_lines(songs: &[BookSong], codes: &HashMap<String, i64>, layout: &SongbookLayout) -> Vec<Line> {
+    let width = codes.values().max().map_or(0, |max| max.to_string().len()).max(4);
+    let mut lines = Vec::with_capacity(songs.len());
+    let mut heading = None;
+    for song in songs {
+        let (primary, secondary) = line_parts(song, layout.sort);
+        if layout.letter_headings {
+            let letter = initial(primary);
+            if heading.as_ref() != Some(&letter) {
+                lines.push(Line::Heading(letter.clone()));
+                heading = Some(letter);
+            }
+        }
+        lines.push(Line::Entry {
+            code: codes.get(&song.id).filter(|_| layout.codes).map(|code| format!("{:0width$}", code, width = width)),
+            text: format!("{} - {}", primary, secondary),
+        });
+    }
+    lines
+}
+
+/// Fill columns of `per_column` lines, `columns` to a page. A heading is
+/// never left alone at the bottom of a column.
+fn paginate(lines: Vec<Line>, per_column: usize, columns: usize) -> Vec<Page> {
+    let per_column = per_column.max(2);
+    let mut pages: Vec<Page> = Vec::new();
+    let mut column: Vec<Line> = Vec::new();
+    let mut page: Page = Vec::new();
+    for line in lines {
+        let full = column.len() == per_column || (matches!(line, Line::Heading(_)) && column.len() == per_column - 1);
+        if full {
+            page.push(std::mem::take(&mut column));
+            if page.len() == columns {
+                pages.push(std::mem::take(&mut page));
+            }
+        }
+        column.push(line);
+    }
+    if !column.is_empty() {
+        page.push(column);
+    }
+    if !page.is_empty() || pages.is_empty() {
+        pages.push(page);
+    }
+    pages
+}
+
+/// `text` the built-in font can show: accents dropped, other scripts `?`.
+fn latin(text: &str) -> String {
+    text.nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .map(|c| if c.is_ascii() && !c.is_ascii_control() { c } else { '?' })
+        .collect()
+}
+
+/// `text` cut to about `max_chars`.
+fn fit(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let kept: String = text.chars().take(max_chars.saturating_sub(3)).collect();
+    format!("{}...", kept.trim_end())
+}
+
+// ---------------------------------------------------------------------------
+// Rendering
+// ---------------------------------------------------------------------------
+
+fn render(pages: &[Page], layout: &SongbookLayout) -> Result<Vec<u8>, String> {
+    let (width, height) = layout.page_size.dimensions();
+    let (doc, first_page, first_layer) = PdfDocument::new(&layout.title, Mm(width), Mm(height), "Songs");
+    let (font, bold, unicode) = match &layout.font_path {
+        Some(path) => {
+            let file = File::open(path).map_err(|e| format!("Failed to open font {}: {}", path, e))?;
+            let font = doc.add_external_font(file).map_err(|e| format!("Failed to load font {}: {}", path, e))?;
+            (font.clone(), font, true)
+        }
+        None => {
+            let font = doc.add_builtin_font(BuiltinFont::Helvetica).map_err(|e| e.to_string())?;
+            let bold = doc.add_builtin_font(BuiltinFont::HelveticaBold).map_err(|e| e.to_string())?;
+            (font, bold, false)
+        }
+    };
+    let text = |s: &str| if unicode { s.to_string() } else { latin(s) };
+
+    let columns = layout.columns.clamp(1, 3) as f32;
+    let column_width = (width - 2.0 * MARGIN_MM - (columns - 1.0) * COLUMN_GAP_MM) / columns;
+    let line_height = layout.font_size * LEADING * MM_PER_PT;
+    let char_width = layout.font_size * AVG_CHAR_EM * MM_PER_PT;
+    let top = height - MARGIN_MM - HEADER_MM;
+
+    for (n, page) in pages.iter().enumerate() {
+        let layer: PdfLayerReference = if n == 0 {
+            doc.get_page(first_page).get_layer(first_layer)
+        } else {
+            let (page, layer) = doc.add_page(Mm(width), Mm(height), "Songs");
+            doc.get_page(page).get_layer(layer)
+        };
+        let put = |s: &str, size: f32, x: f32, y: f32, font: &IndirectFontRef| layer.use_text(s, size, Mm(x), Mm(y), font);
+        put(&text(&layout.title), layout.font_size * 1.6, MARGIN_MM, height - MARGIN_MM - 4.0, &bold);
+        let footer = format!("{} / {}", n + 1, pages.len());
+        put(&footer, layout.font_size * 0.9, width / 2.0 - 4.0, MARGIN_MM, &font);
+
+        for (c, column) in page.iter().enumerate() {
+            let x = MARGIN_MM + c as f32 * (column_width + COLUMN_GAP_MM);
+            for (i, line) in column.iter().enumerate() {
+                let y = top - i as f32 * line_height;
+                match line {
+                    Line::Heading(letter) => put(&text(letter), layout.font_size * 1.2, x, y, &bold),
+                    Line::Entry { code, text: entry } => {
+                        let mut indent = 0.0;
+                        if let Some(code) = code {
+                            put(code, layout.font_size, x, y, &bold);
+                            indent = (code.len() + 1) as f32 * char_width * 1.1;
+                        }
+                        let max_chars = ((column_width - indent) / char_width) as usize;
+                        put(&fit(&text(entry), max_chars), layout.font_size, x + indent, y, &font);
+                    }
+                }
+            }
+        }
+    }
+    doc.save_to_bytes().map_err(|e| format!("Failed to write PDF: {}", e))
+}
+
+/// Lines that fit in one column of `layout`.
+fn lines_per_column(layout: &SongbookLayout) -> usize {
+    let (_, height) = layout.page_size.dimensions();
+    let usable = height - 2.0 * MARGIN_MM - HEADER_MM - FOOTER_MM;
+    (usable / (layout.font_size * LEADING * MM_PER_PT)) as usize
+}
+
+fn export(app: &AppHandle, filter: &SongFilters, layout: &SongbookLayout, path: &Path) -> Result<SongbookReport, String> {
+    let (songs, codes) = {
+        let db = app.state::<DbState>();
+        let conn = db.conn.lock().map_err(|e| e.to_string())?;
+        let mut songs = load_songs(&conn, filter)?;
+        sort_songs(&mut songs, layout.sort);
+        let codes = if layout.codes { assign_codes(&conn, &songs)? } else { HashMap::new() };
+        (songs, codes)
+    };
+    let count = songs.len();
+    let pages = paginate(book_lines(&songs, &codes, layout), lines_per_column(layout), layout.columns.clamp(1, 3) as usize);
+    let bytes = render(&pages, layout)?;
+    let file = File::create(path).map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+    std::io::Write::write_all(&mut BufWriter::new(file), &bytes)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    tracing::info!("[songbook] {} songs on {} pages written to {}", count, pages.len(), path.display());
+    Ok(SongbookReport { path: path.to_string_lossy().to_string(), songs: count, pages: pages.len() })
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+/// Write a printable song book of the songs matching `filter` to `path`.
+#[tauri::command]
+pub async fn export_songbook(
+    app: AppHandle,
+    webview: tauri::Webview,
+    filter: Option<SongFilters>,
+    layout: Option<SongbookLayout>,
+    path: String,
+) -> Result<SongbookReport, String> {
+    require_webview(&webview, Capability::ManageLibrary)?;
+    let layout = layout.unwrap_or_default();
+    if !(4.0..=24.0).contains(&layout.font_size) {
+        return Err(format!("Font size must be between 4 and 24 points, not {}", layout.font_size));
+    }
+    tauri::async_runtime::spawn_blocking(move || export(&app, &filter.unwrap_or_default(), &layout, Path::new(&path)))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// The song printed as `code` in a song book; `None` if no song has it.
+#[tauri::command]
+pub fn lookup_song_code(app: AppHandle, webview: tauri::Webview, code: String) -> Result<Option<Value>, String> {
+    require_webview(&webview, Capability::ViewLibrary)?;
+    let db = app.state::<DbState>();
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    song_for_code(&conn, &code)
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(code: &str, text: &str) -> Line {
+        Line::Entry { code: Some(code.to_string()), text: text.to_string() }
+    }
+
+    fn song(id: &str, artist: &str, title: &str) -> BookSong {
+        BookSong { id: id.to_string(), artist: artist.to_string(), title: title.to_string() }
+    }
+
+    #[test]
+    fn sorts_numbers_and_paginates_the_book() {
+        let conn = crate::db::test_conn();
+        let mut songs = vec![
+            song("q", "Queen", "Radio Ga Ga"),
+            song("a", "ABBA", "Waterloo"),
+            song("e", "Édith Piaf", "La vie en rose"),
+            song("2", "2Pac", "Changes"),
+        ];
+        sort_songs(&mut songs, SongbookSort::Artist);
+        let codes = assign_codes(&conn, &songs).unwrap();
+        let lines = book_lines(&songs, &codes, &SongbookLayout::default());
+        assert_eq!(
+            lines,
+            vec![
+                Line::Heading("#".to_string()),
+                entry("0001", "2Pac - Changes"),
+                Line::Heading("A".to_string()),
+                entry("0002", "ABBA - Waterloo"),
+                Line::Heading("E".to_string()),
+                entry("0003", "Édith Piaf - La vie en rose"),
+                Line::Heading("Q".to_string()),
+                entry("0004", "Queen - Radio Ga Ga"),
+            ]
+        );
+
+        // Headings never end a column; they move on to the next
+        let pages = paginate(lines, 3, 2);
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[0][0].len(), 2);
+        assert_eq!(pages[0][1], vec![Line::Heading("A".to_string()), entry("0002", "ABBA - Waterloo")]);
+        assert_eq!(pages[1][1][0], Line::Heading("Q".to_string()));
+
+        assert_eq!(latin("Édith Piaf – 東京"), "Edith Piaf ? ??");
+        assert_eq!(fit("Bohemian Rhapsody", 10), "Bohemia...");
+
+        let pdf = render(&pages, &SongbookLayout::default()).unwrap();
+        assert!(pdf.starts_with(b"%PDF"));
+    }
+
+    #[test]
+    fn codes_survive_new_songs_and_reprints() {
+        let conn = crate::db::test_conn();
+        let mut songs = vec![song("q", "Queen", "Radio Ga Ga"), song("c", "Cher", "Believe")];
+        sort_songs(&mut songs, SongbookSort::Artist);
+        let first = assign_codes(&conn, &songs).unwrap();
+        assert_eq!((first["c"], first["q"]), (1, 2));
+
+        // A later song sorts first but takes the next free code
+        songs.push(song("a", "ABBA", "Waterloo"));
+        sort_songs(&mut songs, SongbookSort::Title);
+        let second = assign_codes(&conn, &songs).unwrap();
+        assert_eq!((second["c"], second["q"], second["a"]), (1, 2, 3));
+
+        conn.execute(
+            "INSERT INTO songs (id, title, artist, folder, folder_path, date_added, json_data)
+             VALUES ('a', 'Waterloo', 'ABBA', '', '', 0, '{\"id\":\"a\"}')",
+            [],
+        )
+        .unwrap();
+        assert_eq!(song_for_code(&conn, " 0003 ").unwrap().unwrap()["id"], "a");
+        assert_eq!(song_for_code(&conn, "0001").unwrap(), None);
+        assert_eq!(song_for_code(&conn, "abc").unwrap(), None);
+    }
+}
@@ -0,0 +1,360 @@
+//! UltraStar `.txt` song files.
+//!
+//! An UltraStar file starts with `#KEY:VALUE` header lines (`#TITLE`,
+//! `#ARTIST`, `#MP3`, `#BPM`, …) followed by the note lines. Only the header
+//! is needed to build a library entry (`parse_header`); singing and scoring
+//! need the notes too (`parse_song`, `load_ultrastar_song`):
+//!
+//! ```text
+//! : 12 4 5 Hel        normal note: start beat, length, pitch, syllable
+//! * 16 4 7 lo         golden note (bonus points)
+//! F 20 2 0 ~          freestyle (not scored), R / G: rap / golden rap
+//! - 24                line break (`- 24 30` in relative files)
+//! P2                  duet: following notes belong to singer 2 (P3 = both)
+//! E                   end of song
+//! ```
+//!
+//! Beats are quarter-beats of `#BPM`: beat `b` plays at
+//! `#GAP + b * 60000 / (BPM * 4)` ms. With `#RELATIVE:yes` beats restart at
+//! each line break. Malformed lines are skipped and reported as warnings;
+//! singers rarely notice a lost syllable but do notice a song that refuses
+//! to load.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde::Serialize;
+use tauri::AppHandle;
+
+use super::roots::require_in_library;
+use crate::access::{require_webview, Capability};
+use crate::paths::long_path;
+
+/// Parsed header of an UltraStar song file. Keys are upper-cased.
+#[derive(Debug, Clone, Default)]
+pub struct UltraStarHeader {
+    pub tags: HashMap<String, String>,
+}
+
+impl UltraStarHeader {
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.tags.get(key).map(|s| s.as_str()).filter(|s| !s.is_empty())
+    }
+
+    pub fn title(&self) -> Option<&str> {
+        self.get("TITLE")
+    }
+
+    pub fn artist(&self) -> Option<&str> {
+        self.get("ARTIST")
+    }
+
+    /// Audio file name: `#AUDIO` (format 1.1+) takes precedence over `#MP3`.
+    pub fn audio_file(&self) -> Option<&str> {
+        self.get("AUDIO").or_else(|| self.get("MP3"))
+    }
+
+    /// Numeric tag; UltraStar files often use a decimal comma (`#BPM:285,6`).
+    pub fn number(&self, key: &str) -> Option<f64> {
+        self.get(key)?.replace(',', ".").trim().parse().ok()
+    }
+}
+
+/// Decode file bytes as UTF-8 (BOM stripped), falling back to Latin-1 —
+/// older UltraStar files are commonly CP1252/ISO-8859-1.
+pub fn decode_text(bytes: &[u8]) -> String {
+    let bytes = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]).unwrap_or(bytes);
+    match std::str::from_utf8(bytes) {
+        Ok(s) => s.to_string(),
+        Err(_) => bytes.iter().map(|&b| b as char).collect(),
+    }
+}
+
+/// Parse the header block of an UltraStar file's text.
+pub fn parse_header(text: &str) -> UltraStarHeader {
+    let mut tags = HashMap::new();
+    for line in text.lines() {
+        let line = line.trim_end_matches('\r');
+        let Some(rest) = line.strip_prefix('#') else {
+            // Header ends at the first note/line-break line
+            if line.trim().is_empty() {
+                continue;
+            }
+            break;
+        };
+        if let Some((key, value)) = rest.split_once(':') {
+            tags.insert(key.trim().to_ascii_uppercase(), value.trim().to_string());
+        }
+    }
+    UltraStarHeader { tags }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoteKind {
+    Normal,
+    Golden,
+    Freestyle,
+    Rap,
+    RapGolden,
+}
+
+impl NoteKind {
+    fn from_marker(marker: char) -> Option<Self> {
+        match marker {
+            ':' => Some(Self::Normal),
+            '*' => Some(Self::Golden),
+            'F' => Some(Self::Freestyle),
+            'R' => Some(Self::Rap),
+            'G' => Some(Self::RapGolden),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Note {
+    pub kind: NoteKind,
+    /// Absolute beat, also in relative files.
+    pub start_beat: i32,
+    pub length: i32,
+    /// Semitones relative to C4 (0 = middle C).
+    pub pitch: i32,
+    /// Syllable, with its leading space if it starts a word.
+    pub text: String,
+}
+
+/// One lyrics line: the notes up to a line break.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Line {
+    pub notes: Vec<Note>,
+    /// Beat of the break that ends the line; `None` for the last line.
+    pub end_beat: Option<i32>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Track {
+    /// Singer 1 or 2; solo songs only have singer 1.
+    pub singer: u8,
+    /// `#P1` / `#DUETSINGERP1`.
+    pub name: Option<String>,
+    pub lines: Vec<Line>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UltraStarSong {
+    pub title: String,
+    pub artist: String,
+    pub bpm: f64,
+    pub gap_ms: f64,
+    pub audio_file: Option<String>,
+    pub video_file: Option<String>,
+    pub cover_file: Option<String>,
+    pub background_file: Option<String>,
+    /// Seconds of the video to skip (`#VIDEOGAP`).
+    pub video_gap: f64,
+    /// `#START`, seconds.
+    pub start: f64,
+    /// `#END`, ms.
+    pub end: Option<f64>,
+    pub relative: bool,
+    pub is_duet: bool,
+    pub tracks: Vec<Track>,
+    /// Every header tag, upper-cased keys.
+    pub tags: HashMap<String, String>,
+    pub warnings: Vec<String>,
+}
+
+impl UltraStarSong {
+    /// Millisecond position of `beat`.
+    pub fn beat_to_ms(&self, beat: f64) -> f64 {
+        self.gap_ms + beat * 60_000.0 / (self.bpm * 4.0)
+    }
+}
+
+/// Parse a whole UltraStar file. Fails only without title, artist or a
+/// usable BPM; everything else degrades to warnings.
+pub fn parse_song(text: &str) -> Result<UltraStarSong, String> {
+    let header = parse_header(text);
+    let title = header.title().ok_or("Missing #TITLE")?.to_string();
+    let artist = header.artist().ok_or("Missing #ARTIST")?.to_string();
+    let bpm = header.number("BPM").filter(|b| *b > 0.0).ok_or("Missing or invalid #BPM")?;
+    let relative = header.get("RELATIVE").is_some_and(|v| v.eq_ignore_ascii_case("yes"));
+    let name = |p: &str| header.get(&format!("P{}", p)).or_else(|| header.get(&format!("DUETSINGERP{}", p))).map(String::from);
+
+    let mut tracks = vec![Track { singer: 1, name: name("1"), lines: vec![Line::default()] }];
+    // Singers the following notes go to (indexes into `tracks`)
+    let mut targets = vec![0usize];
+    let mut offset = 0i32;
+    let mut warnings = Vec::new();
+
+    let body = text.lines().map(|l| l.trim_end_matches('\r')).enumerate().skip_while(|(_, l)| l.starts_with('#') || l.trim().is_empty());
+    for (number, line) in body {
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() {
+            continue;
+        }
+        if trimmed == "E" || trimmed.starts_with("E ") {
+            break;
+        }
+        let warn = |warnings: &mut Vec<String>, what: &str| warnings.push(format!("Line {}: {} ({})", number + 1, what, line.trim()));
+
+        if let Some(singer) = trimmed.strip_prefix('P') {
+            targets = match singer.trim() {
+                "1" => vec![0],
+                "2" => vec![1],
+                "3" => vec![0, 1],
+                _ => {
+                    warn(&mut warnings, "unknown singer");
+                    continue;
+                }
+            };
+            while tracks.len() < 2 && targets.contains(&1) {
+                tracks.push(Track { singer: 2, name: name("2"), lines: vec![Line::default()] });
+            }
+            continue;
+        }
+
+        let Some(marker) = trimmed.chars().next() else { continue };
+        let rest = &trimmed[marker.len_utf8()..];
+        if marker == '-' {
+            let beats: Vec<i32> = rest.split_whitespace().filter_map(|b| b.parse().ok()).collect();
+            let Some(&end) = beats.first() else {
+                warn(&mut warnings, "line break without beat");
+                continue;
+            };
+            for &t in &targets {
+                let track = &mut tracks[t];
+                if track.lines.last().is_some_and(|l| !l.notes.is_empty()) {
+                    track.lines.last_mut().expect("non-empty").end_beat = Some(end + offset);
+                    track.lines.push(Line::default());
+                }
+            }
+            if relative {
+                offset += beats.get(1).copied().unwrap_or(end);
+            }
+            continue;
+        }
+
+        let Some(kind) = NoteKind::from_marker(marker) else {
+            warn(&mut warnings, "unknown line type");
+            continue;
+        };
+        match parse_note(rest) {
+            Some((start, length, pitch, text)) => {
+                let note = Note { kind, start_beat: start + offset, length, pitch, text };
+                for &t in &targets {
+                    tracks[t].lines.last_mut().expect("tracks start with a line").notes.push(note.clone());
+                }
+            }
+            None => warn(&mut warnings, "malformed note"),
+        }
+    }
+
+    for track in &mut tracks {
+        track.lines.retain(|l| !l.notes.is_empty());
+        if let Some(last) = track.lines.last_mut() {
+            last.end_beat = None;
+        }
+    }
+    Ok(UltraStarSong {
+        title,
+        artist,
+        bpm,
+        gap_ms: header.number("GAP").unwrap_or(0.0),
+        audio_file: header.audio_file().map(String::from),
+        video_file: header.get("VIDEO").map(String::from),
+        cover_file: header.get("COVER").map(String::from),
+        background_file: header.get("BACKGROUND").map(String::from),
+        video_gap: header.number("VIDEOGAP").unwrap_or(0.0),
+        start: header.number("START").unwrap_or(0.0),
+        end: header.number("END"),
+        relative,
+        is_duet: tracks.len() > 1,
+        tracks,
+        tags: header.tags,
+        warnings,
+    })
+}
+
+/// `<start> <length> <pitch> <syllable>`; the syllable keeps its spaces.
+fn parse_note(rest: &str) -> Option<(i32, i32, i32, String)> {
+    let mut fields = Vec::with_capacity(3);
+    let mut remainder = rest;
+    for _ in 0..3 {
+        let trimmed = remainder.trim_start();
+        let end = trimmed.find(char::is_whitespace).unwrap_or(trimmed.len());
+        fields.push(trimmed[..end].parse::<i32>().ok()?);
+        remainder = &trimmed[end..];
+    }
+    // One separator space; any further spaces belong to the syllable
+    let text = remainder.strip_prefix([' ', '\t']).unwrap_or(remainder);
+    Some((fields[0], fields[1], fields[2], text.to_string()))
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+/// Notes, timing and duet tracks of an UltraStar file.
+#[tauri::command]
+pub async fn load_ultrastar_song(app: AppHandle, webview: tauri::Webview, path: String) -> Result<UltraStarSong, String> {
+    require_webview(&webview, Capability::ViewLibrary)?;
+    require_in_library(&app, &path)?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let bytes = std::fs::read(long_path(Path::new(&path))).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+        parse_song(&decode_text(&bytes)).map_err(|e| format!("{}: {}", path, e))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_header_tags() {
+        let text = "\u{feff}#TITLE:Song\r\n#ARTIST:Band\r\n#MP3:song.mp3\r\n#BPM:285,6\r\n#GAP:1200\r\n: 0 4 59 Hel\r\n#IGNORED:x\r\n";
+        let header = parse_header(text.trim_start_matches('\u{feff}'));
+        assert_eq!(header.title(), Some("Song"));
+        assert_eq!(header.artist(), Some("Band"));
+        assert_eq!(header.audio_file(), Some("song.mp3"));
+        assert_eq!(header.number("BPM"), Some(285.6));
+        assert_eq!(header.number("GAP"), Some(1200.0));
+        assert!(header.get("IGNORED").is_none());
+    }
+
+    #[test]
+    fn decodes_latin1_fallback() {
+        assert_eq!(decode_text(&[0x4D, 0xFC, 0x6C, 0x6C]), "Müll");
+        assert_eq!(decode_text(&[0xEF, 0xBB, 0xBF, b'o', b'k']), "ok");
+    }
+
+    #[test]
+    fn parses_notes_duets_and_relative_beats() {
+        let text = "#TITLE:Duet\n#ARTIST:Two\n#BPM:300\n#GAP:1000\n#P1:Anna\n#P2:Ben\n\
+                    P1\n: 0 2 5 Hel\n* 2 2 7 lo\n- 4\nP2\nF 6 2 0  you\nP3\nR 8 1 0 all\nE\n: 99 1 1 ignored\n";
+        let song = parse_song(text).unwrap();
+        assert!(song.is_duet);
+        assert_eq!(song.tracks[0].name.as_deref(), Some("Anna"));
+        let anna: Vec<&Note> = song.tracks[0].lines.iter().flat_map(|l| &l.notes).collect();
+        assert_eq!(anna.len(), 3);
+        assert_eq!(anna[1].kind, NoteKind::Golden);
+        assert_eq!(song.tracks[0].lines[0].end_beat, Some(4));
+        assert_eq!(song.tracks[1].lines[0].notes[0].text, " you");
+        assert_eq!(song.tracks[1].lines[0].notes[1].kind, NoteKind::Rap);
+        assert_eq!(song.beat_to_ms(20.0), 2000.0);
+        assert!(song.warnings.is_empty());
+
+        let relative = "#TITLE:R\n#ARTIST:A\n#BPM:100\n#RELATIVE:YES\n: 0 2 0 a\n- 4 10\n: 2 2 0 b\nx 1 1 1 bad\n";
+        let song = parse_song(relative).unwrap();
+        assert_eq!(song.tracks[0].lines[1].notes[0].start_beat, 12);
+        assert_eq!(song.warnings.len(), 1);
+        assert!(parse_song("#TITLE:No BPM\n#ARTIST:A\n").is_err());
+    }
+}
@@ -0,0 +1,338 @@
+//! Live updates of the library from file-system events.
+//!
+//! Every folder in `root_folders` is watched recursively (notify: inotify,
+//! FSEvents, ReadDirectoryChangesW). Events are collected until the folders
+//! have been quiet for `DEBOUNCE`, so a copied album is one update, then:
+//!   - a directory that appeared (copied or renamed in) is scanned like a
+//!     new root;
+//!   - the directory of a changed file is re-classified on its own, without
+//!     descending: new songs are upserted, songs whose files are gone are
+//!     removed (a rename is both);
+//!   - a path that vanished takes every song below it along.
+//! The result is published as `library://changed`, so songs bought mid-party
+//! show up without a rescan. Only songs the native scanner indexed
+//! (`native-*` ids) are ever removed.
+//!
+//! Root folders added later (in the app or in `config.toml`) are picked up
+//! every `RESYNC_INTERVAL`; a root that cannot be watched (unplugged drive)
+//! is retried then.
+
+use std::collections::{BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use rusqlite::Connection;
+use serde::Serialize;
+use serde_json::Value;
+use tauri::{AppHandle, Manager};
+use tokio::sync::mpsc;
+
+use super::artwork;
+use super::formats;
+use super::scan_pool::{self, ScanOptions};
+use super::scanner;
+use crate::db::DbState;
+use crate::events::{publish, AppEvent};
+use crate::paths::{display_path, long_path, normalize_path};
+use crate::runtime::TaskSupervisor;
+
+pub const LIBRARY_CHANGED_EVENT: &str = "library://changed";
+
+/// Quiet time before queued events are applied.
+const DEBOUNCE: Duration = Duration::from_millis(1500);
+/// Upper bound on queuing while events keep coming (long copies).
+const MAX_DELAY: Duration = Duration::from_secs(10);
+const RESYNC_INTERVAL: Duration = Duration::from_secs(30);
+/// Files still being written by browsers and download tools.
+const PARTIAL_EXTENSIONS: &[&str] = &["part", "tmp", "crdownload", "download"];
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LibraryChange {
+    /// New or updated songs, in frontend JSON shape.
+    pub songs: Vec<Value>,
+    pub removed_ids: Vec<String>,
+}
+
+/// What to re-index for a set of changed paths.
+#[derive(Debug, Default, PartialEq)]
+struct Plan {
+    /// Scan recursively.
+    trees: BTreeSet<PathBuf>,
+    /// Re-classify the files directly inside.
+    dirs: BTreeSet<PathBuf>,
+    /// Gone; drop the songs at and below.
+    removed: BTreeSet<PathBuf>,
+}
+
+fn plan(changed: impl IntoIterator<Item = PathBuf>, is_dir: impl Fn(&Path) -> bool, exists: impl Fn(&Path) -> bool) -> Plan {
+    let mut plan = Plan::default();
+    for path in changed {
+        if scanner::has_extension(&path, PARTIAL_EXTENSIONS) {
+            continue;
+        }
+        if is_dir(&path) {
+            plan.trees.insert(path);
+            continue;
+        }
+        if !exists(&path) {
+            plan.removed.insert(path.clone());
+        }
+        if let Some(parent) = path.parent() {
+            plan.dirs.insert(parent.to_path_buf());
+        }
+    }
+    // A tree scan already covers the folders inside it
+    let trees: Vec<PathBuf> = plan.trees.iter().cloned().collect();
+    let covered = |p: &Path| trees.iter().any(|t| p.starts_with(t));
+    plan.trees.retain(|t| !trees.iter().any(|other| other != t && t.starts_with(other)));
+    plan.dirs.retain(|d| !covered(d));
+    plan
+}
+
+/// `LIKE` pattern for everything below `dir`.
+fn like_below(dir: &str) -> String {
+    let escaped = dir.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    format!("{}{}%", escaped, std::path::MAIN_SEPARATOR.to_string().replace('\\', "\\\\"))
+}
+
+fn folder_key(path: &Path) -> String {
+    normalize_path(&display_path(path)).to_string_lossy().to_string()
+}
+
+/// First column of every row of `sql`.
+fn string_column(conn: &Connection, sql: &str, params: impl rusqlite::Params) -> Result<Vec<String>, String> {
+    let mut stmt = conn.prepare(sql).map_err(|e| format!("Library lookup failed: {}", e))?;
+    let ids = stmt
+        .query_map(params, |row| row.get::<_, String>(0))
+        .map_err(|e| format!("Library lookup failed: {}", e))?
+        .flatten()
+        .collect();
+    Ok(ids)
+}
+
+fn remove_songs(conn: &mut Connection, ids: &[String]) -> Result<(), String> {
+    let tx = conn.transaction().map_err(|e| format!("Transaction failed: {}", e))?;
+    for id in ids {
+        tx.execute("DELETE FROM songs WHERE id = ?1", [id])
+            .map_err(|e| format!("Failed to remove song: {}", e))?;
+    }
+    tx.commit().map_err(|e| format!("Commit failed: {}", e))
+}
+
+/// Songs directly inside `dir`, as the scanner would index them.
+fn songs_in_dir(dir: &Path, options: &ScanOptions) -> Result<Vec<Value>, String> {
+    let entries = std::fs::read_dir(long_path(dir)).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    let files: Vec<PathBuf> = entries
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|t| !t.is_dir()))
+        .map(|entry| display_path(&entry.path()))
+        .collect();
+    let mut songs = Vec::new();
+    for candidate in formats::classify_dir(&files) {
+        match scan_pool::parse_candidate(&candidate, options) {
+            Ok(found) => songs.extend(found),
+            Err(e) => tracing::warn!("[watch] {}", e),
+        }
+    }
+    Ok(songs)
+}
+
+/// Bring the index in line with `plan`.
+fn apply(app: &AppHandle, plan: Plan) -> Result<LibraryChange, String> {
+    let db = app.state::<DbState>();
+    let options = ScanOptions { artwork_dir: artwork::cache_dir(app), ..ScanOptions::default() };
+    let mut change = LibraryChange::default();
+
+    for tree in &plan.trees {
+        let report = scan_pool::scan(
+            tree,
+            &options,
+            |batch| {
+                let n = {
+                    let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+                    scanner::save_songs(&mut conn, &batch)?
+                };
+                change.songs.extend(batch);
+                Ok(n)
+            },
+            |_| {},
+        );
+        if let Err(e) = report {
+            tracing::warn!("[watch] Scan of {} failed: {}", tree.display(), e);
+        }
+    }
+
+    for dir in &plan.dirs {
+        // Removed folders are handled below; their parent shows up here too
+        if !long_path(dir).is_dir() {
+            continue;
+        }
+        let songs = songs_in_dir(dir, &options)?;
+        let found: HashSet<&str> = songs.iter().filter_map(|s| s.get("id").and_then(|v| v.as_str())).collect();
+        let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+        let stale: Vec<String> = string_column(
+            &conn,
+            "SELECT id FROM songs WHERE folder_path = ?1 AND id LIKE 'native-%'",
+            [folder_key(dir)],
+        )?
+        .into_iter()
+        .filter(|id| !found.contains(id.as_str()))
+        .collect();
+        scanner::save_songs(&mut conn, &songs)?;
+        remove_songs(&mut conn, &stale)?;
+        change.songs.extend(songs);
+        change.removed_ids.extend(stale);
+    }
+
+    for path in &plan.removed {
+        let key = folder_key(path);
+        let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+        let gone = string_column(
+            &conn,
+            "SELECT id FROM songs WHERE (folder_path = ?1 OR folder_path LIKE ?2 ESCAPE '\\') AND id LIKE 'native-%'",
+            [key.clone(), like_below(&key)],
+        )?;
+        remove_songs(&mut conn, &gone)?;
+        change.removed_ids.extend(gone);
+    }
+    Ok(change)
+}
+
+/// Watch the root folders not watched yet, and stop watching removed ones.
+fn sync_roots(app: &AppHandle, watcher: &mut RecommendedWatcher, watched: &mut HashSet<PathBuf>, failed: &mut HashSet<PathBuf>) {
+    let roots: HashSet<PathBuf> = {
+        let db = app.state::<DbState>();
+        let Ok(conn) = db.conn.lock() else { return };
+        match string_column(&conn, "SELECT path FROM root_folders", []) {
+            Ok(paths) => paths.into_iter().map(PathBuf::from).collect(),
+            Err(e) => {
+                tracing::warn!("[watch] {}", e);
+                return;
+            }
+        }
+    };
+    for root in watched.difference(&roots).cloned().collect::<Vec<_>>() {
+        let _ = watcher.unwatch(&long_path(&root));
+        watched.remove(&root);
+    }
+    for root in roots.difference(watched).cloned().collect::<Vec<_>>() {
+        match watcher.watch(&long_path(&root), RecursiveMode::Recursive) {
+            Ok(()) => {
+                tracing::info!("[watch] Watching {}", root.display());
+                failed.remove(&root);
+                watched.insert(root);
+            }
+            // Warn once per outage, not every resync
+            Err(e) if failed.insert(root.clone()) => tracing::warn!("[watch] Cannot watch {}: {}", root.display(), e),
+            Err(_) => {}
+        }
+    }
+}
+
+/// Paths worth re-indexing from one notify event.
+fn event_paths(event: notify::Result<Event>, watched: &HashSet<PathBuf>, out: &mut Vec<PathBuf>) {
+    match event {
+        Ok(event) if event.need_rescan() => {
+            // The OS dropped events; only a full pass is safe
+            out.extend(watched.iter().cloned());
+        }
+        Ok(event) if !matches!(event.kind, EventKind::Access(_)) => {
+            out.extend(event.paths.iter().map(|p| display_path(p)));
+        }
+        Ok(_) => {}
+        Err(e) => tracing::warn!("[watch] {}", e),
+    }
+}
+
+/// Start watching the library folders. Call once the database is managed.
+pub fn spawn_watcher(app: AppHandle) {
+    let supervisor = app.state::<TaskSupervisor>();
+    supervisor.spawn("library-watch", move |token| async move {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut watcher = match notify::recommended_watcher(move |event| {
+            let _ = tx.send(event);
+        }) {
+            Ok(watcher) => watcher,
+            Err(e) => {
+                tracing::error!("[watch] File watching unavailable: {}", e);
+                return;
+            }
+        };
+        let mut watched = HashSet::new();
+        let mut failed = HashSet::new();
+        let mut resync = tokio::time::interval(RESYNC_INTERVAL);
+
+        loop {
+            let first = tokio::select! {
+                _ = token.cancelled() => break,
+                _ = resync.tick() => {
+                    sync_roots(&app, &mut watcher, &mut watched, &mut failed);
+                    continue;
+                }
+                event = rx.recv() => match event {
+                    Some(event) => event,
+                    None => break,
+                },
+            };
+            let mut changed = Vec::new();
+            event_paths(first, &watched, &mut changed);
+            let started = Instant::now();
+            while started.elapsed() < MAX_DELAY {
+                tokio::select! {
+                    _ = token.cancelled() => return,
+                    next = tokio::time::timeout(DEBOUNCE, rx.recv()) => match next {
+                        Ok(Some(event)) => event_paths(event, &watched, &mut changed),
+                        _ => break,
+                    },
+                }
+            }
+            if changed.is_empty() {
+                continue;
+            }
+
+            let task_app = app.clone();
+            let result = tauri::async_runtime::spawn_blocking(move || {
+                let plan = plan(changed, |p| long_path(p).is_dir(), |p| long_path(p).exists());
+                apply(&task_app, plan)
+            })
+            .await;
+            match result {
+                Ok(Ok(change)) if !change.songs.is_empty() || !change.removed_ids.is_empty() => {
+                    tracing::info!(
+                        "[watch] {} songs added or updated, {} removed",
+                        change.songs.len(),
+                        change.removed_ids.len()
+                    );
+                    publish(&app, AppEvent::LibraryChanged(change));
+                }
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => tracing::warn!("[watch] Update failed: {}", e),
+                Err(e) => tracing::error!("[watch] Update task failed: {}", e),
+            }
+        }
+    });
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plans_tree_scans_dir_refreshes_and_removals() {
+        let dirs = ["/lib/New Album", "/lib/New Album/CD2"];
+        let plan = plan(
+            ["/lib/New Album", "/lib/New Album/CD2", "/lib/New Album/CD2/a.cdg", "/lib/Old/b.mp4", "/lib/c.mp3.part", "/lib/Gone"]
+                .map(PathBuf::from),
+            |p| dirs.iter().any(|d| p == Path::new(d)),
+            |p| !p.ends_with("b.mp4") && !p.ends_with("Gone"),
+        );
+        assert_eq!(plan.trees, BTreeSet::from([PathBuf::from("/lib/New Album")]));
+        assert_eq!(plan.dirs, BTreeSet::from([PathBuf::from("/lib"), PathBuf::from("/lib/Old")]));
+        assert_eq!(plan.removed, BTreeSet::from([PathBuf::from("/lib/Old/b.mp4"), PathBuf::from("/lib/Gone")]));
+    }
+}
@@ -0,0 +1,448 @@
+//! Party lighting: DMX scenes over Art-Net or sACN, cued by the show.
+//!
+//! Small venues run a cheap Art-Net or sACN node in front of a few PAR
+//! cans; this drives it directly, so the lights follow the songs without
+//! a lighting desk or extra software. `[lighting]` of `config.toml` names
+//! the protocol, the node (broadcast for Art-Net, the universe's multicast
+//! group for sACN when unset) and one universe, and maps cues to scenes:
+//!   - `idle` when lighting comes up;
+//!   - `song_start` when the native player starts a track from the top;
+//!   - `song_end` when it reaches the end of one;
+//!   - `chorus` on the first line of a chorus (see `Lyrics::chorus_starts`);
+//!   - `score_reveal` when a singer's result is in.
+//!
+//! A scene sets `values` from `start_channel` on and leaves the other
+//! channels as they were. With `hold_ms` it is a flash: the lights return
+//! to the scene before once it has passed. The universe is re-sent every
+//! `REFRESH`, so nodes and receivers that time out keep the look.
+//! `test_lighting_scene` shows a cue's scene from the settings page.
+
+pub mod packet;
+
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, ToSocketAddrs, UdpSocket};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::MissedTickBehavior;
+
+use crate::access::{require_webview, Capability};
+use crate::config::{LightingConfig, LightingScene};
+use crate::events::{AppEvent, EventBus};
+use crate::runtime::TaskSupervisor;
+use packet::{Frame, ARTNET_MAX_UNIVERSE, ARTNET_PORT, SACN_PORT, SACN_UNIVERSES, UNIVERSE_SIZE};
+
+/// Flash expiry check; well under any hold worth configuring.
+const TICK: Duration = Duration::from_millis(50);
+/// Art-Net nodes and sACN receivers drop a universe not seen for ~2.5 s.
+const REFRESH: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LightingProtocol {
+    #[default]
+    Artnet,
+    Sacn,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LightingCue {
+    Idle,
+    SongStart,
+    SongEnd,
+    Chorus,
+    ScoreReveal,
+}
+
+/// Reject universes the protocol cannot address and scenes running past
+/// the end of the universe.
+pub fn validate(config: &LightingConfig) -> Result<(), String> {
+    match config.protocol {
+        LightingProtocol::Artnet if config.universe > ARTNET_MAX_UNIVERSE => {
+            return Err(format!("Art-Net universe {} is out of range (0–{})", config.universe, ARTNET_MAX_UNIVERSE));
+        }
+        LightingProtocol::Sacn if !SACN_UNIVERSES.contains(&config.universe) => {
+            return Err(format!("sACN universe {} is out of range (1–63999)", config.universe));
+        }
+        _ => {}
+    }
+    for scene in &config.scenes {
+        let last = scene.start_channel as usize + scene.values.len().max(1) - 1;
+        if scene.start_channel == 0 || last > UNIVERSE_SIZE {
+            return Err(format!(
+                "Lighting scene for {:?} spans channels {}–{} (must be within 1–{})",
+                scene.cue, scene.start_channel, last, UNIVERSE_SIZE
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// `base` with `scene` applied.
+fn render(base: &Frame, scene: &LightingScene) -> Frame {
+    let mut frame = *base;
+    let start = scene.start_channel as usize - 1;
+    frame[start..start + scene.values.len()].copy_from_slice(&scene.values);
+    frame
+}
+
+/// Where packets go: the configured node, else the protocol's default.
+fn resolve_target(config: &LightingConfig) -> Result<SocketAddr, String> {
+    let port = match config.protocol {
+        LightingProtocol::Artnet => ARTNET_PORT,
+        LightingProtocol::Sacn => SACN_PORT,
+    };
+    let Some(target) = config.target.as_deref().map(str::trim).filter(|t| !t.is_empty()) else {
+        let ip = match config.protocol {
+            LightingProtocol::Artnet => Ipv4Addr::BROADCAST,
+            LightingProtocol::Sacn => packet::sacn_multicast(config.universe),
+        };
+        return Ok(SocketAddr::V4(SocketAddrV4::new(ip, port)));
+    };
+    let with_port = if target.contains(':') { target.to_string() } else { format!("{}:{}", target, port) };
+    with_port
+        .to_socket_addrs()
+        .map_err(|e| format!("Failed to resolve lighting target {}: {}", target, e))?
+        .find(SocketAddr::is_ipv4)
+        .ok_or_else(|| format!("Lighting target {} has no IPv4 address", target))
+}
+
+struct Output {
+    socket: UdpSocket,
+    target: SocketAddr,
+}
+
+impl Output {
+    fn open(config: &LightingConfig) -> Result<Self, String> {
+        let target = resolve_target(config)?;
+        let socket = UdpSocket::bind("0.0.0.0:0").map_err(|e| format!("Failed to open the lighting socket: {}", e))?;
+        socket
+            .set_broadcast(true)
+            .map_err(|e| format!("Failed to enable broadcast: {}", e))?;
+        socket.set_nonblocking(true).map_err(|e| e.to_string())?;
+        Ok(Self { socket, target })
+    }
+}
+
+struct Rig {
+    config: LightingConfig,
+    output: Option<Output>,
+    sequence: u8,
+    /// The latched look.
+    base: Frame,
+    /// A held scene shown over `base` until the instant.
+    flash: Option<(Frame, Instant)>,
+    last_sent: Option<Instant>,
+}
+
+impl Rig {
+    fn frame(&self) -> &Frame {
+        self.flash.as_ref().map(|(frame, _)| frame).unwrap_or(&self.base)
+    }
+
+    fn cue(&mut self, cue: LightingCue, now: Instant) -> bool {
+        let Some(scene) = self.config.scenes.iter().find(|scene| scene.cue == cue) else { return false };
+        let frame = render(&self.base, scene);
+        match scene.hold_ms {
+            Some(hold_ms) => self.flash = Some((frame, now + Duration::from_millis(hold_ms))),
+            None => {
+                self.base = frame;
+                self.flash = None;
+            }
+        }
+        true
+    }
+
+    /// Send the universe now.
+    fn send(&mut self, identity: &Identity, now: Instant) -> Result<(), String> {
+        let Some(output) = &self.output else { return Ok(()) };
+        self.sequence = self.sequence.wrapping_add(1);
+        // Art-Net reads 0 as "not sequenced"; sACN receivers take the skip
+        if self.sequence == 0 {
+            self.sequence = 1;
+        }
+        let frame = self.frame();
+        let datagram = match self.config.protocol {
+            LightingProtocol::Artnet => packet::artdmx(self.config.universe, self.sequence, frame),
+            LightingProtocol::Sacn => {
+                packet::sacn_data(self.config.universe, self.sequence, &identity.cid, &identity.name, frame)
+            }
+        };
+        self.last_sent = Some(now);
+        output
+            .socket
+            .send_to(&datagram, output.target)
+            .map(|_| ())
+            .map_err(|e| format!("Failed to send DMX to {}: {}", output.target, e))
+    }
+
+    /// Drop an expired flash; whether the universe is due to be sent.
+    fn due(&mut self, now: Instant) -> bool {
+        if self.flash.as_ref().is_some_and(|(_, until)| now >= *until) {
+            self.flash = None;
+            return true;
+        }
+        !matches!(self.last_sent, Some(sent) if now.duration_since(sent) < REFRESH)
+    }
+}
+
+/// Who the packets are from, for sACN receivers.
+struct Identity {
+    cid: [u8; 16],
+    name: String,
+}
+
+/// Managed state: the rig as configured by the last `sync`.
+pub struct LightingState {
+    rig: Mutex<Rig>,
+    identity: Identity,
+}
+
+impl LightingState {
+    /// `name` is the source sACN receivers show; `identifier` seeds its
+    /// component id, so they see the same source across restarts.
+    pub fn new(name: &str, identifier: &str) -> Self {
+        let high = crate::library::scanner::fnv1a64(identifier.as_bytes());
+        let low = crate::library::scanner::fnv1a64(format!("{}/sacn", identifier).as_bytes());
+        let mut cid = [0u8; 16];
+        cid[..8].copy_from_slice(&high.to_be_bytes());
+        cid[8..].copy_from_slice(&low.to_be_bytes());
+        Self {
+            rig: Mutex::new(Rig {
+                config: LightingConfig::default(),
+                output: None,
+                sequence: 0,
+                base: [0; UNIVERSE_SIZE],
+                flash: None,
+                last_sent: None,
+            }),
+            identity: Identity { cid, name: name.to_string() },
+        }
+    }
+
+    /// Show the scene of `cue`, if one is mapped. `false` when none is.
+    fn cue(&self, cue: LightingCue) -> Result<bool, String> {
+        let mut rig = self.rig.lock().map_err(|e| e.to_string())?;
+        let now = Instant::now();
+        if !rig.cue(cue, now) {
+            return Ok(false);
+        }
+        rig.send(&self.identity, now)?;
+        Ok(true)
+    }
+
+    fn refresh(&self) {
+        let Ok(mut rig) = self.rig.lock() else { return };
+        let now = Instant::now();
+        if rig.output.is_some() && rig.due(now) {
+            if let Err(e) = rig.send(&self.identity, now) {
+                tracing::debug!("[lighting] {}", e);
+            }
+        }
+    }
+}
+
+/// Apply `[lighting]`: reopen the output when the node or protocol changed
+/// and show the idle scene when lighting comes up.
+pub fn sync(app: &AppHandle, config: &LightingConfig) {
+    let Some(state) = app.try_state::<LightingState>() else { return };
+    let Ok(mut rig) = state.rig.lock() else { return };
+    let was_enabled = rig.output.is_some();
+    let reopen = !was_enabled
+        || rig.config.protocol != config.protocol
+        || rig.config.target != config.target
+        || rig.config.universe != config.universe;
+    rig.config = config.clone();
+    if !config.enabled {
+        if was_enabled {
+            tracing::info!("[lighting] Lighting off");
+        }
+        rig.output = None;
+        return;
+    }
+    if reopen {
+        rig.output = match Output::open(config) {
+            Ok(output) => {
+                tracing::info!("[lighting] Sending {:?} universe {} to {}", config.protocol, config.universe, output.target);
+                Some(output)
+            }
+            Err(e) => {
+                tracing::warn!("[lighting] {}", e);
+                None
+            }
+        };
+    }
+    if !was_enabled && rig.output.is_some() {
+        let now = Instant::now();
+        rig.cue(LightingCue::Idle, now);
+        if let Err(e) = rig.send(&state.identity, now) {
+            tracing::warn!("[lighting] {}", e);
+        }
+    }
+}
+
+/// Playback starting this close to the top of a track is a song start.
+const START_WINDOW_MS: u64 = 1_500;
+
+/// Play state seen on the last position event, to tell starts and ends
+/// from the ticks in between.
+#[derive(Debug, Default)]
+struct Playback {
+    playing: bool,
+    ended: bool,
+}
+
+impl Playback {
+    /// The cue an event stands for. Songs opened directly start playing
+    /// from the top; staged ones announce themselves as a track change.
+    fn cue_for(&mut self, event: &AppEvent) -> Option<LightingCue> {
+        match event {
+            AppEvent::AudioTrackChanged(_) => {
+                self.ended = false;
+                Some(LightingCue::SongStart)
+            }
+            AppEvent::AudioPosition(position) => {
+                let started = position.is_playing && !self.playing && position.position_ms < START_WINDOW_MS;
+                let reached_end = position.ended && !self.ended;
+                self.playing = position.is_playing;
+                self.ended = position.ended;
+                if started {
+                    Some(LightingCue::SongStart)
+                } else {
+                    reached_end.then_some(LightingCue::SongEnd)
+                }
+            }
+            AppEvent::LyricsLine(line) if line.chorus => Some(LightingCue::Chorus),
+            AppEvent::ScoringResult(_) => Some(LightingCue::ScoreReveal),
+            _ => None,
+        }
+    }
+}
+
+/// Turn playback events into cues and keep the universe refreshed; runs
+/// for the lifetime of the app. Call once the event bus and
+/// `LightingState` are managed.
+pub fn spawn_cues(app: AppHandle) {
+    let mut events = app.state::<EventBus>().subscribe();
+    let task_app = app.clone();
+    app.state::<TaskSupervisor>().spawn("lighting", move |token| async move {
+        let mut tick = tokio::time::interval(TICK);
+        tick.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        let mut playback = Playback::default();
+        loop {
+            tokio::select! {
+                _ = token.cancelled() => return,
+                _ = tick.tick() => task_app.state::<LightingState>().refresh(),
+                received = events.recv() => match received {
+                    Ok(envelope) => {
+                        let Some(cue) = playback.cue_for(&envelope.event) else { continue };
+                        let state = task_app.state::<LightingState>();
+                        let enabled = state.rig.lock().map(|rig| rig.output.is_some()).unwrap_or(false);
+                        if enabled {
+                            if let Err(e) = state.cue(cue) {
+                                tracing::warn!("[lighting] {:?}: {}", cue, e);
+                            }
+                        }
+                    }
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return,
+                },
+            }
+        }
+    });
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+/// Show the scene mapped to `cue` now, as the show would. Lighting must be
+/// enabled with a reachable node in `config.toml`.
+#[tauri::command]
+pub fn test_lighting_scene(app: AppHandle, webview: tauri::Webview, cue: LightingCue) -> Result<(), String> {
+    require_webview(&webview, Capability::ChangeSettings)?;
+    let state = app.state::<LightingState>();
+    {
+        let rig = state.rig.lock().map_err(|e| e.to_string())?;
+        if !rig.config.enabled {
+            return Err("Lighting is disabled in config.toml".to_string());
+        }
+        if rig.output.is_none() {
+            return Err("The lighting node is not reachable; check the lighting target".to_string());
+        }
+    }
+    if !state.cue(cue)? {
+        return Err(format!("No lighting scene is mapped to {:?}", cue));
+    }
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::position::AudioPosition;
+
+    fn scene(cue: LightingCue, start_channel: u16, values: Vec<u8>, hold_ms: Option<u64>) -> LightingScene {
+        LightingScene { cue, start_channel, values, hold_ms }
+    }
+
+    #[test]
+    fn validates_universes_and_scene_spans() {
+        let mut config = LightingConfig { enabled: true, ..LightingConfig::default() };
+        assert!(validate(&config).is_ok());
+        config.universe = 0x8000;
+        assert!(validate(&config).is_err());
+        config.protocol = LightingProtocol::Sacn;
+        config.universe = 0;
+        assert!(validate(&config).is_err());
+        config.universe = 1;
+        config.scenes.push(scene(LightingCue::Chorus, 510, vec![1, 2, 3], None));
+        assert!(validate(&config).is_ok());
+        config.scenes.push(scene(LightingCue::Idle, 511, vec![1, 2, 3], None));
+        assert!(validate(&config).is_err());
+        config.scenes[1].start_channel = 0;
+        assert!(validate(&config).is_err());
+    }
+
+    #[test]
+    fn latches_scenes_and_returns_from_flashes() {
+        let state = LightingState::new("Test", "com.example.test");
+        let mut rig = state.rig.lock().unwrap();
+        rig.config.scenes = vec![
+            scene(LightingCue::SongStart, 1, vec![255, 0, 0], None),
+            scene(LightingCue::Chorus, 2, vec![200], Some(1_000)),
+        ];
+        let start = Instant::now();
+        assert!(!rig.cue(LightingCue::SongEnd, start));
+        assert!(rig.cue(LightingCue::SongStart, start));
+        assert_eq!(rig.frame()[..4], [255, 0, 0, 0]);
+
+        assert!(rig.cue(LightingCue::Chorus, start));
+        assert_eq!(rig.frame()[..4], [255, 200, 0, 0]);
+        rig.last_sent = Some(start);
+        assert!(!rig.due(start + Duration::from_millis(500)));
+        assert!(rig.due(start + Duration::from_millis(1_000)));
+        assert_eq!(rig.frame()[..4], [255, 0, 0, 0]);
+    }
+
+    #[test]
+    fn maps_playback_events_to_cues() {
+        let mut playback = Playback::default();
+        let position = |ms, playing| AppEvent::AudioPosition(AudioPosition::new(ms, 60_000, playing));
+        assert_eq!(playback.cue_for(&position(0, false)), None);
+        assert_eq!(playback.cue_for(&position(40, true)), Some(LightingCue::SongStart));
+        assert_eq!(playback.cue_for(&position(59_000, true)), None);
+        // Resuming mid-song is no start
+        assert_eq!(playback.cue_for(&position(59_500, false)), None);
+        assert_eq!(playback.cue_for(&position(59_500, true)), None);
+        assert_eq!(playback.cue_for(&position(60_000, false)), Some(LightingCue::SongEnd));
+        assert_eq!(playback.cue_for(&position(60_000, false)), None);
+    }
+}
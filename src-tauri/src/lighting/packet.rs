@@ -0,0 +1,134 @@
+//! DMX data packets: Art-Net `ArtDmx` and sACN (ANSI E1.31) data packets,
+//! one full universe each.
+
+use std::net::Ipv4Addr;
+
+pub const ARTNET_PORT: u16 = 6454;
+pub const SACN_PORT: u16 = 5568;
+/// Slots (channels) in a DMX universe.
+pub const UNIVERSE_SIZE: usize = 512;
+/// Art-Net addresses 15-bit port-addresses (net, sub-net, universe).
+pub const ARTNET_MAX_UNIVERSE: u16 = 0x7fff;
+/// sACN universes run 1–63999.
+pub const SACN_UNIVERSES: std::ops::RangeInclusive<u16> = 1..=63_999;
+
+pub type Frame = [u8; UNIVERSE_SIZE];
+
+const ARTNET_ID: &[u8; 8] = b"Art-Net\0";
+const ARTNET_OP_DMX: u16 = 0x5000;
+const ARTNET_PROTOCOL: u16 = 14;
+
+const ACN_ID: &[u8; 12] = b"ASC-E1.17\0\0\0";
+const VECTOR_ROOT_E131_DATA: u32 = 0x0000_0004;
+const VECTOR_E131_DATA_PACKET: u32 = 0x0000_0002;
+const VECTOR_DMP_SET_PROPERTY: u8 = 0x02;
+const SACN_PRIORITY: u8 = 100;
+const SACN_SOURCE_NAME_LEN: usize = 64;
+const SACN_PACKET_LEN: usize = 126 + UNIVERSE_SIZE;
+
+/// `ArtDmx` for `universe` (its 15-bit port-address).
+pub fn artdmx(universe: u16, sequence: u8, frame: &Frame) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(18 + UNIVERSE_SIZE);
+    packet.extend_from_slice(ARTNET_ID);
+    packet.extend_from_slice(&ARTNET_OP_DMX.to_le_bytes());
+    packet.extend_from_slice(&ARTNET_PROTOCOL.to_be_bytes());
+    packet.push(sequence);
+    // Physical input port; informational only
+    packet.push(0);
+    // SubUni then Net, i.e. the port-address little-endian
+    packet.extend_from_slice(&(universe & ARTNET_MAX_UNIVERSE).to_le_bytes());
+    packet.extend_from_slice(&(UNIVERSE_SIZE as u16).to_be_bytes());
+    packet.extend_from_slice(frame);
+    packet
+}
+
+/// Flags (0x7) and the length of a PDU starting at `offset`, as E1.31
+/// writes them.
+fn flags_and_length(offset: usize) -> [u8; 2] {
+    (0x7000 | (SACN_PACKET_LEN - offset) as u16).to_be_bytes()
+}
+
+/// E1.31 data packet for `universe` from the source `cid` / `source_name`.
+pub fn sacn_data(universe: u16, sequence: u8, cid: &[u8; 16], source_name: &str, frame: &Frame) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(SACN_PACKET_LEN);
+    // Root layer
+    packet.extend_from_slice(&0x0010u16.to_be_bytes());
+    packet.extend_from_slice(&0x0000u16.to_be_bytes());
+    packet.extend_from_slice(ACN_ID);
+    packet.extend_from_slice(&flags_and_length(16));
+    packet.extend_from_slice(&VECTOR_ROOT_E131_DATA.to_be_bytes());
+    packet.extend_from_slice(cid);
+    // Framing layer
+    packet.extend_from_slice(&flags_and_length(38));
+    packet.extend_from_slice(&VECTOR_E131_DATA_PACKET.to_be_bytes());
+    let mut name = [0u8; SACN_SOURCE_NAME_LEN];
+    let mut end = source_name.len().min(SACN_SOURCE_NAME_LEN - 1);
+    while !source_name.is_char_boundary(end) {
+        end -= 1;
+    }
+    name[..end].copy_from_slice(&source_name.as_bytes()[..end]);
+    packet.extend_from_slice(&name);
+    packet.push(SACN_PRIORITY);
+    // Synchronization address: none
+    packet.extend_from_slice(&0u16.to_be_bytes());
+    packet.push(sequence);
+    // Options: not preview, not terminated
+    packet.push(0);
+    packet.extend_from_slice(&universe.to_be_bytes());
+    // DMP layer
+    packet.extend_from_slice(&flags_and_length(115));
+    packet.push(VECTOR_DMP_SET_PROPERTY);
+    packet.push(0xa1);
+    packet.extend_from_slice(&0u16.to_be_bytes());
+    packet.extend_from_slice(&1u16.to_be_bytes());
+    packet.extend_from_slice(&(UNIVERSE_SIZE as u16 + 1).to_be_bytes());
+    // DMX start code, then the slots
+    packet.push(0);
+    packet.extend_from_slice(frame);
+    packet
+}
+
+/// The multicast group receivers of an sACN universe join.
+pub fn sacn_multicast(universe: u16) -> Ipv4Addr {
+    let [high, low] = universe.to_be_bytes();
+    Ipv4Addr::new(239, 255, high, low)
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_artnet_and_sacn_packets() {
+        let mut frame = [0u8; UNIVERSE_SIZE];
+        frame[0] = 255;
+        frame[511] = 7;
+
+        let art = artdmx(0x1234, 9, &frame);
+        assert_eq!(art.len(), 18 + UNIVERSE_SIZE);
+        assert_eq!(&art[..8], b"Art-Net\0");
+        assert_eq!(&art[8..18], &[0x00, 0x50, 0, 14, 9, 0, 0x34, 0x12, 0x02, 0x00]);
+        assert_eq!((art[18], art[529]), (255, 7));
+
+        let cid = [0xab; 16];
+        let sacn = sacn_data(1, 3, &cid, "Karaoke Successor", &frame);
+        assert_eq!(sacn.len(), 638);
+        assert_eq!(&sacn[4..16], b"ASC-E1.17\0\0\0");
+        assert_eq!(&sacn[16..18], &[0x72, 0x6e]);
+        assert_eq!(&sacn[22..38], &cid);
+        assert_eq!(&sacn[38..40], &[0x72, 0x58]);
+        assert_eq!(&sacn[44..61], b"Karaoke Successor");
+        assert_eq!(sacn[61], 0);
+        assert_eq!((sacn[108], sacn[111], &sacn[113..115]), (100, 3, &[0u8, 1][..]));
+        assert_eq!(&sacn[115..117], &[0x72, 0x0b]);
+        assert_eq!(&sacn[123..126], &[0x02, 0x01, 0]);
+        assert_eq!((sacn[126], sacn[637]), (255, 7));
+
+        assert_eq!(sacn_multicast(1), Ipv4Addr::new(239, 255, 0, 1));
+        assert_eq!(sacn_multicast(0x0102), Ipv4Addr::new(239, 255, 1, 2));
+    }
+}
@@ -0,0 +1,164 @@
+//! Structured logging through `tracing`.
+//!
+//! Events go to stdout and to daily files (`karaoke.YYYY-MM-DD.log`) in the
+//! platform log directory (`app_log_dir`); the last `MAX_LOG_FILES` days
+//! are kept. The level is an `EnvFilter` directive from the `log_level`
+//! setting (default `info`, e.g. `debug` or `info,karaoke_successor_lib::audio=trace`)
+//! and can be changed at runtime with `set_log_level`. A level pinned in
+//! `config.toml` (`[logging] level`) wins over the setting, and `RUST_LOG`,
+//! when set, over both at startup. The last lines are also kept in memory
+//! for crash reports (see `crash`).
+
+use tauri::{AppHandle, Manager};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, reload, EnvFilter, Registry};
+
+use crate::db::DbState;
+
+pub const LEVEL_KEY: &str = "log_level";
+const DEFAULT_LEVEL: &str = "info";
+const LOG_FILE_PREFIX: &str = "karaoke";
+const MAX_LOG_FILES: usize = 14;
+
+pub struct LogState {
+    filter: reload::Handle<EnvFilter, Registry>,
+    /// Flushes the file writer when dropped at exit.
+    _guard: Option<WorkerGuard>,
+}
+
+/// Validate a level directive; returns it trimmed and lower-cased.
+pub(crate) fn parse_level(level: &str) -> Result<(String, EnvFilter), String> {
+    let level = level.trim().to_lowercase();
+    if level.is_empty() {
+        return Err("Log level must not be empty".to_string());
+    }
+    EnvFilter::try_new(&level)
+        .map(|filter| (level.clone(), filter))
+        .map_err(|e| format!("Invalid log level '{}': {}", level, e))
+}
+
+/// Install the global subscriber. Call first thing in `setup`; events
+/// before that are dropped.
+pub fn init(app: &AppHandle) {
+    let env_filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_LEVEL));
+    let (filter, handle) = reload::Layer::new(env_filter);
+
+    let file = app
+        .path()
+        .app_log_dir()
+        .map_err(|e| e.to_string())
+        .and_then(|dir| {
+            RollingFileAppender::builder()
+                .rotation(Rotation::DAILY)
+                .filename_prefix(LOG_FILE_PREFIX)
+                .filename_suffix("log")
+                .max_log_files(MAX_LOG_FILES)
+                .build(&dir)
+                .map_err(|e| format!("{}: {}", dir.display(), e))
+        });
+    let (file_layer, guard, file_error) = match file {
+        Ok(appender) => {
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            (Some(fmt::layer().with_writer(writer).with_ansi(false)), Some(guard), None)
+        }
+        Err(e) => (None, None, Some(e)),
+    };
+
+    let installed = tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt::layer())
+        .with(file_layer)
+        // Last lines for crash reports
+        .with(fmt::layer().with_writer(crate::crash::RecentLog).with_ansi(false))
+        .try_init();
+    if let Err(e) = installed {
+        eprintln!("[logging] Failed to install logger: {}", e);
+        return;
+    }
+    if let Some(e) = file_error {
+        tracing::warn!("Logging to stdout only, no log file: {}", e);
+    }
+    app.manage(LogState { filter: handle, _guard: guard });
+}
+
+/// Switch to the level saved in settings, unless `RUST_LOG` is set. Call
+/// once the database is managed.
+pub fn apply_saved_level(app: &AppHandle) {
+    if std::env::var_os(EnvFilter::DEFAULT_ENV).is_some() {
+        return;
+    }
+    let saved = app
+        .try_state::<DbState>()
+        .and_then(|db| db.conn.lock().ok().and_then(|conn| crate::scheduler::read_setting(&conn, LEVEL_KEY)));
+    let Some(saved) = saved else { return };
+    match parse_level(&saved) {
+        Ok((_, filter)) => reload(app, filter),
+        Err(e) => tracing::warn!("Ignoring saved log level: {}", e),
+    };
+}
+
+/// Switch to `level` now, without saving it; ignored while `RUST_LOG` is set.
+pub fn apply_level(app: &AppHandle, level: &str) -> Result<(), String> {
+    let (_, filter) = parse_level(level)?;
+    if std::env::var_os(EnvFilter::DEFAULT_ENV).is_some() {
+        return Ok(());
+    }
+    reload(app, filter);
+    Ok(())
+}
+
+fn reload(app: &AppHandle, filter: EnvFilter) {
+    let Some(state) = app.try_state::<LogState>() else { return };
+    if let Err(e) = state.filter.reload(filter) {
+        tracing::warn!("Failed to change log level: {}", e);
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+/// Change the log level now and for later runs. Returns the saved directive.
+#[tauri::command]
+pub fn set_log_level(app: AppHandle, webview: tauri::Webview, level: String) -> Result<String, String> {
+    crate::access::require_webview(&webview, crate::access::Capability::ChangeSettings)?;
+    let (level, filter) = parse_level(&level)?;
+    {
+        let db = app.state::<DbState>();
+        let conn = db.conn.lock().map_err(|e| e.to_string())?;
+        conn.execute(
+            "INSERT OR REPLACE INTO app_settings (key, value) VALUES (?1, ?2)",
+            (LEVEL_KEY, &level),
+        )
+        .map_err(|e| format!("Failed to save setting: {}", e))?;
+    }
+    // Keep a level pinned in config.toml from overriding this on restart
+    let mut config = crate::config::current(&app);
+    if config.logging.level.is_some() {
+        config.logging.level = Some(level.clone());
+        crate::config::update(&app, config)?;
+    }
+    reload(&app, filter);
+    tracing::info!("Log level set to {}", level);
+    Ok(level)
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_levels_and_directives() {
+        assert_eq!(parse_level(" DEBUG ").unwrap().0, "debug");
+        assert!(parse_level("info,karaoke_successor_lib::audio=trace").is_ok());
+        assert!(parse_level("").is_err());
+        assert!(parse_level("info,audio=shouting").is_err());
+    }
+}
@@ -0,0 +1,156 @@
+//! LRC and enhanced LRC.
+//!
+//! ```text
+//! [ti:Song] [ar:Artist] [al:Album] [offset:+250]
+//! [00:12.30]A whole line
+//! [00:15.00][01:20.50]A repeated chorus line
+//! [00:18.00]<00:18.00>Word <00:18.40>by <00:18.90>word
+//! ```
+//!
+//! A line may carry several timestamps (it is then sung several times);
+//! `<mm:ss.xx>` stamps inside a line time its words. A positive `offset`
+//! shows lyrics earlier, as in every LRC player.
+
+use super::{Lyrics, LyricsLine, Word};
+
+/// `mm:ss`, `mm:ss.xx` or `mm:ss.xxx` (also `mm:ss:xx`) in ms.
+fn parse_timestamp(stamp: &str) -> Option<u64> {
+    let (minutes, rest) = stamp.trim().split_once(':')?;
+    let (seconds, fraction) = match rest.find(['.', ':']) {
+        Some(at) => (&rest[..at], &rest[at + 1..]),
+        None => (rest, ""),
+    };
+    let minutes: u64 = minutes.parse().ok()?;
+    let seconds: u64 = seconds.parse().ok()?;
+    let fraction_ms = match fraction.len() {
+        0 => 0,
+        1 => fraction.parse::<u64>().ok()? * 100,
+        2 => fraction.parse::<u64>().ok()? * 10,
+        _ => fraction[..3].parse::<u64>().ok()?,
+    };
+    (seconds < 60).then_some(minutes * 60_000 + seconds * 1000 + fraction_ms)
+}
+
+/// Words of an enhanced line, or `None` if it has no word stamps.
+fn parse_words(text: &str) -> Option<(String, Vec<Word>)> {
+    if !text.contains('<') {
+        return None;
+    }
+    let mut words = Vec::new();
+    let mut plain = String::new();
+    let mut rest = text;
+    while let Some(open) = rest.find('<') {
+        let Some(close) = rest[open..].find('>').map(|c| open + c) else { break };
+        let Some(start_ms) = parse_timestamp(&rest[open + 1..close]) else {
+            // Not a stamp: keep the text as typed
+            plain.push_str(&rest[..=close]);
+            rest = &rest[close + 1..];
+            continue;
+        };
+        // Text before the first stamp belongs to no word; keep it visible
+        if words.is_empty() {
+            plain.push_str(&rest[..open]);
+        }
+        let after = &rest[close + 1..];
+        let end = after.find('<').unwrap_or(after.len());
+        let word = &after[..end];
+        plain.push_str(word);
+        // A trailing `<mm:ss.xx>` with no text only ends the previous word
+        if !word.trim().is_empty() {
+            words.push(Word { start_ms, end_ms: 0, text: word.to_string() });
+        } else if let Some(previous) = words.last_mut() {
+            previous.end_ms = start_ms;
+        }
+        rest = &after[end..];
+    }
+    plain.push_str(rest);
+    (!words.is_empty()).then(|| (plain.trim().to_string(), words))
+}
+
+pub fn parse(text: &str) -> Lyrics {
+    let mut lyrics = Lyrics::default();
+    let mut offset_ms: i64 = 0;
+    let mut lines = Vec::new();
+
+    for raw in text.lines() {
+        let mut rest = raw.trim();
+        let mut stamps = Vec::new();
+        // Leading [..] groups: timestamps or one metadata tag
+        while let Some(tag) = rest.strip_prefix('[') {
+            let Some(close) = tag.find(']') else { break };
+            let inner = &tag[..close];
+            rest = tag[close + 1..].trim_start();
+            if let Some(ms) = parse_timestamp(inner) {
+                stamps.push(ms);
+                continue;
+            }
+            if let Some((key, value)) = inner.split_once(':') {
+                let value = value.trim().to_string();
+                match key.trim().to_ascii_lowercase().as_str() {
+                    "ti" => lyrics.title = Some(value),
+                    "ar" => lyrics.artist = Some(value),
+                    "al" => lyrics.album = Some(value),
+                    "offset" => offset_ms = value.trim_start_matches('+').parse().unwrap_or(0),
+                    _ => {}
+                }
+            }
+        }
+        if stamps.is_empty() {
+            continue;
+        }
+        let (text, words) = match parse_words(rest) {
+            Some((text, words)) => (text, words),
+            None => (rest.to_string(), Vec::new()),
+        };
+        for start in stamps {
+            lines.push((start, text.clone(), words.clone()));
+        }
+    }
+
+    lines.sort_by_key(|(start, ..)| *start);
+    let shift = |ms: u64| (ms as i64 - offset_ms).max(0) as u64;
+    let starts: Vec<u64> = lines.iter().map(|(start, ..)| shift(*start)).collect();
+    for (i, (start, text, mut words)) in lines.into_iter().enumerate() {
+        let start_ms = shift(start);
+        // A line lasts until the next one; the last one gets a few seconds
+        let end_ms = starts.get(i + 1).copied().unwrap_or(start_ms + 5_000);
+        let next_starts: Vec<u64> = words.iter().skip(1).map(|w| shift(w.start_ms)).collect();
+        for (j, word) in words.iter_mut().enumerate() {
+            let explicit_end = (word.end_ms > 0).then(|| shift(word.end_ms));
+            word.start_ms = shift(word.start_ms);
+            word.end_ms = explicit_end.or(next_starts.get(j).copied()).unwrap_or(end_ms).max(word.start_ms);
+        }
+        lyrics.enhanced |= !words.is_empty();
+        lyrics.lines.push(LyricsLine { start_ms, end_ms, text, words });
+    }
+    lyrics.offset_ms = offset_ms;
+    lyrics
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_repeats_offsets_and_word_stamps() {
+        let text = "[ti:Song]\n[offset:+500]\n[00:15.00][00:05.5]Chorus\n\
+                    [00:10.00]<00:10.00>Word <00:10.40>by <00:10.90>word<00:11.50>\n[bad]ignored\n";
+        let lyrics = parse(text);
+        assert_eq!(lyrics.title.as_deref(), Some("Song"));
+        assert!(lyrics.enhanced);
+        let starts: Vec<u64> = lyrics.lines.iter().map(|l| l.start_ms).collect();
+        assert_eq!(starts, vec![5_000, 9_500, 14_500]);
+        assert_eq!(lyrics.lines[0].end_ms, 9_500);
+
+        let line = &lyrics.lines[1];
+        assert_eq!(line.text, "Word by word");
+        let words: Vec<(u64, u64, &str)> = line.words.iter().map(|w| (w.start_ms, w.end_ms, w.text.as_str())).collect();
+        assert_eq!(words, vec![(9_500, 9_900, "Word "), (9_900, 10_400, "by "), (10_400, 11_000, "word")]);
+        assert_eq!(parse_timestamp("01:02.345"), Some(62_345));
+        assert_eq!(parse_timestamp("1:75"), None);
+    }
+}
@@ -0,0 +1,249 @@
+//! Timed lyrics for karaoke over plain music.
+//!
+//! `load_lyrics` parses a lyrics file (`.lrc` / enhanced LRC, see `lrc`;
+//! `.kar` / `.mid`, see `crate::midi`) into lines with optional per-word
+//! timing. `lyrics_follow` then tracks the native player's position and
+//! publishes `lyrics://line` whenever the
+//! current line changes and `lyrics://word` for every word of enhanced
+//! files, so the renderer only draws what it is told instead of polling
+//! and searching itself. Seeks are followed automatically: the position is
+//! re-located on every tick. One file is followed at a time.
+//!
+//! No lyrics format marks the chorus, so a line sung more than once in the
+//! song counts as chorus; the line event of the first line of each such run
+//! is flagged, which is what the lighting cues key on.
+
+pub mod lrc;
+
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+use tokio_util::sync::CancellationToken;
+
+use crate::access::{require_webview, Capability};
+use crate::audio::commands::AudioState;
+use crate::audio::device_offsets;
+use crate::db::DbState;
+use crate::events::{publish, AppEvent};
+use crate::library::roots::require_in_library;
+use crate::library::ultrastar::decode_text;
+use crate::paths::long_path;
+use crate::runtime::{sleep_or_cancel, TaskSupervisor};
+
+pub const LINE_EVENT: &str = "lyrics://line";
+pub const WORD_EVENT: &str = "lyrics://word";
+/// Position polling interval; well under the shortest sung syllable.
+const FOLLOW_TICK: Duration = Duration::from_millis(20);
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Word {
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LyricsLine {
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub text: String,
+    /// Empty unless the file times single words.
+    pub words: Vec<Word>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Lyrics {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    /// Offset from the file, already applied to every time.
+    pub offset_ms: i64,
+    /// Some lines have word timing.
+    pub enhanced: bool,
+    /// Sorted by start.
+    pub lines: Vec<LyricsLine>,
+}
+
+impl Lyrics {
+    /// Current line and word at `position_ms`. A line stays current until
+    /// the next one starts; a word only while it is sung.
+    pub fn locate(&self, position_ms: u64) -> (Option<usize>, Option<usize>) {
+        let line = self.lines.partition_point(|l| l.start_ms <= position_ms).checked_sub(1);
+        let word = line.and_then(|i| {
+            let words = &self.lines[i].words;
+            let w = words.partition_point(|w| w.start_ms <= position_ms).checked_sub(1)?;
+            (position_ms < words[w].end_ms).then_some(w)
+        });
+        (line, word)
+    }
+
+    /// Per line, whether it opens a chorus: its text recurs elsewhere in
+    /// the song and the line before it does not.
+    pub fn chorus_starts(&self) -> Vec<bool> {
+        let key = |line: &LyricsLine| -> String {
+            line.text.chars().filter(|c| c.is_alphanumeric()).flat_map(char::to_lowercase).collect()
+        };
+        let keys: Vec<String> = self.lines.iter().map(key).collect();
+        let mut counts: std::collections::HashMap<&str, usize> = std::collections::HashMap::new();
+        for key in keys.iter().filter(|k| !k.is_empty()) {
+            *counts.entry(key.as_str()).or_default() += 1;
+        }
+        let repeated: Vec<bool> = keys.iter().map(|k| counts.get(k.as_str()).is_some_and(|n| *n > 1)).collect();
+        (0..repeated.len()).map(|i| repeated[i] && (i == 0 || !repeated[i - 1])).collect()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LineEvent {
+    /// `None` before the first line.
+    pub index: Option<usize>,
+    pub line: Option<LyricsLine>,
+    pub position_ms: u64,
+    /// The line opens a chorus (see `Lyrics::chorus_starts`).
+    pub chorus: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WordEvent {
+    pub line_index: usize,
+    pub index: usize,
+    pub word: Word,
+    pub position_ms: u64,
+}
+
+/// Parse a lyrics file by extension.
+pub fn read_file(path: &Path) -> Result<Lyrics, String> {
+    let bytes = std::fs::read(long_path(path)).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_ascii_lowercase();
+    match extension.as_str() {
+        "lrc" => Ok(lrc::parse(&decode_text(&bytes))),
+        "kar" | "mid" | "midi" => crate::midi::parse(&bytes).map(|song| song.lyrics),
+        _ => Err(format!("Unsupported lyrics format: {}", path.display())),
+    }
+}
+
+/// Managed state: stop token of the running follower.
+#[derive(Default)]
+pub struct LyricsState {
+    follower: Mutex<Option<CancellationToken>>,
+}
+
+impl LyricsState {
+    fn replace(&self, next: Option<CancellationToken>) {
+        if let Ok(mut follower) = self.follower.lock() {
+            if let Some(previous) = std::mem::replace(&mut *follower, next) {
+                previous.cancel();
+            }
+        }
+    }
+}
+
+fn follow(app: AppHandle, lyrics: Lyrics, offset_ms: i64, stop: CancellationToken) {
+    let supervisor = app.state::<TaskSupervisor>();
+    supervisor.spawn("lyrics-follow", move |token| async move {
+        let chorus_starts = lyrics.chorus_starts();
+        let mut current = (None, None);
+        let mut first = true;
+        while sleep_or_cancel(&token, FOLLOW_TICK).await && !stop.is_cancelled() {
+            let Some(audio) = app.try_state::<AudioState>() else { continue };
+            let position_ms = (audio.position_ms() as i64 + offset_ms).max(0) as u64;
+            let (line, word) = lyrics.locate(position_ms);
+            if line != current.0 || first {
+                publish(&app, AppEvent::LyricsLine(LineEvent {
+                    index: line,
+                    line: line.map(|i| lyrics.lines[i].clone()),
+                    position_ms,
+                    chorus: line.is_some_and(|i| chorus_starts[i]),
+                }));
+            }
+            if let (Some(line_index), Some(index)) = (line, word) {
+                if (line, word) != current {
+                    publish(&app, AppEvent::LyricsWord(WordEvent {
+                        line_index,
+                        index,
+                        word: lyrics.lines[line_index].words[index].clone(),
+                        position_ms,
+                    }));
+                }
+            }
+            current = (line, word);
+            first = false;
+        }
+    });
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+#[tauri::command]
+pub async fn load_lyrics(app: AppHandle, webview: tauri::Webview, path: String) -> Result<Lyrics, String> {
+    require_webview(&webview, Capability::ViewLibrary)?;
+    read_library_file(&app, path).await
+}
+
+/// `read_file` on a blocking worker, for a file inside the library.
+async fn read_library_file(app: &AppHandle, path: String) -> Result<Lyrics, String> {
+    require_in_library(app, &path)?;
+    tauri::async_runtime::spawn_blocking(move || read_file(Path::new(&path)))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Follow the native player with the lyrics of `path`, replacing any
+/// file followed before. `offset_ms` shifts the lyrics later (negative:
+/// earlier) on top of the file's own offset and the calibrated output
+/// latency.
+#[tauri::command]
+pub async fn lyrics_follow(app: AppHandle, webview: tauri::Webview, path: String, offset_ms: Option<i64>) -> Result<Lyrics, String> {
+    require_webview(&webview, Capability::ControlPlayback)?;
+    let lyrics = read_library_file(&app, path).await?;
+    let output_latency_ms = {
+        let db = app.state::<DbState>();
+        let conn = db.conn.lock().map_err(|e| e.to_string())?;
+        device_offsets::output_latency(&conn)?
+    };
+    let stop = CancellationToken::new();
+    app.state::<LyricsState>().replace(Some(stop.clone()));
+    follow(app, lyrics.clone(), -offset_ms.unwrap_or(0) - output_latency_ms, stop);
+    Ok(lyrics)
+}
+
+#[tauri::command]
+pub fn lyrics_stop(app: AppHandle, webview: tauri::Webview) -> Result<(), String> {
+    require_webview(&webview, Capability::ControlPlayback)?;
+    app.state::<LyricsState>().replace(None);
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locates_lines_and_only_sung_words() {
+        let lyrics = lrc::parse("[00:01.00]<00:01.00>One <00:01.50>two<00:02.00>\n[00:04.00]Three\n");
+        assert_eq!(lyrics.locate(500), (None, None));
+        assert_eq!(lyrics.locate(1_200), (Some(0), Some(0)));
+        assert_eq!(lyrics.locate(1_500), (Some(0), Some(1)));
+        // Between the last word and the next line
+        assert_eq!(lyrics.locate(3_000), (Some(0), None));
+        assert_eq!(lyrics.locate(60_000), (Some(1), None));
+    }
+
+    #[test]
+    fn flags_the_first_line_of_repeated_runs() {
+        let lyrics = lrc::parse(
+            "[00:01.00]Verse one\n[00:02.00]Sing it loud!\n[00:03.00]All night long\n[00:04.00]Verse two\n\
+             [00:05.00]sing it LOUD\n[00:06.00]All night long.\n",
+        );
+        assert_eq!(lyrics.chorus_starts(), vec![false, true, false, false, true, false]);
+    }
+}
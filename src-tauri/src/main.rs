@@ -2,5 +2,8 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 fn main() {
+    if let Some(code) = karaoke_successor_lib::run_cli() {
+        std::process::exit(code);
+    }
     karaoke_successor_lib::run()
 }
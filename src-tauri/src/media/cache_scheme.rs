@@ -0,0 +1,204 @@
+//! Files from the app cache, served over `cache://`.
+//!
+//! Converted media (`transcode`), cover art (`library::artwork`) and video
+//! thumbnails (`thumbnails`) are written under `<app cache dir>`, which the
+//! webview cannot read: there is no asset protocol. `url_for` turns such a
+//! file into `cache://localhost/<subdir>/<file>` (`http://cache.localhost/…`
+//! on Windows), and `handle` answers those from `SUBDIRS` and nothing else.
+//! Byte ranges are honoured, at most `MAX_RANGE` per request, so a long
+//! video seeks without being read whole.
+
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+
+use tauri::http::{header, Request, Response, StatusCode};
+use tauri::{AppHandle, Manager, UriSchemeResponder};
+
+use crate::server::shell::{content_type, segments};
+
+pub const CACHE_SCHEME: &str = "cache";
+/// The cache folders served; everything else in the cache stays private.
+const SUBDIRS: &[&str] = &["transcoded", "artwork", "thumbnails"];
+const MAX_RANGE: u64 = 8 * 1024 * 1024;
+
+fn base_url() -> String {
+    // Custom schemes are served from http://<scheme>.localhost on Windows/Android
+    if cfg!(any(windows, target_os = "android")) {
+        format!("http://{}.localhost/", CACHE_SCHEME)
+    } else {
+        format!("{}://localhost/", CACHE_SCHEME)
+    }
+}
+
+/// The `cache://` URL of `path`; `None` unless it lies directly in one of
+/// the served cache folders.
+pub fn url_for(app: &AppHandle, path: &Path) -> Option<String> {
+    url_under(&app.path().app_cache_dir().ok()?, path)
+}
+
+fn url_under(root: &Path, path: &Path) -> Option<String> {
+    let parts = path
+        .strip_prefix(root)
+        .ok()?
+        .components()
+        .map(|c| match c {
+            Component::Normal(part) => part.to_str(),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let [subdir, file] = parts.as_slice() else { return None };
+    if !SUBDIRS.contains(subdir) {
+        return None;
+    }
+    let file: String = file
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect();
+    Some(format!("{}{}/{}", base_url(), subdir, file))
+}
+
+/// The cache file a request path names, if it may be served.
+fn resolve(root: &Path, path: &str) -> Option<PathBuf> {
+    let segments = segments(path)?;
+    let [subdir, file] = segments.as_slice() else { return None };
+    SUBDIRS.contains(&subdir.as_str()).then(|| root.join(subdir).join(file))
+}
+
+/// First range of a `Range` header as inclusive offsets into a file of
+/// `len` bytes, capped at `MAX_RANGE`; `None` if it cannot be satisfied.
+fn parse_range(value: &str, len: u64) -> Option<(u64, u64)> {
+    let spec = value.trim().strip_prefix("bytes=")?.split(',').next()?.trim();
+    let (from, to) = spec.split_once('-')?;
+    if len == 0 {
+        return None;
+    }
+    let (start, end) = if from.is_empty() {
+        let suffix: u64 = to.parse().ok()?;
+        if suffix == 0 {
+            return None;
+        }
+        (len.saturating_sub(suffix), len - 1)
+    } else {
+        let start: u64 = from.parse().ok()?;
+        let end = if to.is_empty() { len - 1 } else { to.parse::<u64>().ok()?.min(len - 1) };
+        (start, end)
+    };
+    if start > end {
+        return None;
+    }
+    Some((start, end.min(start + MAX_RANGE - 1)))
+}
+
+fn empty(status: StatusCode) -> Response<Vec<u8>> {
+    Response::builder().status(status).body(Vec::new()).unwrap_or_default()
+}
+
+fn read_slice(path: &Path, start: u64, len: u64) -> std::io::Result<Vec<u8>> {
+    let mut file = std::fs::File::open(path)?;
+    file.seek(SeekFrom::Start(start))?;
+    let mut bytes = Vec::with_capacity(len as usize);
+    file.take(len).read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn respond(root: &Path, request: &Request<Vec<u8>>) -> Response<Vec<u8>> {
+    let head = request.method() == tauri::http::Method::HEAD;
+    if !head && request.method() != tauri::http::Method::GET {
+        return empty(StatusCode::METHOD_NOT_ALLOWED);
+    }
+    let Some(path) = resolve(root, request.uri().path()) else { return empty(StatusCode::NOT_FOUND) };
+    let Ok(len) = std::fs::metadata(&path).map(|m| m.len()) else { return empty(StatusCode::NOT_FOUND) };
+
+    let range = match request.headers().get(header::RANGE).and_then(|v| v.to_str().ok()) {
+        Some(value) => match parse_range(value, len) {
+            Some(range) => Some(range),
+            None => {
+                return Response::builder()
+                    .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                    .header(header::CONTENT_RANGE, format!("bytes */{}", len))
+                    .body(Vec::new())
+                    .unwrap_or_default()
+            }
+        },
+        None => None,
+    };
+    let (start, count) = match range {
+        Some((start, end)) => (start, end - start + 1),
+        None => (0, len),
+    };
+    let body = if head {
+        Vec::new()
+    } else {
+        match read_slice(&path, start, count) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                tracing::warn!("[cache] Failed to read {}: {}", path.display(), e);
+                return empty(StatusCode::NOT_FOUND);
+            }
+        }
+    };
+
+    let mut builder = Response::builder()
+        .header(header::CONTENT_TYPE, content_type(&path))
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*");
+    if let Some((start, end)) = range {
+        builder = builder
+            .status(StatusCode::PARTIAL_CONTENT)
+            .header(header::CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, len));
+    }
+    builder.body(body).unwrap_or_else(|_| empty(StatusCode::INTERNAL_SERVER_ERROR))
+}
+
+/// Handler for every `cache://` request.
+pub fn handle(app: AppHandle, request: Request<Vec<u8>>, responder: UriSchemeResponder) {
+    tauri::async_runtime::spawn_blocking(move || {
+        let response = match app.path().app_cache_dir() {
+            Ok(root) => respond(&root, &request),
+            Err(_) => empty(StatusCode::NOT_FOUND),
+        };
+        responder.respond(response);
+    });
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serves_only_the_media_cache_folders() {
+        let root = crate::paths::test_dir("cache-scheme");
+        let copy = root.join("transcoded").join("0123abcd.mp4");
+        let url = url_under(&root, &copy).unwrap();
+        assert!(url.ends_with("/transcoded/0123abcd.mp4"));
+        assert_eq!(url_under(&root, &root.join("logs").join("app.log")), None);
+        assert_eq!(url_under(&root, &root.join("transcoded").join("deep").join("x.mp4")), None);
+        assert_eq!(url_under(&root.join("elsewhere"), &copy), None);
+
+        assert_eq!(resolve(&root, "/artwork/ab-256.jpg"), Some(root.join("artwork").join("ab-256.jpg")));
+        assert_eq!(resolve(&root, "/artwork/%2e%2e/secret"), None);
+        assert_eq!(resolve(&root, "/downloads/song.mp3"), None);
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn byte_ranges_are_clamped() {
+        assert_eq!(parse_range("bytes=0-", 100), Some((0, 99)));
+        assert_eq!(parse_range("bytes=10-19", 100), Some((10, 19)));
+        assert_eq!(parse_range("bytes=90-200", 100), Some((90, 99)));
+        assert_eq!(parse_range("bytes=-10", 100), Some((90, 99)));
+        assert_eq!(parse_range("bytes=0-", 3 * MAX_RANGE), Some((0, MAX_RANGE - 1)));
+        assert_eq!(parse_range("bytes=100-", 100), None);
+        assert_eq!(parse_range("bytes=5-1", 100), None);
+        assert_eq!(parse_range("items=0-1", 100), None);
+        assert_eq!(parse_range("bytes=0-", 0), None);
+    }
+}
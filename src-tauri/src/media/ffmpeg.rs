@@ -0,0 +1,137 @@
+//! Locating and running ffmpeg.
+//!
+//! Lookup order:
+//!   1. the `ffmpeg_path` setting (user override),
+//!   2. a bundled binary in `bundled/native/`,
+//!   3. `ffmpeg` on the system PATH.
+
+use std::path::PathBuf;
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tauri::{AppHandle, Manager};
+
+use crate::db::DbState;
+
+const FFMPEG_PATH_KEY: &str = "ffmpeg_path";
+/// ffmpeg's error output kept for the failure message.
+pub(crate) const STDERR_TAIL: usize = 4096;
+
+#[cfg(target_os = "windows")]
+const FFMPEG_BINARY: &str = "ffmpeg.exe";
+#[cfg(not(target_os = "windows"))]
+const FFMPEG_BINARY: &str = "ffmpeg";
+
+/// Find an ffmpeg executable, or `None` if it is not installed.
+pub fn locate_ffmpeg(app: &AppHandle) -> Option<PathBuf> {
+    let configured = app.try_state::<DbState>().and_then(|db| {
+        let conn = db.conn.lock().ok()?;
+        conn.query_row("SELECT value FROM app_settings WHERE key = ?1", [FFMPEG_PATH_KEY], |row| {
+            row.get::<_, String>(0)
+        })
+        .ok()
+    });
+    if let Some(path) = configured.map(|p| PathBuf::from(p.trim())).filter(|p| p.is_file()) {
+        return Some(path);
+    }
+
+    if let Ok(resource_dir) = app.path().resource_dir() {
+        let bundled = resource_dir.join("bundled").join("native").join(FFMPEG_BINARY);
+        if bundled.is_file() {
+            return Some(bundled);
+        }
+    }
+
+    let finder = if cfg!(target_os = "windows") { "where" } else { "which" };
+    let output = hidden_command(finder).arg(FFMPEG_BINARY).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .next()
+        .map(|line| PathBuf::from(line.trim()))
+        .filter(|p| p.is_file())
+}
+
+/// A `Command` that does not flash a console window on Windows.
+pub fn hidden_command(program: impl AsRef<std::ffi::OsStr>) -> Command {
+    #[allow(unused_mut)]
+    let mut cmd = Command::new(program);
+    #[cfg(target_os = "windows")]
+    {
+        use std::os::windows::process::CommandExt;
+        const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+        cmd.creation_flags(CREATE_NO_WINDOW);
+    }
+    cmd
+}
+
+/// Run ffmpeg with `args`, killing it if it takes longer than `timeout`.
+pub fn run_ffmpeg(ffmpeg: &PathBuf, args: &[String], timeout: Duration) -> Result<(), String> {
+    let mut child = hidden_command(ffmpeg)
+        .args(["-hide_banner", "-loglevel", "error", "-nostdin"])
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to start ffmpeg: {}", e))?;
+    // Drained while it runs so a chatty ffmpeg never stalls on a full pipe
+    let stderr = child.stderr.take().map(|stderr| thread::spawn(move || stderr_tail(stderr)));
+    let reason = move || {
+        let error = stderr.and_then(|reader| reader.join().ok()).unwrap_or_default();
+        error.trim().lines().last().unwrap_or_default().to_string()
+    };
+
+    let started = Instant::now();
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() => return Ok(()),
+            Ok(Some(status)) => return Err(format!("ffmpeg failed ({}): {}", status, reason())),
+            Ok(None) if started.elapsed() > timeout => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("ffmpeg timed out after {} s", timeout.as_secs()));
+            }
+            Ok(None) => thread::sleep(Duration::from_millis(20)),
+            Err(e) => return Err(format!("Failed to wait for ffmpeg: {}", e)),
+        }
+    }
+}
+
+/// The last `STDERR_TAIL` bytes ffmpeg writes to `stderr`, read to the end
+/// so it never blocks on a full pipe.
+pub(crate) fn stderr_tail(mut stderr: impl Read) -> String {
+    let mut tail = Vec::new();
+    let mut buf = [0u8; 1024];
+    while let Ok(n) = stderr.read(&mut buf) {
+        if n == 0 {
+            break;
+        }
+        tail.extend_from_slice(&buf[..n]);
+        if tail.len() > STDERR_TAIL {
+            tail.drain(..tail.len() - STDERR_TAIL);
+        }
+    }
+    String::from_utf8_lossy(&tail).into_owned()
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_end_of_long_error_output() {
+        let output = format!("{}\nInvalid data found when processing input\n", "x".repeat(3 * STDERR_TAIL));
+        let tail = stderr_tail(output.as_bytes());
+        assert!(tail.len() <= STDERR_TAIL);
+        assert!(tail.trim_end().ends_with("Invalid data found when processing input"));
+    }
+}
@@ -0,0 +1,12 @@
+//! Video and media helpers backed by external tools (ffmpeg, yt-dlp).
+//!
+//! Video-backed songs and background clips are never decoded on the UI
+//! thread: work is handed to background workers that shell out to ffmpeg.
+
+pub mod cache_scheme;
+pub mod ffmpeg;
+pub mod native_video;
+pub mod online;
+pub mod thumbnails;
+pub mod tools;
+pub mod transcode;
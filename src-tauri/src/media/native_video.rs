@@ -0,0 +1,445 @@
+//! Native, hardware-decoded video in the player window.
+//!
+//! 1080p MP4 karaoke tracks stutter in the webview on weak laptops. While
+//! the player window is open, video files opened on the native player are
+//! rendered by mpv with hardware decoding instead: embedded into the player
+//! window by native handle on Windows and X11, as a borderless window over
+//! it elsewhere (macOS and Wayland allow no foreign embedding). Sound stays
+//! with the native audio engine — mpv plays none — so key change, vocal
+//! removal and output routing still apply, and the transport is the one
+//! CDG songs use: `audio_load`, `audio_play`, `audio_pause`, `audio_seek`
+//! and `audio_stop` drive the picture too. It follows the audio clock and
+//! is re-seeked when it drifts more than `MAX_DRIFT_MS`. A window over the
+//! player is moved with it: `placed` is called on every move or resize, and
+//! the sync thread reads the player's geometry when it applies it, so the
+//! last position wins however fast the events come.
+//!
+//! mpv is found like the other tools (managed, bundled, `PATH`). Without
+//! it, or with the `native_video` setting `false`, nothing changes and the
+//! player view plays the video itself; `video://native` tells it which
+//! case applies.
+
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Child, Stdio};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use raw_window_handle::{HasWindowHandle, RawWindowHandle};
+use serde::Serialize;
+use serde_json::{json, Value};
+use tauri::{AppHandle, Manager, WebviewWindow};
+
+use super::ffmpeg::hidden_command;
+use super::tools::locate_tool;
+use crate::access::{require_webview, Capability};
+use crate::audio::commands::AudioState;
+use crate::db::DbState;
+use crate::desktop::player_window::PLAYER_LABEL;
+use crate::events::{publish, AppEvent};
+use crate::library::scanner::has_extension;
+use crate::scheduler::read_setting;
+
+pub const NATIVE_EVENT: &str = "video://native";
+
+const SETTING_KEY: &str = "native_video";
+const NATIVE_EXTENSIONS: &[&str] = &["mp4", "m4v", "mkv", "webm", "mov"];
+const MAX_DRIFT_MS: i64 = 80;
+const SYNC_INTERVAL: Duration = Duration::from_millis(250);
+const IPC_TIMEOUT: Duration = Duration::from_secs(2);
+const REQUEST_ID: u64 = 1;
+
+/// Whether the player window shows a native picture, and of what.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NativeVideo {
+    pub active: bool,
+    pub path: Option<String>,
+}
+
+/// Managed state: the running mpv, if any.
+#[derive(Default)]
+pub struct NativeVideoState {
+    session: Mutex<Option<Session>>,
+}
+
+enum Nudge {
+    /// Re-check pause state and drift now.
+    Check,
+    /// The transport seeked to this position (ms).
+    Seek(u64),
+    /// The player window moved or was resized.
+    Place,
+}
+
+struct Session {
+    child: Child,
+    ipc: String,
+    path: String,
+    /// mpv draws in its own window over the player (`Target::Over`).
+    over: bool,
+    /// Wakes the sync thread; dropping it ends the thread.
+    nudge: mpsc::Sender<Nudge>,
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        // mpv only renders, so nothing is lost by killing it
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        #[cfg(unix)]
+        let _ = std::fs::remove_file(&self.ipc);
+    }
+}
+
+/// Where mpv draws.
+#[derive(Debug, Clone, PartialEq)]
+enum Target {
+    /// Inside the player window (`--wid`).
+    Embed(u64),
+    /// A borderless window covering it.
+    Over { x: i32, y: i32, width: u32, height: u32 },
+}
+
+fn target(window: &WebviewWindow) -> Result<Target, String> {
+    let handle = window.window_handle().map_err(|e| format!("No native window handle: {}", e))?;
+    match handle.as_raw() {
+        RawWindowHandle::Win32(h) => return Ok(Target::Embed(h.hwnd.get() as u64)),
+        RawWindowHandle::Xlib(h) => return Ok(Target::Embed(h.window as u64)),
+        RawWindowHandle::Xcb(h) => return Ok(Target::Embed(h.window.get() as u64)),
+        _ => {}
+    }
+    let position = window.outer_position().map_err(|e| e.to_string())?;
+    let size = window.outer_size().map_err(|e| e.to_string())?;
+    Ok(Target::Over { x: position.x, y: position.y, width: size.width, height: size.height })
+}
+
+fn geometry(width: u32, height: u32, x: i32, y: i32) -> String {
+    format!("{}x{}+{}+{}", width, height, x, y)
+}
+
+fn ipc_name() -> String {
+    let name = format!("karaoke-mpv-{}", std::process::id());
+    if cfg!(windows) {
+        format!(r"\\.\pipe\{}", name)
+    } else {
+        std::env::temp_dir().join(format!("{}.sock", name)).to_string_lossy().to_string()
+    }
+}
+
+fn mpv_args(path: &str, ipc: &str, start_ms: u64, target: &Target) -> Vec<String> {
+    let mut args: Vec<String> = [
+        "--no-config",
+        "--no-terminal",
+        "--aid=no",
+        "--hwdec=auto-safe",
+        "--vo=gpu",
+        "--pause",
+        "--keep-open=always",
+        "--force-window=immediate",
+        "--osc=no",
+        "--osd-level=0",
+        "--input-default-bindings=no",
+        "--input-vo-keyboard=no",
+        "--cursor-autohide=always",
+    ]
+    .into_iter()
+    .map(String::from)
+    .collect();
+    args.push(format!("--input-ipc-server={}", ipc));
+    args.push(format!("--start={:.3}", start_ms as f64 / 1000.0));
+    match target {
+        Target::Embed(id) => args.push(format!("--wid={}", id)),
+        Target::Over { x, y, width, height } => {
+            args.extend(["--no-border".to_string(), "--ontop".to_string()]);
+            args.push(format!("--geometry={}", geometry(*width, *height, *x, *y)));
+        }
+    }
+    args.extend(["--".to_string(), path.to_string()]);
+    args
+}
+
+// ---------------------------------------------------------------------------
+// mpv JSON IPC
+// ---------------------------------------------------------------------------
+
+#[cfg(unix)]
+fn connect(ipc: &str) -> std::io::Result<std::os::unix::net::UnixStream> {
+    let stream = std::os::unix::net::UnixStream::connect(ipc)?;
+    stream.set_read_timeout(Some(IPC_TIMEOUT))?;
+    stream.set_write_timeout(Some(IPC_TIMEOUT))?;
+    Ok(stream)
+}
+
+/// Opens the pipe, retrying while mpv serves another client. A pipe has
+/// no read timeout, so `request` bounds the whole exchange instead.
+#[cfg(windows)]
+fn connect(ipc: &str) -> std::io::Result<std::fs::File> {
+    const ERROR_PIPE_BUSY: i32 = 231;
+    let started = Instant::now();
+    loop {
+        match std::fs::OpenOptions::new().read(true).write(true).open(ipc) {
+            Err(e) if e.raw_os_error() == Some(ERROR_PIPE_BUSY) && started.elapsed() < IPC_TIMEOUT => {
+                thread::sleep(Duration::from_millis(20));
+            }
+            result => return result,
+        }
+    }
+}
+
+/// The answer to our request in one line of mpv output; `None` for events
+/// and other replies.
+fn parse_reply(line: &str) -> Option<Result<Value, String>> {
+    let value: Value = serde_json::from_str(line.trim()).ok()?;
+    if value.get("request_id")?.as_u64()? != REQUEST_ID {
+        return None;
+    }
+    Some(match value.get("error").and_then(Value::as_str) {
+        Some("success") => Ok(value.get("data").cloned().unwrap_or(Value::Null)),
+        error => Err(format!("mpv: {}", error.unwrap_or("no answer"))),
+    })
+}
+
+fn exchange(ipc: &str, command: Value) -> Result<Value, String> {
+    let mut stream = connect(ipc).map_err(|e| format!("Failed to reach mpv: {}", e))?;
+    let line = format!("{}\n", json!({ "command": command, "request_id": REQUEST_ID }));
+    stream.write_all(line.as_bytes()).map_err(|e| format!("Failed to send to mpv: {}", e))?;
+    let mut reader = BufReader::new(stream);
+    let mut buf = String::new();
+    loop {
+        buf.clear();
+        if reader.read_line(&mut buf).map_err(|e| format!("Failed to read from mpv: {}", e))? == 0 {
+            return Err("mpv closed the connection".to_string());
+        }
+        if let Some(reply) = parse_reply(&buf) {
+            return reply;
+        }
+    }
+}
+
+#[cfg(unix)]
+fn request(ipc: &str, command: Value) -> Result<Value, String> {
+    exchange(ipc, command)
+}
+
+/// A stuck pipe read is left on its own thread; killing mpv ends it.
+#[cfg(windows)]
+fn request(ipc: &str, command: Value) -> Result<Value, String> {
+    let (tx, rx) = mpsc::channel();
+    let ipc = ipc.to_string();
+    thread::Builder::new()
+        .name("karaoke-mpv-ipc".into())
+        .spawn(move || {
+            let _ = tx.send(exchange(&ipc, command));
+        })
+        .map_err(|e| format!("Failed to spawn mpv request: {}", e))?;
+    rx.recv_timeout(IPC_TIMEOUT).unwrap_or_else(|_| Err("mpv did not answer".to_string()))
+}
+
+fn seek_command(position_ms: u64) -> Value {
+    json!(["seek", position_ms as f64 / 1000.0, "absolute+exact"])
+}
+
+fn drifted(video_ms: i64, audio_ms: u64) -> bool {
+    (video_ms - audio_ms as i64).abs() > MAX_DRIFT_MS
+}
+
+/// Move mpv's window onto the player window where it is now.
+fn place(app: &AppHandle, ipc: &str) {
+    let Some(window) = app.get_webview_window(PLAYER_LABEL) else { return };
+    if let Ok(Target::Over { x, y, width, height }) = target(&window) {
+        let _ = request(ipc, json!(["set_property", "geometry", geometry(width, height, x, y)]));
+    }
+}
+
+/// Keeps mpv on the audio clock until the session's sender is dropped.
+fn run_sync(app: AppHandle, ipc: String, over: bool, nudges: mpsc::Receiver<Nudge>) {
+    let started = Instant::now();
+    while request(&ipc, json!(["get_property", "pid"])).is_err() {
+        if started.elapsed() > IPC_TIMEOUT {
+            tracing::warn!("[video] mpv did not open its IPC socket");
+            return;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    // The window may have moved while mpv started
+    if over {
+        place(&app, &ipc);
+    }
+
+    let mut paused = true;
+    loop {
+        let first = match nudges.recv_timeout(SYNC_INTERVAL) {
+            Ok(nudge) => Some(nudge),
+            Err(mpsc::RecvTimeoutError::Timeout) => None,
+            Err(mpsc::RecvTimeoutError::Disconnected) => return,
+        };
+        // Fold what queued up meanwhile: a drag sends a burst of moves
+        let (mut seek_to, mut moved) = (None, false);
+        for nudge in first.into_iter().chain(std::iter::from_fn(|| nudges.try_recv().ok())) {
+            match nudge {
+                Nudge::Seek(position_ms) => seek_to = Some(position_ms),
+                Nudge::Place => moved = true,
+                Nudge::Check => {}
+            }
+        }
+        if moved && over {
+            place(&app, &ipc);
+        }
+        if let Some(position_ms) = seek_to {
+            let _ = request(&ipc, seek_command(position_ms));
+            continue;
+        }
+        let Some(audio) = app.try_state::<AudioState>() else { continue };
+        let clock = audio.position();
+        if clock.is_playing == paused && request(&ipc, json!(["set_property", "pause", !clock.is_playing])).is_ok() {
+            paused = !clock.is_playing;
+        }
+        let video_ms = request(&ipc, json!(["get_property", "time-pos"])).ok().and_then(|v| v.as_f64());
+        if let Some(video_ms) = video_ms.map(|s| (s * 1000.0) as i64) {
+            if drifted(video_ms, clock.position_ms) {
+                let _ = request(&ipc, seek_command(clock.position_ms));
+            }
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Transport
+// ---------------------------------------------------------------------------
+
+fn enabled(app: &AppHandle) -> bool {
+    let Some(db) = app.try_state::<DbState>() else { return true };
+    let Ok(conn) = db.conn.lock() else { return true };
+    read_setting(&conn, SETTING_KEY).is_none_or(|v| v.trim() != "false")
+}
+
+fn start(app: &AppHandle, window: &WebviewWindow, file_path: &str) -> Result<Session, String> {
+    let (mpv, _) = locate_tool(app, "mpv").ok_or("mpv not found")?;
+    let target = target(window)?;
+    let over = matches!(target, Target::Over { .. });
+    let ipc = ipc_name();
+    let start_ms = app.try_state::<AudioState>().map_or(0, |audio| audio.position().position_ms);
+    let child = hidden_command(&mpv)
+        .args(mpv_args(file_path, &ipc, start_ms, &target))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("Failed to start mpv: {}", e))?;
+
+    let (nudge, nudges) = mpsc::channel();
+    let (sync_app, sync_ipc) = (app.clone(), ipc.clone());
+    thread::Builder::new()
+        .name("karaoke-native-video".into())
+        .spawn(move || run_sync(sync_app, sync_ipc, over, nudges))
+        .map_err(|e| format!("Failed to spawn video sync: {}", e))?;
+    Ok(Session { child, ipc, path: file_path.to_string(), over, nudge })
+}
+
+fn status(session: Option<&Session>) -> NativeVideo {
+    NativeVideo { active: session.is_some(), path: session.map(|s| s.path.clone()) }
+}
+
+/// Show `file_path` natively if it is a video, the player window is open
+/// and mpv is available; otherwise end any native picture. Called whenever
+/// a file is opened on the native player.
+pub fn open(app: &AppHandle, file_path: &str) {
+    let Some(state) = app.try_state::<NativeVideoState>() else { return };
+    let Ok(mut session) = state.session.lock() else { return };
+    let was_active = session.take().is_some();
+
+    let window = app.get_webview_window(PLAYER_LABEL).filter(|w| w.is_visible().unwrap_or(false));
+    if let Some(window) = window.filter(|_| has_extension(Path::new(file_path), NATIVE_EXTENSIONS) && enabled(app)) {
+        match start(app, &window, file_path) {
+            Ok(started) => {
+                tracing::info!("[video] Native playback of {}", file_path);
+                *session = Some(started);
+            }
+            Err(e) => tracing::warn!("[video] Native playback unavailable, the player view plays it: {}", e),
+        }
+    }
+    if was_active || session.is_some() {
+        publish(app, AppEvent::NativeVideo(status(session.as_ref())));
+    }
+}
+
+/// End the native picture (stop, player window closed, exit).
+pub fn close(app: &AppHandle) {
+    let Some(state) = app.try_state::<NativeVideoState>() else { return };
+    let Ok(mut session) = state.session.lock() else { return };
+    if session.take().is_some() {
+        publish(app, AppEvent::NativeVideo(NativeVideo::default()));
+    }
+}
+
+fn send(app: &AppHandle, nudge: Nudge) {
+    let Some(state) = app.try_state::<NativeVideoState>() else { return };
+    if let Ok(session) = state.session.lock() {
+        if let Some(session) = session.as_ref() {
+            let _ = session.nudge.send(nudge);
+        }
+    }
+}
+
+/// The transport played or paused; the picture follows at once.
+pub fn follow(app: &AppHandle) {
+    send(app, Nudge::Check);
+}
+
+/// The transport seeked to `position_ms`.
+pub fn seek(app: &AppHandle, position_ms: u64) {
+    send(app, Nudge::Seek(position_ms));
+}
+
+/// The player window moved or was resized; a picture over it follows.
+pub fn placed(app: &AppHandle) {
+    let Some(state) = app.try_state::<NativeVideoState>() else { return };
+    if let Ok(session) = state.session.lock() {
+        if let Some(session) = session.as_ref().filter(|s| s.over) {
+            let _ = session.nudge.send(Nudge::Place);
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+#[tauri::command]
+pub fn get_native_video(app: AppHandle, webview: tauri::Webview) -> Result<NativeVideo, String> {
+    require_webview(&webview, Capability::ViewLibrary)?;
+    let state = app.state::<NativeVideoState>();
+    let session = state.session.lock().map_err(|e| e.to_string())?;
+    Ok(status(session.as_ref()))
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_mpv_arguments_per_target() {
+        let embedded = mpv_args("/songs/a.mp4", "/tmp/mpv.sock", 1_500, &Target::Embed(42));
+        assert!(embedded.contains(&"--wid=42".to_string()) && embedded.contains(&"--aid=no".to_string()));
+        assert!(embedded.contains(&"--start=1.500".to_string()));
+        // The path comes last, after `--`, even if it starts with a dash
+        assert_eq!(embedded[embedded.len() - 2..], ["--".to_string(), "/songs/a.mp4".to_string()]);
+
+        let over = mpv_args("a.mkv", "ipc", 0, &Target::Over { x: 1920, y: 0, width: 1920, height: 1080 });
+        assert!(over.contains(&"--geometry=1920x1080+1920+0".to_string()) && !over.iter().any(|a| a.starts_with("--wid")));
+    }
+
+    #[test]
+    fn picks_our_reply_out_of_mpv_output() {
+        assert_eq!(parse_reply(r#"{"event":"playback-restart"}"#), None);
+        assert_eq!(parse_reply(r#"{"data":12.5,"request_id":1,"error":"success"}"#), Some(Ok(json!(12.5))));
+        assert!(matches!(parse_reply(r#"{"request_id":1,"error":"property unavailable"}"#), Some(Err(_))));
+        assert!(drifted(1_000, 1_100) && !drifted(1_000, 1_050));
+    }
+}
@@ -0,0 +1,344 @@
+//! Karaoke videos fetched from online video sites with yt-dlp.
+//!
+//! `fetch_online_track` runs the yt-dlp the app manages (see `tools`) for
+//! one link, in the background: the video (or only its audio) is saved to
+//! the downloads directory, with title and uploader embedded as tags when
+//! ffmpeg is around, and then handed to the import worker like a dropped
+//! file, which reads its metadata and files it into the library (see
+//! `library::drop_import`). Progress is published as `online://progress`,
+//! one line of yt-dlp's progress template at a time.
+//!
+//! Pulling content from the internet is off until a venue turns it on
+//! with `[online] enabled = true` in `config.toml`.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio_util::sync::CancellationToken;
+
+use super::ffmpeg::{hidden_command, locate_ffmpeg};
+use super::tools::locate_tool;
+use crate::access::{require_webview, Capability};
+use crate::events::{publish, AppEvent};
+use crate::library::import_queue::ImportQueue;
+use crate::library::quota::{storage_dir, StorageKind};
+use crate::runtime::TaskSupervisor;
+
+pub const PROGRESS_EVENT: &str = "online://progress";
+
+const PROGRESS_PREFIX: &str = "[progress]";
+const FILE_PREFIX: &str = "[file]";
+/// Highest video resolution fetched; karaoke lyrics are legible well below.
+const MAX_HEIGHT: u32 = 1080;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FetchPhase {
+    Starting,
+    Downloading,
+    /// Merging, converting or tagging after the download.
+    Processing,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+/// Payload of `online://progress`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FetchProgress {
+    pub fetch_id: u64,
+    pub url: String,
+    pub phase: FetchPhase,
+    pub bytes_done: Option<u64>,
+    pub bytes_total: Option<u64>,
+    pub bytes_per_sec: Option<u64>,
+    pub eta_secs: Option<u64>,
+    /// The downloaded file, once done.
+    pub path: Option<String>,
+    /// Import job filing it into the library (`library://import-*`).
+    pub import_job_id: Option<u64>,
+    pub error: Option<String>,
+}
+
+impl FetchProgress {
+    fn new(fetch_id: u64, url: &str, phase: FetchPhase) -> Self {
+        Self {
+            fetch_id,
+            url: url.to_string(),
+            phase,
+            bytes_done: None,
+            bytes_total: None,
+            bytes_per_sec: None,
+            eta_secs: None,
+            path: None,
+            import_job_id: None,
+            error: None,
+        }
+    }
+}
+
+/// Managed state: running fetches, stoppable by id.
+#[derive(Default)]
+pub struct OnlineState {
+    next_id: AtomicU64,
+    running: Mutex<HashMap<u64, CancellationToken>>,
+}
+
+impl OnlineState {
+    fn finish(&self, id: u64) {
+        if let Ok(mut running) = self.running.lock() {
+            running.remove(&id);
+        }
+    }
+}
+
+/// Whether `[online] enabled` allows fetching.
+pub fn enabled(app: &AppHandle) -> bool {
+    crate::config::current(app).online.enabled
+}
+
+/// yt-dlp arguments for `url`, saving into `dir`. Without ffmpeg only
+/// single-file formats can be fetched and nothing is tagged or converted.
+/// A yt-dlp config file on the machine is ignored: its options could
+/// change where and what gets saved, or the output we parse.
+fn build_args(url: &str, dir: &Path, audio_only: bool, ffmpeg: Option<&Path>) -> Vec<String> {
+    let mut args: Vec<String> = [
+        "--ignore-config",
+        "--no-playlist",
+        "--newline",
+        "--progress",
+        "--no-simulate",
+        "--progress-template",
+        "download:[progress]%(progress.downloaded_bytes)s/%(progress.total_bytes,progress.total_bytes_estimate)s/%(progress.speed)s/%(progress.eta)s",
+        "--print",
+        "after_move:[file]%(filepath)s",
+        "--windows-filenames",
+        "--no-mtime",
+        "--no-overwrites",
+    ]
+    .map(String::from)
+    .to_vec();
+    args.extend(["--paths".to_string(), dir.to_string_lossy().to_string()]);
+    args.extend(["--output".to_string(), "%(title)s.%(ext)s".to_string()]);
+    match (audio_only, ffmpeg) {
+        (true, Some(_)) => args.extend(["--extract-audio", "--audio-format", "mp3"].map(String::from)),
+        (true, None) => args.extend(["--format", "ba[ext=m4a]/ba"].map(String::from)),
+        (false, Some(_)) => {
+            let format = format!("bv*[height<={h}]+ba/b[height<={h}]/b", h = MAX_HEIGHT);
+            args.extend(["--format".to_string(), format, "--merge-output-format".to_string(), "mp4".to_string()]);
+        }
+        (false, None) => {
+            let format = format!("b[ext=mp4][height<={h}]/b[height<={h}]/b", h = MAX_HEIGHT);
+            args.extend(["--format".to_string(), format]);
+        }
+    }
+    if let Some(ffmpeg) = ffmpeg {
+        args.extend(["--embed-metadata".to_string(), "--ffmpeg-location".to_string(), ffmpeg.to_string_lossy().to_string()]);
+    }
+    // Nothing after this is read as an option, whatever the link holds
+    args.extend(["--".to_string(), url.to_string()]);
+    args
+}
+
+/// Bytes done, total, speed and ETA from one progress line; yt-dlp prints
+/// `NA` for what it does not know.
+fn parse_progress(line: &str) -> Option<[Option<u64>; 4]> {
+    let fields: Vec<Option<u64>> = line
+        .strip_prefix(PROGRESS_PREFIX)?
+        .trim()
+        .split('/')
+        .map(|field| field.trim().parse::<f64>().ok().filter(|n| n.is_finite() && *n >= 0.0).map(|n| n as u64))
+        .collect();
+    fields.try_into().ok()
+}
+
+/// Run yt-dlp for one fetch; returns the saved file.
+async fn run_fetch(
+    app: &AppHandle,
+    id: u64,
+    url: &str,
+    audio_only: bool,
+    token: &CancellationToken,
+) -> Result<Option<PathBuf>, String> {
+    let (yt_dlp, _) = locate_tool(app, "yt-dlp").ok_or("yt-dlp is not installed")?;
+    let dir = storage_dir(app, StorageKind::Downloads)?;
+    std::fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let ffmpeg = locate_ffmpeg(app);
+
+    let mut command = tokio::process::Command::from(hidden_command(&yt_dlp));
+    command
+        .args(build_args(url, &dir, audio_only, ffmpeg.as_deref()))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    let mut child = command.spawn().map_err(|e| format!("Failed to start yt-dlp: {}", e))?;
+
+    // The last line on stderr explains a failure
+    let stderr = child.stderr.take().ok_or("No yt-dlp stderr")?;
+    let last_error = tauri::async_runtime::spawn(async move {
+        let mut lines = BufReader::new(stderr).lines();
+        let mut last = String::new();
+        while let Ok(Some(line)) = lines.next_line().await {
+            if !line.trim().is_empty() {
+                last = line.trim().to_string();
+            }
+        }
+        last
+    });
+
+    let stdout = child.stdout.take().ok_or("No yt-dlp stdout")?;
+    let mut lines = BufReader::new(stdout).lines();
+    let mut saved = None;
+    loop {
+        let line = tokio::select! {
+            _ = token.cancelled() => {
+                let _ = child.kill().await;
+                return Ok(None);
+            }
+            line = lines.next_line() => line.map_err(|e| format!("Failed to read yt-dlp output: {}", e))?,
+        };
+        let Some(line) = line else { break };
+        if let Some([done, total, speed, eta]) = parse_progress(&line) {
+            publish(
+                app,
+                AppEvent::OnlineFetchProgress(FetchProgress {
+                    bytes_done: done,
+                    bytes_total: total,
+                    bytes_per_sec: speed,
+                    eta_secs: eta,
+                    ..FetchProgress::new(id, url, FetchPhase::Downloading)
+                }),
+            );
+        } else if let Some(path) = line.strip_prefix(FILE_PREFIX) {
+            saved = Some(PathBuf::from(path.trim()));
+        } else if line.starts_with("[Merger]") || line.starts_with("[ExtractAudio]") || line.starts_with("[Metadata]") {
+            publish(app, AppEvent::OnlineFetchProgress(FetchProgress::new(id, url, FetchPhase::Processing)));
+        }
+    }
+
+    let status = tokio::select! {
+        _ = token.cancelled() => {
+            let _ = child.kill().await;
+            return Ok(None);
+        }
+        status = child.wait() => status.map_err(|e| format!("Failed to wait for yt-dlp: {}", e))?,
+    };
+    if token.is_cancelled() {
+        return Ok(None);
+    }
+    if !status.success() {
+        let reason = last_error.await.unwrap_or_default();
+        return Err(if reason.is_empty() { format!("yt-dlp failed ({})", status) } else { reason });
+    }
+    saved.filter(|path| path.is_file()).map(Some).ok_or_else(|| "yt-dlp saved no file".to_string())
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+/// Fetch the video behind `url` (only its audio with `audio_only`) and
+/// import it. Returns the fetch id used in `online://progress` at once.
+#[tauri::command]
+pub fn fetch_online_track(
+    app: AppHandle,
+    webview: tauri::Webview,
+    url: String,
+    audio_only: Option<bool>,
+) -> Result<u64, String> {
+    require_webview(&webview, Capability::ManageLibrary)?;
+    if !enabled(&app) {
+        return Err("Fetching online tracks is off; turn it on with [online] enabled = true".to_string());
+    }
+    let url = crate::downloads::validate_url(&url)?.to_string();
+    if locate_tool(&app, "yt-dlp").is_none() {
+        return Err("yt-dlp is not installed".to_string());
+    }
+
+    let state = app.state::<OnlineState>();
+    let id = state.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+    let supervisor = app.state::<TaskSupervisor>();
+    // Registered before the task starts, so a cancel sent right after this
+    // returns finds it
+    let token = supervisor.token();
+    if token.is_cancelled() {
+        return Err("Shutting down".to_string());
+    }
+    state.running.lock().map_err(|e| e.to_string())?.insert(id, token.clone());
+    let task_app = app.clone();
+    supervisor.spawn("online-fetch", move |_| async move {
+        let app = task_app;
+        publish(&app, AppEvent::OnlineFetchProgress(FetchProgress::new(id, &url, FetchPhase::Starting)));
+
+        let outcome = match run_fetch(&app, id, &url, audio_only.unwrap_or(false), &token).await {
+            Ok(Some(path)) => {
+                tracing::info!("[online] Fetch {} saved {}", id, path.display());
+                let import = app.state::<ImportQueue>().enqueue_into_library(vec![path.clone()]);
+                match import {
+                    Ok(job_id) => FetchProgress {
+                        path: Some(path.to_string_lossy().to_string()),
+                        import_job_id: Some(job_id),
+                        ..FetchProgress::new(id, &url, FetchPhase::Done)
+                    },
+                    Err(e) => FetchProgress { error: Some(e), ..FetchProgress::new(id, &url, FetchPhase::Failed) },
+                }
+            }
+            Ok(None) => FetchProgress::new(id, &url, FetchPhase::Cancelled),
+            Err(e) => {
+                tracing::error!("[online] Fetch {} failed: {}", id, e);
+                FetchProgress { error: Some(e), ..FetchProgress::new(id, &url, FetchPhase::Failed) }
+            }
+        };
+        app.state::<OnlineState>().finish(id);
+        publish(&app, AppEvent::OnlineFetchProgress(outcome));
+    });
+    Ok(id)
+}
+
+/// Stop a running fetch; returns whether one was running.
+#[tauri::command]
+pub fn cancel_online_fetch(app: AppHandle, webview: tauri::Webview, fetch_id: u64) -> Result<bool, String> {
+    require_webview(&webview, Capability::ManageLibrary)?;
+    let state = app.state::<OnlineState>();
+    let running = state.running.lock().map_err(|e| e.to_string())?;
+    Ok(running.get(&fetch_id).map(CancellationToken::cancel).is_some())
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_progress_lines() {
+        assert_eq!(
+            parse_progress("[progress]1048576/10485760/524288.5/17"),
+            Some([Some(1_048_576), Some(10_485_760), Some(524_288), Some(17)])
+        );
+        assert_eq!(parse_progress("[progress]2048/NA/NA/NA"), Some([Some(2048), None, None, None]));
+        assert_eq!(parse_progress("[download] Destination: a.mp4"), None);
+    }
+
+    #[test]
+    fn links_never_become_options() {
+        let args = build_args("https://youtu.be/abc", Path::new("/dl"), false, None);
+        assert_eq!(args[0], "--ignore-config");
+        assert_eq!(&args[args.len() - 2..], ["--", "https://youtu.be/abc"]);
+        assert!(!args.iter().any(|a| a == "--embed-metadata"));
+
+        let args = build_args("https://youtu.be/abc", Path::new("/dl"), true, Some(Path::new("/bin/ffmpeg")));
+        assert!(args.iter().any(|a| a == "--extract-audio"));
+        assert!(args.windows(2).any(|w| w == ["--ffmpeg-location", "/bin/ffmpeg"]));
+    }
+}
@@ -0,0 +1,309 @@
+//! Background video thumbnail extraction with an LRU-evicted cache.
+//!
+//! `get_video_thumbnail` answers from the cache immediately; on a miss it
+//! queues a high-priority background job (see `jobs`) that grabs one frame
+//! with ffmpeg and emits `thumbnail://ready` (or `thumbnail://failed`) when
+//! done. Both carry the `cache://` URL the webview loads the JPEG from
+//! (see `cache_scheme`).
+//!
+//! Thumbnails live in `<app cache dir>/thumbnails/` as JPEGs keyed by path,
+//! size, mtime, timestamp and width, so an edited video gets a fresh one.
+//! A file's mtime doubles as its last-use time: hits touch it, and when the
+//! folder grows past `thumbnail_cache_max_mb` the least recently used files
+//! are evicted first.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+use super::cache_scheme;
+use super::ffmpeg::{locate_ffmpeg, run_ffmpeg};
+use crate::access::{require_webview, Capability};
+use crate::db::DbState;
+use crate::events::{publish, AppEvent};
+use crate::jobs::{self, JobSpec};
+use crate::library::roots::require_in_library;
+use crate::library::scanner::fnv1a64;
+
+pub const THUMBNAIL_READY_EVENT: &str = "thumbnail://ready";
+pub const THUMBNAIL_FAILED_EVENT: &str = "thumbnail://failed";
+
+pub const CACHE_SUBDIR: &str = "thumbnails";
+const MAX_CACHE_KEY: &str = "thumbnail_cache_max_mb";
+const DEFAULT_MAX_CACHE_MB: u64 = 200;
+
+const DEFAULT_WIDTH: u32 = 320;
+/// Default grab position: past typical black intro frames.
+const DEFAULT_TIME_SEC: f64 = 10.0;
+const FFMPEG_TIMEOUT: Duration = Duration::from_secs(20);
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ThumbnailEvent {
+    pub video_path: String,
+    pub thumbnail_path: Option<String>,
+    /// `cache://` URL of the thumbnail.
+    pub url: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase", tag = "status")]
+pub enum ThumbnailStatus {
+    /// Cached; the webview loads it from `url`.
+    Ready { path: String, url: Option<String> },
+    /// Queued; a `thumbnail://ready` event follows.
+    Pending,
+}
+
+/// One frame to grab, as queued.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ThumbnailJob {
+    pub(crate) video: PathBuf,
+    pub(crate) time_sec: f64,
+    pub(crate) width: u32,
+    pub(crate) target: PathBuf,
+}
+
+/// Managed state: the cache and the grabs queued.
+pub struct ThumbnailService {
+    /// Targets queued or being extracted, so repeated requests while a
+    /// library view scrolls do not queue duplicates.
+    in_flight: Mutex<HashSet<PathBuf>>,
+    cache_dir: PathBuf,
+}
+
+impl ThumbnailService {
+    pub fn new(app: AppHandle) -> Result<Self, String> {
+        let cache_dir = app
+            .path()
+            .app_cache_dir()
+            .map_err(|e| format!("Failed to get cache dir: {}", e))?
+            .join(CACHE_SUBDIR);
+
+        Ok(Self {
+            in_flight: Mutex::new(HashSet::new()),
+            cache_dir,
+        })
+    }
+}
+
+/// Let `target` be queued again, once its job ran or was cancelled.
+pub(crate) fn forget(app: &AppHandle, target: &Path) {
+    if let Some(service) = app.try_state::<ThumbnailService>() {
+        if let Ok(mut in_flight) = service.in_flight.lock() {
+            in_flight.remove(target);
+        }
+    }
+}
+
+/// Grab the frame of a thumbnail job; blocking.
+pub(crate) fn run_job(app: &AppHandle, job: ThumbnailJob) -> Result<(), String> {
+    let result = extract(app, &job);
+    forget(app, &job.target);
+
+    let video_path = job.video.to_string_lossy().to_string();
+    match result {
+        Ok(()) => {
+            publish(
+                app,
+                AppEvent::ThumbnailReady(ThumbnailEvent {
+                    video_path,
+                    thumbnail_path: Some(job.target.to_string_lossy().to_string()),
+                    url: cache_scheme::url_for(app, &job.target),
+                    error: None,
+                }),
+            );
+            if let Some(parent) = job.target.parent() {
+                evict_lru(parent, max_cache_bytes(app));
+            }
+            Ok(())
+        }
+        Err(e) => {
+            tracing::warn!("[thumbnails] {}: {}", video_path, e);
+            publish(
+                app,
+                AppEvent::ThumbnailFailed(ThumbnailEvent {
+                    video_path,
+                    thumbnail_path: None,
+                    url: None,
+                    error: Some(e.clone()),
+                }),
+            );
+            Err(e)
+        }
+    }
+}
+
+fn extract(app: &AppHandle, job: &ThumbnailJob) -> Result<(), String> {
+    let ffmpeg = locate_ffmpeg(app).ok_or("ffmpeg not found")?;
+    let dir = job.target.parent().ok_or("Invalid thumbnail path")?;
+    std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create thumbnail cache: {}", e))?;
+
+    // Clips shorter than the grab position yield no frame: retry at the start
+    let tmp = job.target.with_extension("tmp.jpg");
+    let mut last_error = String::new();
+    for time in [job.time_sec, 0.0] {
+        let args = vec![
+            "-ss".to_string(),
+            format!("{:.3}", time),
+            "-i".to_string(),
+            job.video.to_string_lossy().to_string(),
+            "-frames:v".to_string(),
+            "1".to_string(),
+            "-vf".to_string(),
+            format!("scale={}:-2", job.width),
+            "-q:v".to_string(),
+            "4".to_string(),
+            "-y".to_string(),
+            tmp.to_string_lossy().to_string(),
+        ];
+        match run_ffmpeg(&ffmpeg, &args, FFMPEG_TIMEOUT) {
+            Ok(()) if tmp.is_file() => {
+                return std::fs::rename(&tmp, &job.target).map_err(|e| format!("Failed to store thumbnail: {}", e));
+            }
+            Ok(()) => last_error = "No frame at that position".to_string(),
+            Err(e) => last_error = e,
+        }
+        if time == 0.0 {
+            break;
+        }
+    }
+    let _ = std::fs::remove_file(&tmp);
+    Err(last_error)
+}
+
+/// Cache file name for a video frame request.
+fn cache_key(video: &Path, time_sec: f64, width: u32) -> Result<String, String> {
+    let meta = std::fs::metadata(video).map_err(|e| format!("Video not found: {}: {}", video.display(), e))?;
+    let mtime = meta
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let id = format!("{}|{}|{}|{:.3}|{}", video.to_string_lossy(), meta.len(), mtime, time_sec, width);
+    Ok(format!("{:016x}.jpg", fnv1a64(id.as_bytes())))
+}
+
+fn max_cache_bytes(app: &AppHandle) -> u64 {
+    let configured = app.try_state::<DbState>().and_then(|db| {
+        let conn = db.conn.lock().ok()?;
+        conn.query_row("SELECT value FROM app_settings WHERE key = ?1", [MAX_CACHE_KEY], |row| {
+            row.get::<_, String>(0)
+        })
+        .ok()
+    });
+    configured
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .filter(|&mb| mb > 0)
+        .unwrap_or(DEFAULT_MAX_CACHE_MB)
+        * 1024
+        * 1024
+}
+
+/// Delete least recently used thumbnails until the folder fits `max_bytes`.
+fn evict_lru(dir: &Path, max_bytes: u64) {
+    let Ok(entries) = std::fs::read_dir(dir) else { return };
+    let mut files: Vec<(SystemTime, u64, PathBuf)> = entries
+        .flatten()
+        .filter_map(|e| {
+            let meta = e.metadata().ok()?;
+            meta.is_file().then(|| (meta.modified().unwrap_or(SystemTime::UNIX_EPOCH), meta.len(), e.path()))
+        })
+        .collect();
+
+    let mut total: u64 = files.iter().map(|(_, len, _)| len).sum();
+    if total <= max_bytes {
+        return;
+    }
+    files.sort_by_key(|(used, _, _)| *used);
+    for (_, len, path) in files {
+        if total <= max_bytes {
+            break;
+        }
+        if std::fs::remove_file(&path).is_ok() {
+            total -= len;
+        }
+    }
+}
+
+/// Mark a cache hit as recently used.
+fn touch(path: &Path) {
+    if let Ok(file) = std::fs::File::options().write(true).open(path) {
+        let _ = file.set_modified(SystemTime::now());
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+/// Thumbnail for `video_path` at `time_sec` (default 10 s), `width` pixels
+/// wide (default 320). Returns the cached file or queues extraction.
+#[tauri::command]
+pub fn get_video_thumbnail(
+    app: AppHandle,
+    webview: tauri::Webview,
+    video_path: String,
+    time_sec: Option<f64>,
+    width: Option<u32>,
+) -> Result<ThumbnailStatus, String> {
+    require_webview(&webview, Capability::ManageLibrary)?;
+    require_in_library(&app, &video_path)?;
+    let service = app.state::<ThumbnailService>();
+    let video = PathBuf::from(&video_path);
+    let time_sec = time_sec.unwrap_or(DEFAULT_TIME_SEC).max(0.0);
+    let width = width.unwrap_or(DEFAULT_WIDTH).clamp(32, 1920);
+    let target = service.cache_dir.join(cache_key(&video, time_sec, width)?);
+
+    if target.is_file() {
+        touch(&target);
+        return Ok(ThumbnailStatus::Ready {
+            path: target.to_string_lossy().to_string(),
+            url: cache_scheme::url_for(&app, &target),
+        });
+    }
+
+    let mut in_flight = service.in_flight.lock().map_err(|e| e.to_string())?;
+    if in_flight.insert(target.clone()) {
+        let job = ThumbnailJob { video, time_sec, width, target: target.clone() };
+        if let Err(e) = jobs::enqueue(&app, JobSpec::Thumbnail(job)) {
+            in_flight.remove(&target);
+            return Err(e);
+        }
+    }
+    Ok(ThumbnailStatus::Pending)
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eviction_removes_oldest_first() {
+        let dir = crate::paths::test_dir("thumbs");
+        std::fs::create_dir_all(&dir).unwrap();
+        let now = SystemTime::now();
+        for (i, name) in ["old.jpg", "mid.jpg", "new.jpg"].iter().enumerate() {
+            let path = dir.join(name);
+            std::fs::write(&path, vec![0u8; 100]).unwrap();
+            let file = std::fs::File::options().write(true).open(&path).unwrap();
+            file.set_modified(now - Duration::from_secs(300 - i as u64 * 100)).unwrap();
+        }
+
+        evict_lru(&dir, 200);
+        assert!(!dir.join("old.jpg").exists());
+        assert!(dir.join("mid.jpg").exists());
+        assert!(dir.join("new.jpg").exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
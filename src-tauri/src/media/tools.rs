@@ -0,0 +1,620 @@
+//! Managed external tools (yt-dlp, ffmpeg, mpv): versions, pinning, updates.
+//!
+//! yt-dlp breaks whenever the sites it scrapes change, and the fix is
+//! always "update yt-dlp". The app therefore keeps its own copy in
+//! `<app data>/tools/`, records what was installed in `manifest.json`, and
+//! can update it from the upstream GitHub releases — stable or nightly —
+//! verifying the download against the release's `SHA2-256SUMS` before it
+//! replaces the working binary. A pinned version is installed exactly and
+//! never auto-updated.
+//!
+//! ffmpeg and mpv are reported (managed, bundled or system copy and its
+//! version) but not updated here: upstream publishes no single-binary
+//! builds.
+//!
+//! Settings (`app_settings`):
+//!   - `tool_channel:<id>` — `stable` (default) or `nightly`
+//!   - `tool_pin:<id>`     — release tag to hold, empty for latest
+//!
+//! Automatic updates run as the `tool_updates` scheduled task.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Manager};
+
+use super::ffmpeg::{hidden_command, locate_ffmpeg};
+use crate::access::{require_webview, Capability};
+use crate::db::DbState;
+use crate::scheduler::read_setting;
+
+const TOOLS_SUBDIR: &str = "tools";
+const MANIFEST_FILE: &str = "manifest.json";
+const CHECKSUMS_ASSET: &str = "SHA2-256SUMS";
+const VERSION_TIMEOUT: Duration = Duration::from_secs(10);
+
+static HTTP_CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(120))
+        .user_agent(concat!("karaoke-successor/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .expect("Failed to build tools HTTP client")
+});
+
+/// A tool the app knows how to find (and maybe update).
+pub struct ToolSpec {
+    pub id: &'static str,
+    /// GitHub `owner/repo` per channel; `None` when updates are not managed.
+    stable_repo: Option<&'static str>,
+    nightly_repo: Option<&'static str>,
+    version_arg: &'static str,
+}
+
+pub const TOOLS: &[ToolSpec] = &[
+    ToolSpec {
+        id: "yt-dlp",
+        stable_repo: Some("yt-dlp/yt-dlp"),
+        nightly_repo: Some("yt-dlp/yt-dlp-nightly-builds"),
+        version_arg: "--version",
+    },
+    ToolSpec {
+        id: "ffmpeg",
+        stable_repo: None,
+        nightly_repo: None,
+        version_arg: "-version",
+    },
+    ToolSpec {
+        id: "mpv",
+        stable_repo: None,
+        nightly_repo: None,
+        version_arg: "--version",
+    },
+];
+
+fn spec(id: &str) -> Result<&'static ToolSpec, String> {
+    TOOLS.iter().find(|t| t.id == id).ok_or_else(|| format!("Unknown tool: {}", id))
+}
+
+impl ToolSpec {
+    fn updatable(&self) -> bool {
+        self.stable_repo.is_some()
+    }
+
+    fn repo(&self, channel: Channel) -> Option<&'static str> {
+        match channel {
+            Channel::Stable => self.stable_repo,
+            Channel::Nightly => self.nightly_repo.or(self.stable_repo),
+        }
+    }
+
+    fn binary_name(&self) -> String {
+        if cfg!(target_os = "windows") {
+            format!("{}.exe", self.id)
+        } else {
+            self.id.to_string()
+        }
+    }
+
+    /// Release asset for this platform.
+    fn asset_name(&self) -> Option<&'static str> {
+        if self.id != "yt-dlp" {
+            return None;
+        }
+        Some(if cfg!(target_os = "windows") {
+            "yt-dlp.exe"
+        } else if cfg!(target_os = "macos") {
+            "yt-dlp_macos"
+        } else if cfg!(target_arch = "aarch64") {
+            "yt-dlp_linux_aarch64"
+        } else {
+            "yt-dlp_linux"
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Channel {
+    #[default]
+    Stable,
+    Nightly,
+}
+
+/// Where the binary in use comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolSource {
+    Managed,
+    Bundled,
+    System,
+    Missing,
+}
+
+// ---------------------------------------------------------------------------
+// Manifest
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InstalledTool {
+    pub version: String,
+    pub channel: Channel,
+    pub sha256: String,
+    /// Epoch ms.
+    pub installed_at: i64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Manifest {
+    #[serde(default)]
+    tools: HashMap<String, InstalledTool>,
+}
+
+fn tools_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    app.path()
+        .app_data_dir()
+        .map(|dir| dir.join(TOOLS_SUBDIR))
+        .map_err(|e| format!("No app data dir: {}", e))
+}
+
+fn read_manifest(dir: &Path) -> Manifest {
+    std::fs::read(dir.join(MANIFEST_FILE))
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+fn write_manifest(dir: &Path, manifest: &Manifest) -> Result<(), String> {
+    let json = serde_json::to_vec_pretty(manifest).map_err(|e| e.to_string())?;
+    let tmp = dir.join(format!("{}.tmp", MANIFEST_FILE));
+    std::fs::write(&tmp, json).map_err(|e| format!("Failed to write tool manifest: {}", e))?;
+    std::fs::rename(&tmp, dir.join(MANIFEST_FILE)).map_err(|e| format!("Failed to write tool manifest: {}", e))
+}
+
+// ---------------------------------------------------------------------------
+// Locating and probing
+// ---------------------------------------------------------------------------
+
+/// Path of the binary to run for `id`: the managed copy first, then the
+/// bundled one, then `PATH`.
+pub fn locate_tool(app: &AppHandle, id: &str) -> Option<(PathBuf, ToolSource)> {
+    let spec = spec(id).ok()?;
+    if let Ok(dir) = tools_dir(app) {
+        let managed = dir.join(spec.binary_name());
+        if managed.is_file() {
+            return Some((managed, ToolSource::Managed));
+        }
+    }
+    if id == "ffmpeg" {
+        // Honours the configured path and the bundled copy
+        return locate_ffmpeg(app).map(|path| {
+            let bundled = app.path().resource_dir().is_ok_and(|res| path.starts_with(res));
+            (path, if bundled { ToolSource::Bundled } else { ToolSource::System })
+        });
+    }
+    if let Ok(resource_dir) = app.path().resource_dir() {
+        let bundled = resource_dir.join("bundled").join("native").join(spec.binary_name());
+        if bundled.is_file() {
+            return Some((bundled, ToolSource::Bundled));
+        }
+    }
+    let finder = if cfg!(target_os = "windows") { "where" } else { "which" };
+    let output = hidden_command(finder).arg(spec.binary_name()).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .next()
+        .map(|line| PathBuf::from(line.trim()))
+        .filter(|p| p.is_file())
+        .map(|p| (p, ToolSource::System))
+}
+
+/// Version reported by the binary itself.
+fn probe_version(path: &Path, spec: &ToolSpec) -> Option<String> {
+    let mut child = hidden_command(path)
+        .arg(spec.version_arg)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .ok()?;
+    let started = std::time::Instant::now();
+    loop {
+        match child.try_wait() {
+            Ok(Some(_)) => break,
+            Ok(None) if started.elapsed() > VERSION_TIMEOUT => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return None;
+            }
+            Ok(None) => std::thread::sleep(Duration::from_millis(20)),
+            Err(_) => return None,
+        }
+    }
+    let mut text = String::new();
+    child.stdout.take()?.read_to_string(&mut text).ok()?;
+    parse_version_output(spec.id, &text)
+}
+
+/// `yt-dlp --version` prints the bare version; `ffmpeg -version` starts
+/// with `ffmpeg version <v> Copyright …`.
+fn parse_version_output(id: &str, output: &str) -> Option<String> {
+    let first = output.lines().next()?.trim();
+    let version = match id {
+        "ffmpeg" => first.strip_prefix("ffmpeg version ")?.split_whitespace().next()?,
+        _ => first,
+    };
+    (!version.is_empty()).then(|| version.to_string())
+}
+
+/// Compare dotted versions numerically (`2024.08.06` < `2024.08.06.232716`);
+/// non-numeric parts compare as text.
+pub fn compare_versions(a: &str, b: &str) -> std::cmp::Ordering {
+    let parts = |v: &str| -> Vec<String> { v.trim_start_matches('v').split(['.', '-']).map(str::to_string).collect() };
+    let (a, b) = (parts(a), parts(b));
+    for i in 0..a.len().max(b.len()) {
+        let (x, y) = (a.get(i), b.get(i));
+        let ord = match (x, y) {
+            (None, _) => std::cmp::Ordering::Less,
+            (_, None) => std::cmp::Ordering::Greater,
+            (Some(x), Some(y)) => match (x.parse::<u64>(), y.parse::<u64>()) {
+                (Ok(x), Ok(y)) => x.cmp(&y),
+                _ => x.cmp(y),
+            },
+        };
+        if ord != std::cmp::Ordering::Equal {
+            return ord;
+        }
+    }
+    std::cmp::Ordering::Equal
+}
+
+/// Expected hash of `asset` from a `sha256sum`-style listing.
+fn checksum_for(listing: &str, asset: &str) -> Option<String> {
+    listing.lines().find_map(|line| {
+        let (hash, name) = line.trim().split_once(char::is_whitespace)?;
+        // `*name` marks binary mode in sha256sum output
+        let name = name.trim().trim_start_matches('*');
+        (name == asset && hash.len() == 64).then(|| hash.to_ascii_lowercase())
+    })
+}
+
+// ---------------------------------------------------------------------------
+// Releases
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Deserialize)]
+struct GithubRelease {
+    tag_name: String,
+    assets: Vec<GithubAsset>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GithubAsset {
+    name: String,
+    browser_download_url: String,
+}
+
+async fn fetch_release(repo: &str, tag: Option<&str>) -> Result<GithubRelease, String> {
+    let url = match tag {
+        Some(tag) => format!("https://api.github.com/repos/{}/releases/tags/{}", repo, tag),
+        None => format!("https://api.github.com/repos/{}/releases/latest", repo),
+    };
+    let response = HTTP_CLIENT
+        .get(&url)
+        .header("Accept", "application/vnd.github+json")
+        .send()
+        .await
+        .map_err(|e| format!("Release check failed: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!("Release check failed: HTTP {} for {}", response.status(), url));
+    }
+    response.json().await.map_err(|e| format!("Invalid release data: {}", e))
+}
+
+async fn download(url: &str) -> Result<Vec<u8>, String> {
+    let response = HTTP_CLIENT.get(url).send().await.map_err(|e| format!("Download failed: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!("Download failed: HTTP {}", response.status()));
+    }
+    response
+        .bytes()
+        .await
+        .map(|b| b.to_vec())
+        .map_err(|e| format!("Download failed: {}", e))
+}
+
+fn tool_setting(app: &AppHandle, prefix: &str, id: &str) -> Option<String> {
+    let db = app.try_state::<DbState>()?;
+    let conn = db.conn.lock().ok()?;
+    read_setting(&conn, &format!("{}:{}", prefix, id)).filter(|v| !v.trim().is_empty())
+}
+
+fn channel_of(app: &AppHandle, id: &str) -> Channel {
+    match tool_setting(app, "tool_channel", id).as_deref() {
+        Some("nightly") => Channel::Nightly,
+        _ => Channel::Stable,
+    }
+}
+
+fn pin_of(app: &AppHandle, id: &str) -> Option<String> {
+    tool_setting(app, "tool_pin", id).map(|v| v.trim().to_string())
+}
+
+// ---------------------------------------------------------------------------
+// State and reports
+// ---------------------------------------------------------------------------
+
+/// Managed state: last known upstream version per tool.
+#[derive(Default)]
+pub struct ToolsState {
+    latest: Mutex<HashMap<&'static str, String>>,
+    /// Serializes updates so two never write the same binary.
+    updating: tokio::sync::Mutex<()>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolVersion {
+    pub id: &'static str,
+    pub source: ToolSource,
+    pub path: Option<String>,
+    /// As reported by the binary.
+    pub version: Option<String>,
+    /// What the app installed, if it manages this copy.
+    pub installed: Option<InstalledTool>,
+    pub channel: Channel,
+    pub pinned: Option<String>,
+    pub updatable: bool,
+    /// From the last update check.
+    pub latest: Option<String>,
+    pub update_available: bool,
+}
+
+fn tool_version(app: &AppHandle, spec: &'static ToolSpec) -> ToolVersion {
+    let located = locate_tool(app, spec.id);
+    let version = located.as_ref().and_then(|(path, _)| probe_version(path, spec));
+    let installed = tools_dir(app).ok().and_then(|dir| read_manifest(&dir).tools.remove(spec.id));
+    let pinned = pin_of(app, spec.id);
+    let latest = app
+        .try_state::<ToolsState>()
+        .and_then(|s| s.latest.lock().ok().and_then(|l| l.get(spec.id).cloned()));
+    let target = pinned.clone().or_else(|| latest.clone());
+    let update_available = spec.updatable()
+        && match (&version, &target) {
+            (Some(current), Some(target)) if pinned.is_some() => current != target,
+            (Some(current), Some(target)) => compare_versions(target, current).is_gt(),
+            (None, Some(_)) => true,
+            _ => false,
+        };
+    ToolVersion {
+        id: spec.id,
+        source: located.as_ref().map(|(_, s)| *s).unwrap_or(ToolSource::Missing),
+        path: located.map(|(p, _)| p.to_string_lossy().to_string()),
+        version,
+        installed,
+        channel: channel_of(app, spec.id),
+        pinned,
+        updatable: spec.updatable(),
+        latest,
+        update_available,
+    }
+}
+
+/// Install `id` at the pinned version or the channel's latest release.
+/// Returns the installed version, or `None` if it was already current.
+pub async fn update_tool_inner(app: &AppHandle, id: &str, force: bool) -> Result<Option<String>, String> {
+    let spec = spec(id)?;
+    let channel = channel_of(app, id);
+    let repo = spec.repo(channel).ok_or_else(|| format!("{} is not updated by the app", id))?;
+    let asset_name = spec.asset_name().ok_or_else(|| format!("No {} build for this platform", id))?;
+    let state = app.state::<ToolsState>();
+    let _guard = state.updating.lock().await;
+
+    let pinned = pin_of(app, id);
+    let release = fetch_release(repo, pinned.as_deref()).await?;
+    if let Ok(mut latest) = state.latest.lock() {
+        if pinned.is_none() {
+            latest.insert(spec.id, release.tag_name.clone());
+        }
+    }
+
+    let dir = tools_dir(app)?;
+    std::fs::create_dir_all(&dir).map_err(|e| format!("Failed to create tools dir: {}", e))?;
+    let mut manifest = read_manifest(&dir);
+    let target = dir.join(spec.binary_name());
+    if !force && target.is_file() {
+        if let Some(current) = manifest.tools.get(id) {
+            if current.version == release.tag_name && current.channel == channel {
+                return Ok(None);
+            }
+        }
+    }
+
+    let asset = release
+        .assets
+        .iter()
+        .find(|a| a.name == asset_name)
+        .ok_or_else(|| format!("Release {} has no {}", release.tag_name, asset_name))?;
+    let sums = release
+        .assets
+        .iter()
+        .find(|a| a.name == CHECKSUMS_ASSET)
+        .ok_or_else(|| format!("Release {} publishes no checksums; refusing to install", release.tag_name))?;
+    let listing = String::from_utf8_lossy(&download(&sums.browser_download_url).await?).to_string();
+    let expected = checksum_for(&listing, asset_name)
+        .ok_or_else(|| format!("No checksum for {} in release {}", asset_name, release.tag_name))?;
+
+    tracing::info!("[tools] Downloading {} {} ({:?})", id, release.tag_name, channel);
+    let bytes = download(&asset.browser_download_url).await?;
+    let actual = format!("{:x}", Sha256::digest(&bytes));
+    if actual != expected {
+        return Err(format!("Checksum mismatch for {} {}: download discarded", id, release.tag_name));
+    }
+
+    // Write beside the target and swap, keeping the old binary until the
+    // new one is in place
+    let tmp = dir.join(format!("{}.download", spec.binary_name()));
+    std::fs::write(&tmp, &bytes).map_err(|e| format!("Failed to write {}: {}", id, e))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&tmp, std::fs::Permissions::from_mode(0o755))
+            .map_err(|e| format!("Failed to mark {} executable: {}", id, e))?;
+    }
+    let previous = dir.join(format!("{}.previous", spec.binary_name()));
+    if target.is_file() {
+        let _ = std::fs::remove_file(&previous);
+        std::fs::rename(&target, &previous).map_err(|e| format!("Failed to replace {}: {}", id, e))?;
+    }
+    if let Err(e) = std::fs::rename(&tmp, &target) {
+        let _ = std::fs::rename(&previous, &target);
+        return Err(format!("Failed to install {}: {}", id, e));
+    }
+
+    manifest.tools.insert(
+        id.to_string(),
+        InstalledTool {
+            version: release.tag_name.clone(),
+            channel,
+            sha256: actual,
+            installed_at: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_millis() as i64)
+                .unwrap_or(0),
+        },
+    );
+    write_manifest(&dir, &manifest)?;
+    tracing::info!("[tools] Installed {} {}", id, release.tag_name);
+    Ok(Some(release.tag_name))
+}
+
+/// Scheduled task: update every unpinned managed tool.
+pub fn auto_update(app: &AppHandle) -> Result<String, String> {
+    let mut updated = Vec::new();
+    let mut errors = Vec::new();
+    for spec in TOOLS.iter().filter(|t| t.updatable()) {
+        if pin_of(app, spec.id).is_some() {
+            continue;
+        }
+        match tauri::async_runtime::block_on(update_tool_inner(app, spec.id, false)) {
+            Ok(Some(version)) => updated.push(format!("{} {}", spec.id, version)),
+            Ok(None) => {}
+            Err(e) => errors.push(format!("{}: {}", spec.id, e)),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors.join("; "));
+    }
+    Ok(if updated.is_empty() { "All tools up to date".to_string() } else { format!("Updated {}", updated.join(", ")) })
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+/// Installed versions of all known tools, with the last update check.
+#[tauri::command]
+pub async fn get_tool_versions(app: AppHandle) -> Result<Vec<ToolVersion>, String> {
+    tauri::async_runtime::spawn_blocking(move || TOOLS.iter().map(|spec| tool_version(&app, spec)).collect())
+        .await
+        .map_err(|e| format!("Tool probe failed: {}", e))
+}
+
+/// Ask upstream for the newest release of each updatable tool.
+#[tauri::command]
+pub async fn check_tool_updates(app: AppHandle, webview: tauri::Webview) -> Result<Vec<ToolVersion>, String> {
+    require_webview(&webview, Capability::ChangeSettings)?;
+    for spec in TOOLS.iter().filter(|t| t.updatable()) {
+        let Some(repo) = spec.repo(channel_of(&app, spec.id)) else { continue };
+        match fetch_release(repo, None).await {
+            Ok(release) => {
+                if let Ok(mut latest) = app.state::<ToolsState>().latest.lock() {
+                    latest.insert(spec.id, release.tag_name);
+                }
+            }
+            Err(e) => tracing::warn!("[tools] {}: {}", spec.id, e),
+        }
+    }
+    get_tool_versions(app).await
+}
+
+/// Download, verify and install `id` (pinned version or latest).
+/// `force` reinstalls even when the version is unchanged.
+#[tauri::command]
+pub async fn update_tool(app: AppHandle, webview: tauri::Webview, id: String, force: Option<bool>) -> Result<ToolVersion, String> {
+    require_webview(&webview, Capability::ChangeSettings)?;
+    update_tool_inner(&app, &id, force.unwrap_or(false)).await?;
+    let spec = spec(&id)?;
+    tauri::async_runtime::spawn_blocking(move || tool_version(&app, spec))
+        .await
+        .map_err(|e| format!("Tool probe failed: {}", e))
+}
+
+/// Choose the release channel and optionally pin a version (`None` or
+/// empty follows the channel's latest).
+#[tauri::command]
+pub fn set_tool_channel(
+    app: AppHandle,
+    webview: tauri::Webview,
+    id: String,
+    channel: Channel,
+    pin: Option<String>,
+) -> Result<(), String> {
+    require_webview(&webview, Capability::ChangeSettings)?;
+    spec(&id)?;
+    let db = app.state::<DbState>();
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let channel = match channel {
+        Channel::Stable => "stable",
+        Channel::Nightly => "nightly",
+    };
+    conn.execute(
+        "INSERT OR REPLACE INTO app_settings (key, value) VALUES (?1, ?2)",
+        (format!("tool_channel:{}", id), channel),
+    )
+    .map_err(|e| format!("Failed to save tool channel: {}", e))?;
+    conn.execute(
+        "INSERT OR REPLACE INTO app_settings (key, value) VALUES (?1, ?2)",
+        (format!("tool_pin:{}", id), pin.unwrap_or_default().trim()),
+    )
+    .map_err(|e| format!("Failed to save tool pin: {}", e))?;
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cmp::Ordering;
+
+    #[test]
+    fn versions_compare_numerically() {
+        assert_eq!(compare_versions("2024.08.06", "2024.10.22"), Ordering::Less);
+        assert_eq!(compare_versions("2024.08.06.232716", "2024.08.06"), Ordering::Greater);
+        assert_eq!(compare_versions("v6.1", "6.1"), Ordering::Equal);
+    }
+
+    #[test]
+    fn parses_versions_and_checksums() {
+        assert_eq!(parse_version_output("yt-dlp", "2024.10.22\n").as_deref(), Some("2024.10.22"));
+        assert_eq!(
+            parse_version_output("ffmpeg", "ffmpeg version 6.1.1-static Copyright (c) 2000-2023\n").as_deref(),
+            Some("6.1.1-static")
+        );
+        let hash = "a".repeat(64);
+        let listing = format!("{}  yt-dlp.exe\n{}  yt-dlp_linux\n", "b".repeat(64), hash);
+        assert_eq!(checksum_for(&listing, "yt-dlp_linux"), Some(hash));
+        assert_eq!(checksum_for(&listing, "yt-dlp_macos"), None);
+    }
+}
@@ -0,0 +1,588 @@
+//! Background transcoding of media the webview cannot play.
+//!
+//! Libraries collected over years hold `.avi`, `.flv` and `.wma` files,
+//! old codecs (MPEG-4 Part 2, WMA) in containers the webview does open, and
+//! audio at sample rates it refuses. `probe` asks ffmpeg what a file holds;
+//! anything outside what every webview plays — H.264/VP8/VP9/AV1 video,
+//! AAC/MP3/Opus/Vorbis/FLAC/PCM audio at 22.05–48 kHz, in mp4, webm, ogg,
+//! mp3, wav or flac files — is converted: videos to mp4 (H.264 + AAC,
+//! streams that already fit are copied), audio to Opus. Originals are never
+//! touched; the copies live in `<app cache dir>/transcoded/`, keyed by
+//! path, size and mtime, so an edited file is converted again, and the
+//! webview loads them through `cache://` (`cache_scheme`).
+//!
+//! Conversions run one at a time on a worker thread:
+//!   - at playback, `prepare_media` answers with the file to play (the
+//!     original, or its finished copy) or queues the conversion; it only
+//!     takes the audio and video files of library songs;
+//!   - after each import job, new songs' files in containers known not to
+//!     play are queued, unless the `transcode_on_import` setting is
+//!     `false`. Codec and sample-rate problems of other files are caught at
+//!     playback, so imports never probe every file.
+//!
+//! Progress comes from ffmpeg's `-progress` output and is published as
+//! `transcode://progress`; the end as `transcode://complete` or
+//! `transcode://failed`.
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Stdio};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+use super::cache_scheme;
+use super::ffmpeg::{hidden_command, locate_ffmpeg, stderr_tail};
+use crate::access::{require_webview, Capability};
+use crate::db::DbState;
+use crate::events::{publish, AppEvent};
+use crate::library::scanner::{fnv1a64, has_extension};
+use crate::paths::long_path;
+use crate::scheduler::read_setting;
+
+pub const PROGRESS_EVENT: &str = "transcode://progress";
+pub const COMPLETE_EVENT: &str = "transcode://complete";
+pub const FAILED_EVENT: &str = "transcode://failed";
+
+const CACHE_SUBDIR: &str = "transcoded";
+const ON_IMPORT_KEY: &str = "transcode_on_import";
+const PROBE_TIMEOUT: Duration = Duration::from_secs(15);
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Containers every webview plays (given playable codecs).
+const PLAYABLE_CONTAINERS: &[&str] = &["mp4", "m4v", "m4a", "webm", "ogg", "oga", "opus", "mp3", "wav", "flac", "aac"];
+/// Containers that never play; queued at import without probing.
+const UNPLAYABLE_CONTAINERS: &[&str] =
+    &["avi", "flv", "wmv", "wma", "mpg", "mpeg", "vob", "3gp", "mkv", "ape", "ra", "rm", "amr", "ac3", "aif", "aiff", "wv"];
+const PLAYABLE_VIDEO_CODECS: &[&str] = &["h264", "vp8", "vp9", "av1"];
+const PLAYABLE_AUDIO_CODECS: &[&str] = &["aac", "mp3", "opus", "vorbis", "flac", "pcm_s16le", "pcm_s24le", "pcm_f32le"];
+const MIN_SAMPLE_RATE: u32 = 22_050;
+const MAX_SAMPLE_RATE: u32 = 48_000;
+
+/// What a file is converted to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TargetKind {
+    /// mp4 with H.264 and AAC.
+    Video,
+    /// Opus.
+    Audio,
+}
+
+impl TargetKind {
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Video => "mp4",
+            Self::Audio => "opus",
+        }
+    }
+}
+
+/// Streams of a file, as ffmpeg reports them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MediaInfo {
+    pub duration_ms: Option<u64>,
+    pub video_codec: Option<String>,
+    pub audio_codec: Option<String>,
+    pub sample_rate: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TranscodeEvent {
+    pub source: String,
+    pub target: Option<String>,
+    /// `cache://` URL of `target`.
+    pub url: Option<String>,
+    /// 0–100, when the duration is known.
+    pub percent: Option<f64>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase", tag = "status")]
+pub enum MediaStatus {
+    /// Play `path`: the original, or its converted copy (`transcoded`),
+    /// which the webview loads from `url`.
+    Ready { path: String, url: Option<String>, transcoded: bool },
+    /// Being converted; `transcode://complete` follows.
+    Pending,
+}
+
+/// A queued or running conversion.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TranscodeJob {
+    pub source: String,
+    pub percent: Option<f64>,
+}
+
+/// Managed state: sender side of the transcode worker.
+pub struct TranscodeService {
+    tx: Mutex<mpsc::Sender<PathBuf>>,
+    /// Sources queued or converting, with their progress.
+    in_flight: Mutex<HashMap<PathBuf, Option<f64>>>,
+    /// The running ffmpeg, so shutdown can stop it.
+    current: Mutex<Option<Child>>,
+    cache_dir: PathBuf,
+}
+
+impl TranscodeService {
+    pub fn new(app: AppHandle) -> Result<Self, String> {
+        let cache_dir = app
+            .path()
+            .app_cache_dir()
+            .map_err(|e| format!("Failed to get cache dir: {}", e))?
+            .join(CACHE_SUBDIR);
+
+        let (tx, rx) = mpsc::channel::<PathBuf>();
+        thread::Builder::new()
+            .name("karaoke-transcode".into())
+            .spawn(move || {
+                for source in rx {
+                    run_job(&app, source);
+                }
+            })
+            .map_err(|e| format!("Failed to spawn transcode worker: {}", e))?;
+
+        Ok(Self {
+            tx: Mutex::new(tx),
+            in_flight: Mutex::new(HashMap::new()),
+            current: Mutex::new(None),
+            cache_dir,
+        })
+    }
+
+    /// Queue `source` unless it is already queued. Returns whether it was.
+    pub fn enqueue(&self, source: PathBuf) -> Result<bool, String> {
+        {
+            let mut in_flight = self.in_flight.lock().map_err(|e| e.to_string())?;
+            if in_flight.contains_key(&source) {
+                return Ok(false);
+            }
+            in_flight.insert(source.clone(), None);
+        }
+        self.tx.lock().map_err(|e| e.to_string())?.send(source).map_err(|e| e.to_string())?;
+        Ok(true)
+    }
+
+    /// Stop the running conversion (app exit).
+    pub fn shutdown(&self) {
+        if let Ok(mut current) = self.current.lock() {
+            if let Some(mut child) = current.take() {
+                let _ = child.kill();
+                let _ = child.wait();
+            }
+        }
+    }
+
+    fn set_progress(&self, source: &Path, percent: Option<f64>) {
+        if let Ok(mut in_flight) = self.in_flight.lock() {
+            if let Some(entry) = in_flight.get_mut(source) {
+                *entry = percent;
+            }
+        }
+    }
+
+    fn done(&self, source: &Path) {
+        if let Ok(mut in_flight) = self.in_flight.lock() {
+            in_flight.remove(source);
+        }
+    }
+
+    /// Finished copy of `source`, if there is one.
+    fn existing_copy(&self, source: &Path) -> Option<PathBuf> {
+        let key = cache_key(source).ok()?;
+        [TargetKind::Video, TargetKind::Audio]
+            .into_iter()
+            .map(|kind| self.cache_dir.join(format!("{}.{}", key, kind.extension())))
+            .find(|path| path.is_file())
+    }
+}
+
+/// Cache file stem for `source`.
+fn cache_key(source: &Path) -> Result<String, String> {
+    let meta = std::fs::metadata(long_path(source)).map_err(|e| format!("File not found: {}: {}", source.display(), e))?;
+    let mtime = meta
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let id = format!("{}|{}|{}", source.to_string_lossy(), meta.len(), mtime);
+    Ok(format!("{:016x}", fnv1a64(id.as_bytes())))
+}
+
+// ---------------------------------------------------------------------------
+// Probing
+// ---------------------------------------------------------------------------
+
+/// `HH:MM:SS.cc` as milliseconds.
+fn parse_timestamp(value: &str) -> Option<u64> {
+    let mut parts = value.trim().split(':');
+    let (h, m, s) = (parts.next()?, parts.next()?, parts.next()?);
+    let seconds = h.parse::<f64>().ok()? * 3600.0 + m.parse::<f64>().ok()? * 60.0 + s.parse::<f64>().ok()?;
+    (seconds.is_finite() && seconds >= 0.0).then(|| (seconds * 1000.0).round() as u64)
+}
+
+/// Streams from the banner `ffmpeg -i` prints. Cover art (`attached pic`)
+/// is not a video.
+pub fn parse_probe(output: &str) -> MediaInfo {
+    let mut info = MediaInfo::default();
+    for line in output.lines() {
+        let line = line.trim();
+        if let Some(rest) = line.strip_prefix("Duration:") {
+            info.duration_ms = info.duration_ms.or_else(|| parse_timestamp(rest.split(',').next().unwrap_or_default()));
+        } else if let Some((_, rest)) = line.split_once("Video: ") {
+            if info.video_codec.is_none() && !line.contains("(attached pic)") {
+                info.video_codec = rest.split([' ', ',']).next().map(str::to_string);
+            }
+        } else if let Some((_, rest)) = line.split_once("Audio: ") {
+            if info.audio_codec.is_none() {
+                info.audio_codec = rest.split([' ', ',']).next().map(str::to_string);
+                info.sample_rate = rest
+                    .split(',')
+                    .find_map(|field| field.trim().strip_suffix(" Hz"))
+                    .and_then(|rate| rate.trim().parse().ok());
+            }
+        }
+    }
+    info
+}
+
+/// What ffmpeg sees in `path`.
+pub fn probe(ffmpeg: &Path, path: &Path) -> Result<MediaInfo, String> {
+    // Without an output ffmpeg exits with an error after printing the streams
+    let mut child = hidden_command(ffmpeg)
+        .args(["-hide_banner", "-nostdin", "-i"])
+        .arg(path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to start ffmpeg: {}", e))?;
+    // Read while it runs: a banner larger than the pipe buffer would stall it
+    let reader = child.stderr.take().map(|mut stderr| {
+        thread::spawn(move || {
+            let mut output = String::new();
+            let _ = stderr.read_to_string(&mut output);
+            output
+        })
+    });
+    let started = Instant::now();
+    loop {
+        match child.try_wait() {
+            Ok(Some(_)) => break,
+            Ok(None) if started.elapsed() > PROBE_TIMEOUT => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("ffmpeg timed out reading {}", path.display()));
+            }
+            Ok(None) => thread::sleep(Duration::from_millis(20)),
+            Err(e) => return Err(format!("Failed to wait for ffmpeg: {}", e)),
+        }
+    }
+    let output = reader.and_then(|reader| reader.join().ok()).unwrap_or_default();
+    let info = parse_probe(&output);
+    if info.video_codec.is_none() && info.audio_codec.is_none() {
+        return Err(format!("No audio or video in {}", path.display()));
+    }
+    Ok(info)
+}
+
+fn playable_audio(info: &MediaInfo) -> bool {
+    let codec_ok = info.audio_codec.as_deref().is_none_or(|codec| PLAYABLE_AUDIO_CODECS.contains(&codec));
+    let rate_ok = info.sample_rate.is_none_or(|rate| (MIN_SAMPLE_RATE..=MAX_SAMPLE_RATE).contains(&rate));
+    codec_ok && rate_ok
+}
+
+/// What `path` needs to be converted to, if anything.
+pub fn plan(path: &Path, info: &MediaInfo) -> Option<TargetKind> {
+    let container_ok = has_extension(path, PLAYABLE_CONTAINERS);
+    let video_ok = info.video_codec.as_deref().is_none_or(|codec| PLAYABLE_VIDEO_CODECS.contains(&codec));
+    if container_ok && video_ok && playable_audio(info) {
+        return None;
+    }
+    Some(if info.video_codec.is_some() { TargetKind::Video } else { TargetKind::Audio })
+}
+
+/// ffmpeg arguments between input and output; streams that already fit
+/// are copied.
+fn codec_args(kind: TargetKind, info: &MediaInfo) -> Vec<String> {
+    let mut args: Vec<&str> = Vec::new();
+    match kind {
+        TargetKind::Video => {
+            args.extend(["-map", "0:v:0", "-map", "0:a:0?"]);
+            if info.video_codec.as_deref() == Some("h264") {
+                args.extend(["-c:v", "copy"]);
+            } else {
+                args.extend(["-c:v", "libx264", "-preset", "veryfast", "-crf", "20", "-pix_fmt", "yuv420p"]);
+            }
+            if info.audio_codec.as_deref() == Some("aac") && playable_audio(info) {
+                args.extend(["-c:a", "copy"]);
+            } else {
+                args.extend(["-c:a", "aac", "-b:a", "192k", "-ar", "48000"]);
+            }
+            args.extend(["-movflags", "+faststart"]);
+        }
+        TargetKind::Audio => args.extend(["-map", "0:a:0", "-vn", "-c:a", "libopus", "-b:a", "160k", "-ar", "48000"]),
+    }
+    args.into_iter().map(String::from).collect()
+}
+
+/// Microseconds from a `-progress` line (`out_time_us`, or the misnamed
+/// `out_time_ms`, which is microseconds too).
+fn progress_us(line: &str) -> Option<u64> {
+    let (key, value) = line.split_once('=')?;
+    matches!(key.trim(), "out_time_us" | "out_time_ms").then(|| value.trim().parse().ok()).flatten()
+}
+
+// ---------------------------------------------------------------------------
+// Worker
+// ---------------------------------------------------------------------------
+
+fn run_job(app: &AppHandle, source: PathBuf) {
+    let Some(service) = app.try_state::<TranscodeService>() else { return };
+    let result = convert(app, &service, &source);
+    service.done(&source);
+
+    let source_path = source.to_string_lossy().to_string();
+    match result {
+        Ok(Some(target)) => {
+            tracing::info!("[transcode] Converted {}", source.display());
+            publish(
+                app,
+                AppEvent::TranscodeComplete(TranscodeEvent {
+                    source: source_path,
+                    url: cache_scheme::url_for(app, &target),
+                    target: Some(target.to_string_lossy().to_string()),
+                    percent: Some(100.0),
+                    error: None,
+                }),
+            );
+        }
+        // Plays as it is
+        Ok(None) => {}
+        Err(e) => {
+            tracing::warn!("[transcode] {}: {}", source.display(), e);
+            publish(
+                app,
+                AppEvent::TranscodeFailed(TranscodeEvent {
+                    source: source_path,
+                    target: None,
+                    url: None,
+                    percent: None,
+                    error: Some(e),
+                }),
+            );
+        }
+    }
+}
+
+/// Convert `source` if it needs it; returns the copy.
+fn convert(app: &AppHandle, service: &TranscodeService, source: &Path) -> Result<Option<PathBuf>, String> {
+    if let Some(copy) = service.existing_copy(source) {
+        return Ok(Some(copy));
+    }
+    let ffmpeg = locate_ffmpeg(app).ok_or("ffmpeg not found")?;
+    let info = probe(&ffmpeg, source)?;
+    let Some(kind) = plan(source, &info) else { return Ok(None) };
+
+    std::fs::create_dir_all(&service.cache_dir).map_err(|e| format!("Failed to create transcode cache: {}", e))?;
+    let key = cache_key(source)?;
+    let target = service.cache_dir.join(format!("{}.{}", key, kind.extension()));
+    // ffmpeg picks the format from the extension, so it stays last
+    let tmp = service.cache_dir.join(format!("{}.tmp.{}", key, kind.extension()));
+
+    let mut child = hidden_command(&ffmpeg)
+        .args(["-hide_banner", "-loglevel", "error", "-nostdin", "-y", "-i"])
+        .arg(long_path(source))
+        .args(codec_args(kind, &info))
+        .args(["-progress", "pipe:1", "-nostats"])
+        .arg(&tmp)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to start ffmpeg: {}", e))?;
+    let stdout = child.stdout.take().ok_or("No ffmpeg stdout")?;
+    let stderr = child.stderr.take().map(|stderr| thread::spawn(move || stderr_tail(stderr)));
+    *service.current.lock().map_err(|e| e.to_string())? = Some(child);
+
+    let mut reported = Instant::now();
+    for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+        let (Some(us), Some(duration)) = (progress_us(&line), info.duration_ms.filter(|&d| d > 0)) else { continue };
+        if reported.elapsed() < PROGRESS_INTERVAL {
+            continue;
+        }
+        reported = Instant::now();
+        let percent = ((us as f64 / 1000.0) / duration as f64 * 100.0).clamp(0.0, 100.0);
+        service.set_progress(source, Some(percent));
+        publish(
+            app,
+            AppEvent::TranscodeProgress(TranscodeEvent {
+                source: source.to_string_lossy().to_string(),
+                target: None,
+                url: None,
+                percent: Some(percent),
+                error: None,
+            }),
+        );
+    }
+
+    // Taken by `shutdown` if the app is quitting
+    let child = service.current.lock().map_err(|e| e.to_string())?.take();
+    let Some(mut child) = child else {
+        let _ = std::fs::remove_file(&tmp);
+        return Err("Stopped".to_string());
+    };
+    let status = child.wait().map_err(|e| format!("Failed to wait for ffmpeg: {}", e))?;
+    let error = stderr.and_then(|reader| reader.join().ok()).unwrap_or_default();
+    if !status.success() {
+        let _ = std::fs::remove_file(&tmp);
+        let reason = error.trim().lines().last().unwrap_or_default().to_string();
+        return Err(format!("ffmpeg failed ({}): {}", status, reason));
+    }
+    std::fs::rename(&tmp, &target).map_err(|e| format!("Failed to store converted file: {}", e))?;
+    Ok(Some(target))
+}
+
+/// Queue the files of songs saved since `since` (epoch ms) whose container
+/// never plays. Called when an import job ends.
+pub fn queue_new_songs(app: &AppHandle, since: i64) {
+    let Some(service) = app.try_state::<TranscodeService>() else { return };
+    let files: Vec<PathBuf> = {
+        let Some(db) = app.try_state::<DbState>() else { return };
+        let Ok(conn) = db.conn.lock() else { return };
+        if read_setting(&conn, ON_IMPORT_KEY).is_some_and(|v| v.trim() == "false") {
+            return;
+        }
+        let query = conn.prepare(
+            "SELECT folder_path, audio_file_name, video_file_name FROM songs
+             WHERE date_added >= ?1 AND (audio_file_name IS NOT NULL OR video_file_name IS NOT NULL)",
+        );
+        let Ok(mut stmt) = query else { return };
+        let rows = stmt.query_map([since], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?, row.get::<_, Option<String>>(2)?))
+        });
+        let Ok(rows) = rows else { return };
+        rows.flatten()
+            .flat_map(|(folder, audio, video)| {
+                [audio, video].into_iter().flatten().map(move |name| Path::new(&folder).join(name))
+            })
+            .filter(|path| has_extension(path, UNPLAYABLE_CONTAINERS) && long_path(path).is_file())
+            .collect()
+    };
+    let mut queued = 0;
+    for file in files {
+        if service.existing_copy(&file).is_none() && service.enqueue(file).unwrap_or(false) {
+            queued += 1;
+        }
+    }
+    if queued > 0 {
+        tracing::info!("[transcode] Queued {} imported file(s) for conversion", queued);
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+/// The file to play for the library file `path`: the original when the
+/// webview plays it, else its converted copy, else `Pending` with the
+/// conversion queued. Without ffmpeg the original is returned as is.
+#[tauri::command]
+pub async fn prepare_media(app: AppHandle, webview: tauri::Webview, path: String) -> Result<MediaStatus, String> {
+    require_webview(&webview, Capability::ControlPlayback)?;
+    let source = PathBuf::from(&path);
+    if !long_path(&source).is_file() {
+        return Err(format!("File not found: {}", path));
+    }
+    {
+        let db = app.state::<DbState>();
+        let conn = db.conn.lock().map_err(|e| e.to_string())?;
+        if crate::deep_link::song_for_file(&conn, &path)?.is_none() {
+            return Err(format!("'{}' is not part of a library song", path));
+        }
+    }
+    let service = app.state::<TranscodeService>();
+    if let Some(copy) = service.existing_copy(&source) {
+        let url = cache_scheme::url_for(&app, &copy);
+        return Ok(MediaStatus::Ready { path: copy.to_string_lossy().to_string(), url, transcoded: true });
+    }
+    if service.in_flight.lock().map_err(|e| e.to_string())?.contains_key(&source) {
+        return Ok(MediaStatus::Pending);
+    }
+    let Some(ffmpeg) = locate_ffmpeg(&app) else {
+        return Ok(MediaStatus::Ready { path, url: None, transcoded: false });
+    };
+    let probe_source = source.clone();
+    let info = tauri::async_runtime::spawn_blocking(move || probe(&ffmpeg, &probe_source))
+        .await
+        .map_err(|e| e.to_string())??;
+    if plan(&source, &info).is_none() {
+        return Ok(MediaStatus::Ready { path, url: None, transcoded: false });
+    }
+    service.enqueue(source)?;
+    Ok(MediaStatus::Pending)
+}
+
+/// Conversions queued or running.
+#[tauri::command]
+pub fn get_transcode_jobs(app: AppHandle) -> Result<Vec<TranscodeJob>, String> {
+    let service = app.state::<TranscodeService>();
+    let in_flight = service.in_flight.lock().map_err(|e| e.to_string())?;
+    Ok(in_flight
+        .iter()
+        .map(|(source, percent)| TranscodeJob { source: source.to_string_lossy().to_string(), percent: *percent })
+        .collect())
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AVI_BANNER: &str = "Input #0, avi, from 'song.avi':
+  Duration: 00:03:25.43, start: 0.000000, bitrate: 1411 kb/s
+  Stream #0:0: Video: mpeg4 (Simple Profile) (XVID / 0x44495658), yuv420p, 640x480, 25 fps
+  Stream #0:1: Audio: mp3 (U[0][0][0] / 0x0055), 44100 Hz, stereo, fltp, 128 kb/s
+At least one output file must be specified";
+
+    const MP3_BANNER: &str = "Input #0, mp3, from 'song.mp3':
+  Duration: 00:04:00.00, start: 0.025057, bitrate: 320 kb/s
+  Stream #0:0: Audio: mp3, 8000 Hz, mono, fltp, 32 kb/s
+  Stream #0:1: Video: mjpeg (Baseline), yuvj420p, 500x500, 90k tbr (attached pic)";
+
+    #[test]
+    fn parses_ffmpeg_banners() {
+        let avi = parse_probe(AVI_BANNER);
+        assert_eq!(avi.duration_ms, Some(205_430));
+        assert_eq!((avi.video_codec.as_deref(), avi.audio_codec.as_deref(), avi.sample_rate), (Some("mpeg4"), Some("mp3"), Some(44_100)));
+
+        let mp3 = parse_probe(MP3_BANNER);
+        assert_eq!((mp3.video_codec, mp3.sample_rate), (None, Some(8000)));
+    }
+
+    #[test]
+    fn plans_only_what_does_not_play() {
+        let avi = parse_probe(AVI_BANNER);
+        assert_eq!(plan(Path::new("song.avi"), &avi), Some(TargetKind::Video));
+        // Low sample rate in a playable container
+        assert_eq!(plan(Path::new("song.mp3"), &parse_probe(MP3_BANNER)), Some(TargetKind::Audio));
+
+        let fine = MediaInfo { audio_codec: Some("aac".into()), video_codec: Some("h264".into()), sample_rate: Some(48_000), duration_ms: None };
+        assert_eq!(plan(Path::new("song.mp4"), &fine), None);
+        // Right codecs, wrong container: the video is copied, not re-encoded
+        assert_eq!(plan(Path::new("song.mkv"), &fine), Some(TargetKind::Video));
+        assert!(codec_args(TargetKind::Video, &fine).windows(2).any(|w| w == ["-c:v", "copy"]));
+        assert_eq!(progress_us("out_time_us=1500000"), Some(1_500_000));
+    }
+}
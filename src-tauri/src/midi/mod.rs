@@ -0,0 +1,375 @@
+//! KAR and MIDI karaoke files.
+//!
+//! A `.kar` is a standard MIDI file whose lyrics sit in text events of
+//! their own track, one syllable per event: `/` starts a new line, `\` a
+//! new verse, and `@`-prefixed events carry metadata (`@T` title, then
+//! artist; `@L` language). Plain `.mid` karaoke files use lyric meta events
+//! instead, with line breaks as `\r`/`\n`. Both become `lyrics::Lyrics`
+//! with one word per syllable, so `lyrics_follow` drives them like LRC.
+//!
+//! The melody is the note track the syllables line up with best (drums on
+//! channel 10 excluded), extracted for pitch scoring. Audio comes from
+//! rendering the file with a SoundFont (`synth`, feature `midi-synth`).
+
+#[cfg(feature = "midi-synth")]
+pub mod synth;
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use midly::{MetaMessage, MidiMessage, Smf, Timing, TrackEventKind};
+use serde::Serialize;
+
+use crate::access::{require_webview, Capability};
+use crate::library::roots::require_in_library;
+use crate::library::ultrastar::decode_text;
+use crate::lyrics::{Lyrics, LyricsLine, Word};
+use crate::paths::long_path;
+
+/// MIDI default tempo, 120 BPM.
+const DEFAULT_TEMPO_US: u32 = 500_000;
+const DRUM_CHANNEL: u8 = 9;
+/// A syllable counts as sung on a note starting this close to it.
+const ALIGN_TOLERANCE_MS: u64 = 60;
+/// How long the last line of a file stays up.
+const LAST_LINE_MS: u64 = 3_000;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MelodyNote {
+    pub start_ms: u64,
+    pub end_ms: u64,
+    /// MIDI key, 60 = middle C.
+    pub key: u8,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct KarSong {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub language: Option<String>,
+    pub duration_ms: u64,
+    pub lyrics: Lyrics,
+    pub melody: Vec<MelodyNote>,
+}
+
+/// Tick to millisecond conversion over the file's tempo changes.
+struct TempoMap {
+    ticks_per_beat: f64,
+    /// (tick, ms at that tick, µs per beat from there).
+    segments: Vec<(u64, f64, u32)>,
+    /// SMPTE files: fixed ms per tick.
+    fixed_ms_per_tick: Option<f64>,
+}
+
+impl TempoMap {
+    fn new(smf: &Smf) -> Self {
+        let (ticks_per_beat, fixed_ms_per_tick) = match smf.header.timing {
+            Timing::Metrical(tpb) => (tpb.as_int().max(1) as f64, None),
+            Timing::Timecode(fps, sub) => (1.0, Some(1000.0 / (fps.as_f32() as f64 * sub.max(1) as f64))),
+        };
+        let mut changes: Vec<(u64, u32)> = Vec::new();
+        for track in &smf.tracks {
+            let mut tick = 0u64;
+            for event in track {
+                tick += event.delta.as_int() as u64;
+                if let TrackEventKind::Meta(MetaMessage::Tempo(us)) = event.kind {
+                    changes.push((tick, us.as_int()));
+                }
+            }
+        }
+        changes.sort_by_key(|(tick, _)| *tick);
+        let mut segments = vec![(0, 0.0, DEFAULT_TEMPO_US)];
+        for (tick, us) in changes {
+            let &(last_tick, last_ms, last_us) = segments.last().expect("starts with a segment");
+            let ms = last_ms + (tick - last_tick) as f64 * last_us as f64 / 1000.0 / ticks_per_beat;
+            if tick == last_tick {
+                segments.pop();
+            }
+            segments.push((tick, ms, us));
+        }
+        Self { ticks_per_beat, segments, fixed_ms_per_tick }
+    }
+
+    fn ms(&self, tick: u64) -> u64 {
+        if let Some(ms_per_tick) = self.fixed_ms_per_tick {
+            return (tick as f64 * ms_per_tick) as u64;
+        }
+        let i = self.segments.partition_point(|(t, ..)| *t <= tick).saturating_sub(1);
+        let (start, ms, us) = self.segments[i];
+        (ms + (tick - start) as f64 * us as f64 / 1000.0 / self.ticks_per_beat) as u64
+    }
+}
+
+struct Syllable {
+    ms: u64,
+    text: String,
+    /// Starts a new line (`/`, `\`, or a break after the previous one).
+    new_line: bool,
+}
+
+/// Lyrics events of the best lyrics track: lyric meta events if any track
+/// has them, otherwise KAR text events.
+fn collect_syllables(smf: &Smf, tempo: &TempoMap, meta: &mut HashMap<char, Vec<String>>) -> Vec<Syllable> {
+    let mut by_track: Vec<(bool, Vec<(u64, String)>)> = Vec::new();
+    for track in &smf.tracks {
+        let (mut tick, mut lyric, mut text) = (0u64, Vec::new(), Vec::new());
+        for event in track {
+            tick += event.delta.as_int() as u64;
+            match event.kind {
+                TrackEventKind::Meta(MetaMessage::Lyric(bytes)) => lyric.push((tick, decode_text(bytes))),
+                TrackEventKind::Meta(MetaMessage::Text(bytes)) => {
+                    let value = decode_text(bytes);
+                    if let Some(rest) = value.strip_prefix('@') {
+                        let mut chars = rest.chars();
+                        if let Some(kind) = chars.next() {
+                            meta.entry(kind.to_ascii_uppercase()).or_default().push(chars.as_str().trim().to_string());
+                        }
+                    } else {
+                        text.push((tick, value));
+                    }
+                }
+                _ => {}
+            }
+        }
+        by_track.push((true, lyric));
+        by_track.push((false, text));
+    }
+    // Prefer lyric events; among equals, the track with the most syllables
+    let best = by_track.into_iter().max_by_key(|(is_lyric, events)| (!events.is_empty() && *is_lyric, events.len()));
+    let Some((_, events)) = best else { return Vec::new() };
+
+    let mut syllables = Vec::with_capacity(events.len());
+    let mut break_pending = true;
+    for (tick, raw) in events {
+        let mut text = raw.as_str();
+        let mut new_line = break_pending;
+        if let Some(rest) = text.strip_prefix(['/', '\\']) {
+            new_line = true;
+            text = rest;
+        }
+        break_pending = text.ends_with(['\r', '\n']);
+        let text = text.trim_end_matches(['\r', '\n']);
+        if text.is_empty() {
+            // A bare break still ends the line
+            break_pending |= new_line;
+            continue;
+        }
+        syllables.push(Syllable { ms: tempo.ms(tick), text: text.to_string(), new_line });
+    }
+    syllables
+}
+
+fn build_lyrics(syllables: &[Syllable], title: Option<String>, artist: Option<String>) -> Lyrics {
+    let mut lines: Vec<LyricsLine> = Vec::new();
+    for (i, syllable) in syllables.iter().enumerate() {
+        if syllable.new_line || lines.is_empty() {
+            lines.push(LyricsLine { start_ms: syllable.ms, end_ms: 0, text: String::new(), words: Vec::new() });
+        }
+        let end_ms = syllables.get(i + 1).map_or(syllable.ms + 500, |next| next.ms);
+        let line = lines.last_mut().expect("pushed above");
+        line.text.push_str(&syllable.text);
+        line.words.push(Word { start_ms: syllable.ms, end_ms: end_ms.max(syllable.ms), text: syllable.text.clone() });
+    }
+    let starts: Vec<u64> = lines.iter().map(|l| l.start_ms).collect();
+    for (i, line) in lines.iter_mut().enumerate() {
+        line.text = line.text.trim().to_string();
+        line.end_ms = starts.get(i + 1).copied().unwrap_or(line.start_ms + LAST_LINE_MS);
+        // The last syllable of a line is sung until the next line at most
+        if let Some(last) = line.words.last_mut() {
+            last.end_ms = last.end_ms.min(line.end_ms);
+        }
+    }
+    Lyrics {
+        title,
+        artist,
+        album: None,
+        offset_ms: 0,
+        enhanced: !lines.is_empty(),
+        lines,
+    }
+}
+
+/// Notes per (track, channel), drums excluded.
+fn collect_notes(smf: &Smf, tempo: &TempoMap) -> HashMap<(usize, u8), Vec<MelodyNote>> {
+    let mut notes: HashMap<(usize, u8), Vec<MelodyNote>> = HashMap::new();
+    for (track_index, track) in smf.tracks.iter().enumerate() {
+        let mut tick = 0u64;
+        let mut open: HashMap<(u8, u8), u64> = HashMap::new();
+        for event in track {
+            tick += event.delta.as_int() as u64;
+            let TrackEventKind::Midi { channel, message } = event.kind else { continue };
+            let channel = channel.as_int();
+            if channel == DRUM_CHANNEL {
+                continue;
+            }
+            let (key, on) = match message {
+                MidiMessage::NoteOn { key, vel } => (key.as_int(), vel.as_int() > 0),
+                MidiMessage::NoteOff { key, .. } => (key.as_int(), false),
+                _ => continue,
+            };
+            if on {
+                open.insert((channel, key), tick);
+            } else if let Some(start) = open.remove(&(channel, key)) {
+                notes.entry((track_index, channel)).or_default().push(MelodyNote {
+                    start_ms: tempo.ms(start),
+                    end_ms: tempo.ms(tick),
+                    key,
+                });
+            }
+        }
+    }
+    for list in notes.values_mut() {
+        list.sort_by_key(|n| (n.start_ms, n.key));
+    }
+    notes
+}
+
+/// The voice whose note starts match the most syllables, reduced to one
+/// note at a time (the highest, which carries the tune in a chord).
+fn pick_melody(candidates: HashMap<(usize, u8), Vec<MelodyNote>>, syllables: &[Syllable]) -> Vec<MelodyNote> {
+    let aligned = |notes: &[MelodyNote]| {
+        syllables
+            .iter()
+            .filter(|s| {
+                let i = notes.partition_point(|n| n.start_ms + ALIGN_TOLERANCE_MS < s.ms);
+                notes.get(i).is_some_and(|n| n.start_ms <= s.ms + ALIGN_TOLERANCE_MS)
+            })
+            .count()
+    };
+    let Some(notes) = candidates
+        .into_iter()
+        .max_by_key(|(voice, notes)| (aligned(notes), std::cmp::Reverse(*voice)))
+        .map(|(_, notes)| notes)
+    else {
+        return Vec::new();
+    };
+    let mut melody: Vec<MelodyNote> = Vec::with_capacity(notes.len());
+    for note in notes {
+        match melody.last_mut() {
+            Some(last) if last.start_ms == note.start_ms => {
+                if note.key > last.key {
+                    *last = note;
+                }
+            }
+            Some(last) if last.end_ms > note.start_ms => {
+                last.end_ms = note.start_ms;
+                melody.push(note);
+            }
+            _ => melody.push(note),
+        }
+    }
+    melody.retain(|n| n.end_ms > n.start_ms);
+    melody
+}
+
+pub fn parse(bytes: &[u8]) -> Result<KarSong, String> {
+    let smf = Smf::parse(bytes).map_err(|e| format!("Not a MIDI file: {}", e))?;
+    let tempo = TempoMap::new(&smf);
+    let mut meta = HashMap::new();
+    let syllables = collect_syllables(&smf, &tempo, &mut meta);
+    // KAR: the first @T is the title, the second the artist
+    let titles = meta.remove(&'T').unwrap_or_default();
+    let title = titles.first().filter(|t| !t.is_empty()).cloned();
+    let artist = titles.get(1).filter(|t| !t.is_empty()).cloned();
+    let language = meta.remove(&'L').and_then(|l| l.into_iter().find(|l| !l.is_empty()));
+
+    let notes = collect_notes(&smf, &tempo);
+    let duration_ms = notes.values().flatten().map(|n| n.end_ms).max().unwrap_or(0);
+    let melody = pick_melody(notes, &syllables);
+    Ok(KarSong {
+        lyrics: build_lyrics(&syllables, title.clone(), artist.clone()),
+        title,
+        artist,
+        language,
+        duration_ms,
+        melody,
+    })
+}
+
+pub fn read_file(path: &Path) -> Result<KarSong, String> {
+    let bytes = std::fs::read(long_path(path)).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    parse(&bytes).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+/// Lyrics, melody and metadata of a `.kar` / `.mid` file.
+#[tauri::command]
+pub async fn load_kar(app: tauri::AppHandle, webview: tauri::Webview, path: String) -> Result<KarSong, String> {
+    require_webview(&webview, Capability::ViewLibrary)?;
+    require_in_library(&app, &path)?;
+    tauri::async_runtime::spawn_blocking(move || read_file(Path::new(&path)))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Render a `.kar` / `.mid` file to WAV (cached) and return its path for
+/// `audio_play_file`. `soundfont` overrides the `midi_soundfont_path` setting.
+#[tauri::command]
+pub async fn render_midi(app: tauri::AppHandle, webview: tauri::Webview, path: String, soundfont: Option<String>) -> Result<String, String> {
+    require_webview(&webview, Capability::ControlPlayback)?;
+    #[cfg(feature = "midi-synth")]
+    {
+        synth::render_cached(app, path, soundfont).await
+    }
+    #[cfg(not(feature = "midi-synth"))]
+    {
+        let _ = (app, path, soundfont);
+        Err("This build has no MIDI synthesizer".to_string())
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use midly::num::{u15, u28, u4, u7};
+    use midly::{Format, Header, TrackEvent};
+
+    fn event(delta: u32, kind: TrackEventKind<'static>) -> TrackEvent<'static> {
+        TrackEvent { delta: u28::new(delta), kind }
+    }
+
+    fn note(delta: u32, channel: u8, key: u8, on: bool) -> TrackEvent<'static> {
+        let (key, vel) = (u7::new(key), u7::new(if on { 100 } else { 0 }));
+        let message = if on { MidiMessage::NoteOn { key, vel } } else { MidiMessage::NoteOff { key, vel } };
+        event(delta, TrackEventKind::Midi { channel: u4::new(channel), message })
+    }
+
+    #[test]
+    fn reads_kar_lyrics_and_the_aligned_melody() {
+        let text = |s: &'static str| TrackEventKind::Meta(MetaMessage::Text(s.as_bytes()));
+        let words = vec![
+            event(0, text("@TSong")),
+            event(0, text("@TArtist")),
+            event(0, text("/Hel")),
+            event(480, text("lo ")),
+            event(480, text("\\World")),
+            event(0, TrackEventKind::Meta(MetaMessage::EndOfTrack)),
+        ];
+        // 480 ticks = one beat = 500 ms at the default tempo
+        let melody = vec![note(0, 0, 60, true), note(480, 0, 60, false), note(0, 0, 62, true), note(480, 0, 62, false), note(0, 0, 64, true), note(480, 0, 64, false)];
+        let bass = vec![note(240, 1, 36, true), note(960, 1, 36, false)];
+        let smf = Smf {
+            header: Header { format: Format::Parallel, timing: Timing::Metrical(u15::new(480)) },
+            tracks: vec![words, melody, bass],
+        };
+        let mut bytes = Vec::new();
+        smf.write_std(&mut bytes).unwrap();
+
+        let song = parse(&bytes).unwrap();
+        assert_eq!(song.title.as_deref(), Some("Song"));
+        assert_eq!(song.artist.as_deref(), Some("Artist"));
+        let lines: Vec<(&str, u64)> = song.lyrics.lines.iter().map(|l| (l.text.as_str(), l.start_ms)).collect();
+        assert_eq!(lines, vec![("Hello", 0), ("World", 1000)]);
+        assert_eq!(song.lyrics.lines[0].words[1].start_ms, 500);
+        let keys: Vec<u8> = song.melody.iter().map(|n| n.key).collect();
+        assert_eq!(keys, vec![60, 62, 64]);
+        assert_eq!(song.duration_ms, 1500);
+    }
+}
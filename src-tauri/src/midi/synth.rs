@@ -0,0 +1,106 @@
+//! Rendering MIDI to audio with a SoundFont (rustysynth).
+//!
+//! The native player plays files, so a KAR is rendered once to a 16-bit
+//! stereo WAV in the app cache and played like any other song. The cache
+//! key covers the MIDI file and the SoundFont, so switching SoundFonts
+//! renders again. The SoundFont is the `midi_soundfont_path` setting
+//! unless the caller passes one; none ships with the app.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use rustysynth::{MidiFile, MidiFileSequencer, SoundFont, Synthesizer, SynthesizerSettings};
+use tauri::{AppHandle, Manager};
+
+use crate::db::DbState;
+use crate::library::scanner::fnv1a64;
+use crate::paths::long_path;
+use crate::scheduler::read_setting;
+
+pub const SOUNDFONT_KEY: &str = "midi_soundfont_path";
+const SAMPLE_RATE: i32 = 44_100;
+const CACHE_SUBDIR: &str = "midi";
+/// Reverb and release tails after the last event.
+const TAIL_SECONDS: f64 = 2.0;
+
+fn write_wav(path: &Path, left: &[f32], right: &[f32]) -> std::io::Result<()> {
+    let data_len = (left.len() * 4) as u32;
+    let mut out = BufWriter::new(File::create(path)?);
+    out.write_all(b"RIFF")?;
+    out.write_all(&(36 + data_len).to_le_bytes())?;
+    out.write_all(b"WAVEfmt ")?;
+    out.write_all(&16u32.to_le_bytes())?;
+    out.write_all(&1u16.to_le_bytes())?; // PCM
+    out.write_all(&2u16.to_le_bytes())?;
+    out.write_all(&(SAMPLE_RATE as u32).to_le_bytes())?;
+    out.write_all(&(SAMPLE_RATE as u32 * 4).to_le_bytes())?;
+    out.write_all(&4u16.to_le_bytes())?;
+    out.write_all(&16u16.to_le_bytes())?;
+    out.write_all(b"data")?;
+    out.write_all(&data_len.to_le_bytes())?;
+    for (l, r) in left.iter().zip(right) {
+        for sample in [l, r] {
+            out.write_all(&((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).to_le_bytes())?;
+        }
+    }
+    out.flush()
+}
+
+/// Render `midi` with `soundfont` into `target`.
+pub fn render(midi: &Path, soundfont: &Path, target: &Path) -> Result<(), String> {
+    let mut sf2 = File::open(long_path(soundfont)).map_err(|e| format!("Failed to open SoundFont {}: {}", soundfont.display(), e))?;
+    let sound_font = Arc::new(SoundFont::new(&mut sf2).map_err(|e| format!("Invalid SoundFont: {:?}", e))?);
+    let mut file = File::open(long_path(midi)).map_err(|e| format!("Failed to open {}: {}", midi.display(), e))?;
+    let midi_file = Arc::new(MidiFile::new(&mut file).map_err(|e| format!("Invalid MIDI file: {:?}", e))?);
+
+    let settings = SynthesizerSettings::new(SAMPLE_RATE);
+    let synthesizer = Synthesizer::new(&sound_font, &settings).map_err(|e| format!("Synthesizer failed: {:?}", e))?;
+    let mut sequencer = MidiFileSequencer::new(synthesizer);
+    sequencer.play(&midi_file, false);
+    let frames = ((midi_file.get_length() + TAIL_SECONDS) * SAMPLE_RATE as f64) as usize;
+    let mut left = vec![0.0f32; frames];
+    let mut right = vec![0.0f32; frames];
+    sequencer.render(&mut left, &mut right);
+
+    let tmp = target.with_extension("part");
+    write_wav(&tmp, &left, &right).map_err(|e| format!("Failed to write {}: {}", tmp.display(), e))?;
+    std::fs::rename(&tmp, target).map_err(|e| format!("Failed to store rendering: {}", e))
+}
+
+fn cache_path(app: &AppHandle, midi: &Path, soundfont: &Path) -> Result<PathBuf, String> {
+    let modified = |p: &Path| {
+        std::fs::metadata(long_path(p))
+            .and_then(|m| m.modified())
+            .map(|t| format!("{:?}", t))
+            .unwrap_or_default()
+    };
+    let key = format!("{}|{}|{}|{}", midi.display(), modified(midi), soundfont.display(), modified(soundfont));
+    let dir = app.path().app_cache_dir().map_err(|e| format!("No cache directory: {}", e))?.join(CACHE_SUBDIR);
+    std::fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    Ok(dir.join(format!("{:016x}.wav", fnv1a64(key.as_bytes()))))
+}
+
+/// Render `path` to WAV unless cached and return the WAV's path.
+pub async fn render_cached(app: AppHandle, path: String, soundfont: Option<String>) -> Result<String, String> {
+    let soundfont = match soundfont.filter(|s| !s.trim().is_empty()) {
+        Some(soundfont) => soundfont,
+        None => {
+            let db = app.state::<DbState>();
+            let conn = db.conn.lock().map_err(|e| e.to_string())?;
+            read_setting(&conn, SOUNDFONT_KEY).ok_or("No SoundFont configured for MIDI playback")?
+        }
+    };
+    tauri::async_runtime::spawn_blocking(move || {
+        let (midi, soundfont) = (PathBuf::from(&path), PathBuf::from(&soundfont));
+        let target = cache_path(&app, &midi, &soundfont)?;
+        if !target.is_file() {
+            render(&midi, &soundfont, &target)?;
+            tracing::info!("[midi] Rendered {} to {}", midi.display(), target.display());
+        }
+        Ok(target.to_string_lossy().to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
@@ -0,0 +1,388 @@
+//! MIDI control surfaces, so the KJ can run the show from pads and faders.
+//!
+//! The input port and the mappings live in `[midi]` of `config.toml`. A
+//! control is written `note:<channel>:<note>` for a pad or key and
+//! `cc:<channel>:<controller>` for a knob, fader or button sending control
+//! changes, channels counted 1–16. Triggers (next singer, pause/resume, key
+//! up/down, filler) fire on a press — a note-on or a non-zero CC — while the
+//! volumes follow the control's value: music from silent to unchanged, the
+//! microphone up to `audio::mic::MAX_GAIN`.
+//!
+//! Every mapped action is published as `midi://action`; next singer and the
+//! filler music belong to the frontend, the rest is applied here as well.
+//! Input no mapping covers is published as `midi://input`, so the settings
+//! page can learn a control by having the KJ move it.
+//!
+//! midir hands messages to a thread of its own, and a connection cannot
+//! move between threads on every backend, so a dedicated thread keeps it:
+//! it connects to the configured port when it shows up and lets go when it
+//! is unplugged or the config changes.
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use midir::{Ignore, MidiInput, MidiInputConnection};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+use crate::access::{require_webview, Capability};
+use crate::audio::commands::AudioState;
+use crate::audio::mic::{MicState, MAX_GAIN};
+use crate::config::{self, MidiConfig};
+use crate::events::{publish, AppEvent};
+
+pub const ACTION_EVENT: &str = "midi://action";
+pub const INPUT_EVENT: &str = "midi://input";
+
+const CLIENT_NAME: &str = "Karaoke Successor";
+/// How often the port list is checked for the configured device.
+const POLL: Duration = Duration::from_secs(2);
+
+/// A pad, key, knob or fader, as its messages address it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Control {
+    /// Note-on of `number` on `channel` (0-based).
+    Note { channel: u8, number: u8 },
+    /// Control change `number` on `channel` (0-based).
+    Cc { channel: u8, number: u8 },
+}
+
+impl FromStr for Control {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid MIDI control '{}' (expected note:<1-16>:<0-127> or cc:<1-16>:<0-127>)", text);
+        let mut parts = text.trim().split(':');
+        let (Some(kind), Some(channel), Some(number), None) = (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid());
+        };
+        let channel: u8 = channel.trim().parse().ok().filter(|c| (1..=16).contains(c)).ok_or_else(invalid)?;
+        let number: u8 = number.trim().parse().ok().filter(|n| *n <= 127).ok_or_else(invalid)?;
+        let channel = channel - 1;
+        match kind.trim().to_ascii_lowercase().as_str() {
+            "note" => Ok(Self::Note { channel, number }),
+            "cc" => Ok(Self::Cc { channel, number }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for Control {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Note { channel, number } => write!(f, "note:{}:{}", channel + 1, number),
+            Self::Cc { channel, number } => write!(f, "cc:{}:{}", channel + 1, number),
+        }
+    }
+}
+
+/// The control a message addresses and its value (velocity or CC value),
+/// for note-ons and control changes; a note-on with velocity 0 is a
+/// note-off and ignored like the rest.
+fn decode(message: &[u8]) -> Option<(Control, u8)> {
+    let (&status, data) = message.split_first()?;
+    let channel = status & 0x0f;
+    match (status & 0xf0, data) {
+        (0x90, &[number, velocity, ..]) if velocity > 0 => Some((Control::Note { channel, number }, velocity)),
+        (0xb0, &[number, value, ..]) => Some((Control::Cc { channel, number }, value)),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MidiAction {
+    NextSinger,
+    PauseResume,
+    KeyUp,
+    KeyDown,
+    ToggleFiller,
+    MusicVolume,
+    MicVolume,
+}
+
+impl MidiAction {
+    /// Follows the control's value instead of firing on a press.
+    fn is_continuous(self) -> bool {
+        matches!(self, Self::MusicVolume | Self::MicVolume)
+    }
+}
+
+/// Payload of `midi://action`.
+#[derive(Debug, Clone, Serialize)]
+pub struct MidiActionEvent {
+    pub action: MidiAction,
+    pub control: String,
+    /// The control's value, 0–1.
+    pub value: f32,
+}
+
+/// Payload of `midi://input`: a control no mapping covers.
+#[derive(Debug, Clone, Serialize)]
+pub struct MidiInputEvent {
+    pub control: String,
+    pub value: u8,
+}
+
+#[derive(Default)]
+struct Shared {
+    device: Mutex<Option<String>>,
+    bindings: Mutex<Vec<(Control, MidiAction)>>,
+}
+
+/// Managed state: the device and mappings applied by the last `sync`.
+#[derive(Default)]
+pub struct MidiControlState {
+    shared: Arc<Shared>,
+}
+
+/// Parse every mapping, rejecting invalid controls and one control mapped
+/// to two actions.
+pub fn parse_mappings(config: &MidiConfig) -> Result<Vec<(Control, MidiAction)>, String> {
+    let mut bindings: Vec<(Control, MidiAction)> = Vec::new();
+    for mapping in &config.mappings {
+        let control: Control = mapping.control.parse()?;
+        if let Some((_, other)) = bindings.iter().find(|(c, _)| *c == control) {
+            return Err(format!("MIDI control '{}' is mapped to both {:?} and {:?}", control, other, mapping.action));
+        }
+        bindings.push((control, mapping.action));
+    }
+    Ok(bindings)
+}
+
+/// Apply the device and mappings of `config`; the listener picks up a
+/// device change on its next look at the ports.
+pub fn sync(app: &AppHandle, config: &MidiConfig) {
+    let bindings = match parse_mappings(config) {
+        Ok(bindings) => bindings,
+        Err(e) => {
+            tracing::warn!("[midi] {}", e);
+            return;
+        }
+    };
+    let Some(state) = app.try_state::<MidiControlState>() else { return };
+    if let Ok(mut device) = state.shared.device.lock() {
+        *device = config.device.clone().filter(|d| !d.trim().is_empty());
+    }
+    if let Ok(mut registered) = state.shared.bindings.lock() {
+        *registered = bindings;
+    }
+}
+
+/// The port named `wanted`: an exact match ignoring case, else the first
+/// whose name contains it.
+fn find_port(names: &[String], wanted: &str) -> Option<usize> {
+    let wanted = wanted.trim().to_lowercase();
+    names
+        .iter()
+        .position(|name| name.to_lowercase() == wanted)
+        .or_else(|| names.iter().position(|name| name.to_lowercase().contains(&wanted)))
+}
+
+fn port_names(input: &MidiInput) -> Vec<String> {
+    input.ports().iter().filter_map(|port| input.port_name(port).ok()).collect()
+}
+
+fn handle(app: &AppHandle, shared: &Shared, message: &[u8]) {
+    let Some((control, value)) = decode(message) else { return };
+    let action = shared
+        .bindings
+        .lock()
+        .ok()
+        .and_then(|bindings| bindings.iter().find(|(c, _)| *c == control).map(|(_, a)| *a));
+    let Some(action) = action else {
+        publish(app, AppEvent::MidiInput(MidiInputEvent { control: control.to_string(), value }));
+        return;
+    };
+    // A button sending CCs reports its release as 0
+    if !action.is_continuous() && value == 0 {
+        return;
+    }
+    let level = value as f32 / 127.0;
+    if let Err(e) = run(app, action, level) {
+        tracing::warn!("[midi] {:?} failed: {}", action, e);
+    }
+    publish(app, AppEvent::MidiAction(MidiActionEvent { action, control: control.to_string(), value: level }));
+}
+
+fn run(app: &AppHandle, action: MidiAction, level: f32) -> Result<(), String> {
+    let audio = app.state::<AudioState>();
+    match action {
+        MidiAction::PauseResume => audio.toggle_playback().map(|_| ()),
+        MidiAction::KeyUp | MidiAction::KeyDown => {
+            let step = if action == MidiAction::KeyUp { 1 } else { -1 };
+            audio.set_key(audio.key() + step);
+            Ok(())
+        }
+        MidiAction::MusicVolume => audio.set_volume(level),
+        MidiAction::MicVolume => {
+            app.state::<MicState>().set_gain(level * MAX_GAIN);
+            Ok(())
+        }
+        // Left to the frontend
+        MidiAction::NextSinger | MidiAction::ToggleFiller => Ok(()),
+    }
+}
+
+fn connect(app: &AppHandle, shared: &Arc<Shared>, wanted: &str) -> Result<Option<(String, MidiInputConnection<()>)>, String> {
+    let mut input = MidiInput::new(CLIENT_NAME).map_err(|e| format!("Failed to open MIDI input: {}", e))?;
+    input.ignore(Ignore::All);
+    let ports = input.ports();
+    let names: Vec<String> = ports.iter().map(|port| input.port_name(port).unwrap_or_default()).collect();
+    let Some(index) = find_port(&names, wanted) else { return Ok(None) };
+    let name = names[index].clone();
+    let callback_app = app.clone();
+    let callback_shared = shared.clone();
+    let connection = input
+        .connect(
+            &ports[index],
+            "karaoke-control",
+            move |_, message, _| handle(&callback_app, &callback_shared, message),
+            (),
+        )
+        .map_err(|e| format!("Failed to connect to {}: {}", name, e))?;
+    Ok(Some((name, connection)))
+}
+
+/// Keep the configured device connected; runs for the lifetime of the app
+/// on a thread of its own. Call once `MidiControlState` is managed.
+pub fn spawn_listener(app: AppHandle) {
+    let shared = app.state::<MidiControlState>().shared.clone();
+    let spawned = std::thread::Builder::new().name("midi-control".to_string()).spawn(move || {
+        let scanner = match MidiInput::new(CLIENT_NAME) {
+            Ok(scanner) => scanner,
+            Err(e) => {
+                tracing::warn!("[midi] MIDI input unavailable: {}", e);
+                return;
+            }
+        };
+        // Connected port, the device setting it was found for, connection
+        let mut connected: Option<(String, String, MidiInputConnection<()>)> = None;
+        // Last connect error, logged once rather than every poll
+        let mut failed: Option<String> = None;
+        loop {
+            let wanted = shared.device.lock().ok().and_then(|device| device.clone());
+            if let Some((port, device, _)) = &connected {
+                let unplugged = !port_names(&scanner).contains(port);
+                if unplugged || wanted.as_deref() != Some(device.as_str()) {
+                    tracing::info!("[midi] Disconnected from {}", port);
+                    connected = None;
+                }
+            }
+            // Checked on the scanner first, so no client is opened per poll
+            let present = |device: &str| find_port(&port_names(&scanner), device).is_some();
+            if let Some(device) = wanted.as_deref().filter(|device| connected.is_none() && present(device)) {
+                match connect(&app, &shared, device) {
+                    Ok(Some((port, connection))) => {
+                        tracing::info!("[midi] Listening to {}", port);
+                        connected = Some((port, device.to_string(), connection));
+                        failed = None;
+                    }
+                    Ok(None) => {}
+                    Err(e) => {
+                        if failed.as_ref() != Some(&e) {
+                            tracing::warn!("[midi] {}", e);
+                        }
+                        failed = Some(e);
+                    }
+                }
+            }
+            std::thread::sleep(POLL);
+        }
+    });
+    if let Err(e) = spawned {
+        tracing::error!("[midi] Failed to start the MIDI listener: {}", e);
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+/// Names of the connected MIDI input ports.
+#[tauri::command]
+pub fn list_midi_inputs() -> Result<Vec<String>, String> {
+    let input = MidiInput::new(CLIENT_NAME).map_err(|e| format!("Failed to open MIDI input: {}", e))?;
+    Ok(port_names(&input))
+}
+
+/// Listen to the input port named `device` (or containing it), or to none
+/// with `None`. Saved to `config.toml`; returns the MIDI settings.
+#[tauri::command]
+pub fn set_midi_device(app: AppHandle, webview: tauri::Webview, device: Option<String>) -> Result<MidiConfig, String> {
+    require_webview(&webview, Capability::ChangeSettings)?;
+    let mut updated = config::current(&app);
+    updated.midi.device = device.map(|d| d.trim().to_string()).filter(|d| !d.is_empty());
+    Ok(config::update(&app, updated)?.midi)
+}
+
+/// Map `control` (`note:<channel>:<note>` or `cc:<channel>:<controller>`)
+/// to `action`, or unmap it with `None`. Saved to `config.toml` and applied
+/// at once; returns the MIDI settings.
+#[tauri::command]
+pub fn set_midi_mapping(
+    app: AppHandle,
+    webview: tauri::Webview,
+    control: String,
+    action: Option<MidiAction>,
+) -> Result<MidiConfig, String> {
+    require_webview(&webview, Capability::ChangeSettings)?;
+    // Stored as displayed, so "CC:01:7" and "cc:1:7" are one mapping
+    let control: Control = control.parse()?;
+    let mut updated = config::current(&app);
+    updated.midi.set(control.to_string(), action);
+    Ok(config::update(&app, updated)?.midi)
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_decodes_controls() {
+        assert_eq!("note:1:36".parse::<Control>().unwrap(), Control::Note { channel: 0, number: 36 });
+        assert_eq!(" CC:16:7 ".parse::<Control>().unwrap(), Control::Cc { channel: 15, number: 7 });
+        assert_eq!(Control::Cc { channel: 15, number: 7 }.to_string(), "cc:16:7");
+        for invalid in ["note:0:36", "cc:17:1", "cc:1:128", "pitch:1:1", "note:1", "note:1:2:3"] {
+            assert!(invalid.parse::<Control>().is_err(), "{}", invalid);
+        }
+
+        assert_eq!(decode(&[0x91, 36, 100]), Some((Control::Note { channel: 1, number: 36 }, 100)));
+        assert_eq!(decode(&[0xb0, 7, 0]), Some((Control::Cc { channel: 0, number: 7 }, 0)));
+        // Note-on with velocity 0, note-off, clock
+        assert_eq!(decode(&[0x90, 36, 0]), None);
+        assert_eq!(decode(&[0x80, 36, 64]), None);
+        assert_eq!(decode(&[0xf8]), None);
+    }
+
+    #[test]
+    fn rejects_invalid_and_duplicate_mappings() {
+        let mut config = MidiConfig::default();
+        config.set("note:1:36".to_string(), Some(MidiAction::NextSinger));
+        config.set("cc:1:7".to_string(), Some(MidiAction::MusicVolume));
+        assert_eq!(parse_mappings(&config).unwrap().len(), 2);
+
+        config.set("CC:01:7".to_string(), Some(MidiAction::MicVolume));
+        assert!(parse_mappings(&config).is_err());
+        config.set("CC:01:7".to_string(), None);
+        config.set("fader".to_string(), Some(MidiAction::KeyUp));
+        assert!(parse_mappings(&config).is_err());
+        config.set("fader".to_string(), None);
+        assert_eq!(parse_mappings(&config).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn finds_ports_by_name() {
+        let names = vec!["Midi Through Port-0".to_string(), "nanoKONTROL2 MIDI 1".to_string(), "nanoPAD2".to_string()];
+        assert_eq!(find_port(&names, "nanopad2"), Some(2));
+        assert_eq!(find_port(&names, "KONTROL"), Some(1));
+        assert_eq!(find_port(&names, "launchpad"), None);
+    }
+}
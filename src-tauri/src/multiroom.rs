@@ -0,0 +1,715 @@
+//! Multi-room playback: a second instance on another machine mirrors this
+//! one, for a venue's smoking area with its own screen and speakers.
+//!
+//! Every instance publishes `room://cue` — the song (relative to the
+//! library root folder it lies in), the position, whether it plays, and the
+//! epoch ms that holds at — whenever playback changes and once a second
+//! while it plays. The WebSocket bridge (`remote`) forwards it, and answers
+//! `clock` messages with its own time.
+//!
+//! The other room sets `[multiroom] leader` to the main machine (`"auto"`
+//! finds it by mDNS) and `token` to the leader's guest token. Its follower
+//! connects to the bridge as a guest,
+//! estimates the clock offset from `clock` round trips like a watch party
+//! does (`watch_party::protocol::ClockSync`) and applies the cues to its own
+//! native player: a song is opened from the same place under one of its
+//! own root folders, seeked to where the leader will be `START_LEAD` from
+//! now and started at that instant on the local clock. While both play, a
+//! drift beyond `MAX_DRIFT_MS` is made up by nudging the engine (see
+//! `audio::playback_feed`), which nobody hears; only one beyond
+//! `MAX_NUDGE_MS` is corrected with a seek. Calibrated output latencies on
+//! both sides are accounted for, and `offset_ms` delays this room on top
+//! (speakers further away than the main room's).
+//!
+//! A leader given without a port is looked up by mDNS for the port its
+//! bridge announces; this machine's own bridge port is assumed when it does
+//! not answer. Tracks only ever resolve under this machine's root folders,
+//! so a leader cannot make it open anything else (a network share, say).
+//!
+//! The leader needs LAN access on (see `server::security`).
+
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use futures_util::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+use tokio::time::MissedTickBehavior;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_util::sync::CancellationToken;
+
+use crate::access::{require_webview, Capability};
+use crate::audio::commands::{self as audio, AudioState};
+use crate::audio::device_offsets;
+use crate::audio::playback_feed::NUDGE_SPACING;
+use crate::config::{self, MultiroomConfig};
+use crate::db::DbState;
+use crate::events::{publish, AppEvent};
+use crate::runtime::{sleep_or_cancel, TaskSupervisor};
+use crate::server::discovery;
+use crate::watch_party::protocol::ClockSync;
+
+pub const ROOM_CUE_EVENT: &str = "room://cue";
+/// `[multiroom] leader` value that finds the leader by mDNS.
+pub const AUTO_LEADER: &str = "auto";
+/// Leader: playback is sampled this often for changes.
+const SAMPLE: Duration = Duration::from_millis(100);
+/// Leader: cues repeat this often while playing.
+const HEARTBEAT: Duration = Duration::from_secs(1);
+/// Leader: a position this far off the extrapolated one is a seek.
+const JUMP_MS: i64 = 250;
+/// Follower: time to open and seek a song before starting it.
+const START_LEAD: Duration = Duration::from_millis(600);
+/// Follower: drift made up by nudging.
+const MAX_DRIFT_MS: i64 = 30;
+/// Follower: drift corrected with a seek instead; nudging it away would
+/// take too long.
+const MAX_NUDGE_MS: i64 = 250;
+/// Follower: seeks need this long to settle before drift is measured again.
+const SETTLE: Duration = Duration::from_secs(2);
+const CLOCK_INTERVAL: Duration = Duration::from_secs(1);
+const RECONNECT: Duration = Duration::from_secs(3);
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(5);
+/// Root folders and the output latency are re-read this often.
+const SETTINGS_REFRESH: Duration = Duration::from_secs(30);
+/// Largest `offset_ms` either way.
+const MAX_OFFSET_MS: i64 = 2_000;
+
+pub fn now_ms() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+/// Payload of `room://cue`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RoomCue {
+    /// The song, relative to its root folder with `/` separators, or its
+    /// absolute path outside the library; `None` when stopped.
+    pub track: Option<String>,
+    pub position_ms: u64,
+    pub playing: bool,
+    /// Epoch ms on the sender's clock at which `position_ms` holds.
+    pub at: i64,
+    /// Calibrated output latency of the sender.
+    pub latency_ms: i64,
+}
+
+impl RoomCue {
+    /// Where the sender's engine is at `leader_ms` on its clock.
+    fn position_at(&self, leader_ms: i64) -> i64 {
+        let elapsed = if self.playing { leader_ms - self.at } else { 0 };
+        self.position_ms as i64 + elapsed
+    }
+}
+
+fn root_folders(app: &AppHandle) -> Vec<PathBuf> {
+    let Some(db) = app.try_state::<DbState>() else { return Vec::new() };
+    let Ok(conn) = db.conn.lock() else { return Vec::new() };
+    let Ok(mut stmt) = conn.prepare("SELECT path FROM root_folders") else { return Vec::new() };
+    let Ok(rows) = stmt.query_map([], |row| row.get::<_, String>(0)) else { return Vec::new() };
+    rows.flatten().map(PathBuf::from).collect()
+}
+
+fn output_latency(app: &AppHandle) -> i64 {
+    app.try_state::<DbState>()
+        .and_then(|db| device_offsets::output_latency(&*db.conn.lock().ok()?).ok())
+        .unwrap_or(0)
+}
+
+/// `path` relative to the root folder it lies in, the deepest one first.
+fn relative_track(path: &Path, roots: &[PathBuf]) -> String {
+    let relative = roots
+        .iter()
+        .filter_map(|root| path.strip_prefix(root).ok())
+        .min_by_key(|rest| rest.components().count());
+    match relative {
+        Some(rest) => rest.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/"),
+        None => path.to_string_lossy().into_owned(),
+    }
+}
+
+/// The local file for a cue's track: under the first root folder that has
+/// it. An absolute track (outside the leader's library) only resolves when
+/// it lies under one of ours; nothing resolves outside them.
+fn resolve_track(track: &str, roots: &[PathBuf], exists: impl Fn(&Path) -> bool) -> Option<PathBuf> {
+    let absolute = Path::new(track);
+    if absolute.is_absolute() || track.starts_with("\\\\") || track.starts_with("//") {
+        let inside = roots.iter().any(|root| absolute.strip_prefix(root).is_ok_and(|rest| plain_parts(rest)));
+        return (inside && exists(absolute)).then(|| absolute.to_path_buf());
+    }
+    let parts: Vec<&str> = track.split('/').collect();
+    if parts.iter().any(|part| part.is_empty() || *part == "." || *part == ".." || part.contains(['\\', ':'])) {
+        return None;
+    }
+    roots
+        .iter()
+        .map(|root| parts.iter().fold(root.clone(), |path, part| path.join(part)))
+        .find(|path| exists(path))
+}
+
+/// `path` has nothing but plain names (no `..` climbing out of a root).
+fn plain_parts(path: &Path) -> bool {
+    path.components().all(|c| matches!(c, std::path::Component::Normal(_)))
+}
+
+pub fn validate(config: &MultiroomConfig) -> Result<(), String> {
+    if config.offset_ms.abs() > MAX_OFFSET_MS {
+        return Err(format!("Multi-room offset must be within ±{} ms", MAX_OFFSET_MS));
+    }
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// Leader
+// ---------------------------------------------------------------------------
+
+/// Whether `next` is worth a cue after `last` went out at `last_sent`.
+fn cue_due(last: Option<&RoomCue>, next: &RoomCue, last_sent: Instant, now: Instant) -> bool {
+    let Some(last) = last else { return true };
+    if last.track != next.track || last.playing != next.playing {
+        return true;
+    }
+    if (last.position_at(next.at) - next.position_ms as i64).abs() > JUMP_MS {
+        return true;
+    }
+    next.playing && now.duration_since(last_sent) >= HEARTBEAT
+}
+
+/// Publish `room://cue` for the native player and follow the configured
+/// leader; runs for the lifetime of the app. Call once the task supervisor
+/// and the audio engine are managed.
+pub fn spawn_cue_publisher(app: AppHandle) {
+    // `config::init` ran before there was a supervisor to follow with
+    sync(&app, &config::current(&app).multiroom);
+    let supervisor = app.state::<TaskSupervisor>();
+    supervisor.spawn("room-cues", move |token| async move {
+        let mut last: Option<RoomCue> = None;
+        let mut last_sent = Instant::now();
+        let (mut roots, mut latency_ms) = (root_folders(&app), output_latency(&app));
+        let mut refreshed = Instant::now();
+        while sleep_or_cancel(&token, SAMPLE).await {
+            let Some(audio) = app.try_state::<AudioState>() else { continue };
+            if refreshed.elapsed() >= SETTINGS_REFRESH {
+                (roots, latency_ms) = (root_folders(&app), output_latency(&app));
+                refreshed = Instant::now();
+            }
+            let position = audio.position();
+            let cue = RoomCue {
+                track: audio.current_path().map(|path| relative_track(Path::new(&path), &roots)),
+                position_ms: position.position_ms,
+                playing: position.is_playing,
+                at: now_ms(),
+                latency_ms,
+            };
+            let now = Instant::now();
+            if cue_due(last.as_ref(), &cue, last_sent, now) {
+                publish(&app, AppEvent::RoomCue(cue.clone()));
+                last = Some(cue);
+                last_sent = now;
+            }
+        }
+    });
+}
+
+// ---------------------------------------------------------------------------
+// Follower
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MultiroomStatus {
+    /// `[multiroom] leader`; `None` when this room leads or plays alone.
+    pub leader: Option<String>,
+    /// The bridge followed, `host:port`.
+    pub connected_to: Option<String>,
+    /// Leader clock minus local clock.
+    pub clock_offset_ms: Option<i64>,
+    pub rtt_ms: Option<i64>,
+    /// Local minus leader position at the last check.
+    pub drift_ms: Option<i64>,
+}
+
+/// Managed state: the running follower and what it reports.
+#[derive(Default)]
+pub struct MultiroomState {
+    follower: Mutex<Option<(MultiroomConfig, CancellationToken)>>,
+    status: Mutex<MultiroomStatus>,
+}
+
+impl MultiroomState {
+    fn update(&self, f: impl FnOnce(&mut MultiroomStatus)) {
+        if let Ok(mut status) = self.status.lock() {
+            f(&mut status);
+        }
+    }
+}
+
+/// Follow the configured leader, or stop following; a running follower is
+/// restarted when the leader or offset changed.
+pub fn sync(app: &AppHandle, config: &MultiroomConfig) {
+    let Some(state) = app.try_state::<MultiroomState>() else { return };
+    let Ok(mut follower) = state.follower.lock() else { return };
+    if follower.as_ref().is_some_and(|(running, _)| running == config) {
+        return;
+    }
+    if let Some((_, token)) = follower.take() {
+        token.cancel();
+    }
+    state.update(|status| *status = MultiroomStatus::default());
+    let Some(leader) = config.leader.clone().filter(|l| !l.trim().is_empty()) else { return };
+    let Some(supervisor) = app.try_state::<TaskSupervisor>() else { return };
+    let token = supervisor.token();
+    *follower = Some((config.clone(), token.clone()));
+    state.update(|status| status.leader = Some(leader.clone()));
+    tracing::info!("[multiroom] Following {}", leader);
+
+    let (app, offset_ms) = (app.clone(), config.offset_ms);
+    let guest_token = config.token.clone().unwrap_or_default();
+    supervisor.spawn("room-follower", move |_| async move {
+        while !token.is_cancelled() {
+            let result = match locate_leader(&app, &leader).await {
+                Ok(address) => follow(&app, &address, &guest_token, offset_ms, &token).await,
+                Err(e) => Err(e),
+            };
+            app.state::<MultiroomState>().update(|status| {
+                status.connected_to = None;
+                status.drift_ms = None;
+            });
+            if let Err(e) = result {
+                tracing::warn!("[multiroom] {}", e);
+            }
+            if !sleep_or_cancel(&token, RECONNECT).await {
+                break;
+            }
+        }
+    });
+}
+
+/// `host:port` of the leader's bridge: as configured, or the first other
+/// instance announcing one over mDNS. A host without a port gets the port
+/// it announces, else the one this machine's bridge uses.
+async fn locate_leader(app: &AppHandle, leader: &str) -> Result<String, String> {
+    let leader = leader.trim();
+    if leader.eq_ignore_ascii_case(AUTO_LEADER) {
+        let found = tauri::async_runtime::spawn_blocking(discover).await.map_err(|e| e.to_string())??;
+        return found.first().cloned().ok_or_else(|| "No other karaoke machine found on the LAN".to_string());
+    }
+    let host = match leader.parse::<std::net::Ipv6Addr>() {
+        Ok(v6) => format!("[{}]", v6),
+        Err(_) if leader.contains(':') => return Ok(leader.to_string()),
+        Err(_) => leader.to_string(),
+    };
+    let ips: Vec<IpAddr> = match tokio::net::lookup_host((host.trim_matches(['[', ']']), 0)).await {
+        Ok(addresses) => addresses.map(|a| a.ip()).collect(),
+        Err(e) => return Err(format!("Cannot resolve {}: {}", leader, e)),
+    };
+    let found = tauri::async_runtime::spawn_blocking(discover).await.map_err(|e| e.to_string())?.unwrap_or_default();
+    if let Some(address) = announced_by(&found, &ips) {
+        return Ok(address);
+    }
+    Ok(format!("{}:{}", host, crate::remote::configured_port(app)))
+}
+
+/// The discovered bridge (`ip:port`) running on one of `ips`.
+fn announced_by(found: &[String], ips: &[IpAddr]) -> Option<String> {
+    found
+        .iter()
+        .find(|address| address.parse::<SocketAddr>().is_ok_and(|a| ips.contains(&a.ip())))
+        .cloned()
+}
+
+/// Other instances on the LAN announcing a WebSocket bridge, as
+/// `host:port`; blocks for up to `DISCOVERY_TIMEOUT`.
+fn discover() -> Result<Vec<String>, String> {
+    let daemon = mdns_sd::ServiceDaemon::new().map_err(|e| format!("Failed to start mDNS: {}", e))?;
+    let receiver = daemon
+        .browse(discovery::SERVICE_TYPE)
+        .map_err(|e| format!("Failed to browse {}: {}", discovery::SERVICE_TYPE, e))?;
+    let own_host = discovery::mdns_host();
+    let deadline = Instant::now() + DISCOVERY_TIMEOUT;
+    let mut found = Vec::new();
+    while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+        let Ok(event) = receiver.recv_timeout(remaining) else { break };
+        let mdns_sd::ServiceEvent::ServiceResolved(info) = event else { continue };
+        if info.get_hostname() == own_host {
+            continue;
+        }
+        let Some(port) = info.get_property_val_str("ws_port").and_then(|p| p.parse::<u16>().ok()) else { continue };
+        let Some(ip) = info.get_addresses().iter().find(|ip| ip.is_ipv4()).or(info.get_addresses().iter().next()) else {
+            continue;
+        };
+        let address = match ip {
+            IpAddr::V4(v4) => format!("{}:{}", v4, port),
+            IpAddr::V6(v6) => format!("[{}]:{}", v6, port),
+        };
+        if !found.contains(&address) {
+            found.push(address);
+        }
+    }
+    let _ = daemon.shutdown();
+    Ok(found)
+}
+
+/// What the follower does about a cue.
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    Stop,
+    /// Open `path` (when given), seek to `position_ms` and start at
+    /// `start_at` on the local clock, or stay paused without one.
+    Start { path: Option<PathBuf>, position_ms: u64, start_at: Option<i64> },
+    Pause { position_ms: u64 },
+    Seek { position_ms: u64 },
+    /// Drift `ms` ahead (behind when negative) without a seek.
+    Nudge { ms: i64 },
+    Nothing,
+}
+
+/// How the two clocks and engines line up.
+#[derive(Debug, Clone, Copy)]
+struct Timing {
+    /// Leader clock minus local clock.
+    offset_ms: i64,
+    /// What the local engine runs behind the leader's: the latency
+    /// difference and the configured offset.
+    shift_ms: i64,
+}
+
+impl Timing {
+    /// Where the local engine should be when the leader's clock reads
+    /// `leader_ms`.
+    fn target(&self, cue: &RoomCue, leader_ms: i64) -> u64 {
+        (cue.position_at(leader_ms) - self.shift_ms).max(0) as u64
+    }
+}
+
+/// The local side of playback as the follower left it.
+#[derive(Debug, Default)]
+struct Mirror {
+    /// The leader's track last cued.
+    track: Option<String>,
+    /// It is open here (this library has it).
+    loaded: bool,
+    playing: bool,
+    settled_at: Option<Instant>,
+}
+
+impl Mirror {
+    /// Decide what brings the local player in line with `cue`, and the
+    /// drift measured, if any. `local_ms` is the local clock.
+    fn plan(
+        &mut self,
+        cue: &RoomCue,
+        timing: Timing,
+        local_ms: i64,
+        local_position_ms: u64,
+        resolve: impl Fn(&str) -> Option<PathBuf>,
+        now: Instant,
+    ) -> (Step, Option<i64>) {
+        let was_loaded = self.loaded;
+        if cue.track != self.track {
+            self.track = cue.track.clone();
+            self.playing = false;
+            self.loaded = false;
+            let path = match cue.track.as_deref() {
+                Some(track) => resolve(track),
+                None => None,
+            };
+            let Some(path) = path else {
+                // Stopped there, or a song this library lacks
+                return (if was_loaded { Step::Stop } else { Step::Nothing }, None);
+            };
+            self.loaded = true;
+            return self.start(cue, Some(path), timing, local_ms, now);
+        }
+        if !self.loaded {
+            return (Step::Nothing, None);
+        }
+        if cue.playing && !self.playing {
+            return self.start(cue, None, timing, local_ms, now);
+        }
+        if !cue.playing {
+            let was_playing = std::mem::replace(&mut self.playing, false);
+            let position_ms = timing.target(cue, cue.at);
+            return (if was_playing { Step::Pause { position_ms } } else { Step::Nothing }, None);
+        }
+        if self.settled_at.is_some_and(|at| now < at) {
+            return (Step::Nothing, None);
+        }
+        let expected = timing.target(cue, local_ms + timing.offset_ms);
+        let drift = local_position_ms as i64 - expected as i64;
+        if drift.abs() > MAX_NUDGE_MS {
+            self.settled_at = Some(now + SETTLE);
+            return (Step::Seek { position_ms: expected }, Some(drift));
+        }
+        if drift.abs() > MAX_DRIFT_MS {
+            // Measured again once the nudge has been made up
+            self.settled_at = Some(now + Duration::from_millis(drift.unsigned_abs() * NUDGE_SPACING) + SETTLE);
+            return (Step::Nudge { ms: -drift }, Some(drift));
+        }
+        (Step::Nothing, Some(drift))
+    }
+
+    /// Open (`path`) or resume at a scheduled instant, or cue up paused.
+    fn start(&mut self, cue: &RoomCue, path: Option<PathBuf>, timing: Timing, local_ms: i64, now: Instant) -> (Step, Option<i64>) {
+        self.playing = cue.playing;
+        self.settled_at = Some(now + START_LEAD + SETTLE);
+        if !cue.playing {
+            return (Step::Start { path, position_ms: timing.target(cue, cue.at), start_at: None }, None);
+        }
+        let start_at = local_ms + START_LEAD.as_millis() as i64;
+        let position_ms = timing.target(cue, start_at + timing.offset_ms);
+        (Step::Start { path, position_ms, start_at: Some(start_at) }, None)
+    }
+}
+
+async fn carry_out(app: &AppHandle, step: Step, token: &CancellationToken) -> Result<(), String> {
+    match step {
+        Step::Nothing => Ok(()),
+        Step::Stop => audio::stop(app.clone()),
+        Step::Pause { position_ms } => {
+            audio::pause(app.clone())?;
+            audio::seek(app.clone(), position_ms)
+        }
+        Step::Seek { position_ms } => audio::seek(app.clone(), position_ms),
+        Step::Nudge { ms } => {
+            app.state::<AudioState>().nudge(ms);
+            Ok(())
+        }
+        Step::Start { path, position_ms, start_at } => {
+            if let Some(path) = path {
+                tracing::info!("[multiroom] Opening {}", path.display());
+                audio::load_track(app.clone(), path.to_string_lossy().into_owned(), None).await?;
+            } else {
+                audio::pause(app.clone())?;
+            }
+            audio::seek(app.clone(), position_ms)?;
+            let Some(start_at) = start_at else { return Ok(()) };
+            let wait = Duration::from_millis((start_at - now_ms()).max(0) as u64);
+            if sleep_or_cancel(token, wait).await {
+                audio::play(app.clone())?;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum BridgeReply {
+    Clock { sent_at: i64, received_at: i64 },
+}
+
+/// Mirror the leader at `address`, authenticated with its `guest_token`,
+/// until the connection drops or `token` fires.
+async fn follow(app: &AppHandle, address: &str, guest_token: &str, offset_ms: i64, token: &CancellationToken) -> Result<(), String> {
+    let url = if guest_token.is_empty() { format!("ws://{}/", address) } else { format!("ws://{}/?token={}", address, guest_token) };
+    let (socket, _) = tokio::time::timeout(DISCOVERY_TIMEOUT, tokio_tungstenite::connect_async(&url))
+        .await
+        .map_err(|_| format!("Timed out connecting to {}", address))?
+        .map_err(|e| format!("Cannot reach the leader at {}: {}", address, e))?;
+    tracing::info!("[multiroom] Connected to {}", address);
+    let state = app.state::<MultiroomState>();
+    state.update(|status| status.connected_to = Some(address.to_string()));
+    let (mut sink, mut source) = socket.split();
+    let mut clock = ClockSync::default();
+    let mut mirror = Mirror::default();
+    let mut ticks = tokio::time::interval(CLOCK_INTERVAL);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let mut latest: Option<RoomCue> = None;
+    let (mut roots, mut latency_ms) = (root_folders(app), output_latency(app));
+    let mut refreshed = Instant::now();
+    loop {
+        let body = tokio::select! {
+            _ = token.cancelled() => break,
+            _ = ticks.tick() => {
+                let ping = serde_json::json!({ "type": "clock", "sent_at": now_ms() }).to_string();
+                sink.send(Message::Text(ping)).await.map_err(|e| format!("Lost the leader: {}", e))?;
+                continue;
+            }
+            incoming = source.next() => match incoming {
+                Some(Ok(Message::Text(body))) => body,
+                Some(Ok(Message::Close(_))) | None => break,
+                Some(Ok(_)) => continue,
+                Some(Err(e)) => return Err(format!("Lost the leader: {}", e)),
+            },
+        };
+        let Ok(value) = serde_json::from_str::<serde_json::Value>(&body) else { continue };
+        if value.get("topic").and_then(|t| t.as_str()) == Some(ROOM_CUE_EVENT) {
+            let Some(cue) = value.get("data").and_then(|data| serde_json::from_value::<RoomCue>(data.clone()).ok()) else {
+                continue;
+            };
+            latest = Some(cue);
+        } else if let Ok(BridgeReply::Clock { sent_at, received_at }) = serde_json::from_value(value) {
+            clock.add_sample(sent_at, received_at, now_ms());
+            state.update(|status| {
+                status.clock_offset_ms = clock.offset_ms();
+                status.rtt_ms = clock.rtt_ms();
+            });
+        }
+        // Cues wait for a first clock estimate; a start off by the offset
+        // is worse than starting a second later
+        let (Some(cue), Some(offset)) = (latest.as_ref(), clock.offset_ms()) else { continue };
+        if refreshed.elapsed() >= SETTINGS_REFRESH {
+            (roots, latency_ms) = (root_folders(app), output_latency(app));
+            refreshed = Instant::now();
+        }
+        let timing = Timing { offset_ms: offset, shift_ms: cue.latency_ms - latency_ms + offset_ms };
+        let local_position_ms = app.state::<AudioState>().position_ms();
+        let resolve = |track: &str| {
+            let found = resolve_track(track, &roots, |path| crate::paths::long_path(path).is_file());
+            if found.is_none() {
+                tracing::warn!("[multiroom] {} is not in this machine's library", track);
+            }
+            found
+        };
+        let (step, drift) = mirror.plan(cue, timing, now_ms(), local_position_ms, resolve, Instant::now());
+        if let Some(drift) = drift {
+            state.update(|status| status.drift_ms = Some(drift));
+        }
+        if step != Step::Nothing {
+            tracing::debug!("[multiroom] {:?} (drift {:?} ms)", step, drift);
+        }
+        if let Err(e) = carry_out(app, step, token).await {
+            tracing::warn!("[multiroom] {}", e);
+        }
+    }
+    let _ = sink.close().await;
+    tracing::info!("[multiroom] Disconnected from {}", address);
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+#[tauri::command]
+pub fn multiroom_status(app: AppHandle) -> MultiroomStatus {
+    app.state::<MultiroomState>().status.lock().map(|status| status.clone()).unwrap_or_default()
+}
+
+/// Other instances on the LAN this room could follow, as `host:port`.
+#[tauri::command]
+pub async fn find_rooms(webview: tauri::Webview) -> Result<Vec<String>, String> {
+    require_webview(&webview, Capability::ChangeSettings)?;
+    tauri::async_runtime::spawn_blocking(discover).await.map_err(|e| e.to_string())?
+}
+
+/// Follow `leader` (`host[:port]` or `"auto"`), or lead / play alone with
+/// `None`. Saved to `config.toml`; returns the multi-room settings.
+#[tauri::command]
+pub fn set_multiroom_leader(
+    app: AppHandle,
+    webview: tauri::Webview,
+    leader: Option<String>,
+    offset_ms: Option<i64>,
+) -> Result<MultiroomConfig, String> {
+    require_webview(&webview, Capability::ChangeSettings)?;
+    let mut updated = config::current(&app);
+    updated.multiroom.leader = leader.map(|l| l.trim().to_string()).filter(|l| !l.is_empty());
+    if let Some(offset_ms) = offset_ms {
+        updated.multiroom.offset_ms = offset_ms;
+    }
+    Ok(config::update(&app, updated)?.multiroom)
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cue(track: Option<&str>, position_ms: u64, playing: bool, at: i64) -> RoomCue {
+        RoomCue { track: track.map(str::to_string), position_ms, playing, at, latency_ms: 0 }
+    }
+
+    #[test]
+    fn maps_tracks_between_libraries() {
+        let leader_roots = vec![PathBuf::from("/music"), PathBuf::from("/music/karaoke")];
+        assert_eq!(relative_track(Path::new("/music/karaoke/Abba/SOS.mp4"), &leader_roots), "Abba/SOS.mp4");
+        assert_eq!(relative_track(Path::new("/tmp/x.mp3"), &leader_roots), "/tmp/x.mp3");
+
+        let roots = vec![PathBuf::from("/mnt/a"), PathBuf::from("/mnt/b")];
+        let found = resolve_track("Abba/SOS.mp4", &roots, |p| p.starts_with("/mnt/b"));
+        assert_eq!(found, Some(PathBuf::from("/mnt/b/Abba/SOS.mp4")));
+        assert_eq!(resolve_track("Abba/SOS.mp4", &roots, |_| false), None);
+        // Nothing outside the local roots, whatever the leader sends
+        assert_eq!(resolve_track("/mnt/a/x.mp3", &roots, |_| true), Some(PathBuf::from("/mnt/a/x.mp3")));
+        assert_eq!(resolve_track("/tmp/x.mp3", &roots, |_| true), None);
+        assert_eq!(resolve_track("/mnt/a/../../etc/x.mp3", &roots, |_| true), None);
+        assert_eq!(resolve_track("../x.mp3", &roots, |_| true), None);
+        assert_eq!(resolve_track("\\\\server\\share\\x.mp3", &roots, |_| true), None);
+        assert_eq!(resolve_track("//server/share/x.mp3", &roots, |_| true), None);
+    }
+
+    #[test]
+    fn finds_the_port_a_leader_announces() {
+        let found = vec!["192.168.1.20:47900".to_string(), "[fe80::1]:47822".to_string()];
+        let leader: IpAddr = "192.168.1.20".parse().unwrap();
+        assert_eq!(announced_by(&found, &[leader]), Some("192.168.1.20:47900".to_string()));
+        assert_eq!(announced_by(&found, &["192.168.1.21".parse().unwrap()]), None);
+    }
+
+    #[test]
+    fn cues_changes_jumps_and_heartbeats() {
+        let now = Instant::now();
+        let playing = cue(Some("a.mp3"), 1_000, true, 10_000);
+        assert!(cue_due(None, &playing, now, now));
+        // On schedule
+        assert!(!cue_due(Some(&playing), &cue(Some("a.mp3"), 1_100, true, 10_100), now, now));
+        // A seek
+        assert!(cue_due(Some(&playing), &cue(Some("a.mp3"), 30_000, true, 10_100), now, now));
+        assert!(cue_due(Some(&playing), &cue(Some("a.mp3"), 1_100, false, 10_100), now, now));
+        assert!(cue_due(Some(&playing), &cue(Some("a.mp3"), 2_000, true, 11_000), now, now + HEARTBEAT));
+        let paused = cue(Some("a.mp3"), 1_000, false, 10_000);
+        assert!(!cue_due(Some(&paused), &cue(Some("a.mp3"), 1_000, false, 20_000), now, now + HEARTBEAT));
+    }
+
+    #[test]
+    fn schedules_starts_and_corrects_drift() {
+        let resolve = |track: &str| Some(PathBuf::from("/local").join(track));
+        let timing = Timing { offset_ms: 500, shift_ms: 0 };
+        let now = Instant::now();
+        let mut mirror = Mirror::default();
+        // Leader clock 500 ms ahead; it is 2 s into the song at 10_500
+        let leader = cue(Some("a.mp3"), 2_000, true, 10_500);
+        let (step, _) = mirror.plan(&leader, timing, 10_000, 0, resolve, now);
+        let start_at = 10_000 + START_LEAD.as_millis() as i64;
+        let position_ms = 2_000 + START_LEAD.as_millis() as u64;
+        assert_eq!(step, Step::Start { path: Some(PathBuf::from("/local/a.mp3")), position_ms, start_at: Some(start_at) });
+
+        // Settling after the start
+        let (step, _) = mirror.plan(&leader, timing, 10_100, 2_100, resolve, now + Duration::from_millis(100));
+        assert_eq!(step, Step::Nothing);
+        // In sync later on
+        let later = now + START_LEAD + SETTLE;
+        let (step, drift) = mirror.plan(&leader, timing, 13_000, 5_010, resolve, later);
+        assert_eq!((step, drift), (Step::Nothing, Some(10)));
+        // Behind by 80 ms: nudged, then left alone while that is made up
+        let (step, drift) = mirror.plan(&leader, timing, 13_000, 4_920, resolve, later);
+        assert_eq!((step, drift), (Step::Nudge { ms: 80 }, Some(-80)));
+        let (step, _) = mirror.plan(&leader, timing, 13_100, 4_930, resolve, later + Duration::from_secs(1));
+        assert_eq!(step, Step::Nothing);
+        // Off by a second: seeked
+        let later = later + Duration::from_millis(80 * NUDGE_SPACING) + SETTLE;
+        let (step, drift) = mirror.plan(&leader, timing, 13_000, 4_000, resolve, later);
+        assert_eq!((step, drift), (Step::Seek { position_ms: 5_000 }, Some(-1_000)));
+
+        let (step, _) = mirror.plan(&cue(Some("a.mp3"), 6_000, false, 14_000), timing, 13_600, 6_000, resolve, later);
+        assert_eq!(step, Step::Pause { position_ms: 6_000 });
+        let (step, _) = mirror.plan(&cue(None, 0, false, 15_000), timing, 14_600, 0, resolve, later);
+        assert_eq!(step, Step::Stop);
+        // A song this library lacks is skipped
+        let (step, _) = mirror.plan(&cue(Some("b.mp3"), 0, true, 16_000), timing, 15_500, 0, |_| None, later);
+        assert_eq!(step, Step::Nothing);
+        // ...and stays skipped without looking it up again
+        let (step, _) = mirror.plan(&cue(Some("b.mp3"), 1_000, true, 17_000), timing, 16_500, 0, |_| unreachable!(), later);
+        assert_eq!(step, Step::Nothing);
+    }
+}
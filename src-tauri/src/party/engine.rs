@@ -0,0 +1,412 @@
+//! Party game mode state machine.
+//!
+//! The engine is fed the song's lyric line timings when a round starts and
+//! decides everything random up front from a seed that never leaves Rust:
+//! who sings which line (pass-the-mic), which lines are hidden (blind
+//! karaoke). The frontend only learns a decision when its cue is due, a
+//! short lead ahead of the line, so neither the singer nor the audience
+//! window can peek. `advance` is driven by the playback position and
+//! returns the cues that became due since the last call.
+
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+/// Cues are released this long before their line starts, so the screens
+/// can switch exactly on time.
+pub const CUE_LEAD_MS: u64 = 150;
+/// A position jump further back than this is a seek: the engine rewinds.
+const REWIND_THRESHOLD_MS: u64 = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LineSpan {
+    pub start_ms: u64,
+    pub end_ms: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum ModeConfig {
+    /// The mic (and the line's score) moves between players at random
+    /// line boundaries.
+    PassTheMic {
+        players: Vec<String>,
+        /// Chance of a switch at each eligible boundary, 0–1.
+        #[serde(default = "default_switch_chance")]
+        switch_chance: f64,
+        /// Lines a player keeps the mic for before a switch may happen.
+        #[serde(default = "default_min_lines")]
+        min_lines_per_turn: u32,
+    },
+    /// Random lines are hidden while they are sung.
+    BlindKaraoke {
+        /// Share of lines hidden, 0–1.
+        #[serde(default = "default_hidden_ratio")]
+        hidden_ratio: f64,
+    },
+    /// The intro plays for `intro_ms`; players guess the song.
+    GuessTheIntro {
+        players: Vec<String>,
+        #[serde(default = "default_intro_ms")]
+        intro_ms: u64,
+    },
+}
+
+fn default_switch_chance() -> f64 {
+    0.35
+}
+fn default_min_lines() -> u32 {
+    2
+}
+fn default_hidden_ratio() -> f64 {
+    0.3
+}
+fn default_intro_ms() -> u64 {
+    10_000
+}
+
+impl ModeConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            ModeConfig::PassTheMic { players, switch_chance, .. } => {
+                if players.len() < 2 {
+                    return Err("Pass-the-mic needs at least two players".to_string());
+                }
+                if !(0.0..=1.0).contains(switch_chance) {
+                    return Err("Switch chance must be 0–1".to_string());
+                }
+            }
+            ModeConfig::BlindKaraoke { hidden_ratio } => {
+                if !(0.0..=1.0).contains(hidden_ratio) {
+                    return Err("Hidden ratio must be 0–1".to_string());
+                }
+            }
+            ModeConfig::GuessTheIntro { players, intro_ms } => {
+                if players.is_empty() {
+                    return Err("Guess-the-intro needs at least one player".to_string());
+                }
+                if !(1_000..=60_000).contains(intro_ms) {
+                    return Err("Intro length must be 1–60 s".to_string());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Something the screens must do at `at_ms` (song position).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "cue", rename_all = "snake_case")]
+pub enum Cue {
+    ActivePlayer { player: String, line_index: usize, at_ms: u64 },
+    HideLine { line_index: usize, at_ms: u64 },
+    ShowLine { line_index: usize, at_ms: u64 },
+    IntroOver { at_ms: u64 },
+    Finished,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    Running,
+    /// Guess-the-intro: the intro is over, guesses are still accepted.
+    Guessing,
+    Finished,
+}
+
+/// xorshift64*: small, seedable, good enough for party games.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed.max(1))
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.0 = x;
+        x.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Uniform in [0, 1).
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n.max(1) as u64) as usize
+    }
+}
+
+pub struct PartyEngine {
+    config: ModeConfig,
+    lines: Vec<LineSpan>,
+    /// Pass-the-mic: singer index per line.
+    singers: Vec<usize>,
+    /// Blind karaoke: hidden line indices.
+    hidden: HashSet<usize>,
+    phase: Phase,
+    /// Next line whose start cue is pending; `show_cursor` the same for
+    /// end-of-hidden-line cues.
+    cursor: usize,
+    show_cursor: usize,
+    last_position: u64,
+    /// After a rewind the current singer is announced even if unchanged.
+    announce_singer: bool,
+    intro_cued: bool,
+    scores: HashMap<String, i64>,
+    /// Guess-the-intro: players who already guessed.
+    guessed: HashSet<String>,
+}
+
+impl PartyEngine {
+    pub fn new(config: ModeConfig, mut lines: Vec<LineSpan>, seed: u64) -> Result<Self, String> {
+        config.validate()?;
+        lines.sort_by_key(|l| l.start_ms);
+        let mut rng = Rng::new(seed);
+
+        let mut singers = Vec::new();
+        let mut hidden = HashSet::new();
+        match &config {
+            ModeConfig::PassTheMic { players, switch_chance, min_lines_per_turn } => {
+                let mut current = rng.below(players.len());
+                let mut held = 0u32;
+                for _ in &lines {
+                    if held >= (*min_lines_per_turn).max(1) && rng.next_f64() < *switch_chance {
+                        // Anyone but the current singer
+                        current = (current + 1 + rng.below(players.len() - 1)) % players.len();
+                        held = 0;
+                    }
+                    singers.push(current);
+                    held += 1;
+                }
+            }
+            ModeConfig::BlindKaraoke { hidden_ratio } => {
+                // The first line always shows, so everyone finds the song
+                for index in 1..lines.len() {
+                    if rng.next_f64() < *hidden_ratio {
+                        hidden.insert(index);
+                    }
+                }
+            }
+            ModeConfig::GuessTheIntro { .. } => {}
+        }
+
+        let scores = match &config {
+            ModeConfig::PassTheMic { players, .. } | ModeConfig::GuessTheIntro { players, .. } => {
+                players.iter().map(|p| (p.clone(), 0)).collect()
+            }
+            ModeConfig::BlindKaraoke { .. } => HashMap::new(),
+        };
+
+        Ok(Self {
+            config,
+            lines,
+            singers,
+            hidden,
+            phase: Phase::Running,
+            cursor: 0,
+            show_cursor: 0,
+            last_position: 0,
+            announce_singer: false,
+            intro_cued: false,
+            scores,
+            guessed: HashSet::new(),
+        })
+    }
+
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    pub fn config(&self) -> &ModeConfig {
+        &self.config
+    }
+
+    pub fn scores(&self) -> &HashMap<String, i64> {
+        &self.scores
+    }
+
+    /// Cues due at playback position `position_ms`.
+    pub fn advance(&mut self, position_ms: u64) -> Vec<Cue> {
+        let mut cues = Vec::new();
+        if self.phase == Phase::Finished {
+            return cues;
+        }
+        if position_ms + REWIND_THRESHOLD_MS < self.last_position {
+            self.rewind(position_ms);
+        }
+        self.last_position = position_ms;
+        let horizon = position_ms + CUE_LEAD_MS;
+
+        if let ModeConfig::GuessTheIntro { intro_ms, .. } = self.config {
+            if !self.intro_cued && horizon >= intro_ms {
+                self.intro_cued = true;
+                self.phase = Phase::Guessing;
+                cues.push(Cue::IntroOver { at_ms: intro_ms });
+            }
+            return cues;
+        }
+
+        while self.cursor < self.lines.len() && self.lines[self.cursor].start_ms <= horizon {
+            let index = self.cursor;
+            let at_ms = self.lines[index].start_ms;
+            match &self.config {
+                ModeConfig::PassTheMic { players, .. } => {
+                    let changed = index == 0 || self.announce_singer || self.singers[index] != self.singers[index - 1];
+                    self.announce_singer = false;
+                    if changed {
+                        cues.push(Cue::ActivePlayer {
+                            player: players[self.singers[index]].clone(),
+                            line_index: index,
+                            at_ms,
+                        });
+                    }
+                }
+                ModeConfig::BlindKaraoke { .. } if self.hidden.contains(&index) => {
+                    cues.push(Cue::HideLine { line_index: index, at_ms });
+                }
+                _ => {}
+            }
+            self.cursor += 1;
+        }
+        while self.show_cursor < self.cursor && self.lines[self.show_cursor].end_ms <= horizon {
+            if self.hidden.contains(&self.show_cursor) {
+                cues.push(Cue::ShowLine {
+                    line_index: self.show_cursor,
+                    at_ms: self.lines[self.show_cursor].end_ms,
+                });
+            }
+            self.show_cursor += 1;
+        }
+
+        if self.show_cursor == self.lines.len() {
+            self.phase = Phase::Finished;
+            cues.push(Cue::Finished);
+        }
+        cues
+    }
+
+    /// After a seek back: re-announce from the line at `position_ms`.
+    fn rewind(&mut self, position_ms: u64) {
+        self.cursor = self.lines.iter().position(|l| l.end_ms > position_ms).unwrap_or(self.lines.len());
+        self.show_cursor = self.cursor;
+        self.announce_singer = true;
+    }
+
+    /// Who sings line `line_index` (pass-the-mic only).
+    pub fn player_for_line(&self, line_index: usize) -> Option<&str> {
+        let ModeConfig::PassTheMic { players, .. } = &self.config else { return None };
+        self.singers.get(line_index).map(|&i| players[i].as_str())
+    }
+
+    /// Credit a line's score to whoever held the mic for it. Returns the
+    /// player credited.
+    pub fn record_line_score(&mut self, line_index: usize, score: i64) -> Result<String, String> {
+        let player = self
+            .player_for_line(line_index)
+            .ok_or_else(|| format!("No singer for line {}", line_index))?
+            .to_string();
+        *self.scores.entry(player.clone()).or_insert(0) += score.max(0);
+        Ok(player)
+    }
+
+    /// Guess-the-intro: only a player's first guess counts; a right answer
+    /// scores more the sooner it comes. Returns the points awarded.
+    pub fn submit_guess(&mut self, player: &str, correct: bool, position_ms: u64) -> Result<i64, String> {
+        let ModeConfig::GuessTheIntro { intro_ms, .. } = self.config else {
+            return Err("Not a guessing round".to_string());
+        };
+        if self.phase == Phase::Finished {
+            return Err("The round is over".to_string());
+        }
+        if !self.scores.contains_key(player) {
+            return Err(format!("'{}' is not playing this round", player));
+        }
+        if !self.guessed.insert(player.to_string()) {
+            return Err(format!("'{}' already guessed", player));
+        }
+        let points = if correct {
+            // 1000 at the first note, 200 once the intro is over
+            let elapsed = position_ms.min(intro_ms) as f64 / intro_ms.max(1) as f64;
+            (1000.0 - 800.0 * elapsed).round() as i64
+        } else {
+            0
+        };
+        *self.scores.entry(player.to_string()).or_insert(0) += points;
+        Ok(points)
+    }
+
+    pub fn finish(&mut self) {
+        self.phase = Phase::Finished;
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(n: u64) -> Vec<LineSpan> {
+        (0..n).map(|i| LineSpan { start_ms: i * 4_000, end_ms: i * 4_000 + 3_000 }).collect()
+    }
+
+    fn pass_the_mic() -> ModeConfig {
+        ModeConfig::PassTheMic {
+            players: vec!["Ann".into(), "Bob".into(), "Cem".into()],
+            switch_chance: 0.5,
+            min_lines_per_turn: 2,
+        }
+    }
+
+    #[test]
+    fn pass_the_mic_holds_for_min_lines_and_credits_singer() {
+        let mut engine = PartyEngine::new(pass_the_mic(), lines(40), 7).unwrap();
+        let mut run = 0;
+        for i in 0..40 {
+            if i > 0 && engine.singers[i] != engine.singers[i - 1] {
+                assert!(run >= 2, "switched after {} lines", run);
+                run = 0;
+            }
+            run += 1;
+        }
+        let singer = engine.player_for_line(5).unwrap().to_string();
+        assert_eq!(engine.record_line_score(5, 300).unwrap(), singer);
+        assert_eq!(engine.scores()[&singer], 300);
+        // Same seed, same plan
+        assert_eq!(PartyEngine::new(pass_the_mic(), lines(40), 7).unwrap().singers, engine.singers);
+    }
+
+    #[test]
+    fn blind_cues_arrive_once_and_only_when_due() {
+        let mut engine = PartyEngine::new(ModeConfig::BlindKaraoke { hidden_ratio: 1.0 }, lines(3), 1).unwrap();
+        assert!(!engine.hidden.contains(&0));
+        assert!(engine.advance(0).is_empty());
+        // Line 1 starts at 4000; its cue is released CUE_LEAD_MS early
+        assert!(engine.advance(3_800).is_empty());
+        assert_eq!(engine.advance(3_900), vec![Cue::HideLine { line_index: 1, at_ms: 4_000 }]);
+        assert!(engine.advance(3_950).is_empty());
+        assert_eq!(engine.advance(6_900), vec![Cue::ShowLine { line_index: 1, at_ms: 7_000 }]);
+        let end = engine.advance(11_000);
+        assert!(end.contains(&Cue::ShowLine { line_index: 2, at_ms: 11_000 }));
+        assert_eq!(end.last(), Some(&Cue::Finished));
+    }
+
+    #[test]
+    fn only_first_guess_counts() {
+        let config = ModeConfig::GuessTheIntro { players: vec!["Ann".into()], intro_ms: 10_000 };
+        let mut engine = PartyEngine::new(config, Vec::new(), 3).unwrap();
+        assert_eq!(engine.submit_guess("Ann", true, 5_000).unwrap(), 600);
+        assert!(engine.submit_guess("Ann", true, 6_000).is_err());
+        assert!(engine.submit_guess("Zed", true, 6_000).is_err());
+        assert_eq!(engine.advance(9_900), vec![Cue::IntroOver { at_ms: 10_000 }]);
+        assert_eq!(engine.phase(), Phase::Guessing);
+    }
+}
@@ -0,0 +1,312 @@
+//! Party game modes: pass-the-mic, blind karaoke and guess-the-intro.
+//!
+//! `party_start` hands the round's lyric timings to a `PartyEngine` and
+//! starts a ticker that follows the playback position. Cues are published
+//! as `party://cue` (and on the event bus, so the audience window and
+//! phones see them too) shortly before they take effect; the frontend never
+//! holds the round's random choices. In guess-the-intro the ticker pauses
+//! playback when the intro is over and guesses are scored here, against
+//! the song id the round was started with. That id stays out of cues and
+//! out of `party_status` for anyone who cannot control playback until the
+//! song is revealed. A player's guess comes from the device that claimed
+//! them (`party_claim_player`); only the host may enter guesses for any
+//! player, e.g. when the room shouts its answers.
+
+pub mod engine;
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+use tokio_util::sync::CancellationToken;
+
+use crate::access::{require_webview, Capability, Origin, Principal};
+use crate::events::{publish, AppEvent};
+use crate::runtime::{sleep_or_cancel, TaskSupervisor};
+use engine::{Cue, LineSpan, ModeConfig, PartyEngine, Phase};
+
+pub const PARTY_CUE_EVENT: &str = "party://cue";
+/// Position polling interval; well inside `engine::CUE_LEAD_MS`.
+const TICK: Duration = Duration::from_millis(20);
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PartyCue {
+    /// `None` while guess-the-intro is still hiding the song.
+    pub song_id: Option<String>,
+    #[serde(flatten)]
+    pub cue: Cue,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PartyStatus {
+    /// `None` while guess-the-intro is still hiding the song.
+    pub song_id: Option<String>,
+    pub config: ModeConfig,
+    pub phase: Phase,
+    pub scores: HashMap<String, i64>,
+}
+
+struct Round {
+    song_id: String,
+    engine: PartyEngine,
+    cancel: CancellationToken,
+    /// Guess-the-intro: caller (`claim_key`) → the player it guesses as.
+    claims: HashMap<String, String>,
+}
+
+impl Round {
+    /// Whether the song id must not be shown yet.
+    fn hides_song(&self) -> bool {
+        matches!(self.engine.config(), ModeConfig::GuessTheIntro { .. }) && self.engine.phase() != Phase::Finished
+    }
+
+    fn visible_song_id(&self) -> Option<String> {
+        (!self.hides_song()).then(|| self.song_id.clone())
+    }
+}
+
+#[derive(Default)]
+pub struct PartyState {
+    round: Mutex<Option<Round>>,
+}
+
+impl PartyState {
+    fn with_round<T>(&self, f: impl FnOnce(&mut Round) -> Result<T, String>) -> Result<T, String> {
+        let mut round = self.round.lock().map_err(|e| e.to_string())?;
+        f(round.as_mut().ok_or("No party round running")?)
+    }
+}
+
+fn round_seed(song_id: &str) -> u64 {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0);
+    let input = format!("{}:{}:{}", song_id, nanos, std::process::id());
+    crate::library::scanner::fnv1a64(input.as_bytes())
+}
+
+/// Who is asking, as a key for `Round::claims`.
+fn claim_key(principal: &Principal) -> String {
+    match &principal.origin {
+        Origin::Webview { label } => format!("webview:{}", label),
+        Origin::Remote { client_id } => format!("remote:{}", client_id),
+    }
+}
+
+/// The round as `party_status` reports it; `trusted` callers see the song
+/// even while it is hidden.
+fn status_of(round: &Round, trusted: bool) -> PartyStatus {
+    PartyStatus {
+        song_id: if trusted { Some(round.song_id.clone()) } else { round.visible_song_id() },
+        config: round.engine.config().clone(),
+        phase: round.engine.phase(),
+        scores: round.engine.scores().clone(),
+    }
+}
+
+async fn run_ticker(app: AppHandle, token: CancellationToken) {
+    while sleep_or_cancel(&token, TICK).await {
+        let Ok(position) = crate::audio::commands::audio_get_position(app.clone()) else {
+            continue;
+        };
+        let (song_id, cues) = {
+            let state = app.state::<PartyState>();
+            let Ok(mut round) = state.round.lock() else { return };
+            let Some(round) = round.as_mut() else { return };
+            let cues = round.engine.advance(position);
+            (round.visible_song_id(), cues)
+        };
+        for cue in cues {
+            match cue {
+                Cue::IntroOver { .. } => {
+                    if let Err(e) = crate::audio::commands::pause(app.clone()) {
+                        tracing::error!("[party] Failed to pause after intro: {}", e);
+                    }
+                }
+                Cue::Finished => token.cancel(),
+                _ => {}
+            }
+            publish(&app, AppEvent::PartyCue(PartyCue { song_id: song_id.clone(), cue }));
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+/// Start a round for the song that is about to play. `lines` are the lyric
+/// line timings (ignored for guess-the-intro). Replaces any running round.
+#[tauri::command]
+pub fn party_start(
+    app: AppHandle,
+    webview: tauri::Webview,
+    song_id: String,
+    config: ModeConfig,
+    lines: Vec<LineSpan>,
+) -> Result<PartyStatus, String> {
+    require_webview(&webview, Capability::ControlPlayback)?;
+    let engine = PartyEngine::new(config, lines, round_seed(&song_id))?;
+    crate::telemetry::feature(&app, "party_mode");
+    let supervisor = app.state::<TaskSupervisor>();
+    let token = supervisor.token();
+    let round = Round { song_id, engine, cancel: token.clone(), claims: HashMap::new() };
+    let status = status_of(&round, true);
+
+    let state = app.state::<PartyState>();
+    if let Some(previous) = state.round.lock().map_err(|e| e.to_string())?.replace(round) {
+        previous.cancel.cancel();
+    }
+    let ticker_app = app.clone();
+    supervisor.spawn("party-modes", move |_| run_ticker(ticker_app, token));
+    tracing::info!("[party] Round started: {:?}", status.config);
+    Ok(status)
+}
+
+/// End the round; the final scores stay readable until the next start.
+#[tauri::command]
+pub fn party_stop(app: AppHandle, webview: tauri::Webview) -> Result<PartyStatus, String> {
+    require_webview(&webview, Capability::ControlPlayback)?;
+    app.state::<PartyState>().with_round(|round| {
+        round.cancel.cancel();
+        round.engine.finish();
+        Ok(status_of(round, true))
+    })
+}
+
+#[tauri::command]
+pub fn party_status(app: AppHandle, webview: tauri::Webview) -> Result<Option<PartyStatus>, String> {
+    let trusted = Principal::from_webview(&webview).role.allows(Capability::ControlPlayback);
+    let state = app.state::<PartyState>();
+    let round = state.round.lock().map_err(|e| e.to_string())?;
+    Ok(round.as_ref().map(|round| status_of(round, trusted)))
+}
+
+/// Pass-the-mic: credit a finished line's score to the singer holding the
+/// mic for it. Returns the player credited.
+#[tauri::command]
+pub fn party_record_line_score(
+    app: AppHandle,
+    webview: tauri::Webview,
+    line_index: usize,
+    score: i64,
+) -> Result<String, String> {
+    require_webview(&webview, Capability::ControlPlayback)?;
+    app.state::<PartyState>().with_round(|round| round.engine.record_line_score(line_index, score))
+}
+
+/// Guess-the-intro: guess as `player` from this device from now on. Each
+/// player can be claimed by one device, and each device claims one player.
+#[tauri::command]
+pub fn party_claim_player(app: AppHandle, webview: tauri::Webview, player: String) -> Result<(), String> {
+    require_webview(&webview, Capability::RequestSong)?;
+    let key = claim_key(&Principal::from_webview(&webview));
+    app.state::<PartyState>().with_round(|round| claim(round, key, player))
+}
+
+fn claim(round: &mut Round, key: String, player: String) -> Result<(), String> {
+    if !round.engine.scores().contains_key(&player) {
+        return Err(format!("'{}' is not playing this round", player));
+    }
+    if round.claims.iter().any(|(other, claimed)| *claimed == player && *other != key) {
+        return Err(format!("'{}' is already taken", player));
+    }
+    if round.claims.get(&key).is_some_and(|claimed| *claimed != player) {
+        return Err("This device already guesses for another player".to_string());
+    }
+    round.claims.insert(key, player);
+    Ok(())
+}
+
+/// Guess-the-intro: name a song. Returns the points awarded (0 for a wrong
+/// guess); only the first guess per player counts. The guess is credited
+/// to the player this device claimed; `player` is only honoured from the
+/// host.
+#[tauri::command]
+pub fn party_submit_guess(app: AppHandle, webview: tauri::Webview, player: Option<String>, song_id: String) -> Result<i64, String> {
+    require_webview(&webview, Capability::RequestSong)?;
+    let principal = Principal::from_webview(&webview);
+    let position = crate::audio::commands::audio_get_position(app.clone())?;
+    app.state::<PartyState>().with_round(|round| {
+        let player = guessing_player(round, &principal, player)?;
+        let correct = round.song_id == song_id;
+        round.engine.submit_guess(&player, correct, position)
+    })
+}
+
+fn guessing_player(round: &Round, principal: &Principal, named: Option<String>) -> Result<String, String> {
+    if principal.role.allows(Capability::ControlPlayback) {
+        if let Some(player) = named {
+            return Ok(player);
+        }
+    }
+    round.claims.get(&claim_key(principal)).cloned().ok_or_else(|| "Pick your player first".to_string())
+}
+
+/// Guess-the-intro: close guessing and reveal the song.
+#[tauri::command]
+pub fn party_reveal(app: AppHandle, webview: tauri::Webview) -> Result<PartyStatus, String> {
+    require_webview(&webview, Capability::ControlPlayback)?;
+    let status = app.state::<PartyState>().with_round(|round| {
+        if round.engine.phase() == Phase::Running {
+            return Err("The intro is still playing".to_string());
+        }
+        round.cancel.cancel();
+        round.engine.finish();
+        Ok(status_of(round, true))
+    })?;
+    publish(
+        &app,
+        AppEvent::PartyCue(PartyCue { song_id: status.song_id.clone(), cue: Cue::Finished }),
+    );
+    Ok(status)
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::access::Role;
+
+    fn intro_round() -> Round {
+        let config = ModeConfig::GuessTheIntro { players: vec!["Ann".into(), "Bob".into()], intro_ms: 10_000 };
+        Round {
+            song_id: "song-7".into(),
+            engine: PartyEngine::new(config, Vec::new(), 1).unwrap(),
+            cancel: CancellationToken::new(),
+            claims: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn intro_song_stays_hidden_until_the_reveal() {
+        let mut round = intro_round();
+        round.engine.advance(12_000);
+        assert_eq!(status_of(&round, false).song_id, None);
+        assert_eq!(status_of(&round, true).song_id.as_deref(), Some("song-7"));
+        round.engine.finish();
+        assert_eq!(status_of(&round, false).song_id.as_deref(), Some("song-7"));
+    }
+
+    #[test]
+    fn guests_guess_only_as_the_player_they_claimed() {
+        let mut round = intro_round();
+        let phone = Principal::remote("phone-1", Role::Guest);
+        let other = Principal::remote("phone-2", Role::Guest);
+        assert!(guessing_player(&round, &phone, Some("Bob".into())).is_err());
+
+        claim(&mut round, claim_key(&phone), "Ann".into()).unwrap();
+        assert!(claim(&mut round, claim_key(&other), "Ann".into()).is_err());
+        assert!(claim(&mut round, claim_key(&phone), "Bob".into()).is_err());
+        assert!(claim(&mut round, claim_key(&other), "Cem".into()).is_err());
+        assert_eq!(guessing_player(&round, &phone, Some("Bob".into())).unwrap(), "Ann");
+
+        let host = Principal { origin: Origin::Webview { label: "main".into() }, role: Role::Host };
+        assert_eq!(guessing_player(&round, &host, Some("Bob".into())).unwrap(), "Bob");
+    }
+}
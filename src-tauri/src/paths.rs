@@ -0,0 +1,271 @@
+//! Path handling shared by the scanner, importer and file commands.
+//!
+//! Windows limits classic paths to 260 characters (`MAX_PATH`); deep
+//! `Artist/Album/Song` trees routinely exceed that. File system calls go
+//! through `long_path`, which switches to the `\\?\` extended-length form,
+//! while paths shown to the user or stored in the database use
+//! `display_path` (no prefix).
+//!
+//! Names created by the app are run through `sanitize_file_name` so a
+//! library written on macOS/Linux stays usable on Windows: reserved device
+//! names (`CON`, `AUX`, `COM1`…), forbidden characters and trailing dots or
+//! spaces are replaced.
+//!
+//! Unicode: macOS file systems hand out decomposed names (NFD, `u` + `¨`)
+//! while Windows and most Linux tools use composed names (NFC, `ü`). Paths
+//! and names stored in the database are normalized to NFC (`nfc`,
+//! `normalize_path`) so the same song scanned on both systems gets the same
+//! id; `find_on_disk` maps a stored NFC path back to the actual on-disk
+//! spelling on byte-exact file systems (ext4, NTFS).
+
+use std::path::{Component, Path, PathBuf};
+
+use unicode_normalization::{is_nfc, UnicodeNormalization};
+
+/// Windows reserved device names (case-insensitive, with or without extension).
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Extended-length form of an absolute path for file system calls.
+/// No-op on other platforms, for relative paths and already-prefixed paths.
+#[cfg(target_os = "windows")]
+pub fn long_path(path: &Path) -> PathBuf {
+    let raw = path.to_string_lossy();
+    if raw.starts_with(r"\\?\") || !path.is_absolute() {
+        return path.to_path_buf();
+    }
+
+    // The `\\?\` form bypasses Win32 normalisation: separators must be
+    // backslashes and `.` components must be gone.
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other.as_os_str()),
+        }
+    }
+    let normalized = normalized.to_string_lossy().replace('/', r"\");
+
+    match normalized.strip_prefix(r"\\") {
+        Some(unc) => PathBuf::from(format!(r"\\?\UNC\{}", unc)),
+        None => PathBuf::from(format!(r"\\?\{}", normalized)),
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+pub fn long_path(path: &Path) -> PathBuf {
+    path.to_path_buf()
+}
+
+/// Drop the `\\?\` prefix (added by `long_path` or `canonicalize` on
+/// Windows) for display, storage and tools like Explorer that reject it.
+pub fn display_path(path: &Path) -> PathBuf {
+    let s = path.to_string_lossy();
+    if let Some(rest) = s.strip_prefix(r"\\?\UNC\") {
+        PathBuf::from(format!(r"\\{}", rest))
+    } else if let Some(rest) = s.strip_prefix(r"\\?\") {
+        PathBuf::from(rest)
+    } else {
+        path.to_path_buf()
+    }
+}
+
+/// NFC-normalize a string (cheap no-op for already-composed text).
+pub fn nfc(s: &str) -> String {
+    if is_nfc(s) {
+        s.to_string()
+    } else {
+        s.nfc().collect()
+    }
+}
+
+/// NFC-normalize every component of a path.
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let s = path.to_string_lossy();
+    if is_nfc(&s) {
+        return path.to_path_buf();
+    }
+    PathBuf::from(nfc(&s))
+}
+
+/// `path` for comparison: NFC, `/` separators, no `\\?\` prefix or
+/// trailing separator, and case-folded on Windows.
+pub fn path_key(path: &str) -> String {
+    let path = display_path(Path::new(path)).to_string_lossy().replace('\\', "/");
+    let mut key = String::with_capacity(path.len());
+    for c in nfc(&path).chars() {
+        if c == '/' && key.ends_with('/') && key.len() > 1 {
+            continue;
+        }
+        key.push(c);
+    }
+    if key.len() > 1 && key.ends_with('/') {
+        key.pop();
+    }
+    if cfg!(windows) {
+        key.to_lowercase()
+    } else {
+        key
+    }
+}
+
+/// Locate `path` on disk even if its components are stored in a different
+/// Unicode normalization than the file system uses. Components that do not
+/// exist verbatim are matched against directory entries by NFC equality.
+pub fn find_on_disk(path: &Path) -> Option<PathBuf> {
+    if long_path(path).exists() {
+        return Some(path.to_path_buf());
+    }
+
+    let mut current = PathBuf::new();
+    for component in path.components() {
+        let Component::Normal(name) = component else {
+            current.push(component.as_os_str());
+            continue;
+        };
+        let candidate = current.join(name);
+        if long_path(&candidate).exists() {
+            current = candidate;
+            continue;
+        }
+        let wanted = nfc(&name.to_string_lossy());
+        let dir = if current.as_os_str().is_empty() { Path::new(".") } else { current.as_path() };
+        let matched = std::fs::read_dir(long_path(dir))
+            .ok()?
+            .flatten()
+            .find(|entry| nfc(&entry.file_name().to_string_lossy()) == wanted)?;
+        current.push(matched.file_name());
+    }
+    Some(current)
+}
+
+/// Make a single file or folder name valid on every platform.
+pub fn sanitize_file_name(name: &str) -> String {
+    let mut out: String = nfc(name)
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+
+    // Windows silently strips trailing dots and spaces, so "Song." and
+    // "Song" would collide
+    let trimmed_len = out.trim_end_matches(['.', ' ']).len();
+    if trimmed_len < out.len() {
+        out.truncate(trimmed_len);
+        out.push('_');
+    }
+
+    let stem = out.split('.').next().unwrap_or("").trim_end();
+    if RESERVED_NAMES.iter().any(|r| stem.eq_ignore_ascii_case(r)) {
+        out.insert(0, '_');
+    }
+
+    if out.is_empty() {
+        return "_".to_string();
+    }
+    out
+}
+
+/// Sanitize the components of `path` that do not exist yet (the ones a
+/// write or mkdir would create). Existing components are left untouched so
+/// already-imported folders keep resolving.
+pub fn sanitize_new_components(path: &Path) -> PathBuf {
+    let components: Vec<Component> = path.components().collect();
+    let mut existing = components.len();
+    let mut probe = path.to_path_buf();
+    while existing > 0 && !long_path(&probe).exists() {
+        probe.pop();
+        existing -= 1;
+    }
+
+    let mut result: PathBuf = components[..existing].iter().map(|c| c.as_os_str()).collect();
+    for component in &components[existing..] {
+        match component {
+            Component::Normal(name) => result.push(sanitize_file_name(&name.to_string_lossy())),
+            other => result.push(other.as_os_str()),
+        }
+    }
+    result
+}
+
+/// A fresh, empty directory for a test, unique to `name` and this process.
+#[cfg(test)]
+pub(crate) fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("karaoke-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitizes_reserved_and_invalid_names() {
+        assert_eq!(sanitize_file_name("CON"), "_CON");
+        assert_eq!(sanitize_file_name("aux.txt"), "_aux.txt");
+        assert_eq!(sanitize_file_name("Com1 .mp3"), "_Com1 .mp3");
+        assert_eq!(sanitize_file_name("Console"), "Console");
+        assert_eq!(sanitize_file_name("Greatest Hits Vol. 2."), "Greatest Hits Vol. 2_");
+        assert_eq!(sanitize_file_name("AC/DC: Live?"), "AC_DC_ Live_");
+        assert_eq!(sanitize_file_name(""), "_");
+    }
+
+    #[test]
+    fn strips_extended_length_prefix() {
+        assert_eq!(display_path(Path::new(r"\\?\C:\Songs\a.txt")), PathBuf::from(r"C:\Songs\a.txt"));
+        assert_eq!(display_path(Path::new(r"\\?\UNC\nas\share")), PathBuf::from(r"\\nas\share"));
+        assert_eq!(display_path(Path::new("/home/a")), PathBuf::from("/home/a"));
+    }
+
+    #[test]
+    fn only_new_components_are_sanitized() {
+        let base = std::env::temp_dir();
+        let path = base.join("NUL").join("Song.");
+        assert_eq!(sanitize_new_components(&path), base.join("_NUL").join("Song_"));
+    }
+
+    #[test]
+    fn nfd_and_nfc_paths_normalize_equal() {
+        let nfd = Path::new("/Songs/Bjo\u{308}rk/Jo\u{301}ga.txt");
+        assert_eq!(normalize_path(nfd), PathBuf::from("/Songs/Björk/Jóga.txt"));
+        assert_eq!(nfc("Mu\u{308}ller"), "Müller");
+        assert_eq!(path_key(r"C:\Songs\A\"), if cfg!(windows) { "c:/songs/a" } else { "C:/Songs/A" });
+        assert_eq!(path_key("/Songs//Bjo\u{308}rk/"), "/Songs/Björk");
+    }
+
+    #[test]
+    fn finds_file_stored_in_other_normalization() {
+        let dir = crate::paths::test_dir("nfc");
+        let on_disk = dir.join("Bjo\u{308}rk.txt");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(&on_disk, "x").unwrap();
+
+        let found = find_on_disk(&dir.join("Björk.txt"));
+        std::fs::remove_dir_all(&dir).ok();
+        // Byte-exact file systems return the NFD entry; normalising ones (APFS)
+        // accept the NFC spelling directly
+        assert!(found.is_some());
+    }
+
+    #[cfg(target_os = "windows")]
+    #[test]
+    fn builds_extended_length_paths() {
+        assert_eq!(long_path(Path::new(r"C:\Songs\.\a.txt")), PathBuf::from(r"\\?\C:\Songs\a.txt"));
+        assert_eq!(long_path(Path::new(r"\\nas\share\a")), PathBuf::from(r"\\?\UNC\nas\share\a"));
+        assert_eq!(long_path(Path::new(r"\\?\C:\x")), PathBuf::from(r"\\?\C:\x"));
+    }
+}
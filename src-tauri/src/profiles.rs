@@ -0,0 +1,410 @@
+//! Singer profiles.
+//!
+//! A profile is a named singer with a preferred key, a mic volume, an
+//! avatar and the scores they have collected. They live in the `profiles`
+//! table the frontend already keeps its player profiles in; its
+//! `json_data` copy is kept in step so `db_load_profiles` sees the same
+//! singer. When `queue_next` puts someone on stage whose name matches a
+//! profile, their key is armed for the song they queued (filler music
+//! played before it keeps its own key) and the mic gain is set; singers
+//! without one get the defaults back.
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+use crate::access::{require_webview, Capability};
+use crate::audio::commands::AudioState;
+use crate::audio::mic::{MicState, MAX_GAIN};
+use crate::audio::pitch_shift::MAX_SEMITONES;
+use crate::db::DbState;
+use crate::queue::QueueEntry;
+use crate::scoring::ScoringResult;
+
+const MAX_NAME_LEN: usize = 64;
+const DEFAULT_COLOR: &str = "#6366f1";
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Profile {
+    pub id: String,
+    pub name: String,
+    /// Image URL or emoji, as the frontend stores it.
+    pub avatar: Option<String>,
+    pub color: String,
+    /// Semitones the singer's songs start in.
+    pub key_offset: i32,
+    /// Linear mic gain, 1 = unchanged.
+    pub mic_volume: f32,
+    pub total_score: i64,
+    pub games_played: i64,
+    /// Epoch ms.
+    pub created_at: i64,
+}
+
+/// Fields to set; `None` leaves a field as it is (`profile_create` uses
+/// the defaults). An empty `avatar` removes it.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ProfileFields {
+    pub name: Option<String>,
+    pub avatar: Option<String>,
+    pub color: Option<String>,
+    pub key_offset: Option<i32>,
+    pub mic_volume: Option<f32>,
+}
+
+impl ProfileFields {
+    /// Trimmed and range-checked copy.
+    fn validated(&self) -> Result<Self, String> {
+        let name = match self.name.as_deref().map(str::trim) {
+            Some("") => return Err("Profile name is empty".to_string()),
+            Some(name) if name.chars().count() > MAX_NAME_LEN => {
+                return Err(format!("Profile name is longer than {} characters", MAX_NAME_LEN))
+            }
+            name => name.map(String::from),
+        };
+        if let Some(key) = self.key_offset.filter(|k| k.abs() > MAX_SEMITONES) {
+            return Err(format!("Key offset {} is outside ±{} semitones", key, MAX_SEMITONES));
+        }
+        if let Some(volume) = self.mic_volume.filter(|v| !(0.0..=MAX_GAIN).contains(v)) {
+            return Err(format!("Mic volume {} is outside 0..={}", volume, MAX_GAIN));
+        }
+        Ok(Self {
+            name,
+            avatar: self.avatar.as_deref().map(|a| a.trim().to_string()),
+            color: self.color.as_deref().map(str::trim).filter(|c| !c.is_empty()).map(String::from),
+            key_offset: self.key_offset,
+            mic_volume: self.mic_volume,
+        })
+    }
+}
+
+const COLUMNS: &str = "id, name, avatar, color, key_offset, mic_volume, total_score, games_played, created_at";
+
+fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Profile> {
+    Ok(Profile {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        avatar: row.get(2)?,
+        color: row.get(3)?,
+        key_offset: row.get(4)?,
+        mic_volume: row.get::<_, f64>(5)? as f32,
+        total_score: row.get(6)?,
+        games_played: row.get(7)?,
+        created_at: row.get(8)?,
+    })
+}
+
+pub fn list(conn: &Connection) -> Result<Vec<Profile>, String> {
+    let mut stmt = conn
+        .prepare(&format!("SELECT {} FROM profiles ORDER BY name COLLATE NOCASE", COLUMNS))
+        .map_err(|e| format!("Failed to read profiles: {}", e))?;
+    let rows = stmt.query_map([], from_row).map_err(|e| format!("Failed to read profiles: {}", e))?;
+    rows.collect::<Result<_, _>>().map_err(|e| format!("Failed to read profiles: {}", e))
+}
+
+pub fn get(conn: &Connection, id: &str) -> Result<Option<Profile>, String> {
+    conn.query_row(&format!("SELECT {} FROM profiles WHERE id = ?1", COLUMNS), [id], from_row)
+        .optional()
+        .map_err(|e| format!("Failed to read profile: {}", e))
+}
+
+/// The profile named `singer`, ignoring case and spacing; the oldest one
+/// when several share the name.
+pub fn for_singer(conn: &Connection, singer: &str) -> Result<Option<Profile>, String> {
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM profiles WHERE name = trim(?1) COLLATE NOCASE ORDER BY created_at, id LIMIT 1",
+            COLUMNS
+        ),
+        [singer],
+        from_row,
+    )
+    .optional()
+    .map_err(|e| format!("Failed to read profile: {}", e))
+}
+
+pub fn create(conn: &Connection, fields: &ProfileFields) -> Result<Profile, String> {
+    let fields = fields.validated()?;
+    let name = fields.name.ok_or("Profile name is empty")?;
+    let now = crate::scheduler::now_ms();
+    let id = format!("profile-{}", now);
+    let avatar = fields.avatar.filter(|a| !a.is_empty());
+    let color = fields.color.unwrap_or_else(|| DEFAULT_COLOR.to_string());
+    let (key_offset, mic_volume) = (fields.key_offset.unwrap_or(0), fields.mic_volume.unwrap_or(1.0));
+    // The shape the frontend's PlayerProfile loads from
+    let json = serde_json::json!({
+        "id": id,
+        "name": name,
+        "avatar": avatar,
+        "color": color,
+        "totalScore": 0,
+        "gamesPlayed": 0,
+        "songsCompleted": 0,
+        "achievements": [],
+        "stats": {},
+        "createdAt": now,
+        "xp": 0,
+        "level": 1,
+        "keyOffset": key_offset,
+        "micVolume": mic_volume,
+    });
+    conn.execute(
+        "INSERT INTO profiles (id, name, avatar, color, key_offset, mic_volume, created_at, is_guest, json_data)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, 0, ?8)",
+        params![id, name, avatar, color, key_offset, mic_volume as f64, now, json.to_string()],
+    )
+    .map_err(|e| format!("Failed to create profile: {}", e))?;
+    get(conn, &id)?.ok_or_else(|| format!("Profile {} vanished", id))
+}
+
+/// Apply `fields` to profile `id`; `None` when there is no such profile.
+pub fn update(conn: &Connection, id: &str, fields: &ProfileFields) -> Result<Option<Profile>, String> {
+    let fields = fields.validated()?;
+    let avatar = fields.avatar.as_deref().map(|a| (!a.is_empty()).then_some(a));
+    let updated = conn
+        .execute(
+            "UPDATE profiles SET
+                 name       = COALESCE(?2, name),
+                 avatar     = CASE WHEN ?3 THEN ?4 ELSE avatar END,
+                 color      = COALESCE(?5, color),
+                 key_offset = COALESCE(?6, key_offset),
+                 mic_volume = COALESCE(?7, mic_volume)
+             WHERE id = ?1",
+            params![
+                id,
+                fields.name,
+                avatar.is_some(),
+                avatar.flatten(),
+                fields.color,
+                fields.key_offset,
+                fields.mic_volume.map(f64::from),
+            ],
+        )
+        .map_err(|e| format!("Failed to update profile: {}", e))?;
+    if updated == 0 {
+        return Ok(None);
+    }
+    sync_json(conn, id)?;
+    get(conn, id)
+}
+
+pub fn delete(conn: &Connection, id: &str) -> Result<bool, String> {
+    conn.execute("DELETE FROM profiles WHERE id = ?1", [id])
+        .map(|n| n > 0)
+        .map_err(|e| format!("Failed to delete profile: {}", e))
+}
+
+/// Add a scored performance to the cumulative totals of the profile that
+/// sang it, if `result` was sung by one.
+pub fn add_score(conn: &Connection, result: &ScoringResult) -> Result<(), String> {
+    conn.execute(
+        "UPDATE profiles SET total_score = total_score + ?2, games_played = games_played + 1 WHERE id = ?1",
+        params![result.player_id, result.score.round() as i64],
+    )
+    .map_err(|e| format!("Failed to update profile scores: {}", e))?;
+    sync_json(conn, &result.player_id)
+}
+
+/// Copy the columns this module owns into `json_data`.
+fn sync_json(conn: &Connection, id: &str) -> Result<(), String> {
+    conn.execute(
+        "UPDATE profiles SET json_data = json_set(json_data,
+             '$.name', name, '$.avatar', avatar, '$.color', color,
+             '$.keyOffset', key_offset, '$.micVolume', mic_volume,
+             '$.totalScore', total_score, '$.gamesPlayed', games_played)
+         WHERE id = ?1 AND json_valid(json_data)",
+        [id],
+    )
+    .map(|_| ())
+    .map_err(|e| format!("Failed to update profile: {}", e))
+}
+
+/// Audio and video files of library song `song_id`.
+fn song_files(conn: &Connection, song_id: &str) -> Result<Vec<String>, String> {
+    let row: Option<(String, [Option<String>; 2])> = conn
+        .query_row(
+            "SELECT folder_path, audio_file_name, video_file_name FROM songs WHERE id = ?1",
+            [song_id],
+            |row| Ok((row.get(0)?, [row.get(1)?, row.get(2)?])),
+        )
+        .optional()
+        .map_err(|e| format!("Failed to look up song {}: {}", song_id, e))?;
+    let Some((folder, names)) = row else { return Ok(Vec::new()) };
+    Ok(names
+        .into_iter()
+        .flatten()
+        .filter(|name| !name.is_empty())
+        .map(|name| std::path::Path::new(&folder).join(name).to_string_lossy().to_string())
+        .collect())
+}
+
+/// Arm the singer's settings for their turn, or the defaults when they
+/// have no profile.
+pub fn apply_for(app: &AppHandle, entry: &QueueEntry) -> Result<Option<Profile>, String> {
+    let (profile, files) = {
+        let db = app.state::<DbState>();
+        let conn = db.conn.lock().map_err(|e| e.to_string())?;
+        (for_singer(&conn, &entry.singer)?, song_files(&conn, &entry.song_id)?)
+    };
+    let (key_offset, mic_volume) = profile.as_ref().map_or((0, 1.0), |p| (p.key_offset, p.mic_volume));
+    if let Some(audio) = app.try_state::<AudioState>() {
+        audio.arm_song_key(&files, key_offset);
+    }
+    if let Some(mic) = app.try_state::<MicState>() {
+        mic.set_gain(mic_volume);
+    }
+    if let Some(profile) = &profile {
+        tracing::info!(
+            "[profiles] {} sings in {:+} semitones, mic at {:.0}%",
+            profile.name,
+            key_offset,
+            mic_volume * 100.0
+        );
+    }
+    Ok(profile)
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+#[tauri::command]
+pub fn profile_list(app: AppHandle) -> Result<Vec<Profile>, String> {
+    let db = app.state::<DbState>();
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    list(&conn)
+}
+
+#[tauri::command]
+pub fn profile_get(app: AppHandle, id: String) -> Result<Option<Profile>, String> {
+    let db = app.state::<DbState>();
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    get(&conn, &id)
+}
+
+#[tauri::command]
+pub fn profile_create(app: AppHandle, webview: tauri::Webview, fields: ProfileFields) -> Result<Profile, String> {
+    require_webview(&webview, Capability::ChangeSettings)?;
+    let db = app.state::<DbState>();
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let profile = create(&conn, &fields)?;
+    tracing::info!("[profiles] Created {} ({})", profile.name, profile.id);
+    Ok(profile)
+}
+
+/// `None` when there is no profile `id`.
+#[tauri::command]
+pub fn profile_update(
+    app: AppHandle,
+    webview: tauri::Webview,
+    id: String,
+    fields: ProfileFields,
+) -> Result<Option<Profile>, String> {
+    require_webview(&webview, Capability::ChangeSettings)?;
+    let db = app.state::<DbState>();
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    update(&conn, &id, &fields)
+}
+
+#[tauri::command]
+pub fn profile_delete(app: AppHandle, webview: tauri::Webview, id: String) -> Result<bool, String> {
+    require_webview(&webview, Capability::ChangeSettings)?;
+    let db = app.state::<DbState>();
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    delete(&conn, &id)
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn db() -> Connection {
+        crate::db::test_conn()
+    }
+
+    fn fields(name: &str) -> ProfileFields {
+        ProfileFields { name: Some(name.to_string()), ..Default::default() }
+    }
+
+    #[test]
+    fn creates_updates_and_finds_by_singer() {
+        let conn = db();
+        let alice = create(&conn, &ProfileFields { key_offset: Some(-3), ..fields(" Alice ") }).unwrap();
+        assert_eq!((alice.name.as_str(), alice.key_offset, alice.mic_volume), ("Alice", -3, 1.0));
+        assert_eq!(for_singer(&conn, "  ALICE").unwrap().map(|p| p.id), Some(alice.id.clone()));
+        assert_eq!(for_singer(&conn, "Bob").unwrap(), None);
+
+        let patch = ProfileFields { mic_volume: Some(1.5), avatar: Some("🎤".to_string()), ..Default::default() };
+        let updated = update(&conn, &alice.id, &patch).unwrap().unwrap();
+        assert_eq!((updated.key_offset, updated.mic_volume, updated.avatar.as_deref()), (-3, 1.5, Some("🎤")));
+        let cleared = update(&conn, &alice.id, &ProfileFields { avatar: Some(String::new()), ..Default::default() });
+        assert_eq!(cleared.unwrap().unwrap().avatar, None);
+        assert_eq!(update(&conn, "nobody", &patch).unwrap(), None);
+
+        // The frontend's copy follows
+        let json: String = conn
+            .query_row("SELECT json_data FROM profiles WHERE id = ?1", [&alice.id], |row| row.get(0))
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!((json["name"].as_str(), json["micVolume"].as_f64()), (Some("Alice"), Some(1.5)));
+
+        assert!(delete(&conn, &alice.id).unwrap());
+        assert!(list(&conn).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_out_of_range_settings() {
+        let conn = db();
+        assert!(create(&conn, &fields("   ")).is_err());
+        assert!(create(&conn, &ProfileFields { key_offset: Some(13), ..fields("Carol") }).is_err());
+        assert!(create(&conn, &ProfileFields { mic_volume: Some(-0.5), ..fields("Carol") }).is_err());
+    }
+
+    #[test]
+    fn finds_the_files_a_profile_key_is_armed_for() {
+        let conn = db();
+        conn.execute(
+            "INSERT INTO songs (id, title, artist, folder, folder_path, date_added, audio_file_name, video_file_name)
+             VALUES ('s1', 'S1', 'X', '', '/songs/s1', 0, 'song.mp3', '')",
+            [],
+        )
+        .unwrap();
+        let audio = std::path::Path::new("/songs/s1").join("song.mp3").to_string_lossy().to_string();
+        assert_eq!(song_files(&conn, "s1").unwrap(), vec![audio]);
+        assert!(song_files(&conn, "gone").unwrap().is_empty());
+    }
+
+    #[test]
+    fn accumulates_scores() {
+        let conn = db();
+        let dave = create(&conn, &fields("Dave")).unwrap();
+        let result = |score: f64| ScoringResult {
+            song_id: "s1".to_string(),
+            song_title: "S1".to_string(),
+            player_id: dave.id.clone(),
+            player_name: dave.name.clone(),
+            difficulty: "medium".to_string(),
+            score,
+            note_score: score,
+            golden_score: 0.0,
+            line_bonus: 0.0,
+            accuracy: 0.0,
+            rating: "",
+            perfect_notes: 0,
+            good_notes: 0,
+            miss_notes: 0,
+            max_combo: 0,
+            lines: Vec::new(),
+        };
+        add_score(&conn, &result(6000.4)).unwrap();
+        add_score(&conn, &result(4000.0)).unwrap();
+        let dave = get(&conn, &dave.id).unwrap().unwrap();
+        assert_eq!((dave.total_score, dave.games_played), (10000, 2));
+    }
+}
@@ -0,0 +1,417 @@
+//! Singer rotation queue, persisted in SQLite.
+//!
+//! The queue used to live only in the Next.js process and was lost with
+//! every server crash or restart; here it is a table (`queue_entries`) the
+//! frontend and remotes edit through commands. Changes are published as
+//! `queue://changed` with the whole queue.
+//!
+//! New entries are placed by fair rotation: the waiting list is read as
+//! rounds in which every singer appears at most once (the one on stage
+//! counts for the first round), and a new entry goes to the end of the
+//! first round its singer is not in yet. Someone adding five songs thus
+//! sings every round instead of five times in a row, and a newcomer waits
+//! at most one round. `queue_reorder` overrides the order by hand, and a
+//! prepared running order (`--autoplay-queue`) is appended as it is.
+
+use std::collections::HashSet;
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+use crate::access::{require_webview, Capability};
+use crate::audio::commands::AudioState;
+use crate::db::DbState;
+use crate::events::{publish, AppEvent};
+use crate::{history, profiles};
+
+pub const QUEUE_CHANGED_EVENT: &str = "queue://changed";
+pub const AUTOPLAY_EVENT: &str = "queue://autoplay";
+const MAX_SINGER_LEN: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryState {
+    Waiting,
+    /// On stage (set by `queue_next`).
+    Current,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueueEntry {
+    pub id: i64,
+    pub singer: String,
+    pub song_id: String,
+    /// `None` when the song has left the library since.
+    pub song_title: Option<String>,
+    pub song_artist: Option<String>,
+    pub state: EntryState,
+    /// Epoch ms.
+    pub added_at: i64,
+    pub started_at: Option<i64>,
+}
+
+/// Payload of `queue://changed`: the current entry (if any) first, then
+/// the waiting ones in order.
+#[derive(Debug, Clone, Serialize)]
+pub struct QueueSnapshot {
+    pub entries: Vec<QueueEntry>,
+}
+
+/// Payload of `queue://autoplay`: the app was started with a queue file
+/// and the frontend should start the first song (`queue_next`).
+#[derive(Debug, Clone, Serialize)]
+pub struct AutoplayRequest {
+    /// Songs queued from the file.
+    pub queued: usize,
+    pub source: String,
+}
+
+/// Singers are the same person regardless of case and spacing.
+fn singer_key(name: &str) -> String {
+    name.trim().to_lowercase()
+}
+
+/// Where a new entry by `singer` goes in `waiting` (singer keys in queue
+/// order), with `on_stage` the singer who sang last or is singing now.
+fn fair_index(waiting: &[String], on_stage: Option<&str>, singer: &str) -> usize {
+    let mut round: HashSet<&str> = on_stage.into_iter().collect();
+    for (i, name) in waiting.iter().enumerate() {
+        if round.contains(name.as_str()) {
+            // `name` repeats, so a new round starts here
+            if !round.contains(singer) {
+                return i;
+            }
+            round.clear();
+        }
+        round.insert(name);
+    }
+    waiting.len()
+}
+
+const ENTRY_SELECT: &str = "SELECT q.id, q.singer, q.song_id, s.title, s.artist, q.state, q.added_at, q.started_at
+     FROM queue_entries q LEFT JOIN songs s ON s.id = q.song_id";
+
+fn entry_from_row(row: &rusqlite::Row) -> rusqlite::Result<QueueEntry> {
+    let state: String = row.get(5)?;
+    Ok(QueueEntry {
+        id: row.get(0)?,
+        singer: row.get(1)?,
+        song_id: row.get(2)?,
+        song_title: row.get(3)?,
+        song_artist: row.get(4)?,
+        state: if state == "current" { EntryState::Current } else { EntryState::Waiting },
+        added_at: row.get(6)?,
+        started_at: row.get(7)?,
+    })
+}
+
+pub fn list(conn: &Connection) -> Result<Vec<QueueEntry>, String> {
+    let sql = format!(
+        "{} WHERE q.state IN ('current', 'waiting') ORDER BY q.state = 'waiting', q.position, q.id",
+        ENTRY_SELECT
+    );
+    let mut stmt = conn.prepare(&sql).map_err(|e| format!("Failed to read queue: {}", e))?;
+    let rows = stmt.query_map([], entry_from_row).map_err(|e| format!("Failed to read queue: {}", e))?;
+    rows.collect::<Result<_, _>>().map_err(|e| format!("Failed to read queue: {}", e))
+}
+
+fn entry(conn: &Connection, id: i64) -> Result<QueueEntry, String> {
+    conn.query_row(&format!("{} WHERE q.id = ?1", ENTRY_SELECT), [id], entry_from_row)
+        .map_err(|e| format!("Queue entry {} not found: {}", id, e))
+}
+
+/// Waiting entries as `(id, singer key)`, in order.
+fn waiting(conn: &Connection) -> Result<Vec<(i64, String)>, String> {
+    let mut stmt = conn
+        .prepare("SELECT id, singer FROM queue_entries WHERE state = 'waiting' ORDER BY position, id")
+        .map_err(|e| format!("Failed to read queue: {}", e))?;
+    let rows = stmt
+        .query_map([], |row| Ok((row.get::<_, i64>(0)?, singer_key(&row.get::<_, String>(1)?))))
+        .map_err(|e| format!("Failed to read queue: {}", e))?;
+    rows.collect::<Result<_, _>>().map_err(|e| format!("Failed to read queue: {}", e))
+}
+
+/// Write positions 0.. in the order of `ids`.
+fn renumber(conn: &Connection, ids: &[i64]) -> Result<(), String> {
+    let mut stmt = conn
+        .prepare("UPDATE queue_entries SET position = ?1 WHERE id = ?2")
+        .map_err(|e| format!("Failed to reorder queue: {}", e))?;
+    for (position, id) in ids.iter().enumerate() {
+        stmt.execute(params![position as i64, id]).map_err(|e| format!("Failed to reorder queue: {}", e))?;
+    }
+    Ok(())
+}
+
+pub fn add(conn: &mut Connection, singer: &str, song_id: &str) -> Result<QueueEntry, String> {
+    let singer = singer.trim();
+    if singer.is_empty() || singer.chars().count() > MAX_SINGER_LEN {
+        return Err(format!("Singer name must be 1–{} characters", MAX_SINGER_LEN));
+    }
+    let known: bool = conn
+        .query_row("SELECT EXISTS(SELECT 1 FROM songs WHERE id = ?1)", [song_id], |row| row.get(0))
+        .map_err(|e| format!("Failed to look up song: {}", e))?;
+    if !known {
+        return Err(format!("Song {} is not in the library", song_id));
+    }
+
+    let tx = conn.transaction().map_err(|e| format!("Transaction failed: {}", e))?;
+    let on_stage: Option<String> = tx
+        .query_row(
+            "SELECT singer FROM queue_entries WHERE state IN ('current', 'done') ORDER BY started_at DESC LIMIT 1",
+            [],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| format!("Failed to read queue: {}", e))?;
+    let mut order = waiting(&tx)?;
+    let keys: Vec<String> = order.iter().map(|(_, key)| key.clone()).collect();
+    let index = fair_index(&keys, on_stage.as_deref().map(singer_key).as_deref(), &singer_key(singer));
+
+    tx.execute(
+        "INSERT INTO queue_entries (singer, song_id, state, added_at) VALUES (?1, ?2, 'waiting', ?3)",
+        params![singer, song_id, crate::scheduler::now_ms()],
+    )
+    .map_err(|e| format!("Failed to add to queue: {}", e))?;
+    let id = tx.last_insert_rowid();
+    order.insert(index, (id, String::new()));
+    renumber(&tx, &order.iter().map(|(id, _)| *id).collect::<Vec<_>>())?;
+    let added = entry(&tx, id)?;
+    tx.commit().map_err(|e| format!("Commit failed: {}", e))?;
+    Ok(added)
+}
+
+/// Remove a waiting or current entry; false if there was none.
+pub fn remove(conn: &Connection, id: i64) -> Result<bool, String> {
+    conn.execute("DELETE FROM queue_entries WHERE id = ?1 AND state != 'done'", [id])
+        .map(|n| n > 0)
+        .map_err(|e| format!("Failed to remove queue entry: {}", e))
+}
+
+/// Put the waiting entries in the order of `ids`, which must name each of
+/// them exactly once.
+pub fn reorder(conn: &mut Connection, ids: &[i64]) -> Result<(), String> {
+    let tx = conn.transaction().map_err(|e| format!("Transaction failed: {}", e))?;
+    let mut expected: Vec<i64> = waiting(&tx)?.into_iter().map(|(id, _)| id).collect();
+    let mut given = ids.to_vec();
+    expected.sort_unstable();
+    given.sort_unstable();
+    if expected != given {
+        return Err("The new order must list every waiting entry once (the queue changed meanwhile?)".to_string());
+    }
+    renumber(&tx, ids)?;
+    tx.commit().map_err(|e| format!("Commit failed: {}", e))
+}
+
+/// Move waiting entry `id` to the front, to be sung next.
+pub fn move_to_front(conn: &mut Connection, id: i64) -> Result<(), String> {
+    let mut ids: Vec<i64> = waiting(conn)?.into_iter().map(|(id, _)| id).filter(|&other| other != id).collect();
+    ids.insert(0, id);
+    reorder(conn, &ids)
+}
+
+/// Add `songs` (singer, song id) behind everything waiting, in their
+/// order rather than by rotation. Songs that cannot be queued are
+/// skipped; returns the entries added and why the others were not.
+pub fn append(conn: &mut Connection, songs: &[(String, String)]) -> Result<(Vec<QueueEntry>, Vec<String>), String> {
+    let mut added = Vec::new();
+    let mut errors = Vec::new();
+    for (singer, song_id) in songs {
+        match add(conn, singer, song_id) {
+            Ok(entry) => added.push(entry),
+            Err(e) => errors.push(e),
+        }
+    }
+    let ids: Vec<i64> = added.iter().map(|e| e.id).collect();
+    let mut order: Vec<i64> = waiting(conn)?.into_iter().map(|(id, _)| id).filter(|id| !ids.contains(id)).collect();
+    order.extend(&ids);
+    reorder(conn, &order)?;
+    Ok((added, errors))
+}
+
+/// Finish the current entry and put the next waiting one on stage.
+pub fn advance(conn: &mut Connection) -> Result<Option<QueueEntry>, String> {
+    let tx = conn.transaction().map_err(|e| format!("Transaction failed: {}", e))?;
+    // The last finished entry is kept only to seat the rotation
+    tx.execute_batch(
+        "DELETE FROM queue_entries WHERE state = 'done';
+         UPDATE queue_entries SET state = 'done' WHERE state = 'current';",
+    )
+    .map_err(|e| format!("Failed to advance queue: {}", e))?;
+    let Some((id, _)) = waiting(&tx)?.into_iter().next() else {
+        tx.commit().map_err(|e| format!("Commit failed: {}", e))?;
+        return Ok(None);
+    };
+    tx.execute(
+        "UPDATE queue_entries SET state = 'current', started_at = ?1 WHERE id = ?2",
+        params![crate::scheduler::now_ms(), id],
+    )
+    .map_err(|e| format!("Failed to advance queue: {}", e))?;
+    let next = entry(&tx, id)?;
+    tx.commit().map_err(|e| format!("Commit failed: {}", e))?;
+    Ok(Some(next))
+}
+
+/// Run `change` on the database, then announce the new queue.
+fn change<T>(app: &AppHandle, change: impl FnOnce(&mut Connection) -> Result<T, String>) -> Result<T, String> {
+    let db = app.state::<DbState>();
+    let (result, entries) = {
+        let mut conn = db.conn.lock().map_err(|e| e.to_string())?;
+        let result = change(&mut conn)?;
+        (result, list(&conn)?)
+    };
+    publish(app, AppEvent::QueueChanged(QueueSnapshot { entries }));
+    Ok(result)
+}
+
+/// `queue_add` for requests that do not come from a webview (deep links);
+/// `next` puts the entry first instead of at its fair place.
+pub fn enqueue(app: &AppHandle, singer: &str, song_id: &str, next: bool) -> Result<QueueEntry, String> {
+    let entry = change(app, |conn| {
+        let entry = add(conn, singer, song_id)?;
+        if next {
+            move_to_front(conn, entry.id)?;
+        }
+        Ok(entry)
+    })?;
+    tracing::info!("[queue] {} queued {}{}", entry.singer, entry.song_id, if next { " to sing next" } else { "" });
+    Ok(entry)
+}
+
+/// `append` for a running order read at startup.
+pub fn enqueue_all(app: &AppHandle, songs: &[(String, String)]) -> Result<(Vec<QueueEntry>, Vec<String>), String> {
+    let (added, errors) = change(app, |conn| append(conn, songs))?;
+    tracing::info!("[queue] Appended {} of {} song(s)", added.len(), songs.len());
+    Ok((added, errors))
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+#[tauri::command]
+pub fn queue_list(app: AppHandle) -> Result<Vec<QueueEntry>, String> {
+    let db = app.state::<DbState>();
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    list(&conn)
+}
+
+/// Add `song_id` for `singer` at their fair place in the rotation.
+#[tauri::command]
+pub fn queue_add(app: AppHandle, webview: tauri::Webview, singer: String, song_id: String) -> Result<QueueEntry, String> {
+    require_webview(&webview, Capability::RequestSong)?;
+    let entry = change(&app, |conn| add(conn, &singer, &song_id))?;
+    tracing::info!("[queue] {} queued {}", entry.singer, entry.song_id);
+    Ok(entry)
+}
+
+#[tauri::command]
+pub fn queue_remove(app: AppHandle, webview: tauri::Webview, id: i64) -> Result<bool, String> {
+    require_webview(&webview, Capability::ManageQueue)?;
+    change(&app, |conn| remove(conn, id))
+}
+
+/// Reorder the waiting entries by hand; `ids` lists all of them.
+#[tauri::command]
+pub fn queue_reorder(app: AppHandle, webview: tauri::Webview, ids: Vec<i64>) -> Result<(), String> {
+    require_webview(&webview, Capability::ManageQueue)?;
+    change(&app, |conn| reorder(conn, &ids))
+}
+
+/// Move on to the next singer; `None` when the queue is empty. Closes the
+/// finished performance in the history, opens the new one and applies the
+/// singer's profile.
+#[tauri::command]
+pub fn queue_next(app: AppHandle, webview: tauri::Webview) -> Result<Option<QueueEntry>, String> {
+    require_webview(&webview, Capability::ControlPlayback)?;
+    // Read before the next song is opened, which resets the key
+    let key_offset = app.try_state::<AudioState>().map(|audio| audio.key()).unwrap_or(0);
+    let next = change(&app, |conn| {
+        history::finish(conn, key_offset)?;
+        let next = advance(conn)?;
+        if let Some(entry) = &next {
+            history::start(conn, entry)?;
+        }
+        Ok(next)
+    })?;
+    match &next {
+        Some(entry) => {
+            tracing::info!("[queue] Up next: {} with {}", entry.singer, entry.song_id);
+            // The queue has moved on already; a profile that cannot be
+            // applied must not make the turn look failed
+            if let Err(e) = profiles::apply_for(&app, entry) {
+                tracing::warn!("[profiles] {}", e);
+            }
+        }
+        None => tracing::info!("[queue] Queue is empty"),
+    }
+    Ok(next)
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn new_entries_go_to_the_first_round_without_the_singer() {
+        let queue = keys(&["bob", "carol", "bob", "carol"]);
+        // A newcomer waits one round at most
+        assert_eq!(fair_index(&queue, None, "dave"), 2);
+        // Bob is in both rounds, so he goes last
+        assert_eq!(fair_index(&queue, None, "bob"), 4);
+        // Alice is on stage, which fills her slot in the first round
+        assert_eq!(fair_index(&keys(&["bob", "carol"]), Some("alice"), "alice"), 2);
+        assert_eq!(fair_index(&keys(&["bob", "carol"]), Some("alice"), "dave"), 2);
+        assert_eq!(fair_index(&[], None, "dave"), 0);
+    }
+
+    #[test]
+    fn rotates_and_advances() {
+        let mut conn = crate::db::test_conn();
+        let insert = "INSERT INTO songs (id, title, artist, folder, folder_path, date_added) VALUES (?1, ?2, 'X', '', '', 0)";
+        for id in ["s1", "s2", "s3", "s4"] {
+            conn.execute(insert, [id, id]).unwrap();
+        }
+
+        add(&mut conn, "Alice", "s1").unwrap();
+        add(&mut conn, "alice ", "s2").unwrap();
+        add(&mut conn, "Bob", "s3").unwrap();
+        assert!(add(&mut conn, "Bob", "nope").is_err());
+        let order = |conn: &Connection| list(conn).unwrap().into_iter().map(|e| e.song_id).collect::<Vec<_>>();
+        assert_eq!(order(&conn), vec!["s1", "s3", "s2"]);
+
+        let first = advance(&mut conn).unwrap().unwrap();
+        assert_eq!((first.song_id.as_str(), first.state), ("s1", EntryState::Current));
+        // Alice is on stage: Carol gets in ahead of Alice's second song
+        add(&mut conn, "Carol", "s4").unwrap();
+        assert_eq!(order(&conn), vec!["s1", "s3", "s4", "s2"]);
+
+        let waiting_ids: Vec<i64> = list(&conn).unwrap().iter().skip(1).map(|e| e.id).rev().collect();
+        reorder(&mut conn, &waiting_ids).unwrap();
+        assert_eq!(order(&conn), vec!["s1", "s2", "s4", "s3"]);
+        assert!(reorder(&mut conn, &waiting_ids[1..]).is_err());
+        move_to_front(&mut conn, waiting_ids[2]).unwrap();
+        assert_eq!(order(&conn), vec!["s1", "s3", "s2", "s4"]);
+
+        // A prepared running order goes behind, as it is
+        let songs = [("Bob", "s1"), ("Bob", "s2"), ("Dave", "nope")].map(|(singer, id)| (singer.to_string(), id.to_string()));
+        let (added, errors) = append(&mut conn, &songs).unwrap();
+        assert_eq!((added.len(), errors.len()), (2, 1));
+        assert_eq!(order(&conn), vec!["s1", "s3", "s2", "s4", "s1", "s2"]);
+
+        for _ in 0..5 {
+            advance(&mut conn).unwrap();
+        }
+        assert_eq!(advance(&mut conn).unwrap(), None);
+        assert!(list(&conn).unwrap().is_empty());
+    }
+}
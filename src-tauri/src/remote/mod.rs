@@ -0,0 +1,396 @@
+//! WebSocket bridge for phone remotes and remote lyric displays.
+//!
+//! A small WebSocket server next to the Node one, so latency-critical sync
+//! does not take the detour through Next.js: every connected client gets
+//! the playback position, track changes, lyric lines and words, queue
+//! changes and score events the moment they are published on the event bus, as the same
+//! `EventEnvelope` JSON `app://event` carries. Clients send control
+//! messages back; playback ones act on the native player, the rest are
+//! published as `remote://command` for the frontend.
+//!
+//! Clients connect to `ws://<lan ip>:<port>/`; the port is the
+//! `remote_ws_port` setting (default 47822); without LAN access (see
+//! `server::security`) only local clients are accepted. With
+//! `?token=<guest token>` (the remote QR code) a client is a guest and only
+//! follows along; with `?token=<access token>` (the URL `remote_status`
+//! shows the KJ) it is an operator and may control playback. Local clients
+//! may also connect without a token, as guests; anything else is refused. A
+//! client that has not finished the handshake within `HANDSHAKE_TIMEOUT` is
+//! dropped, so idle sockets cannot hold every slot. Messages, one JSON
+//! object each:
+//!   client → `{"type":"toggle_playback"}`, `pause`, `resume`,
+//!            `{"type":"seek","position_ms":…}`, `{"type":"set_key","semitones":…}`,
+//!            `key_up`, `key_down`, `next_song`, `ping`,
+//!            `{"type":"clock","sent_at":…}` (epoch ms)
+//!   server → `{"type":"hello","version":…,"playing":…,"position_ms":…}` once,
+//!            `{"type":"ack"}` / `{"type":"error","reason":"…"}` / `{"type":"pong"}`
+//!            / `{"type":"clock","sent_at":…,"received_at":…}` per message,
+//!            and event envelopes (`{"topic":…,"type":…,"data":…}`).
+//!
+//! `clock` lets a client estimate the offset between the two clocks, which
+//! is how another instance in a second room mirrors playback (`multiroom`).
+
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicU16, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast::error::RecvError;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::{StatusCode, Uri};
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_util::sync::CancellationToken;
+
+use crate::access::{require_webview, Capability, Principal, Role};
+use crate::audio::commands::AudioState;
+use crate::db::DbState;
+use crate::events::{publish, AppEvent, EventBus, EVENT_SCHEMA_VERSION};
+use crate::runtime::TaskSupervisor;
+use crate::server::{discovery, security};
+
+pub const REMOTE_COMMAND_EVENT: &str = "remote://command";
+const PORT_SETTING: &str = "remote_ws_port";
+pub const DEFAULT_PORT: u16 = 47_822;
+const MAX_CLIENTS: usize = 32;
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Control messages are tiny; anything bigger is not a remote.
+const MAX_MESSAGE: usize = 4 * 1024;
+
+/// Payload of `remote://command`: a phone asked for something the
+/// frontend carries out.
+#[derive(Debug, Clone, Serialize)]
+pub struct RemoteCommand {
+    pub action: String,
+    /// Address of the phone.
+    pub client: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ControlMessage {
+    TogglePlayback,
+    Pause,
+    Resume,
+    Seek { position_ms: u64 },
+    SetKey { semitones: i32 },
+    KeyUp,
+    KeyDown,
+    NextSong,
+    Ping,
+    Clock { sent_at: i64 },
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Reply {
+    Hello { version: u32, playing: bool, position_ms: u64 },
+    Ack,
+    Pong,
+    Clock { sent_at: i64, received_at: i64 },
+    Error { reason: String },
+}
+
+/// Managed state: the listening port (0 while stopped) and client count.
+#[derive(Default)]
+pub struct RemoteState {
+    port: AtomicU16,
+    clients: Arc<AtomicUsize>,
+}
+
+/// Decrements the client count when a connection ends.
+struct ClientSlot(Arc<AtomicUsize>);
+
+impl Drop for ClientSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Whether a client gets the event: what a remote display needs to follow,
+/// not the high-rate internals (pitch frames, mic levels).
+fn forwarded(event: &AppEvent) -> bool {
+    matches!(
+        event,
+        AppEvent::AudioPosition(_)
+            | AppEvent::AudioTrackChanged(_)
+            | AppEvent::LyricsLine(_)
+            | AppEvent::LyricsWord(_)
+            | AppEvent::QueueChanged(_)
+            | AppEvent::ScoringLine(_)
+            | AppEvent::ScoringResult(_)
+            | AppEvent::RoomCue(_)
+    )
+}
+
+/// Role for the upgrade request: operator with the `operator` token, guest
+/// with the `guest` token or without one from this machine (`local`),
+/// `None` (refused) otherwise.
+fn role_for(uri: &Uri, operator: &str, guest: &str, local: bool) -> Option<Role> {
+    let token = uri
+        .query()
+        .into_iter()
+        .flat_map(|query| query.split('&'))
+        .filter_map(|pair| pair.split_once('='))
+        .find_map(|(key, value)| (key == "token").then_some(value));
+    match token {
+        None if local => Some(Role::Guest),
+        None => None,
+        Some(token) if !operator.is_empty() && token == operator => Some(Role::Operator),
+        Some(token) if !guest.is_empty() && token == guest => Some(Role::Guest),
+        Some(_) => None,
+    }
+}
+
+pub(crate) fn configured_port(app: &AppHandle) -> u16 {
+    app.try_state::<DbState>()
+        .and_then(|db| {
+            let conn = db.conn.lock().ok()?;
+            crate::scheduler::read_setting(&conn, PORT_SETTING)?.trim().parse().ok()
+        })
+        .unwrap_or(DEFAULT_PORT)
+}
+
+fn handle(app: &AppHandle, principal: &Principal, client: SocketAddr, message: ControlMessage) -> Reply {
+    match message {
+        ControlMessage::Ping => return Reply::Pong,
+        ControlMessage::Clock { sent_at } => return Reply::Clock { sent_at, received_at: crate::multiroom::now_ms() },
+        _ => {}
+    }
+    if let Err(reason) = principal.require(Capability::ControlPlayback) {
+        return Reply::Error { reason };
+    }
+    let audio = app.state::<AudioState>();
+    let result = match message {
+        ControlMessage::Ping | ControlMessage::Clock { .. } => Ok(()),
+        ControlMessage::TogglePlayback => audio.toggle_playback().map(|_| ()),
+        ControlMessage::Pause => audio.pause(),
+        ControlMessage::Resume => audio.resume(),
+        ControlMessage::Seek { position_ms } => audio.seek(position_ms),
+        ControlMessage::SetKey { semitones } => {
+            audio.set_key(semitones);
+            Ok(())
+        }
+        ControlMessage::KeyUp => {
+            audio.set_key(audio.key() + 1);
+            Ok(())
+        }
+        ControlMessage::KeyDown => {
+            audio.set_key(audio.key() - 1);
+            Ok(())
+        }
+        ControlMessage::NextSong => {
+            let command = RemoteCommand { action: "next_song".to_string(), client: client.ip().to_string() };
+            publish(app, AppEvent::RemoteCommand(command));
+            Ok(())
+        }
+    };
+    match result {
+        Ok(()) => Reply::Ack,
+        Err(reason) => Reply::Error { reason },
+    }
+}
+
+fn text(value: &impl Serialize) -> Option<Message> {
+    serde_json::to_string(value).ok().map(Message::Text)
+}
+
+async fn serve_client(app: AppHandle, stream: TcpStream, client: SocketAddr, cancel: CancellationToken) -> Result<(), String> {
+    let mut role = None;
+    let accept = |request: &Request, response: Response| -> Result<Response, ErrorResponse> {
+        role = role_for(request.uri(), &security::access_token(), &security::guest_token(), client.ip().is_loopback());
+        if role.is_some() {
+            return Ok(response);
+        }
+        let mut denied = ErrorResponse::new(Some("Invalid token".to_string()));
+        *denied.status_mut() = StatusCode::UNAUTHORIZED;
+        Err(denied)
+    };
+    let config = WebSocketConfig {
+        max_message_size: Some(MAX_MESSAGE),
+        max_frame_size: Some(MAX_MESSAGE),
+        ..Default::default()
+    };
+    let handshake = tokio_tungstenite::accept_hdr_async_with_config(stream, accept, Some(config));
+    let socket = tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake)
+        .await
+        .map_err(|_| format!("Handshake with {} timed out", client))?
+        .map_err(|e| format!("Handshake with {} failed: {}", client, e))?;
+    let principal = Principal::remote(client.to_string(), role.unwrap_or(Role::Guest));
+    tracing::info!("[remote] {} connected as {:?}", client, principal.role);
+    let (mut sink, mut source) = socket.split();
+    let mut events = app.state::<EventBus>().subscribe();
+
+    let hello = {
+        let audio = app.state::<AudioState>();
+        Reply::Hello { version: EVENT_SCHEMA_VERSION, playing: audio.is_playing(), position_ms: audio.position_ms() }
+    };
+    if let Some(message) = text(&hello) {
+        sink.send(message).await.map_err(|e| e.to_string())?;
+    }
+    loop {
+        let outgoing = tokio::select! {
+            _ = cancel.cancelled() => break,
+            received = events.recv() => match received {
+                Ok(envelope) if forwarded(&envelope.event) => text(&*envelope),
+                Ok(_) => None,
+                Err(RecvError::Lagged(missed)) => {
+                    tracing::debug!("[remote] {} missed {} events", client, missed);
+                    None
+                }
+                Err(RecvError::Closed) => break,
+            },
+            incoming = source.next() => match incoming {
+                Some(Ok(Message::Text(body))) => {
+                    let reply = match serde_json::from_str::<ControlMessage>(&body) {
+                        Ok(message) => handle(&app, &principal, client, message),
+                        Err(e) => Reply::Error { reason: format!("Invalid message: {}", e) },
+                    };
+                    text(&reply)
+                }
+                Some(Ok(Message::Close(_))) | None => break,
+                // Pings are answered by tungstenite itself
+                Some(Ok(_)) => None,
+                Some(Err(e)) => return Err(format!("{}: {}", client, e)),
+            },
+        };
+        if let Some(message) = outgoing {
+            if sink.send(message).await.is_err() {
+                break;
+            }
+        }
+    }
+    let _ = sink.close().await;
+    tracing::info!("[remote] {} disconnected", client);
+    Ok(())
+}
+
+/// Port the server listens on, while it runs.
+pub(crate) fn listening_port(app: &AppHandle) -> Option<u16> {
+    let state = app.try_state::<RemoteState>()?;
+    Some(state.port.load(Ordering::Relaxed)).filter(|&p| p != 0)
+}
+
+async fn run(app: AppHandle, listener: TcpListener, cancel: CancellationToken) {
+    let clients = app.state::<RemoteState>().clients.clone();
+    let supervisor = app.state::<TaskSupervisor>();
+    loop {
+        let accepted = tokio::select! {
+            _ = cancel.cancelled() => break,
+            accepted = listener.accept() => accepted,
+        };
+        let (stream, client) = match accepted {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                tracing::error!("[remote] Accept failed: {}", e);
+                continue;
+            }
+        };
+        if !security::lan_access() && !client.ip().is_loopback() {
+            tracing::warn!("[remote] Refused {}: LAN access is off", client);
+            continue;
+        }
+        if clients.fetch_add(1, Ordering::Relaxed) >= MAX_CLIENTS {
+            clients.fetch_sub(1, Ordering::Relaxed);
+            tracing::warn!("[remote] Refused {}: {} clients connected", client, MAX_CLIENTS);
+            continue;
+        }
+        let slot = ClientSlot(clients.clone());
+        let _ = stream.set_nodelay(true);
+        let app = app.clone();
+        supervisor.spawn("remote-client", move |token| async move {
+            let _slot = slot;
+            if let Err(e) = serve_client(app, stream, client, token).await {
+                tracing::warn!("[remote] {}", e);
+            }
+        });
+    }
+    app.state::<RemoteState>().port.store(0, Ordering::Relaxed);
+    tracing::info!("[remote] Stopped");
+}
+
+/// Start the WebSocket server on the configured port. Call once the event
+/// bus and database are managed; a taken port is logged and skipped.
+pub fn spawn_server(app: AppHandle) {
+    let supervisor = app.state::<TaskSupervisor>();
+    supervisor.spawn("remote-ws", move |token| async move {
+        let port = configured_port(&app);
+        let listener = match TcpListener::bind(("0.0.0.0", port)).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                tracing::warn!("[remote] Cannot listen on port {}: {}", port, e);
+                return;
+            }
+        };
+        app.state::<RemoteState>().port.store(port, Ordering::Relaxed);
+        tracing::info!("[remote] Listening on port {}", port);
+        run(app, listener, token).await;
+    });
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteStatus {
+    /// `None` while the server is not running.
+    pub port: Option<u16>,
+    /// `ws://…/?token=…` on the main LAN address.
+    pub url: Option<String>,
+    pub clients: usize,
+}
+
+/// Carries the access token, so only the host may ask.
+#[tauri::command]
+pub fn remote_status(app: AppHandle, webview: tauri::Webview) -> Result<RemoteStatus, String> {
+    require_webview(&webview, Capability::ChangeSettings)?;
+    let state = app.state::<RemoteState>();
+    let port = Some(state.port.load(Ordering::Relaxed)).filter(|&p| p != 0);
+    let host = discovery::route_ip().map(|ip| match ip {
+        IpAddr::V4(v4) => v4.to_string(),
+        IpAddr::V6(v6) => format!("[{}]", v6),
+    });
+    Ok(RemoteStatus {
+        url: port.zip(host).map(|(port, host)| format!("ws://{}:{}/?token={}", host, port, security::access_token())),
+        port,
+        clients: state.clients.load(Ordering::Relaxed),
+    })
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_decides_the_role() {
+        let uri = |s: &str| s.parse::<Uri>().unwrap();
+        assert_eq!(role_for(&uri("/?token=abc"), "abc", "xyz", false), Some(Role::Operator));
+        assert_eq!(role_for(&uri("/?view=lyrics&token=abc"), "abc", "xyz", false), Some(Role::Operator));
+        assert_eq!(role_for(&uri("/?token=xyz"), "abc", "xyz", false), Some(Role::Guest));
+        assert_eq!(role_for(&uri("/?token=abcd"), "abc", "xyz", false), None);
+        assert_eq!(role_for(&uri("/"), "abc", "xyz", true), Some(Role::Guest));
+        // Phones need at least the guest token
+        assert_eq!(role_for(&uri("/"), "abc", "xyz", false), None);
+        // No token configured: nobody is an operator
+        assert_eq!(role_for(&uri("/?token="), "", "", false), None);
+    }
+
+    #[test]
+    fn parses_control_messages() {
+        let parse = |s: &str| serde_json::from_str::<ControlMessage>(s);
+        assert_eq!(parse(r#"{"type":"seek","position_ms":1500}"#).unwrap(), ControlMessage::Seek { position_ms: 1500 });
+        assert_eq!(parse(r#"{"type":"key_up"}"#).unwrap(), ControlMessage::KeyUp);
+        assert_eq!(parse(r#"{"type":"clock","sent_at":42}"#).unwrap(), ControlMessage::Clock { sent_at: 42 });
+        assert!(parse(r#"{"type":"format_disk"}"#).is_err());
+    }
+}
@@ -0,0 +1,88 @@
+//! Supervised background tasks on the tokio runtime.
+//!
+//! Long-running loops — the server supervisor and its health polling, the
+//! RSS watchdog, the scheduler tick — run as tasks on Tauri's tokio runtime
+//! instead of each parking an OS thread in `thread::sleep`. Every task gets
+//! a child of one root `CancellationToken`; on exit `shutdown` cancels them
+//! all and waits (bounded) for them to finish, so no loop wakes up halfway
+//! through teardown and restarts a server that was just killed.
+//!
+//! Blocking work inside a task (process spawns, library scans) goes through
+//! `tauri::async_runtime::spawn_blocking`.
+
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use tauri::async_runtime::{self, JoinHandle};
+use tokio_util::sync::CancellationToken;
+
+/// Managed state: owner of all supervised tasks.
+pub struct TaskSupervisor {
+    root: CancellationToken,
+    tasks: Mutex<Vec<(&'static str, JoinHandle<()>)>>,
+}
+
+impl TaskSupervisor {
+    pub fn new() -> Self {
+        Self {
+            root: CancellationToken::new(),
+            tasks: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Spawn `task` with its own cancellation token. `name` is used in logs.
+    pub fn spawn<F, Fut>(&self, name: &'static str, task: F)
+    where
+        F: FnOnce(CancellationToken) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        if self.root.is_cancelled() {
+            tracing::warn!("[runtime] Not starting {}: shutting down", name);
+            return;
+        }
+        let handle = async_runtime::spawn(task(self.root.child_token()));
+        let Ok(mut tasks) = self.tasks.lock() else { return };
+        tasks.retain(|(_, h)| !h.inner().is_finished());
+        tasks.push((name, handle));
+    }
+
+    /// Token that is cancelled on shutdown, for work outside `spawn`.
+    pub fn token(&self) -> CancellationToken {
+        self.root.child_token()
+    }
+
+    /// Cancel every task and wait up to `timeout` for all of them to stop;
+    /// stragglers are aborted. Blocking; safe to call more than once.
+    pub fn shutdown(&self, timeout: Duration) {
+        self.root.cancel();
+        let tasks = match self.tasks.lock() {
+            Ok(mut tasks) => std::mem::take(&mut *tasks),
+            Err(_) => return,
+        };
+        if tasks.is_empty() {
+            return;
+        }
+        let deadline = Instant::now() + timeout;
+        async_runtime::block_on(async move {
+            for (name, handle) in tasks {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                let abort = handle.inner().abort_handle();
+                if tokio::time::timeout(remaining, handle).await.is_err() {
+                    tracing::warn!("[runtime] {} did not stop within {:?}, aborting", name, timeout);
+                    abort.abort();
+                }
+            }
+        });
+        tracing::info!("[runtime] Background tasks stopped");
+    }
+}
+
+/// Sleep for `duration` unless `token` is cancelled first.
+/// Returns `false` if cancelled, so loops can `if !sleep_or_cancel(..) { break }`.
+pub async fn sleep_or_cancel(token: &CancellationToken, duration: Duration) -> bool {
+    tokio::select! {
+        _ = token.cancelled() => false,
+        _ = tokio::time::sleep(duration) => true,
+    }
+}
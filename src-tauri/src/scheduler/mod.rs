@@ -0,0 +1,330 @@
+//! Lightweight scheduler for periodic maintenance jobs.
+//!
+//! A single supervised task wakes once a minute and runs every enabled
+//! task whose interval has elapsed since its last run. Tasks run one at a
+//! time on a blocking worker, so a slow library scan simply delays the next
+//! job; the tick stops with the other background tasks on shutdown.
+//!
+//! Each task is configured through `app_settings`:
+//!   - `schedule_<id>_enabled`        — `true` / `false`
+//!   - `schedule_<id>_interval_hours` — hours between runs
+//!   - `schedule_<id>_last_run`       — epoch ms of the last run (written here)
+//!   - `schedule_<id>_first_seen`     — epoch ms the scheduler first saw the
+//!     task enabled (written here)
+//!
+//! A task that has never run is first due one full interval after the
+//! scheduler first sees it, so a fresh install does not start a library
+//! rescan right at launch. Its `last_run` stays unset until then: tasks
+//! read it (the incremental scan only looks at files changed since), and
+//! a run that never happened must not count.
+
+pub mod tasks;
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use rusqlite::Connection;
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+use crate::access::{require_webview, Capability};
+use crate::db::DbState;
+use crate::runtime::{sleep_or_cancel, TaskSupervisor};
+
+const TICK: Duration = Duration::from_secs(60);
+
+/// Static description of a scheduled task.
+pub struct TaskSpec {
+    pub id: &'static str,
+    pub name: &'static str,
+    pub default_enabled: bool,
+    pub default_interval_hours: u32,
+    run: fn(&AppHandle) -> Result<String, String>,
+}
+
+/// All known tasks, in display order.
+pub const TASKS: &[TaskSpec] = &[
+    TaskSpec {
+        id: "library_scan",
+        name: "Incremental library scan",
+        default_enabled: true,
+        default_interval_hours: 24,
+        run: tasks::incremental_library_scan,
+    },
+    TaskSpec {
+        id: "cache_prune",
+        name: "Cache pruning",
+        default_enabled: true,
+        default_interval_hours: 24,
+        run: tasks::prune_caches,
+    },
+    TaskSpec {
+        id: "backup_upload",
+        name: "Database backup",
+        default_enabled: false,
+        default_interval_hours: 24,
+        run: tasks::backup_database,
+    },
+    TaskSpec {
+        id: "log_rotation",
+        name: "Log rotation",
+        default_enabled: true,
+        default_interval_hours: 24,
+        run: tasks::rotate_logs,
+    },
+    TaskSpec {
+        id: "tool_updates",
+        name: "yt-dlp / tool updates",
+        default_enabled: true,
+        default_interval_hours: 72,
+        run: crate::media::tools::auto_update,
+    },
+    TaskSpec {
+        id: "storage_quota",
+        name: "Storage quota check",
+        default_enabled: true,
+        default_interval_hours: 6,
+        run: crate::library::quota::check_quotas,
+    },
+];
+
+fn spec(id: &str) -> Result<&'static TaskSpec, String> {
+    TASKS.iter().find(|t| t.id == id).ok_or_else(|| format!("Unknown scheduled task: {}", id))
+}
+
+// ---------------------------------------------------------------------------
+// State
+// ---------------------------------------------------------------------------
+
+/// Result of the most recent run of a task (in-memory, since app start).
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskOutcome {
+    pub success: bool,
+    pub message: String,
+    pub duration_ms: u64,
+}
+
+/// Managed state shared by the scheduler tick and the commands.
+#[derive(Default)]
+pub struct SchedulerState {
+    running: Mutex<HashSet<&'static str>>,
+    outcomes: Mutex<HashMap<&'static str, TaskOutcome>>,
+}
+
+/// Snapshot returned by `get_scheduled_tasks`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduledTaskInfo {
+    pub id: String,
+    pub name: String,
+    pub enabled: bool,
+    pub interval_hours: u32,
+    pub last_run_ms: Option<i64>,
+    pub next_run_ms: Option<i64>,
+    pub running: bool,
+    pub last_outcome: Option<TaskOutcome>,
+}
+
+// ---------------------------------------------------------------------------
+// Settings
+// ---------------------------------------------------------------------------
+
+pub(crate) fn read_setting(conn: &Connection, key: &str) -> Option<String> {
+    conn.query_row("SELECT value FROM app_settings WHERE key = ?1", [key], |row| row.get(0))
+        .ok()
+}
+
+fn write_setting(conn: &Connection, key: &str, value: &str) -> Result<(), String> {
+    conn.execute(
+        "INSERT OR REPLACE INTO app_settings (key, value) VALUES (?1, ?2)",
+        (key, value),
+    )
+    .map(|_| ())
+    .map_err(|e| format!("Failed to save setting '{}': {}", key, e))
+}
+
+struct TaskConfig {
+    enabled: bool,
+    interval_hours: u32,
+    last_run_ms: Option<i64>,
+    first_seen_ms: Option<i64>,
+}
+
+fn load_config(conn: &Connection, spec: &TaskSpec) -> TaskConfig {
+    let key = |suffix: &str| format!("schedule_{}_{}", spec.id, suffix);
+    TaskConfig {
+        enabled: read_setting(conn, &key("enabled"))
+            .map(|v| v.trim() == "true")
+            .unwrap_or(spec.default_enabled),
+        interval_hours: read_setting(conn, &key("interval_hours"))
+            .and_then(|v| v.trim().parse::<u32>().ok())
+            .filter(|&h| h > 0)
+            .unwrap_or(spec.default_interval_hours),
+        last_run_ms: read_setting(conn, &key("last_run")).and_then(|v| v.trim().parse::<i64>().ok()),
+        first_seen_ms: read_setting(conn, &key("first_seen")).and_then(|v| v.trim().parse::<i64>().ok()),
+    }
+}
+
+fn next_run_ms(config: &TaskConfig) -> Option<i64> {
+    let since = config.last_run_ms.or(config.first_seen_ms)?;
+    config.enabled.then(|| since + i64::from(config.interval_hours) * 3_600_000)
+}
+
+// ---------------------------------------------------------------------------
+// Scheduler tick
+// ---------------------------------------------------------------------------
+
+/// Start the scheduler tick. Requires `DbState`, `SchedulerState` and
+/// `TaskSupervisor`.
+pub fn spawn_scheduler(app: AppHandle) {
+    let supervisor = app.state::<TaskSupervisor>();
+    supervisor.spawn("scheduler", move |token| async move {
+        while sleep_or_cancel(&token, TICK).await {
+            for spec in TASKS {
+                if !is_due(&app, spec) {
+                    continue;
+                }
+                // Tasks do blocking I/O (scans, VACUUM); keep them off the runtime
+                let app = app.clone();
+                if let Err(e) = tauri::async_runtime::spawn_blocking(move || run_task(&app, spec)).await {
+                    tracing::error!("[scheduler] {} panicked: {}", spec.id, e);
+                }
+            }
+        }
+    });
+}
+
+fn is_due(app: &AppHandle, spec: &TaskSpec) -> bool {
+    let Some(db) = app.try_state::<DbState>() else {
+        return false;
+    };
+    let Ok(conn) = db.conn.lock() else {
+        return false;
+    };
+    let config = load_config(&conn, spec);
+    if !config.enabled {
+        return false;
+    }
+    let now = now_ms();
+    match next_run_ms(&config) {
+        Some(next) => now >= next,
+        None => {
+            // First sighting: start the clock instead of running immediately
+            let _ = write_setting(&conn, &format!("schedule_{}_first_seen", spec.id), &now.to_string());
+            false
+        }
+    }
+}
+
+/// Run a task on the current thread, record its outcome and last-run time.
+/// Returns `None` if the task is already running.
+fn run_task(app: &AppHandle, spec: &'static TaskSpec) -> Option<TaskOutcome> {
+    let state = app.state::<SchedulerState>();
+    if !state.running.lock().ok()?.insert(spec.id) {
+        return None;
+    }
+
+    tracing::info!("[scheduler] Running '{}'", spec.id);
+    let started = Instant::now();
+    let result = (spec.run)(app);
+    let outcome = TaskOutcome {
+        success: result.is_ok(),
+        message: result.unwrap_or_else(|e| e),
+        duration_ms: started.elapsed().as_millis() as u64,
+    };
+    if outcome.success {
+        tracing::info!("[scheduler] '{}' finished in {} ms: {}", spec.id, outcome.duration_ms, outcome.message);
+    } else {
+        tracing::error!("[scheduler] '{}' failed: {}", spec.id, outcome.message);
+    }
+
+    // A failed run still counts as a run, otherwise it would retry every tick
+    if let Some(db) = app.try_state::<DbState>() {
+        if let Ok(conn) = db.conn.lock() {
+            let key = format!("schedule_{}_last_run", spec.id);
+            if let Err(e) = write_setting(&conn, &key, &now_ms().to_string()) {
+                tracing::warn!("[scheduler] {}", e);
+            }
+        }
+    }
+    if let Ok(mut outcomes) = state.outcomes.lock() {
+        outcomes.insert(spec.id, outcome.clone());
+    }
+    if let Ok(mut running) = state.running.lock() {
+        running.remove(spec.id);
+    }
+    Some(outcome)
+}
+
+pub(crate) fn now_ms() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+#[tauri::command]
+pub fn get_scheduled_tasks(app: AppHandle) -> Result<Vec<ScheduledTaskInfo>, String> {
+    let db = app.state::<DbState>();
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let state = app.state::<SchedulerState>();
+    let running = state.running.lock().map_err(|e| e.to_string())?;
+    let outcomes = state.outcomes.lock().map_err(|e| e.to_string())?;
+
+    Ok(TASKS
+        .iter()
+        .map(|spec| {
+            let config = load_config(&conn, spec);
+            ScheduledTaskInfo {
+                id: spec.id.to_string(),
+                name: spec.name.to_string(),
+                enabled: config.enabled,
+                interval_hours: config.interval_hours,
+                last_run_ms: config.last_run_ms,
+                next_run_ms: next_run_ms(&config),
+                running: running.contains(spec.id),
+                last_outcome: outcomes.get(spec.id).cloned(),
+            }
+        })
+        .collect())
+}
+
+/// Enable/disable a task and optionally change its interval.
+#[tauri::command]
+pub fn set_scheduled_task(
+    app: AppHandle,
+    webview: tauri::Webview,
+    id: String,
+    enabled: bool,
+    interval_hours: Option<u32>,
+) -> Result<(), String> {
+    require_webview(&webview, Capability::ChangeSettings)?;
+    let spec = spec(&id)?;
+    let db = app.state::<DbState>();
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    write_setting(&conn, &format!("schedule_{}_enabled", spec.id), if enabled { "true" } else { "false" })?;
+    if let Some(hours) = interval_hours {
+        if hours == 0 {
+            return Err("Interval must be at least one hour".to_string());
+        }
+        write_setting(&conn, &format!("schedule_{}_interval_hours", spec.id), &hours.to_string())?;
+    }
+    Ok(())
+}
+
+/// Run a task immediately, regardless of its schedule.
+#[tauri::command]
+pub async fn run_scheduled_task(app: AppHandle, webview: tauri::Webview, id: String) -> Result<TaskOutcome, String> {
+    require_webview(&webview, Capability::ManageLibrary)?;
+    let spec = spec(&id)?;
+    tauri::async_runtime::spawn_blocking(move || run_task(&app, spec))
+        .await
+        .map_err(|e| format!("Task join failed: {}", e))?
+        .ok_or_else(|| format!("Task '{}' is already running", id))
+}
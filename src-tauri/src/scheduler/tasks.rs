@@ -0,0 +1,266 @@
+//! Maintenance task implementations run by the scheduler.
+//!
+//! Every task returns a short human-readable summary on success; the
+//! scheduler stores it as the task's last outcome.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use tauri::{AppHandle, Manager};
+
+use super::{now_ms, read_setting};
+use crate::audio::analysis_cache;
+use crate::db::DbState;
+use crate::library::scan_pool::{self, ScanOptions};
+
+/// Files in the cache dir (and deletion journal rows) older than this go.
+const CACHE_MAX_AGE_KEY: &str = "cache_max_age_days";
+const DEFAULT_CACHE_MAX_AGE_DAYS: u64 = 30;
+
+/// Folder the database snapshot is written to — typically a NAS share or a
+/// cloud-synced folder, which then takes care of the actual upload.
+const BACKUP_TARGET_KEY: &str = "backup_target_dir";
+const BACKUP_KEEP_KEY: &str = "backup_keep";
+const DEFAULT_BACKUP_KEEP: usize = 7;
+
+const LOG_RETENTION_KEY: &str = "log_retention_days";
+const DEFAULT_LOG_RETENTION_DAYS: u64 = 14;
+/// Logs larger than this are rotated (`app.log` → `app.log.1` …).
+const LOG_ROTATE_BYTES: u64 = 5 * 1024 * 1024;
+const LOG_GENERATIONS: u32 = 5;
+
+fn setting(app: &AppHandle, key: &str) -> Option<String> {
+    let db = app.try_state::<DbState>()?;
+    let conn = db.conn.lock().ok()?;
+    read_setting(&conn, key)
+}
+
+fn days_setting(app: &AppHandle, key: &str, default: u64) -> Duration {
+    let days = setting(app, key)
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .filter(|&d| d > 0)
+        .unwrap_or(default);
+    Duration::from_secs(days * 24 * 3600)
+}
+
+fn is_older_than(path: &Path, max_age: Duration) -> bool {
+    std::fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.elapsed().ok())
+        .is_some_and(|age| age > max_age)
+}
+
+// ---------------------------------------------------------------------------
+// Library scan
+// ---------------------------------------------------------------------------
+
+/// Rescan every root folder, parsing only song files changed since the
+/// task last ran.
+pub fn incremental_library_scan(app: &AppHandle) -> Result<String, String> {
+    let db = app.state::<DbState>();
+    let (roots, since) = {
+        let conn = db.conn.lock().map_err(|e| e.to_string())?;
+        let mut stmt = conn
+            .prepare("SELECT path FROM root_folders ORDER BY path")
+            .map_err(|e| format!("Failed to load root folders: {}", e))?;
+        let roots: Vec<String> = stmt
+            .query_map([], |row| row.get::<_, String>(0))
+            .map_err(|e| format!("Failed to load root folders: {}", e))?
+            .flatten()
+            .collect();
+        let since = read_setting(&conn, "schedule_library_scan_last_run")
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .map(|ms| UNIX_EPOCH + Duration::from_millis(ms));
+        (roots, since)
+    };
+
+    if roots.is_empty() {
+        return Ok("No library folders configured".to_string());
+    }
+
+    let mut saved = 0;
+    let mut errors = 0;
+    for root in &roots {
+        let options = ScanOptions {
+            since,
+            artwork_dir: crate::library::artwork::cache_dir(app),
+            ..ScanOptions::default()
+        };
+        match scan_pool::scan_into_db(&db, Path::new(root), &options, |_| {}) {
+            Ok(report) => {
+                errors += report.errors.len();
+                saved += report.songs_found;
+            }
+            Err(e) => {
+                tracing::error!("[scheduler] Scan of {} failed: {}", root, e);
+                errors += 1;
+            }
+        }
+    }
+    Ok(format!("{} songs updated in {} folders, {} errors", saved, roots.len(), errors))
+}
+
+// ---------------------------------------------------------------------------
+// Cache pruning
+// ---------------------------------------------------------------------------
+
+/// Delete stale files from the app cache dir and expire old entries of the
+/// deleted-songs journal. The analysis cache is content-keyed and kept.
+pub fn prune_caches(app: &AppHandle) -> Result<String, String> {
+    let max_age = days_setting(app, CACHE_MAX_AGE_KEY, DEFAULT_CACHE_MAX_AGE_DAYS);
+
+    let mut files_removed = 0;
+    if let Ok(cache_dir) = app.path().app_cache_dir() {
+        let mut stack = vec![cache_dir];
+        while let Some(dir) = stack.pop() {
+            let Ok(entries) = std::fs::read_dir(&dir) else { continue };
+            for entry in entries.flatten() {
+                let path = entry.path();
+                let Ok(file_type) = entry.file_type() else { continue };
+                if file_type.is_dir() {
+                    if entry.file_name() != analysis_cache::CACHE_SUBDIR {
+                        stack.push(path);
+                    }
+                } else if is_older_than(&path, max_age) && std::fs::remove_file(&path).is_ok() {
+                    files_removed += 1;
+                }
+            }
+        }
+    }
+
+    let cutoff = now_ms() - max_age.as_millis() as i64;
+    let db = app.state::<DbState>();
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    let journal_removed = conn
+        .execute("DELETE FROM deleted_songs_journal WHERE deleted_at < ?1", [cutoff])
+        .map_err(|e| format!("Failed to prune deletion journal: {}", e))?;
+
+    Ok(format!("{} cache files and {} journal entries removed", files_removed, journal_removed))
+}
+
+// ---------------------------------------------------------------------------
+// Backup
+// ---------------------------------------------------------------------------
+
+/// Write a consistent snapshot of the database to the backup folder and
+/// keep only the newest `backup_keep` snapshots there.
+pub fn backup_database(app: &AppHandle) -> Result<String, String> {
+    let target = setting(app, BACKUP_TARGET_KEY)
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .ok_or_else(|| format!("No backup folder configured ({})", BACKUP_TARGET_KEY))?;
+    let target = PathBuf::from(target);
+    std::fs::create_dir_all(&target)
+        .map_err(|e| format!("Cannot create backup folder {}: {}", target.display(), e))?;
+
+    let file = target.join(format!("karaoke-{}.db", now_ms()));
+    {
+        let db = app.state::<DbState>();
+        let conn = db.conn.lock().map_err(|e| e.to_string())?;
+        // VACUUM INTO produces a compact, consistent copy even in WAL mode
+        conn.execute("VACUUM INTO ?1", [file.to_string_lossy().as_ref()])
+            .map_err(|e| format!("Backup failed: {}", e))?;
+    }
+
+    let keep = setting(app, BACKUP_KEEP_KEY)
+        .and_then(|v| v.trim().parse::<usize>().ok())
+        .filter(|&k| k > 0)
+        .unwrap_or(DEFAULT_BACKUP_KEEP);
+    let mut backups: Vec<PathBuf> = std::fs::read_dir(&target)
+        .map_err(|e| format!("Cannot list backup folder: {}", e))?
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| {
+            p.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.starts_with("karaoke-") && n.ends_with(".db"))
+        })
+        .collect();
+    // Timestamped names sort chronologically
+    backups.sort();
+    let excess = backups.len().saturating_sub(keep);
+    for old in &backups[..excess] {
+        if let Err(e) = std::fs::remove_file(old) {
+            tracing::error!("[scheduler] Failed to remove old backup {}: {}", old.display(), e);
+        }
+    }
+
+    Ok(format!("Backup written to {}", file.display()))
+}
+
+// ---------------------------------------------------------------------------
+// Log rotation
+// ---------------------------------------------------------------------------
+
+/// Rotate oversized `*.log` files and delete rotated logs past retention.
+pub fn rotate_logs(app: &AppHandle) -> Result<String, String> {
+    let log_dir = app.path().app_log_dir().map_err(|e| format!("Failed to get log dir: {}", e))?;
+    let Ok(entries) = std::fs::read_dir(&log_dir) else {
+        return Ok("No log folder".to_string());
+    };
+    let retention = days_setting(app, LOG_RETENTION_KEY, DEFAULT_LOG_RETENTION_DAYS);
+
+    let mut rotated = 0;
+    let mut removed = 0;
+    for path in entries.flatten().map(|e| e.path()) {
+        let is_log = path.extension().is_some_and(|e| e == "log");
+        if is_log {
+            let size = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+            if size > LOG_ROTATE_BYTES {
+                match rotate_file(&path) {
+                    Ok(()) => rotated += 1,
+                    Err(e) => tracing::warn!("[scheduler] {}", e),
+                }
+            }
+        } else if is_rotated_log(&path) && is_older_than(&path, retention) && std::fs::remove_file(&path).is_ok() {
+            removed += 1;
+        }
+    }
+    Ok(format!("{} logs rotated, {} old logs removed", rotated, removed))
+}
+
+/// `app.log.1`, `app.log.2`, …
+fn is_rotated_log(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+        return false;
+    };
+    name.rsplit_once('.')
+        .is_some_and(|(stem, generation)| stem.ends_with(".log") && generation.parse::<u32>().is_ok())
+}
+
+/// Shift `x.log.N` → `x.log.N+1` (dropping the oldest), then `x.log` → `x.log.1`.
+fn rotate_file(path: &Path) -> Result<(), String> {
+    let generation = |n: u32| {
+        let mut name = path.as_os_str().to_owned();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    };
+    let _ = std::fs::remove_file(generation(LOG_GENERATIONS));
+    for n in (1..LOG_GENERATIONS).rev() {
+        let from = generation(n);
+        if from.exists() {
+            let _ = std::fs::rename(&from, generation(n + 1));
+        }
+    }
+    std::fs::rename(path, generation(1))
+        .map_err(|e| format!("Failed to rotate {}: {}", path.display(), e))
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognises_rotated_log_names() {
+        assert!(is_rotated_log(Path::new("/logs/app.log.1")));
+        assert!(is_rotated_log(Path::new("/logs/server.log.12")));
+        assert!(!is_rotated_log(Path::new("/logs/app.log")));
+        assert!(!is_rotated_log(Path::new("/logs/notes.txt.1")));
+        assert!(!is_rotated_log(Path::new("/logs/app.log.old")));
+    }
+}
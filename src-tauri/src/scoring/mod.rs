@@ -0,0 +1,522 @@
+//! Scoring sung pitch against UltraStar notes.
+//!
+//! `start_scoring` loads the song's notes and follows `pitch://frame` on
+//! the event bus (see `audio::live_pitch`): every voiced frame inside a
+//! note scores when it is within the difficulty's tolerance of the note,
+//! octave-independent, so a bass singing a soprano line an octave down
+//! still hits. Rap notes only need a voiced frame; freestyle notes do not
+//! count. Like UltraStar, a song is worth 10 000 points: 9 000 for notes
+//! (golden notes count double) and 1 000 spread over the lines as a bonus
+//! for singing them well.
+//!
+//! A `scoring://line` event goes out as each line ends. When the last note
+//! is over (or `stop_scoring` is called) the result is saved to
+//! `highscores` and published as `scoring://result`. One session runs at
+//! a time; starting another discards the running one.
+
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use rusqlite::Connection;
+use serde::Serialize;
+use serde_json::json;
+use tauri::{AppHandle, Manager};
+use tokio::sync::broadcast::error::RecvError;
+use tokio_util::sync::CancellationToken;
+
+use crate::access::{require_webview, Capability};
+use crate::audio::commands::AudioState;
+use crate::audio::live_pitch::FRAMES_PER_SECOND;
+use crate::db::commands::insert_highscore;
+use crate::db::DbState;
+use crate::events::{publish, AppEvent, EventBus};
+use crate::library::ultrastar::{self, decode_text, NoteKind, UltraStarSong};
+use crate::paths::long_path;
+use crate::runtime::TaskSupervisor;
+
+pub const LINE_EVENT: &str = "scoring://line";
+pub const RESULT_EVENT: &str = "scoring://result";
+const MAX_NOTE_SCORE: f64 = 9_000.0;
+const MAX_LINE_BONUS: f64 = 1_000.0;
+/// Sung quality below this earns no line bonus.
+const LINE_BONUS_FLOOR: f64 = 0.2;
+/// Grace after the last note before the result is final.
+const FINISH_GRACE_MS: u64 = 1_000;
+/// Position check interval while no pitch frames arrive.
+const TICK: Duration = Duration::from_millis(100);
+
+/// Total-score tiers, as in UltraStar.
+const RATINGS: &[(f64, &str)] = &[
+    (9_000.0, "Ultrastar"),
+    (8_000.0, "Superstar"),
+    (7_000.0, "Lead Singer"),
+    (6_000.0, "Rising Star"),
+    (5_000.0, "Hopeful"),
+    (4_000.0, "Wannabe"),
+    (2_000.0, "Amateur"),
+    (0.0, "Tone Deaf"),
+];
+
+pub fn rating(score: f64) -> &'static str {
+    RATINGS.iter().find(|(min, _)| score >= *min).map_or("Tone Deaf", |(_, name)| name)
+}
+
+fn line_rating(quality: f64) -> &'static str {
+    match quality {
+        q if q >= 0.95 => "Perfect",
+        q if q >= 0.8 => "Great",
+        q if q >= 0.6 => "Good",
+        q if q >= 0.3 => "OK",
+        _ => "Miss",
+    }
+}
+
+/// Allowed distance in semitones per difficulty.
+fn tolerance(difficulty: &str) -> f64 {
+    match difficulty {
+        "easy" => 2.0,
+        "hard" => 0.5,
+        _ => 1.0,
+    }
+}
+
+struct ScoredNote {
+    start_ms: u64,
+    end_ms: u64,
+    /// MIDI key (UltraStar pitch 0 = C4 = 60).
+    key: f64,
+    kind: NoteKind,
+    line: usize,
+    /// Golden notes count double.
+    weight: f64,
+    hits: u32,
+}
+
+impl ScoredNote {
+    fn expected_frames(&self) -> f64 {
+        ((self.end_ms - self.start_ms) as f64 * FRAMES_PER_SECOND as f64 / 1000.0).max(1.0)
+    }
+
+    /// Share of the note that was sung on pitch, 0..=1.
+    fn quality(&self) -> f64 {
+        (self.hits as f64 / self.expected_frames()).min(1.0)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LineScore {
+    pub line_index: usize,
+    /// Notes plus bonus earned by this line.
+    pub score: f64,
+    pub quality: f64,
+    pub rating: &'static str,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScoringResult {
+    pub song_id: String,
+    pub song_title: String,
+    pub player_id: String,
+    pub player_name: String,
+    pub difficulty: String,
+    pub score: f64,
+    pub note_score: f64,
+    pub golden_score: f64,
+    pub line_bonus: f64,
+    /// Weighted share of all notes sung on pitch, in percent.
+    pub accuracy: f64,
+    pub rating: &'static str,
+    pub perfect_notes: u32,
+    pub good_notes: u32,
+    pub miss_notes: u32,
+    pub max_combo: u32,
+    pub lines: Vec<LineScore>,
+}
+
+/// Scores one singer's track; pure, fed with (position, sung note) pairs.
+pub struct Scorer {
+    notes: Vec<ScoredNote>,
+    /// End of each line's last note, ms.
+    line_ends: Vec<u64>,
+    lines_done: usize,
+    line_scores: Vec<LineScore>,
+    /// Unrounded bonus of each reported line.
+    line_bonuses: Vec<f64>,
+    tolerance: f64,
+    total_weight: f64,
+}
+
+impl Scorer {
+    pub fn new(song: &UltraStarSong, track: usize, difficulty: &str) -> Self {
+        let mut notes = Vec::new();
+        let mut line_ends = Vec::new();
+        let lines = song.tracks.get(track).map(|t| t.lines.as_slice()).unwrap_or_default();
+        for line in lines {
+            let scored: Vec<_> = line.notes.iter().filter(|n| n.kind != NoteKind::Freestyle && n.length > 0).collect();
+            if scored.is_empty() {
+                continue;
+            }
+            let index = line_ends.len();
+            for note in &scored {
+                let golden = matches!(note.kind, NoteKind::Golden | NoteKind::RapGolden);
+                notes.push(ScoredNote {
+                    start_ms: song.beat_to_ms(note.start_beat as f64).max(0.0) as u64,
+                    end_ms: song.beat_to_ms((note.start_beat + note.length) as f64).max(0.0) as u64,
+                    key: 60.0 + note.pitch as f64,
+                    kind: note.kind,
+                    line: index,
+                    weight: note.length as f64 * if golden { 2.0 } else { 1.0 },
+                    hits: 0,
+                });
+            }
+            line_ends.push(notes.last().map_or(0, |n| n.end_ms));
+        }
+        let total_weight = notes.iter().map(|n| n.weight).sum::<f64>().max(1.0);
+        Self {
+            notes,
+            line_ends,
+            lines_done: 0,
+            line_scores: Vec::new(),
+            line_bonuses: Vec::new(),
+            tolerance: tolerance(difficulty),
+            total_weight,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.notes.is_empty()
+    }
+
+    /// Credit one pitch frame heard at `position_ms`.
+    pub fn push(&mut self, position_ms: u64, midi_note: Option<f64>) {
+        let Some(sung) = midi_note else { return };
+        let i = self.notes.partition_point(|n| n.start_ms <= position_ms);
+        let Some(note) = i.checked_sub(1).map(|i| &mut self.notes[i]) else { return };
+        if position_ms >= note.end_ms {
+            return;
+        }
+        let hit = match note.kind {
+            NoteKind::Rap | NoteKind::RapGolden => true,
+            _ => {
+                let distance = (sung - note.key).rem_euclid(12.0);
+                distance.min(12.0 - distance) <= self.tolerance
+            }
+        };
+        if hit {
+            note.hits += 1;
+        }
+    }
+
+    fn points(&self, note: &ScoredNote) -> f64 {
+        MAX_NOTE_SCORE * note.weight / self.total_weight * note.quality()
+    }
+
+    fn score_line(&self, line: usize) -> (LineScore, f64) {
+        let notes: Vec<&ScoredNote> = self.notes.iter().filter(|n| n.line == line).collect();
+        let weight: f64 = notes.iter().map(|n| n.weight).sum::<f64>().max(1e-9);
+        let quality = notes.iter().map(|n| n.quality() * n.weight).sum::<f64>() / weight;
+        let bonus_share = MAX_LINE_BONUS / self.line_ends.len().max(1) as f64;
+        let bonus = bonus_share * ((quality - LINE_BONUS_FLOOR) / (1.0 - LINE_BONUS_FLOOR)).clamp(0.0, 1.0);
+        let score = notes.iter().map(|n| self.points(n)).sum::<f64>() + bonus;
+        (LineScore { line_index: line, score: score.round(), quality, rating: line_rating(quality) }, bonus)
+    }
+
+    /// Lines that ended by `position_ms` and were not reported yet.
+    pub fn finish_lines(&mut self, position_ms: u64) -> Vec<LineScore> {
+        let mut finished = Vec::new();
+        while self.lines_done < self.line_ends.len() && self.line_ends[self.lines_done] <= position_ms {
+            let (line, bonus) = self.score_line(self.lines_done);
+            self.line_scores.push(line.clone());
+            self.line_bonuses.push(bonus);
+            finished.push(line);
+            self.lines_done += 1;
+        }
+        finished
+    }
+
+    pub fn is_finished(&self, position_ms: u64) -> bool {
+        self.line_ends.last().map_or(true, |end| position_ms >= end + FINISH_GRACE_MS)
+    }
+
+    /// Totals over every line, scoring those not reported yet as they are.
+    /// Song and player fields are left empty for the caller.
+    pub fn totals(&mut self) -> ScoringResult {
+        self.finish_lines(u64::MAX);
+        let note_score: f64 = self.notes.iter().map(|n| self.points(n)).sum();
+        let golden_score: f64 = self
+            .notes
+            .iter()
+            .filter(|n| matches!(n.kind, NoteKind::Golden | NoteKind::RapGolden))
+            .map(|n| self.points(n))
+            .sum();
+        let line_bonus: f64 = self.line_bonuses.iter().sum();
+        let (mut perfect, mut good, mut miss, mut combo, mut max_combo) = (0, 0, 0, 0, 0);
+        for note in &self.notes {
+            match note.quality() {
+                q if q >= 0.9 => perfect += 1,
+                q if q >= 0.5 => good += 1,
+                _ => miss += 1,
+            }
+            combo = if note.quality() >= 0.5 { combo + 1 } else { 0 };
+            max_combo = max_combo.max(combo);
+        }
+        let accuracy = self.notes.iter().map(|n| n.quality() * n.weight).sum::<f64>() / self.total_weight * 100.0;
+        let score = (note_score + line_bonus).round();
+        ScoringResult {
+            song_id: String::new(),
+            song_title: String::new(),
+            player_id: String::new(),
+            player_name: String::new(),
+            difficulty: String::new(),
+            score,
+            note_score: note_score.round(),
+            golden_score: golden_score.round(),
+            line_bonus: line_bonus.round(),
+            accuracy: (accuracy * 10.0).round() / 10.0,
+            rating: rating(score),
+            perfect_notes: perfect,
+            good_notes: good,
+            miss_notes: miss,
+            max_combo,
+            lines: self.line_scores.clone(),
+        }
+    }
+}
+
+struct ScoringSession {
+    /// Ends the session early with a result.
+    finish: CancellationToken,
+    /// Ends it without one.
+    abort: CancellationToken,
+}
+
+/// Managed state: the running session.
+#[derive(Default)]
+pub struct ScoringState {
+    session: Mutex<Option<ScoringSession>>,
+}
+
+impl ScoringState {
+    fn replace(&self, next: Option<ScoringSession>) {
+        if let Ok(mut session) = self.session.lock() {
+            if let Some(previous) = std::mem::replace(&mut *session, next) {
+                previous.abort.cancel();
+            }
+        }
+    }
+
+    /// Whether a session was still running.
+    fn finish(&self) -> bool {
+        match self.session.lock().ok().and_then(|mut s| s.take()) {
+            Some(session) => {
+                let running = !session.finish.is_cancelled();
+                session.finish.cancel();
+                running
+            }
+            None => false,
+        }
+    }
+}
+
+fn load_song(conn: &Connection, song_id: &str) -> Result<(UltraStarSong, String), String> {
+    let json: Option<String> = conn
+        .query_row("SELECT json_data FROM songs WHERE id = ?1", [song_id], |row| row.get(0))
+        .map_err(|e| format!("Song {} not found: {}", song_id, e))?;
+    let song: serde_json::Value = json
+        .and_then(|j| serde_json::from_str(&j).ok())
+        .ok_or_else(|| format!("Song {} has no data", song_id))?;
+    let field = |key: &str| song.get(key).and_then(|v| v.as_str()).filter(|v| !v.is_empty());
+    let (Some(folder), Some(txt)) = (field("folderPath"), field("txtFileName")) else {
+        return Err(format!("Song {} has no UltraStar notes to score", song_id));
+    };
+    let path = Path::new(folder).join(txt);
+    let bytes = std::fs::read(long_path(&path)).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let parsed = ultrastar::parse_song(&decode_text(&bytes)).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let title = field("title").map(String::from).unwrap_or_else(|| parsed.title.clone());
+    Ok((parsed, title))
+}
+
+fn player_name(conn: &Connection, player_id: &str) -> String {
+    conn.query_row("SELECT name FROM profiles WHERE id = ?1", [player_id], |row| row.get(0))
+        .unwrap_or_else(|_| player_id.to_string())
+}
+
+fn save_result(app: &AppHandle, result: &ScoringResult) -> Result<(), String> {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0);
+    let record = json!({
+        "id": format!("hs-{}-{}", now, result.player_id),
+        "playerId": result.player_id,
+        "playerName": result.player_name,
+        "songId": result.song_id,
+        "songTitle": result.song_title,
+        "score": result.score,
+        "accuracy": result.accuracy,
+        "maxCombo": result.max_combo,
+        "perfectNotes": result.perfect_notes,
+        "goodNotes": result.good_notes,
+        "missNotes": result.miss_notes,
+        "difficulty": result.difficulty,
+        "gameMode": "standard",
+        "rankTitle": result.rating,
+        "playedAt": now,
+        "goldenScore": result.golden_score,
+        "lineBonus": result.line_bonus,
+        "lines": result.lines,
+    });
+    let db = app.state::<DbState>();
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    insert_highscore(&conn, &record, &record.to_string()).map(|_| ())
+}
+
+/// Add the result to the performance history and the singer's profile.
+fn record_performance(app: &AppHandle, result: &ScoringResult) -> Result<(), String> {
+    let key_offset = app.try_state::<AudioState>().map(|audio| audio.key()).unwrap_or(0);
+    let db = app.state::<DbState>();
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    crate::history::record_score(&conn, result, key_offset)?;
+    crate::profiles::add_score(&conn, result)
+}
+
+/// Song and player a session scores for.
+struct SessionInfo {
+    song_id: String,
+    song_title: String,
+    player_id: String,
+    player_name: String,
+    difficulty: String,
+}
+
+fn run_session(app: AppHandle, mut scorer: Scorer, info: SessionInfo, session: ScoringSession) {
+    let supervisor = app.state::<TaskSupervisor>();
+    let mut frames = app.state::<EventBus>().subscribe();
+    supervisor.spawn("scoring", move |token| async move {
+        loop {
+            tokio::select! {
+                _ = token.cancelled() => return,
+                _ = session.abort.cancelled() => return,
+                _ = session.finish.cancelled() => break,
+                received = frames.recv() => match received {
+                    Ok(envelope) => {
+                        if let AppEvent::PitchFrame(frame) = &envelope.event {
+                            scorer.push(frame.position_ms, frame.midi_note);
+                        }
+                    }
+                    Err(RecvError::Lagged(missed)) => tracing::warn!("[scoring] Missed {} events", missed),
+                    Err(RecvError::Closed) => return,
+                },
+                _ = tokio::time::sleep(TICK) => {}
+            }
+            let position_ms = app.try_state::<AudioState>().map(|a| a.position_ms()).unwrap_or(0);
+            for line in scorer.finish_lines(position_ms) {
+                publish(&app, AppEvent::ScoringLine(line));
+            }
+            if scorer.is_finished(position_ms) {
+                break;
+            }
+        }
+        // Marks the session done for `stop_scoring`
+        session.finish.cancel();
+
+        let result = ScoringResult {
+            song_id: info.song_id,
+            song_title: info.song_title,
+            player_id: info.player_id,
+            player_name: info.player_name,
+            difficulty: info.difficulty,
+            ..scorer.totals()
+        };
+        if let Err(e) = save_result(&app, &result) {
+            tracing::error!("[scoring] Failed to save result: {}", e);
+        }
+        if let Err(e) = record_performance(&app, &result) {
+            tracing::error!("[scoring] {}", e);
+        }
+        tracing::info!("[scoring] {} scored {} on {} ({})", result.player_name, result.score, result.song_title, result.rating);
+        publish(&app, AppEvent::ScoringResult(result));
+    });
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+/// Score `player` (a profile id) singing `song_id` from now on. `track`
+/// picks the duet part (0 = P1); `difficulty` is easy, medium or hard.
+/// Returns the number of scored lines.
+#[tauri::command]
+pub async fn start_scoring(
+    app: AppHandle,
+    webview: tauri::Webview,
+    song_id: String,
+    player: String,
+    track: Option<usize>,
+    difficulty: Option<String>,
+) -> Result<usize, String> {
+    require_webview(&webview, Capability::ControlPlayback)?;
+    let difficulty = difficulty.unwrap_or_else(|| "medium".to_string()).to_ascii_lowercase();
+    let (song, song_title, player_name) = {
+        let db = app.state::<DbState>();
+        let conn = db.conn.lock().map_err(|e| e.to_string())?;
+        let (song, title) = load_song(&conn, &song_id)?;
+        (song, title, player_name(&conn, &player))
+    };
+    let scorer = Scorer::new(&song, track.unwrap_or(0), &difficulty);
+    if scorer.is_empty() {
+        return Err(format!("{} has no notes to score", song_title));
+    }
+    let lines = scorer.line_ends.len();
+    let info = SessionInfo { song_id, song_title, player_id: player, player_name, difficulty };
+    let session = ScoringSession { finish: CancellationToken::new(), abort: CancellationToken::new() };
+    let handles = ScoringSession { finish: session.finish.clone(), abort: session.abort.clone() };
+    app.state::<ScoringState>().replace(Some(handles));
+    run_session(app, scorer, info, session);
+    Ok(lines)
+}
+
+/// End the running session now; its result is still saved and published.
+#[tauri::command]
+pub fn stop_scoring(app: AppHandle, webview: tauri::Webview) -> Result<bool, String> {
+    require_webview(&webview, Capability::ControlPlayback)?;
+    Ok(app.state::<ScoringState>().finish())
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scores_hits_octave_free_and_golden_double() {
+        // UltraStar BPM 240: a beat is 62.5 ms, so 16 beats are 1 s
+        let text = "#TITLE:T\n#ARTIST:A\n#BPM:240\n#GAP:0\n: 0 16 0 la\n* 16 16 2 la\n- 40\n: 48 16 4 end\nE\n";
+        let song = ultrastar::parse_song(text).unwrap();
+        let mut scorer = Scorer::new(&song, 0, "medium");
+        // First note sung an octave low, golden note missed, last note on pitch
+        for i in 0..60 {
+            scorer.push(i * 1000 / 60, Some(48.2));
+            scorer.push(1_000 + i * 1000 / 60, Some(66.0));
+            scorer.push(3_000 + i * 1000 / 60, Some(64.0));
+        }
+        let lines = scorer.finish_lines(2_100);
+        assert_eq!(lines.len(), 1);
+        assert!(scorer.finish_lines(2_500).is_empty());
+
+        let result = scorer.totals();
+        // Weights 16 + 32 + 16: notes give 2250 + 0 + 2250
+        assert_eq!(result.note_score, 4_500.0);
+        assert_eq!(result.golden_score, 0.0);
+        assert_eq!((result.perfect_notes, result.miss_notes, result.max_combo), (2, 1, 1));
+        assert_eq!(result.lines[1].rating, "Perfect");
+        // Line 1: quality 1/3 → 500 * (0.333 - 0.2) / 0.8; line 2: full 500
+        assert_eq!(result.line_bonus, 583.0);
+        assert_eq!((result.score, result.rating), (5_083.0, "Hopeful"));
+        assert!(scorer.is_finished(5_000) && !scorer.is_finished(4_500));
+    }
+}
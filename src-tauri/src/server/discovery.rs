@@ -0,0 +1,236 @@
+//! mDNS / zeroconf advertisement of the server for phone remotes.
+//!
+//! Whenever `server://ready` is published with LAN access on (see
+//! `security`), the server is announced as `_karaoke._tcp` on every LAN
+//! interface, with TXT records for the port, the app id and the WebSocket
+//! bridge's port (`ws_port`, see `remote`), so a companion app or a second
+//! room (see `multiroom`) finds it without anyone typing an IP address.
+//! The TXT records carry no token: anyone on the LAN can read them, so
+//! companions still pair with the QR code or a typed token. A restart on
+//! another port re-announces, one without
+//! LAN access and app shutdown send the goodbye. `get_connection_info` reports the LAN
+//! URLs for the same server (browsers cannot browse mDNS themselves).
+
+use std::net::{IpAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use mdns_sd::{ServiceDaemon, ServiceInfo};
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+use tokio::sync::broadcast::error::RecvError;
+
+use super::{health, port, security};
+use crate::access::{require_webview, Capability};
+use crate::events::{AppEvent, EventBus};
+use crate::runtime::TaskSupervisor;
+
+pub const SERVICE_TYPE: &str = "_karaoke._tcp.local.";
+const GOODBYE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Whether the server is announced right now.
+static ADVERTISED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectionInfo {
+    pub port: u16,
+    /// `http://<ip>:<port>`, the interface with the default route first.
+    pub urls: Vec<String>,
+    /// Whether those URLs are reachable at all (see `security`).
+    pub lan_access: bool,
+    /// Operator access (see `security`).
+    pub token: String,
+    /// Guest access, what the remote QR code carries.
+    pub guest_token: String,
+    pub service_type: String,
+    /// `<host>.local.`, as announced.
+    pub mdns_host: String,
+    pub advertised: bool,
+}
+
+/// LAN address of the interface that routes to the internet, usually the
+/// one companions reach us on. UDP `connect` only picks the route; nothing
+/// is sent.
+pub fn route_ip() -> Option<IpAddr> {
+    let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect("8.8.8.8:80").ok()?;
+    let ip = socket.local_addr().ok()?.ip();
+    usable(&ip).then_some(ip)
+}
+
+/// Not loopback, unspecified or link-local.
+fn usable(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => !(v4.is_loopback() || v4.is_unspecified() || v4.is_link_local()),
+        IpAddr::V6(v6) => !(v6.is_loopback() || v6.is_unspecified() || (v6.segments()[0] & 0xffc0) == 0xfe80),
+    }
+}
+
+/// Usable interface addresses, `preferred` first, then IPv4 before IPv6.
+fn order_addrs(mut addrs: Vec<IpAddr>, preferred: Option<IpAddr>) -> Vec<IpAddr> {
+    addrs.retain(usable);
+    addrs.sort_by_key(|ip| (Some(*ip) != preferred, ip.is_ipv6(), *ip));
+    addrs.dedup();
+    addrs
+}
+
+fn lan_addrs() -> Vec<IpAddr> {
+    let addrs = if_addrs::get_if_addrs()
+        .map(|ifaces| ifaces.iter().map(|iface| iface.ip()).collect())
+        .unwrap_or_else(|e| {
+            tracing::warn!("[mdns] Cannot list network interfaces: {}", e);
+            Vec::new()
+        });
+    order_addrs(addrs, route_ip())
+}
+
+fn url_for(ip: &IpAddr, port: u16) -> String {
+    match ip {
+        IpAddr::V4(v4) => format!("http://{}:{}", v4, port),
+        IpAddr::V6(v6) => format!("http://[{}]:{}", v6, port),
+    }
+}
+
+/// This machine's name as an mDNS host (`karaoke-pc.local.`).
+pub(crate) fn mdns_host() -> String {
+    let name: String = sysinfo::System::host_name()
+        .unwrap_or_else(|| "karaoke".to_string())
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '-' })
+        .collect();
+    let name = name.trim_matches('-');
+    format!("{}.local.", if name.is_empty() { "karaoke" } else { name })
+}
+
+/// A registered announcement; `stop` withdraws it.
+struct Advertisement {
+    daemon: ServiceDaemon,
+    fullname: String,
+    port: u16,
+}
+
+impl Advertisement {
+    fn start(port: u16, ws_port: Option<u16>) -> Result<Self, String> {
+        let host = mdns_host();
+        let instance = format!("Karaoke ZERO on {}", host.trim_end_matches(".local."));
+        let addrs = lan_addrs();
+        let port_txt = port.to_string();
+        let ws_port_txt = ws_port.map(|p| p.to_string());
+        let mut properties = vec![
+            ("app", health::APP_ID),
+            ("port", port_txt.as_str()),
+            ("version", env!("CARGO_PKG_VERSION")),
+        ];
+        if let Some(ws_port) = &ws_port_txt {
+            properties.push(("ws_port", ws_port.as_str()));
+        }
+        let info = ServiceInfo::new(SERVICE_TYPE, &instance, &host, &addrs[..], port, &properties[..])
+            .map_err(|e| format!("Invalid mDNS service: {}", e))?
+            .enable_addr_auto();
+        let daemon = ServiceDaemon::new().map_err(|e| format!("Failed to start mDNS: {}", e))?;
+        let fullname = info.get_fullname().to_string();
+        daemon.register(info).map_err(|e| format!("Failed to announce {}: {}", SERVICE_TYPE, e))?;
+        tracing::info!("[mdns] Announced '{}' on port {}", instance, port);
+        Ok(Self { daemon, fullname, port })
+    }
+
+    /// Send the goodbye so browsers drop us at once, then stop the daemon.
+    fn stop(self) {
+        if let Ok(status) = self.daemon.unregister(&self.fullname) {
+            let _ = status.recv_timeout(GOODBYE_TIMEOUT);
+        }
+        let _ = self.daemon.shutdown();
+        tracing::info!("[mdns] Withdrew '{}'", self.fullname);
+    }
+}
+
+/// Announce the server each time it becomes ready. Call once the event
+/// bus is managed, before the server starts.
+pub fn spawn_advertiser(app: AppHandle) {
+    let mut events = app.state::<EventBus>().subscribe();
+    let supervisor = app.state::<TaskSupervisor>();
+    supervisor.spawn("mdns", move |token| async move {
+        let mut current: Option<Advertisement> = None;
+        loop {
+            tokio::select! {
+                _ = token.cancelled() => break,
+                received = events.recv() => match received {
+                    Ok(envelope) => {
+                        let AppEvent::ServerReady { .. } = &envelope.event else { continue };
+                        let wanted = security::lan_access().then(port::current);
+                        if current.as_ref().map(|ad| ad.port) == wanted {
+                            continue;
+                        }
+                        if let Some(old) = current.take() {
+                            old.stop();
+                        }
+                        if let Some(port) = wanted {
+                            let ws_port = crate::remote::listening_port(&app);
+                            current = Advertisement::start(port, ws_port).inspect_err(|e| tracing::warn!("[mdns] {}", e)).ok();
+                        }
+                        ADVERTISED.store(current.is_some(), Ordering::Relaxed);
+                    }
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                },
+            }
+        }
+        if let Some(ad) = current {
+            ad.stop();
+        }
+        ADVERTISED.store(false, Ordering::Relaxed);
+    });
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+/// How phones on the LAN reach the server. Carries the access token, so
+/// only the host may ask.
+#[tauri::command]
+pub fn get_connection_info(webview: tauri::Webview) -> Result<ConnectionInfo, String> {
+    require_webview(&webview, Capability::ChangeSettings)?;
+    Ok(connection_info())
+}
+
+/// `get_connection_info` without the capability check.
+pub(crate) fn connection_info() -> ConnectionInfo {
+    let port = port::current();
+    ConnectionInfo {
+        port,
+        urls: lan_addrs().iter().map(|ip| url_for(ip, port)).collect(),
+        lan_access: security::lan_access(),
+        token: security::access_token(),
+        guest_token: security::guest_token(),
+        service_type: SERVICE_TYPE.to_string(),
+        mdns_host: mdns_host(),
+        advertised: ADVERTISED.load(Ordering::Relaxed),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orders_lan_addresses() {
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        let addrs = vec![
+            ip("127.0.0.1"),
+            ip("fd00::12"),
+            ip("10.0.0.5"),
+            ip("169.254.3.4"),
+            ip("fe80::1"),
+            ip("192.168.1.20"),
+        ];
+        let ordered = order_addrs(addrs, Some(ip("192.168.1.20")));
+        assert_eq!(ordered, vec![ip("192.168.1.20"), ip("10.0.0.5"), ip("fd00::12")]);
+        assert_eq!(url_for(&ordered[2], 3000), "http://[fd00::12]:3000");
+    }
+}
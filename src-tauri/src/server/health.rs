@@ -0,0 +1,179 @@
+//! Making sure the server on our port is ours.
+//!
+//! Each app run generates a session token and hands it to the server in
+//! `KARAOKE_SESSION_TOKEN`. `GET /api/health` answers with the app id and
+//! a hash of that token (never the token itself), so a foreign service
+//! that happens to listen on the port — or another instance's server — is
+//! told apart from the one we started. The hash of the token is also what
+//! the server lock records for a server other instances may adopt.
+//!
+//! Readiness is polled over HTTP too: an accepted TCP connection only means
+//! the port is open, not that Next.js finished compiling its routes.
+//! Timing comes from settings: `server_ready_timeout_secs` (default 60)
+//! and `server_health_interval_ms` (default 500).
+
+use std::sync::{LazyLock, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Manager};
+
+use crate::db::DbState;
+
+pub const TOKEN_ENV: &str = "KARAOKE_SESSION_TOKEN";
+/// `app` field of every health response from our server.
+pub const APP_ID: &str = "karaoke-successor";
+const HEALTH_PATH: &str = "/api/health";
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+const READY_TIMEOUT_KEY: &str = "server_ready_timeout_secs";
+const INTERVAL_KEY: &str = "server_health_interval_ms";
+const DEFAULT_READY_TIMEOUT_SECS: u64 = 60;
+const DEFAULT_INTERVAL_MS: u64 = 500;
+
+static TOKEN: OnceLock<String> = OnceLock::new();
+
+static HTTP_CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    reqwest::Client::builder()
+        .timeout(PROBE_TIMEOUT)
+        .no_proxy()
+        .build()
+        .expect("Failed to build health-check HTTP client")
+});
+
+#[derive(Debug, Deserialize)]
+struct HealthResponse {
+    status: String,
+    #[serde(default)]
+    app: Option<String>,
+    #[serde(default)]
+    instance: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Identity {
+    /// Nothing accepts connections on the port.
+    Closed,
+    /// The port is open but the app is not answering yet (still compiling,
+    /// HTTP errors, slow responses).
+    Starting(String),
+    /// Something answered, but not the server we expect.
+    Foreign(String),
+    Ours,
+}
+
+/// How `wait_until_ready` polls.
+#[derive(Debug, Clone, Copy)]
+pub struct HealthConfig {
+    pub timeout: Duration,
+    pub interval: Duration,
+}
+
+impl HealthConfig {
+    pub fn load(app: &AppHandle) -> Self {
+        let setting = |key: &str| -> Option<u64> {
+            let db = app.try_state::<DbState>()?;
+            let conn = db.conn.lock().ok()?;
+            crate::scheduler::read_setting(&conn, key)?.trim().parse().ok()
+        };
+        Self {
+            timeout: Duration::from_secs(setting(READY_TIMEOUT_KEY).unwrap_or(DEFAULT_READY_TIMEOUT_SECS).clamp(5, 600)),
+            interval: Duration::from_millis(setting(INTERVAL_KEY).unwrap_or(DEFAULT_INTERVAL_MS).clamp(100, 10_000)),
+        }
+    }
+}
+
+/// Token for servers started by this run.
+pub fn session_token() -> &'static str {
+    TOKEN.get_or_init(|| {
+        // Unpredictable enough to tell processes apart; it authenticates nothing
+        let mut hasher = Sha256::new();
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0);
+        hasher.update(nanos.to_le_bytes());
+        hasher.update(std::process::id().to_le_bytes());
+        hasher.update(format!("{:p}", &nanos).as_bytes());
+        format!("{:x}", hasher.finalize())
+    })
+}
+
+/// What `/api/health` reports as `instance` for `token`.
+pub fn instance_key(token: &str) -> String {
+    format!("{:x}", Sha256::digest(format!("karaoke-health:{}", token).as_bytes()))
+}
+
+fn classify(response: Result<HealthResponse, String>, expected_instance: Option<&str>) -> Identity {
+    let response = match response {
+        Ok(response) => response,
+        Err(e) => return Identity::Foreign(e),
+    };
+    if response.app.as_deref() != Some(APP_ID) {
+        return Identity::Foreign(format!("not a {} server", APP_ID));
+    }
+    if let Some(expected) = expected_instance {
+        if response.instance.as_deref() != Some(expected) {
+            return Identity::Foreign("a server from another session".to_string());
+        }
+    }
+    if response.status != "ok" {
+        return Identity::Foreign(format!("server reports status '{}'", response.status));
+    }
+    Identity::Ours
+}
+
+/// Ask the server on `port` who it is. `expected_instance` = `None` accepts
+/// any of our servers (e.g. the dev server started by the Tauri CLI).
+pub async fn probe(port: u16, expected_instance: Option<&str>) -> Identity {
+    let url = format!("http://127.0.0.1:{}{}", port, HEALTH_PATH);
+    let response = match HTTP_CLIENT.get(&url).send().await {
+        Ok(response) => response,
+        Err(e) if e.is_connect() => return Identity::Closed,
+        Err(e) if e.is_timeout() => return Identity::Starting("no HTTP response yet".to_string()),
+        Err(e) => return Identity::Starting(format!("health check failed: {}", e)),
+    };
+    // Next.js answers 404/500 while routes are still compiling
+    if !response.status().is_success() {
+        return Identity::Starting(format!("health check returned HTTP {}", response.status()));
+    }
+    let body = response
+        .json::<HealthResponse>()
+        .await
+        .map_err(|e| format!("unexpected health response: {}", e));
+    classify(body, expected_instance)
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(app: Option<&str>, instance: Option<&str>) -> Result<HealthResponse, String> {
+        Ok(HealthResponse {
+            status: "ok".to_string(),
+            app: app.map(String::from),
+            instance: instance.map(String::from),
+        })
+    }
+
+    #[test]
+    fn only_our_session_is_ours() {
+        let key = instance_key(session_token());
+        assert_eq!(classify(response(Some(APP_ID), Some(&key)), Some(&key)), Identity::Ours);
+        assert!(matches!(classify(response(Some(APP_ID), Some("other")), Some(&key)), Identity::Foreign(_)));
+        assert!(matches!(classify(response(None, None), Some(&key)), Identity::Foreign(_)));
+        // Without an expected instance any of our servers will do
+        assert_eq!(classify(response(Some(APP_ID), None), None), Identity::Ours);
+    }
+
+    #[test]
+    fn key_does_not_reveal_token() {
+        let token = session_token();
+        assert_eq!(token, session_token());
+        let key = instance_key(token);
+        assert_ne!(key, token);
+        assert!(!key.contains(token));
+    }
+}
@@ -0,0 +1,166 @@
+//! How the server was last started, so the next launch can skip discovery.
+//!
+//! Finding the server means listing the bundle, looking runtimes up on
+//! `PATH` (`runtime::resolve`) and running `<runtime> --version`
+//! (`node_check`) — several seconds on a venue machine with a spinning
+//! disk. After a launch that became ready, `launch-plan.json` in the app
+//! config dir keeps what it resolved: the server script and the runtime.
+//! The port is not part of it; every launch picks one from the preferred
+//! port again. The next launch spawns right away if the plan still holds:
+//!   - same app version, `[server] runtime` and preferred port;
+//!   - the script still exists under the resource dir;
+//!   - the runtime binary has the size and modification time it had when
+//!     it passed `node_check`, so its check still stands.
+//!
+//! A plan that no longer holds, fails to spawn or starts a server that
+//! never becomes ready is deleted, and that launch (or the next) goes
+//! through full discovery again.
+
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use serde::{Deserialize, Serialize};
+
+use super::runtime::{Runtime, RuntimeChoice, RuntimeKind};
+
+const PLAN_FILE: &str = "launch-plan.json";
+
+/// Size and modification time (Unix seconds) of a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileStamp {
+    pub len: u64,
+    pub modified: u64,
+}
+
+impl FileStamp {
+    pub fn of(path: &Path) -> Option<Self> {
+        let metadata = std::fs::metadata(path).ok()?;
+        let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?.as_secs();
+        Some(Self { len: metadata.len(), modified })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LaunchPlan {
+    pub app_version: String,
+    pub choice: RuntimeChoice,
+    pub preferred_port: u16,
+    pub server_path: PathBuf,
+    pub runtime: RuntimeKind,
+    pub program: PathBuf,
+    pub bundled: bool,
+    pub program_stamp: FileStamp,
+    /// What `node_check` reported, for the log.
+    pub version: String,
+}
+
+impl LaunchPlan {
+    /// The plan for a server started from `server_path` on `runtime`;
+    /// `None` if the runtime binary cannot be stamped.
+    pub fn new(
+        app_version: &str,
+        choice: RuntimeChoice,
+        preferred_port: u16,
+        server_path: &Path,
+        runtime: &Runtime,
+        version: &str,
+    ) -> Option<Self> {
+        Some(Self {
+            app_version: app_version.to_string(),
+            choice,
+            preferred_port,
+            server_path: server_path.to_path_buf(),
+            runtime: runtime.kind,
+            program: runtime.program.clone(),
+            bundled: runtime.bundled,
+            program_stamp: FileStamp::of(&runtime.program)?,
+            version: version.to_string(),
+        })
+    }
+
+    pub fn runtime(&self) -> Runtime {
+        Runtime { kind: self.runtime, program: self.program.clone(), bundled: self.bundled }
+    }
+
+    /// Why the plan no longer holds for this launch, if it does not.
+    pub fn stale_reason(&self, app_version: &str, choice: RuntimeChoice, preferred_port: u16, resource_dir: &Path) -> Option<String> {
+        if self.app_version != app_version {
+            return Some(format!("made by {}", self.app_version));
+        }
+        if self.choice != choice || self.preferred_port != preferred_port {
+            return Some("server settings changed".to_string());
+        }
+        if !self.server_path.starts_with(resource_dir) || !self.server_path.is_file() {
+            return Some(format!("{} is gone", self.server_path.display()));
+        }
+        if FileStamp::of(&self.program) != Some(self.program_stamp) {
+            return Some(format!("{} changed", self.program.display()));
+        }
+        None
+    }
+}
+
+pub fn plan_path(config_dir: &Path) -> PathBuf {
+    config_dir.join(PLAN_FILE)
+}
+
+/// The saved plan; `None` if there is none or it is unreadable.
+pub fn load(config_dir: &Path) -> Option<LaunchPlan> {
+    let text = std::fs::read_to_string(plan_path(config_dir)).ok()?;
+    serde_json::from_str(&text).ok()
+}
+
+pub fn save(config_dir: &Path, plan: &LaunchPlan) -> Result<(), String> {
+    let text = serde_json::to_string_pretty(plan).map_err(|e| format!("Failed to encode launch plan: {}", e))?;
+    std::fs::create_dir_all(config_dir).map_err(|e| format!("Failed to create {}: {}", config_dir.display(), e))?;
+    let path = plan_path(config_dir);
+    std::fs::write(&path, text).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+pub fn clear(config_dir: &Path) {
+    let path = plan_path(config_dir);
+    match std::fs::remove_file(&path) {
+        Ok(()) => tracing::info!("[server] Dropped the cached launch plan"),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => tracing::warn!("[server] Failed to remove {}: {}", path.display(), e),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plan_holds_until_the_install_changes() {
+        let dir = crate::paths::test_dir("launch-plan");
+        let resources = dir.join("resources");
+        let server = resources.join("bundled").join("server").join("server.js");
+        let program = resources.join("bundled").join("bun").join("bun");
+        std::fs::create_dir_all(server.parent().unwrap()).unwrap();
+        std::fs::create_dir_all(program.parent().unwrap()).unwrap();
+        std::fs::write(&server, b"").unwrap();
+        std::fs::write(&program, b"bun").unwrap();
+
+        let runtime = Runtime { kind: RuntimeKind::Bun, program: program.clone(), bundled: true };
+        let plan = LaunchPlan::new("1.4.0", RuntimeChoice::Auto, 3000, &server, &runtime, "1.1.38").unwrap();
+        save(&dir, &plan).unwrap();
+        let loaded = load(&dir).unwrap();
+        assert_eq!(loaded, plan);
+        assert_eq!(loaded.runtime(), runtime);
+        assert_eq!(loaded.stale_reason("1.4.0", RuntimeChoice::Auto, 3000, &resources), None);
+        assert!(loaded.stale_reason("1.5.0", RuntimeChoice::Auto, 3000, &resources).is_some());
+        assert!(loaded.stale_reason("1.4.0", RuntimeChoice::Node, 3000, &resources).is_some());
+        assert!(loaded.stale_reason("1.4.0", RuntimeChoice::Auto, 3000, &dir.join("moved")).is_some());
+
+        std::fs::write(&program, b"bun 1.2").unwrap();
+        assert!(loaded.stale_reason("1.4.0", RuntimeChoice::Auto, 3000, &resources).is_some());
+
+        clear(&dir);
+        assert_eq!(load(&dir), None);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,203 @@
+//! Resource limits for the Node server sidecar.
+//!
+//! Configured from settings so low-RAM venue machines can be protected:
+//!   - `server_max_old_space_mb` — V8 heap cap, passed as
+//!     `NODE_OPTIONS=--max-old-space-size=<mb>` (also reaches the node child
+//!     of `npm run dev`);
+//!   - `server_priority` — `normal` (default), `below_normal`, `low` or
+//!     `high`; Windows priority class / Unix niceness;
+//!   - `server_rss_ceiling_mb` — if the server's resident memory (summed
+//!     over its process tree, see `stats`) stays above this for
+//!     `RSS_STRIKES` consecutive checks it is restarted;
+//!   - `server_shutdown_grace_ms` — how long the server may take to exit
+//!     after being asked to stop before it is killed (default 5000).
+
+use std::process::Command;
+use std::time::Duration;
+
+use tauri::{AppHandle, Manager};
+
+use super::{stats, ServerManager};
+use crate::db::DbState;
+use crate::runtime::{sleep_or_cancel, TaskSupervisor};
+
+const MAX_OLD_SPACE_KEY: &str = "server_max_old_space_mb";
+const PRIORITY_KEY: &str = "server_priority";
+const RSS_CEILING_KEY: &str = "server_rss_ceiling_mb";
+const SHUTDOWN_GRACE_KEY: &str = "server_shutdown_grace_ms";
+
+const DEFAULT_SHUTDOWN_GRACE_MS: u64 = 5_000;
+const MAX_SHUTDOWN_GRACE_MS: u64 = 30_000;
+
+/// Smallest heap cap accepted — below this Next.js cannot even start.
+const MIN_OLD_SPACE_MB: u32 = 256;
+
+const RSS_POLL_INTERVAL: Duration = Duration::from_secs(15);
+/// Consecutive over-ceiling samples before restarting (ignores short spikes).
+const RSS_STRIKES: u32 = 3;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ProcessPriority {
+    #[default]
+    Normal,
+    BelowNormal,
+    Low,
+    High,
+}
+
+impl ProcessPriority {
+    fn parse(value: &str) -> Self {
+        match value {
+            "below_normal" => Self::BelowNormal,
+            "low" => Self::Low,
+            "high" => Self::High,
+            _ => Self::Normal,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ServerLimits {
+    pub max_old_space_mb: Option<u32>,
+    pub priority: ProcessPriority,
+    pub rss_ceiling_mb: Option<u64>,
+    /// `None` = default grace period.
+    pub shutdown_grace_ms: Option<u64>,
+}
+
+impl ServerLimits {
+    /// Read limits from settings; missing or invalid values mean "no limit".
+    pub fn load(app: &AppHandle) -> Self {
+        let Some(db) = app.try_state::<DbState>() else {
+            return Self::default();
+        };
+        let Ok(conn) = db.conn.lock() else {
+            return Self::default();
+        };
+        let setting = |key: &str| -> Option<String> {
+            conn.query_row("SELECT value FROM app_settings WHERE key = ?1", [key], |row| row.get(0))
+                .ok()
+        };
+
+        Self {
+            max_old_space_mb: setting(MAX_OLD_SPACE_KEY)
+                .and_then(|v| v.trim().parse::<u32>().ok())
+                .filter(|&mb| mb > 0)
+                .map(|mb| mb.max(MIN_OLD_SPACE_MB)),
+            priority: setting(PRIORITY_KEY).map(|v| ProcessPriority::parse(v.trim())).unwrap_or_default(),
+            rss_ceiling_mb: setting(RSS_CEILING_KEY)
+                .and_then(|v| v.trim().parse::<u64>().ok())
+                .filter(|&mb| mb > 0),
+            shutdown_grace_ms: setting(SHUTDOWN_GRACE_KEY)
+                .and_then(|v| v.trim().parse::<u64>().ok())
+                .map(|ms| ms.min(MAX_SHUTDOWN_GRACE_MS)),
+        }
+    }
+
+    pub fn shutdown_grace(&self) -> Duration {
+        Duration::from_millis(self.shutdown_grace_ms.unwrap_or(DEFAULT_SHUTDOWN_GRACE_MS))
+    }
+
+    /// Apply the heap cap before spawning. The Windows priority class is
+    /// part of the creation flags set by `ServerCommand`.
+    pub fn apply_to_command(&self, cmd: &mut Command) {
+        if let Some(mb) = self.max_old_space_mb {
+            let mut options = std::env::var("NODE_OPTIONS").unwrap_or_default();
+            if !options.is_empty() {
+                options.push(' ');
+            }
+            options.push_str(&format!("--max-old-space-size={}", mb));
+            cmd.env("NODE_OPTIONS", options);
+        }
+    }
+
+    /// Windows priority class for `CreateProcess` (0 = inherit).
+    #[cfg(target_os = "windows")]
+    pub fn priority_class(&self) -> u32 {
+        const IDLE_PRIORITY_CLASS: u32 = 0x0000_0040;
+        const HIGH_PRIORITY_CLASS: u32 = 0x0000_0080;
+        const BELOW_NORMAL_PRIORITY_CLASS: u32 = 0x0000_4000;
+        match self.priority {
+            ProcessPriority::Normal => 0,
+            ProcessPriority::BelowNormal => BELOW_NORMAL_PRIORITY_CLASS,
+            ProcessPriority::Low => IDLE_PRIORITY_CLASS,
+            ProcessPriority::High => HIGH_PRIORITY_CLASS,
+        }
+    }
+
+    /// Apply niceness on Unix once the PID is known.
+    #[cfg(unix)]
+    pub fn apply_after_spawn(&self, pid: u32) {
+        let nice = match self.priority {
+            ProcessPriority::Normal => return,
+            ProcessPriority::BelowNormal => 5,
+            ProcessPriority::Low => 15,
+            // Negative niceness needs privileges; failure is only logged
+            ProcessPriority::High => -5,
+        };
+        let result = Command::new("renice")
+            .args(["-n", &nice.to_string(), "-p", &pid.to_string()])
+            .output();
+        match result {
+            Ok(out) if out.status.success() => tracing::info!("[server] Niceness set to {} for PID {}", nice, pid),
+            Ok(out) => tracing::warn!("[server] renice failed: {}", String::from_utf8_lossy(&out.stderr).trim()),
+            Err(e) => tracing::warn!("[server] renice unavailable: {}", e),
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn apply_after_spawn(&self, _pid: u32) {}
+}
+
+/// Start the RSS watchdog if a ceiling is configured.
+pub fn spawn_rss_watchdog(app: AppHandle, limits: ServerLimits) {
+    let Some(ceiling_mb) = limits.rss_ceiling_mb else {
+        return;
+    };
+    let supervisor = app.state::<TaskSupervisor>();
+    supervisor.spawn("server-rss-watchdog", move |token| async move {
+        let mut system = Some(sysinfo::System::new());
+        let mut strikes = 0;
+        while sleep_or_cancel(&token, RSS_POLL_INTERVAL).await {
+            let Some(pid) = app.state::<ServerManager>().pid() else {
+                strikes = 0;
+                continue;
+            };
+            let pid = sysinfo::Pid::from_u32(pid);
+            let mut sampler = system.take().unwrap_or_else(sysinfo::System::new);
+            let sampled = tauri::async_runtime::spawn_blocking(move || {
+                let rss_mb = stats::tree_rss_mb(&mut sampler, pid);
+                (sampler, rss_mb)
+            })
+            .await;
+            let Ok((sampler, rss_mb)) = sampled else { continue };
+            system = Some(sampler);
+            let Some(rss_mb) = rss_mb else {
+                continue;
+            };
+
+            if rss_mb <= ceiling_mb {
+                strikes = 0;
+                continue;
+            }
+            strikes += 1;
+            tracing::warn!("[server] RSS {} MB above ceiling {} MB ({}/{})", rss_mb, ceiling_mb, strikes, RSS_STRIKES);
+            if strikes < RSS_STRIKES {
+                continue;
+            }
+            strikes = 0;
+            // Killing waits for the old process to exit
+            let app = app.clone();
+            let _ = tauri::async_runtime::spawn_blocking(move || restart_over_ceiling(&app)).await;
+        }
+    });
+}
+
+/// Kill the server and start it again with the same command line.
+fn restart_over_ceiling(app: &AppHandle) {
+    tracing::warn!("[server] Restarting server after exceeding the memory ceiling");
+    crate::telemetry::server_restart(app, "memory");
+    if let Err(e) = app.state::<ServerManager>().restart(app) {
+        tracing::warn!("[server] {}", e);
+    }
+}
@@ -0,0 +1,186 @@
+//! PID/lock file guarding against duplicate server spawns.
+//!
+//! `server.lock` in the app data dir records which process owns the managed
+//! server and on which port it listens. It is created with `create_new`, so
+//! two app instances (or a crash-restart race) cannot both claim it. A lock
+//! whose processes are gone is stale and gets replaced.
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use super::health::{instance_key, session_token};
+
+const LOCK_FILE: &str = "server.lock";
+
+/// How long a lock without server PID (spawn in progress) is honoured.
+const STARTING_GRACE_SECS: u64 = 90;
+
+/// Lock file acquired by this process, released on shutdown.
+static HELD_LOCK: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LockInfo {
+    /// App process that spawned the server.
+    pub owner_pid: u32,
+    /// Server process, 0 while it is still being spawned.
+    pub server_pid: u32,
+    pub port: u16,
+    /// Unix seconds when the lock was written.
+    pub started_at: u64,
+    /// `health::instance_key` the server answers with.
+    #[serde(default)]
+    pub instance: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum AcquireResult {
+    /// We own the lock and should spawn the server.
+    Acquired,
+    /// A live server (or spawn in progress) belongs to another process.
+    Held(LockInfo),
+}
+
+pub fn lock_path(data_dir: &Path) -> PathBuf {
+    data_dir.join(LOCK_FILE)
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+fn process_alive(pid: u32) -> bool {
+    let mut system = sysinfo::System::new();
+    system.refresh_process(sysinfo::Pid::from_u32(pid))
+}
+
+/// Whether a lock can be discarded. `alive` reports process liveness.
+fn is_stale(info: &LockInfo, alive: impl Fn(u32) -> bool, now: u64) -> bool {
+    if info.server_pid != 0 {
+        return !alive(info.server_pid);
+    }
+    // Spawn in progress: honour it only while the owner lives and briefly
+    !alive(info.owner_pid) || now.saturating_sub(info.started_at) > STARTING_GRACE_SECS
+}
+
+fn read_lock(path: &Path) -> Option<LockInfo> {
+    let text = std::fs::read_to_string(path).ok()?;
+    serde_json::from_str(&text).ok()
+}
+
+fn write_new(path: &Path, info: &LockInfo) -> std::io::Result<()> {
+    let mut file = OpenOptions::new().write(true).create_new(true).open(path)?;
+    file.write_all(serde_json::to_string(info).unwrap_or_default().as_bytes())
+}
+
+/// Claim the server lock before spawning.
+pub fn acquire(data_dir: &Path, port: u16) -> Result<AcquireResult, String> {
+    let path = lock_path(data_dir);
+    let ours = LockInfo {
+        owner_pid: std::process::id(),
+        server_pid: 0,
+        port,
+        started_at: now_secs(),
+        instance: Some(instance_key(session_token())),
+    };
+
+    // Two attempts: the second one after removing a stale lock
+    for _ in 0..2 {
+        match write_new(&path, &ours) {
+            Ok(()) => {
+                if let Ok(mut held) = HELD_LOCK.lock() {
+                    *held = Some(path.clone());
+                }
+                return Ok(AcquireResult::Acquired);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                match read_lock(&path) {
+                    Some(info) if !is_stale(&info, process_alive, now_secs()) => {
+                        return Ok(AcquireResult::Held(info));
+                    }
+                    other => {
+                        tracing::info!("[server] Removing stale server lock: {:?}", other);
+                        std::fs::remove_file(&path)
+                            .map_err(|e| format!("Failed to remove stale lock: {}", e))?;
+                    }
+                }
+            }
+            Err(e) => return Err(format!("Failed to create server lock: {}", e)),
+        }
+    }
+    Err("Could not acquire server lock".into())
+}
+
+/// Record the spawned server's PID in the lock we hold.
+pub fn record_server(server_pid: u32, port: u16) {
+    let Some(path) = HELD_LOCK.lock().ok().and_then(|h| h.clone()) else {
+        return;
+    };
+    let info = LockInfo {
+        owner_pid: std::process::id(),
+        server_pid,
+        port,
+        started_at: now_secs(),
+        instance: Some(instance_key(session_token())),
+    };
+    let json = serde_json::to_string(&info).unwrap_or_default();
+    if let Err(e) = std::fs::write(&path, json) {
+        tracing::error!("[server] Failed to update server lock: {}", e);
+    }
+}
+
+/// Remove the lock if this process holds it.
+pub fn release() {
+    let Some(path) = HELD_LOCK.lock().ok().and_then(|mut h| h.take()) else {
+        return;
+    };
+    if read_lock(&path).map(|i| i.owner_pid == std::process::id()).unwrap_or(false) {
+        let _ = std::fs::remove_file(&path);
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(owner_pid: u32, server_pid: u32, started_at: u64) -> LockInfo {
+        LockInfo { owner_pid, server_pid, port: 3000, started_at, instance: None }
+    }
+
+    #[test]
+    fn stale_when_server_is_dead() {
+        let alive = |pid: u32| pid == 10;
+        assert!(!is_stale(&info(1, 10, 0), alive, 1000));
+        assert!(is_stale(&info(10, 11, 0), alive, 1000));
+    }
+
+    #[test]
+    fn spawn_in_progress_expires() {
+        let alive = |pid: u32| pid == 10;
+        assert!(!is_stale(&info(10, 0, 1000), alive, 1030));
+        assert!(is_stale(&info(10, 0, 1000), alive, 1000 + STARTING_GRACE_SECS + 1));
+        assert!(is_stale(&info(11, 0, 1000), alive, 1001));
+    }
+
+    #[test]
+    fn second_acquire_sees_held_lock() {
+        let dir = crate::paths::test_dir("lock");
+        std::fs::create_dir_all(&dir).unwrap();
+        let _ = std::fs::remove_file(lock_path(&dir));
+
+        assert!(matches!(acquire(&dir, 3000).unwrap(), AcquireResult::Acquired));
+        // Our own process is alive and the spawn just started
+        assert!(matches!(acquire(&dir, 3000).unwrap(), AcquireResult::Held(_)));
+        release();
+        assert!(!lock_path(&dir).exists());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,527 @@
+//! Management of the bundled Node.js (Next.js) server sidecar.
+//!
+//! `ServerManager` (managed state) owns the child process, the command line
+//! it was started with (so it can be restarted identically, e.g. by the RSS
+//! watchdog), its lifecycle status and the last lines of its output.
+//! `server_status`, `restart_server`, `stop_server` (also named
+//! `server_restart` and `server_stop`) and `server_logs` expose it to the
+//! frontend (the settings "Restart backend" button); `watchdog`
+//! restarts it after a crash, `stats` reports its resource usage,
+//! `discovery` announces it on the LAN and `remote_qr` draws its QR code.
+//! `security` decides whether the LAN may reach it at all; `shell` serves
+//! the UI from the bundle while it starts; `runtime`
+//! picks Node or Bun to run it and `node_check` vets that binary before
+//! it is spawned; `launch_plan` remembers both for the next launch.
+
+pub mod discovery;
+pub mod health;
+pub mod launch_plan;
+pub mod limits;
+pub mod lock;
+pub mod node_check;
+pub mod port;
+pub mod process;
+pub mod remote_qr;
+pub mod runtime;
+pub mod security;
+pub mod shell;
+pub mod stats;
+pub mod watchdog;
+
+use std::collections::{HashMap, VecDeque};
+use std::io::{BufRead, BufReader, Read};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+use crate::access::{require_webview, Capability};
+use crate::events::{publish, AppEvent};
+use crate::runtime::{sleep_or_cancel, TaskSupervisor};
+use limits::ServerLimits;
+
+/// Output lines kept for `server_logs`.
+const LOG_CAPACITY: usize = 500;
+
+/// Recipe for spawning the server.
+#[derive(Debug, Clone)]
+pub struct ServerCommand {
+    pub program: PathBuf,
+    pub args: Vec<String>,
+    pub cwd: PathBuf,
+    pub envs: HashMap<String, String>,
+}
+
+impl ServerCommand {
+    pub fn new(program: impl Into<PathBuf>, cwd: impl Into<PathBuf>) -> Self {
+        Self {
+            program: program.into(),
+            args: Vec::new(),
+            cwd: cwd.into(),
+            envs: HashMap::new(),
+        }
+    }
+
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    pub fn env(mut self, key: &str, value: &str) -> Self {
+        self.envs.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    fn port(&self) -> u16 {
+        self.envs.get("PORT").and_then(|p| p.parse().ok()).unwrap_or(port::PREFERRED_PORT)
+    }
+
+    /// Spawn the process tree with `limits` applied and its output piped.
+    fn spawn_child(&self, limits: &ServerLimits) -> std::io::Result<process::ServerProcess> {
+        let mut cmd = Command::new(&self.program);
+        cmd.args(&self.args)
+            .current_dir(&self.cwd)
+            .envs(&self.envs)
+            .env(health::TOKEN_ENV, health::session_token())
+            .envs(security::child_env())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        limits.apply_to_command(&mut cmd);
+        process::prepare(&mut cmd);
+        #[cfg(target_os = "windows")]
+        {
+            use std::os::windows::process::CommandExt;
+            cmd.creation_flags(process::creation_flags() | limits.priority_class());
+        }
+
+        let child = cmd.spawn()?;
+        limits.apply_after_spawn(child.id());
+        Ok(process::ServerProcess::new(child))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ServerState {
+    Stopped,
+    /// Spawned, not answering on its port yet.
+    Starting,
+    Running,
+    /// Exited without being asked to.
+    Exited,
+    /// Managed by another app instance (see `lock`).
+    External,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerStatus {
+    pub state: ServerState,
+    pub pid: Option<u32>,
+    pub port: u16,
+    pub url: String,
+    pub restarts: u32,
+    /// Epoch ms of the last (re)start.
+    pub started_at: Option<i64>,
+    pub last_exit: Option<String>,
+}
+
+struct Inner {
+    child: Option<process::ServerProcess>,
+    recipe: Option<ServerCommand>,
+    state: ServerState,
+    external_pid: Option<u32>,
+    /// What the server's `/api/health` must report (see `health`).
+    expected_instance: Option<String>,
+    restarts: u32,
+    started_at: Option<i64>,
+    last_exit: Option<String>,
+    /// From the limits the server was started with.
+    shutdown_grace: Duration,
+}
+
+type LogBuffer = Arc<Mutex<VecDeque<String>>>;
+
+pub struct ServerManager {
+    inner: Mutex<Inner>,
+    logs: LogBuffer,
+}
+
+impl Default for ServerManager {
+    fn default() -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                child: None,
+                recipe: None,
+                state: ServerState::Stopped,
+                external_pid: None,
+                expected_instance: None,
+                restarts: 0,
+                started_at: None,
+                last_exit: None,
+                shutdown_grace: ServerLimits::default().shutdown_grace(),
+            }),
+            logs: Arc::new(Mutex::new(VecDeque::new())),
+        }
+    }
+}
+
+fn now_ms() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as i64).unwrap_or(0)
+}
+
+fn push_log(logs: &LogBuffer, line: String) {
+    if let Ok(mut logs) = logs.lock() {
+        if logs.len() >= LOG_CAPACITY {
+            logs.pop_front();
+        }
+        logs.push_back(line);
+    }
+}
+
+/// Log a child stream line by line (target `server`) and keep it in the log buffer.
+fn forward_output(stream: impl Read + Send + 'static, logs: LogBuffer) {
+    std::thread::spawn(move || {
+        for line in BufReader::new(stream).lines().map_while(Result::ok) {
+            tracing::info!(target: "server", "{}", line);
+            push_log(&logs, line);
+        }
+    });
+}
+
+impl ServerManager {
+    /// Spawn `recipe` as the managed server, replacing any current one.
+    /// The old server is killed first so both never hold the port, and the
+    /// new one is only spawned once the lock is held, so no error path can
+    /// leave it running unmanaged.
+    pub fn start(&self, recipe: &ServerCommand, limits: &ServerLimits) -> std::io::Result<()> {
+        let mut inner = self.inner.lock().map_err(|e| std::io::Error::other(e.to_string()))?;
+        if let Some(mut old) = inner.child.take() {
+            old.kill_tree();
+            inner.state = ServerState::Stopped;
+        }
+
+        let mut child = recipe.spawn_child(limits)?;
+        if let Some(out) = child.child.stdout.take() {
+            forward_output(out, self.logs.clone());
+        }
+        if let Some(err) = child.child.stderr.take() {
+            forward_output(err, self.logs.clone());
+        }
+        lock::record_server(child.id(), recipe.port());
+        port::set(recipe.port());
+
+        inner.child = Some(child);
+        inner.recipe = Some(recipe.clone());
+        inner.shutdown_grace = limits.shutdown_grace();
+        inner.state = ServerState::Starting;
+        inner.external_pid = None;
+        inner.expected_instance = Some(health::instance_key(health::session_token()));
+        inner.started_at = Some(now_ms());
+        Ok(())
+    }
+
+    /// Another instance owns the server; we only use it. `instance` is what
+    /// its health endpoint must report (from the lock), if known.
+    pub fn adopt_external(&self, pid: u32, port: u16, instance: Option<String>) {
+        port::set(port);
+        if let Ok(mut inner) = self.inner.lock() {
+            inner.state = ServerState::External;
+            inner.external_pid = Some(pid).filter(|&p| p != 0);
+            inner.expected_instance = instance;
+            inner.started_at = Some(now_ms());
+        }
+    }
+
+    /// The server answered on its port.
+    pub fn mark_ready(&self) {
+        if let Ok(mut inner) = self.inner.lock() {
+            if inner.state == ServerState::Starting {
+                inner.state = ServerState::Running;
+            }
+        }
+    }
+
+    /// Kill whatever is left of a failed start and record it as a crash.
+    pub fn mark_exited(&self, reason: &str) {
+        self.stop();
+        if let Ok(mut inner) = self.inner.lock() {
+            inner.state = ServerState::Exited;
+            inner.last_exit = Some(reason.to_string());
+        }
+    }
+
+    /// PID of the managed server, if one is running.
+    pub fn pid(&self) -> Option<u32> {
+        self.inner.lock().ok()?.child.as_ref().map(|c| c.id())
+    }
+
+    pub fn expected_instance(&self) -> Option<String> {
+        self.inner.lock().ok()?.expected_instance.clone()
+    }
+
+    pub fn has_recipe(&self) -> bool {
+        self.inner.lock().map(|i| i.recipe.is_some()).unwrap_or(false)
+    }
+
+    /// Stop the managed server process (if any): ask it to exit, kill it
+    /// after the grace period. Blocking.
+    pub fn stop(&self) {
+        let (child, grace) = {
+            let Ok(mut inner) = self.inner.lock() else {
+                return;
+            };
+            if inner.state != ServerState::External {
+                inner.state = ServerState::Stopped;
+            }
+            (inner.child.take(), inner.shutdown_grace)
+        };
+        if let Some(mut child) = child {
+            match child.stop(grace) {
+                process::StopOutcome::Graceful => tracing::info!("Server process stopped"),
+                process::StopOutcome::Killed => tracing::info!("Server process killed after {:?} grace period", grace),
+                process::StopOutcome::AlreadyExited => {}
+            }
+        }
+    }
+
+    /// Kill the server and start it again with the same command line.
+    /// Limits are re-read so a changed setting applies on restart.
+    pub fn restart(&self, app: &AppHandle) -> Result<(), String> {
+        let recipe = self
+            .inner
+            .lock()
+            .map_err(|e| e.to_string())?
+            .recipe
+            .clone()
+            .ok_or("Cannot restart: this instance did not start the server")?;
+        self.stop();
+        self.start(&recipe, &ServerLimits::load(app))
+            .map_err(|e| format!("Server restart failed: {}", e))?;
+        if let Ok(mut inner) = self.inner.lock() {
+            inner.restarts += 1;
+        }
+        Ok(())
+    }
+
+    pub fn status(&self) -> ServerStatus {
+        let port = port::current();
+        let mut status = ServerStatus {
+            state: ServerState::Stopped,
+            pid: None,
+            port,
+            url: port::server_url(port),
+            restarts: 0,
+            started_at: None,
+            last_exit: None,
+        };
+        let Ok(mut inner) = self.inner.lock() else {
+            return status;
+        };
+        // Notice a server that died on its own
+        let exited = inner.child.as_mut().and_then(|c| c.try_wait());
+        if let Some(exit) = exited {
+            // Descendants (e.g. Next.js under npm) may still hold the port
+            if let Some(mut tree) = inner.child.take() {
+                tree.kill_tree();
+            }
+            inner.state = ServerState::Exited;
+            inner.last_exit = Some(exit.to_string());
+        }
+        status.state = inner.state;
+        status.pid = inner.child.as_ref().map(|c| c.id()).or(inner.external_pid);
+        status.restarts = inner.restarts;
+        status.started_at = inner.started_at;
+        status.last_exit = inner.last_exit.clone();
+        status
+    }
+
+    /// Last `limit` output lines, oldest first.
+    pub fn logs(&self, limit: usize) -> Vec<String> {
+        let Ok(logs) = self.logs.lock() else {
+            return Vec::new();
+        };
+        logs.iter().skip(logs.len().saturating_sub(limit)).cloned().collect()
+    }
+}
+
+/// How waiting for the server ended.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Readiness {
+    Ready,
+    /// The process exited while starting.
+    Exited(String),
+    /// Something else answers on the port.
+    Foreign(String),
+    /// `port_open`: the port accepted connections but the app never
+    /// answered its health check.
+    TimedOut { seconds: u64, port_open: bool },
+    Cancelled,
+}
+
+/// Poll the server's health endpoint until our server answers; marks the
+/// server ready. Publishes `server://port-open` once the port accepts
+/// connections. Gives up early if the managed process dies meanwhile or a
+/// foreign service holds the port.
+pub(crate) async fn wait_until_ready(app: &AppHandle, token: &tokio_util::sync::CancellationToken) -> Readiness {
+    let config = health::HealthConfig::load(app);
+    let deadline = std::time::Instant::now() + config.timeout;
+    let mut port_open = false;
+    let mut attempts = 0u32;
+    loop {
+        attempts += 1;
+        let port = port::current();
+        let expected = app.try_state::<ServerManager>().and_then(|m| m.expected_instance());
+        let identity = health::probe(port, expected.as_deref()).await;
+        if !port_open && identity != health::Identity::Closed {
+            port_open = true;
+            tracing::info!("Server port {} is open, waiting for the app...", port);
+            publish(app, AppEvent::ServerPortOpen { port });
+        }
+        match identity {
+            health::Identity::Ours => {
+                tracing::info!("Server is ready after {} attempts!", attempts);
+                if let Some(manager) = app.try_state::<ServerManager>() {
+                    manager.mark_ready();
+                }
+                return Readiness::Ready;
+            }
+            health::Identity::Foreign(reason) => {
+                tracing::warn!("Port {} is answered by something else: {}", port, reason);
+                return Readiness::Foreign(reason);
+            }
+            health::Identity::Starting(_) | health::Identity::Closed => {}
+        }
+
+        if let Some(status) = app.try_state::<ServerManager>().map(|m| m.status()) {
+            if status.state == ServerState::Exited {
+                let reason = status.last_exit.unwrap_or_else(|| "exited".to_string());
+                tracing::warn!("Server exited while starting: {}", reason);
+                return Readiness::Exited(reason);
+            }
+        }
+        if std::time::Instant::now() >= deadline {
+            let seconds = config.timeout.as_secs();
+            tracing::error!("Server startup timeout after {} seconds (port open: {})", seconds, port_open);
+            return Readiness::TimedOut { seconds, port_open };
+        }
+        if !sleep_or_cancel(token, config.interval).await {
+            tracing::info!("Server readiness wait cancelled");
+            return Readiness::Cancelled;
+        }
+    }
+}
+
+/// Kill the server on app exit and release the server lock.
+pub(crate) fn shutdown_server(app: &AppHandle) {
+    if let Some(manager) = app.try_state::<ServerManager>() {
+        manager.stop();
+    }
+    lock::release();
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+#[tauri::command]
+pub fn server_status(app: AppHandle) -> ServerStatus {
+    app.state::<ServerManager>().status()
+}
+
+/// Restart the managed server and wait (in the background) for it to
+/// answer again; `server://ready` follows. Also starts a stopped server.
+#[tauri::command]
+pub async fn restart_server(app: AppHandle, webview: tauri::Webview) -> Result<ServerStatus, String> {
+    require_webview(&webview, Capability::ChangeSettings)?;
+    crate::telemetry::server_restart(&app, "manual");
+    restart_and_announce(app).await
+}
+
+/// The same as `restart_server`, under its `server_*` name.
+#[tauri::command]
+pub async fn server_restart(app: AppHandle, webview: tauri::Webview) -> Result<ServerStatus, String> {
+    require_webview(&webview, Capability::ChangeSettings)?;
+    crate::telemetry::server_restart(&app, "manual");
+    restart_and_announce(app).await
+}
+
+/// `restart_server` without the caller check, for the tray menu.
+pub async fn restart_and_announce(app: AppHandle) -> Result<ServerStatus, String> {
+    let worker = app.clone();
+    tauri::async_runtime::spawn_blocking(move || worker.state::<ServerManager>().restart(&worker))
+        .await
+        .map_err(|e| e.to_string())??;
+
+    let ready_app = app.clone();
+    app.state::<TaskSupervisor>().spawn("server-restart-ready", move |token| async move {
+        if let Readiness::Ready = wait_until_ready(&ready_app, &token).await {
+            publish(&ready_app, AppEvent::ServerReady { url: port::server_url(port::current()) });
+        }
+    });
+    Ok(app.state::<ServerManager>().status())
+}
+
+/// Stop the managed server; `restart_server` brings it back.
+#[tauri::command]
+pub async fn stop_server(app: AppHandle, webview: tauri::Webview) -> Result<ServerStatus, String> {
+    require_webview(&webview, Capability::ChangeSettings)?;
+    stop_blocking(app).await
+}
+
+/// The same as `stop_server`, under its `server_*` name.
+#[tauri::command]
+pub async fn server_stop(app: AppHandle, webview: tauri::Webview) -> Result<ServerStatus, String> {
+    require_webview(&webview, Capability::ChangeSettings)?;
+    stop_blocking(app).await
+}
+
+async fn stop_blocking(app: AppHandle) -> Result<ServerStatus, String> {
+    let worker = app.clone();
+    tauri::async_runtime::spawn_blocking(move || worker.state::<ServerManager>().stop())
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(app.state::<ServerManager>().status())
+}
+
+#[tauri::command]
+pub fn server_logs(app: AppHandle, webview: tauri::Webview, limit: Option<usize>) -> Result<Vec<String>, String> {
+    require_webview(&webview, Capability::ChangeSettings)?;
+    Ok(app.state::<ServerManager>().logs(limit.unwrap_or(200).min(LOG_CAPACITY)))
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_buffer_keeps_newest_lines() {
+        let manager = ServerManager::default();
+        for i in 0..LOG_CAPACITY + 10 {
+            push_log(&manager.logs, format!("line {}", i));
+        }
+        let last = manager.logs(3);
+        assert_eq!(last, vec![
+            format!("line {}", LOG_CAPACITY + 7),
+            format!("line {}", LOG_CAPACITY + 8),
+            format!("line {}", LOG_CAPACITY + 9),
+        ]);
+        assert_eq!(manager.logs(usize::MAX).len(), LOG_CAPACITY);
+    }
+
+    #[test]
+    fn fresh_manager_is_stopped() {
+        let manager = ServerManager::default();
+        assert!(!manager.has_recipe());
+        assert_eq!(manager.status().state, ServerState::Stopped);
+        manager.stop();
+        assert_eq!(manager.status().state, ServerState::Stopped);
+    }
+}
@@ -0,0 +1,246 @@
+//! Whether a Node or Bun binary can run the server, checked before
+//! spawning it.
+//!
+//! A runtime that is too old, or built for another CPU, used to surface only
+//! as the generic readiness timeout a minute later. `check` instead:
+//!   1. reads the executable's header (ELF, PE or Mach-O) for the CPU it
+//!      was built for and compares it with the OS — an x64 Node on arm64
+//!      runs emulated if at all, a 32-bit one runs out of address space;
+//!   2. runs `<node> --version` and compares it with `MIN_NODE_VERSION`,
+//!      the minimum of the bundled Next.js (`MIN_BUN_VERSION` for Bun).
+//!
+//! A failed check is published as `server://runtime-incompatible` with a
+//! sentence naming the problem ("bundled Node is x64 but the OS is
+//! arm64"), which also becomes the startup error.
+
+use std::io::Read;
+use std::path::Path;
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use super::runtime::{Runtime, RuntimeKind};
+
+pub const INCOMPATIBLE_EVENT: &str = "server://runtime-incompatible";
+/// Next.js 16 needs Node 20.9.
+pub const MIN_NODE_VERSION: (u32, u32, u32) = (20, 9, 0);
+pub const MIN_BUN_VERSION: (u32, u32, u32) = (1, 1, 0);
+const VERSION_TIMEOUT: Duration = Duration::from_secs(10);
+const VERSION_POLL: Duration = Duration::from_millis(50);
+/// Enough for the ELF and Mach-O headers and a typical PE header offset.
+const HEADER_BYTES: usize = 4096;
+
+/// Payload of `server://runtime-incompatible`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RuntimeIncompatible {
+    pub runtime: String,
+    /// `v20.11.1`, if it ran.
+    pub version: Option<String>,
+    /// Node's name for the CPU the binary was built for (`x64`, `arm64`, `ia32`, `arm`).
+    pub arch: Option<String>,
+    pub os_arch: String,
+    pub reason: String,
+}
+
+/// A runtime that passed the check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckedRuntime {
+    pub version: String,
+    pub arch: Option<&'static str>,
+}
+
+/// Node's name for a Rust/uname architecture.
+fn node_arch(arch: &str) -> &'static str {
+    match arch {
+        "x86_64" | "amd64" | "x64" => "x64",
+        "aarch64" | "arm64" => "arm64",
+        "x86" | "i386" | "i586" | "i686" | "ia32" => "ia32",
+        a if a.starts_with("arm") => "arm",
+        _ => "unknown",
+    }
+}
+
+/// Architecture of the OS, which differs from the app's under emulation.
+fn os_arch() -> &'static str {
+    let arch = sysinfo::System::cpu_arch().map(|a| node_arch(&a.to_ascii_lowercase())).unwrap_or("unknown");
+    if arch == "unknown" {
+        node_arch(std::env::consts::ARCH)
+    } else {
+        arch
+    }
+}
+
+/// CPU an executable was built for, from its ELF, PE or Mach-O header.
+/// `None` for anything unrecognised, including universal Mach-O binaries.
+fn binary_arch(header: &[u8]) -> Option<&'static str> {
+    let u16_le = |at: usize| header.get(at..at + 2).map(|b| u16::from_le_bytes([b[0], b[1]]));
+    let u32_le = |at: usize| header.get(at..at + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+    match header.get(..4)? {
+        [0x7f, b'E', b'L', b'F'] => {
+            let machine = header.get(18..20)?;
+            let machine = if header.get(5) == Some(&2) {
+                u16::from_be_bytes([machine[0], machine[1]])
+            } else {
+                u16::from_le_bytes([machine[0], machine[1]])
+            };
+            match machine {
+                0x3e => Some("x64"),
+                0xb7 => Some("arm64"),
+                0x03 => Some("ia32"),
+                0x28 => Some("arm"),
+                _ => None,
+            }
+        }
+        [b'M', b'Z', ..] => {
+            let pe = u32_le(0x3c)? as usize;
+            if header.get(pe..pe + 4)? != b"PE\0\0" {
+                return None;
+            }
+            match u16_le(pe + 4)? {
+                0x8664 => Some("x64"),
+                0xaa64 => Some("arm64"),
+                0x014c => Some("ia32"),
+                0x01c4 => Some("arm"),
+                _ => None,
+            }
+        }
+        // 64-bit Mach-O, little-endian
+        [0xcf, 0xfa, 0xed, 0xfe] => match u32_le(4)? {
+            0x0100_0007 => Some("x64"),
+            0x0100_000c => Some("arm64"),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn read_header(program: &Path) -> Option<Vec<u8>> {
+    let mut file = std::fs::File::open(program).ok()?;
+    let mut header = Vec::with_capacity(HEADER_BYTES);
+    file.by_ref().take(HEADER_BYTES as u64).read_to_end(&mut header).ok()?;
+    Some(header)
+}
+
+/// `(major, minor, patch)` from `v20.11.1` (Node) or `1.1.38` (Bun).
+fn parse_version(output: &str) -> Option<(u32, u32, u32)> {
+    let version = output.trim().trim_start_matches('v');
+    let mut parts = version.split(|c: char| !c.is_ascii_digit()).map(|part| part.parse::<u32>());
+    Some((parts.next()?.ok()?, parts.next()?.ok()?, parts.next().and_then(|p| p.ok()).unwrap_or(0)))
+}
+
+/// Human name for the runtime in messages.
+fn describe(runtime: &Runtime) -> String {
+    format!("{} {}", if runtime.bundled { "bundled" } else { "system" }, runtime.kind.name())
+}
+
+/// Run `<program> --version`, killing it after `VERSION_TIMEOUT`.
+fn run_version(program: &Path) -> Result<String, String> {
+    let mut child = crate::media::ffmpeg::hidden_command(program)
+        .arg("--version")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("could not be run: {}", e))?;
+    let started = Instant::now();
+    loop {
+        match child.try_wait() {
+            Ok(Some(_)) => break,
+            Ok(None) if started.elapsed() >= VERSION_TIMEOUT => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("did not answer --version within {} s", VERSION_TIMEOUT.as_secs()));
+            }
+            Ok(None) => std::thread::sleep(VERSION_POLL),
+            Err(e) => return Err(format!("could not be run: {}", e)),
+        }
+    }
+    let output = child.wait_with_output().map_err(|e| format!("could not be run: {}", e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let detail = stderr.lines().find(|l| !l.trim().is_empty()).unwrap_or("no output");
+        return Err(format!("failed on --version ({}): {}", output.status, detail.trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Problem with a binary built for `arch` on `os`, if any.
+fn arch_problem(arch: &str, os: &str) -> Option<String> {
+    if arch == os || os == "unknown" {
+        return None;
+    }
+    if arch == "ia32" && (os == "x64" || os == "arm64") {
+        return Some(format!("is 32-bit (ia32) but the OS is {}", os));
+    }
+    Some(format!("is {} but the OS is {}", arch, os))
+}
+
+/// Check `runtime` before the server is spawned with it.
+pub fn check(runtime: &Runtime) -> Result<CheckedRuntime, RuntimeIncompatible> {
+    let program = runtime.program.as_path();
+    let name = describe(runtime);
+    let os = os_arch();
+    let arch = read_header(program).and_then(|header| binary_arch(&header));
+    let fail = |version: Option<String>, problem: String| RuntimeIncompatible {
+        runtime: program.to_string_lossy().to_string(),
+        version,
+        arch: arch.map(str::to_string),
+        os_arch: os.to_string(),
+        reason: format!("{} {}", name, problem),
+    };
+
+    if let Some(problem) = arch.and_then(|arch| arch_problem(arch, os)) {
+        return Err(fail(None, problem));
+    }
+    let output = run_version(program).map_err(|problem| fail(None, problem))?;
+    let version = output.lines().next().unwrap_or_default().to_string();
+    let parsed = parse_version(&version).ok_or_else(|| fail(Some(version.clone()), format!("reported an unreadable version {:?}", version)))?;
+    let minimum = match runtime.kind {
+        RuntimeKind::Node => MIN_NODE_VERSION,
+        RuntimeKind::Bun => MIN_BUN_VERSION,
+    };
+    if parsed < minimum {
+        let (major, minor, patch) = minimum;
+        return Err(fail(
+            Some(version.clone()),
+            format!("is {} but the server needs {}.{}.{} or newer", version, major, minor, patch),
+        ));
+    }
+    Ok(CheckedRuntime { version, arch })
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_architecture_and_version() {
+        let mut elf = vec![0x7f, b'E', b'L', b'F', 2, 1, 1, 0];
+        elf.resize(18, 0);
+        elf.extend_from_slice(&0xb7u16.to_le_bytes());
+        assert_eq!(binary_arch(&elf), Some("arm64"));
+
+        let mut pe = vec![0u8; 0x80];
+        pe[..2].copy_from_slice(b"MZ");
+        pe[0x3c..0x40].copy_from_slice(&0x40u32.to_le_bytes());
+        pe[0x40..0x44].copy_from_slice(b"PE\0\0");
+        pe[0x44..0x46].copy_from_slice(&0x014cu16.to_le_bytes());
+        assert_eq!(binary_arch(&pe), Some("ia32"));
+        assert_eq!(binary_arch(b"#!/bin/sh\n"), None);
+
+        assert_eq!(arch_problem("x64", "arm64").as_deref(), Some("is x64 but the OS is arm64"));
+        assert_eq!(arch_problem("ia32", "x64").as_deref(), Some("is 32-bit (ia32) but the OS is x64"));
+        assert_eq!(arch_problem("arm64", "arm64"), None);
+
+        assert_eq!(parse_version("v20.11.1\n"), Some((20, 11, 1)));
+        assert_eq!(parse_version("1.1.38"), Some((1, 1, 38)));
+        assert!(parse_version("v18.19.0").unwrap() < MIN_NODE_VERSION);
+        assert_eq!(parse_version("node"), None);
+    }
+}
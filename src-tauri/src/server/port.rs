@@ -0,0 +1,95 @@
+//! Port selection for the Node server.
+//!
+//! The server prefers port 3000 (what the dev setup and older companion
+//! links expect) but moves to the next free port in `SCAN_RANGE` when
+//! something else already listens there, and asks the OS for any free port
+//! as a last resort. The chosen port is passed to the child as `PORT` and
+//! reported to the frontend through `get_server_port`.
+
+use std::net::{Ipv4Addr, SocketAddr, TcpListener};
+use std::ops::RangeInclusive;
+use std::sync::atomic::{AtomicU16, Ordering};
+
+use super::security;
+
+pub const PREFERRED_PORT: u16 = 3000;
+const SCAN_RANGE: RangeInclusive<u16> = 3000..=3099;
+
+/// Port the managed (or adopted) server listens on.
+static SERVER_PORT: AtomicU16 = AtomicU16::new(PREFERRED_PORT);
+
+pub fn current() -> u16 {
+    SERVER_PORT.load(Ordering::Relaxed)
+}
+
+pub(crate) fn set(port: u16) {
+    SERVER_PORT.store(port, Ordering::Relaxed);
+}
+
+/// URL the webview loads for `port`.
+pub fn server_url(port: u16) -> String {
+    format!("http://localhost:{}", port)
+}
+
+/// Loopback address for probing the server.
+pub fn probe_addr(port: u16) -> SocketAddr {
+    SocketAddr::from((Ipv4Addr::LOCALHOST, port))
+}
+
+/// Whether the server could listen on `port`, probed on the interface it
+/// binds (`security::bind_addr`): all of them with LAN access, loopback
+/// otherwise. The probe listener is dropped immediately, freeing the port
+/// again.
+pub fn port_free(port: u16) -> bool {
+    TcpListener::bind((security::bind_addr(), port)).is_ok()
+}
+
+fn pick_with(preferred: u16, free: impl Fn(u16) -> bool) -> Option<u16> {
+    std::iter::once(preferred)
+        .chain(SCAN_RANGE.filter(|&p| p != preferred))
+        .find(|&p| free(p))
+}
+
+/// Pick a port for a server about to be spawned.
+pub fn pick_free_port(preferred: u16) -> Result<u16, String> {
+    if let Some(port) = pick_with(preferred, port_free) {
+        return Ok(port);
+    }
+    let listener = TcpListener::bind((security::bind_addr(), 0))
+        .map_err(|e| format!("No free port for the server: {}", e))?;
+    listener
+        .local_addr()
+        .map(|a| a.port())
+        .map_err(|e| format!("No free port for the server: {}", e))
+}
+
+/// Port the frontend (and companions) should connect to.
+#[tauri::command]
+pub fn get_server_port() -> u16 {
+    current()
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefers_requested_port_then_scans() {
+        assert_eq!(pick_with(3000, |_| true), Some(3000));
+        assert_eq!(pick_with(3000, |p| p > 3002), Some(3003));
+        // A preferred port outside the range is still tried first
+        assert_eq!(pick_with(8080, |p| p == 8080 || p == 3000), Some(8080));
+        assert_eq!(pick_with(3000, |_| false), None);
+    }
+
+    #[test]
+    fn skips_occupied_port() {
+        let taken = TcpListener::bind((security::bind_addr(), 0)).unwrap();
+        let port = taken.local_addr().unwrap().port();
+        assert_ne!(pick_free_port(port).unwrap(), port);
+    }
+}
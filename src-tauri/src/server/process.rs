@@ -0,0 +1,269 @@
+//! Stopping the server process tree: ask it to exit, then force it.
+//!
+//! The server is often not a single process — `bun run dev` / `npm run dev`
+//! start a shell and Next.js below it — so every signal goes to the whole
+//! tree:
+//!   - Unix: the server leads its own process group; SIGTERM, and after the
+//!     grace period SIGKILL, go to the group.
+//!   - Windows: the server runs in its own process group (for CTRL_BREAK,
+//!     which Node reports as SIGBREAK) and in a Job Object that is
+//!     terminated on kill. The job is also kill-on-close, so the tree dies
+//!     with the app even after a crash.
+//!
+//! Whatever is still alive after the grace period is killed.
+//!
+//! On Windows the server also gets `CREATE_NO_WINDOW`: a hidden console
+//! that its descendants inherit, so neither node nor an npm/bun tree flashes
+//! a terminal. Start the app with `--debug-console` (or set
+//! `KARAOKE_DEBUG_CONSOLE=1`) to see the server's console instead.
+
+use std::process::{Child, Command, ExitStatus};
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
+
+const EXIT_POLL: Duration = Duration::from_millis(50);
+pub const DEBUG_CONSOLE_FLAG: &str = "--debug-console";
+const DEBUG_CONSOLE_ENV: &str = "KARAOKE_DEBUG_CONSOLE";
+
+static DEBUG_CONSOLE: LazyLock<bool> = LazyLock::new(|| {
+    std::env::args().any(|a| a == DEBUG_CONSOLE_FLAG)
+        || std::env::var(DEBUG_CONSOLE_ENV).map(|v| v == "1" || v == "true").unwrap_or(false)
+});
+
+/// Whether the server's console should be visible (Windows).
+pub fn debug_console() -> bool {
+    *DEBUG_CONSOLE
+}
+
+/// Windows creation flags for the server: its own process group (for
+/// CTRL_BREAK) and, unless debugging, no console window.
+#[cfg(windows)]
+pub fn creation_flags() -> u32 {
+    const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+    if debug_console() {
+        CREATE_NEW_PROCESS_GROUP
+    } else {
+        CREATE_NEW_PROCESS_GROUP | CREATE_NO_WINDOW
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StopOutcome {
+    AlreadyExited,
+    /// Exited on its own within the grace period.
+    Graceful,
+    Killed,
+}
+
+/// Make the process started by `cmd` the root of a tree we can signal.
+pub fn prepare(cmd: &mut Command) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        cmd.process_group(0);
+    }
+    #[cfg(windows)]
+    {
+        // Combined with the priority class by `ServerCommand`; the job is
+        // attached after spawning
+        let _ = cmd;
+    }
+}
+
+#[cfg(windows)]
+mod job {
+    use std::os::windows::io::AsRawHandle;
+    use std::process::Child;
+
+    use windows_sys::Win32::Foundation::{CloseHandle, HANDLE};
+    use windows_sys::Win32::System::JobObjects::{
+        AssignProcessToJobObject, CreateJobObjectW, JobObjectExtendedLimitInformation, SetInformationJobObject,
+        TerminateJobObject, JOBOBJECT_EXTENDED_LIMIT_INFORMATION, JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE,
+    };
+
+    pub struct Job(HANDLE);
+
+    // SAFETY: a job handle may be used and closed from any thread
+    unsafe impl Send for Job {}
+    unsafe impl Sync for Job {}
+
+    impl Job {
+        /// Put `child` (and everything it spawns from now on) in a new
+        /// kill-on-close job.
+        pub fn attach(child: &Child) -> Option<Self> {
+            // SAFETY: plain Win32 calls; every handle is checked before use
+            unsafe {
+                let handle = CreateJobObjectW(std::ptr::null(), std::ptr::null());
+                if handle.is_null() {
+                    return None;
+                }
+                let job = Job(handle);
+                let mut info: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = std::mem::zeroed();
+                info.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+                let configured = SetInformationJobObject(
+                    job.0,
+                    JobObjectExtendedLimitInformation,
+                    &info as *const _ as *const _,
+                    std::mem::size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+                );
+                if configured == 0 || AssignProcessToJobObject(job.0, child.as_raw_handle() as HANDLE) == 0 {
+                    return None;
+                }
+                Some(job)
+            }
+        }
+
+        pub fn terminate(&self) {
+            // SAFETY: handle owned by self
+            unsafe {
+                TerminateJobObject(self.0, 1);
+            }
+        }
+    }
+
+    impl Drop for Job {
+        fn drop(&mut self) {
+            // SAFETY: handle owned by self; closing it kills the job's processes
+            unsafe {
+                CloseHandle(self.0);
+            }
+        }
+    }
+}
+
+/// The spawned server and a handle on all of its descendants.
+pub struct ServerProcess {
+    pub child: Child,
+    #[cfg(windows)]
+    job: Option<job::Job>,
+}
+
+impl ServerProcess {
+    /// Wrap a child spawned from a `prepare`d command.
+    pub fn new(child: Child) -> Self {
+        #[cfg(windows)]
+        {
+            let job = job::Job::attach(&child);
+            if job.is_none() {
+                tracing::warn!("[server] Could not attach server to a job object; descendants may outlive it");
+            }
+            Self { child, job }
+        }
+        #[cfg(not(windows))]
+        {
+            Self { child }
+        }
+    }
+
+    pub fn id(&self) -> u32 {
+        self.child.id()
+    }
+
+    pub fn try_wait(&mut self) -> Option<ExitStatus> {
+        self.child.try_wait().ok().flatten()
+    }
+
+    /// Ask the whole tree to shut down. Returns whether the request could
+    /// be delivered.
+    fn request_terminate(&self) -> bool {
+        #[cfg(unix)]
+        {
+            // SAFETY: kill(2) has no memory-safety preconditions; a negative
+            // pid addresses the process group led by the server
+            unsafe { libc::kill(-(self.id() as libc::pid_t), libc::SIGTERM) == 0 }
+        }
+        #[cfg(windows)]
+        {
+            use windows_sys::Win32::System::Console::{GenerateConsoleCtrlEvent, CTRL_BREAK_EVENT};
+            // Only reaches the group if we share a console with it (debug
+            // builds attached to a terminal); otherwise the grace period
+            // ends in a kill.
+            // SAFETY: plain Win32 call on a process group id
+            unsafe { GenerateConsoleCtrlEvent(CTRL_BREAK_EVENT, self.id()) != 0 }
+        }
+        #[cfg(not(any(unix, windows)))]
+        {
+            false
+        }
+    }
+
+    /// Kill every process of the tree that is still alive.
+    pub fn kill_tree(&mut self) {
+        #[cfg(unix)]
+        // SAFETY: see `request_terminate`; ESRCH for an empty group is fine
+        unsafe {
+            libc::kill(-(self.id() as libc::pid_t), libc::SIGKILL);
+        }
+        #[cfg(windows)]
+        if let Some(job) = &self.job {
+            job.terminate();
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+
+    fn wait_for_exit(&mut self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        loop {
+            match self.child.try_wait() {
+                Ok(Some(_)) => return true,
+                Ok(None) if Instant::now() < deadline => std::thread::sleep(EXIT_POLL),
+                // Still running at the deadline, or cannot be queried
+                _ => return false,
+            }
+        }
+    }
+
+    /// Stop the tree, giving it `grace` to exit by itself. Blocking.
+    pub fn stop(&mut self, grace: Duration) -> StopOutcome {
+        let outcome = if self.try_wait().is_some() {
+            StopOutcome::AlreadyExited
+        } else if !grace.is_zero() && self.request_terminate() && self.wait_for_exit(grace) {
+            StopOutcome::Graceful
+        } else {
+            StopOutcome::Killed
+        };
+        // Also sweeps descendants that outlived their parent
+        self.kill_tree();
+        outcome
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::process::Stdio;
+
+    fn spawn(script: &str) -> ServerProcess {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", script]).stdout(Stdio::piped());
+        prepare(&mut cmd);
+        ServerProcess::new(cmd.spawn().unwrap())
+    }
+
+    #[test]
+    fn sigterm_stops_within_grace() {
+        let mut process = spawn("exec sleep 30");
+        assert_eq!(process.stop(Duration::from_secs(5)), StopOutcome::Graceful);
+        assert_eq!(process.stop(Duration::from_secs(5)), StopOutcome::AlreadyExited);
+    }
+
+    #[test]
+    fn kill_reaches_grandchildren() {
+        // Shell and its background child both ignore SIGTERM
+        let mut process = spawn("trap '' TERM; sleep 30 & wait");
+        let mut stdout = process.child.stdout.take().unwrap();
+        std::thread::sleep(Duration::from_millis(200));
+        assert_eq!(process.stop(Duration::from_millis(300)), StopOutcome::Killed);
+        // EOF only once the grandchild holding the pipe is gone too
+        let started = Instant::now();
+        stdout.read_to_end(&mut Vec::new()).unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
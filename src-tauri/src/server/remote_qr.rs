@@ -0,0 +1,140 @@
+//! QR code for the phone remote.
+//!
+//! `generate_remote_qr` encodes the remote UI's LAN URL, with the guest
+//! token (see `security`), as a PNG or SVG `data:` URL the player screen can show in an
+//! `<img>` between performances ("scan to request a song"). Whoever scans
+//! it gets in as a guest, never as an operator.
+
+use base64::Engine;
+use image::{GrayImage, Luma};
+use qrcode::render::svg;
+use qrcode::{Color, EcLevel, QrCode};
+use serde::{Deserialize, Serialize};
+
+use super::{discovery, security};
+
+use crate::access::{require_webview, Capability};
+
+const REMOTE_PATH: &str = "/?view=remote";
+/// Modules of light border on each side, as the spec asks for.
+const QUIET_ZONE: u32 = 4;
+const DEFAULT_SIZE: u32 = 512;
+const MAX_SIZE: u32 = 4096;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QrFormat {
+    #[default]
+    Png,
+    Svg,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteQr {
+    /// What the code encodes.
+    pub url: String,
+    /// `data:image/png;base64,…` or `data:image/svg+xml;base64,…`.
+    pub data_url: String,
+    /// Pixels per side.
+    pub size: u32,
+}
+
+/// The remote UI on `base` (`http://<ip>:<port>`), authenticated by the guest `token`.
+fn remote_url(base: &str, token: &str) -> String {
+    format!("{}{}&token={}", base, REMOTE_PATH, token)
+}
+
+fn encode(text: &str) -> Result<QrCode, String> {
+    QrCode::with_error_correction_level(text.as_bytes(), EcLevel::M).map_err(|e| format!("Cannot encode QR code: {}", e))
+}
+
+/// Whole pixels per module, so phones see crisp edges; at least 1.
+fn render_png(code: &QrCode, size: u32) -> Result<(Vec<u8>, u32), String> {
+    let modules = code.width() as u32;
+    let scale = (size / (modules + 2 * QUIET_ZONE)).max(1);
+    let side = (modules + 2 * QUIET_ZONE) * scale;
+    let colors = code.to_colors();
+    let image = GrayImage::from_fn(side, side, |x, y| {
+        let (mx, my) = ((x / scale).checked_sub(QUIET_ZONE), (y / scale).checked_sub(QUIET_ZONE));
+        let dark = match (mx, my) {
+            (Some(mx), Some(my)) if mx < modules && my < modules => colors[(my * modules + mx) as usize] == Color::Dark,
+            _ => false,
+        };
+        Luma([if dark { 0 } else { 255 }])
+    });
+    let mut png = Vec::new();
+    image
+        .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+        .map_err(|e| format!("Failed to encode QR image: {}", e))?;
+    Ok((png, side))
+}
+
+fn render_svg(code: &QrCode, size: u32) -> String {
+    code.render::<svg::Color>().min_dimensions(size, size).quiet_zone(true).build()
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+/// QR code of the remote URL on the main LAN address; `size` in pixels
+/// (default 512). Needs LAN access.
+#[tauri::command]
+pub async fn generate_remote_qr(webview: tauri::Webview, format: Option<QrFormat>, size: Option<u32>) -> Result<RemoteQr, String> {
+    require_webview(&webview, Capability::ManageQueue)?;
+    let size = size.unwrap_or(DEFAULT_SIZE).clamp(64, MAX_SIZE);
+    let format = format.unwrap_or_default();
+    if !security::lan_access() {
+        return Err("LAN access is off: turn it on so phones can reach the server".to_string());
+    }
+    tauri::async_runtime::spawn_blocking(move || {
+        let base = discovery::connection_info()
+            .urls
+            .into_iter()
+            .next()
+            .ok_or("No LAN address: connect this computer to a network first")?;
+        let url = remote_url(&base, &security::guest_token());
+        let code = encode(&url)?;
+        let b64 = base64::engine::general_purpose::STANDARD;
+        let (data_url, size) = match format {
+            QrFormat::Png => {
+                let (png, side) = render_png(&code, size)?;
+                (format!("data:image/png;base64,{}", b64.encode(png)), side)
+            }
+            QrFormat::Svg => (format!("data:image/svg+xml;base64,{}", b64.encode(render_svg(&code, size))), size),
+        };
+        tracing::debug!("[qr] Remote QR for {}", base);
+        Ok(RemoteQr { url, data_url, size })
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_scaled_code_with_quiet_zone() {
+        let url = remote_url("http://192.168.1.20:3000", "abc123");
+        assert_eq!(url, "http://192.168.1.20:3000/?view=remote&token=abc123");
+        let code = encode(&url).unwrap();
+        let modules = code.width() as u32;
+
+        let (png, side) = render_png(&code, 512).unwrap();
+        assert!(side <= 512 && side % (modules + 2 * QUIET_ZONE) == 0);
+        let image = image::load_from_memory(&png).unwrap().to_luma8();
+        assert_eq!(image.width(), side);
+        let scale = side / (modules + 2 * QUIET_ZONE);
+        // Border is light, the finder pattern's corner is dark
+        assert_eq!(image.get_pixel(0, 0).0[0], 255);
+        assert_eq!(image.get_pixel(QUIET_ZONE * scale, QUIET_ZONE * scale).0[0], 0);
+
+        assert!(render_svg(&code, 256).contains("<svg"));
+    }
+}
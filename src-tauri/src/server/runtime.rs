@@ -0,0 +1,178 @@
+//! Which JavaScript runtime runs the server, and how it is launched.
+//!
+//! The standalone Next.js server runs on Node or on Bun, which starts it
+//! several times faster. Either can ship with the app (`bundled/node/`,
+//! `bundled/bun/`, filled by `scripts/prepare-bundle.mjs` from
+//! `portable-node/` and `portable-bun/`) or be found on `PATH`.
+//! `[server] runtime` in `config.toml` picks one:
+//!   - `auto` (default): bundled Bun, bundled Node, system Node, system
+//!     Bun — a build that ships Bun means to run on it;
+//!   - `node` or `bun`: only that runtime, bundled before system.
+//!
+//! `Runtime::command` adds what differs between them: Bun has no
+//! `--max-old-space-size`, so the heap cap from `limits` becomes `--smol`
+//! and a JSC RAM size instead.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+
+use super::limits::ServerLimits;
+use super::ServerCommand;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuntimeChoice {
+    #[default]
+    Auto,
+    Node,
+    Bun,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuntimeKind {
+    Node,
+    Bun,
+}
+
+impl RuntimeKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            RuntimeKind::Node => "Node",
+            RuntimeKind::Bun => "Bun",
+        }
+    }
+
+    /// Executable inside `bundled/`.
+    fn bundled_path(self) -> &'static [&'static str] {
+        match (self, cfg!(target_os = "windows")) {
+            (RuntimeKind::Node, true) => &["node", "node.exe"],
+            (RuntimeKind::Node, false) => &["node", "bin", "node"],
+            (RuntimeKind::Bun, true) => &["bun", "bun.exe"],
+            (RuntimeKind::Bun, false) => &["bun", "bun"],
+        }
+    }
+
+    /// Name looked up with `where` (Windows) and `which`.
+    fn executable(self) -> (&'static str, &'static str) {
+        match self {
+            RuntimeKind::Node => ("node.exe", "node"),
+            RuntimeKind::Bun => ("bun.exe", "bun"),
+        }
+    }
+}
+
+/// A runtime found to start the server with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Runtime {
+    pub kind: RuntimeKind,
+    pub program: PathBuf,
+    /// Ships with the app rather than found on `PATH`.
+    pub bundled: bool,
+}
+
+impl Runtime {
+    /// Command line that runs `server_path` on this runtime.
+    pub fn command(&self, server_path: &Path, cwd: &Path, port: &str, limits: &ServerLimits) -> ServerCommand {
+        let mut recipe = ServerCommand::new(&self.program, cwd);
+        if let (RuntimeKind::Bun, Some(mb)) = (self.kind, limits.max_old_space_mb) {
+            // JSC sizes its heap from the RAM it believes it has
+            recipe = recipe.arg("--smol").env("BUN_JSC_forceRAMSize", &(u64::from(mb) * 1024 * 1024).to_string());
+        }
+        recipe
+            .arg(server_path.to_string_lossy())
+            .env("PORT", port)
+            .env("NODE_ENV", "production")
+    }
+}
+
+/// The runtime of `kind` bundled under `resource_dir`, if it ships.
+#[cfg(any(target_os = "windows", target_os = "macos", target_os = "linux"))]
+pub fn bundled(resource_dir: &Path, kind: RuntimeKind) -> Option<PathBuf> {
+    let path = kind.bundled_path().iter().fold(resource_dir.join("bundled"), |path, part| path.join(part));
+    if path.exists() {
+        tracing::info!("Found bundled {} at: {:?}", kind.name(), path);
+        return Some(path);
+    }
+    None
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+pub fn bundled(_resource_dir: &Path, _kind: RuntimeKind) -> Option<PathBuf> {
+    None
+}
+
+/// First `program` on `PATH` according to `finder` (`where` or `which`).
+fn find_with(finder: &str, program: &str) -> Option<PathBuf> {
+    let output = Command::new(finder).arg(program).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let path_str = String::from_utf8_lossy(&output.stdout);
+    let p = PathBuf::from(path_str.lines().next()?.trim());
+    p.exists().then_some(p)
+}
+
+/// The runtime of `kind` on `PATH`.
+pub fn system(kind: RuntimeKind) -> Option<PathBuf> {
+    let (windows_name, unix_name) = kind.executable();
+    let found = find_with("where", windows_name).or_else(|| find_with("which", unix_name));
+    if let Some(p) = &found {
+        tracing::info!("Found system {} at: {:?}", kind.name(), p);
+    }
+    found
+}
+
+/// Runtimes tried for `choice`, in order, as (kind, bundled).
+fn candidates(choice: RuntimeChoice) -> &'static [(RuntimeKind, bool)] {
+    match choice {
+        RuntimeChoice::Auto => &[
+            (RuntimeKind::Bun, true),
+            (RuntimeKind::Node, true),
+            (RuntimeKind::Node, false),
+            (RuntimeKind::Bun, false),
+        ],
+        RuntimeChoice::Node => &[(RuntimeKind::Node, true), (RuntimeKind::Node, false)],
+        RuntimeChoice::Bun => &[(RuntimeKind::Bun, true), (RuntimeKind::Bun, false)],
+    }
+}
+
+/// The runtime to start the bundled server with, per `choice`.
+pub fn resolve(resource_dir: &Path, choice: RuntimeChoice) -> Option<Runtime> {
+    candidates(choice).iter().find_map(|&(kind, is_bundled)| {
+        let program = if is_bundled { bundled(resource_dir, kind) } else { system(kind) }?;
+        Some(Runtime { kind, program, bundled: is_bundled })
+    })
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefers_a_bundled_runtime_and_adapts_the_command() {
+        let res = crate::paths::test_dir("runtime");
+        let bun = RuntimeKind::Bun.bundled_path().iter().fold(res.join("bundled"), |path, part| path.join(part));
+        std::fs::create_dir_all(bun.parent().unwrap()).unwrap();
+        std::fs::write(&bun, b"").unwrap();
+
+        let found = resolve(&res, RuntimeChoice::Auto).unwrap();
+        assert_eq!((found.kind, found.bundled), (RuntimeKind::Bun, true));
+        assert_eq!(found.program, bun);
+        let _ = std::fs::remove_dir_all(&res);
+
+        let limits = ServerLimits { max_old_space_mb: Some(512), ..Default::default() };
+        let recipe = found.command(Path::new("server.js"), Path::new("."), "3000", &limits);
+        assert_eq!(recipe.args, vec!["--smol", "server.js"]);
+        assert_eq!(recipe.envs.get("BUN_JSC_forceRAMSize").map(String::as_str), Some("536870912"));
+        let node = Runtime { kind: RuntimeKind::Node, program: PathBuf::from("node"), bundled: false };
+        assert_eq!(node.command(Path::new("server.js"), Path::new("."), "3000", &limits).args, vec!["server.js"]);
+        assert_eq!(candidates(RuntimeChoice::Node).iter().filter(|(kind, _)| *kind == RuntimeKind::Bun).count(), 0);
+    }
+}
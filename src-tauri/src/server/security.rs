@@ -0,0 +1,254 @@
+//! Who may reach the server.
+//!
+//! By default the server binds 127.0.0.1 and only this machine's webview
+//! reaches it. With `[server] lan_access = true` it binds every interface
+//! and requires a token from all clients. There are two:
+//!   - the **access token** makes a client an operator. The server gets it
+//!     as `KARAOKE_ACCESS_TOKEN`, and the app's own pages are loaded with
+//!     `?token=`, which the server's middleware swaps for a cookie; the
+//!     pages also see it as `window.__KARAOKE_ACCESS_TOKEN__`. Only the
+//!     host sees it elsewhere (`get_connection_info`, `remote_status`).
+//!   - the **guest token** only lets a phone in as a guest (browse,
+//!     request songs, follow along). The server gets it as
+//!     `KARAOKE_GUEST_TOKEN`; it is what the remote QR code carries, so a
+//!     QR photographed off the screen never hands out operator rights.
+//!
+//! Neither is advertised over mDNS. Both are kept in `app_settings` so
+//! phones paired once keep working across restarts.
+//! `regenerate_access_token` replaces both, which locks every paired
+//! phone out, and restarts the server to pick them up.
+
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::RwLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use sha2::{Digest, Sha256};
+use tauri::plugin::{Builder as PluginBuilder, TauriPlugin};
+use tauri::webview::PageLoadEvent;
+use tauri::{AppHandle, Manager, Runtime, Url};
+
+use super::{port, wait_until_ready, Readiness, ServerManager, ServerState};
+use crate::access::{require_webview, Capability};
+use crate::db::DbState;
+use crate::runtime::TaskSupervisor;
+
+pub const ACCESS_TOKEN_ENV: &str = "KARAOKE_ACCESS_TOKEN";
+pub const GUEST_TOKEN_ENV: &str = "KARAOKE_GUEST_TOKEN";
+const HOST_ENV: &str = "HOSTNAME";
+const TOKEN_SETTING: &str = "server_access_token";
+const GUEST_TOKEN_SETTING: &str = "server_guest_token";
+
+static LAN_ACCESS: AtomicBool = AtomicBool::new(false);
+static TOKEN: RwLock<String> = RwLock::new(String::new());
+static GUEST_TOKEN: RwLock<String> = RwLock::new(String::new());
+
+pub fn lan_access() -> bool {
+    LAN_ACCESS.load(Ordering::Relaxed)
+}
+
+/// Interface the server listens on.
+pub fn bind_addr() -> IpAddr {
+    IpAddr::V4(if lan_access() { Ipv4Addr::UNSPECIFIED } else { Ipv4Addr::LOCALHOST })
+}
+
+/// The token operators authenticate with (empty before `sync`).
+pub fn access_token() -> String {
+    TOKEN.read().map(|token| token.clone()).unwrap_or_default()
+}
+
+/// The token guests authenticate with (empty before `sync`).
+pub fn guest_token() -> String {
+    GUEST_TOKEN.read().map(|token| token.clone()).unwrap_or_default()
+}
+
+/// Environment for the server process; without a token the server lets
+/// everyone in, which only this machine can be when bound to loopback.
+pub(crate) fn child_env() -> Vec<(&'static str, String)> {
+    let mut env = vec![(HOST_ENV, bind_addr().to_string())];
+    if lan_access() {
+        env.push((ACCESS_TOKEN_ENV, access_token()));
+        env.push((GUEST_TOKEN_ENV, guest_token()));
+    }
+    env
+}
+
+/// URL the main webview loads, authenticated in LAN mode.
+pub fn ui_url(port: u16) -> String {
+    let url = port::server_url(port);
+    if lan_access() {
+        format!("{}/?token={}", url, access_token())
+    } else {
+        url
+    }
+}
+
+/// 128 random bits as hex. `RandomState` is keyed from the OS random
+/// source; time and pid only keep two draws in one process apart.
+pub(crate) fn generate_token() -> String {
+    let mut hasher = Sha256::new();
+    for i in 0u8..4 {
+        hasher.update(RandomState::new().hash_one(i).to_le_bytes());
+    }
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0);
+    hasher.update(nanos.to_le_bytes());
+    hasher.update(std::process::id().to_le_bytes());
+    let digest = hasher.finalize();
+    digest[..16].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// The token stored under `key`, or a new one (stored) on first use.
+fn load_token(app: &AppHandle, key: &str) -> Result<String, String> {
+    let db = app.state::<DbState>();
+    let conn = db.conn.lock().map_err(|e| e.to_string())?;
+    if let Some(token) = crate::scheduler::read_setting(&conn, key).filter(|t| !t.trim().is_empty()) {
+        return Ok(token.trim().to_string());
+    }
+    store_token(&conn, key, &generate_token())
+}
+
+fn store_token(conn: &rusqlite::Connection, key: &str, token: &str) -> Result<String, String> {
+    conn.execute("INSERT OR REPLACE INTO app_settings (key, value) VALUES (?1, ?2)", (key, token))
+        .map_err(|e| format!("Failed to save access token: {}", e))?;
+    Ok(token.to_string())
+}
+
+fn set_token(slot: &RwLock<String>, token: String) {
+    if let Ok(mut current) = slot.write() {
+        *current = token;
+    }
+}
+
+/// Apply `lan_access` from the config. A change restarts a running server
+/// so it binds the new interface.
+pub fn sync(app: &AppHandle, lan_access: bool) {
+    for (slot, key) in [(&TOKEN, TOKEN_SETTING), (&GUEST_TOKEN, GUEST_TOKEN_SETTING)] {
+        if slot.read().is_ok_and(|token| token.is_empty()) {
+            match load_token(app, key) {
+                Ok(token) => set_token(slot, token),
+                Err(e) => tracing::warn!("[security] {}", e),
+            }
+        }
+    }
+    // Never open the server to the LAN without tokens to check
+    let lan_access = lan_access && !access_token().is_empty() && !guest_token().is_empty();
+    if LAN_ACCESS.swap(lan_access, Ordering::Relaxed) == lan_access {
+        return;
+    }
+    tracing::info!("[security] LAN access {}", if lan_access { "on" } else { "off" });
+    let running = app.try_state::<ServerManager>().is_some_and(|m| m.status().state == ServerState::Running);
+    if running {
+        restart_and_reload(app.clone());
+    }
+}
+
+/// Restart the server in the background with the current mode and token,
+/// then reload the main window so it authenticates again.
+fn restart_and_reload(app: AppHandle) {
+    crate::telemetry::server_restart(&app, "lan_access");
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = super::restart_and_announce(app.clone()).await {
+            tracing::error!("[security] {}", e);
+            return;
+        }
+        let reload_app = app.clone();
+        app.state::<TaskSupervisor>().spawn("server-reauth", move |token| async move {
+            if let Readiness::Ready = wait_until_ready(&reload_app, &token).await {
+                reload_main_window(&reload_app);
+            }
+        });
+    });
+}
+
+fn reload_main_window(app: &AppHandle) {
+    let Some(window) = app.get_webview_window("main") else { return };
+    let url = if super::shell::is_active() {
+        super::shell::shell_url()
+    } else {
+        ui_url(port::current()).parse::<Url>().map_err(|e| e.to_string())
+    };
+    match url {
+        Ok(url) => {
+            if let Err(e) = window.navigate(url) {
+                tracing::error!("[security] Failed to reload the main window: {}", e);
+            }
+        }
+        Err(e) => tracing::error!("[security] Invalid server URL: {}", e),
+    }
+}
+
+/// Our own server's pages, which may see the token.
+fn is_server_page(url: &Url) -> bool {
+    super::shell::is_shell_url(url)
+        || (url.scheme() == "http"
+            && url.port() == Some(port::current())
+            && url.host_str().is_some_and(|host| matches!(host, "localhost" | "127.0.0.1")))
+}
+
+fn token_script() -> String {
+    let token = lan_access().then(access_token);
+    format!("window.__KARAOKE_ACCESS_TOKEN__ = {};", serde_json::to_string(&token).unwrap_or_default())
+}
+
+/// Hands the token to the app's pages once they have loaded.
+pub fn plugin<R: Runtime>() -> TauriPlugin<R> {
+    PluginBuilder::new("server-security")
+        .on_page_load(|webview, payload| {
+            if payload.event() == PageLoadEvent::Finished && is_server_page(payload.url()) {
+                let _ = webview.eval(&token_script());
+            }
+        })
+        .build()
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+/// Replace the access and guest tokens, locking out every phone paired
+/// with the old ones. Restarts the server when LAN access is on. Returns
+/// the new access token.
+#[tauri::command]
+pub fn regenerate_access_token(app: AppHandle, webview: tauri::Webview) -> Result<String, String> {
+    require_webview(&webview, Capability::ChangeSettings)?;
+    let (token, guest) = {
+        let db = app.state::<DbState>();
+        let conn = db.conn.lock().map_err(|e| e.to_string())?;
+        (store_token(&conn, TOKEN_SETTING, &generate_token())?, store_token(&conn, GUEST_TOKEN_SETTING, &generate_token())?)
+    };
+    set_token(&TOKEN, token.clone());
+    set_token(&GUEST_TOKEN, guest);
+    tracing::info!("[security] Access tokens regenerated");
+    if lan_access() {
+        restart_and_reload(app);
+    }
+    Ok(token)
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_are_fresh_hex() {
+        let (a, b) = (generate_token(), generate_token());
+        assert_eq!(a.len(), 32);
+        assert!(a.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn only_our_server_pages_see_the_token() {
+        let page = |url: &str| is_server_page(&url.parse().unwrap());
+        let port = port::current();
+        assert!(page(&format!("http://localhost:{}/?view=remote", port)));
+        assert!(!page(&format!("http://192.168.1.20:{}/", port)));
+        assert!(!page("https://example.com/"));
+    }
+}
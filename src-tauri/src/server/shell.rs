@@ -0,0 +1,481 @@
+//! The UI shell, served from the bundle over `app://`.
+//!
+//! The main window used to stay behind the splash until the Node server
+//! answered. Release builds with `[server] static_shell = true` (the
+//! default) instead load `app://localhost/` at once, answered here:
+//!   - `/_next/static/…`, files in `public/` and the pages Next.js
+//!     prerendered at build time (`.next/server/app/<route>.html`, and
+//!     `<route>.rsc` for client-side navigation) come straight from
+//!     `bundled/server/`;
+//!   - everything else, `/api/…` above all, is forwarded to the server,
+//!     with the access token attached in LAN mode. While it starts (or
+//!     restarts) requests wait up to `SERVER_WAIT` for it, then get `503`
+//!     with `Retry-After`.
+//!
+//! A scheme handler answers with one buffer, so no forwarded body is held
+//! whole: a `GET` asks the server for at most `MAX_BODY` bytes with a
+//! `Range`, and a longer answer comes back as `206` with the first
+//! `MAX_BODY` bytes (media elements ask for the rest themselves). Larger
+//! answers the server cannot serve in ranges end in `502`, and event
+//! streams in `501`: like WebSocket upgrades, which cannot go through a
+//! scheme handler either, pages that need them connect to the server's
+//! port directly.
+//!
+//! When the server failed to start or the watchdog gave up, forwarded
+//! requests are answered at once with `503` and `X-Karaoke-Degraded: 1`,
+//! so the UI can stay up read-only on the Tauri commands instead of
+//! hanging.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+
+use tauri::http::{header, Request, Response, StatusCode};
+use tauri::{AppHandle, Manager, UriSchemeResponder, Url};
+use tokio::sync::broadcast::error::RecvError;
+
+use super::{port, security, ServerManager, ServerState};
+use crate::events::{AppEvent, EventBus, EventEnvelope};
+use crate::runtime::TaskSupervisor;
+
+pub const SHELL_SCHEME: &str = "app";
+pub const DEGRADED_HEADER: &str = "X-Karaoke-Degraded";
+/// How long a forwarded request waits for a starting server.
+const SERVER_WAIT: Duration = Duration::from_secs(10);
+/// `Retry-After` (seconds) when it is still starting after that.
+const RETRY_AFTER: u64 = 2;
+/// Most of a forwarded response body held at once.
+const MAX_BODY: u64 = 8 * 1024 * 1024;
+const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+
+/// The main window shows the shell.
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+/// Startup failed or the watchdog gave up; nothing will answer.
+static UNAVAILABLE: AtomicBool = AtomicBool::new(false);
+
+static CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    // Redirects go back to the webview, which follows them on `app://`
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap_or_else(|_| reqwest::Client::new())
+});
+
+pub fn is_active() -> bool {
+    ACTIVE.load(Ordering::Relaxed)
+}
+
+fn shell_root(app: &AppHandle) -> Option<PathBuf> {
+    app.path().resource_dir().ok().map(|dir| dir.join("bundled").join("server"))
+}
+
+/// The bundle has a prerendered start page and its static assets.
+fn available(root: &Path) -> bool {
+    root.join(".next").join("server").join("app").join("index.html").is_file() && root.join(".next").join("static").is_dir()
+}
+
+pub fn shell_url() -> Result<Url, String> {
+    // Custom schemes are served from http://<scheme>.localhost on Windows/Android
+    let url = if cfg!(any(windows, target_os = "android")) {
+        format!("http://{}.localhost/", SHELL_SCHEME)
+    } else {
+        format!("{}://localhost/", SHELL_SCHEME)
+    };
+    url.parse().map_err(|e| format!("Invalid shell URL: {}", e))
+}
+
+pub fn is_shell_url(url: &Url) -> bool {
+    match (url.scheme(), url.host_str()) {
+        (SHELL_SCHEME, Some("localhost")) => true,
+        ("http" | "https", Some(host)) => host == format!("{}.localhost", SHELL_SCHEME),
+        _ => false,
+    }
+}
+
+/// Show the shell in the main window instead of the splash. `false` when
+/// it is off, a dev build, or the bundle has no prerendered shell.
+pub fn open(app: &AppHandle) -> bool {
+    if cfg!(debug_assertions) || !crate::config::current(app).server.static_shell {
+        return false;
+    }
+    let Some(root) = shell_root(app).filter(|root| available(root)) else {
+        tracing::info!("[shell] No prerendered shell in the bundle, waiting for the server");
+        return false;
+    };
+    let Some(window) = app.get_webview_window("main") else { return false };
+    let navigated = shell_url().and_then(|url| window.navigate(url).map_err(|e| e.to_string()));
+    if let Err(e) = navigated {
+        tracing::error!("[shell] Failed to open the shell: {}", e);
+        return false;
+    }
+    ACTIVE.store(true, Ordering::Relaxed);
+    tracing::info!("[shell] Serving the UI from {}", root.display());
+    crate::desktop::splash::close(app);
+    true
+}
+
+/// Track whether the server can still come up. Call once the event bus
+/// is managed, before the server starts.
+pub fn spawn_status_watch(app: AppHandle) {
+    let mut events = app.state::<EventBus>().subscribe();
+    app.state::<TaskSupervisor>().spawn("shell-status", move |token| async move {
+        loop {
+            tokio::select! {
+                _ = token.cancelled() => break,
+                received = events.recv() => match received {
+                    Ok(envelope) => note_status(&envelope),
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                },
+            }
+        }
+    });
+}
+
+fn note_status(envelope: &Arc<EventEnvelope>) {
+    match envelope.event {
+        AppEvent::ServerError { .. } | AppEvent::ServerTimeout { .. } | AppEvent::ServerRuntimeIncompatible(_) | AppEvent::ServerGaveUp(_) => {
+            UNAVAILABLE.store(true, Ordering::Relaxed)
+        }
+        AppEvent::ServerStarting { .. } | AppEvent::ServerReady { .. } | AppEvent::ServerRecovered(_) => {
+            UNAVAILABLE.store(false, Ordering::Relaxed)
+        }
+        _ => {}
+    }
+}
+
+pub(crate) fn content_type(path: &Path) -> &'static str {
+    let ext = path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase).unwrap_or_default();
+    match ext.as_str() {
+        "html" => "text/html; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "json" | "map" => "application/json",
+        "webmanifest" => "application/manifest+json",
+        "rsc" => "text/x-component",
+        "txt" => "text/plain; charset=utf-8",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        "wasm" => "application/wasm",
+        "mp3" => "audio/mpeg",
+        "wav" => "audio/wav",
+        "ogg" | "opus" => "audio/ogg",
+        "m4a" => "audio/mp4",
+        "flac" => "audio/flac",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Percent-decoded path segments; `None` for anything that could leave
+/// the bundle.
+pub(crate) fn segments(path: &str) -> Option<Vec<String>> {
+    let mut out = Vec::new();
+    for raw in path.split('/').filter(|s| !s.is_empty()) {
+        let mut bytes = Vec::with_capacity(raw.len());
+        let mut iter = raw.bytes();
+        while let Some(b) = iter.next() {
+            if b == b'%' {
+                let hex = [iter.next()?, iter.next()?];
+                bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+            } else {
+                bytes.push(b);
+            }
+        }
+        let segment = String::from_utf8(bytes).ok()?;
+        if segment == "." || segment == ".." || segment.contains(['/', '\\', ':', '\0']) {
+            return None;
+        }
+        out.push(segment);
+    }
+    Some(out)
+}
+
+/// File under `root` answering `path`, and whether it may be cached
+/// forever. `rsc` asks for the prerendered payload instead of the HTML.
+fn resolve(root: &Path, path: &str, rsc: bool) -> Option<(PathBuf, bool)> {
+    let segments = segments(path)?;
+    let join = |base: PathBuf, parts: &[String]| parts.iter().fold(base, |p, part| p.join(part));
+    if segments.len() > 2 && segments[0] == "_next" && segments[1] == "static" {
+        let file = join(root.join(".next").join("static"), &segments[2..]);
+        return file.is_file().then_some((file, true));
+    }
+    if segments.first().is_some_and(|s| s == "_next") {
+        return None;
+    }
+    let public = join(root.join("public"), &segments);
+    if public.is_file() {
+        return Some((public, false));
+    }
+    if segments.last().is_some_and(|s| s.contains('.')) {
+        return None;
+    }
+    let ext = if rsc { "rsc" } else { "html" };
+    let (name, dirs) = segments.split_last().map(|(last, dirs)| (last.as_str(), dirs)).unwrap_or(("index", &[]));
+    let page = join(root.join(".next").join("server").join("app"), dirs).join(format!("{}.{}", name, ext));
+    page.is_file().then_some((page, false))
+}
+
+fn empty(status: StatusCode) -> Response<Vec<u8>> {
+    Response::builder().status(status).body(Vec::new()).unwrap_or_default()
+}
+
+fn degraded(reason: &str) -> Response<Vec<u8>> {
+    let body = serde_json::json!({ "error": reason, "degraded": true }).to_string();
+    Response::builder()
+        .status(StatusCode::SERVICE_UNAVAILABLE)
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(DEGRADED_HEADER, "1")
+        .body(body.into_bytes())
+        .unwrap_or_else(|_| empty(StatusCode::SERVICE_UNAVAILABLE))
+}
+
+async fn serve_file(path: &Path, immutable: bool, head: bool) -> Response<Vec<u8>> {
+    match tokio::fs::read(path).await {
+        Ok(bytes) => Response::builder()
+            .header(header::CONTENT_TYPE, content_type(path))
+            .header(header::CACHE_CONTROL, if immutable { IMMUTABLE } else { "no-cache" })
+            .body(if head { Vec::new() } else { bytes })
+            .unwrap_or_else(|_| empty(StatusCode::INTERNAL_SERVER_ERROR)),
+        Err(e) => {
+            tracing::warn!("[shell] Failed to read {}: {}", path.display(), e);
+            empty(StatusCode::NOT_FOUND)
+        }
+    }
+}
+
+fn starting() -> Response<Vec<u8>> {
+    Response::builder()
+        .status(StatusCode::SERVICE_UNAVAILABLE)
+        .header(header::RETRY_AFTER, RETRY_AFTER.to_string())
+        .body(Vec::new())
+        .unwrap_or_else(|_| empty(StatusCode::SERVICE_UNAVAILABLE))
+}
+
+/// Whether the server is up now. `None` while it may still come up.
+fn server_up(app: &AppHandle) -> Option<bool> {
+    if UNAVAILABLE.load(Ordering::Relaxed) {
+        return Some(false);
+    }
+    let state = app.try_state::<ServerManager>().map(|m| m.status().state);
+    matches!(state, Some(ServerState::Running | ServerState::External)).then_some(true)
+}
+
+/// Wait up to `SERVER_WAIT` for the server to answer, woken by its
+/// lifecycle events; `None` if it is still starting by then.
+async fn wait_for_server(app: &AppHandle) -> Option<bool> {
+    let mut events = app.state::<EventBus>().subscribe();
+    let deadline = tokio::time::Instant::now() + SERVER_WAIT;
+    loop {
+        if let Some(up) = server_up(app) {
+            return Some(up);
+        }
+        match tokio::time::timeout_at(deadline, events.recv()).await {
+            Err(_) => return None,
+            Ok(Err(RecvError::Closed)) => return Some(false),
+            Ok(_) => {}
+        }
+    }
+}
+
+/// `range` (a `Range` header) asking for at most `MAX_BODY` bytes; `None`
+/// for one this cannot narrow, which is forwarded as it is.
+fn capped_range(range: Option<&str>) -> Option<String> {
+    let Some(range) = range else { return Some(format!("bytes=0-{}", MAX_BODY - 1)) };
+    let spec = range.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') {
+        return None;
+    }
+    let (from, to) = spec.split_once('-')?;
+    if from.is_empty() {
+        let suffix: u64 = to.trim().parse().ok()?;
+        return Some(format!("bytes=-{}", suffix.min(MAX_BODY)));
+    }
+    let start: u64 = from.trim().parse().ok()?;
+    let last = start + MAX_BODY - 1;
+    let end = if to.trim().is_empty() { last } else { to.trim().parse::<u64>().ok()?.min(last) };
+    Some(format!("bytes={}-{}", start, end))
+}
+
+async fn forward(app: &AppHandle, request: Request<Vec<u8>>) -> Response<Vec<u8>> {
+    match wait_for_server(app).await {
+        Some(true) => {}
+        Some(false) => return degraded("The server is not running"),
+        None => return starting(),
+    }
+    let port = port::current();
+    let target = format!("http://127.0.0.1:{}{}", port, request.uri().path_and_query().map(|pq| pq.as_str()).unwrap_or("/"));
+    let Ok(method) = reqwest::Method::from_bytes(request.method().as_str().as_bytes()) else {
+        return empty(StatusCode::METHOD_NOT_ALLOWED);
+    };
+    let mut outgoing = CLIENT.request(method, &target);
+    for (name, value) in request.headers() {
+        // The server checks the origin of server actions against its host
+        if name == header::HOST || name == header::ORIGIN {
+            continue;
+        }
+        outgoing = outgoing.header(name.as_str(), value.as_bytes());
+    }
+    if request.headers().contains_key(header::ORIGIN) {
+        outgoing = outgoing.header(header::ORIGIN.as_str(), port::server_url(port));
+    }
+    if security::lan_access() && !request.headers().contains_key(header::AUTHORIZATION) {
+        outgoing = outgoing.bearer_auth(security::access_token());
+    }
+    let asked_range = request.headers().contains_key(header::RANGE);
+    if request.method() == tauri::http::Method::GET {
+        if let Some(range) = capped_range(request.headers().get(header::RANGE).and_then(|v| v.to_str().ok())) {
+            outgoing = outgoing.header(header::RANGE.as_str(), range);
+        }
+    }
+    let response = match outgoing.body(request.into_body()).send().await {
+        Ok(response) => response,
+        Err(e) => {
+            tracing::warn!("[shell] Forwarding to the server failed: {}", e);
+            return degraded("The server did not answer");
+        }
+    };
+
+    let event_stream = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/event-stream"));
+    if event_stream {
+        return empty(StatusCode::NOT_IMPLEMENTED);
+    }
+    // We asked for a range the webview did not: hand a whole short body
+    // back as the `200` it expects
+    let status = response.status().as_u16();
+    let whole = status == 206
+        && !asked_range
+        && response
+            .headers()
+            .get(reqwest::header::CONTENT_RANGE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.rsplit('/').next())
+            .and_then(|total| total.parse::<u64>().ok())
+            .is_some_and(|total| total <= MAX_BODY);
+
+    let mut builder = Response::builder().status(if whole { 200 } else { status });
+    for (name, value) in response.headers() {
+        if name.as_str().eq_ignore_ascii_case("transfer-encoding") || name.as_str().eq_ignore_ascii_case("connection") {
+            continue;
+        }
+        if whole && name.as_str().eq_ignore_ascii_case("content-range") {
+            continue;
+        }
+        builder = builder.header(name.as_str(), value.as_bytes());
+    }
+    match read_capped(response).await {
+        Ok(Some(body)) => builder.body(body).unwrap_or_else(|_| empty(StatusCode::BAD_GATEWAY)),
+        Ok(None) => {
+            tracing::warn!("[shell] The server's answer is over {} bytes and not ranged", MAX_BODY);
+            empty(StatusCode::BAD_GATEWAY)
+        }
+        Err(e) => {
+            tracing::warn!("[shell] Reading the server's response failed: {}", e);
+            empty(StatusCode::BAD_GATEWAY)
+        }
+    }
+}
+
+/// The body, read chunk by chunk; `None` once it grows past `MAX_BODY`.
+async fn read_capped(mut response: reqwest::Response) -> Result<Option<Vec<u8>>, reqwest::Error> {
+    if response.content_length().is_some_and(|len| len > MAX_BODY) {
+        return Ok(None);
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if (body.len() + chunk.len()) as u64 > MAX_BODY {
+            return Ok(None);
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(Some(body))
+}
+
+async fn respond(app: &AppHandle, request: Request<Vec<u8>>) -> Response<Vec<u8>> {
+    let Some(root) = shell_root(app) else { return empty(StatusCode::NOT_FOUND) };
+    let head = request.method() == tauri::http::Method::HEAD;
+    if head || request.method() == tauri::http::Method::GET {
+        // Segment prefetches have no prerendered file of their own
+        let rsc = request.headers().contains_key("rsc");
+        let local = if request.headers().contains_key("next-router-segment-prefetch") {
+            None
+        } else {
+            resolve(&root, request.uri().path(), rsc)
+        };
+        if let Some((path, immutable)) = local {
+            return serve_file(&path, immutable, head).await;
+        }
+    }
+    forward(app, request).await
+}
+
+/// Handler for every `app://` request.
+pub fn handle(app: AppHandle, request: Request<Vec<u8>>, responder: UriSchemeResponder) {
+    tauri::async_runtime::spawn(async move {
+        responder.respond(respond(&app, request).await);
+    });
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_assets_pages_and_refuses_escapes() {
+        let root = crate::paths::test_dir("shell");
+        let app_dir = root.join(".next").join("server").join("app");
+        std::fs::create_dir_all(app_dir.join("mobile")).unwrap();
+        std::fs::create_dir_all(root.join(".next").join("static").join("chunks").join("[id]")).unwrap();
+        std::fs::create_dir_all(root.join("public")).unwrap();
+        std::fs::write(app_dir.join("index.html"), "<html>").unwrap();
+        std::fs::write(app_dir.join("index.rsc"), "0:[]").unwrap();
+        std::fs::write(app_dir.join("mobile").join("queue.html"), "<html>").unwrap();
+        std::fs::write(root.join(".next").join("static").join("chunks").join("[id]").join("page.js"), "").unwrap();
+        std::fs::write(root.join("public").join("logo.svg"), "<svg/>").unwrap();
+
+        assert_eq!(resolve(&root, "/", false), Some((app_dir.join("index.html"), false)));
+        assert_eq!(resolve(&root, "/", true), Some((app_dir.join("index.rsc"), false)));
+        assert_eq!(resolve(&root, "/mobile/queue", false), Some((app_dir.join("mobile").join("queue.html"), false)));
+        let chunk = resolve(&root, "/_next/static/chunks/%5Bid%5D/page.js", false).unwrap();
+        assert!(chunk.1 && chunk.0.ends_with("page.js"));
+        assert_eq!(resolve(&root, "/logo.svg", false).map(|(_, immutable)| immutable), Some(false));
+        assert_eq!(resolve(&root, "/api/songs", false), None);
+        assert_eq!(resolve(&root, "/_next/static/../../secret", false), None);
+        assert_eq!(resolve(&root, "/%2e%2e/secret", false), None);
+        assert!(available(&root));
+        let _ = std::fs::remove_dir_all(&root);
+
+        assert!(is_shell_url(&"app://localhost/".parse().unwrap()));
+        assert!(is_shell_url(&"http://app.localhost/queue".parse().unwrap()));
+        assert!(!is_shell_url(&"http://localhost:3000/".parse().unwrap()));
+    }
+
+    #[test]
+    fn forwarded_ranges_are_capped() {
+        assert_eq!(capped_range(None), Some(format!("bytes=0-{}", MAX_BODY - 1)));
+        assert_eq!(capped_range(Some("bytes=10-19")), Some("bytes=10-19".to_string()));
+        assert_eq!(capped_range(Some("bytes=100-")), Some(format!("bytes=100-{}", 100 + MAX_BODY - 1)));
+        assert_eq!(capped_range(Some("bytes=-100")), Some("bytes=-100".to_string()));
+        assert_eq!(capped_range(Some("bytes=0-1,5-9")), None);
+        assert_eq!(capped_range(Some("items=0-1")), None);
+    }
+}
@@ -0,0 +1,136 @@
+//! Resource usage of the server for the diagnostics panel.
+//!
+//! CPU and memory are summed over the whole server tree (npm → Next.js),
+//! sampled with `sysinfo`. CPU usage is measured between two refreshes, so
+//! the sampler is kept in managed state and the first call does a short
+//! double sample.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use serde::Serialize;
+use sysinfo::{Pid, System};
+use tauri::{AppHandle, Manager};
+
+use super::limits::ServerLimits;
+use super::ServerManager;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerStats {
+    pub pid: Option<u32>,
+    /// Processes in the server tree.
+    pub processes: usize,
+    /// Percent of one core, summed over the tree.
+    pub cpu_percent: f32,
+    pub rss_mb: u64,
+    /// Configured `server_rss_ceiling_mb`, for comparison.
+    pub rss_ceiling_mb: Option<u64>,
+    pub uptime_secs: Option<u64>,
+    pub restarts: u32,
+}
+
+#[derive(Default)]
+pub struct ServerStatsState {
+    system: Mutex<Option<System>>,
+}
+
+/// `root` and every process whose parent chain leads to it.
+fn tree_of(root: Pid, parents: &HashMap<Pid, Pid>) -> Vec<Pid> {
+    let mut tree = vec![root];
+    let mut i = 0;
+    while i < tree.len() {
+        let current = tree[i];
+        tree.extend(parents.iter().filter(|(_, &parent)| parent == current).map(|(&pid, _)| pid));
+        i += 1;
+    }
+    tree
+}
+
+fn sample(state: &ServerStatsState, root: Pid) -> Result<(usize, f32, u64), String> {
+    let mut system = state.system.lock().map_err(|e| e.to_string())?;
+    let first = system.is_none();
+    let system = system.get_or_insert_with(System::new);
+    system.refresh_processes();
+    if first {
+        // CPU usage needs a previous sample
+        std::thread::sleep(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL);
+        system.refresh_processes();
+    }
+
+    Ok(tree_usage(system, root))
+}
+
+/// (processes, CPU percent, RSS in MB) of the tree under `root`.
+fn tree_usage(system: &System, root: Pid) -> (usize, f32, u64) {
+    let parents: HashMap<Pid, Pid> = system
+        .processes()
+        .iter()
+        .filter_map(|(&pid, process)| process.parent().map(|parent| (pid, parent)))
+        .collect();
+    let tree = tree_of(root, &parents);
+    let (cpu, memory) = tree
+        .iter()
+        .filter_map(|pid| system.process(*pid))
+        .fold((0.0f32, 0u64), |(cpu, memory), p| (cpu + p.cpu_usage(), memory + p.memory()));
+    (tree.len(), cpu, memory / (1024 * 1024))
+}
+
+/// Resident memory (MB) of the tree under `root`, for the RSS ceiling:
+/// under `npm run dev` the server is the node child, not `root`. `None`
+/// once `root` is gone. Blocking: it reads the whole process table.
+pub(crate) fn tree_rss_mb(system: &mut System, root: Pid) -> Option<u64> {
+    system.refresh_processes();
+    system.process(root)?;
+    Some(tree_usage(system, root).2)
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+#[tauri::command]
+pub async fn get_server_stats(app: AppHandle) -> Result<ServerStats, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let status = app.state::<ServerManager>().status();
+        let now = super::now_ms();
+        let mut stats = ServerStats {
+            pid: status.pid,
+            processes: 0,
+            cpu_percent: 0.0,
+            rss_mb: 0,
+            rss_ceiling_mb: ServerLimits::load(&app).rss_ceiling_mb,
+            uptime_secs: status.started_at.map(|t| (now - t).max(0) as u64 / 1000),
+            restarts: status.restarts,
+        };
+        if let Some(pid) = status.pid {
+            let (processes, cpu, rss) = sample(&app.state::<ServerStatsState>(), Pid::from_u32(pid))?;
+            stats.processes = processes;
+            stats.cpu_percent = cpu;
+            stats.rss_mb = rss;
+        }
+        Ok(stats)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tree_follows_grandchildren_only() {
+        let pid = Pid::from_u32;
+        // 10 (npm) → 11 (sh) → 12 (next); 20 is unrelated, as is its child
+        let parents: HashMap<Pid, Pid> =
+            [(pid(11), pid(10)), (pid(12), pid(11)), (pid(21), pid(20)), (pid(10), pid(1))].into_iter().collect();
+        let mut tree = tree_of(pid(10), &parents);
+        tree.sort();
+        assert_eq!(tree, vec![pid(10), pid(11), pid(12)]);
+    }
+}
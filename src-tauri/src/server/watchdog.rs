@@ -0,0 +1,160 @@
+//! Crash watchdog for the Node server.
+//!
+//! Polls the `ServerManager` and, when the server exits without being asked
+//! to, restarts it with exponential backoff. Progress is published so the
+//! UI can show a reconnect overlay instead of a dead page:
+//!   - `server://restarting` before each attempt (with the delay);
+//!   - `server://recovered` once the server answers again;
+//!   - `server://gave-up` after `server_restart_max_attempts` (default 5)
+//!     failed attempts. A manual `restart_server` re-arms the watchdog.
+//!
+//! The first exit of a series is saved as a crash report (see `crash`).
+
+use std::time::Duration;
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+use super::{wait_until_ready, Readiness, ServerManager, ServerState};
+use crate::db::DbState;
+use crate::events::{publish, AppEvent};
+use crate::runtime::{sleep_or_cancel, TaskSupervisor};
+
+pub const RESTARTING_EVENT: &str = "server://restarting";
+pub const RECOVERED_EVENT: &str = "server://recovered";
+pub const GAVE_UP_EVENT: &str = "server://gave-up";
+
+const MAX_ATTEMPTS_KEY: &str = "server_restart_max_attempts";
+const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const BASE_DELAY: Duration = Duration::from_secs(1);
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerRecovery {
+    /// 1-based attempt number (for `recovered`: attempts it took).
+    pub attempt: u32,
+    pub max_attempts: u32,
+    /// Wait before this attempt.
+    pub delay_ms: u64,
+    /// How the server last exited.
+    pub reason: String,
+}
+
+/// Delay before restart attempt `attempt` (1-based): 1 s, 2 s, 4 s, … 30 s.
+pub fn backoff_delay(attempt: u32) -> Duration {
+    let factor = 1u32 << attempt.saturating_sub(1).min(16);
+    (BASE_DELAY * factor).min(MAX_DELAY)
+}
+
+fn max_attempts(app: &AppHandle) -> u32 {
+    app.try_state::<DbState>()
+        .and_then(|db| {
+            let conn = db.conn.lock().ok()?;
+            crate::scheduler::read_setting(&conn, MAX_ATTEMPTS_KEY)
+        })
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(DEFAULT_MAX_ATTEMPTS)
+}
+
+/// Start watching the managed server.
+pub fn spawn_watchdog(app: AppHandle) {
+    let supervisor = app.state::<TaskSupervisor>();
+    supervisor.spawn("server-watchdog", move |token| async move {
+        let mut attempt = 0u32;
+        while sleep_or_cancel(&token, POLL_INTERVAL).await {
+            let manager = app.state::<ServerManager>();
+            let status = manager.status();
+            if status.state != ServerState::Exited || !manager.has_recipe() {
+                // Running (again, possibly after a manual restart) re-arms
+                if status.state == ServerState::Running {
+                    attempt = 0;
+                }
+                continue;
+            }
+
+            let max = max_attempts(&app);
+            let reason = status.last_exit.unwrap_or_else(|| "exited".to_string());
+            if attempt >= max {
+                continue;
+            }
+            attempt += 1;
+            if attempt == 1 {
+                // Writes the crash report
+                let (crash_app, crash_reason, output) = (app.clone(), reason.clone(), manager.logs(crate::crash::RECENT_LINES));
+                let _ = tauri::async_runtime::spawn_blocking(move || {
+                    crate::crash::record_server_crash(&crash_app, &crash_reason, &output)
+                })
+                .await;
+            }
+            let delay = backoff_delay(attempt);
+            let payload = ServerRecovery {
+                attempt,
+                max_attempts: max,
+                delay_ms: delay.as_millis() as u64,
+                reason: reason.clone(),
+            };
+            tracing::warn!("[server] Server {} — restart {}/{} in {:?}", reason, attempt, max, delay);
+            publish(&app, AppEvent::ServerRestarting(payload.clone()));
+            if !sleep_or_cancel(&token, delay).await {
+                return;
+            }
+
+            crate::telemetry::server_restart(&app, "crash");
+            let restart_app = app.clone();
+            let restarted = tauri::async_runtime::spawn_blocking(move || {
+                restart_app.state::<ServerManager>().restart(&restart_app)
+            })
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|r| r);
+            let failure = match restarted {
+                Ok(()) => match wait_until_ready(&app, &token).await {
+                    Readiness::Ready => None,
+                    Readiness::Exited(reason) | Readiness::Foreign(reason) => Some(reason),
+                    Readiness::TimedOut { .. } | Readiness::Cancelled => Some("did not become ready".to_string()),
+                },
+                Err(e) => Some(e),
+            };
+            if token.is_cancelled() {
+                return;
+            }
+
+            match failure {
+                None => {
+                    tracing::info!("[server] Recovered after {} attempt(s)", attempt);
+                    publish(&app, AppEvent::ServerRecovered(ServerRecovery { delay_ms: 0, ..payload }));
+                    attempt = 0;
+                }
+                Some(e) => {
+                    tracing::error!("[server] Restart attempt {} failed: {}", attempt, e);
+                    // Treated like another crash on the next poll
+                    app.state::<ServerManager>().mark_exited(&e);
+                    if attempt >= max {
+                        tracing::warn!("[server] Giving up after {} restart attempts", attempt);
+                        publish(&app, AppEvent::ServerGaveUp(ServerRecovery { reason: e, ..payload }));
+                    }
+                }
+            }
+        }
+    });
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_cap() {
+        assert_eq!(backoff_delay(1), Duration::from_secs(1));
+        assert_eq!(backoff_delay(2), Duration::from_secs(2));
+        assert_eq!(backoff_delay(4), Duration::from_secs(8));
+        assert_eq!(backoff_delay(6), MAX_DELAY);
+        assert_eq!(backoff_delay(u32::MAX), MAX_DELAY);
+    }
+}
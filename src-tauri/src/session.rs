@@ -0,0 +1,291 @@
+//! Save and restore a karaoke night to a single `.karaoke-session` file.
+//!
+//! A session captures everything needed to resume a multi-night competition
+//! exactly where it stopped: the queue, the singer rotation, team scores and
+//! per-song overrides (key shift, tempo, sync offset). The frontend owns the
+//! live state and hands a snapshot to `save_session`; `load_session` returns
+//! it validated.
+//!
+//! The file is pretty-printed JSON with a format tag and version. Unknown
+//! fields are kept (`extra`), so a file saved by a newer build still loads
+//! and re-saves without losing data. A file is refused if its format tag is
+//! not ours, its format version is newer than this build, or its rotation
+//! points past its singers.
+//!
+//! While a night runs, the frontend also hands every change to
+//! `autosave_session`, which keeps it in the app data folder. After a crash
+//! or a reboot `load_autosave` returns it; `autostarted` says the login
+//! item started the app (see `autostart`), so a kiosk resumes without
+//! asking anyone.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+use crate::access::{require_webview, Capability};
+
+pub const SESSION_FORMAT: &str = "karaoke-successor-session";
+pub const SESSION_VERSION: u32 = 1;
+pub const SESSION_EXTENSION: &str = "karaoke-session";
+const AUTOSAVE_FILE: &str = "autosave.karaoke-session";
+
+type Extra = serde_json::Map<String, serde_json::Value>;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueueEntry {
+    pub song_id: String,
+    #[serde(default)]
+    pub singers: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub team: Option<String>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SingerRotation {
+    #[serde(default)]
+    pub singers: Vec<String>,
+    /// Index into `singers` of who is up next.
+    #[serde(default)]
+    pub next_index: usize,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TeamScore {
+    pub name: String,
+    #[serde(default)]
+    pub members: Vec<String>,
+    #[serde(default)]
+    pub score: i64,
+    /// Scores per round, in order.
+    #[serde(default)]
+    pub rounds: Vec<i64>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SongOverride {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_shift: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tempo: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offset_ms: Option<i64>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionFile {
+    pub format: String,
+    pub version: u32,
+    /// Epoch ms; set by `save_session`.
+    #[serde(default)]
+    pub saved_at: i64,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub queue: Vec<QueueEntry>,
+    #[serde(default)]
+    pub rotation: SingerRotation,
+    #[serde(default)]
+    pub teams: Vec<TeamScore>,
+    /// Keyed by song id.
+    #[serde(default)]
+    pub song_overrides: HashMap<String, SongOverride>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+/// Returned by `load_autosave`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResumeInfo {
+    pub session: SessionFile,
+    /// Started at login: resume without asking.
+    pub autostarted: bool,
+}
+
+impl SessionFile {
+    /// Set the format tag, version and save time, then validate.
+    fn stamp(&mut self) -> Result<(), String> {
+        self.format = SESSION_FORMAT.to_string();
+        self.version = SESSION_VERSION;
+        self.saved_at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or(0);
+        self.validate()
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.format != SESSION_FORMAT {
+            return Err(format!("Not a session file (format '{}')", self.format));
+        }
+        if self.version > SESSION_VERSION {
+            return Err(format!(
+                "Session was saved by a newer version (format v{}, this build reads up to v{})",
+                self.version, SESSION_VERSION
+            ));
+        }
+        if !self.rotation.singers.is_empty() && self.rotation.next_index >= self.rotation.singers.len() {
+            return Err("Singer rotation index out of range".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// `path` with the session extension added if it has none.
+fn session_path(path: &str) -> PathBuf {
+    let path = PathBuf::from(path);
+    if path.extension().is_none() {
+        path.with_extension(SESSION_EXTENSION)
+    } else {
+        path
+    }
+}
+
+pub fn write_session(path: &Path, session: &SessionFile) -> Result<(), String> {
+    let json = serde_json::to_vec_pretty(session).map_err(|e| format!("Failed to serialize session: {}", e))?;
+    // Temp file + rename: an interrupted save never destroys the previous one.
+    // Appended, so `night.tmp` next to `night.karaoke-session` is not overwritten
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    std::fs::write(&tmp, json).map_err(|e| format!("Failed to write session: {}", e))?;
+    std::fs::rename(&tmp, path).map_err(|e| format!("Failed to write session: {}", e))
+}
+
+pub fn read_session(path: &Path) -> Result<SessionFile, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("Failed to read session {}: {}", path.display(), e))?;
+    let session: SessionFile = serde_json::from_slice(&bytes).map_err(|e| format!("Invalid session file: {}", e))?;
+    session.validate()?;
+    Ok(session)
+}
+
+fn autosave_path(app: &AppHandle) -> Result<PathBuf, String> {
+    let dir = app.path().app_data_dir().map_err(|e| format!("No app data dir: {}", e))?;
+    std::fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    Ok(dir.join(AUTOSAVE_FILE))
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+/// Save `session` to `path` (extension added if missing). Returns the path
+/// written.
+#[tauri::command]
+pub fn save_session(webview: tauri::Webview, path: String, mut session: SessionFile) -> Result<String, String> {
+    require_webview(&webview, Capability::ManageQueue)?;
+    session.stamp()?;
+
+    let path = session_path(&path);
+    write_session(&path, &session)?;
+    tracing::info!(
+        "[session] Saved {} queue entries, {} teams to {}",
+        session.queue.len(),
+        session.teams.len(),
+        path.display()
+    );
+    Ok(path.to_string_lossy().to_string())
+}
+
+/// Load and validate a session saved with `save_session`.
+#[tauri::command]
+pub fn load_session(webview: tauri::Webview, path: String) -> Result<SessionFile, String> {
+    require_webview(&webview, Capability::ManageQueue)?;
+    read_session(&session_path(&path))
+}
+
+/// Keep `session` as the one to resume after a restart; `None` when the
+/// night is over and nothing should be resumed.
+#[tauri::command]
+pub fn autosave_session(app: AppHandle, webview: tauri::Webview, session: Option<SessionFile>) -> Result<(), String> {
+    require_webview(&webview, Capability::ManageQueue)?;
+    let path = autosave_path(&app)?;
+    let Some(mut session) = session else {
+        return match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(format!("Failed to remove the autosave: {}", e)),
+            _ => Ok(()),
+        };
+    };
+    session.stamp()?;
+    write_session(&path, &session)
+}
+
+/// The autosaved session, if there is one to resume.
+#[tauri::command]
+pub fn load_autosave(app: AppHandle, webview: tauri::Webview) -> Result<Option<ResumeInfo>, String> {
+    require_webview(&webview, Capability::ManageQueue)?;
+    let path = autosave_path(&app)?;
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let session = read_session(&path)?;
+    let autostarted = crate::cli::gui_flags().autostarted;
+    tracing::info!("[session] Resuming the session saved at {} ({} queue entries)", session.saved_at, session.queue.len());
+    Ok(Some(ResumeInfo { session, autostarted }))
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_and_keeps_unknown_fields() {
+        let json = serde_json::json!({
+            "format": SESSION_FORMAT,
+            "version": 1,
+            "queue": [{ "songId": "abc", "singers": ["Ann"], "duet": true }],
+            "rotation": { "singers": ["Ann", "Bob"], "nextIndex": 1 },
+            "teams": [{ "name": "Red", "score": 1200, "rounds": [700, 500] }],
+            "songOverrides": { "abc": { "keyShift": -2 } },
+            "round": 3
+        });
+        let session: SessionFile = serde_json::from_value(json).unwrap();
+        session.validate().unwrap();
+        assert_eq!(session.queue[0].extra["duet"], true);
+        assert_eq!(session.song_overrides["abc"].key_shift, Some(-2));
+
+        let dir = crate::paths::test_dir("session");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("night.karaoke-session");
+        std::fs::write(dir.join("night.tmp"), b"notes").unwrap();
+        write_session(&path, &session).unwrap();
+        assert_eq!(std::fs::read(dir.join("night.tmp")).unwrap(), b"notes");
+        let loaded = read_session(&path).unwrap();
+        assert_eq!(loaded, session);
+        assert_eq!(loaded.extra["round"], 3);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn rejects_newer_versions_and_foreign_files() {
+        let mut session: SessionFile =
+            serde_json::from_value(serde_json::json!({ "format": SESSION_FORMAT, "version": 1 })).unwrap();
+        session.version = SESSION_VERSION + 1;
+        assert!(session.validate().is_err());
+        session.version = 1;
+        session.format = "something-else".into();
+        assert!(session.validate().is_err());
+        session.format = SESSION_FORMAT.into();
+        session.rotation = SingerRotation { singers: vec!["Ann".into()], next_index: 1, ..SingerRotation::default() };
+        assert!(session.validate().is_err());
+    }
+}
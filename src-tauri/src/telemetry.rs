@@ -0,0 +1,356 @@
+//! Opt-in anonymous usage metrics.
+//!
+//! Off unless `[telemetry] enabled = true`. While on, a few facts are kept
+//! in `telemetry-pending.json` in the app config dir: how each launch got its server up (which startup path and
+//! runtime, whether the port had to move, how long until it answered),
+//! server restarts by trigger, and how often a handful of features are
+//! used. No song titles, paths, names or addresses, and no install id:
+//! a batch carries the app version, the OS and the CPU architecture and
+//! nothing that tells two machines apart.
+//!
+//! Shortly after launch and then every `REPORT_INTERVAL` the pending batch
+//! is POSTed as JSON to `[telemetry] endpoint`; it is only cleared once the
+//! endpoint accepted it, so what a short session recorded (its startup
+//! above all) goes out with the next launch instead of being lost. Without
+//! an endpoint nothing ever leaves the machine. `get_pending_telemetry`
+//! returns exactly the body the next report sends, read from the file,
+//! and turning telemetry off deletes it.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+use crate::config::{self, TelemetryConfig};
+use crate::runtime::{sleep_or_cancel, TaskSupervisor};
+use crate::scheduler::now_ms;
+
+const PENDING_FILE: &str = "telemetry-pending.json";
+/// Until the first report, which sends what earlier launches left.
+const FIRST_REPORT_DELAY: Duration = Duration::from_secs(5 * 60);
+const REPORT_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const REPORT_TIMEOUT: Duration = Duration::from_secs(15);
+/// Oldest events are dropped beyond this, e.g. while the endpoint is down.
+const MAX_PENDING: usize = 500;
+
+/// What happened; each variant is one kind of event.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Metric {
+    /// One launch of the server, from setup until it answered (or not).
+    Startup {
+        startup_ms: u64,
+        /// `cached_plan` (see `server::launch_plan`), `bundled`,
+        /// `system_node`, `dev_server`, `external` (another instance's
+        /// server was adopted) or `none`.
+        path: String,
+        /// `Node` or `Bun`, when the bundled server (or a cached plan) was
+        /// started.
+        runtime: Option<String>,
+        bundled_runtime: bool,
+        /// The preferred port was taken and another one was used.
+        port_fallback: bool,
+        /// `ready`, `exited`, `foreign`, `timed_out` or `not_started`.
+        outcome: String,
+    },
+    /// `crash`, `memory`, `manual` or `lan_access`.
+    ServerRestart { trigger: String },
+    /// Uses of a feature since the last report, counted in one event.
+    FeatureUsed { feature: String, count: u32 },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TelemetryEvent {
+    /// Epoch ms of the (first) occurrence.
+    pub at: i64,
+    #[serde(flatten)]
+    pub metric: Metric,
+}
+
+/// The body of a report.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TelemetryBatch {
+    pub app_version: String,
+    pub os: String,
+    pub arch: String,
+    pub events: Vec<TelemetryEvent>,
+}
+
+pub struct TelemetryState {
+    enabled: AtomicBool,
+    /// When setup began, the zero of `startup_ms`.
+    launched: Instant,
+    pending: Mutex<Vec<TelemetryEvent>>,
+}
+
+impl Default for TelemetryState {
+    fn default() -> Self {
+        Self { enabled: AtomicBool::new(false), launched: Instant::now(), pending: Mutex::new(Vec::new()) }
+    }
+}
+
+/// Add `metric` to `pending`: feature uses are counted into the pending
+/// event for the feature, and the oldest events go beyond `MAX_PENDING`.
+fn push(pending: &mut Vec<TelemetryEvent>, at: i64, metric: Metric) {
+    if let Metric::FeatureUsed { feature, count } = &metric {
+        let existing = pending.iter_mut().find_map(|event| match &mut event.metric {
+            Metric::FeatureUsed { feature: f, count: c } if f == feature => Some(c),
+            _ => None,
+        });
+        if let Some(total) = existing {
+            *total = total.saturating_add(*count);
+            return;
+        }
+    }
+    pending.push(TelemetryEvent { at, metric });
+    if pending.len() > MAX_PENDING {
+        let excess = pending.len() - MAX_PENDING;
+        pending.drain(..excess);
+    }
+}
+
+fn pending_path(app: &AppHandle) -> Option<PathBuf> {
+    app.path().app_config_dir().ok().map(|dir| dir.join(PENDING_FILE))
+}
+
+/// Pending events saved by `save`; none if the file is missing or unreadable.
+fn load(path: &Path) -> Vec<TelemetryEvent> {
+    std::fs::read_to_string(path).ok().and_then(|text| serde_json::from_str(&text).ok()).unwrap_or_default()
+}
+
+/// Replace the file with `events`; no events, no file.
+fn save(path: &Path, events: &[TelemetryEvent]) -> Result<(), String> {
+    if events.is_empty() {
+        return match std::fs::remove_file(path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(format!("Failed to remove {}: {}", path.display(), e)),
+            _ => Ok(()),
+        };
+    }
+    let text = serde_json::to_string(events).map_err(|e| format!("Failed to encode usage metrics: {}", e))?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, text).map_err(|e| format!("Failed to write {}: {}", tmp.display(), e))?;
+    std::fs::rename(&tmp, path).map_err(|e| format!("Failed to replace {}: {}", path.display(), e))
+}
+
+/// Save `events` as the pending batch, logging failures.
+fn persist(app: &AppHandle, events: &[TelemetryEvent]) {
+    let Some(path) = pending_path(app) else { return };
+    if let Err(e) = save(&path, events) {
+        tracing::debug!("[telemetry] {}", e);
+    }
+}
+
+/// Keep `metric` for the next report; nothing happens unless telemetry is on.
+pub fn record(app: &AppHandle, metric: Metric) {
+    let Some(state) = app.try_state::<TelemetryState>() else { return };
+    if !state.enabled.load(Ordering::Relaxed) {
+        return;
+    }
+    if let Ok(mut pending) = state.pending.lock() {
+        push(&mut pending, now_ms(), metric);
+        persist(app, &pending);
+    }
+}
+
+/// Count one use of `feature`.
+pub fn feature(app: &AppHandle, feature: &str) {
+    record(app, Metric::FeatureUsed { feature: feature.to_string(), count: 1 });
+}
+
+/// Count a server restart caused by `trigger`.
+pub fn server_restart(app: &AppHandle, trigger: &str) {
+    record(app, Metric::ServerRestart { trigger: trigger.to_string() });
+}
+
+/// Time since setup began, for `Metric::Startup`.
+pub fn since_launch(app: &AppHandle) -> Duration {
+    app.try_state::<TelemetryState>().map(|state| state.launched.elapsed()).unwrap_or_default()
+}
+
+/// What a report would send now.
+fn batch(app: &AppHandle, events: Vec<TelemetryEvent>) -> TelemetryBatch {
+    TelemetryBatch {
+        app_version: app.package_info().version.to_string(),
+        os: sysinfo::System::long_os_version().unwrap_or_else(|| std::env::consts::OS.to_string()),
+        arch: std::env::consts::ARCH.to_string(),
+        events,
+    }
+}
+
+/// The saved batch, which includes a report in flight.
+fn pending(app: &AppHandle) -> Vec<TelemetryEvent> {
+    let enabled = app.try_state::<TelemetryState>().is_some_and(|state| state.enabled.load(Ordering::Relaxed));
+    match pending_path(app) {
+        Some(path) if enabled => load(&path),
+        _ => Vec::new(),
+    }
+}
+
+pub fn validate(config: &TelemetryConfig) -> Result<(), String> {
+    let Some(endpoint) = &config.endpoint else { return Ok(()) };
+    let url = reqwest::Url::parse(endpoint).map_err(|e| format!("Invalid telemetry endpoint '{}': {}", endpoint, e))?;
+    if url.scheme() != "https" {
+        return Err(format!("Telemetry endpoint '{}' must use https", endpoint));
+    }
+    Ok(())
+}
+
+/// Follow `[telemetry] enabled`: turning it on picks up what earlier
+/// launches left, turning it off forgets pending events.
+pub fn sync(app: &AppHandle, config: &TelemetryConfig) {
+    let Some(state) = app.try_state::<TelemetryState>() else { return };
+    if state.enabled.swap(config.enabled, Ordering::Relaxed) == config.enabled {
+        return;
+    }
+    tracing::info!("[telemetry] Usage metrics {}", if config.enabled { "on" } else { "off" });
+    let Ok(mut pending) = state.pending.lock() else { return };
+    if config.enabled {
+        let Some(path) = pending_path(app) else { return };
+        let recorded = std::mem::replace(&mut *pending, load(&path));
+        for event in recorded {
+            push(&mut pending, event.at, event.metric);
+        }
+    } else {
+        pending.clear();
+    }
+    persist(app, &pending);
+}
+
+/// POST `batch` to `endpoint`.
+async fn send(endpoint: &str, batch: &TelemetryBatch) -> Result<(), String> {
+    reqwest::Client::new()
+        .post(endpoint)
+        .timeout(REPORT_TIMEOUT)
+        .json(batch)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| format!("Failed to send usage metrics: {}", e))?;
+    Ok(())
+}
+
+/// Report pending events after `FIRST_REPORT_DELAY`, then every
+/// `REPORT_INTERVAL`, while telemetry is on and has an endpoint.
+pub fn spawn_reporter(app: AppHandle) {
+    let supervisor = app.state::<TaskSupervisor>();
+    supervisor.spawn("telemetry-report", move |token| async move {
+        let mut wait = FIRST_REPORT_DELAY;
+        while sleep_or_cancel(&token, wait).await {
+            wait = REPORT_INTERVAL;
+            let settings = config::current(&app).telemetry;
+            let Some(endpoint) = settings.endpoint.filter(|_| settings.enabled) else { continue };
+            let state = app.state::<TelemetryState>();
+            let events = match state.pending.lock() {
+                Ok(mut pending) => std::mem::take(&mut *pending),
+                Err(_) => continue,
+            };
+            if events.is_empty() {
+                continue;
+            }
+            // The file keeps them until the endpoint accepted them
+            let sent = events.len();
+            match send(&endpoint, &batch(&app, events.clone())).await {
+                Ok(()) => {
+                    tracing::debug!("[telemetry] Sent {} event(s)", sent);
+                    if let Ok(pending) = state.pending.lock() {
+                        if state.enabled.load(Ordering::Relaxed) {
+                            persist(&app, &pending);
+                        }
+                    }
+                }
+                Err(e) => {
+                    tracing::debug!("[telemetry] {}", e);
+                    // Put them back ahead of anything recorded meanwhile,
+                    // unless telemetry was turned off in the meantime
+                    if !state.enabled.load(Ordering::Relaxed) {
+                        continue;
+                    }
+                    if let Ok(mut pending) = state.pending.lock() {
+                        let newer = std::mem::replace(&mut *pending, events);
+                        for event in newer {
+                            push(&mut pending, event.at, event.metric);
+                        }
+                        persist(&app, &pending);
+                    }
+                }
+            }
+        }
+    });
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+/// Exactly what the next report would send, as saved; no events while
+/// telemetry is off.
+#[tauri::command]
+pub fn get_pending_telemetry(app: AppHandle) -> TelemetryBatch {
+    batch(&app, pending(&app))
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_features_and_caps_pending_events() {
+        let mut pending = Vec::new();
+        push(&mut pending, 1, Metric::FeatureUsed { feature: "cast".into(), count: 1 });
+        push(&mut pending, 2, Metric::ServerRestart { trigger: "crash".into() });
+        push(&mut pending, 3, Metric::FeatureUsed { feature: "cast".into(), count: 1 });
+        assert_eq!(pending.len(), 2);
+        assert_eq!(pending[0], TelemetryEvent { at: 1, metric: Metric::FeatureUsed { feature: "cast".into(), count: 2 } });
+
+        for at in 0..MAX_PENDING as i64 {
+            push(&mut pending, at, Metric::ServerRestart { trigger: "memory".into() });
+        }
+        assert_eq!(pending.len(), MAX_PENDING);
+        assert!(!pending.iter().any(|event| matches!(&event.metric, Metric::FeatureUsed { .. })));
+
+        let json = serde_json::to_value(&pending[0]).unwrap();
+        assert_eq!(json, serde_json::json!({ "at": 0, "type": "server_restart", "trigger": "memory" }));
+    }
+
+    #[test]
+    fn pending_events_survive_a_restart() {
+        let dir = crate::paths::test_dir("telemetry-pending");
+        let path = dir.join(PENDING_FILE);
+        assert!(load(&path).is_empty());
+        let events = vec![TelemetryEvent {
+            at: 7,
+            metric: Metric::Startup {
+                startup_ms: 1_200,
+                path: "bundled".into(),
+                runtime: Some("Bun".into()),
+                bundled_runtime: true,
+                port_fallback: false,
+                outcome: "ready".into(),
+            },
+        }];
+        save(&path, &events).unwrap();
+        assert_eq!(load(&path), events);
+        save(&path, &[]).unwrap();
+        assert!(!path.exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn endpoints_must_use_https() {
+        let config = |endpoint: &str| TelemetryConfig { enabled: true, endpoint: Some(endpoint.to_string()) };
+        assert!(validate(&TelemetryConfig::default()).is_ok());
+        assert!(validate(&config("https://metrics.example.org/v1/karaoke")).is_ok());
+        assert!(validate(&config("http://metrics.example.org/")).is_err());
+        assert!(validate(&config("not a url")).is_err());
+    }
+}
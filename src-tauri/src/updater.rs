@@ -0,0 +1,315 @@
+//! App updates through `tauri-plugin-updater`.
+//!
+//! `check_for_updates` asks the endpoint of the channel set in
+//! `config.toml` (`[updates] channel = "stable"` or `"beta"`) for a newer
+//! signed build. `install_update` downloads it in the background, with
+//! `update://progress` events, and then installs it and restarts — but
+//! only once the show is over: nothing has played and the queue has been
+//! empty for `IDLE_BEFORE_RESTART`, or the operator said so with
+//! `restart_for_update`. A pause or a gap between two queued songs is
+//! never taken for the end (`update://ready` says that it is waiting).
+//! On Windows the installer replaces the running app, so installing is
+//! deferred the same way. Failures are published as `update://failed`.
+//!
+//! Builds only verify updates against a public key: `KARAOKE_UPDATER_PUBKEY`
+//! at build time (release builds get it from CI), else `plugins.updater.pubkey`
+//! in `tauri.conf.json`; without one, checking reports that updates are not
+//! set up.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+use tauri_plugin_updater::{Update, UpdaterExt};
+
+use crate::access::{require_webview, Capability};
+use crate::audio::commands::AudioState;
+use crate::events::{publish, AppEvent};
+use crate::runtime::{sleep_or_cancel, TaskSupervisor};
+
+pub const PROGRESS_EVENT: &str = "update://progress";
+pub const READY_EVENT: &str = "update://ready";
+pub const FAILED_EVENT: &str = "update://failed";
+
+const STABLE_ENDPOINT: &str = "https://github.com/marco4192-ui/karaoke-successor/releases/latest/download/latest.json";
+const BETA_ENDPOINT: &str = "https://github.com/marco4192-ui/karaoke-successor/releases/download/beta/latest.json";
+/// Built-in updater key, set by release builds.
+const BUILD_PUBKEY: Option<&str> = option_env!("KARAOKE_UPDATER_PUBKEY");
+/// Nothing may play and the queue must be empty this long before restarting.
+const IDLE_BEFORE_RESTART: Duration = Duration::from_secs(10);
+const IDLE_POLL: Duration = Duration::from_secs(1);
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UpdateChannel {
+    #[default]
+    Stable,
+    Beta,
+}
+
+impl UpdateChannel {
+    fn endpoint(self) -> &'static str {
+        match self {
+            UpdateChannel::Stable => STABLE_ENDPOINT,
+            UpdateChannel::Beta => BETA_ENDPOINT,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateInfo {
+    pub version: String,
+    pub current_version: String,
+    pub channel: UpdateChannel,
+    /// Release notes.
+    pub notes: Option<String>,
+}
+
+/// Payload of `update://progress`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateProgress {
+    pub version: String,
+    pub downloaded: u64,
+    /// `None` when the server does not say.
+    pub total: Option<u64>,
+}
+
+/// Payload of `update://ready`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateReady {
+    pub version: String,
+    /// Installing waits for the show to end or for `restart_for_update`.
+    pub waiting_for_playback: bool,
+}
+
+/// Payload of `update://failed`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateFailed {
+    pub version: String,
+    pub error: String,
+}
+
+/// Managed state: the update found by the last check, and whether one is
+/// being installed.
+#[derive(Default)]
+pub struct UpdateState {
+    available: Mutex<Option<Update>>,
+    installing: AtomicBool,
+    /// The operator allowed the restart (`restart_for_update`).
+    confirmed: AtomicBool,
+}
+
+/// Tracks how long the show has been over.
+#[derive(Debug, Default)]
+struct IdleClock {
+    idle_since: Option<Instant>,
+}
+
+impl IdleClock {
+    /// Whether the app has been `idle` (nothing playing, nothing queued)
+    /// for `IDLE_BEFORE_RESTART` at `now`.
+    fn settled(&mut self, idle: bool, now: Instant) -> bool {
+        if !idle {
+            self.idle_since = None;
+            return false;
+        }
+        let since = *self.idle_since.get_or_insert(now);
+        now.duration_since(since) >= IDLE_BEFORE_RESTART
+    }
+}
+
+fn info(update: &Update, channel: UpdateChannel) -> UpdateInfo {
+    UpdateInfo {
+        version: update.version.clone(),
+        current_version: update.current_version.clone(),
+        channel,
+        notes: update.body.clone(),
+    }
+}
+
+/// The updater plugin, with the built-in key when the build has one.
+pub fn plugin<R: tauri::Runtime>() -> tauri::plugin::TauriPlugin<R> {
+    let builder = tauri_plugin_updater::Builder::new();
+    match BUILD_PUBKEY.filter(|key| !key.trim().is_empty()) {
+        Some(key) => builder.pubkey(key).build(),
+        None => builder.build(),
+    }
+}
+
+fn has_pubkey(app: &AppHandle) -> bool {
+    if BUILD_PUBKEY.is_some_and(|key| !key.trim().is_empty()) {
+        return true;
+    }
+    app.config()
+        .plugins
+        .0
+        .get("updater")
+        .and_then(|config| config.get("pubkey"))
+        .and_then(|key| key.as_str())
+        .is_some_and(|key| !key.trim().is_empty())
+}
+
+async fn check(app: &AppHandle) -> Result<Option<Update>, String> {
+    if !has_pubkey(app) {
+        return Err("Updates are not set up in this build (no signing key)".to_string());
+    }
+    let channel = crate::config::current(app).updates.channel;
+    let endpoint = channel.endpoint().parse().map_err(|e| format!("Invalid update endpoint: {}", e))?;
+    let updater = app
+        .updater_builder()
+        .endpoints(vec![endpoint])
+        .and_then(|builder| builder.build())
+        .map_err(|e| format!("Updater unavailable: {}", e))?;
+    updater.check().await.map_err(|e| format!("Update check failed: {}", e))
+}
+
+/// Nothing plays and nothing is queued.
+fn show_over(app: &AppHandle) -> bool {
+    if app.state::<AudioState>().is_playing() {
+        return false;
+    }
+    let Some(db) = app.try_state::<crate::db::DbState>() else { return false };
+    let Ok(conn) = db.conn.lock() else { return false };
+    crate::queue::list(&conn).is_ok_and(|queue| queue.is_empty())
+}
+
+/// Download `update`, wait until the show is over or the operator allows
+/// it, install and restart.
+async fn download_and_install(app: &AppHandle, update: Update, token: &tokio_util::sync::CancellationToken) -> Result<(), String> {
+    let version = update.version.clone();
+    let mut downloaded = 0u64;
+    let mut reported = Instant::now();
+    let bytes = update
+        .download(
+            |chunk, total| {
+                downloaded += chunk as u64;
+                if reported.elapsed() >= PROGRESS_INTERVAL {
+                    reported = Instant::now();
+                    publish(app, AppEvent::UpdateProgress(UpdateProgress { version: version.clone(), downloaded, total }));
+                }
+            },
+            || {},
+        )
+        .await
+        .map_err(|e| format!("Update download failed: {}", e))?;
+
+    let mut clock = IdleClock::default();
+    let mut announced = false;
+    loop {
+        if app.state::<UpdateState>().confirmed.load(Ordering::Acquire) || clock.settled(show_over(app), Instant::now()) {
+            break;
+        }
+        if !announced {
+            announced = true;
+            publish(app, AppEvent::UpdateReady(UpdateReady { version: version.clone(), waiting_for_playback: true }));
+        }
+        if !sleep_or_cancel(token, IDLE_POLL).await {
+            return Err("Shutting down before the update could be installed".to_string());
+        }
+    }
+
+    tracing::info!("[updater] Installing {} and restarting", version);
+    update.install(bytes).map_err(|e| format!("Update install failed: {}", e))?;
+    app.restart()
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+/// Newer version on the configured channel, if any.
+#[tauri::command]
+pub async fn check_for_updates(app: AppHandle, webview: tauri::Webview) -> Result<Option<UpdateInfo>, String> {
+    require_webview(&webview, Capability::ChangeSettings)?;
+    let channel = crate::config::current(&app).updates.channel;
+    let update = check(&app).await?;
+    let found = update.as_ref().map(|update| info(update, channel));
+    match &found {
+        Some(found) => tracing::info!("[updater] {} available on {:?}", found.version, channel),
+        None => tracing::info!("[updater] Up to date on {:?}", channel),
+    }
+    *app.state::<UpdateState>().available.lock().map_err(|e| e.to_string())? = update;
+    Ok(found)
+}
+
+/// Download and install the available update; restarts once the show is
+/// over or `restart_for_update` allows it. Progress and the outcome arrive
+/// as events.
+#[tauri::command]
+pub async fn install_update(app: AppHandle, webview: tauri::Webview) -> Result<UpdateInfo, String> {
+    require_webview(&webview, Capability::ChangeSettings)?;
+    let channel = crate::config::current(&app).updates.channel;
+    let state = app.state::<UpdateState>();
+    // Claimed before the cached update is taken, so a second call cannot
+    // take it or start another download meanwhile
+    if state.installing.swap(true, Ordering::AcqRel) {
+        return Err("An update is already being installed".to_string());
+    }
+    state.confirmed.store(false, Ordering::Release);
+    let cached = state.available.lock().ok().and_then(|mut available| available.take());
+    let update = match cached {
+        Some(update) => Ok(update),
+        None => check(&app).await.and_then(|update| update.ok_or_else(|| "No update available".to_string())),
+    };
+    let update = match update {
+        Ok(update) => update,
+        Err(e) => {
+            state.installing.store(false, Ordering::Release);
+            return Err(e);
+        }
+    };
+    let found = info(&update, channel);
+    let job_app = app.clone();
+    app.state::<TaskSupervisor>().spawn("update-install", move |token| async move {
+        let version = update.version.clone();
+        if let Err(error) = download_and_install(&job_app, update, &token).await {
+            tracing::error!("[updater] {}", error);
+            publish(&job_app, AppEvent::UpdateFailed(UpdateFailed { version, error }));
+        }
+        job_app.state::<UpdateState>().installing.store(false, Ordering::Release);
+    });
+    Ok(found)
+}
+
+/// Install the downloaded update now instead of waiting for the show to
+/// end; right after the download when it is still running.
+#[tauri::command]
+pub fn restart_for_update(app: AppHandle, webview: tauri::Webview) -> Result<(), String> {
+    require_webview(&webview, Capability::ChangeSettings)?;
+    let state = app.state::<UpdateState>();
+    if !state.installing.load(Ordering::Acquire) {
+        return Err("No update is being installed".to_string());
+    }
+    tracing::info!("[updater] Restart allowed by the operator");
+    state.confirmed.store(true, Ordering::Release);
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waits_for_the_show_to_stay_over() {
+        let mut clock = IdleClock::default();
+        let start = Instant::now();
+        assert!(!clock.settled(false, start));
+        assert!(!clock.settled(true, start + Duration::from_secs(1)));
+        // A song starting (or one queued) starts over
+        assert!(!clock.settled(false, start + Duration::from_secs(5)));
+        assert!(!clock.settled(true, start + Duration::from_secs(6)));
+        assert!(clock.settled(true, start + Duration::from_secs(6) + IDLE_BEFORE_RESTART));
+        assert_eq!(toml::from_str::<crate::config::AppConfig>("[updates]\nchannel = \"beta\"").unwrap().updates.channel, UpdateChannel::Beta);
+    }
+}
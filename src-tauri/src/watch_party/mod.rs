@@ -0,0 +1,732 @@
+//! Internet watch party: two app instances share one queue and start songs
+//! at the same moment.
+//!
+//! One side hosts (`watch_party_host` listens on a TCP port), the other
+//! joins by address; behind NAT both can meet at a relay instead (`relay`).
+//! Both must use the same room code. Once connected:
+//!   - queue snapshots are exchanged whenever either side changes the queue
+//!     (`watch_party_share_queue`); conflicting edits converge by revision;
+//!   - `watch_party_start_song` schedules a start a few seconds ahead and
+//!     sends it to the peer, translated through the measured clock offset,
+//!     so both homes start within a few milliseconds of each other;
+//!   - scores are mirrored as they come in.
+//!
+//! Everything from the peer is published as `watch-party://event`. Nothing
+//! is read from the peer before it proved it knows the room code
+//! (`protocol`), and no line may exceed `protocol::MAX_LINE_BYTES`. The
+//! peer acts with operator rights (`access`), so it may edit the shared
+//! queue and start songs but never touches devices or settings here; a
+//! host started with `guest_peer` lets it only watch and mirror scores.
+//! A peer that stops reading is dropped once `OUTGOING_CAPACITY` messages
+//! are waiting for it, instead of piling them up in memory.
+//!
+//! Room codes have at least `MIN_ROOM_CODE_CHARS` characters, and guessing
+//! one online is slow: after `FREE_ATTEMPTS` wrong codes from one address
+//! each further attempt waits twice as long as the one before (up to
+//! `MAX_BACKOFF`), and after `MAX_ATTEMPTS` that address is turned away for
+//! the rest of the party. Peers through a relay share one budget, and
+//! past `MAX_ATTEMPTS` the relay is asked for one every `MAX_BACKOFF`.
+
+pub mod protocol;
+pub mod relay;
+
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+use futures_util::StreamExt;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_util::codec::{FramedRead, LinesCodec, LinesCodecError};
+use tokio_util::sync::CancellationToken;
+
+use crate::access::{require_webview, Capability, Principal, Role};
+use crate::events::{publish, AppEvent};
+use crate::runtime::{sleep_or_cancel, TaskSupervisor};
+use crate::server::security::generate_token;
+use protocol::{decode, encode, proof, proofs_match, queue_wins, ClockSync, PeerMessage, MAX_LINE_BYTES, PROTOCOL_VERSION};
+
+pub const WATCH_PARTY_EVENT: &str = "watch-party://event";
+const DEFAULT_PORT: u16 = 47_820;
+const PING_INTERVAL: Duration = Duration::from_secs(2);
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Messages waiting for the peer before it counts as too slow.
+const OUTGOING_CAPACITY: usize = 256;
+/// How long one write may block before the peer counts as too slow.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_START_LEAD_MS: i64 = 3_000;
+const RELAY_RETRY: Duration = Duration::from_secs(5);
+const MIN_ROOM_CODE_CHARS: usize = 8;
+/// Wrong room codes from one address before the back-off starts.
+const FREE_ATTEMPTS: u32 = 3;
+/// Wrong room codes after which an address is refused for good.
+const MAX_ATTEMPTS: u32 = 10;
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+/// Stands in for the address of peers paired by a relay.
+const RELAYED: IpAddr = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
+
+/// Published for everything that arrives from (or happens to) the peer.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum WatchPartyEvent {
+    Connected { peer_name: String },
+    Disconnected { reason: String },
+    QueueChanged { revision: u64, entries: Vec<serde_json::Value> },
+    /// `start_at` is on the local clock (epoch ms).
+    SongStart { song_id: String, start_at: i64 },
+    Score { song_id: String, singer: String, score: i64, is_final: bool },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PartyMode {
+    Idle,
+    Hosting,
+    Joined,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchPartyStatus {
+    pub mode: PartyMode,
+    pub connected: bool,
+    pub peer_name: Option<String>,
+    pub port: Option<u16>,
+    /// Peer clock minus local clock.
+    pub clock_offset_ms: Option<i64>,
+    pub rtt_ms: Option<i64>,
+    pub queue_revision: u64,
+}
+
+struct Session {
+    mode: PartyMode,
+    port: Option<u16>,
+    cancel: CancellationToken,
+    /// Outgoing messages and the token ending that connection; `None`
+    /// until the peer has connected.
+    outgoing: Option<(mpsc::Sender<PeerMessage>, CancellationToken)>,
+    peer_name: Option<String>,
+    /// The peer may only watch (`watch_party_host`'s `guest_peer`).
+    guest_peer: bool,
+}
+
+/// Managed state.
+pub struct WatchPartyState {
+    instance_id: String,
+    session: Mutex<Option<Session>>,
+    clock: Mutex<ClockSync>,
+    /// (revision, origin instance id, entries)
+    queue: Mutex<(u64, String, Vec<serde_json::Value>)>,
+    /// Ping id base, advanced per connection so stale pongs never match.
+    ping_id: AtomicU64,
+}
+
+impl WatchPartyState {
+    pub fn new() -> Self {
+        let seed = format!("{}-{}", std::process::id(), now_ms());
+        Self {
+            instance_id: format!("{:016x}", crate::library::scanner::fnv1a64(seed.as_bytes())),
+            session: Mutex::new(None),
+            clock: Mutex::new(ClockSync::default()),
+            queue: Mutex::new((0, String::new(), Vec::new())),
+            ping_id: AtomicU64::new(0),
+        }
+    }
+
+    fn send(&self, message: PeerMessage) -> Result<(), String> {
+        let session = self.session.lock().map_err(|e| e.to_string())?;
+        let (tx, connection) = session
+            .as_ref()
+            .and_then(|s| s.outgoing.as_ref())
+            .ok_or("No watch-party peer connected")?;
+        queue_message(tx, connection, message)
+    }
+}
+
+/// Queue `message` for the peer; a peer with a full queue is disconnected.
+fn queue_message(tx: &mpsc::Sender<PeerMessage>, connection: &CancellationToken, message: PeerMessage) -> Result<(), String> {
+    match tx.try_send(message) {
+        Ok(()) => Ok(()),
+        Err(mpsc::error::TrySendError::Full(_)) => {
+            tracing::warn!("[watch-party] {} messages waiting for the peer; disconnecting", OUTGOING_CAPACITY);
+            connection.cancel();
+            Err("Watch-party peer is not keeping up; disconnected it".to_string())
+        }
+        Err(mpsc::error::TrySendError::Closed(_)) => Err("Watch-party connection closed".to_string()),
+    }
+}
+
+fn now_ms() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+fn emit(app: &AppHandle, event: WatchPartyEvent) {
+    publish(app, AppEvent::WatchParty(event));
+}
+
+/// Wrong room codes per peer address, for the back-off.
+#[derive(Default)]
+struct Attempts(HashMap<IpAddr, (u32, Instant)>);
+
+impl Attempts {
+    /// How long `ip` must still wait before its next attempt; `None` once
+    /// it is refused for good.
+    fn wait(&self, ip: IpAddr, now: Instant) -> Option<Duration> {
+        let Some(&(failures, last)) = self.0.get(&ip) else { return Some(Duration::ZERO) };
+        if failures >= MAX_ATTEMPTS {
+            return None;
+        }
+        Some(backoff(failures).saturating_sub(now.saturating_duration_since(last)))
+    }
+
+    fn record(&mut self, ip: IpAddr, proved: bool, now: Instant) {
+        if proved {
+            self.0.remove(&ip);
+        } else {
+            let failures = self.0.get(&ip).map_or(0, |&(n, _)| n);
+            self.0.insert(ip, (failures.saturating_add(1), now));
+        }
+    }
+}
+
+/// How long to wait after `failures` wrong room codes in a row.
+fn backoff(failures: u32) -> Duration {
+    if failures < FREE_ATTEMPTS {
+        return Duration::ZERO;
+    }
+    Duration::from_secs(1u64 << (failures - FREE_ATTEMPTS).min(16)).min(MAX_BACKOFF)
+}
+
+// ---------------------------------------------------------------------------
+// Connection handling
+// ---------------------------------------------------------------------------
+
+/// Drive one peer connection until it closes or `cancel` fires. Generic over
+/// the stream so relayed connections reuse it. Returns whether the peer
+/// got through the handshake.
+pub(crate) async fn run_peer<S>(
+    app: AppHandle,
+    stream: S,
+    hosting: bool,
+    room_code: String,
+    name: String,
+    cancel: CancellationToken,
+) -> bool
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let connection = cancel.child_token();
+    let mut proved = false;
+    let reason = match peer_loop(&app, stream, hosting, &room_code, &name, &connection, &mut proved).await {
+        Ok(()) => "Connection closed".to_string(),
+        Err(e) => e,
+    };
+    tracing::info!("[watch-party] {}", reason);
+
+    let state = app.state::<WatchPartyState>();
+    if let Ok(mut session) = state.session.lock() {
+        if let Some(s) = session.as_mut() {
+            s.outgoing = None;
+            s.peer_name = None;
+        }
+    }
+    if let Ok(mut clock) = state.clock.lock() {
+        clock.reset();
+    }
+    emit(&app, WatchPartyEvent::Disconnected { reason });
+    proved
+}
+
+type Lines<R> = FramedRead<R, LinesCodec>;
+
+async fn next_line<R: AsyncRead + Unpin>(lines: &mut Lines<R>) -> Result<Option<String>, String> {
+    match lines.next().await {
+        None => Ok(None),
+        Some(Ok(line)) => Ok(Some(line)),
+        Some(Err(LinesCodecError::MaxLineLengthExceeded)) => Err("Peer sent an oversized message".to_string()),
+        Some(Err(LinesCodecError::Io(e))) => Err(format!("Read failed: {}", e)),
+    }
+}
+
+/// The next message during the handshake.
+async fn handshake_message<R: AsyncRead + Unpin>(lines: &mut Lines<R>) -> Result<PeerMessage, String> {
+    let line = tokio::time::timeout(HANDSHAKE_TIMEOUT, next_line(lines))
+        .await
+        .map_err(|_| "Handshake timed out".to_string())??
+        .ok_or("Peer closed during handshake")?;
+    decode(&line)
+}
+
+async fn peer_loop<S>(
+    app: &AppHandle,
+    stream: S,
+    hosting: bool,
+    room_code: &str,
+    name: &str,
+    cancel: &CancellationToken,
+    proved: &mut bool,
+) -> Result<(), String>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let state = app.state::<WatchPartyState>();
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut lines = FramedRead::new(reader, LinesCodec::new_with_max_length(MAX_LINE_BYTES));
+
+    // Handshake: the host challenges, the joiner proves it knows the room
+    // code, the host proves it back (welcome) or refuses (reject)
+    let peer_name = if hosting {
+        let host_nonce = generate_token();
+        let challenge = PeerMessage::Challenge { protocol: PROTOCOL_VERSION, nonce: host_nonce.clone() };
+        writer.write_all(&encode(&challenge)?).await.map_err(|e| format!("Write failed: {}", e))?;
+        let PeerMessage::Hello { protocol, instance_id: _, name: peer, nonce: join_nonce, proof: join_proof } =
+            handshake_message(&mut lines).await?
+        else {
+            return Err("Expected hello".to_string());
+        };
+        let refusal = if protocol != PROTOCOL_VERSION {
+            Some(format!("Protocol v{} not supported (this app speaks v{})", protocol, PROTOCOL_VERSION))
+        } else if !proofs_match(&join_proof, &proof(room_code, "join", &host_nonce, &join_nonce)) {
+            Some("Wrong room code".to_string())
+        } else {
+            None
+        };
+        if let Some(reason) = refusal {
+            let _ = writer.write_all(&encode(&PeerMessage::Reject { reason: reason.clone() })?).await;
+            return Err(format!("Rejected peer '{}': {}", peer, reason));
+        }
+        let welcome = PeerMessage::Welcome {
+            instance_id: state.instance_id.clone(),
+            name: name.to_string(),
+            proof: proof(room_code, "host", &host_nonce, &join_nonce),
+        };
+        writer.write_all(&encode(&welcome)?).await.map_err(|e| format!("Write failed: {}", e))?;
+        peer
+    } else {
+        let host_nonce = match handshake_message(&mut lines).await? {
+            PeerMessage::Challenge { protocol, nonce } if protocol == PROTOCOL_VERSION => nonce,
+            PeerMessage::Challenge { protocol, .. } => {
+                return Err(format!("Host speaks protocol v{} (this app speaks v{})", protocol, PROTOCOL_VERSION))
+            }
+            _ => return Err("Expected challenge".to_string()),
+        };
+        let join_nonce = generate_token();
+        let hello = PeerMessage::Hello {
+            protocol: PROTOCOL_VERSION,
+            instance_id: state.instance_id.clone(),
+            name: name.to_string(),
+            proof: proof(room_code, "join", &host_nonce, &join_nonce),
+            nonce: join_nonce.clone(),
+        };
+        writer.write_all(&encode(&hello)?).await.map_err(|e| format!("Write failed: {}", e))?;
+        match handshake_message(&mut lines).await? {
+            PeerMessage::Welcome { name, proof: host_proof, .. } => {
+                if !proofs_match(&host_proof, &proof(room_code, "host", &host_nonce, &join_nonce)) {
+                    return Err("Host does not know the room code".to_string());
+                }
+                name
+            }
+            PeerMessage::Reject { reason } => return Err(format!("Host refused: {}", reason)),
+            _ => return Err("Expected welcome".to_string()),
+        }
+    };
+
+    *proved = true;
+    let (tx, mut rx) = mpsc::channel::<PeerMessage>(OUTGOING_CAPACITY);
+    let mut guest_peer = false;
+    if let Ok(mut session) = state.session.lock() {
+        if let Some(s) = session.as_mut() {
+            s.outgoing = Some((tx.clone(), cancel.clone()));
+            s.peer_name = Some(peer_name.clone());
+            guest_peer = hosting && s.guest_peer;
+        }
+    }
+    tracing::info!("[watch-party] Connected to '{}'", peer_name);
+    emit(app, WatchPartyEvent::Connected { peer_name: peer_name.clone() });
+    let role = if guest_peer { Role::Guest } else { Role::Operator };
+    let peer = Principal::remote(format!("watch-party:{}", peer_name), role);
+
+    // The host's current queue is the starting point for both sides
+    if hosting {
+        let (revision, origin, entries) = state.queue.lock().map_err(|e| e.to_string())?.clone();
+        queue_message(&tx, cancel, PeerMessage::Queue { revision, origin, entries })?;
+    }
+
+    let writer_cancel = cancel.clone();
+    let ping_base = state.ping_id.fetch_add(1 << 32, Ordering::Relaxed);
+    let writer_task = tokio::spawn(async move {
+        let mut ping = tokio::time::interval(PING_INTERVAL);
+        let mut ping_id = ping_base;
+        loop {
+            let message = tokio::select! {
+                _ = writer_cancel.cancelled() => PeerMessage::Bye,
+                _ = ping.tick() => {
+                    ping_id += 1;
+                    PeerMessage::Ping { id: ping_id, sent_at: now_ms() }
+                }
+                msg = rx.recv() => match msg {
+                    Some(msg) => msg,
+                    None => break,
+                },
+            };
+            let bye = message == PeerMessage::Bye;
+            let Ok(bytes) = encode(&message) else { continue };
+            match tokio::time::timeout(WRITE_TIMEOUT, writer.write_all(&bytes)).await {
+                Ok(Ok(())) if !bye => {}
+                Ok(Ok(())) => break,
+                Ok(Err(_)) | Err(_) => {
+                    tracing::warn!("[watch-party] Peer stopped reading; disconnecting");
+                    writer_cancel.cancel();
+                    return;
+                }
+            }
+        }
+        let _ = tokio::time::timeout(WRITE_TIMEOUT, writer.shutdown()).await;
+    });
+
+    let result = loop {
+        let line = tokio::select! {
+            _ = cancel.cancelled() => break Ok(()),
+            line = next_line(&mut lines) => line,
+        };
+        let line = match line {
+            Ok(Some(line)) => line,
+            Ok(None) => break Ok(()),
+            Err(e) => break Err(e),
+        };
+        let message = match decode(&line) {
+            Ok(m) => m,
+            Err(e) => {
+                tracing::warn!("[watch-party] Ignoring message: {}", e);
+                continue;
+            }
+        };
+        match message {
+            PeerMessage::Ping { id, sent_at } => {
+                if let Err(e) = queue_message(&tx, cancel, PeerMessage::Pong { id, ping_sent_at: sent_at, received_at: now_ms() }) {
+                    break Err(e);
+                }
+            }
+            PeerMessage::Pong { ping_sent_at, received_at, .. } => {
+                if let Ok(mut clock) = state.clock.lock() {
+                    clock.add_sample(ping_sent_at, received_at, now_ms());
+                }
+            }
+            PeerMessage::Queue { revision, origin, entries } => {
+                if let Err(e) = peer.require(Capability::ManageQueue) {
+                    tracing::warn!("[watch-party] Ignoring queue from '{}': {}", peer_name, e);
+                    continue;
+                }
+                let mut queue = state.queue.lock().map_err(|e| e.to_string())?;
+                if queue_wins((revision, &origin), (queue.0, &queue.1)) {
+                    *queue = (revision, origin, entries.clone());
+                    drop(queue);
+                    emit(app, WatchPartyEvent::QueueChanged { revision, entries });
+                }
+            }
+            PeerMessage::SongStart { song_id, start_at } => {
+                if let Err(e) = peer.require(Capability::ControlPlayback) {
+                    tracing::warn!("[watch-party] Ignoring song start from '{}': {}", peer_name, e);
+                    continue;
+                }
+                let local = state.clock.lock().map(|c| c.to_local(start_at)).unwrap_or(start_at);
+                emit(app, WatchPartyEvent::SongStart { song_id, start_at: local });
+            }
+            PeerMessage::Score { song_id, singer, score, is_final } => {
+                emit(app, WatchPartyEvent::Score { song_id, singer, score, is_final });
+            }
+            PeerMessage::Bye => break Ok(()),
+            PeerMessage::Challenge { .. } | PeerMessage::Hello { .. } | PeerMessage::Welcome { .. } | PeerMessage::Reject { .. } => {}
+        }
+    };
+
+    // Ends the writer too: the session still holds a sender
+    cancel.cancel();
+    drop(tx);
+    let _ = writer_task.await;
+    result
+}
+
+fn start_session(
+    state: &WatchPartyState,
+    mode: PartyMode,
+    port: Option<u16>,
+    cancel: CancellationToken,
+    guest_peer: bool,
+) -> Result<(), String> {
+    let mut session = state.session.lock().map_err(|e| e.to_string())?;
+    if session.is_some() {
+        return Err("A watch party is already active; leave it first".to_string());
+    }
+    *session = Some(Session { mode, port, cancel, outgoing: None, peer_name: None, guest_peer });
+    Ok(())
+}
+
+fn end_session(app: &AppHandle) {
+    if let Ok(mut session) = app.state::<WatchPartyState>().session.lock() {
+        if let Some(s) = session.take() {
+            s.cancel.cancel();
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+/// Host a watch party on `port` (default 47820; 0 picks a free one), or
+/// through a relay when one is given or configured. With `guest_peer` the
+/// peer may not change the queue or start songs here. Returns the port
+/// listening (0 when relayed).
+#[tauri::command]
+pub async fn watch_party_host(
+    app: AppHandle,
+    webview: tauri::Webview,
+    room_code: String,
+    name: String,
+    port: Option<u16>,
+    relay: Option<String>,
+    guest_peer: Option<bool>,
+) -> Result<u16, String> {
+    require_webview(&webview, Capability::ManageQueue)?;
+    if room_code.trim().chars().count() < MIN_ROOM_CODE_CHARS {
+        return Err(format!("Room code must have at least {} characters", MIN_ROOM_CODE_CHARS));
+    }
+    crate::telemetry::feature(&app, "watch_party");
+    if let Some(relay) = relay::configured_relay(&app, relay) {
+        relay::relay_address(&relay)?;
+        return host_via_relay(app, relay, room_code, name, guest_peer.unwrap_or(false)).map(|()| 0);
+    }
+    let listener = TcpListener::bind(("0.0.0.0", port.unwrap_or(DEFAULT_PORT)))
+        .await
+        .map_err(|e| format!("Cannot listen for watch-party peers: {}", e))?;
+    let port = listener.local_addr().map_err(|e| e.to_string())?.port();
+
+    let supervisor = app.state::<TaskSupervisor>();
+    let token = supervisor.token();
+    start_session(&app.state::<WatchPartyState>(), PartyMode::Hosting, Some(port), token.clone(), guest_peer.unwrap_or(false))?;
+    tracing::info!("[watch-party] Hosting on port {}", port);
+
+    let handle = app.clone();
+    supervisor.spawn("watch-party-host", move |_| async move {
+        // One peer at a time; after it leaves, the next may connect
+        let mut attempts = Attempts::default();
+        loop {
+            let accepted = tokio::select! {
+                _ = token.cancelled() => break,
+                accepted = listener.accept() => accepted,
+            };
+            match accepted {
+                Ok((stream, addr)) => {
+                    match attempts.wait(addr.ip(), Instant::now()) {
+                        Some(wait) if wait.is_zero() => {}
+                        Some(wait) => {
+                            tracing::warn!("[watch-party] Turned {} away: wrong room codes, {} s to wait", addr, wait.as_secs().max(1));
+                            continue;
+                        }
+                        None => {
+                            tracing::warn!("[watch-party] Turned {} away: too many wrong room codes", addr);
+                            continue;
+                        }
+                    }
+                    tracing::info!("[watch-party] Peer connecting from {}", addr);
+                    let _ = stream.set_nodelay(true);
+                    let proved = run_peer(handle.clone(), stream, true, room_code.clone(), name.clone(), token.clone()).await;
+                    attempts.record(addr.ip(), proved, Instant::now());
+                }
+                Err(e) => {
+                    tracing::error!("[watch-party] Accept failed: {}", e);
+                    if !sleep_or_cancel(&token, Duration::from_secs(1)).await {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+    Ok(port)
+}
+
+/// Keep a registration open at the relay; each pairing becomes one peer
+/// connection, after which the host registers again.
+fn host_via_relay(app: AppHandle, relay: String, room_code: String, name: String, guest_peer: bool) -> Result<(), String> {
+    let supervisor = app.state::<TaskSupervisor>();
+    let token = supervisor.token();
+    start_session(&app.state::<WatchPartyState>(), PartyMode::Hosting, None, token.clone(), guest_peer)?;
+    tracing::info!("[watch-party] Hosting via relay {}", relay);
+
+    let handle = app.clone();
+    supervisor.spawn("watch-party-host", move |_| async move {
+        let mut attempts = Attempts::default();
+        while !token.is_cancelled() {
+            // Relayed peers have no address of their own: wait out the
+            // back-off before taking the next one
+            let wait = attempts.wait(RELAYED, Instant::now()).unwrap_or(MAX_BACKOFF);
+            if !wait.is_zero() && !sleep_or_cancel(&token, wait).await {
+                break;
+            }
+            match relay::connect(&relay, &room_code, relay::Side::Host, &token).await {
+                Ok(stream) => {
+                    let proved = run_peer(handle.clone(), stream, true, room_code.clone(), name.clone(), token.clone()).await;
+                    attempts.record(RELAYED, proved, Instant::now());
+                }
+                Err(e) => {
+                    tracing::warn!("[watch-party] {}", e);
+                    if !sleep_or_cancel(&token, RELAY_RETRY).await {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Join a watch party at `address` ("host:port"), or by room code alone
+/// through a relay when one is given or configured.
+#[tauri::command]
+pub async fn watch_party_join(
+    app: AppHandle,
+    webview: tauri::Webview,
+    address: Option<String>,
+    room_code: String,
+    name: String,
+    relay: Option<String>,
+) -> Result<(), String> {
+    require_webview(&webview, Capability::ManageQueue)?;
+    let address = address.filter(|a| !a.trim().is_empty());
+    let supervisor = app.state::<TaskSupervisor>();
+    let token = supervisor.token();
+
+    let stream = match (address, relay::configured_relay(&app, relay)) {
+        (Some(address), _) => {
+            let address = if address.contains(':') { address } else { format!("{}:{}", address, DEFAULT_PORT) };
+            let stream = tokio::time::timeout(HANDSHAKE_TIMEOUT, TcpStream::connect(&address))
+                .await
+                .map_err(|_| format!("Timed out connecting to {}", address))?
+                .map_err(|e| format!("Cannot connect to {}: {}", address, e))?;
+            let _ = stream.set_nodelay(true);
+            stream
+        }
+        (None, Some(relay)) => relay::connect(&relay, &room_code, relay::Side::Join, &token).await?,
+        (None, None) => return Err("Enter the host's address or configure a relay".to_string()),
+    };
+    start_session(&app.state::<WatchPartyState>(), PartyMode::Joined, None, token.clone(), false)?;
+
+    let handle = app.clone();
+    supervisor.spawn("watch-party-peer", move |_| async move {
+        run_peer(handle.clone(), stream, false, room_code, name, token).await;
+        // A joined session ends with its only connection
+        end_session(&handle);
+    });
+    Ok(())
+}
+
+/// Leave (or stop hosting) the watch party.
+#[tauri::command]
+pub fn watch_party_leave(app: AppHandle, webview: tauri::Webview) -> Result<(), String> {
+    require_webview(&webview, Capability::ManageQueue)?;
+    end_session(&app);
+    Ok(())
+}
+
+/// Publish the local queue to the peer. Returns the new revision.
+#[tauri::command]
+pub fn watch_party_share_queue(app: AppHandle, webview: tauri::Webview, entries: Vec<serde_json::Value>) -> Result<u64, String> {
+    require_webview(&webview, Capability::ManageQueue)?;
+    let state = app.state::<WatchPartyState>();
+    let revision = {
+        let mut queue = state.queue.lock().map_err(|e| e.to_string())?;
+        *queue = (queue.0 + 1, state.instance_id.clone(), entries.clone());
+        queue.0
+    };
+    state.send(PeerMessage::Queue { revision, origin: state.instance_id.clone(), entries })?;
+    Ok(revision)
+}
+
+/// Schedule `song_id` to start `lead_ms` from now (default 3000) on both
+/// machines. Returns the local start time (epoch ms) to wait for.
+#[tauri::command]
+pub fn watch_party_start_song(app: AppHandle, webview: tauri::Webview, song_id: String, lead_ms: Option<i64>) -> Result<i64, String> {
+    require_webview(&webview, Capability::ControlPlayback)?;
+    let start_at = now_ms() + lead_ms.unwrap_or(DEFAULT_START_LEAD_MS).clamp(500, 30_000);
+    app.state::<WatchPartyState>().send(PeerMessage::SongStart { song_id, start_at })?;
+    Ok(start_at)
+}
+
+/// Mirror a (live or final) score to the peer.
+#[tauri::command]
+pub fn watch_party_send_score(
+    app: AppHandle,
+    webview: tauri::Webview,
+    song_id: String,
+    singer: String,
+    score: i64,
+    is_final: Option<bool>,
+) -> Result<(), String> {
+    require_webview(&webview, Capability::ControlPlayback)?;
+    app.state::<WatchPartyState>().send(PeerMessage::Score {
+        song_id,
+        singer,
+        score,
+        is_final: is_final.unwrap_or(false),
+    })
+}
+
+#[tauri::command]
+pub fn watch_party_status(app: AppHandle) -> Result<WatchPartyStatus, String> {
+    let state = app.state::<WatchPartyState>();
+    let session = state.session.lock().map_err(|e| e.to_string())?;
+    let clock = state.clock.lock().map_err(|e| e.to_string())?;
+    let revision = state.queue.lock().map_err(|e| e.to_string())?.0;
+    Ok(WatchPartyStatus {
+        mode: session.as_ref().map(|s| s.mode).unwrap_or(PartyMode::Idle),
+        connected: session.as_ref().is_some_and(|s| s.outgoing.is_some()),
+        peer_name: session.as_ref().and_then(|s| s.peer_name.clone()),
+        port: session.as_ref().and_then(|s| s.port),
+        clock_offset_ms: clock.offset_ms(),
+        rtt_ms: clock.rtt_ms(),
+        queue_revision: revision,
+    })
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrong_room_codes_back_off_then_lock_out() {
+        let (guesser, friend): (IpAddr, IpAddr) = ("203.0.113.7".parse().unwrap(), "198.51.100.2".parse().unwrap());
+        let start = Instant::now();
+        let mut attempts = Attempts::default();
+        for _ in 0..FREE_ATTEMPTS {
+            assert_eq!(attempts.wait(guesser, start), Some(Duration::ZERO));
+            attempts.record(guesser, false, start);
+        }
+        assert_eq!(attempts.wait(guesser, start), Some(Duration::from_secs(1)));
+        assert_eq!(attempts.wait(guesser, start + Duration::from_secs(1)), Some(Duration::ZERO));
+        // Other addresses are not held up
+        assert_eq!(attempts.wait(friend, start), Some(Duration::ZERO));
+
+        for _ in FREE_ATTEMPTS..MAX_ATTEMPTS {
+            attempts.record(guesser, false, start);
+        }
+        assert_eq!(attempts.wait(guesser, start + MAX_BACKOFF * 10), None);
+        assert_eq!(backoff(u32::MAX), MAX_BACKOFF);
+
+        attempts.record(friend, false, start);
+        attempts.record(friend, true, start);
+        assert_eq!(attempts.wait(friend, start), Some(Duration::ZERO));
+    }
+}
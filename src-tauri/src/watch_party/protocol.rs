@@ -0,0 +1,203 @@
+//! Wire protocol between two watch-party hosts.
+//!
+//! Messages are newline-delimited JSON objects tagged by `type`, at most
+//! `MAX_LINE_BYTES` each. The room code never goes over the wire; both
+//! sides prove they know it instead:
+//!   host   → `challenge` with a fresh nonce;
+//!   joiner → `hello` with its own nonce and `proof("join", …)`;
+//!   host   → `welcome` with `proof("host", …)`, or `reject`.
+//! Nothing else is accepted before that. Afterwards either side may send
+//! at any time; pings run continuously to estimate the clock offset
+//! between the machines so a scheduled song start happens at the same
+//! instant in both homes.
+
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Longest accepted line; a full queue snapshot fits easily.
+pub const MAX_LINE_BYTES: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PeerMessage {
+    Challenge {
+        protocol: u32,
+        nonce: String,
+    },
+    Hello {
+        protocol: u32,
+        instance_id: String,
+        name: String,
+        nonce: String,
+        proof: String,
+    },
+    Welcome {
+        instance_id: String,
+        name: String,
+        proof: String,
+    },
+    Reject {
+        reason: String,
+    },
+    Ping {
+        id: u64,
+        sent_at: i64,
+    },
+    Pong {
+        id: u64,
+        ping_sent_at: i64,
+        received_at: i64,
+    },
+    /// Full shared queue. The higher revision wins; ties go to the larger
+    /// instance id so both sides converge on the same queue.
+    Queue {
+        revision: u64,
+        origin: String,
+        entries: Vec<serde_json::Value>,
+    },
+    /// Start `song_id` at `start_at` (sender's clock, epoch ms).
+    SongStart {
+        song_id: String,
+        start_at: i64,
+    },
+    Score {
+        song_id: String,
+        singer: String,
+        score: i64,
+        #[serde(default)]
+        is_final: bool,
+    },
+    Bye,
+}
+
+pub fn encode(message: &PeerMessage) -> Result<Vec<u8>, String> {
+    let mut line = serde_json::to_vec(message).map_err(|e| format!("Failed to encode message: {}", e))?;
+    line.push(b'\n');
+    Ok(line)
+}
+
+pub fn decode(line: &str) -> Result<PeerMessage, String> {
+    if line.len() > MAX_LINE_BYTES {
+        return Err("Message too large".to_string());
+    }
+    serde_json::from_str(line).map_err(|e| format!("Invalid message: {}", e))
+}
+
+/// Proof that `side` (`"host"` or `"join"`) knows `room_code`, bound to
+/// both nonces so it cannot be replayed on another connection.
+pub fn proof(room_code: &str, side: &str, host_nonce: &str, join_nonce: &str) -> String {
+    let mut hasher = Sha256::new();
+    for part in ["karaoke-watch-party", side, room_code.trim(), host_nonce, join_nonce] {
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part.as_bytes());
+    }
+    hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Compare proofs without an early exit.
+pub fn proofs_match(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Whether an incoming queue snapshot should replace ours.
+pub fn queue_wins(incoming: (u64, &str), current: (u64, &str)) -> bool {
+    incoming.0 > current.0 || (incoming.0 == current.0 && incoming.1 > current.1)
+}
+
+// ---------------------------------------------------------------------------
+// Clock offset estimation
+// ---------------------------------------------------------------------------
+
+/// Ping samples kept; the lowest-latency one gives the offset estimate.
+const CLOCK_SAMPLES: usize = 8;
+
+/// NTP-style offset estimate from ping round trips.
+#[derive(Debug, Default)]
+pub struct ClockSync {
+    /// (round trip ms, peer clock minus local clock ms)
+    samples: VecDeque<(i64, i64)>,
+}
+
+impl ClockSync {
+    /// Record a round trip: we sent at `sent`, the peer stamped `peer_received`,
+    /// we got the pong at `received` (local clock, epoch ms).
+    pub fn add_sample(&mut self, sent: i64, peer_received: i64, received: i64) {
+        let rtt = (received - sent).max(0);
+        let offset = peer_received - (sent + received) / 2;
+        if self.samples.len() >= CLOCK_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((rtt, offset));
+    }
+
+    /// Peer clock minus local clock, from the quickest recent round trip
+    /// (least queuing delay, so the most symmetric path).
+    pub fn offset_ms(&self) -> Option<i64> {
+        self.samples.iter().min_by_key(|(rtt, _)| *rtt).map(|(_, offset)| *offset)
+    }
+
+    pub fn rtt_ms(&self) -> Option<i64> {
+        self.samples.iter().map(|(rtt, _)| *rtt).min()
+    }
+
+    /// Convert a time on the peer's clock to ours.
+    pub fn to_local(&self, peer_ms: i64) -> i64 {
+        peer_ms - self.offset_ms().unwrap_or(0)
+    }
+
+    pub fn reset(&mut self) {
+        self.samples.clear();
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_round_trip_as_tagged_lines() {
+        let msg = PeerMessage::SongStart { song_id: "abc".into(), start_at: 1_000 };
+        let line = encode(&msg).unwrap();
+        assert!(line.ends_with(b"\n"));
+        let text = std::str::from_utf8(&line).unwrap();
+        assert!(text.contains("\"type\":\"song_start\""));
+        assert_eq!(decode(text.trim_end()).unwrap(), msg);
+    }
+
+    #[test]
+    fn proofs_need_the_code_and_both_nonces() {
+        let join = proof("PARTY42", "join", "h1", "j1");
+        assert!(proofs_match(&join, &proof(" PARTY42 ", "join", "h1", "j1")));
+        assert!(!proofs_match(&join, &proof("PARTY43", "join", "h1", "j1")));
+        assert!(!proofs_match(&join, &proof("PARTY42", "host", "h1", "j1")));
+        assert!(!proofs_match(&join, &proof("PARTY42", "join", "h2", "j1")));
+        assert!(!join.contains("PARTY42"));
+    }
+
+    #[test]
+    fn clock_offset_prefers_fastest_round_trip() {
+        let mut clock = ClockSync::default();
+        // Peer clock is 500 ms ahead. A congested sample skews the estimate…
+        clock.add_sample(1_000, 1_700, 1_300);
+        // …the quick one does not
+        clock.add_sample(2_000, 2_510, 2_020);
+        assert_eq!(clock.offset_ms(), Some(500));
+        assert_eq!(clock.rtt_ms(), Some(20));
+        assert_eq!(clock.to_local(10_500), 10_000);
+    }
+
+    #[test]
+    fn queue_conflicts_converge() {
+        assert!(queue_wins((3, "a"), (2, "z")));
+        assert!(queue_wins((2, "b"), (2, "a")));
+        assert!(!queue_wins((2, "a"), (2, "b")));
+    }
+}
@@ -0,0 +1,334 @@
+//! Optional relay for watch parties behind NAT.
+//!
+//! When neither home can accept an incoming connection, both sides dial out
+//! to a relay instead: a small TCP rendezvous that pairs one host and one
+//! joiner by room and then pipes bytes between them untouched. The relay
+//! address comes from the command (`relay`) or the `watch_party_relay`
+//! setting; `watch_party_run_relay` turns this app into one, e.g. on a
+//! machine with a public address or a port forward.
+//!
+//! The relay only ever sees a key derived from the room code: SHA-256 over
+//! a salt and the code, stretched over `ROOM_KEY_ROUNDS` rounds so trying
+//! every short code against it is slow. The code itself is still checked
+//! end to end in the `hello` handshake, where it never goes over the wire
+//! either (see `protocol::proof`).
+//!
+//! Handshake, one JSON line each way before the pipe starts:
+//!   client → `{"relay":2,"room":"<key>","side":"host"|"join"}`
+//!   relay  → `{"status":"waiting"}` (host only), then `{"status":"paired"}`
+//!            or `{"status":"error","reason":"…"}`
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Manager};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_util::sync::CancellationToken;
+
+use crate::access::{require_webview, Capability};
+use crate::db::DbState;
+use crate::runtime::TaskSupervisor;
+
+pub const RELAY_PROTOCOL_VERSION: u32 = 2;
+const RELAY_SETTING_KEY: &str = "watch_party_relay";
+pub const DEFAULT_RELAY_PORT: u16 = 47_821;
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// A host waits this long for a joiner before re-registering.
+const HOST_WAIT: Duration = Duration::from_secs(15 * 60);
+const MAX_WAITING_ROOMS: usize = 256;
+const MAX_HANDSHAKE_LINE: usize = 1024;
+const ROOM_KEY_SALT: &str = "karaoke-watch-party-relay/2";
+const ROOM_KEY_ROUNDS: usize = 100_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Side {
+    Host,
+    Join,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct RelayRequest {
+    relay: u32,
+    room: String,
+    side: Side,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+enum RelayReply {
+    Waiting,
+    Paired,
+    Error { reason: String },
+}
+
+/// What the relay learns about a room: a stretched, salted key, not the
+/// code. Takes a few tens of ms.
+pub fn room_key(room_code: &str) -> String {
+    let code = room_code.trim().as_bytes();
+    let mut hasher = Sha256::new();
+    for part in [ROOM_KEY_SALT.as_bytes(), code] {
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+    let mut key = hasher.finalize();
+    for _ in 1..ROOM_KEY_ROUNDS {
+        key = Sha256::new().chain_update(key).chain_update(code).finalize();
+    }
+    key.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// `host[:port]`, with or without a `tcp://` prefix, to `host:port`.
+pub fn relay_address(relay: &str) -> Result<String, String> {
+    let relay = relay.trim();
+    let relay = relay.strip_prefix("tcp://").unwrap_or(relay).trim_end_matches('/');
+    if relay.is_empty() || relay.contains('/') {
+        return Err(format!("Invalid relay address '{}'", relay));
+    }
+    // Bare IPv6 needs brackets to carry a port
+    let has_port = match relay.rsplit_once(':') {
+        Some((host, port)) => port.parse::<u16>().is_ok() && (!host.contains(':') || host.ends_with(']')),
+        None => false,
+    };
+    Ok(if has_port { relay.to_string() } else { format!("{}:{}", relay, DEFAULT_RELAY_PORT) })
+}
+
+/// The relay from the command, else from settings.
+pub fn configured_relay(app: &AppHandle, explicit: Option<String>) -> Option<String> {
+    explicit.filter(|r| !r.trim().is_empty()).or_else(|| {
+        let db = app.try_state::<DbState>()?;
+        let conn = db.conn.lock().ok()?;
+        conn.query_row("SELECT value FROM app_settings WHERE key = ?1", [RELAY_SETTING_KEY], |row| {
+            row.get::<_, String>(0)
+        })
+        .ok()
+        .filter(|r| !r.trim().is_empty())
+    })
+}
+
+/// Read one handshake line byte by byte, so nothing after it is buffered
+/// away from the peer connection.
+async fn read_line(stream: &mut TcpStream) -> Result<String, String> {
+    let mut line = Vec::new();
+    loop {
+        let byte = stream.read_u8().await.map_err(|e| format!("Relay read failed: {}", e))?;
+        if byte == b'\n' {
+            break;
+        }
+        if line.len() >= MAX_HANDSHAKE_LINE {
+            return Err("Relay handshake line too long".to_string());
+        }
+        line.push(byte);
+    }
+    String::from_utf8(line).map_err(|_| "Relay handshake is not UTF-8".to_string())
+}
+
+async fn write_json<T: Serialize>(stream: &mut TcpStream, value: &T) -> Result<(), String> {
+    let mut line = serde_json::to_vec(value).map_err(|e| e.to_string())?;
+    line.push(b'\n');
+    stream.write_all(&line).await.map_err(|e| format!("Relay write failed: {}", e))
+}
+
+async fn read_reply(stream: &mut TcpStream) -> Result<RelayReply, String> {
+    serde_json::from_str(&read_line(stream).await?).map_err(|e| format!("Invalid relay reply: {}", e))
+}
+
+// ---------------------------------------------------------------------------
+// Client
+// ---------------------------------------------------------------------------
+
+/// Dial `relay` and wait until it pairs us with the other side of
+/// `room_code`. The returned stream then behaves like a direct connection.
+pub async fn connect(relay: &str, room_code: &str, side: Side, cancel: &CancellationToken) -> Result<TcpStream, String> {
+    let address = relay_address(relay)?;
+    let mut stream = tokio::time::timeout(HANDSHAKE_TIMEOUT, TcpStream::connect(&address))
+        .await
+        .map_err(|_| format!("Timed out connecting to relay {}", address))?
+        .map_err(|e| format!("Cannot reach relay {}: {}", address, e))?;
+    let _ = stream.set_nodelay(true);
+    let code = room_code.to_string();
+    let room = tauri::async_runtime::spawn_blocking(move || room_key(&code)).await.map_err(|e| e.to_string())?;
+    let request = RelayRequest { relay: RELAY_PROTOCOL_VERSION, room, side };
+    write_json(&mut stream, &request).await?;
+
+    let wait = if side == Side::Host { HOST_WAIT } else { HANDSHAKE_TIMEOUT };
+    tokio::select! {
+        _ = cancel.cancelled() => return Err("Cancelled".to_string()),
+        result = tokio::time::timeout(wait, wait_paired(&mut stream, &address)) => {
+            result.map_err(|_| "Timed out waiting for the relay to pair".to_string())??
+        }
+    }
+    tracing::info!("[relay] Paired via {}", address);
+    Ok(stream)
+}
+
+async fn wait_paired(stream: &mut TcpStream, address: &str) -> Result<(), String> {
+    loop {
+        match read_reply(stream).await? {
+            RelayReply::Waiting => tracing::info!("[relay] Registered with {}, waiting for a guest", address),
+            RelayReply::Paired => return Ok(()),
+            RelayReply::Error { reason } => return Err(format!("Relay refused: {}", reason)),
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Server
+// ---------------------------------------------------------------------------
+
+type WaitingHosts = Arc<Mutex<HashMap<String, TcpStream>>>;
+
+async fn serve_client(mut stream: TcpStream, waiting: WaitingHosts) -> Result<(), String> {
+    let line = tokio::time::timeout(HANDSHAKE_TIMEOUT, read_line(&mut stream))
+        .await
+        .map_err(|_| "Handshake timed out".to_string())??;
+    let request: RelayRequest = match serde_json::from_str(&line) {
+        Ok(r) => r,
+        Err(e) => {
+            let reason = format!("Invalid request: {}", e);
+            let _ = write_json(&mut stream, &RelayReply::Error { reason: reason.clone() }).await;
+            return Err(reason);
+        }
+    };
+    if request.relay != RELAY_PROTOCOL_VERSION {
+        let reason = format!("Relay protocol v{} not supported", request.relay);
+        let _ = write_json(&mut stream, &RelayReply::Error { reason: reason.clone() }).await;
+        return Err(reason);
+    }
+
+    match request.side {
+        Side::Host => {
+            let refusal = {
+                let mut waiting = waiting.lock().map_err(|e| e.to_string())?;
+                if waiting.len() >= MAX_WAITING_ROOMS && !waiting.contains_key(&request.room) {
+                    Some("Relay is full")
+                } else {
+                    None
+                }
+            };
+            if let Some(reason) = refusal {
+                let _ = write_json(&mut stream, &RelayReply::Error { reason: reason.to_string() }).await;
+                return Err(reason.to_string());
+            }
+            write_json(&mut stream, &RelayReply::Waiting).await?;
+            // A re-registering host replaces its stale connection
+            if let Ok(mut waiting) = waiting.lock() {
+                waiting.insert(request.room, stream);
+            }
+            Ok(())
+        }
+        Side::Join => {
+            let host = waiting.lock().map_err(|e| e.to_string())?.remove(&request.room);
+            let Some(mut host) = host else {
+                let reason = "No host is waiting for this room".to_string();
+                let _ = write_json(&mut stream, &RelayReply::Error { reason: reason.clone() }).await;
+                return Err(reason);
+            };
+            write_json(&mut host, &RelayReply::Paired).await?;
+            write_json(&mut stream, &RelayReply::Paired).await?;
+            tracing::info!("[relay] Paired room {}", request.room);
+            let _ = tokio::io::copy_bidirectional(&mut host, &mut stream).await;
+            Ok(())
+        }
+    }
+}
+
+async fn run_relay(listener: TcpListener, cancel: CancellationToken) {
+    let waiting: WaitingHosts = Arc::new(Mutex::new(HashMap::new()));
+    loop {
+        let accepted = tokio::select! {
+            _ = cancel.cancelled() => break,
+            accepted = listener.accept() => accepted,
+        };
+        match accepted {
+            Ok((stream, _)) => {
+                let _ = stream.set_nodelay(true);
+                let waiting = waiting.clone();
+                let cancel = cancel.clone();
+                tokio::spawn(async move {
+                    tokio::select! {
+                        _ = cancel.cancelled() => {}
+                        result = serve_client(stream, waiting) => {
+                            if let Err(e) = result {
+                                tracing::warn!("[relay] {}", e);
+                            }
+                        }
+                    }
+                });
+            }
+            Err(e) => tracing::error!("[relay] Accept failed: {}", e),
+        }
+    }
+    tracing::info!("[relay] Stopped");
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+/// Managed state: the relay this app is running, if any.
+#[derive(Default)]
+pub struct RelayServerState {
+    running: Mutex<Option<(u16, CancellationToken)>>,
+}
+
+/// Run a relay on `port` (default 47821) until stopped or the app exits.
+/// Returns the port listening.
+#[tauri::command]
+pub async fn watch_party_run_relay(app: AppHandle, webview: tauri::Webview, port: Option<u16>) -> Result<u16, String> {
+    require_webview(&webview, Capability::ChangeSettings)?;
+    let state = app.state::<RelayServerState>();
+    if let Some((port, _)) = *state.running.lock().map_err(|e| e.to_string())? {
+        return Ok(port);
+    }
+    let listener = TcpListener::bind(("0.0.0.0", port.unwrap_or(DEFAULT_RELAY_PORT)))
+        .await
+        .map_err(|e| format!("Cannot start relay: {}", e))?;
+    let port = listener.local_addr().map_err(|e| e.to_string())?.port();
+
+    let supervisor = app.state::<TaskSupervisor>();
+    let token = supervisor.token();
+    *state.running.lock().map_err(|e| e.to_string())? = Some((port, token.clone()));
+    supervisor.spawn("watch-party-relay", move |_| run_relay(listener, token));
+    tracing::info!("[relay] Listening on port {}", port);
+    Ok(port)
+}
+
+#[tauri::command]
+pub fn watch_party_stop_relay(app: AppHandle, webview: tauri::Webview) -> Result<(), String> {
+    require_webview(&webview, Capability::ChangeSettings)?;
+    if let Some((_, token)) = app.state::<RelayServerState>().running.lock().map_err(|e| e.to_string())?.take() {
+        token.cancel();
+    }
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relay_addresses_get_default_port() {
+        assert_eq!(relay_address("relay.example.com").unwrap(), "relay.example.com:47821");
+        assert_eq!(relay_address("tcp://relay.example.com:9000/").unwrap(), "relay.example.com:9000");
+        assert_eq!(relay_address("[::1]:9000").unwrap(), "[::1]:9000");
+        assert!(relay_address("https://relay.example.com/path").is_err());
+    }
+
+    #[test]
+    fn room_key_hides_the_code() {
+        let key = room_key("PARTY42");
+        assert_eq!(key, room_key(" PARTY42 "));
+        assert!(!key.contains("PARTY42"));
+        assert_eq!(key.len(), 64);
+        assert_ne!(key, room_key("PARTY43"));
+    }
+}
@@ -0,0 +1,25 @@
+import { createHash } from "crypto";
+import { NextResponse } from "next/server";
+
+/**
+ * GET /api/health
+ *
+ * Identifies this server to the desktop app. The app starts the server with
+ * KARAOKE_SESSION_TOKEN and checks `instance` against its own hash of that
+ * token, so it never mistakes another service on the same port for us.
+ * Only the hash is returned; the token itself never leaves the process.
+ */
+export const dynamic = "force-dynamic";
+
+const token = process.env.KARAOKE_SESSION_TOKEN;
+const instance = token
+  ? createHash("sha256").update(`karaoke-health:${token}`).digest("hex")
+  : null;
+
+export async function GET() {
+  return NextResponse.json({
+    status: "ok",
+    app: "karaoke-successor",
+    instance,
+  });
+}
@@ -23,8 +23,8 @@ export async function GET(request: NextRequest) {
 
     return NextResponse.json({
       ip: clientIp || null,
-      // The server port is always 3000 in Tauri (hardcoded in Rust side)
-      port: 3000,
+      // The desktop app picks the port and passes it in PORT
+      port: Number(process.env.PORT) || 3000,
     });
   }
 
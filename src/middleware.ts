@@ -0,0 +1,73 @@
+import { NextRequest, NextResponse } from "next/server";
+
+/**
+ * LAN access control.
+ *
+ * With LAN access on, the desktop app binds the server to every interface
+ * and starts it with KARAOKE_ACCESS_TOKEN (operators) and
+ * KARAOKE_GUEST_TOKEN (guests, the remote QR code); every request must
+ * then carry one of them, as `?token=` (QR codes, the app's own first page
+ * load), `Authorization: Bearer` (companion apps) or the cookie set on the
+ * first authenticated page load. Routes see which one as the
+ * `x-karaoke-role` request header (`operator` or `guest`). Without the
+ * variables the server is bound to localhost and lets everything through.
+ *
+ * `/api/health` stays open: the desktop app probes it to recognise its
+ * own server and only ever gets a hash back.
+ */
+const COOKIE = "karaoke_access";
+const OPEN_PATHS = new Set(["/api/health"]);
+
+/** Compare without an early exit, so timing does not reveal a prefix. */
+function matches(candidate: string | null | undefined, token: string): boolean {
+  if (!candidate || candidate.length !== token.length) return false;
+  let diff = 0;
+  for (let i = 0; i < token.length; i++) {
+    diff |= candidate.charCodeAt(i) ^ token.charCodeAt(i);
+  }
+  return diff === 0;
+}
+
+type Role = "operator" | "guest";
+
+/** Which role `candidate` authenticates as, if any. */
+function roleFor(candidate: string | null | undefined, operator: string, guest: string | undefined): Role | null {
+  if (matches(candidate, operator)) return "operator";
+  if (guest && matches(candidate, guest)) return "guest";
+  return null;
+}
+
+/** Pass the request on with the role it authenticated as. */
+function next(request: NextRequest, role: Role): NextResponse {
+  const headers = new Headers(request.headers);
+  headers.set("x-karaoke-role", role);
+  return NextResponse.next({ request: { headers } });
+}
+
+export function middleware(request: NextRequest) {
+  const token = process.env.KARAOKE_ACCESS_TOKEN;
+  const guestToken = process.env.KARAOKE_GUEST_TOKEN;
+  if (!token || OPEN_PATHS.has(request.nextUrl.pathname)) {
+    return NextResponse.next();
+  }
+
+  const url = request.nextUrl;
+  const queryToken = url.searchParams.get("token");
+  const queryRole = roleFor(queryToken, token, guestToken);
+  if (queryToken && queryRole) {
+    // Swap the token in the URL for the cookie, so it does not stay in
+    // the address bar and history
+    const clean = url.clone();
+    clean.searchParams.delete("token");
+    const response = request.method === "GET" ? NextResponse.redirect(clean) : next(request, queryRole);
+    response.cookies.set(COOKIE, queryToken, { httpOnly: true, sameSite: "lax", path: "/" });
+    return response;
+  }
+
+  const bearer = request.headers.get("authorization")?.replace(/^Bearer\s+/i, "");
+  const role = roleFor(request.cookies.get(COOKIE)?.value, token, guestToken) ?? roleFor(bearer, token, guestToken);
+  if (role) {
+    return next(request, role);
+  }
+  return new NextResponse("Access token required", { status: 401 });
+}